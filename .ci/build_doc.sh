@@ -14,6 +14,7 @@ cargo doc --no-deps -p tsukuyomi-server --all-features
 
 cargo doc --no-deps -p tsukuyomi-askama
 cargo doc --no-deps -p tsukuyomi-cors
+cargo doc --no-deps -p tsukuyomi-fs
 cargo doc --no-deps -p tsukuyomi-juniper
 cargo doc --no-deps -p tsukuyomi-session --all-features
 cargo doc --no-deps -p tsukuyomi-tungstenite
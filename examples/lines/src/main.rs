@@ -0,0 +1,45 @@
+//! A line-based echo protocol served via HTTP/1.1 upgrade.
+//!
+//! ```shell
+//! $ printf 'GET /echo HTTP/1.1\r\nHost: localhost\r\nConnection: upgrade\r\nUpgrade: lines\r\n\r\nhello\n' \
+//!     | nc -q 1 localhost 4000
+//! ```
+
+use {
+    http::Response,
+    std::net::SocketAddr,
+    tsukuyomi::{
+        config::prelude::*, //
+        upgrade::{self, UpgradeRequest},
+        App,
+    },
+    tsukuyomi_server::Server,
+};
+
+fn main() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/echo") //
+            .to(endpoint::get()
+                .extract(upgrade::upgrade())
+                .call(|req: UpgradeRequest| {
+                    req.require_protocol("lines")?;
+                    req.finish(
+                        &mut Response::builder(),
+                        upgrade::lines(|line: String| {
+                            println!("Line from client: {:?}", line);
+                            if line == "quit" {
+                                Ok(None)
+                            } else {
+                                Ok(Some(line))
+                            }
+                        }),
+                    )
+                })),
+    )?;
+
+    let addr: SocketAddr = "127.0.0.1:4000".parse()?;
+    println!("Listening on http://{}", addr);
+    Server::new(app) //
+        .bind(addr) //
+        .run()
+}
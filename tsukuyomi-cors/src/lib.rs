@@ -15,8 +15,7 @@ use {
     failure::Fail,
     http::{
         header::{
-            HeaderMap, //
-            HeaderName,
+            HeaderName, //
             HeaderValue,
             ACCESS_CONTROL_ALLOW_CREDENTIALS,
             ACCESS_CONTROL_ALLOW_HEADERS,
@@ -26,11 +25,12 @@ use {
             ACCESS_CONTROL_REQUEST_HEADERS,
             ACCESS_CONTROL_REQUEST_METHOD,
             ORIGIN,
+            VARY,
         },
         HttpTryFrom, Method, Request, Response, StatusCode, Uri,
     },
     std::{collections::HashSet, sync::Arc, time::Duration},
-    tsukuyomi::{HttpError, Input},
+    tsukuyomi::{input::response::ResponseHeaders, output::vary, HttpError, Input},
 };
 
 /// A builder of `CORS`.
@@ -478,6 +478,9 @@ impl Inner {
 
         let mut response = Response::default();
         *response.status_mut() = StatusCode::NO_CONTENT;
+        if let AllowedOrigin::Some(..) = origin {
+            vary::add(response.headers_mut(), "origin");
+        }
         response
             .headers_mut()
             .insert(ACCESS_CONTROL_ALLOW_ORIGIN, origin.into());
@@ -507,19 +510,23 @@ impl Inner {
         &self,
         request: &Request<T>,
         origin: AllowedOrigin,
-        hdrs: &mut HeaderMap,
+        hdrs: &mut ResponseHeaders,
     ) -> Result<(), CORSError> {
         if !self.methods.contains(request.method()) {
             return Err(CORSErrorKind::DisallowedRequestMethod.into());
         }
 
-        hdrs.append(ACCESS_CONTROL_ALLOW_ORIGIN, origin.into());
+        // The response depends on `Origin` unless any origin is allowed.
+        if let AllowedOrigin::Some(..) = origin {
+            hdrs.append(VARY, "origin").expect("never fails");
+        }
+        let origin: HeaderValue = origin.into();
+        hdrs.set(ACCESS_CONTROL_ALLOW_ORIGIN, origin)
+            .expect("never fails");
 
         if self.allow_credentials {
-            hdrs.append(
-                ACCESS_CONTROL_ALLOW_CREDENTIALS,
-                HeaderValue::from_static("true"),
-            );
+            hdrs.set(ACCESS_CONTROL_ALLOW_CREDENTIALS, "true")
+                .expect("never fails");
         }
 
         Ok(())
@@ -535,8 +542,7 @@ impl Inner {
                 .map(Some)
                 .map_err(Into::into)
         } else {
-            let response_headers = input.response_headers.get_or_insert_with(Default::default);
-            self.process_simple_request(input.request, origin, response_headers)
+            self.process_simple_request(input.request, origin, input.response())
                 .map(|_| None)
                 .map_err(Into::into)
         }
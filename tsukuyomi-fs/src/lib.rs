@@ -0,0 +1,465 @@
+//! A WebDAV subset for managing the files in a directory, built on Tsukuyomi.
+//!
+//! The endpoint provided by this crate supports the following methods:
+//!
+//! * `GET` / `HEAD` - retrieves the content of a file.
+//! * `PUT` - creates or replaces a file with the request body.
+//! * `DELETE` - removes a file or a collection.
+//! * `MKCOL` - creates a collection (directory).
+//! * `MOVE` - moves a file or a collection to the location specified by `Destination`.
+//! * `PROPFIND` - retrieves the properties of a resource, with `Depth: 0` or `Depth: 1`.
+//!
+//! The locking methods (`LOCK` and `UNLOCK`) are not supported and
+//! always return `501 Not Implemented`.
+//!
+//! ```
+//! use tsukuyomi::{config::prelude::*, App};
+//! use tsukuyomi_fs::WebDav;
+//!
+//! # fn main() -> tsukuyomi::app::Result<()> {
+//! let app = App::create(
+//!     path!("/dav/*path") //
+//!         .to(WebDav::new("./data")),
+//! )?;
+//! # drop(app);
+//! # Ok(())
+//! # }
+//! ```
+
+#![doc(html_root_url = "https://docs.rs/tsukuyomi-fs/0.1.0")]
+#![deny(
+    missing_docs,
+    missing_debug_implementations,
+    nonstandard_style,
+    rust_2018_idioms,
+    rust_2018_compatibility,
+    unused
+)]
+#![forbid(clippy::unimplemented)]
+
+mod propfind;
+mod upload;
+
+use {
+    futures::{Async, Poll},
+    http::{
+        header::{HeaderMap, HeaderValue, ALLOW, CONTENT_LENGTH, HOST},
+        Method, Request, Response, StatusCode, Uri,
+    },
+    std::{
+        fs, io,
+        path::{Path, PathBuf},
+        sync::Arc,
+    },
+    tsukuyomi::{error::Error, output::ResponseBody},
+    url::percent_encoding::percent_decode,
+};
+
+const METHODS: &[&str] = &[
+    "OPTIONS", "GET", "HEAD", "PUT", "DELETE", "PROPFIND", "MKCOL", "MOVE", "LOCK", "UNLOCK",
+];
+
+/// An `Endpoint` that provides a WebDAV subset over the specified root directory.
+///
+/// The endpoint takes the relative path of the target resource as a parameter,
+/// and is intended to be registered with a catch-all path such as `"/dav/*path"`.
+/// The paths containing `..` are rejected with `403 Forbidden`.
+#[derive(Debug, Clone)]
+pub struct WebDav {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug, Clone)]
+struct Inner {
+    root: PathBuf,
+    read_only: bool,
+}
+
+impl WebDav {
+    /// Creates a `WebDav` with the specified root directory.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                root: root.into(),
+                read_only: false,
+            }),
+        }
+    }
+
+    /// Sets whether to reject the methods that modify the directory.
+    ///
+    /// If enabled, `PUT`, `DELETE`, `MKCOL` and `MOVE` are rejected with `403 Forbidden`.
+    ///
+    /// The default value is `false`.
+    pub fn read_only(mut self, enabled: bool) -> Self {
+        Arc::make_mut(&mut self.inner).read_only = enabled;
+        self
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Operation {
+    Options,
+    Get,
+    Put,
+    Delete,
+    Propfind,
+    Mkcol,
+    Move,
+    Lock,
+}
+
+impl Operation {
+    fn from_method(method: &Method) -> Option<Self> {
+        match method.as_str() {
+            "OPTIONS" => Some(Operation::Options),
+            "GET" | "HEAD" => Some(Operation::Get),
+            "PUT" => Some(Operation::Put),
+            "DELETE" => Some(Operation::Delete),
+            "PROPFIND" => Some(Operation::Propfind),
+            "MKCOL" => Some(Operation::Mkcol),
+            "MOVE" => Some(Operation::Move),
+            "LOCK" | "UNLOCK" => Some(Operation::Lock),
+            _ => None,
+        }
+    }
+
+    fn is_mutating(self) -> bool {
+        match self {
+            Operation::Put | Operation::Delete | Operation::Mkcol | Operation::Move => true,
+            _ => false,
+        }
+    }
+}
+
+mod impl_endpoint_for_webdav {
+    use {
+        super::{Operation, WebDav, WebDavFuture, METHODS},
+        http::{Method, Response},
+        tsukuyomi::{
+            endpoint::{ApplyContext, ApplyError, ApplyResult, Endpoint},
+            error::Error,
+            handler::AllowedMethods,
+            output::ResponseBody,
+        },
+    };
+
+    impl Endpoint<(String,)> for WebDav {
+        type Output = Response<ResponseBody>;
+        type Error = Error;
+        type Future = WebDavFuture;
+
+        fn apply(
+            &self,
+            (path,): (String,),
+            cx: &mut ApplyContext<'_, '_>,
+        ) -> ApplyResult<(String,), Self> {
+            match Operation::from_method(cx.method()) {
+                Some(operation) => Ok(WebDavFuture::new(self.inner.clone(), path, operation)),
+                None => Err(((path,), ApplyError::method_not_allowed())),
+            }
+        }
+
+        fn allowed_methods(&self) -> Option<AllowedMethods> {
+            Some(
+                METHODS
+                    .iter()
+                    .map(|method| {
+                        Method::from_bytes(method.as_bytes()).expect("should be a valid method")
+                    })
+                    .collect(),
+            )
+        }
+    }
+}
+
+/// The `TryFuture` returned from `WebDav`.
+#[derive(Debug)]
+pub struct WebDavFuture {
+    inner: Arc<Inner>,
+    path: String,
+    operation: Operation,
+    state: State,
+}
+
+#[derive(Debug)]
+enum State {
+    Init,
+    Get(tsukuyomi::fs::OpenNamedFile<PathBuf>),
+    Put(self::upload::Upload),
+    Blocking(PathBuf),
+}
+
+impl WebDavFuture {
+    fn new(inner: Arc<Inner>, path: String, operation: Operation) -> Self {
+        Self {
+            inner,
+            path,
+            operation,
+            state: State::Init,
+        }
+    }
+}
+
+impl tsukuyomi::future::TryFuture for WebDavFuture {
+    type Ok = Response<ResponseBody>;
+    type Error = Error;
+
+    fn poll_ready(&mut self, input: &mut tsukuyomi::Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        loop {
+            self.state = match self.state {
+                State::Init => {
+                    if self.inner.read_only && self.operation.is_mutating() {
+                        return Err(tsukuyomi::error::forbidden("the directory is read-only"));
+                    }
+                    let path = resolve(&self.inner.root, &self.path)
+                        .ok_or_else(|| tsukuyomi::error::forbidden("invalid path"))?;
+                    match self.operation {
+                        Operation::Options => return Ok(Async::Ready(options())),
+                        Operation::Lock => {
+                            return Err(tsukuyomi::error::custom(
+                                StatusCode::NOT_IMPLEMENTED,
+                                "locking is not supported",
+                            ));
+                        }
+                        Operation::Get => State::Get(tsukuyomi::Responder::respond(
+                            tsukuyomi::fs::NamedFile::open(path),
+                        )),
+                        Operation::Put => State::Put(self::upload::Upload::new(path)),
+                        _ => State::Blocking(path),
+                    }
+                }
+                State::Get(ref mut open) => return open.poll_ready(input),
+                State::Put(ref mut upload) => return upload.poll_ready(input),
+                State::Blocking(ref path) => {
+                    let operation = self.operation;
+                    let root = &self.inner.root;
+                    let request = input.request;
+                    let params = &input.params;
+                    return blocking(|| match operation {
+                        Operation::Propfind => self::propfind::propfind(path, request),
+                        Operation::Mkcol => mkcol(path, root, request),
+                        Operation::Delete => delete(path, root),
+                        Operation::Move => {
+                            let raw = params
+                                .as_ref()
+                                .and_then(|params| params.catch_all())
+                                .unwrap_or("");
+                            move_to(path, root, request, raw)
+                        }
+                        _ => unreachable!("unexpected operation"),
+                    });
+                }
+            };
+        }
+    }
+}
+
+fn blocking<T>(f: impl FnOnce() -> Result<T, Error>) -> Poll<T, Error> {
+    match tokio_threadpool::blocking(f) {
+        Ok(Async::Ready(ready)) => ready.map(Async::Ready),
+        Ok(Async::NotReady) => Ok(Async::NotReady),
+        Err(err) => Err(tsukuyomi::error::internal_server_error(err)),
+    }
+}
+
+/// Joins the segments in `path` to the root directory, rejecting the segments
+/// which may point to outside of the root directory.
+fn resolve(root: &Path, path: &str) -> Option<PathBuf> {
+    let mut resolved = root.to_path_buf();
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => continue,
+            ".." => return None,
+            s if s.contains('\\') || s.contains('\0') => return None,
+            s if cfg!(windows) && s.contains(':') => return None,
+            s => resolved.push(s),
+        }
+    }
+    Some(resolved)
+}
+
+fn io_error(err: io::Error) -> Error {
+    match err.kind() {
+        io::ErrorKind::NotFound => tsukuyomi::error::not_found(err),
+        io::ErrorKind::PermissionDenied => tsukuyomi::error::forbidden(err),
+        _ => tsukuyomi::error::internal_server_error(err),
+    }
+}
+
+fn conflict(msg: &'static str) -> Error {
+    tsukuyomi::error::custom(StatusCode::CONFLICT, msg)
+}
+
+fn empty_response(status: StatusCode) -> Response<ResponseBody> {
+    let mut response = Response::new(ResponseBody::empty());
+    *response.status_mut() = status;
+    response
+}
+
+fn options() -> Response<ResponseBody> {
+    let mut response = empty_response(StatusCode::OK);
+    response.headers_mut().insert(
+        ALLOW,
+        HeaderValue::from_shared(METHODS.join(", ").into())
+            .expect("should be a valid header value"),
+    );
+    response
+        .headers_mut()
+        .insert("dav", HeaderValue::from_static("1"));
+    response
+}
+
+fn mkcol(path: &Path, root: &Path, request: &Request<()>) -> Result<Response<ResponseBody>, Error> {
+    if has_body(request.headers()) {
+        return Err(tsukuyomi::error::custom(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "the request body of MKCOL is not supported",
+        ));
+    }
+    if path == root || path.exists() {
+        return Err(tsukuyomi::error::method_not_allowed(
+            "the resource already exists",
+        ));
+    }
+    if !parent_exists(path) {
+        return Err(conflict("the parent collection does not exist"));
+    }
+    fs::create_dir(path).map_err(io_error)?;
+    Ok(empty_response(StatusCode::CREATED))
+}
+
+fn delete(path: &Path, root: &Path) -> Result<Response<ResponseBody>, Error> {
+    if path == root {
+        return Err(tsukuyomi::error::forbidden(
+            "the root collection cannot be deleted",
+        ));
+    }
+    let metadata = fs::symlink_metadata(path).map_err(io_error)?;
+    if metadata.is_dir() {
+        fs::remove_dir_all(path).map_err(io_error)?;
+    } else {
+        fs::remove_file(path).map_err(io_error)?;
+    }
+    Ok(empty_response(StatusCode::NO_CONTENT))
+}
+
+fn move_to(
+    path: &Path,
+    root: &Path,
+    request: &Request<()>,
+    raw: &str,
+) -> Result<Response<ResponseBody>, Error> {
+    if path == root {
+        return Err(tsukuyomi::error::forbidden(
+            "the root collection cannot be moved",
+        ));
+    }
+    fs::symlink_metadata(path).map_err(io_error)?;
+
+    let destination = destination(root, request, raw)?;
+    if destination == path || destination.starts_with(path) {
+        return Err(tsukuyomi::error::forbidden(
+            "the destination must not be the source or its member",
+        ));
+    }
+
+    let overwrite = match request
+        .headers()
+        .get("overwrite")
+        .map(HeaderValue::as_bytes)
+    {
+        None | Some(b"T") => true,
+        Some(b"F") => false,
+        Some(..) => return Err(tsukuyomi::error::bad_request("invalid Overwrite header")),
+    };
+    let exists = fs::symlink_metadata(&destination).ok();
+    if let Some(ref metadata) = exists {
+        if !overwrite {
+            return Err(tsukuyomi::error::custom(
+                StatusCode::PRECONDITION_FAILED,
+                "the destination already exists",
+            ));
+        }
+        if metadata.is_dir() {
+            fs::remove_dir_all(&destination).map_err(io_error)?;
+        } else {
+            fs::remove_file(&destination).map_err(io_error)?;
+        }
+    }
+    if !parent_exists(&destination) {
+        return Err(conflict("the parent collection does not exist"));
+    }
+
+    fs::rename(path, &destination).map_err(io_error)?;
+
+    Ok(empty_response(if exists.is_some() {
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::CREATED
+    }))
+}
+
+/// Validates the value of `Destination` and resolves it into the local path.
+///
+/// The destination must be on the same host and under the same prefix as the request.
+fn destination(root: &Path, request: &Request<()>, raw: &str) -> Result<PathBuf, Error> {
+    let uri: Uri = request
+        .headers()
+        .get("destination")
+        .ok_or_else(|| tsukuyomi::error::bad_request("missing Destination header"))?
+        .to_str()
+        .map_err(tsukuyomi::error::bad_request)?
+        .parse()
+        .map_err(tsukuyomi::error::bad_request)?;
+
+    if let Some(authority) = uri.authority_part() {
+        let host = request
+            .headers()
+            .get(HOST)
+            .and_then(|host| host.to_str().ok())
+            .or_else(|| request.uri().authority_part().map(|a| a.as_str()));
+        let same_host = match host {
+            Some(host) => host.eq_ignore_ascii_case(authority.as_str()),
+            None => false,
+        };
+        if !same_host {
+            return Err(tsukuyomi::error::custom(
+                StatusCode::BAD_GATEWAY,
+                "the destination is on another server",
+            ));
+        }
+    }
+
+    let request_path = request.uri().path();
+    let prefix = &request_path[..request_path.len() - raw.len()];
+    let relative = uri
+        .path()
+        .get(prefix.len()..)
+        .filter(|_| uri.path().starts_with(prefix))
+        .ok_or_else(|| tsukuyomi::error::forbidden("the destination is out of the collection"))?;
+    let relative = percent_decode(relative.as_bytes())
+        .decode_utf8()
+        .map_err(tsukuyomi::error::bad_request)?;
+
+    resolve(root, &relative)
+        .filter(|path| path != root)
+        .ok_or_else(|| tsukuyomi::error::forbidden("invalid destination"))
+}
+
+fn has_body(headers: &HeaderMap) -> bool {
+    let content_length = headers
+        .get(CONTENT_LENGTH)
+        .and_then(|len| len.to_str().ok());
+    match content_length {
+        Some(len) => len.trim() != "0",
+        None => false,
+    }
+}
+
+fn parent_exists(path: &Path) -> bool {
+    match path.parent() {
+        Some(parent) => parent.is_dir(),
+        None => false,
+    }
+}
@@ -0,0 +1,150 @@
+//! The implementation of `PROPFIND`.
+
+use {
+    super::io_error,
+    http::{
+        header::{HeaderValue, CONTENT_TYPE},
+        Request, Response, StatusCode,
+    },
+    std::{
+        fmt::Write as _Write,
+        fs::{self, Metadata},
+        path::Path,
+        time::UNIX_EPOCH,
+    },
+    tsukuyomi::{error::Error, output::ResponseBody},
+    url::percent_encoding::{utf8_percent_encode, PATH_SEGMENT_ENCODE_SET},
+};
+
+#[derive(Debug)]
+struct Entry {
+    href: String,
+    name: String,
+    metadata: Metadata,
+}
+
+pub(crate) fn propfind(
+    path: &Path,
+    request: &Request<()>,
+) -> Result<Response<ResponseBody>, Error> {
+    // The depth `infinity` is not supported, in order to avoid the expensive traversal.
+    let depth = match request.headers().get("depth").map(HeaderValue::as_bytes) {
+        Some(b"0") => 0,
+        Some(b"1") | None => 1,
+        Some(b"infinity") => {
+            return Err(tsukuyomi::error::forbidden(
+                "PROPFIND with Depth: infinity is not supported",
+            ));
+        }
+        Some(..) => return Err(tsukuyomi::error::bad_request("invalid Depth header")),
+    };
+
+    let metadata = fs::metadata(path).map_err(io_error)?;
+    let mut href = request.uri().path().to_owned();
+    if metadata.is_dir() && !href.ends_with('/') {
+        href.push('/');
+    }
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("")
+        .to_owned();
+
+    let mut entries = vec![];
+    if depth == 1 && metadata.is_dir() {
+        for child in fs::read_dir(path).map_err(io_error)? {
+            let child = child.map_err(io_error)?;
+            let name = match child.file_name().into_string() {
+                Ok(name) => name,
+                Err(..) => continue,
+            };
+            let metadata = child.metadata().map_err(io_error)?;
+            let mut child_href = format!(
+                "{}{}",
+                href,
+                utf8_percent_encode(&name, PATH_SEGMENT_ENCODE_SET)
+            );
+            if metadata.is_dir() {
+                child_href.push('/');
+            }
+            entries.push(Entry {
+                href: child_href,
+                name,
+                metadata,
+            });
+        }
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+    }
+    entries.insert(
+        0,
+        Entry {
+            href,
+            name,
+            metadata,
+        },
+    );
+
+    let mut response = Response::new(multistatus(&entries).into());
+    *response.status_mut() = StatusCode::MULTI_STATUS;
+    response.headers_mut().insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("application/xml; charset=utf-8"),
+    );
+    Ok(response)
+}
+
+fn multistatus(entries: &[Entry]) -> String {
+    let mut xml = String::from(r#"<?xml version="1.0" encoding="utf-8"?>"#);
+    xml.push_str(r#"<D:multistatus xmlns:D="DAV:">"#);
+    for entry in entries {
+        let _ = write!(
+            xml,
+            "<D:response><D:href>{}</D:href><D:propstat><D:prop>",
+            escape(&entry.href)
+        );
+        let _ = write!(
+            xml,
+            "<D:displayname>{}</D:displayname>",
+            escape(&entry.name)
+        );
+        if entry.metadata.is_dir() {
+            xml.push_str("<D:resourcetype><D:collection/></D:resourcetype>");
+        } else {
+            xml.push_str("<D:resourcetype/>");
+            let _ = write!(
+                xml,
+                "<D:getcontentlength>{}</D:getcontentlength>",
+                entry.metadata.len()
+            );
+        }
+        if let Some(modified) = last_modified(&entry.metadata) {
+            let _ = write!(xml, "<D:getlastmodified>{}</D:getlastmodified>", modified);
+        }
+        xml.push_str("</D:prop><D:status>HTTP/1.1 200 OK</D:status></D:propstat></D:response>");
+    }
+    xml.push_str("</D:multistatus>");
+    xml
+}
+
+/// Formats the modification time of the metadata in the HTTP-date format.
+#[allow(clippy::cast_possible_wrap)]
+fn last_modified(metadata: &Metadata) -> Option<String> {
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    let tm = time::at_utc(time::Timespec::new(modified.as_secs() as i64, 0));
+    Some(tm.rfc822().to_string())
+}
+
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,175 @@
+//! Streaming upload of the request body into a file, used by `PUT`.
+//!
+//! The body is written into a temporary file in the same directory, which is
+//! renamed into place when the body is completely received. Hence the existing
+//! file is left intact if the upload fails halfway.
+
+use {
+    super::{blocking, conflict, empty_response, io_error, parent_exists},
+    bytes::Bytes,
+    futures::{Async, Poll, Stream},
+    http::{Response, StatusCode},
+    std::{
+        fs::{self, File, OpenOptions},
+        io::Write,
+        path::{Path, PathBuf},
+        process,
+        sync::atomic::{AtomicUsize, Ordering},
+    },
+    tsukuyomi::{
+        error::Error,
+        input::{body::RequestBody, localmap::LocalData, Input},
+        output::ResponseBody,
+    },
+};
+
+/// A future that writes the chunks of the request body into the file
+/// as they arrive, without buffering the whole body in memory.
+#[derive(Debug)]
+pub(crate) struct Upload {
+    path: PathBuf,
+    // The temporary file being written, removed if the upload is not completed.
+    temp_path: Option<PathBuf>,
+    state: State,
+}
+
+#[derive(Debug)]
+enum State {
+    Init,
+    Writing {
+        file: File,
+        body: RequestBody,
+        pending: Option<Bytes>,
+    },
+    Committing(File),
+    Done,
+}
+
+impl Upload {
+    pub(crate) fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            temp_path: None,
+            state: State::Init,
+        }
+    }
+
+    pub(crate) fn poll_ready(
+        &mut self,
+        input: &mut Input<'_>,
+    ) -> Poll<Response<ResponseBody>, Error> {
+        loop {
+            match self.state {
+                State::Init => {
+                    let path = &self.path;
+                    let (file, temp_path) = futures::try_ready!(blocking(|| create(path)));
+                    self.temp_path = Some(temp_path);
+                    let body = RequestBody::take_from(input.locals).ok_or_else(|| {
+                        tsukuyomi::error::internal_server_error(
+                            "the request body has already been stolen",
+                        )
+                    })?;
+                    self.state = State::Writing {
+                        file,
+                        body,
+                        pending: None,
+                    };
+                }
+                State::Writing {
+                    ref mut file,
+                    ref mut body,
+                    ref mut pending,
+                } => {
+                    if pending.is_none() {
+                        match futures::try_ready!(body
+                            .poll()
+                            .map_err(tsukuyomi::error::bad_request))
+                        {
+                            Some(chunk) => *pending = Some(chunk.into()),
+                            None => {
+                                match std::mem::replace(&mut self.state, State::Done) {
+                                    State::Writing { file, .. } => {
+                                        self.state = State::Committing(file)
+                                    }
+                                    _ => unreachable!(),
+                                }
+                                continue;
+                            }
+                        }
+                    }
+                    if let Some(ref chunk) = *pending {
+                        futures::try_ready!(blocking(|| file.write_all(chunk).map_err(io_error)));
+                    }
+                    *pending = None;
+                }
+                State::Committing(ref file) => {
+                    let path = &self.path;
+                    let temp_path = self.temp_path.as_ref().expect("missing temporary file");
+                    let created = futures::try_ready!(blocking(|| commit(file, temp_path, path)));
+                    self.temp_path = None;
+                    self.state = State::Done;
+                    return Ok(Async::Ready(empty_response(if created {
+                        StatusCode::CREATED
+                    } else {
+                        StatusCode::NO_CONTENT
+                    })));
+                }
+                State::Done => panic!("the future has already polled"),
+            }
+        }
+    }
+}
+
+impl Drop for Upload {
+    fn drop(&mut self) {
+        if let Some(temp_path) = self.temp_path.take() {
+            // close the file before removing it.
+            self.state = State::Done;
+            let _ = fs::remove_file(&temp_path);
+        }
+    }
+}
+
+fn create(path: &Path) -> Result<(File, PathBuf), Error> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    if path.is_dir() {
+        return Err(tsukuyomi::error::method_not_allowed(
+            "cannot PUT to a collection",
+        ));
+    }
+    if !parent_exists(path) {
+        return Err(conflict("the parent collection does not exist"));
+    }
+
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| tsukuyomi::error::forbidden("invalid path"))?;
+    let mut temp_name = std::ffi::OsString::from(".");
+    temp_name.push(file_name);
+    temp_name.push(format!(
+        ".{}-{}.upload",
+        process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let temp_path = path.with_file_name(temp_name);
+
+    let file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&temp_path)
+        .map_err(io_error)?;
+    Ok((file, temp_path))
+}
+
+/// Moves the completely written temporary file into place, and returns
+/// whether the file has been newly created.
+fn commit(file: &File, temp_path: &Path, path: &Path) -> Result<bool, Error> {
+    file.sync_all().map_err(io_error)?;
+    if path.is_dir() {
+        return Err(conflict("the destination has become a collection"));
+    }
+    let created = !path.exists();
+    fs::rename(temp_path, path).map_err(io_error)?;
+    Ok(created)
+}
@@ -0,0 +1,267 @@
+use {
+    http::{Request, StatusCode},
+    std::{
+        fs,
+        path::PathBuf,
+        sync::atomic::{AtomicUsize, Ordering},
+    },
+    tsukuyomi::{config::prelude::*, vendor::futures::stream, App},
+    tsukuyomi_fs::WebDav,
+};
+
+#[test]
+fn test_version_sync() {
+    version_sync::assert_html_root_url_updated!("src/lib.rs");
+}
+
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new() -> Self {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "tsukuyomi-fs-{}-{}",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::SeqCst)
+        ));
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn request(method: &str, uri: &str) -> http::request::Builder {
+    let mut request = Request::builder();
+    request.method(method).uri(uri);
+    request
+}
+
+#[test]
+fn litmus_sequence() -> tsukuyomi_server::Result<()> {
+    let dir = TempDir::new();
+    let app = App::create(path!("/dav/*path").to(WebDav::new(&dir.0)))?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(request("MKCOL", "/dav/col/"))?;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert!(dir.0.join("col").is_dir());
+
+    let response = server.perform(request("MKCOL", "/dav/col/"))?;
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+    let response = server.perform(request("MKCOL", "/dav/missing/col/"))?;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    let response = server.perform(request("PUT", "/dav/col/hello%20world.txt").body("Hello"))?;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert_eq!(
+        fs::read_to_string(dir.0.join("col/hello world.txt")).unwrap(),
+        "Hello"
+    );
+
+    let response = server.perform(request("PUT", "/dav/col/hello%20world.txt").body("Hi"))?;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    let response = server.perform("/dav/col/hello%20world.txt")?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.body().to_utf8()?, "Hi");
+
+    let response = server.perform(request("PROPFIND", "/dav/col/").header("depth", "1"))?;
+    assert_eq!(response.status(), StatusCode::MULTI_STATUS);
+    let body = response.body().to_utf8()?;
+    assert!(body.contains("<D:href>/dav/col/</D:href>"), "{}", body);
+    assert!(body.contains("<D:collection/>"), "{}", body);
+    assert!(
+        body.contains("<D:href>/dav/col/hello%20world.txt</D:href>"),
+        "{}",
+        body
+    );
+    assert!(
+        body.contains("<D:getcontentlength>2</D:getcontentlength>"),
+        "{}",
+        body
+    );
+    assert!(body.contains("<D:getlastmodified>"), "{}", body);
+
+    let response = server.perform(request("PROPFIND", "/dav/col/").header("depth", "0"))?;
+    assert_eq!(response.status(), StatusCode::MULTI_STATUS);
+    assert!(!response.body().to_utf8()?.contains("hello%20world.txt"));
+
+    let response = server.perform(
+        request("MOVE", "/dav/col/hello%20world.txt")
+            .header("destination", "http://localhost/dav/moved.txt")
+            .header("host", "localhost"),
+    )?;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert!(!dir.0.join("col/hello world.txt").exists());
+    assert!(dir.0.join("moved.txt").is_file());
+
+    let response = server.perform(request("DELETE", "/dav/moved.txt"))?;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert!(!dir.0.join("moved.txt").exists());
+
+    let response = server.perform(request("DELETE", "/dav/col/"))?;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert!(!dir.0.join("col").exists());
+
+    let response = server.perform(request("DELETE", "/dav/col/"))?;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    Ok(())
+}
+
+#[test]
+fn failed_upload_keeps_existing_file() -> tsukuyomi_server::Result<()> {
+    let dir = TempDir::new();
+    fs::write(dir.0.join("file.txt"), "content").unwrap();
+    let app = App::create(path!("/dav/*path").to(WebDav::new(&dir.0)))?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let chunks: Vec<Result<_, std::io::Error>> = vec![
+        Ok("partial"),
+        Err(std::io::Error::new(
+            std::io::ErrorKind::ConnectionReset,
+            "disconnected",
+        )),
+    ];
+    let body = hyper::Body::wrap_stream(stream::iter_result(chunks));
+    let response = server.perform(request("PUT", "/dav/file.txt").body(body))?;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        fs::read_to_string(dir.0.join("file.txt")).unwrap(),
+        "content"
+    );
+    // the temporary file is removed.
+    assert_eq!(fs::read_dir(&dir.0).unwrap().count(), 1);
+
+    let response = server.perform(request("PUT", "/dav/file.txt").body("replaced"))?;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert_eq!(
+        fs::read_to_string(dir.0.join("file.txt")).unwrap(),
+        "replaced"
+    );
+    assert_eq!(fs::read_dir(&dir.0).unwrap().count(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn move_validates_destination() -> tsukuyomi_server::Result<()> {
+    let dir = TempDir::new();
+    fs::write(dir.0.join("a.txt"), "a").unwrap();
+    fs::write(dir.0.join("b.txt"), "b").unwrap();
+    let app = App::create(path!("/dav/*path").to(WebDav::new(&dir.0)))?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(request("MOVE", "/dav/a.txt"))?;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = server.perform(
+        request("MOVE", "/dav/a.txt")
+            .header("destination", "http://example.com/dav/c.txt")
+            .header("host", "localhost"),
+    )?;
+    assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+
+    for destination in &["/other/c.txt", "/dav/../c.txt", "/dav/%2E%2E/c.txt"] {
+        let response =
+            server.perform(request("MOVE", "/dav/a.txt").header("destination", *destination))?;
+        assert_eq!(
+            response.status(),
+            StatusCode::FORBIDDEN,
+            "destination = {}",
+            destination
+        );
+    }
+
+    let response = server.perform(
+        request("MOVE", "/dav/a.txt")
+            .header("destination", "/dav/b.txt")
+            .header("overwrite", "F"),
+    )?;
+    assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+
+    let response =
+        server.perform(request("MOVE", "/dav/a.txt").header("destination", "/dav/b.txt"))?;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert_eq!(fs::read_to_string(dir.0.join("b.txt")).unwrap(), "a");
+
+    Ok(())
+}
+
+#[test]
+fn rejects_traversal() -> tsukuyomi_server::Result<()> {
+    let dir = TempDir::new();
+    fs::create_dir(dir.0.join("root")).unwrap();
+    fs::write(dir.0.join("secret.txt"), "secret").unwrap();
+    let app = App::create(path!("/dav/*path").to(WebDav::new(dir.0.join("root"))))?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    for uri in &["/dav/%2E%2E/secret.txt", "/dav/a/%2e%2e/%2e%2e/secret.txt"] {
+        let response = server.perform(*uri)?;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN, "uri = {}", uri);
+
+        let response = server.perform(request("DELETE", uri))?;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN, "uri = {}", uri);
+    }
+    assert!(dir.0.join("secret.txt").exists());
+
+    Ok(())
+}
+
+#[test]
+fn read_only_rejects_mutating_methods() -> tsukuyomi_server::Result<()> {
+    let dir = TempDir::new();
+    fs::write(dir.0.join("file.txt"), "content").unwrap();
+    let app = App::create(path!("/dav/*path").to(WebDav::new(&dir.0).read_only(true)))?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    for method in &["PUT", "DELETE", "MKCOL", "MOVE"] {
+        let response = server.perform(
+            request(method, "/dav/file.txt")
+                .header("destination", "/dav/other.txt")
+                .body("new content"),
+        )?;
+        assert_eq!(
+            response.status(),
+            StatusCode::FORBIDDEN,
+            "method = {}",
+            method
+        );
+    }
+    assert_eq!(
+        fs::read_to_string(dir.0.join("file.txt")).unwrap(),
+        "content"
+    );
+
+    let response = server.perform(request("PROPFIND", "/dav/").header("depth", "1"))?;
+    assert_eq!(response.status(), StatusCode::MULTI_STATUS);
+
+    Ok(())
+}
+
+#[test]
+fn locking_is_not_implemented() -> tsukuyomi_server::Result<()> {
+    let dir = TempDir::new();
+    let app = App::create(path!("/dav/*path").to(WebDav::new(&dir.0)))?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    for method in &["LOCK", "UNLOCK"] {
+        let response = server.perform(request(method, "/dav/file.txt"))?;
+        assert_eq!(response.status(), StatusCode::NOT_IMPLEMENTED);
+    }
+
+    let response = server.perform(request("PATCH", "/dav/file.txt"))?;
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+    let response = server.perform(request("OPTIONS", "/dav/"))?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers().get("dav").unwrap(), "1");
+
+    Ok(())
+}
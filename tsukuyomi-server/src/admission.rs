@@ -0,0 +1,526 @@
+//! The admission control of the incoming connections at the listener level.
+//!
+//! The limits are applied before the connections reach the acceptor, so the
+//! excessive connections never consume the TLS handshakes nor the services.
+
+use {
+    crate::io::Listener,
+    futures::{task::AtomicTask, Async, Future, Poll, Stream},
+    std::{
+        collections::{HashMap, VecDeque},
+        net::{IpAddr, SocketAddr},
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        time::{Duration, Instant},
+    },
+    tokio::timer::Delay,
+};
+
+/// The maximum number of the entries in the list of the recent offenders.
+const MAX_OFFENDERS: usize = 64;
+
+/// How the connections exceeding `Server::max_connections` are treated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    /// The connections are accepted and closed immediately.
+    Close,
+
+    /// The connections are left in the backlog of the listener until the
+    /// number of the established connections falls below the limit.
+    Defer,
+}
+
+impl Default for Overflow {
+    fn default() -> Self {
+        Overflow::Close
+    }
+}
+
+/// The limits applied to the listener.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct AdmissionLimits {
+    pub(crate) max_connections: Option<usize>,
+    pub(crate) overflow: Overflow,
+    pub(crate) max_connections_per_ip: Option<usize>,
+    pub(crate) accept_rate: Option<(u32, u32)>,
+}
+
+/// The counters of the connections on a server.
+///
+/// The clones of this value refer to the same counters, so the handle obtained
+/// before starting the server can be used for exporting the metrics while it is running.
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionStats {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    active: AtomicUsize,
+    accepted: AtomicUsize,
+    rejected_global: AtomicUsize,
+    rejected_per_ip: AtomicUsize,
+    throttled: AtomicUsize,
+    // only the addresses with the established connections are kept.
+    per_ip: Mutex<HashMap<IpAddr, usize>>,
+    offenders: Mutex<VecDeque<(IpAddr, u64)>>,
+    released: AtomicTask,
+}
+
+/// The reason why a connection was rejected.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Rejection {
+    Global,
+    PerIp(IpAddr),
+}
+
+impl ConnectionStats {
+    /// Returns a snapshot of the counters.
+    pub fn metrics(&self) -> ConnectionMetrics {
+        let inner = &*self.inner;
+        ConnectionMetrics {
+            active: inner.active.load(Ordering::SeqCst) as u64,
+            accepted: inner.accepted.load(Ordering::Relaxed) as u64,
+            rejected_global: inner.rejected_global.load(Ordering::Relaxed) as u64,
+            rejected_per_ip: inner.rejected_per_ip.load(Ordering::Relaxed) as u64,
+            throttled: inner.throttled.load(Ordering::Relaxed) as u64,
+        }
+    }
+
+    /// Returns the number of the established connections from the specified address.
+    pub fn active_per_ip(&self, ip: IpAddr) -> usize {
+        self.inner
+            .per_ip
+            .lock()
+            .unwrap()
+            .get(&ip)
+            .cloned()
+            .unwrap_or(0)
+    }
+
+    /// Returns the addresses recently rejected by the per-IP limit, along with
+    /// the numbers of their rejections, in the order of the most recent first.
+    ///
+    /// At most 64 addresses are remembered.
+    pub fn recent_offenders(&self) -> Vec<(IpAddr, u64)> {
+        self.inner
+            .offenders
+            .lock()
+            .unwrap()
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    fn is_full(&self, limits: &AdmissionLimits) -> bool {
+        limits
+            .max_connections
+            .map_or(false, |max| self.inner.active.load(Ordering::SeqCst) >= max)
+    }
+
+    /// Registers a new connection if it is within the limits.
+    pub(crate) fn admit(
+        &self,
+        limits: &AdmissionLimits,
+        peer_addr: Option<SocketAddr>,
+    ) -> Result<Permit, Rejection> {
+        if self.is_full(limits) {
+            self.inner.rejected_global.fetch_add(1, Ordering::Relaxed);
+            return Err(Rejection::Global);
+        }
+
+        if let Some(ip) = peer_addr.map(|addr| addr.ip()) {
+            let mut per_ip = self.inner.per_ip.lock().unwrap();
+            let count = per_ip.entry(ip).or_insert(0);
+            if limits
+                .max_connections_per_ip
+                .map_or(false, |max| *count >= max)
+            {
+                if *count == 0 {
+                    per_ip.remove(&ip);
+                }
+                drop(per_ip);
+                self.inner.rejected_per_ip.fetch_add(1, Ordering::Relaxed);
+                self.record_offender(ip);
+                return Err(Rejection::PerIp(ip));
+            }
+            *count += 1;
+        }
+
+        self.inner.active.fetch_add(1, Ordering::SeqCst);
+        self.inner.accepted.fetch_add(1, Ordering::Relaxed);
+        Ok(Permit {
+            stats: self.clone(),
+            peer_addr,
+        })
+    }
+
+    fn record_offender(&self, ip: IpAddr) {
+        let mut offenders = self.inner.offenders.lock().unwrap();
+        let count = match offenders.iter().position(|&(addr, _)| addr == ip) {
+            Some(pos) => offenders.remove(pos).map_or(0, |(_, count)| count),
+            None => 0,
+        };
+        offenders.push_front((ip, count + 1));
+        offenders.truncate(MAX_OFFENDERS);
+    }
+
+    fn release(&self, ip: Option<IpAddr>) {
+        if let Some(ip) = ip {
+            let mut per_ip = self.inner.per_ip.lock().unwrap();
+            let remaining = per_ip.get_mut(&ip).map(|count| {
+                *count -= 1;
+                *count
+            });
+            if remaining == Some(0) {
+                per_ip.remove(&ip);
+            }
+        }
+        self.inner.active.fetch_sub(1, Ordering::SeqCst);
+        self.inner.released.notify();
+    }
+}
+
+/// A snapshot of the counters of the connections.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ConnectionMetrics {
+    /// The number of the established connections.
+    pub active: u64,
+
+    /// The number of the admitted connections.
+    pub accepted: u64,
+
+    /// The number of the connections rejected by `Server::max_connections`.
+    pub rejected_global: u64,
+
+    /// The number of the connections rejected by `Server::max_connections_per_ip`.
+    pub rejected_per_ip: u64,
+
+    /// The number of times the accepting was delayed by `Server::accept_rate`.
+    pub throttled: u64,
+}
+
+impl ConnectionMetrics {
+    /// Returns the pairs of the metric names prefixed with `server_connections_` and
+    /// their values, for exporting them to a metrics registry.
+    pub fn entries(&self) -> [(&'static str, u64); 5] {
+        [
+            ("server_connections_active", self.active),
+            ("server_connections_accepted_total", self.accepted),
+            (
+                "server_connections_rejected_global_total",
+                self.rejected_global,
+            ),
+            (
+                "server_connections_rejected_per_ip_total",
+                self.rejected_per_ip,
+            ),
+            ("server_connections_throttled_total", self.throttled),
+        ]
+    }
+}
+
+/// An admitted connection, released on drop.
+#[derive(Debug)]
+pub(crate) struct Permit {
+    stats: ConnectionStats,
+    peer_addr: Option<SocketAddr>,
+}
+
+impl Permit {
+    pub(crate) fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.stats.release(self.peer_addr.map(|addr| addr.ip()));
+    }
+}
+
+/// A token bucket limiting the rate of accepting the connections.
+#[derive(Debug)]
+pub(crate) struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    pub(crate) fn new(rate: u32, burst: u32, now: Instant) -> Self {
+        let burst = f64::from(burst.max(1));
+        Self {
+            rate: f64::from(rate.max(1)),
+            burst,
+            tokens: burst,
+            last: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        if now > self.last {
+            let elapsed = now - self.last;
+            let elapsed = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) * 1e-9;
+            self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+            self.last = now;
+        }
+    }
+
+    /// Returns the duration to wait until a token becomes available.
+    pub(crate) fn wait_time(&mut self, now: Instant) -> Option<Duration> {
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            None
+        } else {
+            let nanos = ((1.0 - self.tokens) / self.rate * 1e9).ceil() as u64;
+            Some(Duration::from_nanos(nanos.max(1)))
+        }
+    }
+
+    pub(crate) fn consume(&mut self, now: Instant) {
+        self.refill(now);
+        self.tokens -= 1.0;
+    }
+}
+
+/// Starts listening, and wraps the incoming connections with the admission control.
+pub(crate) fn listen<L>(
+    listener: L,
+    limits: AdmissionLimits,
+    stats: ConnectionStats,
+) -> Result<Admitted<L::Incoming>, L::Error>
+where
+    L: Listener,
+{
+    Ok(Admitted {
+        incoming: listener.listen()?,
+        peer_addr: L::peer_addr,
+        bucket: limits
+            .accept_rate
+            .map(|(rate, burst)| TokenBucket::new(rate, burst, Instant::now())),
+        delay: None,
+        limits,
+        stats,
+    })
+}
+
+/// A stream of the incoming connections within the limits.
+#[allow(missing_debug_implementations)]
+pub(crate) struct Admitted<S: Stream> {
+    incoming: S,
+    peer_addr: fn(&S::Item) -> Option<SocketAddr>,
+    limits: AdmissionLimits,
+    stats: ConnectionStats,
+    bucket: Option<TokenBucket>,
+    delay: Option<Delay>,
+}
+
+impl<S> Stream for Admitted<S>
+where
+    S: Stream,
+{
+    type Item = (S::Item, Permit);
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            if self.limits.overflow == Overflow::Defer && self.stats.is_full(&self.limits) {
+                self.stats.inner.released.register();
+                if self.stats.is_full(&self.limits) {
+                    return Ok(Async::NotReady);
+                }
+            }
+
+            if let Some(ref mut delay) = self.delay {
+                match delay.poll() {
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Ok(Async::Ready(())) => {}
+                    Err(err) => log::error!("accept rate timer error: {}", err),
+                }
+                self.delay = None;
+            }
+            if let Some(ref mut bucket) = self.bucket {
+                let now = Instant::now();
+                if let Some(wait) = bucket.wait_time(now) {
+                    self.stats.inner.throttled.fetch_add(1, Ordering::Relaxed);
+                    self.delay = Some(Delay::new(now + wait));
+                    continue;
+                }
+            }
+
+            let io = match futures::try_ready!(self.incoming.poll()) {
+                Some(io) => io,
+                None => return Ok(Async::Ready(None)),
+            };
+            if let Some(ref mut bucket) = self.bucket {
+                bucket.consume(Instant::now());
+            }
+            match self.stats.admit(&self.limits, (self.peer_addr)(&io)) {
+                Ok(permit) => return Ok(Async::Ready(Some((io, permit)))),
+                Err(Rejection::Global) => {
+                    log::debug!("closed a connection exceeding the maximum number of connections");
+                }
+                Err(Rejection::PerIp(ip)) => {
+                    log::debug!("closed a connection exceeding the per-IP limit: {}", ip);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        futures::executor::{self, Notify, NotifyHandle},
+    };
+
+    fn addr(ip: [u8; 4], port: u16) -> SocketAddr {
+        (ip, port).into()
+    }
+
+    fn admitted(
+        addrs: Vec<SocketAddr>,
+        limits: AdmissionLimits,
+    ) -> Admitted<impl Stream<Item = SocketAddr, Error = ()>> {
+        Admitted {
+            incoming: futures::stream::iter_ok(addrs),
+            peer_addr: |addr| Some(*addr),
+            limits,
+            stats: ConnectionStats::default(),
+            bucket: None,
+            delay: None,
+        }
+    }
+
+    struct Noop;
+
+    impl Notify for Noop {
+        fn notify(&self, _: usize) {}
+    }
+
+    #[test]
+    fn global_cap_rejects_excess() {
+        let limits = AdmissionLimits {
+            max_connections: Some(2),
+            ..Default::default()
+        };
+        let stats = ConnectionStats::default();
+        let first = stats.admit(&limits, Some(addr([10, 0, 0, 1], 1))).unwrap();
+        let _second = stats.admit(&limits, Some(addr([10, 0, 0, 2], 1))).unwrap();
+        assert_eq!(
+            stats
+                .admit(&limits, Some(addr([10, 0, 0, 3], 1)))
+                .unwrap_err(),
+            Rejection::Global
+        );
+
+        drop(first);
+        assert!(stats.admit(&limits, Some(addr([10, 0, 0, 3], 1))).is_ok());
+
+        let metrics = stats.metrics();
+        assert_eq!(metrics.accepted, 3);
+        assert_eq!(metrics.rejected_global, 1);
+    }
+
+    #[test]
+    fn per_ip_cap_allows_other_addresses() {
+        let limits = AdmissionLimits {
+            max_connections_per_ip: Some(1),
+            ..Default::default()
+        };
+        let mut stream = executor::spawn(admitted(
+            vec![
+                addr([10, 0, 0, 1], 1),
+                addr([10, 0, 0, 1], 2),
+                addr([10, 0, 0, 2], 1),
+            ],
+            limits,
+        ));
+        let stats = stream.get_ref().stats.clone();
+
+        let (first, _permit1) = stream.wait_stream().unwrap().unwrap();
+        let (second, _permit2) = stream.wait_stream().unwrap().unwrap();
+        assert_eq!(first, addr([10, 0, 0, 1], 1));
+        assert_eq!(second, addr([10, 0, 0, 2], 1));
+        assert!(stream.wait_stream().is_none());
+
+        assert_eq!(stats.metrics().rejected_per_ip, 1);
+        assert_eq!(
+            stats.recent_offenders(),
+            vec![(IpAddr::from([10, 0, 0, 1]), 1)]
+        );
+    }
+
+    #[test]
+    fn counters_after_close() {
+        let limits = AdmissionLimits {
+            max_connections_per_ip: Some(2),
+            ..Default::default()
+        };
+        let stats = ConnectionStats::default();
+        let ip = IpAddr::from([10, 0, 0, 1]);
+        let permits: Vec<_> = (0..2)
+            .map(|port| {
+                stats
+                    .admit(&limits, Some(addr([10, 0, 0, 1], port)))
+                    .unwrap()
+            })
+            .collect();
+        let unknown = stats.admit(&limits, None).unwrap();
+        assert_eq!(stats.metrics().active, 3);
+        assert_eq!(stats.active_per_ip(ip), 2);
+
+        // the permits are dropped along with the connection tasks, even if aborted.
+        drop(permits);
+        drop(unknown);
+        assert_eq!(stats.metrics().active, 0);
+        assert_eq!(stats.active_per_ip(ip), 0);
+        assert!(stats.inner.per_ip.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn defer_leaves_connections_in_backlog() {
+        let limits = AdmissionLimits {
+            max_connections: Some(1),
+            overflow: Overflow::Defer,
+            ..Default::default()
+        };
+        let notify = NotifyHandle::from(Arc::new(Noop));
+        let mut stream = executor::spawn(admitted(
+            vec![addr([10, 0, 0, 1], 1), addr([10, 0, 0, 2], 1)],
+            limits,
+        ));
+
+        let first = match stream.poll_stream_notify(&notify, 0) {
+            Ok(Async::Ready(Some(conn))) => conn,
+            _ => panic!("the first connection should be admitted"),
+        };
+        match stream.poll_stream_notify(&notify, 0) {
+            Ok(Async::NotReady) => {}
+            _ => panic!("the second connection should be deferred"),
+        }
+
+        drop(first);
+        match stream.poll_stream_notify(&notify, 0) {
+            Ok(Async::Ready(Some((conn, _permit)))) => assert_eq!(conn, addr([10, 0, 0, 2], 1)),
+            _ => panic!("the second connection should be admitted after the close"),
+        }
+        assert_eq!(stream.get_ref().stats.metrics().rejected_global, 0);
+    }
+
+    #[test]
+    fn token_bucket_refills() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(10, 2, now);
+        assert_eq!(bucket.wait_time(now), None);
+        bucket.consume(now);
+        bucket.consume(now);
+        assert_eq!(bucket.wait_time(now), Some(Duration::from_millis(100)));
+        assert_eq!(bucket.wait_time(now + Duration::from_millis(100)), None);
+    }
+}
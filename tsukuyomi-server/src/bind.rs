@@ -0,0 +1,111 @@
+//! Binding the listeners which accept both IPv4 and IPv6 clients.
+//!
+//! Whether the socket bound to `[::]` also accepts the IPv4 clients depends on
+//! the platform: Linux enables it by default (unless `net.ipv6.bindv6only` is set),
+//! Windows and the BSDs disable it, and OpenBSD does not support it at all.
+//! `Server::bind_dual_stack` sets `IPV6_V6ONLY` explicitly, and falls back to the
+//! separate sockets per address family where the dual-stack socket is unavailable.
+
+use {
+    net2::TcpBuilder,
+    std::{
+        fmt, io,
+        net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener},
+    },
+};
+
+/// The number of pending connections queued by the listening sockets.
+const BACKLOG: i32 = 1024;
+
+/// The way of accepting both address families chosen by `Server::bind_dual_stack`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BindStrategy {
+    /// A socket bound to `[::]` with `IPV6_V6ONLY` disabled, which accepts the IPv4
+    /// clients as the IPv4-mapped addresses.
+    DualStackSocket,
+
+    /// The sockets bound to `[::]` (with `IPV6_V6ONLY` enabled) and `0.0.0.0`
+    /// on the same port, used where the dual-stack socket is not supported.
+    SeparateSockets,
+
+    /// A socket bound to `0.0.0.0`, used where IPv6 is not available on the host.
+    Ipv4Only,
+}
+
+impl fmt::Display for BindStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BindStrategy::DualStackSocket => "dual-stack socket",
+            BindStrategy::SeparateSockets => "separate IPv4/IPv6 sockets",
+            BindStrategy::Ipv4Only => "IPv4 only",
+        })
+    }
+}
+
+/// The information about the listeners of the server, reported before it starts.
+#[derive(Debug, Clone)]
+pub struct StartupInfo {
+    pub(crate) local_addrs: Vec<SocketAddr>,
+    pub(crate) bind_strategy: Option<BindStrategy>,
+}
+
+impl StartupInfo {
+    /// Returns the local addresses of the listeners.
+    pub fn local_addrs(&self) -> &[SocketAddr] {
+        &self.local_addrs
+    }
+
+    /// Returns the strategy chosen by `Server::bind_dual_stack`.
+    ///
+    /// The value is `None` if the listeners are not bound by `bind_dual_stack`.
+    pub fn bind_strategy(&self) -> Option<BindStrategy> {
+        self.bind_strategy
+    }
+}
+
+/// Binds the listeners on the specified port of all the addresses of both families.
+///
+/// If `port` is `0`, all of the listeners share the port assigned by the OS.
+pub(crate) fn dual_stack(port: u16) -> io::Result<(Vec<TcpListener>, BindStrategy)> {
+    let v6 = match TcpBuilder::new_v6() {
+        Ok(builder) => builder,
+        // creating a socket fails only if the family is not supported by the host.
+        Err(err) => return ipv4_only(port, &err),
+    };
+
+    let dual_stack = v6.only_v6(false).is_ok();
+    if !dual_stack {
+        log::debug!("the dual-stack socket is not supported; binding the separate sockets");
+        v6.only_v6(true)?;
+    }
+    let v6 = match listen(v6, (Ipv6Addr::UNSPECIFIED, port)) {
+        Ok(listener) => listener,
+        Err(ref err) if err.kind() == io::ErrorKind::AddrNotAvailable => {
+            return ipv4_only(port, err);
+        }
+        Err(err) => return Err(err),
+    };
+    if dual_stack {
+        return Ok((vec![v6], BindStrategy::DualStackSocket));
+    }
+
+    // the IPv4 socket follows the port assigned to the IPv6 one.
+    let port = v6.local_addr()?.port();
+    let v4 = listen(TcpBuilder::new_v4()?, (Ipv4Addr::UNSPECIFIED, port))?;
+    Ok((vec![v6, v4], BindStrategy::SeparateSockets))
+}
+
+fn listen(builder: TcpBuilder, addr: impl Into<SocketAddr>) -> io::Result<TcpListener> {
+    // the same option as `std::net::TcpListener::bind`, for restarting the server
+    // while the old connections are in TIME_WAIT.
+    #[cfg(unix)]
+    builder.reuse_address(true)?;
+    builder.bind(addr.into())?;
+    builder.listen(BACKLOG)
+}
+
+fn ipv4_only(port: u16, err: &io::Error) -> io::Result<(Vec<TcpListener>, BindStrategy)> {
+    log::debug!("IPv6 is not available: {}", err);
+    let listener = listen(TcpBuilder::new_v4()?, (Ipv4Addr::UNSPECIFIED, port))?;
+    Ok((vec![listener], BindStrategy::Ipv4Only))
+}
@@ -0,0 +1,240 @@
+//! The controls of the lifetime of the connections.
+
+use {
+    futures::{Async, Future, Poll},
+    http::{
+        header::{HeaderMap, HeaderValue, CONNECTION},
+        Request, Response,
+    },
+    hyper::body::Payload,
+    std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        time::{Duration, Instant},
+    },
+    tokio::timer::Delay,
+    tsukuyomi_service::Service,
+};
+
+/// The limits applied to each connection.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct ConnectionLimits {
+    pub(crate) max_requests: Option<usize>,
+    pub(crate) keep_alive_timeout: Option<Duration>,
+}
+
+/// Marks the response as the last one on the connection.
+///
+/// hyper closes the connection after sending a response with `Connection: close`.
+pub(crate) fn set_close(headers: &mut HeaderMap) {
+    headers.insert(CONNECTION, HeaderValue::from_static("close"));
+}
+
+/// Returns `true` if the header fields ask to close the connection.
+pub(crate) fn is_close(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|token| token.trim().eq_ignore_ascii_case("close"))
+}
+
+/// The state of a connection.
+#[derive(Debug)]
+pub(crate) struct ConnState {
+    requests: AtomicUsize,
+    in_flight: AtomicUsize,
+    idle_since: Mutex<Instant>,
+}
+
+impl Default for ConnState {
+    fn default() -> Self {
+        Self {
+            requests: AtomicUsize::new(0),
+            in_flight: AtomicUsize::new(0),
+            idle_since: Mutex::new(Instant::now()),
+        }
+    }
+}
+
+impl ConnState {
+    /// Counts a new request, and returns `true` if it is the last one on the connection.
+    pub(crate) fn start_request(&self, limits: &ConnectionLimits) -> bool {
+        let count = self.requests.fetch_add(1, Ordering::SeqCst) + 1;
+        limits.max_requests.map_or(false, |max| count >= max)
+    }
+}
+
+/// Wraps the service for a connection with the limits, and creates the future
+/// that completes when the connection should be closed for being idle.
+pub(crate) fn limit<S>(service: S, limits: ConnectionLimits) -> (LimitedService<S>, Watchdog) {
+    let state = Arc::new(ConnState::default());
+    let watchdog = Watchdog {
+        timeout: limits.keep_alive_timeout,
+        state: state.clone(),
+        delay: None,
+    };
+    let service = LimitedService {
+        service,
+        limits,
+        state,
+    };
+    (service, watchdog)
+}
+
+#[allow(missing_debug_implementations)]
+pub(crate) struct LimitedService<S> {
+    service: S,
+    limits: ConnectionLimits,
+    state: Arc<ConnState>,
+}
+
+impl<S, Bd> Service<Request<hyper::Body>> for LimitedService<S>
+where
+    S: Service<Request<hyper::Body>, Response = Response<Bd>>,
+    Bd: Payload,
+{
+    type Response = Response<Tracked<Bd>>;
+    type Error = S::Error;
+    type Future = LimitedFuture<S::Future>;
+
+    #[inline]
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.service.poll_ready()
+    }
+
+    fn call(&mut self, request: Request<hyper::Body>) -> Self::Future {
+        let close = self.state.start_request(&self.limits);
+        LimitedFuture {
+            future: self.service.call(request),
+            close,
+            in_flight: Some(InFlight::new(&self.state)),
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub(crate) struct LimitedFuture<F> {
+    future: F,
+    close: bool,
+    in_flight: Option<InFlight>,
+}
+
+impl<F, Bd> Future for LimitedFuture<F>
+where
+    F: Future<Item = Response<Bd>>,
+{
+    type Item = Response<Tracked<Bd>>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let mut response = futures::try_ready!(self.future.poll());
+        if self.close {
+            set_close(response.headers_mut());
+        }
+        let in_flight = self.in_flight.take();
+        Ok(Async::Ready(response.map(|body| Tracked {
+            body,
+            _in_flight: in_flight,
+        })))
+    }
+}
+
+/// A request being processed, including the transmission of its response body.
+#[derive(Debug)]
+struct InFlight(Arc<ConnState>);
+
+impl InFlight {
+    fn new(state: &Arc<ConnState>) -> Self {
+        state.in_flight.fetch_add(1, Ordering::SeqCst);
+        InFlight(state.clone())
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        *self.0.idle_since.lock().unwrap() = Instant::now();
+        self.0.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// A response body that keeps the request in flight until it is dropped.
+#[derive(Debug)]
+pub(crate) struct Tracked<Bd> {
+    body: Bd,
+    _in_flight: Option<InFlight>,
+}
+
+impl<Bd: Payload> Payload for Tracked<Bd> {
+    type Data = Bd::Data;
+    type Error = Bd::Error;
+
+    #[inline]
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        self.body.poll_data()
+    }
+
+    #[inline]
+    fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, Self::Error> {
+        self.body.poll_trailers()
+    }
+
+    #[inline]
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream()
+    }
+
+    #[inline]
+    fn content_length(&self) -> Option<u64> {
+        self.body.content_length()
+    }
+}
+
+/// A future that completes when the connection has been idle longer than the keep-alive timeout.
+///
+/// It never completes while a request is in flight, or if no timeout is set.
+#[allow(missing_debug_implementations)]
+pub(crate) struct Watchdog {
+    timeout: Option<Duration>,
+    state: Arc<ConnState>,
+    delay: Option<Delay>,
+}
+
+impl Future for Watchdog {
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let timeout = match self.timeout {
+            Some(timeout) => timeout,
+            None => return Ok(Async::NotReady),
+        };
+        loop {
+            let now = Instant::now();
+            let deadline = if self.state.in_flight.load(Ordering::SeqCst) > 0 {
+                now + timeout
+            } else {
+                let deadline = *self.state.idle_since.lock().unwrap() + timeout;
+                if deadline <= now {
+                    return Ok(Async::Ready(()));
+                }
+                deadline
+            };
+
+            let delay = self.delay.get_or_insert_with(|| Delay::new(deadline));
+            delay.reset(deadline);
+            match delay.poll() {
+                Ok(Async::Ready(())) => continue,
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Err(err) => {
+                    log::error!("keep-alive timer error: {}", err);
+                    self.timeout = None;
+                    return Ok(Async::NotReady);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,320 @@
+//! A connector of the HTTP client racing the connection attempts to the upstream
+//! addresses, in the manner of Happy Eyeballs ([RFC 8305]).
+//!
+//! When a host has both IPv4 and IPv6 addresses, one of them may be unreachable
+//! without refusing the connection, and the connection attempt to it hangs until
+//! the TCP timeout. The connector starts the next attempt after a short delay,
+//! alternating the address families, and uses whichever connection is established first.
+//!
+//! [RFC 8305]: https://tools.ietf.org/html/rfc8305
+
+use {
+    futures::{Async, Future, IntoFuture, Poll},
+    hyper::client::connect::{
+        dns::{GaiResolver, Name, Resolve},
+        Connect, Connected, Destination,
+    },
+    std::{
+        collections::VecDeque,
+        fmt, io,
+        net::{IpAddr, SocketAddr},
+        time::{Duration, Instant},
+    },
+    tokio::{net::TcpStream, timer::Delay},
+};
+
+/// The default delay between the connection attempts, recommended by RFC 8305.
+fn default_attempt_delay() -> Duration {
+    Duration::from_millis(250)
+}
+
+/// A `Connect` for `hyper::Client` establishing the TCP connections with Happy Eyeballs.
+///
+/// ```no_run
+/// # use tsukuyomi_server::connect::HappyEyeballs;
+/// # use std::time::Duration;
+/// let connector = HappyEyeballs::new().attempt_delay(Duration::from_millis(100));
+/// let client = hyper::Client::builder().build::<_, hyper::Body>(connector);
+/// # drop(client);
+/// ```
+pub struct HappyEyeballs<R = GaiResolver> {
+    resolver: R,
+    attempt_delay: Duration,
+    nodelay: bool,
+}
+
+impl<R> fmt::Debug for HappyEyeballs<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HappyEyeballs")
+            .field("attempt_delay", &self.attempt_delay)
+            .field("nodelay", &self.nodelay)
+            .finish()
+    }
+}
+
+impl HappyEyeballs {
+    /// Creates a `HappyEyeballs` resolving the host names with `getaddrinfo`
+    /// on a background thread pool.
+    pub fn new() -> Self {
+        Self::with_resolver(GaiResolver::new(4))
+    }
+}
+
+impl Default for HappyEyeballs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<R> HappyEyeballs<R> {
+    /// Creates a `HappyEyeballs` with the specified resolver of the host names.
+    pub fn with_resolver(resolver: R) -> Self {
+        Self {
+            resolver,
+            attempt_delay: default_attempt_delay(),
+            nodelay: false,
+        }
+    }
+
+    /// Sets the delay before starting the next connection attempt while the
+    /// previous ones are in progress.
+    ///
+    /// The default value is 250 milliseconds.
+    pub fn attempt_delay(self, attempt_delay: Duration) -> Self {
+        Self {
+            attempt_delay,
+            ..self
+        }
+    }
+
+    /// Sets whether to set `TCP_NODELAY` on the established connections.
+    pub fn nodelay(self, nodelay: bool) -> Self {
+        Self { nodelay, ..self }
+    }
+}
+
+impl<R> Connect for HappyEyeballs<R>
+where
+    R: Resolve + Send + Sync,
+    R::Addrs: 'static,
+    R::Future: Send + 'static,
+{
+    type Transport = TcpStream;
+    type Error = io::Error;
+    type Future = Box<dyn Future<Item = (TcpStream, Connected), Error = io::Error> + Send>;
+
+    fn connect(&self, dst: Destination) -> Self::Future {
+        let port = dst
+            .port()
+            .unwrap_or_else(|| if dst.scheme() == "https" { 443 } else { 80 });
+        let host = dst.host().trim_start_matches('[').trim_end_matches(']');
+
+        let addrs: Box<dyn Future<Item = Vec<IpAddr>, Error = io::Error> + Send> =
+            match host.parse::<IpAddr>() {
+                Ok(addr) => Box::new(futures::future::ok(vec![addr])),
+                Err(..) => match host.parse::<Name>() {
+                    Ok(name) => Box::new(self.resolver.resolve(name).map(Iterator::collect)),
+                    Err(err) => Box::new(futures::future::err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        err,
+                    ))),
+                },
+            };
+
+        let attempt_delay = self.attempt_delay;
+        let nodelay = self.nodelay;
+        Box::new(
+            addrs
+                .and_then(move |addrs| {
+                    let addrs = addrs.into_iter().map(|addr| SocketAddr::new(addr, port));
+                    race(addrs, attempt_delay, |addr| TcpStream::connect(&addr))
+                })
+                .and_then(move |(stream, addr)| {
+                    log::debug!("connected to {}", addr);
+                    stream.set_nodelay(nodelay)?;
+                    Ok((stream, Connected::new()))
+                }),
+        )
+    }
+}
+
+/// Races the connection attempts to `addrs`, started one by one at the interval
+/// of `attempt_delay`.
+///
+/// The addresses are reordered so that the address families alternate, starting
+/// with the family of the first address. The next attempt is also started as soon
+/// as the previous one fails. The returned future resolves to the first established
+/// connection along with its address, and the other attempts in progress are cancelled
+/// by dropping them. If all of the attempts fail, the last error is returned.
+pub fn race<F, T>(
+    addrs: impl IntoIterator<Item = SocketAddr>,
+    attempt_delay: Duration,
+    connect: F,
+) -> Race<F, T::Future>
+where
+    F: FnMut(SocketAddr) -> T,
+    T: IntoFuture<Error = io::Error>,
+{
+    Race {
+        pending: interleave(addrs),
+        attempts: vec![],
+        connect,
+        attempt_delay,
+        timer: None,
+        start_next: true,
+        last_error: None,
+    }
+}
+
+/// Reorders the addresses so that the IPv4 and IPv6 ones alternate.
+fn interleave(addrs: impl IntoIterator<Item = SocketAddr>) -> VecDeque<SocketAddr> {
+    let mut addrs = addrs.into_iter().peekable();
+    let preferred_v6 = match addrs.peek() {
+        Some(addr) => addr.is_ipv6(),
+        None => return VecDeque::new(),
+    };
+    let (preferred, fallback): (VecDeque<_>, VecDeque<_>) =
+        addrs.partition(|addr| addr.is_ipv6() == preferred_v6);
+
+    let mut interleaved = VecDeque::with_capacity(preferred.len() + fallback.len());
+    let mut preferred = preferred.into_iter();
+    let mut fallback = fallback.into_iter();
+    loop {
+        match (preferred.next(), fallback.next()) {
+            (None, None) => break,
+            (addr1, addr2) => interleaved.extend(addr1.into_iter().chain(addr2)),
+        }
+    }
+    interleaved
+}
+
+/// The future returned from `race`.
+#[must_use = "futures do nothing unless polled"]
+pub struct Race<F, Fut> {
+    pending: VecDeque<SocketAddr>,
+    attempts: Vec<(SocketAddr, Fut)>,
+    connect: F,
+    attempt_delay: Duration,
+    timer: Option<Delay>,
+    start_next: bool,
+    last_error: Option<io::Error>,
+}
+
+impl<F, Fut> fmt::Debug for Race<F, Fut> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Race")
+            .field("pending", &self.pending)
+            .field(
+                "attempts",
+                &self
+                    .attempts
+                    .iter()
+                    .map(|(addr, _)| addr)
+                    .collect::<Vec<_>>(),
+            )
+            .field("attempt_delay", &self.attempt_delay)
+            .finish()
+    }
+}
+
+impl<F, T> Future for Race<F, T::Future>
+where
+    F: FnMut(SocketAddr) -> T,
+    T: IntoFuture<Error = io::Error>,
+{
+    type Item = (T::Item, SocketAddr);
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            if self.start_next {
+                self.start_next = false;
+                if let Some(addr) = self.pending.pop_front() {
+                    log::trace!("start connecting to {}", addr);
+                    let attempt = (self.connect)(addr).into_future();
+                    self.attempts.push((addr, attempt));
+                    self.timer = if self.pending.is_empty() {
+                        None
+                    } else {
+                        Some(Delay::new(Instant::now() + self.attempt_delay))
+                    };
+                }
+            }
+
+            let mut i = 0;
+            while i < self.attempts.len() {
+                match self.attempts[i].1.poll() {
+                    Ok(Async::Ready(conn)) => {
+                        let (addr, _) = self.attempts.swap_remove(i);
+                        // the losers are cancelled by dropping them.
+                        self.attempts.clear();
+                        self.pending.clear();
+                        return Ok(Async::Ready((conn, addr)));
+                    }
+                    Ok(Async::NotReady) => i += 1,
+                    Err(err) => {
+                        let (addr, _) = self.attempts.remove(i);
+                        log::debug!("failed to connect to {}: {}", addr, err);
+                        self.last_error = Some(err);
+                        self.start_next = true;
+                    }
+                }
+            }
+
+            if self.attempts.is_empty() && self.pending.is_empty() {
+                return Err(self.last_error.take().unwrap_or_else(|| {
+                    io::Error::new(io::ErrorKind::AddrNotAvailable, "no address to connect")
+                }));
+            }
+            if self.start_next {
+                continue;
+            }
+
+            match self.timer {
+                Some(ref mut timer) => {
+                    futures::try_ready!(timer
+                        .poll()
+                        .map_err(|err| io::Error::new(io::ErrorKind::Other, err)));
+                    self.start_next = true;
+                }
+                None => return Ok(Async::NotReady),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addrs(addrs: &[&str]) -> Vec<SocketAddr> {
+        addrs.iter().map(|addr| addr.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn interleave_address_families() {
+        let interleaved = interleave(addrs(&[
+            "[::1]:80",
+            "[::2]:80",
+            "[::3]:80",
+            "127.0.0.1:80",
+            "127.0.0.2:80",
+        ]));
+        assert_eq!(
+            interleaved.into_iter().collect::<Vec<_>>(),
+            addrs(&[
+                "[::1]:80",
+                "127.0.0.1:80",
+                "[::2]:80",
+                "127.0.0.2:80",
+                "[::3]:80",
+            ])
+        );
+
+        let interleaved = interleave(addrs(&["127.0.0.1:80", "[::1]:80", "127.0.0.2:80"]));
+        assert_eq!(
+            interleaved.into_iter().collect::<Vec<_>>(),
+            addrs(&["127.0.0.1:80", "[::1]:80", "127.0.0.2:80"])
+        );
+    }
+}
@@ -0,0 +1,209 @@
+//! The listeners bound by another process, for handing them off between the
+//! processes without dropping the connections.
+//!
+//! The listening sockets are passed by the process manager (e.g. the socket
+//! activation of systemd) or inherited from the previous instance of the server.
+//! The new process starts accepting the connections on the same sockets while
+//! the old one finishes the in-flight requests with the graceful shutdown.
+
+use {
+    crate::{
+        bind::{BindStrategy, StartupInfo},
+        io::Listener,
+    },
+    futures::Stream,
+    std::{io, net::SocketAddr},
+    tokio::{
+        net::{TcpListener, TcpStream},
+        reactor::Handle,
+    },
+};
+
+/// A set of the pre-bound TCP listeners, used by `Server::from_listener`.
+///
+/// The connections accepted by all of the listeners are served by one server.
+#[derive(Debug)]
+pub struct InheritedListeners {
+    listeners: Vec<std::net::TcpListener>,
+    local_addrs: Vec<SocketAddr>,
+    bind_strategy: Option<BindStrategy>,
+}
+
+impl InheritedListeners {
+    /// Creates an `InheritedListeners` from the pre-bound listeners.
+    ///
+    /// The listeners are switched into non-blocking mode, as required by the
+    /// event loop. This function returns an error if `listeners` is empty, or
+    /// any of them is not a bound socket or cannot be switched into non-blocking mode.
+    pub fn new(listeners: Vec<std::net::TcpListener>) -> io::Result<Self> {
+        if listeners.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "no listener is given",
+            ));
+        }
+        let local_addrs = listeners
+            .iter()
+            .map(|listener| {
+                let addr = listener.local_addr()?;
+                listener.set_nonblocking(true)?;
+                Ok(addr)
+            })
+            .collect::<io::Result<_>>()?;
+        Ok(Self {
+            listeners,
+            local_addrs,
+            bind_strategy: None,
+        })
+    }
+
+    /// Binds the listeners accepting both IPv4 and IPv6 clients on the specified port,
+    /// as `Server::bind_dual_stack` does.
+    pub fn dual_stack(port: u16) -> io::Result<Self> {
+        let (listeners, bind_strategy) = crate::bind::dual_stack(port)?;
+        Ok(Self {
+            bind_strategy: Some(bind_strategy),
+            ..Self::new(listeners)?
+        })
+    }
+
+    /// Creates an `InheritedListeners` from the sockets passed by the socket activation
+    /// of systemd, described by the environment variables `LISTEN_PID` and `LISTEN_FDS`.
+    ///
+    /// The environment variables are removed so that they are not inherited by the
+    /// child processes, and the sockets are marked as close-on-exec.
+    #[cfg(all(unix, feature = "systemd"))]
+    pub fn from_systemd() -> io::Result<Self> {
+        use std::os::unix::io::FromRawFd;
+
+        let fds = systemd::listen_fds(|name| std::env::var(name).ok(), std::process::id())?;
+        for name in &["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+            std::env::remove_var(name);
+        }
+        let listeners = fds
+            .map(|fd| unsafe {
+                libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+                std::net::TcpListener::from_raw_fd(fd)
+            })
+            .collect();
+        Self::new(listeners)
+    }
+
+    /// Returns the local addresses of the listeners, in the given order.
+    ///
+    /// The addresses are the actual ones to which the sockets are bound, and can be
+    /// used to report the port assigned by the OS to the listener bound to port `0`.
+    pub fn local_addrs(&self) -> &[SocketAddr] {
+        &self.local_addrs
+    }
+
+    /// Returns the information about these listeners, including the strategy
+    /// chosen by `dual_stack`.
+    pub fn startup_info(&self) -> StartupInfo {
+        StartupInfo {
+            local_addrs: self.local_addrs.clone(),
+            bind_strategy: self.bind_strategy,
+        }
+    }
+}
+
+impl Listener for InheritedListeners {
+    type Conn = TcpStream;
+    type Error = io::Error;
+    type Incoming = Box<dyn Stream<Item = TcpStream, Error = io::Error> + Send>;
+
+    fn listen(self) -> io::Result<Self::Incoming> {
+        let mut incoming: Option<Self::Incoming> = None;
+        let bind_strategy = self.bind_strategy;
+        for (listener, addr) in self.listeners.into_iter().zip(self.local_addrs) {
+            let stream = TcpListener::from_std(listener, &Handle::default())?.incoming();
+            match bind_strategy {
+                Some(strategy) => log::info!("listening on {} ({})", addr, strategy),
+                None => log::info!("listening on {} (inherited)", addr),
+            }
+            incoming = Some(match incoming {
+                Some(incoming) => Box::new(incoming.select(stream)),
+                None => Box::new(stream),
+            });
+        }
+        Ok(incoming.expect("the listeners should not be empty"))
+    }
+
+    #[inline]
+    fn peer_addr(conn: &Self::Conn) -> Option<SocketAddr> {
+        conn.peer_addr().ok()
+    }
+}
+
+#[cfg(all(unix, any(test, feature = "systemd")))]
+mod systemd {
+    use std::{io, ops::Range, os::unix::io::RawFd};
+
+    /// The first file descriptor passed by the socket activation.
+    const SD_LISTEN_FDS_START: RawFd = 3;
+
+    fn invalid(message: String) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidInput, message)
+    }
+
+    /// Returns the range of the file descriptors passed to the process `pid`,
+    /// from the environment variables read by `var`.
+    pub(super) fn listen_fds(
+        var: impl Fn(&str) -> Option<String>,
+        pid: u32,
+    ) -> io::Result<Range<RawFd>> {
+        let listen_pid = var("LISTEN_PID")
+            .ok_or_else(|| invalid("LISTEN_PID is not set".into()))?
+            .trim()
+            .parse::<u32>()
+            .map_err(|err| invalid(format!("invalid LISTEN_PID: {}", err)))?;
+        if listen_pid != pid {
+            return Err(invalid(format!(
+                "the sockets are passed to another process (LISTEN_PID={})",
+                listen_pid
+            )));
+        }
+
+        let listen_fds = var("LISTEN_FDS")
+            .ok_or_else(|| invalid("LISTEN_FDS is not set".into()))?
+            .trim()
+            .parse::<RawFd>()
+            .map_err(|err| invalid(format!("invalid LISTEN_FDS: {}", err)))?;
+        if listen_fds <= 0 {
+            return Err(invalid("no socket is passed (LISTEN_FDS=0)".into()));
+        }
+
+        Ok(SD_LISTEN_FDS_START..SD_LISTEN_FDS_START + listen_fds)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use {super::*, std::collections::HashMap};
+
+        fn parse(vars: &[(&str, &str)], pid: u32) -> io::Result<Range<RawFd>> {
+            let vars: HashMap<_, _> = vars.iter().cloned().collect();
+            listen_fds(|name| vars.get(name).map(|&value| value.to_owned()), pid)
+        }
+
+        #[test]
+        fn listen_fds_passed_to_this_process() {
+            let fds = parse(&[("LISTEN_PID", "42"), ("LISTEN_FDS", "2")], 42).unwrap();
+            assert_eq!(fds, 3..5);
+        }
+
+        #[test]
+        fn listen_fds_rejects_invalid_environment() {
+            for vars in &[
+                &[][..],
+                &[("LISTEN_FDS", "2")][..],
+                &[("LISTEN_PID", "43"), ("LISTEN_FDS", "2")][..],
+                &[("LISTEN_PID", "x"), ("LISTEN_FDS", "2")][..],
+                &[("LISTEN_PID", "42")][..],
+                &[("LISTEN_PID", "42"), ("LISTEN_FDS", "0")][..],
+                &[("LISTEN_PID", "42"), ("LISTEN_FDS", "-1")][..],
+            ] {
+                assert!(parse(vars, 42).is_err(), "{:?}", vars);
+            }
+        }
+    }
+}
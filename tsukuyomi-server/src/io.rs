@@ -1,7 +1,9 @@
 use {
     crate::CritError,
-    futures::{Future, IntoFuture, Stream},
+    futures::{Async, Future, IntoFuture, Poll, Stream},
+    std::net::SocketAddr,
     tokio::io::{AsyncRead, AsyncWrite},
+    tsukuyomi_service::ConnectionInfo,
 };
 
 /// A trait that represents the low-level I/O.
@@ -12,6 +14,15 @@ pub trait Listener {
 
     /// Creates a `Stream` of asynchronous I/Os.
     fn listen(self) -> Result<Self::Incoming, Self::Error>;
+
+    /// Returns the address of the peer of the connection, if available.
+    ///
+    /// The per-IP limits of the server are not applied to the connections
+    /// without the address.
+    #[allow(unused_variables)]
+    fn peer_addr(conn: &Self::Conn) -> Option<SocketAddr> {
+        None
+    }
 }
 
 /// A trait that represents the conversion of asynchronous I/Os.
@@ -23,6 +34,53 @@ pub trait Acceptor<T> {
     type Accept: Future<Item = Self::Conn, Error = Self::Error>;
 
     fn accept(&self, io: T) -> Self::Accept;
+
+    /// Fills the information about the established connection, such as the
+    /// parameters negotiated in the TLS handshake.
+    #[allow(unused_variables)]
+    fn connection_info(conn: &Self::Conn, info: &mut ConnectionInfo) {}
+}
+
+/// Accepts the I/O, and collects the information about the connection if the
+/// peer address is available.
+pub(crate) fn accept_with_info<A, T>(
+    acceptor: &A,
+    io: T,
+    peer_addr: Option<SocketAddr>,
+) -> AcceptWithInfo<A::Accept, A::Conn>
+where
+    A: Acceptor<T>,
+{
+    AcceptWithInfo {
+        accept: acceptor.accept(io),
+        peer_addr,
+        fill: A::connection_info,
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub(crate) struct AcceptWithInfo<F, C> {
+    accept: F,
+    peer_addr: Option<SocketAddr>,
+    fill: fn(&C, &mut ConnectionInfo),
+}
+
+impl<F> Future for AcceptWithInfo<F, F::Item>
+where
+    F: Future,
+{
+    type Item = (F::Item, Option<ConnectionInfo>);
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let conn = futures::try_ready!(self.accept.poll());
+        let info = self.peer_addr.map(|peer_addr| {
+            let mut info = ConnectionInfo::new(peer_addr);
+            (self.fill)(&conn, &mut info);
+            info
+        });
+        Ok(Async::Ready((conn, info)))
+    }
 }
 
 impl<F, T, R> Acceptor<T> for F
@@ -74,6 +132,11 @@ mod tcp {
         fn listen(self) -> io::Result<Self::Incoming> {
             (&self).listen()
         }
+
+        #[inline]
+        fn peer_addr(conn: &Self::Conn) -> Option<SocketAddr> {
+            conn.peer_addr().ok()
+        }
     }
 
     impl<'a> Listener for &'a SocketAddr {
@@ -85,6 +148,11 @@ mod tcp {
         fn listen(self) -> io::Result<Self::Incoming> {
             Ok(TcpListener::bind(self)?.incoming())
         }
+
+        #[inline]
+        fn peer_addr(conn: &Self::Conn) -> Option<SocketAddr> {
+            conn.peer_addr().ok()
+        }
     }
 
     impl Listener for std::net::TcpListener {
@@ -97,6 +165,11 @@ mod tcp {
             let listener = TcpListener::from_std(self, &Handle::current())?;
             Ok(listener.incoming())
         }
+
+        #[inline]
+        fn peer_addr(conn: &Self::Conn) -> Option<SocketAddr> {
+            conn.peer_addr().ok()
+        }
     }
 
     impl Listener for TcpListener {
@@ -108,6 +181,11 @@ mod tcp {
         fn listen(self) -> io::Result<Self::Incoming> {
             Ok(self.incoming())
         }
+
+        #[inline]
+        fn peer_addr(conn: &Self::Conn) -> Option<SocketAddr> {
+            conn.peer_addr().ok()
+        }
     }
 }
 
@@ -211,6 +289,7 @@ mod rustls {
         rustls::ServerSession,
         tokio::io::{AsyncRead, AsyncWrite},
         tokio_rustls::{Accept, TlsAcceptor, TlsStream},
+        tsukuyomi_service::ConnectionInfo,
     };
 
     impl<T> Acceptor<T> for TlsAcceptor
@@ -225,6 +304,10 @@ mod rustls {
         fn accept(&self, io: T) -> Self::Accept {
             self.accept(io)
         }
+
+        fn connection_info(conn: &Self::Conn, info: &mut ConnectionInfo) {
+            crate::tls::session_info(conn.get_ref().1, info);
+        }
     }
 }
 
@@ -13,25 +13,56 @@
 )]
 #![forbid(clippy::unimplemented)]
 
+mod admission;
+mod bind;
+mod conn;
+pub mod connect;
 mod error;
+mod inherit;
 mod io;
+mod reload;
 pub mod rt;
+#[cfg(feature = "signals")]
+mod signal;
 pub mod test;
+#[cfg(feature = "use-rustls")]
+pub mod tls;
+mod warmup;
 
 pub use crate::{
+    admission::{ConnectionMetrics, ConnectionStats, Overflow},
+    bind::{BindStrategy, StartupInfo},
     error::{Error, Result},
+    inherit::InheritedListeners,
     io::{Acceptor, Listener},
+    reload::ReloadCallbacks,
+    warmup::{Warmup, WarmupEntry, WarmupReport},
 };
 
+#[cfg(feature = "signals")]
+pub use crate::signal::SignalConfig;
+
 use {
-    futures::{Future, Poll, Stream},
+    crate::{admission::AdmissionLimits, conn::ConnectionLimits},
+    futures::{task::AtomicTask, Future, Poll, Stream},
     http::{Request, Response},
     hyper::{
         body::{Body, Payload},
         server::conn::Http,
     },
-    std::{marker::PhantomData, net::SocketAddr, rc::Rc, sync::Arc},
-    tsukuyomi_service::{MakeServiceRef, Service},
+    std::{
+        fmt,
+        marker::PhantomData,
+        net::SocketAddr,
+        rc::Rc,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::{Duration, Instant},
+    },
+    tokio::timer::Interval,
+    tsukuyomi_service::{ConnectionInfo, MakeServiceRef, Service},
 };
 
 type CritError = Box<dyn std::error::Error + Send + Sync + 'static>;
@@ -43,7 +74,12 @@ pub struct Server<S, L = SocketAddr, A = (), R = tokio::runtime::Runtime> {
     listener: L,
     acceptor: A,
     protocol: Http,
+    connection: ConnectionLimits,
+    admission: AdmissionLimits,
+    stats: ConnectionStats,
     runtime: Option<R>,
+    background: Background,
+    warmup: Option<PendingWarmup<S>>,
 }
 
 impl<S> Server<S> {
@@ -54,11 +90,51 @@ impl<S> Server<S> {
             listener: ([127, 0, 0, 1], 4000).into(),
             acceptor: (),
             protocol: Http::new(),
+            connection: ConnectionLimits::default(),
+            admission: AdmissionLimits::default(),
+            stats: ConnectionStats::default(),
             runtime: None,
+            background: Background::default(),
+            warmup: None,
         }
     }
 }
 
+impl<S> Server<S, InheritedListeners> {
+    /// Creates a `Server` accepting the connections on the pre-bound listener,
+    /// such as the one inherited from the previous instance of the server.
+    ///
+    /// The listener is switched into non-blocking mode, and the server skips binding
+    /// the address. Combined with the graceful shutdown of the previous instance,
+    /// the listener is handed off without dropping the connections.
+    pub fn from_listener(make_service: S, listener: std::net::TcpListener) -> crate::Result<Self> {
+        Self::from_listeners(make_service, vec![listener])
+    }
+
+    /// Creates a `Server` accepting the connections on all of the pre-bound listeners.
+    pub fn from_listeners(
+        make_service: S,
+        listeners: Vec<std::net::TcpListener>,
+    ) -> crate::Result<Self> {
+        Ok(Server::new(make_service).bind(InheritedListeners::new(listeners)?))
+    }
+
+    /// Creates a `Server` accepting the connections on the sockets passed by the
+    /// socket activation of systemd.
+    #[cfg(all(unix, feature = "systemd"))]
+    pub fn from_systemd(make_service: S) -> crate::Result<Self> {
+        Ok(Server::new(make_service).bind(InheritedListeners::from_systemd()?))
+    }
+}
+
+impl<S, A, R> Server<S, InheritedListeners, A, R> {
+    /// Returns the information about the listeners of this server, such as the
+    /// ports assigned by the OS and the strategy chosen by `bind_dual_stack`.
+    pub fn startup_info(&self) -> StartupInfo {
+        self.listener.startup_info()
+    }
+}
+
 impl<S, L, A, R> Server<S, L, A, R> {
     /// Sets the transport used by the server.
     ///
@@ -72,10 +148,25 @@ impl<S, L, A, R> Server<S, L, A, R> {
             listener,
             acceptor: self.acceptor,
             protocol: self.protocol,
+            connection: self.connection,
+            admission: self.admission,
+            stats: self.stats,
             runtime: self.runtime,
+            background: self.background,
+            warmup: self.warmup,
         }
     }
 
+    /// Binds the listeners accepting both IPv4 and IPv6 clients on the specified port.
+    ///
+    /// Whether a socket bound to `[::]` accepts the IPv4 clients differs between the
+    /// platforms. This method disables `IPV6_V6ONLY` explicitly, and binds the separate
+    /// sockets for each address family if the platform does not support it, or only
+    /// `0.0.0.0` if IPv6 is not available. The chosen strategy is reported by `startup_info`.
+    pub fn bind_dual_stack(self, port: u16) -> crate::Result<Server<S, InheritedListeners, A, R>> {
+        Ok(self.bind(InheritedListeners::dual_stack(port)?))
+    }
+
     /// Sets the instance of `Acceptor` to the server.
     ///
     /// By default, the raw acceptor is set, which returns the incoming
@@ -90,7 +181,12 @@ impl<S, L, A, R> Server<S, L, A, R> {
             listener: self.listener,
             acceptor,
             protocol: self.protocol,
+            connection: self.connection,
+            admission: self.admission,
+            stats: self.stats,
             runtime: self.runtime,
+            background: self.background,
+            warmup: self.warmup,
         }
     }
 
@@ -101,6 +197,73 @@ impl<S, L, A, R> Server<S, L, A, R> {
         Self { protocol, ..self }
     }
 
+    /// Sets the maximum number of requests served on a connection.
+    ///
+    /// The response to the last request has `Connection: close`, and the
+    /// connection is closed after it is sent. This is useful for spreading
+    /// the load over the servers behind a load balancer.
+    ///
+    /// By default, the number of requests is not limited.
+    pub fn max_requests_per_connection(mut self, max_requests: usize) -> Self {
+        self.connection.max_requests = Some(max_requests);
+        self
+    }
+
+    /// Sets the duration to keep an idle connection open for the next request.
+    ///
+    /// The connection is closed if no request is in flight for this duration,
+    /// including the time before the first request. The transmission of a
+    /// response body is regarded as in flight.
+    ///
+    /// By default, the idle connections are kept open until the client closes them.
+    pub fn keep_alive_timeout(mut self, timeout: Duration) -> Self {
+        self.connection.keep_alive_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the maximum number of the concurrent connections.
+    ///
+    /// The connections exceeding the limit are treated according to
+    /// `connection_overflow`. By default, the number of connections is not limited.
+    pub fn max_connections(mut self, max_connections: usize) -> Self {
+        self.admission.max_connections = Some(max_connections);
+        self
+    }
+
+    /// Sets how the connections exceeding `max_connections` are treated.
+    ///
+    /// The default value is `Overflow::Close`.
+    pub fn connection_overflow(mut self, overflow: Overflow) -> Self {
+        self.admission.overflow = overflow;
+        self
+    }
+
+    /// Sets the maximum number of the concurrent connections from a client IP address.
+    ///
+    /// The excessive connections are closed immediately, and the addresses are
+    /// recorded as the recent offenders in `ConnectionStats`. The limit is not
+    /// applied to the listeners which cannot tell the peer addresses, such as
+    /// Unix domain sockets. By default, the number of connections is not limited.
+    pub fn max_connections_per_ip(mut self, max_connections: usize) -> Self {
+        self.admission.max_connections_per_ip = Some(max_connections);
+        self
+    }
+
+    /// Limits the rate of accepting the connections, by a token bucket refilled
+    /// `per_second` times a second and holding up to `burst` tokens.
+    ///
+    /// The pending connections wait in the backlog of the listener, which smooths
+    /// out the bursts of the reconnecting clients. By default, the rate is not limited.
+    pub fn accept_rate(mut self, per_second: u32, burst: u32) -> Self {
+        self.admission.accept_rate = Some((per_second, burst));
+        self
+    }
+
+    /// Returns the handle of the counters of the connections on this server.
+    pub fn connection_stats(&self) -> ConnectionStats {
+        self.stats.clone()
+    }
+
     /// Sets the instance of runtime to the specified `runtime`.
     pub fn runtime<R2>(self, runtime: R2) -> Server<S, L, A, R2> {
         Server {
@@ -108,10 +271,58 @@ impl<S, L, A, R> Server<S, L, A, R> {
             listener: self.listener,
             acceptor: self.acceptor,
             protocol: self.protocol,
+            connection: self.connection,
+            admission: self.admission,
+            stats: self.stats,
             runtime: Some(runtime),
+            background: self.background,
+            warmup: self.warmup,
         }
     }
 
+    /// Registers a function called periodically while the server is running,
+    /// typically for reloading the configuration file.
+    ///
+    /// The function is called on the runtime of the server, at the specified
+    /// interval after the server has started.
+    pub fn config_watcher<F>(mut self, interval: Duration, reload: F) -> Self
+    where
+        F: FnMut() + Send + 'static,
+    {
+        let reload = self.background.reload.add_shared(reload);
+        self.background
+            .watchers
+            .push(ConfigWatcher { interval, reload });
+        self
+    }
+
+    /// Returns the registry of the callbacks invoked when the server is requested
+    /// to reload its configuration.
+    ///
+    /// The functions registered by `config_watcher` are also contained in this registry.
+    pub fn reload_callbacks(&self) -> &ReloadCallbacks {
+        &self.background.reload
+    }
+
+    /// Installs the handlers of the termination and reload signals when the server starts.
+    ///
+    /// On the termination signal, the server stops accepting new connections and waits
+    /// for the established ones to complete until the grace period elapses.
+    #[cfg(feature = "signals")]
+    pub fn handle_signals(mut self, config: SignalConfig) -> Self {
+        self.background.signals = Some(config);
+        self
+    }
+
+    /// Sets the maximum duration of the graceful shutdown.
+    ///
+    /// The default value is 30 seconds.
+    #[cfg(feature = "signals")]
+    pub fn grace_period(mut self, grace_period: Duration) -> Self {
+        self.background.grace_period = grace_period;
+        self
+    }
+
     /// Switches the runtime to be used to [`current_thread::Runtime`].
     ///
     /// [`current_thread::Runtime`]: https://docs.rs/tokio/0.1/tokio/runtime/current_thread/struct.Runtime.html
@@ -121,7 +332,37 @@ impl<S, L, A, R> Server<S, L, A, R> {
             listener: self.listener,
             acceptor: self.acceptor,
             protocol: self.protocol,
+            connection: self.connection,
+            admission: self.admission,
+            stats: self.stats,
             runtime: None,
+            background: self.background,
+            warmup: self.warmup,
+        }
+    }
+}
+
+impl<S, L, A, R, Bd> Server<S, L, A, R>
+where
+    S: MakeServiceRef<(), Request<hyper::Body>, Response = Response<Bd>>,
+    S::Error: Into<crate::CritError>,
+    S::MakeError: Into<crate::CritError>,
+    S::Future: Send + 'static,
+    S::Service: Send + 'static,
+    <S::Service as Service<Request<hyper::Body>>>::Future: Send + 'static,
+    Bd: Payload,
+{
+    /// Registers the synthetic requests dispatched to the service before the server
+    /// starts accepting the connections, such as for populating the caches or
+    /// initializing the lazily created components.
+    ///
+    /// The requests are dispatched after the listener is bound, with a service
+    /// created with the context `()`. If the warm-up is fatal and a request fails,
+    /// `run` returns the error without serving any connection.
+    pub fn warmup(self, warmup: Warmup) -> Self {
+        Self {
+            warmup: Some(PendingWarmup(warmup.into_task())),
+            ..self
         }
     }
 }
@@ -133,27 +374,33 @@ macro_rules! serve {
         listener: $listener:expr,
         acceptor: $acceptor:expr,
         protocol: $protocol:expr,
+        limits: $limits:expr,
+        admission: $admission:expr,
+        stats: $stats:expr,
+        connections: $connections:expr,
         spawn: $spawn:expr,
     ) => {{
         let make_service = $make_service;
         let listener = $listener;
         let acceptor = $acceptor;
         let protocol = $protocol;
+        let limits = $limits;
+        let admission = $admission;
+        let stats = $stats;
+        let connections = $connections;
         let spawn = $spawn;
 
-        let incoming = listener
-            .listen()
+        let incoming = crate::admission::listen(listener, admission, stats)
             .map_err(|err| failure::Error::from_boxed_compat(err.into()))?;
         incoming
             .map_err(|e| log::error!("transport error: {}", e.into()))
-            .for_each(move |io| {
-                let accept = acceptor
-                    .accept(io)
+            .for_each(move |(io, permit)| {
+                let accept = crate::io::accept_with_info(&acceptor, io, permit.peer_addr())
                     .map_err(|e| log::error!("acceptor error: {}", e.into()));
 
                 let protocol = protocol.clone();
                 let make_service = make_service.clone();
-                let task = accept.and_then(move |io| {
+                let task = accept.and_then(move |(io, info)| {
                     let service = make_service
                         .make_service_ref(&io)
                         .map_err(|e| log::error!("make_service error: {}", e.into()));
@@ -163,13 +410,22 @@ macro_rules! serve {
                                 .map_err(|e| log::error!("service error: {}", e.into()))
                         })
                         .and_then(move |service| {
+                            let (service, watchdog) = conn::limit(service, limits);
                             protocol
-                                .serve_connection(io, LiftedHttpService { service })
+                                .serve_connection(io, LiftedHttpService { service, info })
                                 .with_upgrades()
                                 .map_err(|e| log::error!("HTTP protocol error: {}", e))
+                                // the idle connection is closed by dropping it.
+                                .select(watchdog)
+                                .then(|_| Ok(()))
                         })
                 });
-                spawn(task);
+                let guard = ConnectionGuard::new(&connections);
+                spawn(task.then(move |result| {
+                    drop(guard);
+                    drop(permit);
+                    result
+                }));
                 Ok(())
             })
     }};
@@ -200,18 +456,47 @@ where
             None => tokio::runtime::Runtime::new()?,
         };
 
+        let make_service = self.make_service;
+        let warmup = self.warmup.map(|mut warmup| (warmup.0)(&make_service));
+
+        let connections = Arc::new(Connections::default());
         let serve = serve! {
-            make_service: Arc::new(self.make_service),
+            make_service: Arc::new(make_service),
             listener: self.listener,
             acceptor: self.acceptor,
             protocol: Arc::new(
                 self.protocol.with_executor(tokio::executor::DefaultExecutor::current())
             ),
+            limits: self.connection,
+            admission: self.admission,
+            stats: self.stats,
+            connections: connections.clone(),
             spawn: |future| crate::rt::spawn(future),
         };
 
-        runtime.spawn(serve);
-        runtime.shutdown_on_idle().wait().unwrap();
+        // the listener has been bound, but the connections are not accepted until
+        // the warm-up requests are completed.
+        if let Some(warmup) = warmup {
+            runtime.block_on(warmup)?;
+        }
+
+        match self.background.signal_task(&connections) {
+            Some((signals, stop)) => {
+                runtime.spawn(serve.select(until(&stop)).then(|_| Ok(())));
+                for watcher in self.background.watchers {
+                    runtime.spawn(watcher.into_task().select(until(&stop)).then(|_| Ok(())));
+                }
+                let _ = runtime.block_on(signals);
+                runtime.shutdown_now().wait().unwrap();
+            }
+            None => {
+                runtime.spawn(serve);
+                for watcher in self.background.watchers {
+                    runtime.spawn(watcher.into_task());
+                }
+                runtime.shutdown_on_idle().wait().unwrap();
+            }
+        }
 
         Ok(())
     }
@@ -239,26 +524,165 @@ where
             None => tokio::runtime::current_thread::Runtime::new()?,
         };
 
+        let make_service = self.make_service;
+        let warmup = self.warmup.map(|mut warmup| (warmup.0)(&make_service));
+
+        let connections = Arc::new(Connections::default());
         let serve = serve! {
-            make_service: Rc::new(self.make_service),
+            make_service: Rc::new(make_service),
             listener: self.listener,
             acceptor: self.acceptor,
             protocol: Rc::new(
                 self.protocol.with_executor(tokio::runtime::current_thread::TaskExecutor::current())
             ),
+            limits: self.connection,
+            admission: self.admission,
+            stats: self.stats,
+            connections: connections.clone(),
             spawn: |future| tokio::runtime::current_thread::spawn(future),
         };
 
-        let _ = runtime.block_on(serve);
-        runtime.run()?;
+        // the listener has been bound, but the connections are not accepted until
+        // the warm-up requests are completed.
+        if let Some(warmup) = warmup {
+            runtime.block_on(warmup)?;
+        }
+
+        match self.background.signal_task(&connections) {
+            Some((signals, stop)) => {
+                runtime.spawn(serve.select(until(&stop)).then(|_| Ok(())));
+                for watcher in self.background.watchers {
+                    runtime.spawn(watcher.into_task().select(until(&stop)).then(|_| Ok(())));
+                }
+                // the remaining tasks are dropped along with the runtime.
+                let _ = runtime.block_on(signals);
+            }
+            None => {
+                for watcher in self.background.watchers {
+                    runtime.spawn(watcher.into_task());
+                }
+                let _ = runtime.block_on(serve);
+                runtime.run()?;
+            }
+        }
 
         Ok(())
     }
 }
 
+/// The components running alongside the server.
+#[derive(Debug)]
+struct Background {
+    watchers: Vec<ConfigWatcher>,
+    reload: ReloadCallbacks,
+    #[cfg(feature = "signals")]
+    signals: Option<SignalConfig>,
+    #[cfg(feature = "signals")]
+    grace_period: Duration,
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Self {
+            watchers: vec![],
+            reload: ReloadCallbacks::default(),
+            #[cfg(feature = "signals")]
+            signals: None,
+            #[cfg(feature = "signals")]
+            grace_period: Duration::from_secs(30),
+        }
+    }
+}
+
+/// The future completed when the server should stop accepting new connections.
+type Stop = futures::future::Shared<futures::sync::oneshot::Receiver<()>>;
+
+fn until(stop: &Stop) -> impl Future<Item = (), Error = ()> + Send + 'static {
+    stop.clone().then(|_| Ok(()))
+}
+
+impl Background {
+    /// Creates the task handling the signals, completed when the server should stop.
+    #[cfg(feature = "signals")]
+    fn signal_task(&self, connections: &Arc<Connections>) -> Option<(signal::SignalTask, Stop)> {
+        self.signals.map(|config| {
+            let dispatcher =
+                signal::Dispatcher::new(config, self.reload.clone(), self.grace_period);
+            signal::SignalTask::new(dispatcher, connections.clone())
+        })
+    }
+
+    #[cfg(not(feature = "signals"))]
+    fn signal_task(&self, _: &Arc<Connections>) -> Option<(futures::future::Empty<(), ()>, Stop)> {
+        None
+    }
+}
+
+/// The number of the established connections, decremented on drop.
+/// The number of the active connections on the server.
+#[derive(Debug, Default)]
+struct Connections {
+    count: AtomicUsize,
+    /// The task notified when the last connection is closed.
+    drained: AtomicTask,
+}
+
+struct ConnectionGuard(Arc<Connections>);
+
+impl ConnectionGuard {
+    fn new(connections: &Arc<Connections>) -> Self {
+        connections.count.fetch_add(1, Ordering::SeqCst);
+        ConnectionGuard(connections.clone())
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        if self.0.count.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.drained.notify();
+        }
+    }
+}
+
+/// The warm-up requests registered to the server, not yet started.
+struct PendingWarmup<S>(warmup::WarmupTask<S>);
+
+impl<S> fmt::Debug for PendingWarmup<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("PendingWarmup").finish()
+    }
+}
+
+/// A function called periodically by the server.
+struct ConfigWatcher {
+    interval: Duration,
+    reload: reload::Callback,
+}
+
+impl fmt::Debug for ConfigWatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConfigWatcher")
+            .field("interval", &self.interval)
+            .finish()
+    }
+}
+
+impl ConfigWatcher {
+    fn into_task(self) -> impl Future<Item = (), Error = ()> + Send + 'static {
+        let Self { interval, reload } = self;
+        Interval::new(Instant::now() + interval, interval)
+            .for_each(move |_| {
+                reload::call(&reload);
+                Ok(())
+            })
+            .map_err(|e| log::error!("config watcher error: {}", e))
+    }
+}
+
 #[allow(missing_debug_implementations)]
 struct LiftedHttpService<S> {
     service: S,
+    info: Option<ConnectionInfo>,
 }
 
 impl<S, Bd> hyper::service::Service for LiftedHttpService<S>
@@ -273,7 +697,10 @@ where
     type Future = S::Future;
 
     #[inline]
-    fn call(&mut self, request: Request<Body>) -> Self::Future {
+    fn call(&mut self, mut request: Request<Body>) -> Self::Future {
+        if let Some(ref info) = self.info {
+            request.extensions_mut().insert(info.clone());
+        }
         self.service.call(request)
     }
 }
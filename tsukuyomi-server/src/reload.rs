@@ -0,0 +1,69 @@
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+pub(crate) type Callback = Arc<Mutex<dyn FnMut() + Send + 'static>>;
+
+/// The registry of the callbacks invoked when the server is requested to reload
+/// its configuration, for example by `SIGHUP`.
+///
+/// The clones of this value refer to the same registry.
+#[derive(Clone, Default)]
+pub struct ReloadCallbacks {
+    callbacks: Arc<Mutex<Vec<Callback>>>,
+}
+
+impl fmt::Debug for ReloadCallbacks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReloadCallbacks")
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+impl ReloadCallbacks {
+    /// Registers a callback.
+    pub fn add<F>(&self, callback: F)
+    where
+        F: FnMut() + Send + 'static,
+    {
+        self.add_shared(callback);
+    }
+
+    pub(crate) fn add_shared<F>(&self, callback: F) -> Callback
+    where
+        F: FnMut() + Send + 'static,
+    {
+        let callback: Callback = Arc::new(Mutex::new(callback));
+        self.lock().push(callback.clone());
+        callback
+    }
+
+    /// Returns the number of the registered callbacks.
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Returns `true` if no callback is registered.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Invokes all registered callbacks, in order of registration.
+    pub fn reload(&self) {
+        let callbacks = self.lock().clone();
+        for callback in callbacks {
+            call(&callback);
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Callback>> {
+        self.callbacks.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+pub(crate) fn call(callback: &Callback) {
+    let mut callback = callback.lock().unwrap_or_else(|err| err.into_inner());
+    (*callback)();
+}
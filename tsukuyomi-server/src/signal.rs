@@ -0,0 +1,298 @@
+//! Handling of the termination and reload signals.
+//!
+//! The signals are received through the streams provided by `tokio-signal`,
+//! and dispatched by a task spawned onto the runtime of the server.
+
+use {
+    crate::{reload::ReloadCallbacks, Connections, Stop},
+    futures::{future, sync::oneshot, Async, Future, Poll, Stream},
+    std::{
+        io,
+        sync::{atomic::Ordering, Arc},
+        time::{Duration, Instant},
+    },
+    tokio::timer::Delay,
+};
+
+/// The configuration of the signal handling of the server.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SignalConfig {
+    /// Whether to shut down the server gracefully on `SIGTERM` or `SIGINT`.
+    ///
+    /// The second signal during the graceful shutdown terminates the server
+    /// immediately. On non-unix platforms, only Ctrl-C is handled.
+    pub terminate: bool,
+
+    /// Whether to invoke the reload callbacks on `SIGHUP`.
+    ///
+    /// This flag is ignored on non-unix platforms.
+    pub reload: bool,
+}
+
+type Signals = Box<dyn Stream<Item = Signal, Error = io::Error> + Send>;
+
+/// Creates the stream of the signals enabled by the configuration.
+///
+/// The stream never completes, even if no signal is enabled.
+fn listen(config: SignalConfig) -> Signals {
+    let mut signals: Signals = Box::new(future::empty().into_stream());
+    if config.terminate {
+        let ctrl_c = tokio_signal::ctrl_c()
+            .flatten_stream()
+            .map(|()| Signal::Terminate);
+        signals = Box::new(signals.select(ctrl_c));
+        #[cfg(unix)]
+        {
+            signals =
+                Box::new(signals.select(unix(tokio_signal::unix::SIGTERM, Signal::Terminate)));
+        }
+    }
+    #[cfg(unix)]
+    {
+        if config.reload {
+            signals = Box::new(signals.select(unix(tokio_signal::unix::SIGHUP, Signal::Reload)));
+        }
+    }
+    signals
+}
+
+#[cfg(unix)]
+fn unix(signum: i32, signal: Signal) -> impl Stream<Item = Signal, Error = io::Error> + Send {
+    tokio_signal::unix::Signal::new(signum)
+        .flatten_stream()
+        .map(move |_| signal)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Signal {
+    Terminate,
+    Reload,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Action {
+    Continue,
+    Graceful,
+    Immediate,
+}
+
+/// The state machine that determines the action for each received signal.
+#[derive(Debug)]
+pub(crate) struct Dispatcher {
+    config: SignalConfig,
+    reload: ReloadCallbacks,
+    grace_period: Duration,
+    deadline: Option<Instant>,
+}
+
+impl Dispatcher {
+    pub(crate) fn new(
+        config: SignalConfig,
+        reload: ReloadCallbacks,
+        grace_period: Duration,
+    ) -> Self {
+        Self {
+            config,
+            reload,
+            grace_period,
+            deadline: None,
+        }
+    }
+
+    pub(crate) fn dispatch(&mut self, signal: Signal, now: Instant) -> Action {
+        match signal {
+            Signal::Reload if self.config.reload => {
+                log::info!("reloading the configuration");
+                self.reload.reload();
+                Action::Continue
+            }
+            Signal::Terminate if self.config.terminate => match self.deadline {
+                None => {
+                    log::info!(
+                        "shutting down gracefully (grace period: {:?})",
+                        self.grace_period
+                    );
+                    self.deadline = Some(now + self.grace_period);
+                    Action::Graceful
+                }
+                Some(..) => {
+                    log::warn!("received the termination signal again; shutting down immediately");
+                    Action::Immediate
+                }
+            },
+            _ => Action::Continue,
+        }
+    }
+
+    pub(crate) fn check_deadline(&self, now: Instant) -> Action {
+        match self.deadline {
+            Some(deadline) if now >= deadline => {
+                log::warn!("the grace period has elapsed; shutting down immediately");
+                Action::Immediate
+            }
+            _ => Action::Continue,
+        }
+    }
+
+    fn is_terminating(&self) -> bool {
+        self.deadline.is_some()
+    }
+}
+
+/// The task that dispatches the received signals, completed when the server should stop.
+#[allow(missing_debug_implementations)]
+pub(crate) struct SignalTask {
+    dispatcher: Dispatcher,
+    signals: Signals,
+    deadline: Option<Delay>,
+    stop: Option<oneshot::Sender<()>>,
+    connections: Arc<Connections>,
+}
+
+impl SignalTask {
+    pub(crate) fn new(dispatcher: Dispatcher, connections: Arc<Connections>) -> (Self, Stop) {
+        let (tx, rx) = oneshot::channel();
+        let task = Self {
+            signals: listen(dispatcher.config),
+            dispatcher,
+            deadline: None,
+            stop: Some(tx),
+            connections,
+        };
+        (task, rx.shared())
+    }
+}
+
+impl Future for SignalTask {
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            let signal = match self.signals.poll() {
+                Ok(Async::Ready(Some(signal))) => signal,
+                Ok(Async::Ready(None)) => return Ok(Async::Ready(())),
+                Ok(Async::NotReady) => break,
+                Err(err) => {
+                    log::error!("signal handling error: {}", err);
+                    return Err(());
+                }
+            };
+            let now = Instant::now();
+            match self.dispatcher.dispatch(signal, now) {
+                Action::Continue => {}
+                Action::Graceful => {
+                    if let Some(stop) = self.stop.take() {
+                        let _ = stop.send(());
+                    }
+                    self.deadline = Some(Delay::new(now + self.dispatcher.grace_period));
+                }
+                Action::Immediate => return Ok(Async::Ready(())),
+            }
+        }
+
+        if let Some(ref mut deadline) = self.deadline {
+            match deadline.poll() {
+                Ok(Async::Ready(())) => {
+                    let action = self.dispatcher.check_deadline(deadline.deadline());
+                    debug_assert_eq!(action, Action::Immediate);
+                    return Ok(Async::Ready(()));
+                }
+                Ok(Async::NotReady) => {}
+                Err(err) => {
+                    log::error!("signal handling error: {}", err);
+                    return Err(());
+                }
+            }
+        }
+
+        if self.dispatcher.is_terminating() {
+            self.connections.drained.register();
+            if self.connections.count.load(Ordering::SeqCst) == 0 {
+                return Ok(Async::Ready(()));
+            }
+        }
+
+        Ok(Async::NotReady)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, std::sync::atomic::AtomicUsize};
+
+    fn counter(reload: &ReloadCallbacks) -> Arc<AtomicUsize> {
+        let count = Arc::new(AtomicUsize::new(0));
+        reload.add({
+            let count = count.clone();
+            move || {
+                count.fetch_add(1, Ordering::SeqCst);
+            }
+        });
+        count
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sighup_invokes_reload_callbacks() {
+        let reload = ReloadCallbacks::default();
+        let count = counter(&reload);
+        let config = SignalConfig {
+            terminate: false,
+            reload: true,
+        };
+        let mut dispatcher = Dispatcher::new(config, reload, Duration::from_secs(30));
+
+        let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+        let signal = runtime
+            .block_on(future::lazy(move || {
+                // the handler is installed at the first poll of the stream.
+                let mut signals = listen(config);
+                let _ = signals.poll();
+                assert_eq!(unsafe { libc::raise(libc::SIGHUP) }, 0);
+                signals.into_future().map_err(|(err, _)| err)
+            }))
+            .unwrap()
+            .0;
+
+        assert_eq!(signal, Some(Signal::Reload));
+        assert_eq!(
+            dispatcher.dispatch(Signal::Reload, Instant::now()),
+            Action::Continue
+        );
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn second_terminate_escalates() {
+        let reload = ReloadCallbacks::default();
+        let count = counter(&reload);
+        let config = SignalConfig {
+            terminate: true,
+            reload: false,
+        };
+        let mut dispatcher = Dispatcher::new(config, reload, Duration::from_secs(30));
+        let now = Instant::now();
+
+        assert_eq!(dispatcher.dispatch(Signal::Reload, now), Action::Continue);
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+
+        assert_eq!(dispatcher.check_deadline(now), Action::Continue);
+        assert_eq!(
+            dispatcher.dispatch(Signal::Terminate, now),
+            Action::Graceful
+        );
+        assert_eq!(
+            dispatcher.check_deadline(now + Duration::from_secs(10)),
+            Action::Continue
+        );
+        assert_eq!(
+            dispatcher.dispatch(Signal::Terminate, now + Duration::from_secs(10)),
+            Action::Immediate
+        );
+        assert_eq!(
+            dispatcher.check_deadline(now + Duration::from_secs(30)),
+            Action::Immediate
+        );
+    }
+}
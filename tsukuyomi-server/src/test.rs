@@ -2,12 +2,16 @@
 
 mod input;
 mod output;
+mod redirect;
 mod server;
+mod upgrade;
 
 pub use self::{
     input::{Input, IntoRequestBody},
     output::Output,
+    redirect::Followed,
     server::{Server, Session},
+    upgrade::Upgraded,
 };
 
 use {
@@ -115,3 +115,10 @@ impl IntoRequestBodyImpl for Vec<u8> {
         self.into()
     }
 }
+
+impl IntoRequestBody for Body {}
+impl IntoRequestBodyImpl for Body {
+    fn into_request_body(self) -> Body {
+        self
+    }
+}
@@ -0,0 +1,168 @@
+use {
+    super::output::Output,
+    bytes::Bytes,
+    http::{
+        header::{CONTENT_LENGTH, CONTENT_TYPE, COOKIE, LOCATION, TRANSFER_ENCODING},
+        uri::{Parts, PathAndQuery},
+        Method, Request, Response, StatusCode, Uri,
+    },
+    hyper::body::Body,
+    std::fmt::Write,
+};
+
+/// The default maximum number of redirects followed by `perform_follow`.
+pub(super) const DEFAULT_MAX_REDIRECTS: usize = 5;
+
+/// The response returned from `perform_follow`, with the redirects followed to reach it.
+#[derive(Debug)]
+pub struct Followed {
+    /// The final response, which is not a redirect.
+    pub response: Response<Output>,
+
+    /// The status codes of the intermediate redirect responses and the resolved
+    /// locations they point to, in the order they were followed.
+    pub redirects: Vec<(StatusCode, Uri)>,
+}
+
+/// A request whose body is buffered, so that it can be replayed on 307/308 redirects.
+pub(super) struct Replay {
+    parts: http::request::Parts,
+    body: Bytes,
+}
+
+impl Replay {
+    pub(super) fn new(parts: http::request::Parts, body: Bytes) -> Self {
+        Self { parts, body }
+    }
+
+    pub(super) fn to_request(&self) -> Request<Body> {
+        let mut request = Request::new(Body::from(self.body.clone()));
+        *request.method_mut() = self.parts.method.clone();
+        *request.uri_mut() = self.parts.uri.clone();
+        *request.version_mut() = self.parts.version;
+        *request.headers_mut() = self.parts.headers.clone();
+        // The cookies are attached by the session at each hop.
+        request.headers_mut().remove(COOKIE);
+        request
+    }
+
+    /// Updates the request to the next hop, according to the redirect response.
+    ///
+    /// It returns `None` if the response is not a redirect.
+    pub(super) fn redirect(
+        &mut self,
+        response: &Response<Output>,
+    ) -> crate::Result<Option<(StatusCode, Uri)>> {
+        let status = response.status();
+        let rewrite_to_get = match status {
+            StatusCode::SEE_OTHER => self.parts.method != Method::HEAD,
+            StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND => self.parts.method == Method::POST,
+            StatusCode::TEMPORARY_REDIRECT | StatusCode::PERMANENT_REDIRECT => false,
+            _ => return Ok(None),
+        };
+
+        let location = match response.headers().get(LOCATION) {
+            Some(location) => location.to_str()?,
+            None => return Ok(None),
+        };
+        let location = resolve(&self.parts.uri, location)?;
+
+        if rewrite_to_get {
+            self.parts.method = Method::GET;
+            self.parts.headers.remove(CONTENT_TYPE);
+            self.parts.headers.remove(CONTENT_LENGTH);
+            self.parts.headers.remove(TRANSFER_ENCODING);
+            self.body = Bytes::new();
+        }
+        self.parts.uri = location.clone();
+
+        Ok(Some((status, location)))
+    }
+}
+
+/// Creates the error reported when the number of redirects exceeds the limit.
+pub(super) fn too_many_redirects(
+    first: &Uri,
+    redirects: &[(StatusCode, Uri)],
+    limit: usize,
+) -> crate::Error {
+    let mut chain = first.to_string();
+    for (status, location) in redirects {
+        let _ = write!(chain, " -({})-> {}", status.as_u16(), location);
+    }
+    failure::format_err!("too many redirects (limit = {}): {}", limit, chain).into()
+}
+
+/// Resolves a `Location` value against the URI of the request.
+fn resolve(base: &Uri, location: &str) -> crate::Result<Uri> {
+    // The fragment is not sent to the server.
+    let location = location.split('#').next().unwrap_or("");
+
+    if location.starts_with("//") {
+        let scheme = base.scheme_part().map_or("http", |scheme| scheme.as_str());
+        return Ok(format!("{}:{}", scheme, location).parse()?);
+    }
+    if let Ok(uri) = location.parse::<Uri>() {
+        if uri.scheme_part().is_some() {
+            return Ok(uri);
+        }
+    }
+
+    let (path, query) = match location.find('?') {
+        Some(pos) => (&location[..pos], Some(&location[pos + 1..])),
+        None => (location, None),
+    };
+    let path = if path.is_empty() {
+        base.path().to_owned()
+    } else if path.starts_with('/') {
+        remove_dot_segments(path)
+    } else {
+        let dir = match base.path().rfind('/') {
+            Some(pos) => &base.path()[..=pos],
+            None => "/",
+        };
+        remove_dot_segments(&format!("{}{}", dir, path))
+    };
+    // An empty reference keeps the query of the base URI.
+    let query = match query {
+        Some(query) => Some(query),
+        None if location.is_empty() => base.query(),
+        None => None,
+    };
+    let path_and_query = match query {
+        Some(query) => format!("{}?{}", path, query),
+        None => path,
+    };
+
+    let mut parts = Parts::default();
+    parts.scheme = base.scheme_part().cloned();
+    parts.authority = base.authority_part().cloned();
+    parts.path_and_query = Some(path_and_query.parse::<PathAndQuery>()?);
+    Ok(Uri::from_parts(parts)?)
+}
+
+/// Removes the `.` and `..` segments from an absolute path, as described in RFC 3986, section 5.2.4.
+fn remove_dot_segments(path: &str) -> String {
+    let mut segments: Vec<&str> = vec![];
+    let mut trailing_slash = false;
+    for segment in path.split('/').skip(1) {
+        trailing_slash = false;
+        match segment {
+            "." => trailing_slash = true,
+            ".." => {
+                segments.pop();
+                trailing_slash = true;
+            }
+            segment => segments.push(segment),
+        }
+    }
+    let mut resolved = String::with_capacity(path.len());
+    for segment in &segments {
+        resolved.push('/');
+        resolved.push_str(segment);
+    }
+    if trailing_slash || resolved.is_empty() {
+        resolved.push('/');
+    }
+    resolved
+}
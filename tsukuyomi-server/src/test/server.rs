@@ -4,16 +4,25 @@ use {
     super::{
         input::Input,
         output::{Output, Receive},
+        redirect::{self, Followed, Replay},
+        upgrade::{self, Upgraded},
     },
-    crate::CritError,
+    crate::{
+        conn::{self, ConnState, ConnectionLimits},
+        CritError, LiftedHttpService,
+    },
+    bytes::Bytes,
     cookie::Cookie,
-    futures::{Future, Poll},
+    futures::{Future, Poll, Stream},
     http::{
         header::{COOKIE, SET_COOKIE},
         Request, Response,
     },
-    hyper::body::Payload,
-    std::{collections::HashMap, mem},
+    hyper::{
+        body::{Body, Payload},
+        server::conn::Http,
+    },
+    std::{collections::HashMap, io::Write, mem},
     tsukuyomi_service::{MakeService, Service},
 };
 
@@ -22,6 +31,7 @@ use {
 pub struct Server<S, Rt = tokio::runtime::Runtime> {
     make_service: S,
     runtime: Rt,
+    limits: ConnectionLimits,
 }
 
 impl<S, Rt> Server<S, Rt>
@@ -33,8 +43,17 @@ where
         Self {
             make_service,
             runtime,
+            limits: ConnectionLimits::default(),
         }
     }
+
+    /// Sets the maximum number of requests served on the connection emulated by a `Session`.
+    ///
+    /// See the documentation of `tsukuyomi_server::Server::max_requests_per_connection`.
+    pub fn max_requests_per_connection(mut self, max_requests: usize) -> Self {
+        self.limits.max_requests = Some(max_requests);
+        self
+    }
 }
 
 /// A type which manages a series of requests.
@@ -43,6 +62,10 @@ where
 pub struct Session<'a, S, Rt: 'a> {
     service: S,
     cookies: Option<HashMap<String, String>>,
+    max_redirects: usize,
+    limits: ConnectionLimits,
+    conn: ConnState,
+    closed: bool,
     runtime: &'a mut Rt,
 }
 
@@ -50,11 +73,15 @@ impl<'a, S, Rt> Session<'a, S, Rt>
 where
     S: Service<Request<hyper::Body>>,
 {
-    fn new(service: S, runtime: &'a mut Rt) -> Self {
+    fn new(service: S, runtime: &'a mut Rt, limits: ConnectionLimits) -> Self {
         Session {
             service,
             runtime,
             cookies: None,
+            max_redirects: redirect::DEFAULT_MAX_REDIRECTS,
+            limits,
+            conn: ConnState::default(),
+            closed: false,
         }
     }
 
@@ -70,10 +97,28 @@ where
         self
     }
 
+    /// Sets the maximum number of redirects followed by `perform_follow`.
+    ///
+    /// The default value is `5`.
+    pub fn max_redirects(self, max_redirects: usize) -> Self {
+        Self {
+            max_redirects,
+            ..self
+        }
+    }
+
     pub fn cookie(&self, name: &str) -> Option<&str> {
         self.cookies.as_ref()?.get(name).map(|s| s.as_str())
     }
 
+    /// Returns `true` if the connection emulated by this session can send more requests.
+    ///
+    /// The connection is closed after a response with `Connection: close`,
+    /// and the subsequent requests in this session fail.
+    pub fn is_reusable(&self) -> bool {
+        !self.closed
+    }
+
     /// Returns the reference to the underlying Tokio runtime.
     pub fn runtime(&mut self) -> &mut Rt {
         &mut *self.runtime
@@ -84,6 +129,11 @@ where
         T: Input,
     {
         let mut request = input.build_request()?;
+        self.attach_cookies(&mut request)?;
+        Ok(request)
+    }
+
+    fn attach_cookies(&self, request: &mut Request<hyper::Body>) -> crate::Result<()> {
         if let Some(cookies) = &self.cookies {
             for (k, v) in cookies {
                 request.headers_mut().append(
@@ -94,7 +144,79 @@ where
                 );
             }
         }
-        Ok(request)
+        Ok(())
+    }
+
+    /// Applies the request and follows the redirects, using the runtime-specific
+    /// functions to buffer the request body and to exchange a request.
+    fn follow<T>(
+        &mut self,
+        input: T,
+        buffer: impl FnOnce(&mut Self, Body) -> crate::Result<Bytes>,
+        mut send: impl FnMut(&mut Self, Request<Body>) -> crate::Result<Response<Output>>,
+    ) -> crate::Result<Followed>
+    where
+        T: Input,
+    {
+        let (parts, body) = input.build_request()?.into_parts();
+        let body = buffer(self, body)?;
+        let mut replay = Replay::new(parts, body);
+        let first = replay.to_request().uri().clone();
+
+        // The cookies are carried across the hops even if the session does not save them.
+        let saves_cookies = self.cookies.is_some();
+        self.cookies.get_or_insert_with(Default::default);
+
+        let mut redirects = vec![];
+        let result = loop {
+            let mut request = replay.to_request();
+            if let Err(err) = self.attach_cookies(&mut request) {
+                break Err(err);
+            }
+            let response = match send(self, request) {
+                Ok(response) => response,
+                Err(err) => break Err(err),
+            };
+            match replay.redirect(&response) {
+                Ok(Some(redirect)) => redirects.push(redirect),
+                Ok(None) => {
+                    break Ok(Followed {
+                        response,
+                        redirects,
+                    })
+                }
+                Err(err) => break Err(err),
+            }
+            if redirects.len() > self.max_redirects {
+                break Err(redirect::too_many_redirects(
+                    &first,
+                    &redirects,
+                    self.max_redirects,
+                ));
+            }
+        };
+
+        if !saves_cookies {
+            self.cookies = None;
+        }
+        result
+    }
+
+    /// Counts a request on the connection, and returns `true` if it is the last one.
+    fn start_request(&self) -> crate::Result<bool> {
+        if self.closed {
+            return Err(
+                failure::format_err!("the connection has been closed by the server").into(),
+            );
+        }
+        Ok(self.conn.start_request(&self.limits))
+    }
+
+    fn finish_response(&mut self, response: &mut Response<Output>, close: bool) {
+        if close {
+            conn::set_close(response.headers_mut());
+        }
+        self.closed = conn::is_close(response.headers());
     }
 
     fn handle_set_cookies(&mut self, response: &Response<Output>) -> crate::Result<()> {
@@ -148,7 +270,7 @@ mod threadpool {
             )
             .map_err(failure::Error::from_boxed_compat)?;
 
-            Ok(Session::new(service, &mut self.runtime))
+            Ok(Session::new(service, &mut self.runtime, self.limits))
         }
 
         pub fn perform<T>(&mut self, input: T) -> crate::Result<Response<Output>>
@@ -159,6 +281,55 @@ mod threadpool {
             let mut session = self.new_session()?;
             session.perform(input)
         }
+
+        /// Applies an HTTP request to a new session, following the redirects.
+        pub fn perform_follow<T>(&mut self, input: T) -> crate::Result<Followed>
+        where
+            T: Input,
+            <S::Service as Service<Request<hyper::Body>>>::Future: Send + 'static,
+        {
+            let mut session = self.new_session()?;
+            session.perform_follow(input)
+        }
+
+        /// Sends a request for the protocol upgrade, and returns the connection to the server.
+        ///
+        /// Unlike `perform`, the request is exchanged over an in-memory connection
+        /// served by hyper, so that the service can take over the connection after
+        /// the handshake. The returned `Upgraded` contains the response head, and
+        /// is used to exchange the data with the upgraded protocol.
+        pub fn perform_upgrade<T>(&mut self, input: T) -> crate::Result<Upgraded>
+        where
+            T: Input,
+            <S::Service as Service<Request<hyper::Body>>>::Future: Send + 'static,
+        {
+            let service = block_on(
+                &mut self.runtime,
+                self.make_service.make_service(()).map_err(Into::into),
+            )
+            .map_err(failure::Error::from_boxed_compat)?;
+
+            let (parts, body) = input.build_request()?.into_parts();
+            let body = block_on(&mut self.runtime, body.concat2())?.into_bytes();
+
+            let (mut client, io) = upgrade::pair();
+            self.runtime.spawn(
+                Http::new()
+                    .serve_connection(
+                        io,
+                        LiftedHttpService {
+                            service,
+                            info: None,
+                        },
+                    )
+                    .with_upgrades()
+                    .map_err(|e| log::debug!("HTTP protocol error: {}", e)),
+            );
+
+            client.write_all(&upgrade::encode_request(&parts, &body))?;
+            client.receive_head()?;
+            Ok(client)
+        }
     }
 
     impl<'a, S, Bd> Session<'a, S, Runtime>
@@ -174,10 +345,37 @@ mod threadpool {
             T: Input,
         {
             let request = self.build_request(input)?;
+            self.send(request)
+        }
+
+        /// Applies an HTTP request to this client, following the redirects in the response.
+        ///
+        /// The method is rewritten to `GET` on 303 and on 301/302 after `POST`,
+        /// and the method and body are preserved on 307/308. The cookies set by
+        /// the intermediate responses are sent to the subsequent hops.
+        /// If the number of redirects exceeds the limit set by `max_redirects`,
+        /// it returns an error describing the redirect chain.
+        pub fn perform_follow<T>(&mut self, input: T) -> crate::Result<Followed>
+        where
+            T: Input,
+        {
+            self.follow(
+                input,
+                |session, body| {
+                    block_on(session.runtime, body.concat2())
+                        .map(|chunk| chunk.into_bytes())
+                        .map_err(Into::into)
+                },
+                Self::send,
+            )
+        }
 
+        fn send(&mut self, request: Request<hyper::Body>) -> crate::Result<Response<Output>> {
+            let close = self.start_request()?;
             let future = TestResponseFuture::Initial(self.service.call(request));
-            let response =
+            let mut response =
                 block_on(&mut self.runtime, future).map_err(failure::Error::from_boxed_compat)?;
+            self.finish_response(&mut response, close);
             self.handle_set_cookies(&response)?;
 
             Ok(response)
@@ -201,7 +399,7 @@ mod current_thread {
                 .runtime
                 .block_on(self.make_service.make_service(()))
                 .map_err(|err| failure::Error::from_boxed_compat(err.into()))?;
-            Ok(Session::new(service, &mut self.runtime))
+            Ok(Session::new(service, &mut self.runtime, self.limits))
         }
 
         pub fn perform<T>(&mut self, input: T) -> crate::Result<Response<Output>>
@@ -211,6 +409,15 @@ mod current_thread {
             let mut session = self.new_session()?;
             session.perform(input)
         }
+
+        /// Applies an HTTP request to a new session, following the redirects.
+        pub fn perform_follow<T>(&mut self, input: T) -> crate::Result<Followed>
+        where
+            T: Input,
+        {
+            let mut session = self.new_session()?;
+            session.perform_follow(input)
+        }
     }
 
     impl<'a, S, Bd> Session<'a, S, Runtime>
@@ -225,12 +432,37 @@ mod current_thread {
             T: Input,
         {
             let request = self.build_request(input)?;
+            self.send(request)
+        }
+
+        /// Applies an HTTP request to this client, following the redirects in the response.
+        ///
+        /// See the documentation of the multi-threaded version for details.
+        pub fn perform_follow<T>(&mut self, input: T) -> crate::Result<Followed>
+        where
+            T: Input,
+        {
+            self.follow(
+                input,
+                |session, body| {
+                    session
+                        .runtime
+                        .block_on(body.concat2())
+                        .map(|chunk| chunk.into_bytes())
+                        .map_err(Into::into)
+                },
+                Self::send,
+            )
+        }
 
+        fn send(&mut self, request: Request<hyper::Body>) -> crate::Result<Response<Output>> {
+            let close = self.start_request()?;
             let future = TestResponseFuture::Initial(self.service.call(request));
-            let response = self
+            let mut response = self
                 .runtime
                 .block_on(future)
                 .map_err(failure::Error::from_boxed_compat)?;
+            self.finish_response(&mut response, close);
             self.handle_set_cookies(&response)?;
 
             Ok(response)
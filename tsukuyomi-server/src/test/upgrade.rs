@@ -0,0 +1,241 @@
+use {
+    futures::{task::Task, Async, Poll},
+    http::{
+        header::{HeaderName, CONTENT_LENGTH, HOST},
+        Response, StatusCode,
+    },
+    std::{
+        collections::VecDeque,
+        io,
+        sync::{Arc, Condvar, Mutex},
+        time::Duration,
+    },
+    tokio::io::{AsyncRead, AsyncWrite},
+};
+
+/// The duration in seconds to wait for the server in the blocking reads of `Upgraded`.
+const READ_TIMEOUT_SECS: u64 = 10;
+
+/// Encodes the request in the HTTP/1.1 format.
+pub(super) fn encode_request(parts: &http::request::Parts, body: &[u8]) -> Vec<u8> {
+    let path = parts.uri.path_and_query().map_or("/", |p| p.as_str());
+    let mut encoded = format!("{} {} HTTP/1.1\r\n", parts.method, path).into_bytes();
+    if !parts.headers.contains_key(HOST) {
+        encoded.extend_from_slice(b"host: localhost\r\n");
+    }
+    if !body.is_empty() && !parts.headers.contains_key(CONTENT_LENGTH) {
+        encoded.extend_from_slice(format!("content-length: {}\r\n", body.len()).as_bytes());
+    }
+    for (name, value) in &parts.headers {
+        encoded.extend_from_slice(name.as_str().as_bytes());
+        encoded.extend_from_slice(b": ");
+        encoded.extend_from_slice(value.as_bytes());
+        encoded.extend_from_slice(b"\r\n");
+    }
+    encoded.extend_from_slice(b"\r\n");
+    encoded.extend_from_slice(body);
+    encoded
+}
+
+/// Creates a pair of in-memory streams connected to each other.
+pub(super) fn pair() -> (Upgraded, ServerIo) {
+    let to_server = Arc::new(Pipe::default());
+    let to_client = Arc::new(Pipe::default());
+    (
+        Upgraded {
+            response: Response::new(()),
+            rx: to_client.clone(),
+            tx: to_server.clone(),
+        },
+        ServerIo {
+            rx: to_server,
+            tx: to_client,
+        },
+    )
+}
+
+/// A unidirectional byte stream, read from a task or from a blocking thread.
+#[derive(Debug, Default)]
+struct Pipe {
+    state: Mutex<PipeState>,
+    readable: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct PipeState {
+    buf: VecDeque<u8>,
+    closed: bool,
+    reader: Option<Task>,
+}
+
+impl Pipe {
+    fn write(&self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+        state.buf.extend(buf);
+        self.wake(&mut state);
+        Ok(buf.len())
+    }
+
+    fn close(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.closed = true;
+        self.wake(&mut state);
+    }
+
+    fn wake(&self, state: &mut PipeState) {
+        if let Some(task) = state.reader.take() {
+            task.notify();
+        }
+        self.readable.notify_all();
+    }
+
+    fn drain(state: &mut PipeState, buf: &mut [u8]) -> usize {
+        let n = buf.len().min(state.buf.len());
+        for (dst, src) in buf.iter_mut().zip(state.buf.drain(..n)) {
+            *dst = src;
+        }
+        n
+    }
+
+    /// Reads the data in the context of a task, without blocking.
+    fn poll_read(&self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut state = self.state.lock().unwrap();
+        if state.buf.is_empty() && !state.closed {
+            state.reader = Some(futures::task::current());
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        Ok(Self::drain(&mut state, buf))
+    }
+
+    /// Reads the data, blocking the current thread until it arrives.
+    fn read_blocking(&self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut state = self.state.lock().unwrap();
+        while state.buf.is_empty() && !state.closed {
+            let (new_state, timeout) = self
+                .readable
+                .wait_timeout(state, Duration::from_secs(READ_TIMEOUT_SECS))
+                .unwrap();
+            if timeout.timed_out() {
+                return Err(io::ErrorKind::TimedOut.into());
+            }
+            state = new_state;
+        }
+        Ok(Self::drain(&mut state, buf))
+    }
+}
+
+/// The server side of the in-memory connection, served by hyper.
+#[derive(Debug)]
+pub(super) struct ServerIo {
+    rx: Arc<Pipe>,
+    tx: Arc<Pipe>,
+}
+
+impl io::Read for ServerIo {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.rx.poll_read(buf)
+    }
+}
+
+impl io::Write for ServerIo {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.tx.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl AsyncRead for ServerIo {}
+
+impl AsyncWrite for ServerIo {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.tx.close();
+        Ok(Async::Ready(()))
+    }
+}
+
+impl Drop for ServerIo {
+    fn drop(&mut self) {
+        self.rx.close();
+        self.tx.close();
+    }
+}
+
+/// The client side of a connection to the test server, returned from `perform_upgrade`.
+///
+/// After the handshake, the data after the response head can be exchanged
+/// through the blocking `Read` and `Write` implementations.
+/// The reads fail with `TimedOut` if no data arrives within 10 seconds.
+#[derive(Debug)]
+pub struct Upgraded {
+    response: Response<()>,
+    rx: Arc<Pipe>,
+    tx: Arc<Pipe>,
+}
+
+impl Upgraded {
+    /// Returns the response to the upgrade request.
+    pub fn response(&self) -> &Response<()> {
+        &self.response
+    }
+
+    /// Reads the response head sent from the server.
+    pub(super) fn receive_head(&mut self) -> crate::Result<()> {
+        let mut head = Vec::new();
+        let mut byte = [0u8; 1];
+        while !head.ends_with(b"\r\n\r\n") {
+            if self.rx.read_blocking(&mut byte)? == 0 {
+                return Err(
+                    failure::format_err!("the connection closed before the response").into(),
+                );
+            }
+            head.push(byte[0]);
+        }
+        let head = String::from_utf8(head)?;
+
+        let mut lines = head.split("\r\n").filter(|line| !line.is_empty());
+        let status = lines
+            .next()
+            .and_then(|line| line.split(' ').nth(1))
+            .ok_or_else(|| failure::format_err!("invalid status line"))?;
+        *self.response.status_mut() = StatusCode::from_bytes(status.as_bytes())?;
+        for line in lines {
+            let pos = line
+                .find(':')
+                .ok_or_else(|| failure::format_err!("invalid header line: {:?}", line))?;
+            self.response.headers_mut().append(
+                HeaderName::from_bytes(&line.as_bytes()[..pos])?,
+                line[pos + 1..].trim().parse()?,
+            );
+        }
+        Ok(())
+    }
+}
+
+impl io::Read for Upgraded {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.rx.read_blocking(buf)
+    }
+}
+
+impl io::Write for Upgraded {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.tx.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for Upgraded {
+    fn drop(&mut self) {
+        self.rx.close();
+        self.tx.close();
+    }
+}
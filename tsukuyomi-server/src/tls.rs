@@ -0,0 +1,348 @@
+//! Components for serving TLS with the certificates reloaded from files.
+//!
+//! The certificate of [`TlsConfig`] is resolved on each handshake, so replacing
+//! it affects only the connections established afterwards.
+//!
+//! [`TlsConfig`]: ./struct.TlsConfig.html
+
+use {
+    rustls::{
+        internal::pemfile, sign::CertifiedKey, NoClientAuth, PrivateKey, ResolvesServerCert,
+        ServerConfig, ServerSession, SignatureScheme,
+    },
+    std::{
+        fmt,
+        fs::File,
+        io::BufReader,
+        path::{Path, PathBuf},
+        sync::{Arc, RwLock},
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    },
+    tokio_rustls::TlsAcceptor,
+    tsukuyomi_service::ConnectionInfo,
+};
+
+/// The TLS configuration of the server whose certificate is loaded from PEM files.
+///
+/// The clones of this value share the same certificate.
+#[derive(Clone)]
+pub struct TlsConfig {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    watch_interval: Option<Duration>,
+    resolver: Arc<CertResolver>,
+    server_config: Arc<ServerConfig>,
+}
+
+impl fmt::Debug for TlsConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TlsConfig")
+            .field("cert_path", &self.cert_path)
+            .field("key_path", &self.key_path)
+            .field("watch_interval", &self.watch_interval)
+            .field("not_after", &self.not_after())
+            .finish()
+    }
+}
+
+impl TlsConfig {
+    /// Creates a `TlsConfig` with the certificate chain and the private key
+    /// loaded from the specified PEM files.
+    pub fn from_pem_files(
+        cert_path: impl AsRef<Path>,
+        key_path: impl AsRef<Path>,
+    ) -> crate::Result<Self> {
+        let cert_path = cert_path.as_ref().to_path_buf();
+        let key_path = key_path.as_ref().to_path_buf();
+        let loaded = Loaded::from_pem_files(&cert_path, &key_path)?;
+
+        let resolver = Arc::new(CertResolver {
+            current: RwLock::new(Arc::new(loaded)),
+        });
+        let mut server_config = ServerConfig::new(NoClientAuth::new());
+        server_config.cert_resolver = resolver.clone();
+
+        Ok(Self {
+            cert_path,
+            key_path,
+            watch_interval: None,
+            resolver,
+            server_config: Arc::new(server_config),
+        })
+    }
+
+    /// Creates a `TlsConfig` whose PEM files are re-read at the specified interval
+    /// while the server is running.
+    ///
+    /// The files are watched when this value is passed to `Server::tls`.
+    pub fn watch_files(
+        cert_path: impl AsRef<Path>,
+        key_path: impl AsRef<Path>,
+        interval: Duration,
+    ) -> crate::Result<Self> {
+        Ok(Self {
+            watch_interval: Some(interval),
+            ..Self::from_pem_files(cert_path, key_path)?
+        })
+    }
+
+    /// Sets the protocols advertised with ALPN, in the order of preference.
+    ///
+    /// The negotiated protocol is reported by `ConnectionInfo::alpn_protocol`.
+    pub fn alpn_protocols(mut self, protocols: &[&str]) -> Self {
+        let protocols: Vec<String> = protocols.iter().map(|&p| p.to_owned()).collect();
+        Arc::make_mut(&mut self.server_config).set_protocols(&protocols);
+        self
+    }
+
+    /// Re-reads the PEM files and replaces the certificate if it has been changed.
+    ///
+    /// If the new files are invalid, the error is logged and the current
+    /// certificate is kept.
+    pub fn reload(&self) -> crate::Result<()> {
+        let loaded = Loaded::from_pem_files(&self.cert_path, &self.key_path).map_err(|err| {
+            log::error!(
+                "failed to reload the certificate {}: {}",
+                self.cert_path.display(),
+                err
+            );
+            err
+        })?;
+
+        let mut current = self
+            .resolver
+            .current
+            .write()
+            .unwrap_or_else(|err| err.into_inner());
+        if current.key.cert != loaded.key.cert {
+            log::info!(
+                "reloaded the certificate {} (not after: {:?})",
+                self.cert_path.display(),
+                loaded.not_after
+            );
+            *current = Arc::new(loaded);
+        }
+        Ok(())
+    }
+
+    /// Returns the expiration time of the current certificate.
+    pub fn not_after(&self) -> SystemTime {
+        self.resolver.current().not_after
+    }
+
+    /// Returns the interval of re-reading the PEM files, if specified.
+    pub fn watch_interval(&self) -> Option<Duration> {
+        self.watch_interval
+    }
+
+    /// Returns the `ServerConfig` that resolves the current certificate.
+    pub fn server_config(&self) -> Arc<ServerConfig> {
+        self.server_config.clone()
+    }
+
+    /// Creates an `Acceptor` that performs the handshakes with the current certificate.
+    pub fn acceptor(&self) -> TlsAcceptor {
+        TlsAcceptor::from(self.server_config())
+    }
+}
+
+/// Fills the parameters negotiated in the handshake of the session.
+///
+/// The version and the cipher suite are formatted as `"TLSv1.3"` and
+/// `"TLS13_AES_128_GCM_SHA256"`, respectively.
+pub fn session_info(session: &ServerSession, info: &mut ConnectionInfo) {
+    info.set_server_name(session.get_sni_hostname().map(ToOwned::to_owned));
+    info.set_alpn_protocol(session.get_alpn_protocol().map(ToOwned::to_owned));
+    info.set_tls_version(
+        session
+            .get_protocol_version()
+            .map(|version| format!("{:?}", version).replace('_', ".")),
+    );
+    info.set_cipher_suite(
+        session
+            .get_negotiated_ciphersuite()
+            .map(|suite| format!("{:?}", suite.suite)),
+    );
+}
+
+struct CertResolver {
+    current: RwLock<Arc<Loaded>>,
+}
+
+impl CertResolver {
+    fn current(&self) -> Arc<Loaded> {
+        self.current
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+            .clone()
+    }
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(
+        &self,
+        _: Option<webpki::DNSNameRef<'_>>,
+        _: &[SignatureScheme],
+    ) -> Option<CertifiedKey> {
+        Some(self.current().key.clone())
+    }
+}
+
+struct Loaded {
+    key: CertifiedKey,
+    not_after: SystemTime,
+}
+
+impl Loaded {
+    fn from_pem_files(cert_path: &Path, key_path: &Path) -> crate::Result<Self> {
+        let certs = pemfile::certs(&mut BufReader::new(File::open(cert_path)?))
+            .map_err(|()| failure::format_err!("invalid certificate file"))?;
+        let end_entity = certs
+            .first()
+            .ok_or_else(|| failure::format_err!("no certificate in the file"))?;
+        let not_after = not_after(&end_entity.0)
+            .ok_or_else(|| failure::format_err!("malformed certificate"))?;
+        if not_after < SystemTime::now() {
+            return Err(failure::format_err!("the certificate has expired").into());
+        }
+
+        let key = load_private_key(key_path)?;
+        let key = rustls::sign::any_supported_type(&key)
+            .map_err(|()| failure::format_err!("unsupported private key"))?;
+
+        Ok(Self {
+            key: CertifiedKey::new(certs, Arc::new(key)),
+            not_after,
+        })
+    }
+}
+
+fn load_private_key(path: &Path) -> crate::Result<PrivateKey> {
+    let mut keys = pemfile::pkcs8_private_keys(&mut BufReader::new(File::open(path)?))
+        .map_err(|()| failure::format_err!("invalid private key file"))?;
+    if keys.is_empty() {
+        keys = pemfile::rsa_private_keys(&mut BufReader::new(File::open(path)?))
+            .map_err(|()| failure::format_err!("invalid private key file"))?;
+    }
+    keys.into_iter()
+        .next()
+        .ok_or_else(|| failure::format_err!("no private key in the file").into())
+}
+
+/// Extracts `notAfter` in the validity of a DER-encoded X.509 certificate.
+fn not_after(cert: &[u8]) -> Option<SystemTime> {
+    let mut cert = Der(cert).expect(0x30)?;
+    let mut tbs = cert.expect(0x30)?;
+    if tbs.peek() == Some(0xa0) {
+        tbs.read()?; // version
+    }
+    tbs.expect(0x02)?; // serialNumber
+    tbs.expect(0x30)?; // signature
+    tbs.expect(0x30)?; // issuer
+    let mut validity = tbs.expect(0x30)?;
+    validity.read()?; // notBefore
+    let (tag, time) = validity.read()?;
+    let time = std::str::from_utf8(time).ok()?;
+    match tag {
+        0x17 => {
+            // UTCTime: YYMMDDHHMMSSZ
+            let year: i64 = time.get(0..2)?.parse().ok()?;
+            let year = if year >= 50 { 1900 + year } else { 2000 + year };
+            parse_time(year, time.get(2..)?)
+        }
+        // GeneralizedTime: YYYYMMDDHHMMSSZ
+        0x18 => parse_time(time.get(0..4)?.parse().ok()?, time.get(4..)?),
+        _ => None,
+    }
+}
+
+/// Parses `MMDDHHMMSSZ` in the specified year.
+fn parse_time(year: i64, s: &str) -> Option<SystemTime> {
+    if s.len() != 11 || !s.ends_with('Z') {
+        return None;
+    }
+    let field = |i: usize| s.get(i..i + 2).and_then(|f| f.parse::<i64>().ok());
+    let (month, day) = (field(0)?, field(2)?);
+    let secs =
+        days_from_civil(year, month, day) * 86400 + field(4)? * 3600 + field(6)? * 60 + field(8)?;
+    if secs >= 0 {
+        Some(UNIX_EPOCH + Duration::from_secs(secs as u64))
+    } else {
+        None
+    }
+}
+
+/// Returns the number of days since 1970-01-01 in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let yoe = year - era * 400;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// A minimal reader of the DER-encoded values.
+struct Der<'a>(&'a [u8]);
+
+impl<'a> Der<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.0.first().cloned()
+    }
+
+    fn read(&mut self) -> Option<(u8, &'a [u8])> {
+        let tag = *self.0.first()?;
+        let first = *self.0.get(1)? as usize;
+        let (len, offset) = if first < 0x80 {
+            (first, 2)
+        } else {
+            let num_bytes = first & 0x7f;
+            if num_bytes == 0 || num_bytes > 4 {
+                return None;
+            }
+            let len = self
+                .0
+                .get(2..2 + num_bytes)?
+                .iter()
+                .fold(0, |len, &b| (len << 8) | b as usize);
+            (len, 2 + num_bytes)
+        };
+        let content = self.0.get(offset..offset + len)?;
+        self.0 = &self.0[offset + len..];
+        Some((tag, content))
+    }
+
+    fn expect(&mut self, tag: u8) -> Option<Der<'a>> {
+        match self.read()? {
+            (t, content) if t == tag => Some(Der(content)),
+            _ => None,
+        }
+    }
+}
+
+impl<S, L, A, R> crate::Server<S, L, A, R> {
+    /// Serves TLS with the specified configuration.
+    ///
+    /// The reloading of the certificate is registered to the reload callbacks, and
+    /// it is also called periodically if the configuration watches the files.
+    pub fn tls(self, config: TlsConfig) -> crate::Server<S, L, TlsAcceptor, R>
+    where
+        L: crate::Listener,
+        TlsAcceptor: crate::Acceptor<L::Conn>,
+    {
+        let server = self.acceptor(config.acceptor());
+        let reload = {
+            let config = config.clone();
+            move || {
+                let _ = config.reload();
+            }
+        };
+        match config.watch_interval() {
+            Some(interval) => server.config_watcher(interval, reload),
+            None => {
+                server.reload_callbacks().add(reload);
+                server
+            }
+        }
+    }
+}
@@ -0,0 +1,410 @@
+use {
+    crate::{CritError, ReadyService},
+    futures::{Async, Future, Stream},
+    http::{Method, Request, Response, StatusCode, Uri},
+    hyper::body::{Body, Payload},
+    std::{
+        fmt,
+        marker::PhantomData,
+        time::{Duration, Instant},
+    },
+    tokio::timer::Timeout,
+    tsukuyomi_service::{MakeServiceRef, Service},
+};
+
+/// The synthetic requests dispatched to the service before the server starts
+/// accepting the connections, registered by `Server::warmup`.
+///
+/// The requests are dispatched one by one in the order of registration, after
+/// the listener is bound. Each request is bounded by the timeout, including the
+/// time to read the entire response body. A request fails if it times out, the
+/// service returns an error, or the response status is neither successful nor
+/// a redirection.
+pub struct Warmup {
+    requests: Vec<Request<()>>,
+    timeout: Duration,
+    fatal: bool,
+    on_complete: Option<OnComplete>,
+}
+
+type OnComplete = Box<dyn FnMut(&WarmupReport) + Send + 'static>;
+
+impl fmt::Debug for Warmup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Warmup")
+            .field("requests", &self.requests)
+            .field("timeout", &self.timeout)
+            .field("fatal", &self.fatal)
+            .finish()
+    }
+}
+
+impl Default for Warmup {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Warmup {
+    /// Creates an empty `Warmup`.
+    pub fn new() -> Self {
+        Self {
+            requests: vec![],
+            timeout: Duration::from_secs(10),
+            fatal: false,
+            on_complete: None,
+        }
+    }
+
+    /// Appends a request, with the method, the path and the header fields.
+    pub fn request(mut self, request: Request<()>) -> Self {
+        self.requests.push(request);
+        self
+    }
+
+    /// Sets the time limit of each request.
+    ///
+    /// The default value is 10 seconds.
+    pub fn timeout(self, timeout: Duration) -> Self {
+        Self { timeout, ..self }
+    }
+
+    /// Sets whether a failed request aborts the startup of the server.
+    ///
+    /// If disabled, the failures are only logged at the level `WARN`.
+    /// The default value is `false`.
+    pub fn fatal(self, fatal: bool) -> Self {
+        Self { fatal, ..self }
+    }
+
+    /// Registers a function called with the report after all requests are completed,
+    /// and before the server starts accepting the connections.
+    pub fn on_complete<F>(self, f: F) -> Self
+    where
+        F: FnOnce(&WarmupReport) + Send + 'static,
+    {
+        let mut f = Some(f);
+        Self {
+            on_complete: Some(Box::new(move |report| {
+                if let Some(f) = f.take() {
+                    f(report);
+                }
+            })),
+            ..self
+        }
+    }
+
+    /// Creates the task that dispatches the requests with a service created by `make_service`.
+    pub(crate) fn into_task<S, Bd>(self) -> WarmupTask<S>
+    where
+        S: MakeServiceRef<(), Request<Body>, Response = Response<Bd>>,
+        S::Error: Into<CritError>,
+        S::MakeError: Into<CritError>,
+        S::Future: Send + 'static,
+        S::Service: Send + 'static,
+        <S::Service as Service<Request<Body>>>::Future: Send + 'static,
+        Bd: Payload,
+    {
+        let mut warmup = Some(self);
+        Box::new(move |make_service: &S| {
+            let Self {
+                requests,
+                timeout,
+                fatal,
+                on_complete,
+            } = warmup
+                .take()
+                .expect("the warm-up task has already been started");
+
+            let service = make_service
+                .make_service_ref(&())
+                .map_err(|err| failure::Error::from_boxed_compat(err.into()));
+            let report = service.and_then(move |service| {
+                futures::stream::iter_ok(requests).fold(
+                    (service, WarmupReport::default()),
+                    move |(service, mut report), request| {
+                        let (parts, ()) = request.into_parts();
+                        let method = parts.method.clone();
+                        let uri = parts.uri.clone();
+                        let request = Request::from_parts(parts, Body::empty());
+
+                        ReadyService(Some(service), PhantomData)
+                            .map_err(|err| failure::Error::from_boxed_compat(err.into()))
+                            .and_then(move |mut service| {
+                                let started = Instant::now();
+                                let dispatch = dispatch(&mut service, request);
+                                Timeout::new(dispatch, timeout).then(move |result| {
+                                    let outcome = match result {
+                                        Ok(status) => Ok(status),
+                                        Err(ref err) if err.is_elapsed() => {
+                                            Err(format!("timed out after {:?}", timeout))
+                                        }
+                                        Err(err) => Err(err.into_inner().map_or_else(
+                                            || "timer error".into(),
+                                            |err| err.to_string(),
+                                        )),
+                                    };
+                                    report.entries.push(WarmupEntry {
+                                        method,
+                                        uri,
+                                        outcome,
+                                        elapsed: started.elapsed(),
+                                    });
+                                    Ok::<_, failure::Error>((service, report))
+                                })
+                            })
+                    },
+                )
+            });
+
+            Box::new(report.from_err().and_then(move |(_service, report)| {
+                for entry in &report.entries {
+                    if entry.is_success() {
+                        log::info!("warm-up: {}", entry);
+                    } else {
+                        log::warn!("warm-up failed: {}", entry);
+                    }
+                }
+                if let Some(mut on_complete) = on_complete {
+                    on_complete(&report);
+                }
+                match report.entries.iter().find(|entry| !entry.is_success()) {
+                    Some(entry) if fatal => {
+                        Err(failure::format_err!("the warm-up request failed: {}", entry).into())
+                    }
+                    _ => Ok(report),
+                }
+            }))
+        })
+    }
+}
+
+/// Calls the service, and reads the entire response body.
+fn dispatch<S, Bd>(
+    service: &mut S,
+    request: Request<Body>,
+) -> impl Future<Item = StatusCode, Error = failure::Error> + Send + 'static
+where
+    S: Service<Request<Body>, Response = Response<Bd>>,
+    S::Error: Into<CritError>,
+    S::Future: Send + 'static,
+    Bd: Payload,
+{
+    service
+        .call(request)
+        .map_err(|err| failure::Error::from_boxed_compat(err.into()))
+        .and_then(|response| {
+            let status = response.status();
+            let mut body = response.into_body();
+            futures::future::poll_fn(move || {
+                while futures::try_ready!(body.poll_data()).is_some() {}
+                Ok(Async::Ready(status))
+            })
+            .map_err(|err: Bd::Error| failure::Error::from_boxed_compat(err.into()))
+        })
+}
+
+// The task is started only once.
+pub(crate) type WarmupTask<S> = Box<dyn FnMut(&S) -> WarmupFuture + Send + 'static>;
+
+pub(crate) type WarmupFuture =
+    Box<dyn Future<Item = WarmupReport, Error = crate::Error> + Send + 'static>;
+
+/// The results of the warm-up requests, passed to `Warmup::on_complete`.
+#[derive(Debug, Default)]
+pub struct WarmupReport {
+    entries: Vec<WarmupEntry>,
+}
+
+impl WarmupReport {
+    /// Returns the results of the requests, in the order of dispatching.
+    pub fn entries(&self) -> &[WarmupEntry] {
+        &self.entries
+    }
+
+    /// Returns `true` if all requests succeeded.
+    pub fn is_success(&self) -> bool {
+        self.entries.iter().all(WarmupEntry::is_success)
+    }
+}
+
+/// The result of a warm-up request.
+#[derive(Debug)]
+pub struct WarmupEntry {
+    method: Method,
+    uri: Uri,
+    outcome: Result<StatusCode, String>,
+    elapsed: Duration,
+}
+
+impl WarmupEntry {
+    /// Returns the method of the request.
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    /// Returns the URI of the request.
+    pub fn uri(&self) -> &Uri {
+        &self.uri
+    }
+
+    /// Returns the status code of the response, if the request completed.
+    pub fn status(&self) -> Option<StatusCode> {
+        self.outcome.as_ref().ok().cloned()
+    }
+
+    /// Returns the description of the error, if the request did not complete.
+    pub fn error(&self) -> Option<&str> {
+        self.outcome.as_ref().err().map(String::as_str)
+    }
+
+    /// Returns the time taken by the request.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// Returns `true` if the response status is successful or a redirection.
+    pub fn is_success(&self) -> bool {
+        match self.outcome {
+            Ok(status) => status.is_success() || status.is_redirection(),
+            Err(..) => false,
+        }
+    }
+}
+
+impl fmt::Display for WarmupEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} -> ", self.method, self.uri)?;
+        match self.outcome {
+            Ok(status) => write!(f, "{}", status)?,
+            Err(ref err) => f.write_str(err)?,
+        }
+        write!(f, " ({:?})", self.elapsed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{Listener, Server},
+        futures::future::FutureResult,
+        std::{
+            io,
+            sync::{
+                atomic::{AtomicBool, AtomicUsize, Ordering},
+                Arc, Mutex,
+            },
+        },
+        tokio::net::TcpStream,
+        tsukuyomi_service::MakeService,
+    };
+
+    /// A listener that yields no connection, so that `run` returns after the warm-up.
+    struct NoConnections;
+
+    impl Listener for NoConnections {
+        type Conn = TcpStream;
+        type Error = io::Error;
+        type Incoming = futures::stream::Empty<TcpStream, io::Error>;
+
+        fn listen(self) -> Result<Self::Incoming, Self::Error> {
+            Ok(futures::stream::empty())
+        }
+    }
+
+    /// Responds `200 OK`, or `500 Internal Server Error` to the path `/broken`.
+    #[derive(Clone)]
+    struct Handler {
+        initialized: Arc<AtomicBool>,
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl Service<Request<Body>> for Handler {
+        type Response = Response<Body>;
+        type Error = io::Error;
+        type Future = FutureResult<Self::Response, Self::Error>;
+
+        fn poll_ready(&mut self) -> futures::Poll<(), Self::Error> {
+            Ok(Async::Ready(()))
+        }
+
+        fn call(&mut self, request: Request<Body>) -> Self::Future {
+            self.initialized.store(true, Ordering::SeqCst);
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            let mut response = Response::new(Body::from("hello"));
+            if request.uri().path() == "/broken" {
+                *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+            }
+            futures::future::ok(response)
+        }
+    }
+
+    impl<'a, T> MakeService<&'a T, Request<Body>> for Handler {
+        type Response = Response<Body>;
+        type Error = io::Error;
+        type Service = Self;
+        type MakeError = io::Error;
+        type Future = FutureResult<Self, io::Error>;
+
+        fn make_service(&self, _: &'a T) -> Self::Future {
+            futures::future::ok(self.clone())
+        }
+    }
+
+    fn handler() -> Handler {
+        Handler {
+            initialized: Arc::new(AtomicBool::new(false)),
+            calls: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    #[test]
+    fn initialized_before_accepting() {
+        let handler = handler();
+        let initialized = handler.initialized.clone();
+        let report = Arc::new(Mutex::new(None));
+        let report2 = report.clone();
+
+        Server::new(handler.clone())
+            .bind(NoConnections)
+            .warmup(
+                Warmup::new()
+                    .request(Request::get("/").body(()).unwrap())
+                    .request(Request::get("/broken").body(()).unwrap())
+                    .on_complete(move |report| {
+                        // called before the server starts accepting the connections.
+                        assert!(initialized.load(Ordering::SeqCst));
+                        let statuses: Vec<_> =
+                            report.entries().iter().map(WarmupEntry::status).collect();
+                        *report2.lock().unwrap() = Some(statuses);
+                    }),
+            )
+            .current_thread()
+            .run()
+            .expect("the failures should not be fatal");
+
+        assert_eq!(handler.calls.load(Ordering::SeqCst), 2);
+        assert_eq!(
+            *report.lock().unwrap(),
+            Some(vec![
+                Some(StatusCode::OK),
+                Some(StatusCode::INTERNAL_SERVER_ERROR)
+            ])
+        );
+    }
+
+    #[test]
+    fn fatal_failure_aborts_startup() {
+        let err = Server::new(handler())
+            .bind(NoConnections)
+            .warmup(
+                Warmup::new()
+                    .request(Request::get("/broken").body(()).unwrap())
+                    .fatal(true),
+            )
+            .run()
+            .unwrap_err();
+        assert!(err.to_string().contains("/broken"), "{}", err);
+    }
+}
@@ -0,0 +1,131 @@
+use {
+    futures::{future, Future, Stream},
+    std::{
+        io,
+        net::SocketAddr,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        time::{Duration, Instant},
+    },
+    tokio::{runtime::current_thread, timer::Delay},
+    tsukuyomi_server::connect,
+};
+
+type Attempt = Box<dyn Future<Item = &'static str, Error = io::Error>>;
+
+/// Set when the connection attempt holding it is dropped.
+struct Cancelled(Arc<AtomicBool>);
+
+impl Drop for Cancelled {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+}
+
+fn slow(name: &'static str, cancelled: &Arc<AtomicBool>) -> Attempt {
+    let guard = Cancelled(cancelled.clone());
+    Box::new(
+        Delay::new(Instant::now() + Duration::from_secs(10))
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))
+            .map(move |()| {
+                drop(guard);
+                name
+            }),
+    )
+}
+
+#[test]
+fn fallback_wins_when_preferred_is_slow() -> io::Result<()> {
+    let v6: SocketAddr = "[::1]:80".parse().unwrap();
+    let v4: SocketAddr = "127.0.0.1:80".parse().unwrap();
+    let attempt_delay = Duration::from_millis(50);
+    let cancelled = Arc::new(AtomicBool::new(false));
+
+    let start = Instant::now();
+    let (conn, addr) = current_thread::block_on_all(connect::race(
+        vec![v6, v4],
+        attempt_delay,
+        |addr| -> Attempt {
+            if addr.is_ipv6() {
+                slow("v6", &cancelled)
+            } else {
+                Box::new(future::ok("v4"))
+            }
+        },
+    ))?;
+    let elapsed = start.elapsed();
+
+    assert_eq!(conn, "v4");
+    assert_eq!(addr, v4);
+    assert!(elapsed >= attempt_delay, "{:?}", elapsed);
+    assert!(elapsed < attempt_delay * 4, "{:?}", elapsed);
+    assert!(
+        cancelled.load(Ordering::SeqCst),
+        "the loser should be cancelled"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn failure_starts_next_attempt_immediately() -> io::Result<()> {
+    let attempt_delay = Duration::from_secs(10);
+
+    let start = Instant::now();
+    let (conn, addr) = current_thread::block_on_all(connect::race(
+        vec!["[::1]:80".parse().unwrap(), "127.0.0.1:80".parse().unwrap()],
+        attempt_delay,
+        |addr| -> Attempt {
+            if addr.is_ipv6() {
+                Box::new(future::err(io::ErrorKind::ConnectionRefused.into()))
+            } else {
+                Box::new(future::ok("v4"))
+            }
+        },
+    ))?;
+
+    assert_eq!(conn, "v4");
+    assert!(addr.is_ipv4());
+    assert!(start.elapsed() < attempt_delay);
+
+    Ok(())
+}
+
+#[test]
+fn all_attempts_failed() {
+    let result = current_thread::block_on_all(connect::race(
+        vec!["[::1]:80".parse().unwrap(), "127.0.0.1:80".parse().unwrap()],
+        Duration::from_millis(10),
+        |_| future::err::<(), _>(io::Error::from(io::ErrorKind::ConnectionRefused)),
+    ));
+    assert_eq!(result.unwrap_err().kind(), io::ErrorKind::ConnectionRefused);
+}
+
+#[test]
+fn connect_with_hyper_client() -> tsukuyomi_server::Result<()> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+    std::thread::spawn(move || {
+        use std::io::{Read, Write};
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut buf = [0; 1024];
+        let _ = stream.read(&mut buf).unwrap();
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhello")
+            .unwrap();
+    });
+
+    let client = hyper::Client::builder()
+        .build::<_, hyper::Body>(connect::HappyEyeballs::new().nodelay(true));
+    let mut runtime = tokio::runtime::Runtime::new()?;
+    let response = runtime.block_on(
+        client
+            .get(format!("http://{}/", addr).parse()?)
+            .and_then(|response| response.into_body().concat2()),
+    )?;
+    assert_eq!(&*response, b"hello");
+
+    Ok(())
+}
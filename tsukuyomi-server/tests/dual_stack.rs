@@ -0,0 +1,57 @@
+use {
+    http::Response,
+    hyper::Body,
+    std::{
+        io::{self, Read, Write},
+        net::{Ipv6Addr, SocketAddr, TcpListener, TcpStream},
+        thread,
+    },
+    tsukuyomi_server::{BindStrategy, Server},
+    tsukuyomi_service::{make_service_ref, service_fn},
+};
+
+fn get(addr: SocketAddr) -> io::Result<String> {
+    let mut stream = TcpStream::connect(addr)?;
+    stream.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    Ok(response)
+}
+
+#[test]
+fn dual_stack_accepts_both_families() -> tsukuyomi_server::Result<()> {
+    let server = Server::new(make_service_ref(|_: &tokio::net::TcpStream| {
+        Ok::<_, io::Error>(service_fn(|_| {
+            Ok::<_, io::Error>(Response::new(Body::from("hello")))
+        }))
+    }))
+    .bind_dual_stack(0)?;
+
+    let info = server.startup_info();
+    let strategy = info.bind_strategy().expect("should be reported");
+    let port = info.local_addrs()[0].port();
+    assert_ne!(port, 0);
+    assert!(info.local_addrs().iter().all(|addr| addr.port() == port));
+    match strategy {
+        BindStrategy::DualStackSocket => assert_eq!(info.local_addrs().len(), 1),
+        BindStrategy::SeparateSockets => assert_eq!(info.local_addrs().len(), 2),
+        BindStrategy::Ipv4Only => assert!(info.local_addrs()[0].is_ipv4()),
+    }
+    thread::spawn(move || server.run());
+
+    let response = get(([127, 0, 0, 1], port).into())?;
+    assert!(response.ends_with("\r\n\r\nhello"), "{}", response);
+
+    let ipv6_loopback = strategy != BindStrategy::Ipv4Only && TcpListener::bind("[::1]:0").is_ok();
+    if ipv6_loopback {
+        let response = get((Ipv6Addr::LOCALHOST, port).into())?;
+        assert!(response.ends_with("\r\n\r\nhello"), "{}", response);
+    } else {
+        eprintln!(
+            "skipping the IPv6 client: the IPv6 loopback is not available ({})",
+            strategy
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,86 @@
+use {
+    http::Response,
+    hyper::Body,
+    std::{
+        io::{self, Read, Write},
+        net::{SocketAddr, TcpListener, TcpStream},
+        thread,
+    },
+    tsukuyomi_server::{InheritedListeners, Server},
+    tsukuyomi_service::{make_service_ref, service_fn},
+};
+
+fn get(addr: SocketAddr) -> io::Result<String> {
+    let mut stream = TcpStream::connect(addr)?;
+    stream.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    Ok(response)
+}
+
+#[test]
+fn serve_on_inherited_listener() -> tsukuyomi_server::Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+
+    let server = Server::from_listener(
+        make_service_ref(|_: &tokio::net::TcpStream| {
+            Ok::<_, io::Error>(service_fn(|_| {
+                Ok::<_, io::Error>(Response::new(Body::from("hello")))
+            }))
+        }),
+        listener,
+    )?;
+    thread::spawn(move || server.run());
+
+    let response = get(addr)?;
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+    assert!(response.ends_with("\r\n\r\nhello"), "{}", response);
+
+    Ok(())
+}
+
+#[test]
+fn serve_on_multiple_inherited_listeners() -> tsukuyomi_server::Result<()> {
+    let listeners = vec![
+        TcpListener::bind("127.0.0.1:0")?,
+        TcpListener::bind("127.0.0.1:0")?,
+    ];
+    let addrs: Vec<SocketAddr> = listeners
+        .iter()
+        .map(TcpListener::local_addr)
+        .collect::<io::Result<_>>()?;
+
+    // the actual addresses of the listeners bound to the ephemeral ports are reported.
+    let inherited = InheritedListeners::new(
+        listeners
+            .iter()
+            .map(TcpListener::try_clone)
+            .collect::<io::Result<_>>()?,
+    )?;
+    assert_eq!(inherited.local_addrs(), &addrs[..]);
+    assert!(addrs.iter().all(|addr| addr.port() != 0));
+    drop(inherited);
+
+    let server = Server::from_listeners(
+        make_service_ref(|_: &tokio::net::TcpStream| {
+            Ok::<_, io::Error>(service_fn(|_| {
+                Ok::<_, io::Error>(Response::new(Body::from("hello")))
+            }))
+        }),
+        listeners,
+    )?;
+    thread::spawn(move || server.run());
+
+    for &addr in &addrs {
+        let response = get(addr)?;
+        assert!(response.ends_with("\r\n\r\nhello"), "{}", response);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn empty_listeners() {
+    assert!(InheritedListeners::new(vec![]).is_err());
+}
@@ -0,0 +1,179 @@
+#![cfg(feature = "use-rustls")]
+
+use {
+    rustls::{internal::pemfile, Certificate, ClientConfig, ClientSession, ServerSession, Session},
+    std::{
+        fs,
+        io::{Read, Write},
+        path::{Path, PathBuf},
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::{Duration, UNIX_EPOCH},
+    },
+    tsukuyomi_server::tls::{self, TlsConfig},
+    tsukuyomi_service::ConnectionInfo,
+};
+
+const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/tls");
+
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new() -> Self {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "tsukuyomi-server-tls-{}-{}",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::SeqCst)
+        ));
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    fn cert_path(&self) -> PathBuf {
+        self.0.join("cert.pem")
+    }
+
+    fn key_path(&self) -> PathBuf {
+        self.0.join("key.pem")
+    }
+
+    /// Replaces the PEM files with the fixture of the specified name.
+    fn install(&self, name: &str) {
+        let fixtures = Path::new(FIXTURES);
+        fs::copy(fixtures.join(format!("{}.crt", name)), self.cert_path()).unwrap();
+        fs::copy(fixtures.join(format!("{}.key", name)), self.key_path()).unwrap();
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn certificate(name: &str) -> Certificate {
+    let path = Path::new(FIXTURES).join(format!("{}.crt", name));
+    let mut reader = std::io::BufReader::new(fs::File::open(path).unwrap());
+    pemfile::certs(&mut reader).unwrap().remove(0)
+}
+
+fn client_config() -> Arc<ClientConfig> {
+    let mut config = ClientConfig::new();
+    config.root_store.add(&certificate("a")).unwrap();
+    config.root_store.add(&certificate("b")).unwrap();
+    Arc::new(config)
+}
+
+fn transfer(from: &mut dyn Session, to: &mut dyn Session) {
+    let mut buf = vec![];
+    while from.wants_write() {
+        from.write_tls(&mut buf).unwrap();
+    }
+    to.read_tls(&mut &buf[..]).unwrap();
+    to.process_new_packets().unwrap();
+}
+
+/// Establishes a TLS session in memory.
+fn connect(config: &TlsConfig) -> (ClientSession, ServerSession) {
+    connect_with(config, &client_config())
+}
+
+fn connect_with(
+    config: &TlsConfig,
+    client_config: &Arc<ClientConfig>,
+) -> (ClientSession, ServerSession) {
+    let name = webpki::DNSNameRef::try_from_ascii_str("localhost").unwrap();
+    let mut client = ClientSession::new(client_config, name);
+    let mut server = ServerSession::new(&config.server_config());
+    while client.is_handshaking() || server.is_handshaking() {
+        transfer(&mut client, &mut server);
+        transfer(&mut server, &mut client);
+    }
+    (client, server)
+}
+
+fn ping(client: &mut ClientSession, server: &mut ServerSession) -> String {
+    client.write_all(b"ping").unwrap();
+    transfer(client, server);
+    let mut buf = [0; 4];
+    server.read_exact(&mut buf).unwrap();
+    server.write_all(b"pong").unwrap();
+    transfer(server, client);
+    client.read_exact(&mut buf).unwrap();
+    String::from_utf8(buf.to_vec()).unwrap()
+}
+
+#[test]
+fn reload_swaps_certificate_for_new_connections() -> tsukuyomi_server::Result<()> {
+    let dir = TempDir::new();
+    dir.install("a");
+    let config = TlsConfig::from_pem_files(dir.cert_path(), dir.key_path())?;
+    assert!(config.not_after() > UNIX_EPOCH + Duration::from_secs(4_000_000_000));
+
+    let (mut client_a, mut server_a) = connect(&config);
+    assert_eq!(
+        client_a.get_peer_certificates(),
+        Some(vec![certificate("a")])
+    );
+
+    dir.install("b");
+    config.reload()?;
+
+    let (client_b, _server_b) = connect(&config);
+    assert_eq!(
+        client_b.get_peer_certificates(),
+        Some(vec![certificate("b")])
+    );
+
+    // the connection established before reloading is still usable.
+    assert_eq!(ping(&mut client_a, &mut server_a), "pong");
+
+    Ok(())
+}
+
+#[test]
+fn invalid_files_keep_current_certificate() -> tsukuyomi_server::Result<()> {
+    let dir = TempDir::new();
+    dir.install("a");
+    let config = TlsConfig::from_pem_files(dir.cert_path(), dir.key_path())?;
+    let not_after = config.not_after();
+
+    fs::write(dir.cert_path(), "-----BEGIN CERTIFICATE-----\ngarbage\n").unwrap();
+    assert!(config.reload().is_err());
+
+    dir.install("expired");
+    assert!(config.reload().is_err());
+    assert_eq!(config.not_after(), not_after);
+
+    let (client, _server) = connect(&config);
+    assert_eq!(client.get_peer_certificates(), Some(vec![certificate("a")]));
+
+    Ok(())
+}
+
+#[test]
+fn session_info_reports_negotiated_parameters() -> tsukuyomi_server::Result<()> {
+    let dir = TempDir::new();
+    dir.install("a");
+    let config = TlsConfig::from_pem_files(dir.cert_path(), dir.key_path())?
+        .alpn_protocols(&["h2", "http/1.1"]);
+
+    let mut client_config = ClientConfig::clone(&client_config());
+    client_config.set_protocols(&["http/1.1".into()]);
+    let (_client, server) = connect_with(&config, &Arc::new(client_config));
+
+    let mut info = ConnectionInfo::new(([127, 0, 0, 1], 12345).into());
+    tls::session_info(&server, &mut info);
+    assert_eq!(info.server_name(), Some("localhost"));
+    assert_eq!(info.alpn_protocol(), Some("http/1.1"));
+    assert!(info
+        .tls_version()
+        .map_or(false, |v| v.starts_with("TLSv1.")));
+    assert!(info.cipher_suite().is_some());
+    assert_eq!(info.stream_id(), None);
+
+    Ok(())
+}
@@ -0,0 +1,85 @@
+use std::net::SocketAddr;
+
+/// The information about the connection which the request arrived on.
+///
+/// The server inserts this value into the extension map of the incoming `Request`
+/// if the peer address is available. The fields other than the peer address are
+/// `None` on the plain HTTP connections.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConnectionInfo {
+    peer_addr: SocketAddr,
+    server_name: Option<String>,
+    alpn_protocol: Option<String>,
+    tls_version: Option<String>,
+    cipher_suite: Option<String>,
+    stream_id: Option<u32>,
+}
+
+impl ConnectionInfo {
+    /// Creates a `ConnectionInfo` with the address of the direct peer.
+    pub fn new(peer_addr: SocketAddr) -> Self {
+        Self {
+            peer_addr,
+            server_name: None,
+            alpn_protocol: None,
+            tls_version: None,
+            cipher_suite: None,
+            stream_id: None,
+        }
+    }
+
+    /// Returns the address of the direct peer.
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.peer_addr
+    }
+
+    /// Returns the server name presented by the client with SNI.
+    pub fn server_name(&self) -> Option<&str> {
+        self.server_name.as_ref().map(|s| &**s)
+    }
+
+    /// Sets the server name presented by the client with SNI.
+    pub fn set_server_name(&mut self, server_name: Option<String>) {
+        self.server_name = server_name;
+    }
+
+    /// Returns the protocol negotiated with ALPN, such as `"h2"`.
+    pub fn alpn_protocol(&self) -> Option<&str> {
+        self.alpn_protocol.as_ref().map(|s| &**s)
+    }
+
+    /// Sets the protocol negotiated with ALPN.
+    pub fn set_alpn_protocol(&mut self, alpn_protocol: Option<String>) {
+        self.alpn_protocol = alpn_protocol;
+    }
+
+    /// Returns the version of TLS, such as `"TLSv1.3"`.
+    pub fn tls_version(&self) -> Option<&str> {
+        self.tls_version.as_ref().map(|s| &**s)
+    }
+
+    /// Sets the version of TLS.
+    pub fn set_tls_version(&mut self, tls_version: Option<String>) {
+        self.tls_version = tls_version;
+    }
+
+    /// Returns the name of the negotiated cipher suite.
+    pub fn cipher_suite(&self) -> Option<&str> {
+        self.cipher_suite.as_ref().map(|s| &**s)
+    }
+
+    /// Sets the name of the negotiated cipher suite.
+    pub fn set_cipher_suite(&mut self, cipher_suite: Option<String>) {
+        self.cipher_suite = cipher_suite;
+    }
+
+    /// Returns the identifier of the HTTP/2 stream which the request arrived on.
+    pub fn stream_id(&self) -> Option<u32> {
+        self.stream_id
+    }
+
+    /// Sets the identifier of the HTTP/2 stream.
+    pub fn set_stream_id(&mut self, stream_id: Option<u32>) {
+        self.stream_id = stream_id;
+    }
+}
@@ -13,8 +13,12 @@
 )]
 #![forbid(clippy::unimplemented)]
 
+mod connection;
+
 use futures::{Async, Future, IntoFuture, Poll};
 
+pub use crate::connection::ConnectionInfo;
+
 #[doc(no_inline)]
 pub use tower_service::Service;
 
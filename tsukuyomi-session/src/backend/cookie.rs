@@ -1,23 +1,64 @@
 use {
-    crate::{Backend, RawSession},
+    crate::{Backend, ConcurrencyPolicy, SessionInner},
     cookie::{Cookie, CookieBuilder},
+    flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression},
     serde_json,
-    std::{borrow::Cow, collections::HashMap, fmt, sync::Arc},
+    std::{
+        borrow::Cow,
+        collections::HashMap,
+        fmt,
+        io::{Read, Write},
+        sync::Arc,
+    },
     tsukuyomi::{
         error::{Error, Result},
         future::{Poll, TryFuture},
         input::{Cookies, Input},
+        vendor::http::header::USER_AGENT,
     },
 };
 
 #[cfg(feature = "secure")]
-use cookie::Key;
+use cookie::{CookieJar, Key};
+
+/// The version of the serialization format, stored as the first byte of the payload.
+///
+/// * (no version): the JSON object of the session data, written by the older releases.
+/// * `1`: `'1' <binding> '.' <JSON>`, where `<binding>` is the hex-encoded hash of
+///   the client attributes, or empty if the session is not bound to the client.
+/// * `2`: `'2' <binding> '.' <compressed>`, where `<compressed>` is the JSON compressed
+///   with deflate and encoded in URL-safe base64 without padding.
+/// * `3` and `4`: `'3' <counter> '.' <binding> '.' <JSON>` and the compressed one,
+///   where `<counter>` is the decimal version counter of the session data.  They are
+///   written only if the concurrency policy is other than `LastWriteWins`.
+const PAYLOAD_VERSION: u8 = b'1';
+const PAYLOAD_VERSION_COMPRESSED: u8 = b'2';
+const PAYLOAD_VERSION_COUNTED: u8 = b'3';
+const PAYLOAD_VERSION_COUNTED_COMPRESSED: u8 = b'4';
+
+/// The default value of the maximum length of the cookie value.
+const DEFAULT_MAX_COOKIE_SIZE: usize = 4096;
+
+/// The maximum ratio of the decompressed payload to the maximum cookie size.
+const MAX_COMPRESSION_RATIO: usize = 16;
+
+/// The behavior when the session cookie to be stored exceeds the maximum size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SizeLimitMode {
+    /// Fails the request with `500 Internal Server Error` without storing the session data.
+    Strict,
+    /// Logs a warning listing the keys in the descending order of their sizes,
+    /// and stores the session data anyway.
+    ///
+    /// In this mode, the incoming cookie entry is not rejected by its size.
+    Lenient,
+}
 
 #[cfg(feature = "secure")]
 enum Security {
     Plain,
-    Signed(Key),
-    Private(Key),
+    Signed(Vec<Key>),
+    Private(Vec<Key>),
 }
 
 #[cfg(not(feature = "secure"))]
@@ -31,33 +72,68 @@ impl fmt::Debug for Security {
         match self {
             Security::Plain => f.debug_tuple("Plain").finish(),
             #[cfg(feature = "secure")]
-            Security::Signed(..) => f.debug_tuple("Signed").field(&"<secret key>").finish(),
+            Security::Signed(ref keys) => f
+                .debug_tuple("Signed")
+                .field(&format_args!("<{} secret key(s)>", keys.len()))
+                .finish(),
             #[cfg(feature = "secure")]
-            Security::Private(..) => f.debug_tuple("Private").field(&"<secret key>").finish(),
+            Security::Private(ref keys) => f
+                .debug_tuple("Private")
+                .field(&format_args!("<{} secret key(s)>", keys.len()))
+                .finish(),
         }
     }
 }
 
 impl Security {
+    /// Retrieves the cookie entry, trying all of the keys in order.
     fn get(&self, name: &str, cookies: &mut Cookies<'_>) -> Result<Option<Cookie<'static>>> {
         match self {
             Security::Plain => Ok(cookies.jar()?.get(name).cloned()),
             #[cfg(feature = "secure")]
-            Security::Signed(ref key) => Ok(cookies.signed_jar(key)?.get(name)),
+            Security::Signed(ref keys) => {
+                for key in keys {
+                    if let Some(cookie) = cookies.signed_jar(key)?.get(name) {
+                        return Ok(Some(cookie));
+                    }
+                }
+                Ok(None)
+            }
             #[cfg(feature = "secure")]
-            Security::Private(ref key) => Ok(cookies.private_jar(key)?.get(name)),
+            Security::Private(ref keys) => {
+                for key in keys {
+                    if let Some(cookie) = cookies.private_jar(key)?.get(name) {
+                        return Ok(Some(cookie));
+                    }
+                }
+                Ok(None)
+            }
         }
     }
 
-    fn add(&self, cookie: Cookie<'static>, cookies: &mut Cookies<'_>) -> Result<()> {
+    /// Converts the cookie entry into the form sent to the client, using the first key.
+    fn seal(&self, cookie: Cookie<'static>) -> Cookie<'static> {
         match self {
-            Security::Plain => cookies.jar()?.add(cookie),
+            Security::Plain => cookie,
             #[cfg(feature = "secure")]
-            Security::Signed(ref key) => cookies.signed_jar(key)?.add(cookie),
+            Security::Signed(ref keys) => {
+                let name = cookie.name().to_owned();
+                let mut jar = CookieJar::new();
+                jar.signed(&keys[0]).add(cookie);
+                jar.get(&name)
+                    .cloned()
+                    .expect("the cookie has just been added")
+            }
             #[cfg(feature = "secure")]
-            Security::Private(ref key) => cookies.private_jar(key)?.add(cookie),
+            Security::Private(ref keys) => {
+                let name = cookie.name().to_owned();
+                let mut jar = CookieJar::new();
+                jar.private(&keys[0]).add(cookie);
+                jar.get(&name)
+                    .cloned()
+                    .expect("the cookie has just been added")
+            }
         }
-        Ok(())
     }
 }
 
@@ -74,6 +150,12 @@ impl CookieBackend {
                 security,
                 cookie_name: "tsukuyomi-session".into(),
                 builder: Box::new(|cookie| cookie),
+                max_cookie_size: DEFAULT_MAX_COOKIE_SIZE,
+                size_limit_mode: SizeLimitMode::Strict,
+                max_value_size: None,
+                compress: false,
+                bind_user_agent: false,
+                concurrency_policy: ConcurrencyPolicy::LastWriteWins,
             }),
         }
     }
@@ -90,13 +172,50 @@ impl CookieBackend {
     /// Create a new `CookieBackend` that signs the cookie entry with the specified `Key`.
     #[cfg(feature = "secure")]
     pub fn signed(secret_key: Key) -> Self {
-        Self::new(Security::Signed(secret_key))
+        Self::signed_with_keys(vec![secret_key])
+    }
+
+    /// Create a new `CookieBackend` that signs the cookie entry with the list of `Key`s.
+    ///
+    /// The first key is used for signing the new cookie entries, and all of the keys
+    /// are tried in order for verifying the incoming ones. Adding a new key in front
+    /// of the list keeps the sessions signed with the old keys valid during rotation.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if the list of keys is empty.
+    #[cfg(feature = "secure")]
+    pub fn signed_with_keys(secret_keys: impl IntoIterator<Item = Key>) -> Self {
+        let keys: Vec<Key> = secret_keys.into_iter().collect();
+        assert!(
+            !keys.is_empty(),
+            "the list of secret keys must not be empty"
+        );
+        Self::new(Security::Signed(keys))
     }
 
     /// Create a new `CookieBackend` that encrypts the cookie entry with the specified `Key`.
     #[cfg(feature = "secure")]
     pub fn private(secret_key: Key) -> Self {
-        Self::new(Security::Private(secret_key))
+        Self::private_with_keys(vec![secret_key])
+    }
+
+    /// Create a new `CookieBackend` that encrypts the cookie entry with the list of `Key`s.
+    ///
+    /// The first key is used for encrypting the new cookie entries, and all of the keys
+    /// are tried in order for decrypting the incoming ones.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if the list of keys is empty.
+    #[cfg(feature = "secure")]
+    pub fn private_with_keys(secret_keys: impl IntoIterator<Item = Key>) -> Self {
+        let keys: Vec<Key> = secret_keys.into_iter().collect();
+        assert!(
+            !keys.is_empty(),
+            "the list of secret keys must not be empty"
+        );
+        Self::new(Security::Private(keys))
     }
 
     /// Sets the name of Cookie entry to be used for storing the session data.
@@ -115,12 +234,87 @@ impl CookieBackend {
         self.inner_mut().builder = Box::new(builder);
         self
     }
+
+    /// Sets the maximum length of the cookie value.
+    ///
+    /// The incoming cookie entry longer than this value is rejected with
+    /// `400 Bad Request` before verifying or decrypting it. The session data is
+    /// checked against this value by the length of the encoded `Set-Cookie` entry,
+    /// including its attributes, and handled according to `size_limit_mode`
+    /// when it exceeds the limit.
+    ///
+    /// The default value is `4096`.
+    pub fn max_cookie_size(mut self, value: usize) -> Self {
+        self.inner_mut().max_cookie_size = value;
+        self
+    }
+
+    /// Sets the behavior when the session cookie to be stored exceeds `max_cookie_size`.
+    ///
+    /// The default value is `SizeLimitMode::Strict`.
+    pub fn size_limit_mode(mut self, mode: SizeLimitMode) -> Self {
+        self.inner_mut().size_limit_mode = mode;
+        self
+    }
+
+    /// Sets the maximum length of a serialized session value.
+    ///
+    /// `Session::set` fails immediately when the value exceeds this limit.
+    /// By default, the length of each value is not limited.
+    pub fn max_value_size(mut self, value: usize) -> Self {
+        self.inner_mut().max_value_size = Some(value);
+        self
+    }
+
+    /// Sets whether to compress the session data with deflate before storing it.
+    ///
+    /// The compressed and uncompressed cookie entries are both accepted regardless
+    /// of this setting, so it can be switched without discarding the existing sessions.
+    ///
+    /// The default value is `false`.
+    pub fn compress(mut self, enabled: bool) -> Self {
+        self.inner_mut().compress = enabled;
+        self
+    }
+
+    /// Sets whether to bind the session to the `User-Agent` of the client.
+    ///
+    /// If enabled, a hash of `User-Agent` is stored with the session data, and
+    /// the session is discarded when the request is sent from another client.
+    /// This raises the cost of reusing a stolen cookie, but does not prevent it.
+    ///
+    /// The default value is `false`.
+    pub fn bind_user_agent(mut self, enabled: bool) -> Self {
+        self.inner_mut().bind_user_agent = enabled;
+        self
+    }
+
+    /// Sets the policy applied when the session data has been modified since it was loaded.
+    ///
+    /// If the policy is other than `LastWriteWins`, the version counter is stored
+    /// in the payload and compared with the session cookie in the cookie jar at the
+    /// time of writing.  Note that the cookie entries are stored in the client, so
+    /// only the modifications visible in the cookie jar of the current request are
+    /// detected; the backends storing the data on the server side are required for
+    /// detecting the ones by the concurrent requests.
+    ///
+    /// The default value is `ConcurrencyPolicy::LastWriteWins`.
+    pub fn concurrency_policy(mut self, policy: ConcurrencyPolicy) -> Self {
+        self.inner_mut().concurrency_policy = policy;
+        self
+    }
 }
 
 struct CookieBackendInner {
     security: Security,
     cookie_name: Cow<'static, str>,
     builder: Box<dyn Fn(CookieBuilder) -> CookieBuilder + Send + Sync + 'static>,
+    max_cookie_size: usize,
+    size_limit_mode: SizeLimitMode,
+    max_value_size: Option<usize>,
+    compress: bool,
+    bind_user_agent: bool,
+    concurrency_policy: ConcurrencyPolicy,
 }
 
 #[cfg_attr(tarpaulin, skip)]
@@ -129,58 +323,270 @@ impl fmt::Debug for CookieBackendInner {
         f.debug_struct("CookieBackendInner")
             .field("security", &self.security)
             .field("cookie_name", &self.cookie_name)
+            .field("max_cookie_size", &self.max_cookie_size)
+            .field("size_limit_mode", &self.size_limit_mode)
+            .field("max_value_size", &self.max_value_size)
+            .field("compress", &self.compress)
+            .field("bind_user_agent", &self.bind_user_agent)
+            .field("concurrency_policy", &self.concurrency_policy)
             .finish()
     }
 }
 
 impl CookieBackendInner {
-    fn deserialize(&self, s: &str) -> Result<HashMap<String, String>> {
-        serde_json::from_str(s).map_err(tsukuyomi::error::bad_request)
+    /// Decodes the payload and its version counter, returning `None` if it is
+    /// bound to another client.
+    fn deserialize(
+        &self,
+        s: &str,
+        binding: Option<&str>,
+    ) -> Result<Option<(u64, HashMap<String, String>)>> {
+        fn split_field(payload: &str) -> Result<(&str, &str)> {
+            let pos = payload
+                .find('.')
+                .ok_or_else(|| tsukuyomi::error::bad_request("malformed session payload"))?;
+            Ok((&payload[..pos], &payload[pos + 1..]))
+        }
+
+        let (counter, bound, data, compressed) = match s.as_bytes().first() {
+            Some(b'{') => (0, None, s, false),
+            Some(&version)
+                if [
+                    PAYLOAD_VERSION,
+                    PAYLOAD_VERSION_COMPRESSED,
+                    PAYLOAD_VERSION_COUNTED,
+                    PAYLOAD_VERSION_COUNTED_COMPRESSED,
+                ]
+                .contains(&version) =>
+            {
+                let mut payload = &s[1..];
+                let mut counter = 0;
+                if version == PAYLOAD_VERSION_COUNTED
+                    || version == PAYLOAD_VERSION_COUNTED_COMPRESSED
+                {
+                    let (field, rest) = split_field(payload)?;
+                    counter = field.parse().map_err(tsukuyomi::error::bad_request)?;
+                    payload = rest;
+                }
+                let (bound, data) = split_field(payload)?;
+                (
+                    counter,
+                    Some(bound).filter(|bound| !bound.is_empty()),
+                    data,
+                    version == PAYLOAD_VERSION_COMPRESSED
+                        || version == PAYLOAD_VERSION_COUNTED_COMPRESSED,
+                )
+            }
+            _ => {
+                return Err(tsukuyomi::error::bad_request(
+                    "unsupported version of session payload",
+                ))
+            }
+        };
+        if binding.is_some() && bound != binding {
+            return Ok(None);
+        }
+        let data = if compressed {
+            Cow::Owned(self.decompress(data)?)
+        } else {
+            Cow::Borrowed(data)
+        };
+        serde_json::from_str(&data)
+            .map(|map| Some((counter, map)))
+            .map_err(tsukuyomi::error::bad_request)
     }
 
-    fn serialize(&self, map: &HashMap<String, String>) -> String {
-        serde_json::to_string(&map).expect("should be success")
+    fn decompress(&self, data: &str) -> Result<String> {
+        let compressed = base64::decode_config(data, base64::URL_SAFE_NO_PAD)
+            .map_err(tsukuyomi::error::bad_request)?;
+        // The decompressed payload is bounded to reject the highly compressed ones.
+        let limit = self.max_cookie_size.saturating_mul(MAX_COMPRESSION_RATIO);
+        let mut decompressed = String::new();
+        DeflateDecoder::new(&compressed[..])
+            .take(limit as u64 + 1)
+            .read_to_string(&mut decompressed)
+            .map_err(tsukuyomi::error::bad_request)?;
+        if decompressed.len() > limit {
+            return Err(tsukuyomi::error::bad_request(
+                "the session payload is too large",
+            ));
+        }
+        Ok(decompressed)
     }
 
-    fn read(&self, input: &mut Input<'_>) -> tsukuyomi::Result<Inner> {
-        match self.security.get(&*self.cookie_name, input.cookies)? {
-            Some(cookie) => {
-                let map = self.deserialize(cookie.value())?;
-                Ok(Inner::Some(map))
-            }
-            None => Ok(Inner::Empty),
+    fn serialize(
+        &self,
+        map: &HashMap<String, String>,
+        binding: Option<&str>,
+        counter: Option<u64>,
+    ) -> String {
+        let version = match (counter.is_some(), self.compress) {
+            (false, false) => PAYLOAD_VERSION,
+            (false, true) => PAYLOAD_VERSION_COMPRESSED,
+            (true, false) => PAYLOAD_VERSION_COUNTED,
+            (true, true) => PAYLOAD_VERSION_COUNTED_COMPRESSED,
+        };
+        let mut data = serde_json::to_string(&map).expect("should be success");
+        if self.compress {
+            let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+            encoder
+                .write_all(data.as_bytes())
+                .expect("writing to Vec never fails");
+            let compressed = encoder.finish().expect("writing to Vec never fails");
+            data = base64::encode_config(&compressed, base64::URL_SAFE_NO_PAD);
         }
+        let counter = counter.map_or_else(String::new, |counter| format!("{}.", counter));
+        format!(
+            "{}{}{}.{}",
+            version as char,
+            counter,
+            binding.unwrap_or(""),
+            data
+        )
     }
 
-    fn write(&self, input: &mut Input<'_>, inner: Inner) -> tsukuyomi::Result<()> {
-        match inner {
-            Inner::Empty => {}
-            Inner::Some(map) => {
-                let value = self.serialize(&map);
-                let cookie =
-                    (self.builder)(Cookie::build(self.cookie_name.clone(), value)).finish();
-                self.security.add(cookie, input.cookies)?;
+    /// Computes the value that binds the session to the client, if enabled.
+    fn binding(&self, input: &Input<'_>) -> Option<String> {
+        if !self.bind_user_agent {
+            return None;
+        }
+        let user_agent = input
+            .request
+            .headers()
+            .get(USER_AGENT)
+            .map_or(&[][..], |value| value.as_bytes());
+        Some(format!("{:016x}", fnv1a(user_agent)))
+    }
+
+    fn read(&self, input: &mut Input<'_>) -> tsukuyomi::Result<SessionInner> {
+        if let Some(cookie) = input.cookies.jar()?.get(&self.cookie_name) {
+            if self.size_limit_mode == SizeLimitMode::Strict
+                && cookie.value().len() > self.max_cookie_size
+            {
+                return Err(tsukuyomi::error::bad_request(
+                    "the session cookie is too large",
+                ));
             }
-            Inner::Clear => {
-                input
-                    .cookies
-                    .jar()?
-                    .remove(Cookie::named(self.cookie_name.clone()));
+        }
+
+        let binding = self.binding(input);
+        let binding = binding.as_ref().map(String::as_str);
+        match self.security.get(&*self.cookie_name, input.cookies)? {
+            Some(cookie) => match self.deserialize(cookie.value(), binding)? {
+                Some((counter, map)) => Ok(SessionInner::from_map(map).with_version(counter)),
+                None => Ok(SessionInner::empty()),
+            },
+            None => Ok(SessionInner::empty()),
+        }
+    }
+
+    fn write(&self, input: &mut Input<'_>, inner: SessionInner) -> tsukuyomi::Result<()> {
+        if !inner.is_cleared() && inner.map().is_none() {
+            return Ok(());
+        }
+
+        let binding = self.binding(input);
+        let binding = binding.as_ref().map(String::as_str);
+        let (map, counter) = match self.concurrency_policy {
+            ConcurrencyPolicy::LastWriteWins => (inner.into_map(), None),
+            policy => {
+                // The session cookie in the jar reflects the writes by the preceding
+                // handlers in the current request.
+                let current = match self.security.get(&*self.cookie_name, input.cookies)? {
+                    Some(cookie) => self.deserialize(cookie.value(), binding)?,
+                    None => None,
+                };
+                let current_version = current.as_ref().map_or(0, |&(counter, _)| counter);
+                let map = inner.resolve(
+                    policy,
+                    current_version,
+                    current.as_ref().map(|(_, map)| map),
+                )?;
+                (map, Some(current_version + 1))
             }
+        };
+
+        match map {
+            None => input
+                .cookies
+                .jar()?
+                .remove(Cookie::named(self.cookie_name.clone())),
+            Some(map) => self.add_cookie(input, &map, binding, counter)?,
         }
 
         Ok(())
     }
+
+    fn add_cookie(
+        &self,
+        input: &mut Input<'_>,
+        map: &HashMap<String, String>,
+        binding: Option<&str>,
+        counter: Option<u64>,
+    ) -> tsukuyomi::Result<()> {
+        let value = self.serialize(map, binding, counter);
+        let cookie = (self.builder)(Cookie::build(self.cookie_name.clone(), value)).finish();
+        let cookie = self.security.seal(cookie);
+        let size = cookie.encoded().to_string().len();
+        if size > self.max_cookie_size {
+            match self.size_limit_mode {
+                SizeLimitMode::Strict => {
+                    return Err(tsukuyomi::error::internal_server_error(format!(
+                        "the session data exceeds the maximum cookie size ({} > {} bytes)",
+                        size, self.max_cookie_size
+                    )));
+                }
+                SizeLimitMode::Lenient => log::warn!(
+                    "session cookie exceeds the maximum size: size={} max={} keys={}",
+                    size,
+                    self.max_cookie_size,
+                    keys_by_size(map),
+                ),
+            }
+        }
+        input.cookies.jar()?.add(cookie);
+        Ok(())
+    }
+}
+
+/// Formats the keys of the session data in the descending order of the sizes of their entries.
+fn keys_by_size(map: &HashMap<String, String>) -> String {
+    let mut entries: Vec<_> = map
+        .iter()
+        .map(|(key, value)| (key, key.len() + value.len()))
+        .collect();
+    entries.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    entries
+        .iter()
+        .map(|(key, size)| format!("{}({})", key, size))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Computes the 64-bit FNV-1a hash, which is stable across the releases.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+    })
 }
 
 impl Backend for CookieBackend {
-    type Session = CookieSession;
     type ReadError = Error;
     type ReadSession = ReadSession;
+    type WriteError = Error;
+    type WriteSession = WriteSession;
 
     fn read(&self) -> Self::ReadSession {
         ReadSession(Some(self.clone()))
     }
+
+    fn write(&self, inner: SessionInner) -> Self::WriteSession {
+        WriteSession(Some((self.clone(), inner)))
+    }
+
+    fn max_value_size(&self) -> Option<usize> {
+        self.inner.max_value_size
+    }
 }
 
 #[doc(hidden)]
@@ -188,83 +594,19 @@ impl Backend for CookieBackend {
 pub struct ReadSession(Option<CookieBackend>);
 
 impl TryFuture for ReadSession {
-    type Ok = CookieSession;
+    type Ok = SessionInner;
     type Error = Error;
 
     #[inline]
     fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
         let backend = self.0.take().expect("the future has already been polled");
-        backend
-            .inner
-            .read(input)
-            .map(|inner| CookieSession { inner, backend }.into())
-    }
-}
-
-#[derive(Debug)]
-pub struct CookieSession {
-    inner: Inner,
-    backend: CookieBackend,
-}
-
-#[derive(Debug)]
-enum Inner {
-    Empty,
-    Some(HashMap<String, String>),
-    Clear,
-}
-
-impl RawSession for CookieSession {
-    type WriteSession = WriteSession;
-    type WriteError = Error;
-
-    fn get(&self, name: &str) -> Option<&str> {
-        match self.inner {
-            Inner::Some(ref map) => map.get(name).map(|s| &**s),
-            _ => None,
-        }
-    }
-
-    fn set(&mut self, name: &str, value: String) {
-        match self.inner {
-            Inner::Empty => {}
-            Inner::Some(ref mut map) => {
-                map.insert(name.to_owned(), value);
-                return;
-            }
-            Inner::Clear => return,
-        }
-
-        match std::mem::replace(&mut self.inner, Inner::Empty) {
-            Inner::Empty => {
-                self.inner = Inner::Some({
-                    let mut map = HashMap::new();
-                    map.insert(name.to_owned(), value);
-                    map
-                });
-            }
-            Inner::Some(..) | Inner::Clear => unreachable!(),
-        }
-    }
-
-    fn remove(&mut self, name: &str) {
-        if let Inner::Some(ref mut map) = self.inner {
-            map.remove(name);
-        }
-    }
-
-    fn clear(&mut self) {
-        self.inner = Inner::Clear;
-    }
-
-    fn write(self) -> Self::WriteSession {
-        WriteSession(Some(self))
+        backend.inner.read(input).map(Into::into)
     }
 }
 
 #[doc(hidden)]
 #[allow(missing_debug_implementations)]
-pub struct WriteSession(Option<CookieSession>);
+pub struct WriteSession(Option<(CookieBackend, SessionInner)>);
 
 impl TryFuture for WriteSession {
     type Ok = ();
@@ -272,11 +614,7 @@ impl TryFuture for WriteSession {
 
     #[inline]
     fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
-        let session = self.0.take().expect("the future has already been polled");
-        session
-            .backend
-            .inner
-            .write(input, session.inner)
-            .map(Into::into)
+        let (backend, inner) = self.0.take().expect("the future has already been polled");
+        backend.inner.write(input, inner).map(Into::into)
     }
 }
@@ -3,6 +3,6 @@
 mod cookie;
 mod redis;
 
-pub use self::cookie::CookieBackend;
+pub use self::cookie::{CookieBackend, SizeLimitMode};
 #[cfg(feature = "use-redis")]
 pub use self::redis::RedisBackend;
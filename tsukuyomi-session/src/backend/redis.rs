@@ -1,10 +1,10 @@
 #![cfg(feature = "use-redis")]
 
 use {
-    crate::{Backend, RawSession},
+    crate::{Backend, ConcurrencyPolicy, SessionInner},
     cookie::Cookie,
-    futures::try_ready,
-    redis::{r#async::Connection, Client, RedisFuture},
+    futures::{try_ready, Future},
+    redis::{r#async::Connection, Client, Cmd, RedisFuture, Value},
     std::time::Duration,
     std::{borrow::Cow, collections::HashMap, mem, sync::Arc},
     tsukuyomi::{
@@ -15,6 +15,30 @@ use {
     uuid::Uuid,
 };
 
+/// The Lua script that stores the session data only if the version counter has not
+/// advanced, returning the current version and data if it has.
+///
+/// * `KEYS`: the key of the session data and the one of the version counter.
+/// * `ARGV`: the expected version, the serialized session data (empty if the session
+///   is cleared), and the timeout in seconds (`0` if not set).
+const SWAP_SCRIPT: &str = r#"
+local version = tonumber(redis.call('GET', KEYS[2]) or '0')
+if version ~= tonumber(ARGV[1]) then
+  return {version, redis.call('GET', KEYS[1])}
+end
+if ARGV[2] == '' then
+  redis.call('DEL', KEYS[1])
+else
+  redis.call('SET', KEYS[1], ARGV[2])
+end
+redis.call('SET', KEYS[2], version + 1)
+if tonumber(ARGV[3]) > 0 then
+  redis.call('EXPIRE', KEYS[1], ARGV[3])
+  redis.call('EXPIRE', KEYS[2], ARGV[3])
+end
+return 1
+"#;
+
 /// A `Backend` using Redis.
 #[derive(Debug, Clone)]
 pub struct RedisBackend {
@@ -30,6 +54,7 @@ impl RedisBackend {
                 key_prefix: "tsukuyomi-session".into(),
                 cookie_name: "session-id".into(),
                 timeout: None,
+                concurrency_policy: ConcurrencyPolicy::LastWriteWins,
             }),
         }
     }
@@ -61,6 +86,19 @@ impl RedisBackend {
         self.inner_mut().timeout = Some(timeout);
         self
     }
+
+    /// Sets the policy applied when the session data has been modified by another
+    /// request since it was loaded.
+    ///
+    /// If the policy is other than `LastWriteWins`, the version counter is stored in
+    /// the key suffixed with `:version`, and the session data is written with
+    /// compare-and-swap by a Lua script.
+    ///
+    /// The default value is `ConcurrencyPolicy::LastWriteWins`.
+    pub fn concurrency_policy(mut self, policy: ConcurrencyPolicy) -> Self {
+        self.inner_mut().concurrency_policy = policy;
+        self
+    }
 }
 
 #[derive(Debug)]
@@ -69,6 +107,7 @@ struct RedisBackendInner {
     key_prefix: Cow<'static, str>,
     cookie_name: Cow<'static, str>,
     timeout: Option<Duration>,
+    concurrency_policy: ConcurrencyPolicy,
 }
 
 impl RedisBackendInner {
@@ -76,6 +115,10 @@ impl RedisBackendInner {
         format!("{}:{}", self.key_prefix, id)
     }
 
+    fn generate_version_key(&self, id: &Uuid) -> String {
+        format!("{}:{}:version", self.key_prefix, id)
+    }
+
     fn get_session_id(&self, input: &mut Input<'_>) -> Result<Option<Uuid>> {
         match input.cookies.jar()?.get(&self.cookie_name) {
             Some(cookie) => {
@@ -91,84 +134,26 @@ impl RedisBackendInner {
 }
 
 impl Backend for RedisBackend {
-    type Session = RedisSession;
     type ReadError = Error;
     type ReadSession = ReadSession;
+    type WriteError = Error;
+    type WriteSession = WriteSession;
 
     fn read(&self) -> Self::ReadSession {
         ReadSession {
             state: ReadSessionState::Init,
-            backend: Some(self.clone()),
+            backend: self.clone(),
         }
     }
-}
 
-#[allow(missing_debug_implementations)]
-pub struct RedisSession {
-    inner: Inner,
-    backend: RedisBackend,
-    conn: Connection,
-    session_id: Option<Uuid>,
-}
-
-#[derive(Debug)]
-enum Inner {
-    Empty,
-    Some(HashMap<String, String>),
-    Clear,
-}
-
-impl RawSession for RedisSession {
-    type WriteError = Error;
-    type WriteSession = WriteSession;
-
-    fn get(&self, name: &str) -> Option<&str> {
-        match self.inner {
-            Inner::Some(ref map) => map.get(name).map(|s| &**s),
-            _ => None,
-        }
-    }
-
-    fn set(&mut self, name: &str, value: String) {
-        match self.inner {
-            Inner::Empty => {}
-            Inner::Some(ref mut map) => {
-                map.insert(name.to_owned(), value);
-                return;
-            }
-            Inner::Clear => return,
-        }
-
-        match std::mem::replace(&mut self.inner, Inner::Empty) {
-            Inner::Empty => {
-                self.inner = Inner::Some({
-                    let mut map = HashMap::new();
-                    map.insert(name.to_owned(), value);
-                    map
-                });
-            }
-            Inner::Some(..) | Inner::Clear => unreachable!(),
-        }
-    }
-
-    fn remove(&mut self, name: &str) {
-        if let Inner::Some(ref mut map) = self.inner {
-            map.remove(name);
-        }
-    }
-
-    fn clear(&mut self) {
-        self.inner = Inner::Clear;
-    }
-
-    fn write(self) -> Self::WriteSession {
-        WriteSession::Init(Some(self))
+    fn write(&self, inner: SessionInner) -> Self::WriteSession {
+        WriteSession::Init(Some((self.clone(), inner)))
     }
 }
 
 #[allow(missing_debug_implementations)]
 pub struct ReadSession {
-    backend: Option<RedisBackend>,
+    backend: RedisBackend,
     state: ReadSessionState,
 }
 
@@ -176,113 +161,128 @@ enum ReadSessionState {
     Init,
     Connecting {
         future: RedisFuture<Connection>,
-        key_name: Option<String>,
-        session_id: Option<Uuid>,
-    },
-    Fetch {
-        future: RedisFuture<(Connection, Option<String>)>,
-        session_id: Uuid,
+        key_name: String,
+        version_key_name: String,
     },
+    Fetch(RedisFuture<(Connection, (Option<String>, Option<u64>))>),
     Done,
 }
 
 impl TryFuture for ReadSession {
-    type Ok = RedisSession;
+    type Ok = SessionInner;
     type Error = Error;
 
     fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
         use self::ReadSessionState::*;
         loop {
-            let (conn, value) = match self.state {
-                Init => {
-                    let backend = self.backend.as_ref().expect("unexpected condition");
-                    let session_id = backend.inner.get_session_id(input)?;
-                    let key_name = session_id
-                        .as_ref()
-                        .map(|session_id| backend.inner.generate_redis_key(session_id));
-                    self.state = ReadSessionState::Connecting {
-                        future: backend.inner.client.get_async_connection(),
-                        key_name,
-                        session_id,
-                    };
-                    continue;
-                }
-                Connecting { ref mut future, .. } => {
-                    let conn = try_ready!(future
-                        .poll()
-                        .map_err(tsukuyomi::error::internal_server_error));
-                    (Some(conn), None)
-                }
-                Fetch { ref mut future, .. } => {
-                    let (conn, value) = try_ready!(future
-                        .poll()
-                        .map_err(tsukuyomi::error::internal_server_error));
-                    (Some(conn), value)
-                }
-                Done => panic!("unexpected state"),
-            };
-
-            match (mem::replace(&mut self.state, Done), conn, value) {
-                (
-                    Connecting {
-                        key_name: Some(key_name),
-                        session_id: Some(session_id),
-                        ..
+            self.state = match mem::replace(&mut self.state, Done) {
+                Init => match self.backend.inner.get_session_id(input)? {
+                    Some(session_id) => Connecting {
+                        future: self.backend.inner.client.get_async_connection(),
+                        key_name: self.backend.inner.generate_redis_key(&session_id),
+                        version_key_name: self.backend.inner.generate_version_key(&session_id),
                     },
-                    Some(conn),
-                    None,
-                ) => {
-                    self.state = Fetch {
-                        future: redis::cmd("GET").arg(key_name).query_async(conn),
-                        session_id,
-                    };
-                }
-
-                (Fetch { session_id, .. }, Some(conn), Some(value)) => {
-                    let map = serde_json::from_str(&value)
-                        .map_err(tsukuyomi::error::internal_server_error)?;
-                    return Ok(Async::Ready(RedisSession {
-                        inner: Inner::Some(map),
-                        backend: self
-                            .backend
-                            .take()
-                            .expect("the future has already been polled."),
-                        conn,
-                        session_id: Some(session_id),
-                    }));
-                }
-
-                (
-                    Connecting {
-                        session_id: None,
-                        key_name: None,
-                        ..
-                    },
-                    Some(conn),
-                    None,
-                )
-                | (Fetch { .. }, Some(conn), None) => {
-                    return Ok(Async::Ready(RedisSession {
-                        inner: Inner::Empty,
-                        backend: self
-                            .backend
-                            .take()
-                            .expect("the future has already been polled."),
-                        conn,
-                        session_id: None,
-                    }));
-                }
-
-                _ => unreachable!("unexpected condition"),
-            }
+                    None => return Ok(Async::Ready(SessionInner::empty())),
+                },
+                Connecting {
+                    mut future,
+                    key_name,
+                    version_key_name,
+                } => match future
+                    .poll()
+                    .map_err(tsukuyomi::error::internal_server_error)?
+                {
+                    Async::Ready(conn) => Fetch(
+                        redis::cmd("MGET")
+                            .arg(key_name)
+                            .arg(version_key_name)
+                            .query_async(conn),
+                    ),
+                    Async::NotReady => {
+                        self.state = Connecting {
+                            future,
+                            key_name,
+                            version_key_name,
+                        };
+                        return Ok(Async::NotReady);
+                    }
+                },
+                Fetch(mut future) => match future
+                    .poll()
+                    .map_err(tsukuyomi::error::internal_server_error)?
+                {
+                    Async::Ready((_conn, (Some(value), version))) => {
+                        let map = serde_json::from_str(&value)
+                            .map_err(tsukuyomi::error::internal_server_error)?;
+                        return Ok(Async::Ready(
+                            SessionInner::from_map(map).with_version(version.unwrap_or(0)),
+                        ));
+                    }
+                    Async::Ready((_conn, (None, version))) => {
+                        return Ok(Async::Ready(
+                            SessionInner::empty().with_version(version.unwrap_or(0)),
+                        ));
+                    }
+                    Async::NotReady => {
+                        self.state = Fetch(future);
+                        return Ok(Async::NotReady);
+                    }
+                },
+                Done => panic!("the future has already been polled."),
+            };
         }
     }
 }
 
 #[allow(missing_debug_implementations)]
 pub enum WriteSession {
-    Init(Option<RedisSession>),
+    Init(Option<(RedisBackend, SessionInner)>),
+    Connecting(RedisFuture<Connection>, Cmd),
     Op(RedisFuture<(Connection, ())>),
+    SwapConnecting(RedisFuture<Connection>, Option<Swap>),
+    Swap(RedisFuture<(Connection, Value)>, Option<Swap>),
+}
+
+/// The context of writing the session data with compare-and-swap.
+#[allow(missing_debug_implementations)]
+pub struct Swap {
+    inner: SessionInner,
+    policy: ConcurrencyPolicy,
+    key_name: String,
+    version_key_name: String,
+    timeout: Option<Duration>,
+}
+
+impl Swap {
+    fn cmd(&self, expected: u64, map: Option<&HashMap<String, String>>) -> Cmd {
+        let value = map.map_or_else(String::new, |map| {
+            serde_json::to_string(map).expect("should be successed")
+        });
+        let mut cmd = redis::cmd("EVAL");
+        cmd.arg(SWAP_SCRIPT)
+            .arg(2)
+            .arg(&self.key_name)
+            .arg(&self.version_key_name)
+            .arg(expected)
+            .arg(value)
+            .arg(self.timeout.map_or(0, |timeout| timeout.as_secs()));
+        cmd
+    }
+
+    /// Resolves the session data against the one stored by another request,
+    /// and creates the command to retry the write.
+    fn retry(&self, reply: &Value) -> Result<Cmd> {
+        let (version, current): (u64, Option<String>) =
+            redis::from_redis_value(reply).map_err(tsukuyomi::error::internal_server_error)?;
+        let current: Option<HashMap<String, String>> = match current {
+            Some(current) => Some(
+                serde_json::from_str(&current).map_err(tsukuyomi::error::internal_server_error)?,
+            ),
+            None => None,
+        };
+        let map = self.inner.resolve(self.policy, version, current.as_ref())?;
+        Ok(self.cmd(version, map.as_ref()))
+    }
 }
 
 impl TryFuture for WriteSession {
@@ -293,66 +293,111 @@ impl TryFuture for WriteSession {
         loop {
             *self = match self {
                 WriteSession::Init(ref mut session) => {
-                    let RedisSession {
-                        inner,
-                        backend,
-                        conn,
-                        session_id,
-                    } = session.take().unwrap();
-
-                    match inner {
-                        Inner::Empty => return Ok(Async::Ready(())),
+                    let (backend, inner) =
+                        session.take().expect("the future has already been polled.");
+                    let session_id = backend.inner.get_session_id(input)?;
 
-                        Inner::Some(value) => {
-                            let session_id = session_id.unwrap_or_else(Uuid::new_v4);
-                            match input.cookies.jar() {
-                                Ok(jar) => jar.add(Cookie::new(
+                    if backend.inner.concurrency_policy != ConcurrencyPolicy::LastWriteWins {
+                        let session_id = match session_id {
+                            Some(session_id) => session_id,
+                            None if inner.is_cleared() => return Ok(Async::Ready(())),
+                            None => {
+                                let session_id = Uuid::new_v4();
+                                input.cookies.jar()?.add(Cookie::new(
                                     backend.inner.cookie_name.clone(),
                                     session_id.to_string(),
-                                )),
-                                Err(err) => return Err(err),
+                                ));
+                                session_id
                             }
-                            let redis_key = backend.inner.generate_redis_key(&session_id);
-
-                            let value = serde_json::to_string(&value).expect("should be successed");
-                            let op = match backend.inner.timeout {
-                                Some(timeout) => redis::cmd("SETEX")
-                                    .arg(redis_key)
-                                    .arg(timeout.as_secs())
-                                    .arg(value)
-                                    .query_async(conn),
-                                None => redis::cmd("SET")
-                                    .arg(redis_key)
-                                    .arg(value)
-                                    .query_async(conn),
-                            };
-                            WriteSession::Op(op)
+                        };
+                        if inner.is_cleared() {
+                            input
+                                .cookies
+                                .jar()?
+                                .remove(Cookie::named(backend.inner.cookie_name.clone()));
                         }
-
-                        Inner::Clear => {
-                            let session_id = if let Some(session_id) = session_id {
-                                session_id
-                            } else {
-                                return Ok(Async::Ready(()));
+                        let swap = Swap {
+                            inner,
+                            policy: backend.inner.concurrency_policy,
+                            key_name: backend.inner.generate_redis_key(&session_id),
+                            version_key_name: backend.inner.generate_version_key(&session_id),
+                            timeout: backend.inner.timeout,
+                        };
+                        WriteSession::SwapConnecting(
+                            backend.inner.client.get_async_connection(),
+                            Some(swap),
+                        )
+                    } else {
+                        let cmd = if inner.is_cleared() {
+                            let session_id = match session_id {
+                                Some(session_id) => session_id,
+                                None => return Ok(Async::Ready(())),
                             };
-                            match input.cookies.jar() {
-                                Ok(jar) => {
-                                    jar.remove(Cookie::named(backend.inner.cookie_name.clone()))
+                            input
+                                .cookies
+                                .jar()?
+                                .remove(Cookie::named(backend.inner.cookie_name.clone()));
+                            let mut cmd = redis::cmd("DEL");
+                            cmd.arg(backend.inner.generate_redis_key(&session_id));
+                            cmd
+                        } else if let Some(map) = inner.map() {
+                            let session_id = session_id.unwrap_or_else(Uuid::new_v4);
+                            input.cookies.jar()?.add(Cookie::new(
+                                backend.inner.cookie_name.clone(),
+                                session_id.to_string(),
+                            ));
+                            let redis_key = backend.inner.generate_redis_key(&session_id);
+                            let value = serde_json::to_string(map).expect("should be successed");
+                            match backend.inner.timeout {
+                                Some(timeout) => {
+                                    let mut cmd = redis::cmd("SETEX");
+                                    cmd.arg(redis_key).arg(timeout.as_secs()).arg(value);
+                                    cmd
+                                }
+                                None => {
+                                    let mut cmd = redis::cmd("SET");
+                                    cmd.arg(redis_key).arg(value);
+                                    cmd
                                 }
-                                Err(err) => return Err(err),
                             }
-                            let redis_key = backend.inner.generate_redis_key(&session_id);
-                            let op = redis::cmd("DEL").arg(redis_key).query_async(conn);
-                            WriteSession::Op(op)
-                        }
+                        } else {
+                            return Ok(Async::Ready(()));
+                        };
+
+                        WriteSession::Connecting(backend.inner.client.get_async_connection(), cmd)
                     }
                 }
+                WriteSession::Connecting(ref mut future, ref cmd) => {
+                    let conn = try_ready!(future
+                        .poll()
+                        .map_err(tsukuyomi::error::internal_server_error));
+                    WriteSession::Op(cmd.query_async(conn))
+                }
                 WriteSession::Op(ref mut op) => {
                     return op
                         .poll()
                         .map(|x| x.map(|_| ()))
                         .map_err(tsukuyomi::error::internal_server_error)
                 }
+                WriteSession::SwapConnecting(ref mut future, ref mut swap) => {
+                    let conn = try_ready!(future
+                        .poll()
+                        .map_err(tsukuyomi::error::internal_server_error));
+                    let swap = swap.take().expect("the future has already been polled.");
+                    let cmd = swap.cmd(swap.inner.version(), swap.inner.map());
+                    WriteSession::Swap(cmd.query_async(conn), Some(swap))
+                }
+                WriteSession::Swap(ref mut future, ref mut swap) => {
+                    let (conn, reply) = try_ready!(future
+                        .poll()
+                        .map_err(tsukuyomi::error::internal_server_error));
+                    if let Value::Int(..) = reply {
+                        return Ok(Async::Ready(()));
+                    }
+                    let swap = swap.take().expect("the future has already been polled.");
+                    let cmd = swap.retry(&reply)?;
+                    WriteSession::Swap(cmd.query_async(conn), Some(swap))
+                }
             }
         }
     }
@@ -16,111 +16,309 @@ mod util;
 
 use {
     serde::{de::DeserializeOwned, ser::Serialize},
+    std::{collections::HashMap, rc::Rc, sync::Arc},
     tsukuyomi::{
         error::Error, //
         extractor::Extractor,
-        future::{MaybeDone, TryFuture},
+        future::TryFuture,
         responder::Responder,
     },
+    tsukuyomi::{
+        output::ResponseBody,
+        vendor::http::{Response, StatusCode},
+    },
 };
 
 /// A trait representing the session backend.
+///
+/// The session data is loaded by `read` before calling the handler, and
+/// the modification is stored by `write` after the handler has completed.
+/// `write` is skipped if the session data has not been modified during
+/// the request handling.
 pub trait Backend {
-    /// The type of session which will be crated by `ReadSession`.
-    type Session: RawSession;
     /// The type or errors which will occur when polling `ReadSession`.
     type ReadError: Into<Error>;
-    /// The type of `TryFuture` that will return a `Session`.
-    type ReadSession: TryFuture<Ok = Self::Session, Error = Self::ReadError>;
+    /// The type of `TryFuture` that will return the session data.
+    type ReadSession: TryFuture<Ok = SessionInner, Error = Self::ReadError>;
+    /// The error type during writing modification to the backend.
+    type WriteError: Into<Error>;
+    /// The type of `TryFuture` to write the modification of session data.
+    type WriteSession: TryFuture<Ok = (), Error = Self::WriteError>;
 
-    /// Creates a `TryFuture` to create a `Session` asynchronously.
+    /// Creates a `TryFuture` to load the session data asynchronously.
     fn read(&self) -> Self::ReadSession;
-}
 
-impl<B> Backend for Box<B>
-where
-    B: Backend,
-{
-    type Session = B::Session;
-    type ReadError = B::ReadError;
-    type ReadSession = B::ReadSession;
+    /// Creates a `TryFuture` to store the modified session data asynchronously.
+    fn write(&self, inner: SessionInner) -> Self::WriteSession;
 
-    #[inline]
-    fn read(&self) -> Self::ReadSession {
-        (**self).read()
+    /// Returns whether to store the modified session data even if the
+    /// handler has replied an error, or a response with the status code
+    /// 4xx or 5xx.
+    ///
+    /// The default implementation always returns `false`.
+    fn persist_on_error(&self) -> bool {
+        false
+    }
+
+    /// Returns the maximum length of a serialized session value, if any.
+    ///
+    /// `Session::set` rejects the value longer than this limit before it is
+    /// stored into the session data.
+    ///
+    /// The default implementation returns `None`.
+    fn max_value_size(&self) -> Option<usize> {
+        None
     }
 }
 
-impl<B> Backend for std::rc::Rc<B>
-where
-    B: Backend,
-{
-    type Session = B::Session;
-    type ReadError = B::ReadError;
-    type ReadSession = B::ReadSession;
+macro_rules! impl_backend_for_pointers {
+    ($($t:ident),*) => {$(
+        impl<B> Backend for $t<B>
+        where
+            B: Backend,
+        {
+            type ReadError = B::ReadError;
+            type ReadSession = B::ReadSession;
+            type WriteError = B::WriteError;
+            type WriteSession = B::WriteSession;
+
+            #[inline]
+            fn read(&self) -> Self::ReadSession {
+                (**self).read()
+            }
+
+            #[inline]
+            fn write(&self, inner: SessionInner) -> Self::WriteSession {
+                (**self).write(inner)
+            }
+
+            #[inline]
+            fn persist_on_error(&self) -> bool {
+                (**self).persist_on_error()
+            }
+
+            #[inline]
+            fn max_value_size(&self) -> Option<usize> {
+                (**self).max_value_size()
+            }
+        }
+    )*};
+}
+
+impl_backend_for_pointers!(Box, Rc, Arc);
+
+/// The policy applied when the stored session data has been modified by another
+/// request since it was loaded.
+///
+/// The backends supporting the optimistic concurrency detect the modification by
+/// comparing the version counter stored with the session data, and resolve the data
+/// to be stored with `SessionInner::resolve`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConcurrencyPolicy {
+    /// Overwrites the stored session data with the one of the current request.
+    ///
+    /// The version counter is not checked with this policy.
+    LastWriteWins,
+
+    /// Merges the modification of the current request into the stored session data
+    /// by keys.
+    ///
+    /// The keys set or removed by the current request overwrite the stored ones, and
+    /// the other keys in the stored data are kept.  Clearing the session wins over
+    /// the modification by another request.
+    Merge,
+
+    /// Discards the modification of the current request, and replaces the response
+    /// with `409 Conflict`.
+    Reject,
+}
 
-    #[inline]
-    fn read(&self) -> Self::ReadSession {
-        (**self).read()
+impl Default for ConcurrencyPolicy {
+    fn default() -> Self {
+        ConcurrencyPolicy::LastWriteWins
     }
 }
 
-impl<B> Backend for std::sync::Arc<B>
-where
-    B: Backend,
-{
-    type Session = B::Session;
-    type ReadError = B::ReadError;
-    type ReadSession = B::ReadSession;
+/// The raw session data loaded from the backend.
+///
+/// This type tracks whether the data has been modified since it was loaded,
+/// as well as the version counter of the stored data and the modified keys.
+#[derive(Debug, Default)]
+pub struct SessionInner {
+    state: State,
+    dirty: bool,
+    version: u64,
+    changes: HashMap<String, Option<String>>,
+}
 
-    #[inline]
-    fn read(&self) -> Self::ReadSession {
-        (**self).read()
+#[derive(Debug)]
+enum State {
+    Empty,
+    Some(HashMap<String, String>),
+    Clear,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        State::Empty
     }
 }
 
-/// A trait that abstracts the management of session data during request handling.
-pub trait RawSession {
-    /// The error type during writing modification to the backend.
-    type WriteError: Into<Error>;
-    /// A `TryFuture` to write the modification of session data.
-    type WriteSession: TryFuture<Ok = (), Error = Self::WriteError>;
+impl SessionInner {
+    /// Creates an empty `SessionInner`.
+    pub fn empty() -> Self {
+        Self::default()
+    }
 
-    /// Returns the value of session data with the specified key name, if exists.
-    fn get(&self, name: &str) -> Option<&str>;
+    /// Creates a `SessionInner` from the values loaded from the backend.
+    pub fn from_map(map: HashMap<String, String>) -> Self {
+        Self {
+            state: State::Some(map),
+            ..Self::default()
+        }
+    }
 
-    /// Appends a value to session data with the specified key name.
-    fn set(&mut self, name: &str, value: String);
+    /// Sets the version counter of the session data loaded from the backend.
+    ///
+    /// The backends without versioning leave it as `0`.
+    pub fn with_version(self, version: u64) -> Self {
+        Self { version, ..self }
+    }
 
-    /// Removes the value with the specified key name from session data.
-    fn remove(&mut self, name: &str);
+    /// Returns the version counter of the session data at the time it was loaded.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
 
-    /// Mark the session data as *cleared*.
-    fn clear(&mut self);
+    /// Returns the value with the specified key name, if exists.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        match self.state {
+            State::Some(ref map) => map.get(name).map(|s| &**s),
+            _ => None,
+        }
+    }
 
-    /// Consumes itself and creates a `TryFuture` to write the modification of session data.
-    fn write(self) -> Self::WriteSession;
+    /// Appends a value with the specified key name.
+    ///
+    /// This method does nothing if the session data has already been cleared.
+    pub fn set(&mut self, name: &str, value: String) {
+        match self.state {
+            State::Empty => self.state = State::Some(HashMap::new()),
+            State::Some(..) => {}
+            State::Clear => return,
+        }
+        if let State::Some(ref mut map) = self.state {
+            map.insert(name.to_owned(), value.clone());
+        }
+        self.changes.insert(name.to_owned(), Some(value));
+        self.dirty = true;
+    }
+
+    /// Removes the value with the specified key name.
+    pub fn remove(&mut self, name: &str) {
+        if let State::Some(ref mut map) = self.state {
+            if map.remove(name).is_some() {
+                self.changes.insert(name.to_owned(), None);
+                self.dirty = true;
+            }
+        }
+    }
+
+    /// Marks the session data as *cleared*.
+    pub fn clear(&mut self) {
+        self.state = State::Clear;
+        self.changes.clear();
+        self.dirty = true;
+    }
+
+    /// Returns `true` if the session data has been modified since it was loaded.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Returns `true` if the session data has been marked as cleared.
+    pub fn is_cleared(&self) -> bool {
+        match self.state {
+            State::Clear => true,
+            _ => false,
+        }
+    }
+
+    /// Returns a reference to the map of session values, if exists.
+    pub fn map(&self) -> Option<&HashMap<String, String>> {
+        match self.state {
+            State::Some(ref map) => Some(map),
+            _ => None,
+        }
+    }
+
+    /// Consumes itself and returns the map of session values, if exists.
+    pub fn into_map(self) -> Option<HashMap<String, String>> {
+        match self.state {
+            State::Some(map) => Some(map),
+            _ => None,
+        }
+    }
+
+    /// Resolves the session data to be stored, against the data `current` stored
+    /// in the backend as the version `current_version`.
+    ///
+    /// If the stored version has not advanced since the session data was loaded,
+    /// the data of this session is returned as it is regardless of the policy.
+    /// The returned value is `None` if the session data should be removed from
+    /// the backend.
+    ///
+    /// This method returns a `409 Conflict` error under `ConcurrencyPolicy::Reject`
+    /// if the stored version has advanced.
+    pub fn resolve(
+        &self,
+        policy: ConcurrencyPolicy,
+        current_version: u64,
+        current: Option<&HashMap<String, String>>,
+    ) -> tsukuyomi::Result<Option<HashMap<String, String>>> {
+        if current_version == self.version || policy == ConcurrencyPolicy::LastWriteWins {
+            return Ok(self.map().cloned());
+        }
+        match policy {
+            ConcurrencyPolicy::Reject => Err(tsukuyomi::error::custom(
+                StatusCode::CONFLICT,
+                "the session data has been modified by another request",
+            )),
+            _ if self.is_cleared() => Ok(None),
+            _ => {
+                let mut map = current.cloned().unwrap_or_default();
+                for (name, value) in &self.changes {
+                    match value {
+                        Some(value) => map.insert(name.clone(), value.clone()),
+                        None => map.remove(name),
+                    };
+                }
+                Ok(Some(map))
+            }
+        }
+    }
 }
 
 /// Create an `Extractor` which returns a `Session`.
 pub fn session<B>(
     backend: B,
 ) -> impl Extractor<
-    Output = (Session<B::Session>,),
+    Output = (Session<B>,),
     Error = B::ReadError,
-    Extract = self::impl_extractor::SessionExtract<B::ReadSession>, // private
+    Extract = self::impl_extractor::SessionExtract<B>, // private
 >
 where
-    B: Backend,
+    B: Backend + Clone,
 {
     tsukuyomi::extractor::extract(move || self::impl_extractor::SessionExtract {
         read_session: backend.read(),
+        backend: Some(backend.clone()),
     })
 }
 
 mod impl_extractor {
     use {
-        super::{RawSession, Session},
+        super::{Backend, Session},
         tsukuyomi::{
             future::{Poll, TryFuture},
             input::Input,
@@ -128,43 +326,47 @@ mod impl_extractor {
     };
 
     #[allow(missing_debug_implementations)]
-    pub struct SessionExtract<Fut> {
-        pub(super) read_session: Fut,
+    pub struct SessionExtract<B: Backend> {
+        pub(super) read_session: B::ReadSession,
+        pub(super) backend: Option<B>,
     }
 
-    impl<Fut> TryFuture for SessionExtract<Fut>
+    impl<B> TryFuture for SessionExtract<B>
     where
-        Fut: TryFuture,
-        Fut::Ok: RawSession,
+        B: Backend,
     {
-        type Ok = (Session<Fut::Ok>,);
-        type Error = Fut::Error;
+        type Ok = (Session<B>,);
+        type Error = B::ReadError;
 
         #[inline]
         fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
-            self.read_session
-                .poll_ready(input)
-                .map(|x| x.map(|raw| (Session { raw },)))
+            let inner = futures::try_ready!(self.read_session.poll_ready(input));
+            let backend = self
+                .backend
+                .take()
+                .expect("the future has already been polled");
+            Ok((Session { inner, backend },).into())
         }
     }
 }
 
 /// An interface of session values.
 #[derive(Debug)]
-pub struct Session<S: RawSession> {
-    raw: S,
+pub struct Session<B: Backend> {
+    inner: SessionInner,
+    backend: B,
 }
 
-impl<S> Session<S>
+impl<B> Session<B>
 where
-    S: RawSession,
+    B: Backend,
 {
     /// Retrieves a field from this session and parses it into the specified type.
     pub fn get<T>(&self, name: &str) -> tsukuyomi::error::Result<Option<T>>
     where
         T: DeserializeOwned,
     {
-        match self.raw.get(name) {
+        match self.inner.get(name) {
             Some(value) => serde_json::from_str(value)
                 .map_err(tsukuyomi::error::internal_server_error)
                 .map(Some),
@@ -174,81 +376,136 @@ where
 
     /// Returns `true` if the field of specified name exists in this session.
     pub fn contains(&self, name: &str) -> bool {
-        self.raw.get(name).is_some()
+        self.inner.get(name).is_some()
     }
 
     /// Sets a field to this session with serializing the specified value into a string.
+    ///
+    /// This method fails if the serialized value is longer than `Backend::max_value_size`.
     pub fn set<T>(&mut self, name: &str, value: T) -> tsukuyomi::error::Result<()>
     where
         T: Serialize,
     {
         let value = serde_json::to_string(&value) //
             .map_err(tsukuyomi::error::internal_server_error)?;
-        self.raw.set(name, value);
+        if let Some(max_value_size) = self.backend.max_value_size() {
+            if value.len() > max_value_size {
+                return Err(tsukuyomi::error::internal_server_error(format!(
+                    "the session value `{}' exceeds the maximum size ({} > {} bytes)",
+                    name,
+                    value.len(),
+                    max_value_size
+                )));
+            }
+        }
+        self.inner.set(name, value);
         Ok(())
     }
 
     /// Removes a field from this session.
     pub fn remove(&mut self, name: &str) {
-        self.raw.remove(name);
+        self.inner.remove(name);
     }
 
     /// Marks this session cleared.
     pub fn clear(&mut self) {
-        self.raw.clear();
+        self.inner.clear();
     }
 
     /// Finalize the current session with the specified output.
+    ///
+    /// The modification of session data is stored into the backend after
+    /// the output has been converted into an HTTP response.  If the output
+    /// results in an error response, the modification is discarded unless
+    /// `Backend::persist_on_error` returns `true`.
     pub fn finish<T>(
         self,
         output: T,
     ) -> impl Responder<
-        Response = T::Response,
+        Response = Response<ResponseBody>,
         Error = Error,
-        Respond = self::impl_responder::SessionRespond<S::WriteSession, T::Respond>, // private
+        Respond = self::impl_responder::SessionRespond<B, T::Respond>, // private
     >
     where
         T: Responder,
     {
         tsukuyomi::responder::respond(self::impl_responder::SessionRespond {
-            write_session: MaybeDone::Pending(self.raw.write()),
-            respond: MaybeDone::Pending(output.respond()),
+            state: self::impl_responder::State::Respond(output.respond()),
+            session: Some((self.backend, self.inner)),
         })
     }
 }
 
 mod impl_responder {
-    use tsukuyomi::{
-        error::Error,
-        future::{try_ready, MaybeDone, Poll, TryFuture},
-        input::Input,
+    use {
+        super::{Backend, SessionInner},
+        tsukuyomi::{
+            error::Error,
+            future::{Async, Poll, TryFuture},
+            input::Input,
+            output::{IntoResponse, ResponseBody},
+            vendor::http::Response,
+        },
     };
 
     #[allow(missing_debug_implementations)]
-    pub struct SessionRespond<S: TryFuture, T: TryFuture> {
-        pub(super) write_session: MaybeDone<S>,
-        pub(super) respond: MaybeDone<T>,
+    pub struct SessionRespond<B: Backend, T: TryFuture> {
+        pub(super) state: State<B::WriteSession, T>,
+        pub(super) session: Option<(B, SessionInner)>,
+    }
+
+    #[allow(missing_debug_implementations)]
+    pub enum State<W, T> {
+        Respond(T),
+        Write(W, Option<Result<Response<ResponseBody>, Error>>),
     }
 
-    impl<S, T> TryFuture for SessionRespond<S, T>
+    impl<B, T> TryFuture for SessionRespond<B, T>
     where
-        S: TryFuture<Ok = ()>,
+        B: Backend,
         T: TryFuture,
+        T::Ok: IntoResponse,
     {
-        type Ok = T::Ok;
+        type Ok = Response<ResponseBody>;
         type Error = Error;
 
         fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
-            try_ready!(self.write_session.poll_ready(input).map_err(Into::into));
-            try_ready!(self.respond.poll_ready(input).map_err(Into::into));
-            self.write_session
-                .take_item()
-                .expect("the future has already been polled.");
-            let output = self
-                .respond
-                .take_item()
-                .expect("the future has already been polled.");
-            Ok(output.into())
+            loop {
+                self.state = match self.state {
+                    State::Respond(ref mut respond) => {
+                        let result = match respond.poll_ready(input) {
+                            Ok(Async::NotReady) => return Ok(Async::NotReady),
+                            Ok(Async::Ready(output)) => output
+                                .into_response(input.request)
+                                .map(|response| response.map(Into::into))
+                                .map_err(Into::into),
+                            Err(err) => Err(err.into()),
+                        };
+                        let (backend, inner) = self
+                            .session
+                            .take()
+                            .expect("the future has already been polled");
+                        let is_error = match result {
+                            Ok(ref response) => {
+                                let status = response.status();
+                                status.is_client_error() || status.is_server_error()
+                            }
+                            Err(..) => true,
+                        };
+                        if !inner.is_dirty() || (is_error && !backend.persist_on_error()) {
+                            return result.map(Async::Ready);
+                        }
+                        State::Write(backend.write(inner), Some(result))
+                    }
+                    State::Write(ref mut write, ref mut result) => {
+                        futures::try_ready!(write.poll_ready(input).map_err(Into::into));
+                        return result
+                            .take()
+                            .expect("the future has already been polled")
+                            .map(Async::Ready);
+                    }
+                };
+            }
         }
     }
 }
@@ -1,10 +1,26 @@
 use {
-    http::Request,
-    tsukuyomi::{config::prelude::*, App},
+    http::{header::COOKIE, Request},
+    std::{
+        collections::HashMap,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+    },
+    tsukuyomi::{
+        config::prelude::*,
+        error::Error,
+        future::{Poll, TryFuture},
+        input::Input,
+        App,
+    },
     tsukuyomi_session::{
-        backend::CookieBackend, //
+        backend::{CookieBackend, SizeLimitMode}, //
         session,
+        Backend,
+        ConcurrencyPolicy,
         Session,
+        SessionInner,
     },
 };
 
@@ -59,7 +75,7 @@ fn smoketest() -> tsukuyomi_server::Result<()> {
     assert!(response.headers().contains_key("set-cookie"));
 
     let response = session.perform(Request::get("/counter"))?;
-    assert!(response.headers().contains_key("set-cookie"));
+    assert!(!response.headers().contains_key("set-cookie"));
     assert_eq!(response.body().to_utf8()?, "Some(1)");
 
     let response = session.perform(Request::put("/counter"))?;
@@ -75,3 +91,587 @@ fn smoketest() -> tsukuyomi_server::Result<()> {
 
     Ok(())
 }
+
+fn counter_app(backend: CookieBackend) -> tsukuyomi::app::Result<App> {
+    let session = Arc::new(session(backend.cookie_name("session")));
+    App::create(path!("/counter").to(chain![
+            endpoint::get() //
+                .extract(session.clone())
+                .call_async(|session: Session<_>| -> tsukuyomi::Result<_> {
+                    let counter: Option<i64> = session.get("counter")?;
+                    Ok(session.finish(format!("{:?}", counter)))
+                }),
+            endpoint::put() //
+                .extract(session)
+                .call_async(|mut session: Session<_>| -> tsukuyomi::Result<_> {
+                    let counter: i64 = session.get("counter")?.unwrap_or_default();
+                    session.set("counter", counter + 1)?;
+                    Ok(session.finish(format!("{}", counter)))
+                }),
+        ]))
+}
+
+#[cfg(feature = "secure")]
+#[test]
+fn key_rotation() -> tsukuyomi_server::Result<()> {
+    use cookie::Key;
+
+    let key_a = Key::generate();
+    let key_b = Key::generate();
+
+    // the session written with the key A.
+    let mut server =
+        tsukuyomi_server::test::server(counter_app(CookieBackend::private(key_a.clone()))?)?;
+    let mut session = server.new_session()?.save_cookies(true);
+    session.perform(Request::put("/counter"))?;
+    let cookie_a = format!("session={}", session.cookie("session").unwrap());
+
+    // still readable after rotating the keys to [B, A].
+    let mut server =
+        tsukuyomi_server::test::server(counter_app(CookieBackend::private_with_keys(vec![
+            key_b.clone(),
+            key_a,
+        ]))?)?;
+    let response = server.perform(Request::get("/counter").header(COOKIE, &*cookie_a))?;
+    assert_eq!(response.body().to_utf8()?, "Some(1)");
+
+    // the modified session is re-encrypted with the key B.
+    let mut session = server.new_session()?.save_cookies(true);
+    session.perform(Request::put("/counter").header(COOKIE, &*cookie_a))?;
+    let cookie_b = format!("session={}", session.cookie("session").unwrap());
+
+    // unreadable after the key A is dropped.
+    let mut server = tsukuyomi_server::test::server(counter_app(CookieBackend::private(key_b))?)?;
+    let response = server.perform(Request::get("/counter").header(COOKIE, &*cookie_a))?;
+    assert_eq!(response.body().to_utf8()?, "None");
+    let response = server.perform(Request::get("/counter").header(COOKIE, &*cookie_b))?;
+    assert_eq!(response.body().to_utf8()?, "Some(2)");
+
+    Ok(())
+}
+
+#[test]
+fn payload_version() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(counter_app(CookieBackend::plain())?)?;
+
+    // `{"counter":"1"}`
+    let data = "%7B%22counter%22%3A%221%22%7D";
+
+    let response =
+        server.perform(Request::get("/counter").header(COOKIE, format!("session=1.{}", data)))?;
+    assert_eq!(response.body().to_utf8()?, "Some(1)");
+
+    // the payload written by the older releases.
+    let response =
+        server.perform(Request::get("/counter").header(COOKIE, format!("session={}", data)))?;
+    assert_eq!(response.body().to_utf8()?, "Some(1)");
+
+    // the corrupted version byte.
+    let response =
+        server.perform(Request::get("/counter").header(COOKIE, format!("session=X.{}", data)))?;
+    assert_eq!(response.status(), 400);
+
+    Ok(())
+}
+
+#[test]
+fn max_cookie_size() -> tsukuyomi_server::Result<()> {
+    let mut server =
+        tsukuyomi_server::test::server(counter_app(CookieBackend::plain().max_cookie_size(16))?)?;
+
+    let response = server.perform(
+        Request::get("/counter").header(COOKIE, format!("session=1.{}", "x".repeat(32))),
+    )?;
+    assert_eq!(response.status(), 400);
+
+    // `1.{"counter":"1"}` is longer than 16 bytes.
+    let response = server.perform(Request::put("/counter"))?;
+    assert_eq!(response.status(), 500);
+    assert!(!response.headers().contains_key("set-cookie"));
+
+    Ok(())
+}
+
+fn blob_app(backend: CookieBackend) -> tsukuyomi::app::Result<App> {
+    let session = Arc::new(session(backend.cookie_name("session")));
+    App::create(chain![
+        path!("/blob").to(endpoint::get().extract(session.clone()).call_async(
+            |session: Session<_>| -> tsukuyomi::Result<_> {
+                let blob: Option<String> = session.get("blob")?;
+                Ok(session.finish(blob.map_or(0, |blob| blob.len()).to_string()))
+            }
+        )),
+        path!("/blob/:len").to(endpoint::put().extract(session).call_async(
+            |len: usize, mut session: Session<_>| -> tsukuyomi::Result<_> {
+                session.set("blob", "a".repeat(len))?;
+                session.set("counter", 1)?;
+                Ok(session.finish("stored"))
+            }
+        )),
+    ])
+}
+
+fn take_warnings() -> Vec<String> {
+    use lazy_static::lazy_static;
+    use log::{Level, Log, Metadata, Record};
+    use std::sync::Mutex;
+
+    struct CapturingLogger(Mutex<Vec<String>>);
+
+    impl Log for CapturingLogger {
+        fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+            metadata.level() <= Level::Warn
+        }
+
+        fn log(&self, record: &Record<'_>) {
+            if record.level() == Level::Warn {
+                self.0.lock().unwrap().push(record.args().to_string());
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    lazy_static! {
+        static ref LOGGER: CapturingLogger = CapturingLogger(Mutex::new(Vec::new()));
+
+        // The logger is installed at the first access.
+        static ref INIT: () = {
+            log::set_logger(&*LOGGER).unwrap();
+            log::set_max_level(log::LevelFilter::Warn);
+        };
+    }
+    lazy_static::initialize(&INIT);
+    std::mem::replace(&mut *LOGGER.0.lock().unwrap(), vec![])
+}
+
+#[test]
+fn over_budget_strict() -> tsukuyomi_server::Result<()> {
+    let mut server =
+        tsukuyomi_server::test::server(blob_app(CookieBackend::plain().max_cookie_size(256))?)?;
+
+    let response = server.perform(Request::put("/blob/64"))?;
+    assert_eq!(response.status(), 200);
+    assert!(response.headers().contains_key("set-cookie"));
+
+    let response = server.perform(Request::put("/blob/512"))?;
+    assert_eq!(response.status(), 500);
+    assert!(!response.headers().contains_key("set-cookie"));
+
+    Ok(())
+}
+
+#[test]
+fn over_budget_lenient() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(blob_app(
+        CookieBackend::plain()
+            .max_cookie_size(256)
+            .size_limit_mode(SizeLimitMode::Lenient),
+    )?)?;
+    let mut session = server.new_session()?.save_cookies(true);
+
+    let _ = take_warnings();
+    let response = session.perform(Request::put("/blob/512"))?;
+    assert_eq!(response.status(), 200);
+    assert!(response.headers().contains_key("set-cookie"));
+
+    let warnings = take_warnings();
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].starts_with("session cookie exceeds the maximum size:"));
+    assert!(warnings[0].ends_with("max=256 keys=blob(518),counter(8)"));
+
+    // the oversized cookie entry is still accepted.
+    let response = session.perform("/blob")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "512");
+
+    Ok(())
+}
+
+#[test]
+fn compression() -> tsukuyomi_server::Result<()> {
+    let backend = || CookieBackend::plain().max_cookie_size(256);
+
+    // the repetitive value fits in the budget only if compressed.
+    let mut server = tsukuyomi_server::test::server(blob_app(backend().compress(true))?)?;
+    let mut session = server.new_session()?.save_cookies(true);
+    let response = session.perform(Request::put("/blob/512"))?;
+    assert_eq!(response.status(), 200);
+    let compressed = session.cookie("session").unwrap().to_owned();
+    assert!(compressed.starts_with("2."));
+    assert_eq!(session.perform("/blob")?.body().to_utf8()?, "512");
+
+    // the uncompressed cookie entry written before enabling the compression.
+    let mut server = tsukuyomi_server::test::server(blob_app(backend())?)?;
+    let mut session = server.new_session()?.save_cookies(true);
+    session.perform(Request::put("/blob/16"))?;
+    let legacy = format!("session={}", session.cookie("session").unwrap());
+
+    let mut server = tsukuyomi_server::test::server(blob_app(backend().compress(true))?)?;
+    let response = server.perform(Request::get("/blob").header(COOKIE, &*legacy))?;
+    assert_eq!(response.body().to_utf8()?, "16");
+
+    // the compressed cookie entry is readable after disabling the compression.
+    let mut server = tsukuyomi_server::test::server(blob_app(backend())?)?;
+    let response =
+        server.perform(Request::get("/blob").header(COOKIE, format!("session={}", compressed)))?;
+    assert_eq!(response.body().to_utf8()?, "512");
+
+    // the malformed compressed payload.
+    let response = server.perform(Request::get("/blob").header(COOKIE, "session=2.!!!"))?;
+    assert_eq!(response.status(), 400);
+
+    Ok(())
+}
+
+#[test]
+fn max_value_size() -> tsukuyomi_server::Result<()> {
+    let mut server =
+        tsukuyomi_server::test::server(blob_app(CookieBackend::plain().max_value_size(32))?)?;
+
+    let response = server.perform(Request::put("/blob/16"))?;
+    assert_eq!(response.status(), 200);
+
+    // `"aaa..."` is rejected by `Session::set` before storing it.
+    let response = server.perform(Request::put("/blob/64"))?;
+    assert_eq!(response.status(), 500);
+    assert!(!response.headers().contains_key("set-cookie"));
+
+    Ok(())
+}
+
+#[test]
+fn bind_user_agent() -> tsukuyomi_server::Result<()> {
+    let mut server =
+        tsukuyomi_server::test::server(counter_app(CookieBackend::plain().bind_user_agent(true))?)?;
+    let mut session = server.new_session()?.save_cookies(true);
+
+    session.perform(Request::put("/counter").header("user-agent", "client-a"))?;
+
+    let response = session.perform(Request::get("/counter").header("user-agent", "client-a"))?;
+    assert_eq!(response.body().to_utf8()?, "Some(1)");
+
+    let response = session.perform(Request::get("/counter").header("user-agent", "client-b"))?;
+    assert_eq!(response.body().to_utf8()?, "None");
+
+    Ok(())
+}
+
+#[derive(Default)]
+struct MockBackend {
+    reads: AtomicUsize,
+    writes: AtomicUsize,
+    fail_read: bool,
+}
+
+impl Backend for MockBackend {
+    type ReadError = Error;
+    type ReadSession = MockRead;
+    type WriteError = Error;
+    type WriteSession = MockWrite;
+
+    fn read(&self) -> Self::ReadSession {
+        self.reads.fetch_add(1, Ordering::SeqCst);
+        MockRead(self.fail_read)
+    }
+
+    fn write(&self, _: SessionInner) -> Self::WriteSession {
+        self.writes.fetch_add(1, Ordering::SeqCst);
+        MockWrite(())
+    }
+}
+
+struct MockRead(bool);
+
+impl TryFuture for MockRead {
+    type Ok = SessionInner;
+    type Error = Error;
+
+    fn poll_ready(&mut self, _: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        if self.0 {
+            return Err(tsukuyomi::error::internal_server_error("read error"));
+        }
+        let mut map = HashMap::new();
+        map.insert("counter".into(), "0".into());
+        Ok(SessionInner::from_map(map).into())
+    }
+}
+
+struct MockWrite(());
+
+impl TryFuture for MockWrite {
+    type Ok = ();
+    type Error = Error;
+
+    fn poll_ready(&mut self, _: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        Ok(().into())
+    }
+}
+
+#[test]
+fn skip_write_if_not_modified() -> tsukuyomi_server::Result<()> {
+    let backend = Arc::new(MockBackend::default());
+    let session = Arc::new(session(backend.clone()));
+
+    let app = App::create(chain![
+        path!("/get").to(endpoint::get().extract(session.clone()).call_async(
+            |session: Session<_>| -> tsukuyomi::Result<_> {
+                let counter: Option<i64> = session.get("counter")?;
+                Ok(session.finish(format!("{:?}", counter)))
+            }
+        )),
+        path!("/set").to(endpoint::put().extract(session.clone()).call_async(
+            |mut session: Session<_>| -> tsukuyomi::Result<_> {
+                session.set("counter", 1)?;
+                Ok(session.finish("set"))
+            }
+        )),
+        path!("/remove-missing").to(endpoint::put().extract(session.clone()).call(
+            |mut session: Session<_>| {
+                session.remove("missing");
+                session.finish("removed")
+            }
+        )),
+        path!("/clear").to(endpoint::put().extract(session.clone()).call(
+            |mut session: Session<_>| {
+                session.clear();
+                session.finish("cleared")
+            }
+        )),
+        path!("/error").to(endpoint::put()
+            .extract(session)
+            .call(|mut session: Session<_>| {
+                session.clear();
+                session.finish(Err::<&'static str, _>(tsukuyomi::error::bad_request(
+                    "error",
+                )))
+            })),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/get")?;
+    assert_eq!(response.body().to_utf8()?, "Some(0)");
+    assert_eq!(backend.reads.load(Ordering::SeqCst), 1);
+    assert_eq!(backend.writes.load(Ordering::SeqCst), 0);
+
+    server.perform(Request::put("/remove-missing"))?;
+    assert_eq!(backend.writes.load(Ordering::SeqCst), 0);
+
+    server.perform(Request::put("/set"))?;
+    assert_eq!(backend.writes.load(Ordering::SeqCst), 1);
+
+    server.perform(Request::put("/clear"))?;
+    assert_eq!(backend.writes.load(Ordering::SeqCst), 2);
+
+    let response = server.perform(Request::put("/error"))?;
+    assert_eq!(response.status(), 400);
+    assert_eq!(backend.writes.load(Ordering::SeqCst), 2);
+
+    Ok(())
+}
+
+#[test]
+fn read_error() -> tsukuyomi_server::Result<()> {
+    let backend = Arc::new(MockBackend {
+        fail_read: true,
+        ..Default::default()
+    });
+    let called = Arc::new(AtomicUsize::new(0));
+
+    let app = App::create(
+        path!("/").to(endpoint::get().extract(session(backend.clone())).call({
+            let called = called.clone();
+            move |session: Session<_>| {
+                called.fetch_add(1, Ordering::SeqCst);
+                session.finish("unreachable")
+            }
+        })),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/")?;
+    assert_eq!(response.status(), 500);
+    assert_eq!(called.load(Ordering::SeqCst), 0);
+    assert_eq!(backend.writes.load(Ordering::SeqCst), 0);
+
+    Ok(())
+}
+
+/// A mock of the server-side backend, which stores the session data with compare-and-swap.
+struct VersionedBackend {
+    policy: ConcurrencyPolicy,
+    stored: Mutex<(u64, HashMap<String, String>)>,
+    // the snapshot returned from the next read instead of the stored data, for
+    // simulating the request which loaded the session before another one wrote it.
+    stale: Mutex<Option<(u64, HashMap<String, String>)>>,
+}
+
+impl VersionedBackend {
+    fn new(policy: ConcurrencyPolicy) -> Self {
+        let mut map = HashMap::new();
+        map.insert("shared".into(), "0".into());
+        Self {
+            policy,
+            stored: Mutex::new((0, map)),
+            stale: Mutex::default(),
+        }
+    }
+
+    fn snapshot(&self) -> (u64, HashMap<String, String>) {
+        self.stored.lock().unwrap().clone()
+    }
+
+    fn read_stale(&self, snapshot: (u64, HashMap<String, String>)) {
+        *self.stale.lock().unwrap() = Some(snapshot);
+    }
+
+    fn stored(&self) -> (u64, Vec<(String, String)>) {
+        let (version, ref map) = *self.stored.lock().unwrap();
+        let mut entries: Vec<_> = map.clone().into_iter().collect();
+        entries.sort();
+        (version, entries)
+    }
+}
+
+impl Backend for VersionedBackend {
+    type ReadError = Error;
+    type ReadSession = Done<SessionInner>;
+    type WriteError = Error;
+    type WriteSession = Done<()>;
+
+    fn read(&self) -> Self::ReadSession {
+        let (version, map) = match self.stale.lock().unwrap().take() {
+            Some(snapshot) => snapshot,
+            None => self.stored.lock().unwrap().clone(),
+        };
+        Done(Some(Ok(SessionInner::from_map(map).with_version(version))))
+    }
+
+    fn write(&self, inner: SessionInner) -> Self::WriteSession {
+        let mut stored = self.stored.lock().unwrap();
+        let (version, ref current) = *stored;
+        Done(Some(
+            inner
+                .resolve(self.policy, version, Some(current))
+                .map(|map| *stored = (version + 1, map.unwrap_or_default())),
+        ))
+    }
+}
+
+struct Done<T>(Option<tsukuyomi::Result<T>>);
+
+impl<T> TryFuture for Done<T> {
+    type Ok = T;
+    type Error = Error;
+
+    fn poll_ready(&mut self, _: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        self.0
+            .take()
+            .expect("the future has already been polled")
+            .map(Into::into)
+    }
+}
+
+fn interleaved(policy: ConcurrencyPolicy) -> tsukuyomi_server::Result<(u16, VersionedBackend)> {
+    let backend = Arc::new(VersionedBackend::new(policy));
+    let session = Arc::new(session(backend.clone()));
+    let app = App::create(
+        path!("/:key/:value").to(endpoint::put().extract(session).call_async(
+            |key: String, value: String, mut session: Session<_>| -> tsukuyomi::Result<_> {
+                session.set(&key, value)?;
+                session.set("shared", &key)?;
+                Ok(session.finish("stored"))
+            },
+        )),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    // The request A loads the session, and then the request B loads and stores it
+    // before A completes.
+    let loaded_by_a = backend.snapshot();
+    let response = server.perform(Request::put("/b/2"))?;
+    assert_eq!(response.status(), 200);
+    backend.read_stale(loaded_by_a);
+    let response = server.perform(Request::put("/a/1"))?;
+    drop(server);
+
+    let backend = Arc::try_unwrap(backend)
+        .ok()
+        .expect("the backend should be unique");
+    Ok((response.status().as_u16(), backend))
+}
+
+#[test]
+fn concurrency_last_write_wins() -> tsukuyomi_server::Result<()> {
+    let (status, backend) = interleaved(ConcurrencyPolicy::LastWriteWins)?;
+    assert_eq!(status, 200);
+    assert_eq!(
+        backend.stored(),
+        (
+            2,
+            vec![
+                ("a".into(), "\"1\"".into()),
+                ("shared".into(), "\"a\"".into())
+            ]
+        )
+    );
+    Ok(())
+}
+
+#[test]
+fn concurrency_merge() -> tsukuyomi_server::Result<()> {
+    let (status, backend) = interleaved(ConcurrencyPolicy::Merge)?;
+    assert_eq!(status, 200);
+    assert_eq!(
+        backend.stored(),
+        (
+            2,
+            vec![
+                ("a".into(), "\"1\"".into()),
+                ("b".into(), "\"2\"".into()),
+                ("shared".into(), "\"a\"".into())
+            ]
+        )
+    );
+    Ok(())
+}
+
+#[test]
+fn concurrency_reject() -> tsukuyomi_server::Result<()> {
+    let (status, backend) = interleaved(ConcurrencyPolicy::Reject)?;
+    assert_eq!(status, 409);
+    assert_eq!(
+        backend.stored(),
+        (
+            1,
+            vec![
+                ("b".into(), "\"2\"".into()),
+                ("shared".into(), "\"b\"".into())
+            ]
+        )
+    );
+    Ok(())
+}
+
+#[test]
+fn cookie_payload_counter() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(counter_app(
+        CookieBackend::plain().concurrency_policy(ConcurrencyPolicy::Reject),
+    )?)?;
+    let mut session = server.new_session()?.save_cookies(true);
+
+    session.perform(Request::put("/counter"))?;
+    assert!(session.cookie("session").unwrap().starts_with("31.."));
+    session.perform(Request::put("/counter"))?;
+    assert!(session.cookie("session").unwrap().starts_with("32.."));
+    let response = session.perform("/counter")?;
+    assert_eq!(response.body().to_utf8()?, "Some(2)");
+
+    // the payload without the counter is read as the version 0.
+    let response = server.perform(
+        Request::put("/counter").header(COOKIE, "session=1.%7B%22counter%22%3A%221%22%7D"),
+    )?;
+    assert_eq!(response.status(), 200);
+
+    Ok(())
+}
@@ -12,23 +12,81 @@
 #![doc(test(attr(deny(deprecated, unused,))))]
 #![forbid(clippy::unimplemented)]
 
+mod registry;
+
+pub use crate::registry::{Closing, ConnectionId, Drain, DrainReport, Registry};
+
 use {
-    futures::IntoFuture,
+    futures::{IntoFuture, Poll, Sink, StartSend, Stream},
     http::Response,
+    std::{
+        fmt,
+        sync::{Arc, Mutex, MutexGuard},
+    },
     tsukuyomi::{error::Error, input::body::UpgradedIo, responder::Responder},
+    tungstenite::error::Error as WsError,
 };
 
 #[doc(no_inline)]
-pub use tungstenite::protocol::{Message, WebSocketConfig};
+pub use tungstenite::protocol::{frame::coding::CloseCode, Message, WebSocketConfig};
+
+type Transport = tokio_tungstenite::WebSocketStream<UpgradedIo>;
 
 /// A transport for exchanging data frames with the peer.
-pub type WebSocketStream = tokio_tungstenite::WebSocketStream<UpgradedIo>;
+///
+/// The underlying connection is shared with the `Registry`, which takes it over
+/// to close the connection on shutdown.
+pub struct WebSocketStream {
+    inner: Arc<Mutex<Transport>>,
+}
+
+impl fmt::Debug for WebSocketStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebSocketStream").finish()
+    }
+}
+
+impl WebSocketStream {
+    fn transport(&self) -> MutexGuard<'_, Transport> {
+        self.inner.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl Stream for WebSocketStream {
+    type Item = Message;
+    type Error = WsError;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        self.transport().poll()
+    }
+}
+
+impl Sink for WebSocketStream {
+    type SinkItem = Message;
+    type SinkError = WsError;
+
+    fn start_send(&mut self, item: Self::SinkItem) -> StartSend<Self::SinkItem, Self::SinkError> {
+        self.transport().start_send(item)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
+        self.transport().poll_complete()
+    }
+
+    fn close(&mut self) -> Poll<(), Self::SinkError> {
+        self.transport().close()
+    }
+}
 
 /// A `Responder` that handles an WebSocket connection.
+///
+/// If a `Registry` is registered as a state in the scope, the connection is
+/// registered with the identifier returned from `id`.
 #[derive(Debug, Clone)]
 pub struct Ws<F> {
     on_upgrade: F,
     config: Option<WebSocketConfig>,
+    id: ConnectionId,
 }
 
 impl<F, R> Ws<F>
@@ -42,9 +100,17 @@ where
         Self {
             on_upgrade,
             config: None,
+            id: ConnectionId::next(),
         }
     }
 
+    /// Returns the identifier of the connection, used in `Registry::close`.
+    ///
+    /// The clones of a `Ws` share the identifier.
+    pub fn id(&self) -> ConnectionId {
+        self.id
+    }
+
     /// Sets the configuration of upgraded WebSocket connection.
     pub fn config(self, config: WebSocketConfig) -> Self {
         Self {
@@ -72,7 +138,8 @@ where
 mod imp {
     use {
         super::{WebSocketStream, Ws},
-        futures::{Future, IntoFuture},
+        crate::registry::Registry,
+        futures::{future::Either, Future, IntoFuture},
         http::{
             header::{
                 CONNECTION, //
@@ -84,6 +151,7 @@ mod imp {
             Request, Response, StatusCode,
         },
         sha1::{Digest, Sha1},
+        std::sync::{Arc, Mutex},
         tsukuyomi::{
             error::HttpError,
             future::{Poll, TryFuture},
@@ -110,11 +178,19 @@ mod imp {
         type Error = tsukuyomi::Error;
 
         fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
-            let Ws { on_upgrade, config } =
-                self.0.take().expect("the future has already been polled");
+            let Ws {
+                on_upgrade,
+                config,
+                id,
+            } = self.0.take().expect("the future has already been polled");
 
             let accept_hash = handshake(input)?;
 
+            // registered before the upgrade, so that the connection is not missed by a drain.
+            let registration = input
+                .state::<Registry>()
+                .map(|registry| registry.register(id, input.clock().clone()));
+
             let body = RequestBody::take_from(input.locals) //
                 .ok_or_else(|| {
                     tsukuyomi::error::internal_server_error(
@@ -126,8 +202,25 @@ mod imp {
                 .on_upgrade()
                 .map_err(|e| log::error!("failed to upgrade the request: {}", e))
                 .and_then(move |io: UpgradedIo| {
-                    let transport = WebSocketStream::from_raw_socket(io, Role::Server, config);
-                    on_upgrade(transport).into_future()
+                    let stream = WebSocketStream {
+                        inner: Arc::new(Mutex::new(
+                            tokio_tungstenite::WebSocketStream::from_raw_socket(
+                                io,
+                                Role::Server,
+                                config,
+                            ),
+                        )),
+                    };
+                    match registration {
+                        Some(registration) => {
+                            let handler = on_upgrade(WebSocketStream {
+                                inner: stream.inner.clone(),
+                            })
+                            .into_future();
+                            Either::A(registration.serve(&stream, handler))
+                        }
+                        None => Either::B(on_upgrade(stream).into_future()),
+                    }
                 });
 
             DefaultExecutor::current()
@@ -0,0 +1,457 @@
+use {
+    crate::{Transport, WebSocketStream},
+    futures::{
+        sync::oneshot, //
+        task::AtomicTask,
+        Async,
+        AsyncSink,
+        Future,
+        Poll,
+        Sink,
+        Stream,
+    },
+    std::{
+        borrow::Cow,
+        collections::HashMap,
+        fmt,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex, MutexGuard,
+        },
+        time::Duration,
+    },
+    tsukuyomi::{
+        app::Lifecycle,
+        rt::{Clock, Delay},
+    },
+    tungstenite::{
+        error::Error as WsError,
+        protocol::{frame::coding::CloseCode, CloseFrame, Message},
+    },
+};
+
+/// The identifier of a WebSocket connection, assigned when `Ws` is created.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ConnectionId(u64);
+
+impl ConnectionId {
+    pub(crate) fn next() -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        ConnectionId(NEXT.fetch_add(1, Ordering::Relaxed) as u64)
+    }
+}
+
+impl fmt::Display for ConnectionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ws-{}", self.0)
+    }
+}
+
+/// The registry of the WebSocket connections, which closes them with the close
+/// handshake when the application shuts down.
+///
+/// The registry takes effect when it is registered as a state by
+/// `config::state_with_lifecycle`. The connections established by `Ws` in the
+/// scope are registered until they complete. On `App::shutdown`, a Close frame
+/// is sent to every connection concurrently, and each connection is dropped
+/// after the peer replies to it or the close timeout elapses. The handlers of
+/// the connections are no longer polled after the Close frame is sent.
+///
+/// ```
+/// # use tsukuyomi::{config::prelude::*, App};
+/// # use tsukuyomi::vendor::futures::{Future, Stream};
+/// # use tsukuyomi_tungstenite::{Registry, Ws};
+/// let registry = Registry::new();
+///
+/// let app = App::create(chain![
+///     tsukuyomi::config::state_with_lifecycle(registry.clone()),
+///     path!("/ws").to(endpoint::get().call(|| {
+///         Ws::new(|stream: tsukuyomi_tungstenite::WebSocketStream| {
+///             let (tx, rx) = stream.split();
+///             rx.forward(tx).map(drop).map_err(drop)
+///         })
+///     })),
+/// ]);
+/// # drop(app);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Registry {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    close_frame: CloseFrame<'static>,
+    close_timeout: Duration,
+    connections: Mutex<HashMap<ConnectionId, Arc<Control>>>,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Registry {
+    /// Creates an empty `Registry`.
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                close_frame: CloseFrame {
+                    code: CloseCode::Away,
+                    reason: Cow::Borrowed("server shutting down"),
+                },
+                close_timeout: Duration::from_secs(5),
+                connections: Mutex::default(),
+            }),
+        }
+    }
+
+    fn inner_mut(&mut self) -> &mut Inner {
+        Arc::get_mut(&mut self.inner).expect("the registry has already been shared")
+    }
+
+    /// Sets the Close frame sent to the connections on shutdown.
+    ///
+    /// The default value is the code `1001` (going away) with the reason
+    /// `"server shutting down"`.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the registry has already been cloned.
+    pub fn close_frame(mut self, code: CloseCode, reason: impl Into<Cow<'static, str>>) -> Self {
+        self.inner_mut().close_frame = CloseFrame {
+            code,
+            reason: reason.into(),
+        };
+        self
+    }
+
+    /// Sets the maximum duration to wait for the peer to reply to the Close frame.
+    ///
+    /// The default value is 5 seconds.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the registry has already been cloned.
+    pub fn close_timeout(mut self, timeout: Duration) -> Self {
+        self.inner_mut().close_timeout = timeout;
+        self
+    }
+
+    /// Returns the identifiers of the registered connections.
+    pub fn connections(&self) -> Vec<ConnectionId> {
+        let mut ids: Vec<_> = self.inner.connections().keys().cloned().collect();
+        ids.sort();
+        ids
+    }
+
+    /// Returns the number of the registered connections.
+    pub fn len(&self) -> usize {
+        self.inner.connections().len()
+    }
+
+    /// Returns `true` if no connection is registered.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Starts the close handshake of the specified connection with a Close frame.
+    ///
+    /// The returned future resolves to `true` if the connection has closed cleanly.
+    /// This method returns `None` if the connection is not registered, or its
+    /// close handshake has already been started.
+    pub fn close(
+        &self,
+        id: ConnectionId,
+        code: CloseCode,
+        reason: impl Into<Cow<'static, str>>,
+    ) -> Option<Closing> {
+        let frame = CloseFrame {
+            code,
+            reason: reason.into(),
+        };
+        let control = self.inner.connections().get(&id).cloned()?;
+        control.request(frame, self.inner.close_timeout)
+    }
+
+    /// Starts the close handshake of all registered connections, and returns a
+    /// future that resolves to the report after all of them are completed.
+    ///
+    /// This method is called by `Lifecycle::on_shutdown`.
+    pub fn drain(&self) -> Drain {
+        let controls: Vec<_> = self.inner.connections().values().cloned().collect();
+        let closings: Vec<_> = controls
+            .into_iter()
+            .filter_map(|control| {
+                control.request(self.inner.close_frame.clone(), self.inner.close_timeout)
+            })
+            .collect();
+        Drain(Box::new(futures::future::join_all(closings).map(
+            |results| {
+                let closed = results.iter().filter(|&&clean| clean).count();
+                DrainReport {
+                    closed,
+                    forced: results.len() - closed,
+                }
+            },
+        )))
+    }
+
+    pub(crate) fn register(&self, id: ConnectionId, clock: Arc<dyn Clock>) -> Registration {
+        let control = Arc::new(Control {
+            clock,
+            request: Mutex::new(None),
+            task: AtomicTask::new(),
+        });
+        self.inner.connections().insert(id, control.clone());
+        Registration {
+            inner: self.inner.clone(),
+            id,
+            control,
+        }
+    }
+}
+
+impl Lifecycle for Registry {
+    const NAME: &'static str = "tsukuyomi_tungstenite::Registry";
+
+    fn on_shutdown(&self) -> Box<dyn Future<Item = (), Error = ()> + Send + 'static> {
+        Box::new(self.drain().map(|report| {
+            log::info!(
+                "closed the WebSocket connections: {} cleanly, {} forcibly",
+                report.closed,
+                report.forced
+            );
+        }))
+    }
+}
+
+impl Inner {
+    fn connections(&self) -> MutexGuard<'_, HashMap<ConnectionId, Arc<Control>>> {
+        self.connections
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+/// The results of the close handshakes started by `Registry::drain`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DrainReport {
+    /// The number of the connections closed by the handshake, or completed by themselves.
+    pub closed: usize,
+
+    /// The number of the connections dropped without completing the handshake,
+    /// due to the timeout or an error.
+    pub forced: usize,
+}
+
+/// A future returned from `Registry::drain`.
+#[must_use = "futures do nothing unless polled"]
+pub struct Drain(Box<dyn Future<Item = DrainReport, Error = ()> + Send + 'static>);
+
+impl fmt::Debug for Drain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Drain").finish()
+    }
+}
+
+impl Future for Drain {
+    type Item = DrainReport;
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        self.0.poll()
+    }
+}
+
+/// A future returned from `Registry::close`, which resolves to `true` if the
+/// connection has closed cleanly.
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct Closing(oneshot::Receiver<bool>);
+
+impl Future for Closing {
+    type Item = bool;
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        // The connection has been dropped without reporting, e.g. along with the runtime.
+        Ok(self.0.poll().unwrap_or(Async::Ready(false)))
+    }
+}
+
+/// The channel from the registry to a connection.
+struct Control {
+    clock: Arc<dyn Clock>,
+    request: Mutex<Option<CloseRequest>>,
+    task: AtomicTask,
+}
+
+impl fmt::Debug for Control {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Control").finish()
+    }
+}
+
+struct CloseRequest {
+    frame: CloseFrame<'static>,
+    timeout: Duration,
+    done: oneshot::Sender<bool>,
+}
+
+impl Control {
+    fn request_slot(&self) -> MutexGuard<'_, Option<CloseRequest>> {
+        self.request.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn request(&self, frame: CloseFrame<'static>, timeout: Duration) -> Option<Closing> {
+        let mut slot = self.request_slot();
+        if slot.is_some() {
+            return None;
+        }
+        let (done, rx) = oneshot::channel();
+        *slot = Some(CloseRequest {
+            frame,
+            timeout,
+            done,
+        });
+        self.task.notify();
+        Some(Closing(rx))
+    }
+}
+
+/// The entry of a connection in the registry, removed on drop.
+#[derive(Debug)]
+pub(crate) struct Registration {
+    inner: Arc<Inner>,
+    id: ConnectionId,
+    control: Arc<Control>,
+}
+
+impl Registration {
+    /// Wraps the handler of the upgraded connection, so that it can be closed by the registry.
+    pub(crate) fn serve<F>(self, stream: &WebSocketStream, handler: F) -> Serve<F>
+    where
+        F: Future<Item = (), Error = ()>,
+    {
+        Serve {
+            registration: self,
+            transport: stream.inner.clone(),
+            handler,
+            state: ServeState::Running,
+        }
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        self.inner.connections().remove(&self.id);
+    }
+}
+
+/// A future that runs the handler of a connection until it completes or the
+/// connection is closed by the registry.
+#[allow(missing_debug_implementations)]
+pub(crate) struct Serve<F> {
+    registration: Registration,
+    transport: Arc<Mutex<Transport>>,
+    handler: F,
+    state: ServeState,
+}
+
+enum ServeState {
+    Running,
+    Closing {
+        pending: Option<Message>,
+        done: Option<oneshot::Sender<bool>>,
+        deadline: Delay,
+    },
+}
+
+impl<F> Future for Serve<F>
+where
+    F: Future<Item = (), Error = ()>,
+{
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let control = self.registration.control.clone();
+        control.task.register();
+        loop {
+            match self.state {
+                ServeState::Running => {
+                    if let Some(request) = control.request_slot().take() {
+                        let deadline = control.clock.now() + request.timeout;
+                        self.state = ServeState::Closing {
+                            pending: Some(Message::Close(Some(request.frame))),
+                            done: Some(request.done),
+                            deadline: control.clock.delay(deadline),
+                        };
+                        continue;
+                    }
+
+                    let polled = self.handler.poll();
+                    if let Ok(Async::NotReady) = polled {
+                        return Ok(Async::NotReady);
+                    }
+                    // A close requested concurrently is regarded as completed.
+                    if let Some(request) = control.request_slot().take() {
+                        let _ = request.done.send(true);
+                    }
+                    return polled;
+                }
+                ServeState::Closing {
+                    ref mut pending,
+                    ref mut done,
+                    ref mut deadline,
+                } => {
+                    let mut transport =
+                        self.transport.lock().unwrap_or_else(|err| err.into_inner());
+                    let clean = match poll_handshake(&mut *transport, pending) {
+                        Ok(Async::Ready(())) => true,
+                        Ok(Async::NotReady) => match deadline.poll() {
+                            Ok(Async::NotReady) => return Ok(Async::NotReady),
+                            Ok(Async::Ready(())) | Err(..) => {
+                                log::debug!(
+                                    "the close handshake of {} timed out",
+                                    self.registration.id
+                                );
+                                false
+                            }
+                        },
+                        Err(err) => {
+                            log::debug!(
+                                "the close handshake of {} failed: {}",
+                                self.registration.id,
+                                err
+                            );
+                            false
+                        }
+                    };
+                    if let Some(done) = done.take() {
+                        let _ = done.send(clean);
+                    }
+                    // The handler and the transport are dropped along with this future.
+                    return Ok(Async::Ready(()));
+                }
+            }
+        }
+    }
+}
+
+/// Sends the Close frame, and waits for the reply from the peer.
+fn poll_handshake(transport: &mut Transport, pending: &mut Option<Message>) -> Poll<(), WsError> {
+    if let Some(message) = pending.take() {
+        if let AsyncSink::NotReady(message) = transport.start_send(message)? {
+            *pending = Some(message);
+            return Ok(Async::NotReady);
+        }
+    }
+    futures::try_ready!(transport.poll_complete());
+
+    // The messages received before the reply are discarded.
+    while futures::try_ready!(transport.poll()).is_some() {}
+    Ok(Async::Ready(()))
+}
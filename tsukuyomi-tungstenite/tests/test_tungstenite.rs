@@ -4,6 +4,7 @@ extern crate tsukuyomi_tungstenite;
 extern crate version_sync;
 
 use {
+    futures::{Future, Stream},
     http::{
         header::{
             CONNECTION, //
@@ -15,12 +16,19 @@ use {
         },
         Request,
     },
+    std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    },
     tsukuyomi::{
-        config::prelude::*, //
+        config::{prelude::*, state_with_lifecycle},
         App,
     },
-    tsukuyomi_server::test::ResponseExt,
-    tsukuyomi_tungstenite::Ws,
+    tsukuyomi_server::test::{ResponseExt, Upgraded},
+    tsukuyomi_tungstenite::{
+        CloseCode, ConnectionId, DrainReport, Message, Registry, WebSocketStream, Ws,
+    },
+    tungstenite::{protocol::Role, WebSocket},
 };
 
 #[test]
@@ -55,4 +63,123 @@ fn test_handshake() -> tsukuyomi_server::Result<()> {
     Ok(())
 }
 
-// TODO: add check whether the task to handle upgraded connection is spawned
+/// An application with an echo endpoint, which records the identifiers of the connections.
+fn echo_app(
+    registry: &Registry,
+    ids: &Arc<Mutex<Vec<ConnectionId>>>,
+) -> tsukuyomi::app::Result<App> {
+    let ids = ids.clone();
+    App::create(chain![
+        state_with_lifecycle(registry.clone()),
+        path!("/ws").to(endpoint::get().call(move || {
+            let ws = Ws::new(|stream: WebSocketStream| {
+                let (tx, rx) = stream.split();
+                rx.forward(tx).map(drop).map_err(drop)
+            });
+            ids.lock().unwrap().push(ws.id());
+            ws
+        })),
+    ])
+}
+
+fn connect(
+    server: &mut tsukuyomi_server::test::Server<App>,
+) -> tsukuyomi_server::Result<WebSocket<Upgraded>> {
+    let upgraded = server.perform_upgrade(
+        Request::get("/ws")
+            .header(CONNECTION, "upgrade")
+            .header(UPGRADE, "websocket")
+            .header(SEC_WEBSOCKET_VERSION, "13")
+            .header(SEC_WEBSOCKET_KEY, "dGhlIHNhbXBsZSBub25jZQ=="),
+    )?;
+    assert_eq!(upgraded.response().status(), 101);
+    Ok(WebSocket::from_raw_socket(upgraded, Role::Client, None))
+}
+
+#[test]
+fn closed_with_handshake_on_shutdown() -> tsukuyomi_server::Result<()> {
+    let registry = Registry::new();
+    let ids = Arc::default();
+    let app = echo_app(&registry, &ids)?;
+    let mut server = tsukuyomi_server::test::server(app.clone())?;
+
+    let mut client = connect(&mut server)?;
+    client.write_message(Message::Text("hello".into()))?;
+    assert_eq!(client.read_message()?, Message::Text("hello".into()));
+    assert_eq!(registry.connections(), *ids.lock().unwrap());
+
+    let shutdown = futures::sync::oneshot::spawn(
+        app.shutdown(Duration::from_secs(5)),
+        &server.new_session()?.runtime().executor(),
+    );
+
+    match client.read_message()? {
+        Message::Close(Some(frame)) => {
+            assert_eq!(frame.code, CloseCode::Away);
+            assert_eq!(frame.reason, "server shutting down");
+        }
+        message => panic!("unexpected message: {:?}", message),
+    }
+    // replies to the Close frame.
+    client.write_pending()?;
+
+    let abandoned = shutdown.wait().unwrap_or_else(|never| match never {});
+    assert!(abandoned.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn unresponsive_client_is_dropped_after_timeout() -> tsukuyomi_server::Result<()> {
+    let registry = Registry::new().close_timeout(Duration::from_millis(100));
+    let ids = Arc::default();
+    let mut server = tsukuyomi_server::test::server(echo_app(&registry, &ids)?)?;
+
+    // the client never reads the Close frame, so it never replies.
+    let _client = connect(&mut server)?;
+    assert_eq!(registry.len(), 1);
+
+    let drain = futures::sync::oneshot::spawn(
+        registry.drain(),
+        &server.new_session()?.runtime().executor(),
+    );
+    let report = drain.wait().expect("the drain should not fail");
+    assert_eq!(
+        report,
+        DrainReport {
+            closed: 0,
+            forced: 1
+        }
+    );
+
+    Ok(())
+}
+
+#[test]
+fn targeted_close() -> tsukuyomi_server::Result<()> {
+    let registry = Registry::new();
+    let ids = Arc::new(Mutex::new(vec![]));
+    let mut server = tsukuyomi_server::test::server(echo_app(&registry, &ids)?)?;
+
+    let mut first = connect(&mut server)?;
+    let _second = connect(&mut server)?;
+    let id = ids.lock().unwrap()[0];
+
+    let closing = registry
+        .close(id, CloseCode::Policy, "kicked")
+        .expect("the connection should be registered");
+    let closing =
+        futures::sync::oneshot::spawn(closing, &server.new_session()?.runtime().executor());
+
+    match first.read_message()? {
+        Message::Close(Some(frame)) => {
+            assert_eq!(frame.code, CloseCode::Policy);
+            assert_eq!(frame.reason, "kicked");
+        }
+        message => panic!("unexpected message: {:?}", message),
+    }
+    first.write_pending()?;
+    assert_eq!(closing.wait(), Ok(true));
+
+    Ok(())
+}
@@ -0,0 +1,36 @@
+//! Benchmarks of the request pipeline (recognize → extract → respond → post-process).
+
+use {
+    criterion::{criterion_group, criterion_main, Criterion},
+    http::Request,
+    hyper::Body,
+};
+
+mod support;
+
+fn bench_route(c: &mut Criterion, name: &str, request: fn() -> Request<Body>) {
+    let app = support::app();
+    let mut service = support::service(&app);
+    c.bench_function(name, move |b| {
+        b.iter(|| support::call(&mut service, request()));
+    });
+}
+
+fn hello_world(c: &mut Criterion) {
+    bench_route(c, "hello_world", support::hello);
+}
+
+fn two_params(c: &mut Criterion) {
+    bench_route(c, "two_params", support::params);
+}
+
+fn json_echo(c: &mut Criterion) {
+    bench_route(c, "json_echo", support::echo);
+}
+
+fn not_found(c: &mut Criterion) {
+    bench_route(c, "not_found", support::not_found);
+}
+
+criterion_group!(benches, hello_world, two_params, json_echo, not_found);
+criterion_main!(benches);
@@ -0,0 +1,131 @@
+//! Fixtures shared by the benchmarks and the allocation audit.
+//!
+//! The requests are passed to `AppService` directly, without any sockets,
+//! so that only the overhead of the framework is measured.
+
+#![allow(dead_code)]
+
+use {
+    cookie::Cookie,
+    http::{header::CONTENT_TYPE, Request, Response},
+    hyper::Body,
+    std::{
+        alloc::{GlobalAlloc, Layout, System},
+        sync::atomic::{AtomicUsize, Ordering},
+    },
+    tsukuyomi::{
+        app::{config::ThreadSafe, AppService},
+        config::prelude::*,
+        extractor,
+        output::ResponseBody,
+        vendor::futures::Future,
+        App,
+    },
+    tsukuyomi_service::{MakeService, Service},
+};
+
+/// Creates the application used in the benchmarks.
+pub fn app() -> App {
+    App::create(chain![
+        path!("/") //
+            .to(endpoint::get().reply("Hello, world!")),
+        path!("/users/:id/posts/:post_id") //
+            .to(endpoint::get().call(|id: u32, post_id: u32| format!("{}:{}", id, post_id))),
+        path!("/echo") //
+            .to(endpoint::post()
+                .extract(extractor::body::json())
+                .call(|value: serde_json::Value| tsukuyomi::output::json(value))),
+        path!("/login") //
+            .to(endpoint::post()
+                .extract(extractor::ready(|input| {
+                    input
+                        .cookies
+                        .jar()?
+                        .add(Cookie::new("session", "dummy-session-id"));
+                    Ok::<_, tsukuyomi::Error>(())
+                }))
+                .reply("Logged in")),
+    ])
+    .expect("failed to create the application")
+}
+
+/// Creates an `AppService` from the application.
+pub fn service(app: &App) -> AppService<ThreadSafe> {
+    MakeService::<(), Request<Body>>::make_service(app, ())
+        .wait()
+        .unwrap_or_else(|never| match never {})
+}
+
+/// Drives the request through the service until the response is returned.
+pub fn call(
+    service: &mut AppService<ThreadSafe>,
+    request: Request<Body>,
+) -> Response<ResponseBody> {
+    tsukuyomi::vendor::futures::executor::spawn(service.call(request))
+        .wait_future()
+        .unwrap_or_else(|never| match never {})
+}
+
+/// `GET /`, replied with a static string.
+pub fn hello() -> Request<Body> {
+    Request::get("/").body(Body::empty()).unwrap()
+}
+
+/// `GET /users/:id/posts/:post_id`, with two captured parameters.
+pub fn params() -> Request<Body> {
+    Request::get("/users/42/posts/3")
+        .body(Body::empty())
+        .unwrap()
+}
+
+/// `POST /echo`, whose JSON payload is parsed and sent back.
+pub fn echo() -> Request<Body> {
+    Request::post("/echo")
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            &br#"{"id":42,"name":"alice","tags":["a","b"]}"#[..],
+        ))
+        .unwrap()
+}
+
+/// `POST /login`, which sets a cookie.
+pub fn login() -> Request<Body> {
+    Request::post("/login").body(Body::empty()).unwrap()
+}
+
+/// `GET /not-found`, which does not match any route.
+pub fn not_found() -> Request<Body> {
+    Request::get("/not-found").body(Body::empty()).unwrap()
+}
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// A wrapper of the system allocator that counts the number of allocations.
+///
+/// It must be registered with `#[global_allocator]` by the target that uses it.
+pub struct CountingAlloc;
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+/// Returns the number of allocations performed by `f`.
+///
+/// The value is meaningful only if `CountingAlloc` is the global allocator.
+pub fn count_allocations<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = ALLOCATIONS.load(Ordering::SeqCst);
+    let value = f();
+    (value, ALLOCATIONS.load(Ordering::SeqCst) - before)
+}
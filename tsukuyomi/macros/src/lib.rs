@@ -10,6 +10,8 @@ extern crate proc_macro;
 
 mod derive_into_response;
 mod path_impl;
+mod route_impl;
+mod static_routes;
 
 use proc_macro::TokenStream;
 
@@ -128,3 +130,17 @@ pub fn path_impl(input: TokenStream) -> TokenStream {
         .unwrap_or_else(|err| err.to_compile_error())
         .into()
 }
+
+#[proc_macro]
+pub fn route_impl(input: TokenStream) -> TokenStream {
+    crate::route_impl::route_impl(input.into())
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
+}
+
+#[proc_macro]
+pub fn static_routes_impl(input: TokenStream) -> TokenStream {
+    crate::static_routes::static_routes(input.into())
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
+}
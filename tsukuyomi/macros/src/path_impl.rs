@@ -34,16 +34,24 @@ pub fn path_impl(input: TokenStream) -> parse::Result<TokenStream> {
 }
 
 #[derive(Debug, Copy, Clone, PartialEq)]
-enum Param<'a> {
+pub(crate) enum Param<'a> {
     Single(&'a str),
     CatchAll(&'a str),
 }
 
+impl<'a> Param<'a> {
+    pub(crate) fn name(self) -> &'a str {
+        match self {
+            Param::Single(name) | Param::CatchAll(name) => name,
+        }
+    }
+}
+
 fn spanned_err<T>(span: Span, message: impl std::fmt::Display) -> parse::Result<T> {
     Err(parse::Error::new(span, message))
 }
 
-fn parse_literal(path: &str, span: Span) -> parse::Result<Vec<Param<'_>>> {
+pub(crate) fn parse_literal(path: &str, span: Span) -> parse::Result<Vec<Param<'_>>> {
     match path {
         "" => return spanned_err(span, "the path cannot be empty"),
         "/" | "*" => return Ok(vec![]),
@@ -96,9 +104,9 @@ fn parse_literal(path: &str, span: Span) -> parse::Result<Vec<Param<'_>>> {
 
 #[derive(Debug)]
 pub struct PathImplOutput<'a> {
-    module: syn::Path,
-    path: &'a str,
-    params: Vec<Param<'a>>,
+    pub(crate) module: syn::Path,
+    pub(crate) path: &'a str,
+    pub(crate) params: Vec<Param<'a>>,
 }
 
 impl<'a> ToTokens for PathImplOutput<'a> {
@@ -0,0 +1,119 @@
+use {
+    crate::path_impl::{parse_literal, PathImplOutput},
+    proc_macro2::TokenStream,
+    std::collections::HashSet,
+    syn::{
+        parse::{self, Parse, ParseStream},
+        punctuated::Punctuated,
+    },
+};
+
+#[derive(Debug)]
+pub struct RouteImplInput {
+    module: syn::Path,
+    path: syn::LitStr,
+    endpoints: Vec<Endpoint>,
+}
+
+impl Parse for RouteImplInput {
+    fn parse(input: ParseStream<'_>) -> parse::Result<Self> {
+        let module = input.parse()?;
+        let _: syn::Token![,] = input.parse()?;
+        let path = input.parse()?;
+        let _: syn::Token![,] = input.parse()?;
+
+        let endpoints = if input.peek(syn::token::Brace) {
+            let content;
+            syn::braced!(content in input);
+            Punctuated::<Endpoint, syn::Token![,]>::parse_terminated(&content)?
+                .into_iter()
+                .collect()
+        } else {
+            let endpoint = input.parse()?;
+            while !input.is_empty() {
+                let _: syn::Token![,] = input.parse()?;
+            }
+            vec![endpoint]
+        };
+
+        Ok(Self {
+            module,
+            path,
+            endpoints,
+        })
+    }
+}
+
+/// An endpoint in `route!`, of the form `METHOD, kind(args).. => handler`.
+///
+/// The method and the kinds of the extractors are checked by `route!` itself,
+/// and only the parameter names in `param(name: T)` are kept here.
+#[derive(Debug)]
+struct Endpoint {
+    method: syn::Ident,
+    params: Vec<syn::Ident>,
+}
+
+impl Parse for Endpoint {
+    fn parse(input: ParseStream<'_>) -> parse::Result<Self> {
+        let method = input.parse()?;
+        let mut params = vec![];
+        while input.peek(syn::Token![,]) {
+            let _: syn::Token![,] = input.parse()?;
+            let kind: syn::Ident = input.parse()?;
+            let content;
+            syn::parenthesized!(content in input);
+            if kind == "param" {
+                params.push(content.parse()?);
+                let _: syn::Token![:] = content.parse()?;
+                let _: syn::Type = content.parse()?;
+            } else {
+                let _: TokenStream = content.parse()?;
+            }
+        }
+        let _: syn::Token![=>] = input.parse()?;
+        let _: syn::Expr = input.parse()?;
+        Ok(Self { method, params })
+    }
+}
+
+pub fn route_impl(input: TokenStream) -> parse::Result<TokenStream> {
+    let input: RouteImplInput = syn::parse2(input)?;
+    let path = &input.path.value();
+    let span = input.path.span();
+
+    let params = parse_literal(path, span)?;
+    for param in &params {
+        if syn::parse_str::<syn::Ident>(param.name()).is_err() {
+            return Err(parse::Error::new(
+                span,
+                format!("invalid parameter name: '{}'", param.name()),
+            ));
+        }
+    }
+
+    let mut methods = HashSet::new();
+    for endpoint in &input.endpoints {
+        if !methods.insert(endpoint.method.to_string()) {
+            return Err(parse::Error::new(
+                endpoint.method.span(),
+                format!("duplicate endpoint for the method '{}'", endpoint.method),
+            ));
+        }
+        for name in &endpoint.params {
+            if params.iter().all(|param| name != param.name()) {
+                return Err(parse::Error::new(
+                    name.span(),
+                    format!("the path does not have a parameter named '{}'", name),
+                ));
+            }
+        }
+    }
+
+    let output = PathImplOutput {
+        path,
+        params,
+        module: input.module,
+    };
+    Ok(quote::quote_spanned!(span => #output))
+}
@@ -0,0 +1,267 @@
+use {
+    proc_macro2::TokenStream,
+    quote::quote,
+    std::{
+        collections::hash_map::DefaultHasher,
+        fs,
+        hash::Hasher,
+        path::{Path, PathBuf},
+    },
+    syn::{
+        parse::{self, Parse, ParseStream},
+        punctuated::Punctuated,
+    },
+};
+
+mod kw {
+    syn::custom_keyword!(exclude);
+    syn::custom_keyword!(index);
+}
+
+#[derive(Debug)]
+pub struct StaticRoutesInput {
+    module: syn::Path,
+    root: syn::LitStr,
+    exclude: Vec<String>,
+    index: Option<String>,
+}
+
+impl Parse for StaticRoutesInput {
+    fn parse(input: ParseStream<'_>) -> parse::Result<Self> {
+        let module = input.parse()?;
+        let _: syn::Token![,] = input.parse()?;
+        let root = input.parse()?;
+
+        let mut exclude = vec![];
+        let mut index = Some("index.html".to_owned());
+        while !input.is_empty() {
+            let _: syn::Token![,] = input.parse()?;
+            if input.is_empty() {
+                break;
+            }
+            let lookahead = input.lookahead1();
+            if lookahead.peek(kw::exclude) {
+                let _: kw::exclude = input.parse()?;
+                let _: syn::Token![=] = input.parse()?;
+                let content;
+                syn::bracketed!(content in input);
+                let patterns =
+                    Punctuated::<syn::LitStr, syn::Token![,]>::parse_terminated(&content)?;
+                exclude.extend(patterns.iter().map(syn::LitStr::value));
+            } else if lookahead.peek(kw::index) {
+                let _: kw::index = input.parse()?;
+                let _: syn::Token![=] = input.parse()?;
+                index = match input.parse()? {
+                    syn::Lit::Str(name) => Some(name.value()),
+                    syn::Lit::Bool(ref enabled) if !enabled.value => None,
+                    lit => {
+                        return Err(parse::Error::new_spanned(
+                            lit,
+                            "expected a string or `false`",
+                        ))
+                    }
+                };
+            } else {
+                return Err(lookahead.error());
+            }
+        }
+
+        Ok(Self {
+            module,
+            root,
+            exclude,
+            index,
+        })
+    }
+}
+
+#[derive(Debug)]
+struct Entry {
+    routes: Vec<String>,
+    path: PathBuf,
+    content_type: String,
+    etag: String,
+}
+
+pub fn static_routes(input: TokenStream) -> parse::Result<TokenStream> {
+    let input: StaticRoutesInput = syn::parse2(input)?;
+    let span = input.root.span();
+
+    // The relative path is resolved from the root of the crate, as well as Cargo does.
+    let root = match std::env::var_os("CARGO_MANIFEST_DIR") {
+        Some(manifest_dir) => Path::new(&manifest_dir).join(input.root.value()),
+        None => PathBuf::from(input.root.value()),
+    };
+    if !root.is_dir() {
+        return Err(parse::Error::new(
+            span,
+            format!("the directory is not found: {}", root.display()),
+        ));
+    }
+
+    let mut entries = vec![];
+    collect_entries(&input, &root, "", &mut entries)?;
+    if entries.is_empty() {
+        return Err(parse::Error::new(
+            span,
+            format!("the directory has no files to serve: {}", root.display()),
+        ));
+    }
+
+    let module = &input.module;
+    let files = entries.iter().map(|entry| {
+        let routes = &entry.routes;
+        let path = entry.path.to_str().expect("the path should be valid UTF-8");
+        let content_type = &entry.content_type;
+        let etag = &entry.etag;
+        quote!(
+            #module::EmbeddedFile {
+                routes: &[#(#routes),*],
+                data: include_bytes!(#path),
+                content_type: #content_type,
+                etag: #etag,
+            }
+        )
+    });
+
+    Ok(quote::quote_spanned!(span =>
+        fn call() -> #module::StaticRoutes {
+            const FILES: &[#module::EmbeddedFile] = &[#(#files),*];
+            #module::StaticRoutes::new(FILES)
+        }
+    ))
+}
+
+fn collect_entries(
+    input: &StaticRoutesInput,
+    dir: &Path,
+    prefix: &str,
+    entries: &mut Vec<Entry>,
+) -> parse::Result<()> {
+    let span = input.root.span();
+    let io_error = |err: std::io::Error| {
+        parse::Error::new(
+            span,
+            format!("failed to read the directory {}: {}", dir.display(), err),
+        )
+    };
+
+    let mut children = fs::read_dir(dir)
+        .map_err(io_error)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(io_error)?;
+    children.sort_by_key(fs::DirEntry::file_name);
+
+    for child in children {
+        let name = child.file_name();
+        let name = name.to_str().ok_or_else(|| {
+            parse::Error::new(
+                span,
+                format!("the filename must be UTF-8: {}", child.path().display()),
+            )
+        })?;
+        let relative = format!("{}{}", prefix, name);
+        if input
+            .exclude
+            .iter()
+            .any(|pattern| is_excluded(pattern, &relative))
+        {
+            continue;
+        }
+
+        let path = child.path();
+        let file_type = child.file_type().map_err(io_error)?;
+        if file_type.is_dir() {
+            collect_entries(input, &path, &format!("{}/", relative), entries)?;
+            continue;
+        }
+
+        let data = fs::read(&path).map_err(io_error)?;
+        let mut hasher = DefaultHasher::new();
+        hasher.write(&data);
+
+        let mut routes = vec![format!("/{}", relative)];
+        if input.index.as_ref().map(String::as_str) == Some(name) {
+            routes.push(format!("/{}", prefix));
+        }
+
+        entries.push(Entry {
+            routes,
+            content_type: mime_guess::guess_mime_type(&path).to_string(),
+            etag: format!("\"{:x}-{:x}\"", data.len(), hasher.finish()),
+            path,
+        });
+    }
+
+    Ok(())
+}
+
+/// Returns whether the relative path matches the exclusion pattern.
+///
+/// The pattern without slashes is matched against the filename, and the
+/// other is matched against the relative path from the root directory.
+fn is_excluded(pattern: &str, relative: &str) -> bool {
+    if pattern.contains('/') {
+        glob_match(
+            pattern.trim_start_matches('/').as_bytes(),
+            relative.as_bytes(),
+        )
+    } else {
+        let name = relative.rsplit('/').next().unwrap_or(relative);
+        glob_match(pattern.as_bytes(), name.as_bytes())
+    }
+}
+
+/// A minimal glob matcher supporting `?`, `*` and `**`.
+fn glob_match(pattern: &[u8], s: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => s.is_empty(),
+        Some((b'*', rest)) if rest.first() == Some(&b'*') => {
+            match rest[1..].split_first() {
+                // `**/` matches zero or more directories.
+                Some((b'/', rest)) => {
+                    (0..=s.len()).any(|i| (i == 0 || s[i - 1] == b'/') && glob_match(rest, &s[i..]))
+                }
+                _ => (0..=s.len()).any(|i| glob_match(&rest[1..], &s[i..])),
+            }
+        }
+        Some((b'*', rest)) => {
+            for i in 0..=s.len() {
+                if glob_match(rest, &s[i..]) {
+                    return true;
+                }
+                if i < s.len() && s[i] == b'/' {
+                    break;
+                }
+            }
+            false
+        }
+        Some((b'?', rest)) => match s.split_first() {
+            Some((&c, s)) if c != b'/' => glob_match(rest, s),
+            _ => false,
+        },
+        Some((&c, rest)) => match s.split_first() {
+            Some((&d, s)) if c == d => glob_match(rest, s),
+            _ => false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(is_excluded("*.map", "app.js.map"));
+        assert!(is_excluded("*.map", "js/app.js.map"));
+        assert!(!is_excluded("*.map", "app.js"));
+        assert!(is_excluded("drafts/*", "drafts/post.html"));
+        assert!(!is_excluded("drafts/*", "drafts/2018/post.html"));
+        assert!(is_excluded("drafts/**", "drafts/2018/post.html"));
+        assert!(is_excluded("**/secret.txt", "secret.txt"));
+        assert!(is_excluded("**/secret.txt", "a/b/secret.txt"));
+        assert!(is_excluded("?.txt", "a.txt"));
+        assert!(!is_excluded("?.txt", "ab.txt"));
+    }
+}
@@ -0,0 +1,156 @@
+//! Serving the HTTP-01 challenges of the ACME protocol (RFC 8555), used by
+//! Let's Encrypt to validate the control over a domain.
+//!
+//! An ACME client registers the key authorization of each pending challenge
+//! into a [`ChallengeStore`], and [`Http01Challenges`] responds to the validation
+//! requests at `/.well-known/acme-challenge/:token` with it.
+//!
+//! The validation requests are sent to port 80, so the challenges are usually
+//! served by a separate application bound to that port while the main application
+//! is served over TLS:
+//!
+//! ```no_run
+//! # use tsukuyomi::{acme::{ChallengeStore, Http01Challenges}, App};
+//! # fn main() -> tsukuyomi_server::Result<()> {
+//! let store = ChallengeStore::default();
+//!
+//! let challenges = App::create(Http01Challenges::new(store.clone()))?;
+//! std::thread::spawn(move || {
+//!     tsukuyomi_server::Server::new(challenges)
+//!         .bind("0.0.0.0:80".parse::<std::net::SocketAddr>().unwrap())
+//!         .run()
+//! });
+//!
+//! // pass `store` to the ACME client, which writes the issued certificate
+//! // to the files watched by the TLS listener.
+//! # drop(store);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`ChallengeStore`]: ./struct.ChallengeStore.html
+//! [`Http01Challenges`]: ./struct.Http01Challenges.html
+
+use {
+    crate::{
+        app::config::{Concurrency, Scope},
+        error::Error,
+        future::{Async, Poll, TryFuture},
+        handler::{AllowedMethods, Handler, ModifyHandler},
+        input::Input,
+    },
+    http::{header::CONTENT_TYPE, Method, Response, StatusCode},
+    std::{
+        collections::HashMap,
+        sync::{Arc, RwLock},
+    },
+};
+
+/// The path of the challenge resources.
+pub const CHALLENGE_PATH: &str = "/.well-known/acme-challenge/:token";
+
+/// A shared table of the key authorizations of the pending challenges, keyed by the token.
+///
+/// The clones of this value refer to the same table.
+#[derive(Debug, Clone, Default)]
+pub struct ChallengeStore {
+    inner: Arc<RwLock<HashMap<String, String>>>,
+}
+
+impl ChallengeStore {
+    /// Registers the key authorization for the specified token.
+    ///
+    /// The previous key authorization of the same token is replaced.
+    pub fn insert(&self, token: impl Into<String>, key_authorization: impl Into<String>) {
+        self.inner
+            .write()
+            .unwrap_or_else(|err| err.into_inner())
+            .insert(token.into(), key_authorization.into());
+    }
+
+    /// Removes the challenge of the specified token, typically after the validation has finished.
+    pub fn remove(&self, token: &str) -> Option<String> {
+        self.inner
+            .write()
+            .unwrap_or_else(|err| err.into_inner())
+            .remove(token)
+    }
+
+    /// Returns the key authorization registered for the specified token.
+    pub fn get(&self, token: &str) -> Option<String> {
+        self.inner
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+            .get(token)
+            .cloned()
+    }
+}
+
+/// A `Config` that registers the route serving the HTTP-01 challenges in a `ChallengeStore`.
+#[derive(Debug)]
+pub struct Http01Challenges {
+    store: ChallengeStore,
+}
+
+impl Http01Challenges {
+    /// Creates an `Http01Challenges` serving the challenges in the specified store.
+    pub fn new(store: ChallengeStore) -> Self {
+        Self { store }
+    }
+}
+
+impl<M, C> crate::config::Config<M, C> for Http01Challenges
+where
+    M: ModifyHandler<ServeChallenge>,
+    M::Handler: Into<C::Handler>,
+    C: Concurrency,
+{
+    type Error = crate::config::Error;
+
+    fn configure(self, scope: &mut Scope<'_, M, C>) -> crate::config::Result<()> {
+        scope.route(CHALLENGE_PATH, ServeChallenge { store: self.store })
+    }
+}
+
+/// The handler that responds the key authorization of the requested token.
+#[derive(Debug, Clone)]
+pub struct ServeChallenge {
+    store: ChallengeStore,
+}
+
+impl Handler for ServeChallenge {
+    type Output = Response<String>;
+    type Error = Error;
+    type Handle = Self;
+
+    fn allowed_methods(&self) -> Option<&AllowedMethods> {
+        Some(AllowedMethods::get())
+    }
+
+    fn handle(&self) -> Self::Handle {
+        self.clone()
+    }
+}
+
+impl TryFuture for ServeChallenge {
+    type Ok = Response<String>;
+    type Error = Error;
+
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        if input.request.method() != Method::GET && input.request.method() != Method::HEAD {
+            return Err(StatusCode::METHOD_NOT_ALLOWED.into());
+        }
+        let key_authorization = input
+            .params
+            .as_ref()
+            .and_then(|params| params.get(0))
+            .and_then(|token| self.store.get(token))
+            .ok_or_else(|| crate::error::not_found("unknown challenge token"))?;
+        Ok(Async::Ready(
+            Response::builder()
+                .header(CONTENT_TYPE, "text/plain")
+                .body(key_authorization)
+                .expect("should be a valid response"),
+        ))
+    }
+}
@@ -1,28 +1,85 @@
 //! Components for constructing HTTP applications.
 
+mod body_buffering;
+mod canonical;
 pub mod config;
+mod detach;
+mod dispatch;
+mod fingerprint;
+mod header_limits;
+mod hygiene;
+mod lifecycle;
+mod locale_prefix;
+mod log_override;
+mod modify_response;
 mod recognizer;
+pub mod redirects;
+mod report;
+mod request_size;
+mod response_size;
+mod routes;
 mod scope;
+mod server_options;
 mod service;
+mod slow_request;
+mod state;
+mod tags;
 
 #[cfg(test)]
 mod tests;
 
-pub(crate) use self::recognizer::Captures;
 pub use self::{
+    body_buffering::BodyBuffering,
+    canonical::CanonicalHost,
     config::{Error, Result},
+    header_limits::{HeaderLimitExceeded, HeaderLimits},
+    hygiene::{HeaderHygiene, HygieneMetrics, Strictness},
+    lifecycle::{Lifecycle, Shutdown},
+    locale_prefix::{LocalePrefix, Localized},
+    log_override::{LogLevelOverride, LogOverride, LOG_OVERRIDE_TARGET},
+    modify_response::{ModifyResponse, ResponseHook},
+    report::{ErrorReport, PanicReport, RequestInfo},
+    request_size::RequestSizeLimit,
+    response_size::ResponseSizeLimit,
+    server_options::{ServerOptions, ServerOptionsHandler},
     service::AppService,
+    slow_request::SlowRequestLog,
+    tags::{RouteHandler, Tag},
+};
+pub(crate) use self::{
+    detach::DetachedInput,
+    dispatch::{Dispatch, DispatchFuture},
+    recognizer::Captures,
+    request_size::check_declared_length,
+    routes::Routes,
+    state::States,
 };
 
 use {
     self::{
         config::Concurrency,
+        lifecycle::Drain,
         recognizer::{RecognizeError, Recognizer},
+        report::Reporter,
         scope::{Scope, ScopeId, Scopes},
+        state::StateMap,
+    },
+    crate::{
+        extractor::forwarded::TrustedProxies,
+        handler::AllowedMethods,
+        input::body::RequestBody,
+        output::ResponseBody,
+        policy::Policy,
+        rt::{Clock, Random},
+        uri::Uri,
+        util::Never,
+    },
+    http::{Method, Request, Response},
+    std::{
+        fmt::{self, Write},
+        sync::Arc,
+        time::Duration,
     },
-    crate::{input::body::RequestBody, uri::Uri, util::Never},
-    http::Request,
-    std::{fmt, sync::Arc},
     tsukuyomi_service::{MakeService, Service},
 };
 
@@ -46,6 +103,153 @@ where
             modify_service,
         }
     }
+
+    /// Replaces the clock used by the framework components, such as `SlowRequestLog`.
+    ///
+    /// The default value is `rt::SystemClock`.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the application has already been cloned.
+    pub fn with_clock(mut self, clock: impl Clock) -> Self {
+        self.inner_mut().clock = Arc::new(clock);
+        self
+    }
+
+    /// Replaces the source of random numbers, available via `Input::random`.
+    ///
+    /// The default value is `rt::SystemRandom`.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the application has already been cloned.
+    pub fn with_random(mut self, random: impl Random) -> Self {
+        self.inner_mut().random = Arc::new(random);
+        self
+    }
+
+    /// Replaces the renderer of the responses for the requests whose method is not
+    /// accepted by the matched route.
+    ///
+    /// The renderer receives the methods accepted by the route. The default value
+    /// is `fallback::method_not_allowed`.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the application has already been cloned.
+    pub fn with_method_not_allowed<F>(mut self, render: F) -> Self
+    where
+        F: Fn(&Request<()>, &AllowedMethods) -> Response<ResponseBody> + Send + Sync + 'static,
+    {
+        self.inner_mut().method_not_allowed = Arc::new(render);
+        self
+    }
+
+    /// Sets whether to render all errors in the application as the problem details
+    /// in `application/problem+json`.
+    ///
+    /// If enabled, the errors that are not `output::problem::Problem`, including the
+    /// ones generated by the framework, are converted by `output::problem::render`,
+    /// and `405 Method Not Allowed` lists the accepted methods in the extension
+    /// member `allowed`. The renderer set by `with_method_not_allowed` is not used.
+    ///
+    /// The default value is `false`.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the application has already been cloned.
+    pub fn problem_details(mut self, enabled: bool) -> Self {
+        self.inner_mut().problem_details = enabled;
+        self
+    }
+
+    /// Sets whether to remove the matrix parameters, such as `;lat=10;long=20` of
+    /// `/map;lat=10;long=20/tiles`, from the path segments before the routing.
+    ///
+    /// The removed parameters are available through `Input::matrix_params` and
+    /// `extractor::path::matrix`. If disabled, `;` is regarded as a part of the segment.
+    ///
+    /// The default value is `false`.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the application has already been cloned.
+    pub fn matrix_params(mut self, enabled: bool) -> Self {
+        self.inner_mut().matrix_params = enabled;
+        self
+    }
+
+    /// Creates a future that shuts down the stateful components of this application.
+    ///
+    /// The future waits for the in-flight requests to complete, and then calls
+    /// `Lifecycle::on_shutdown` of the states registered by `config::state_with_lifecycle`.
+    /// The hooks which do not complete within `timeout` are abandoned with a warning,
+    /// and their names (`Lifecycle::NAME`) are returned. The hooks are called only once, even if
+    /// this method is called multiple times.
+    ///
+    /// This method should be called after the server has stopped accepting the connections.
+    pub fn shutdown(&self, timeout: Duration) -> Shutdown<C> {
+        Shutdown::new(self.inner.clone(), timeout)
+    }
+
+    fn inner_mut(&mut self) -> &mut AppInner<C> {
+        Arc::get_mut(&mut self.inner).expect("the application has already been shared")
+    }
+
+    /// Returns a human-readable listing of the registered routes, for debugging.
+    ///
+    /// Each path is followed by the endpoints registered at it, starting with the
+    /// latest one, along with their tags. The endpoints replaced by `override_existing`
+    /// remain in the listing, with the methods that are now handled by the newer ones
+    /// marked as shadowed.
+    pub fn debug_routes(&self) -> String {
+        fn fmt_methods<'a>(methods: impl IntoIterator<Item = &'a Method>) -> String {
+            let methods: Vec<_> = methods.into_iter().map(Method::as_str).collect();
+            methods.join(", ")
+        }
+
+        let mut listing = String::new();
+        for endpoint in self.inner.recognizer.values() {
+            let _ = writeln!(listing, "{}", endpoint.uri);
+
+            // The methods handled by the newer endpoints, or `None` if they accept any method.
+            let mut handled: Option<Vec<&Method>> = Some(vec![]);
+            let mut current = Some(&**endpoint);
+            while let Some(endpoint) = current {
+                let methods = match endpoint.methods {
+                    Some(ref methods) => fmt_methods(methods),
+                    None => "*".into(),
+                };
+                let prefix = &self.inner.scope(endpoint.scope).data.prefix;
+                let _ = write!(listing, "    {} [scope {}]", methods, prefix);
+                if !endpoint.tags.is_empty() {
+                    let tags: Vec<_> = endpoint.tags.iter().map(|tag| tag.0).collect();
+                    let _ = write!(listing, " tags: {}", tags.join(", "));
+                }
+                if let Some(ref policy) = endpoint.policy {
+                    let _ = write!(listing, " policy: {}", policy);
+                }
+
+                let shadowed = match handled {
+                    Some(ref handled) => {
+                        fmt_methods(handled.iter().cloned().filter(|m| endpoint.accepts(m)))
+                    }
+                    None => "*".into(),
+                };
+                if !shadowed.is_empty() {
+                    let _ = write!(listing, " shadowed: {}", shadowed);
+                }
+                let _ = writeln!(listing);
+
+                match (&mut handled, &endpoint.methods) {
+                    (Some(ref mut handled), Some(ref methods)) => handled.extend(methods.iter()),
+                    (handled, _) => *handled = None,
+                }
+                current = endpoint.overridden.as_ref().map(|e| &**e);
+            }
+        }
+        listing
+    }
 }
 
 impl<C, Ctx, Bd> MakeService<Ctx, Request<Bd>> for AppBase<C>
@@ -99,10 +303,42 @@ mod with_modify_service {
 pub type App = AppBase<self::config::ThreadSafe>;
 pub type LocalApp = AppBase<self::config::CurrentThread>;
 
-#[derive(Debug)]
 struct AppInner<C: Concurrency> {
     recognizer: Recognizer<Arc<Endpoint<C>>>,
     scopes: Scopes<ScopeData<C>>,
+    reporter: Reporter,
+    canonical_host: Option<CanonicalHost>,
+    header_limits: Option<HeaderLimits>,
+    header_hygiene: Option<HeaderHygiene>,
+    clock: Arc<dyn Clock>,
+    random: Arc<dyn Random>,
+    method_not_allowed: Arc<RenderMethodNotAllowed>,
+    drain: Arc<Drain>,
+    strict_tags: bool,
+    problem_details: bool,
+    matrix_params: bool,
+}
+
+type RenderMethodNotAllowed =
+    dyn Fn(&Request<()>, &AllowedMethods) -> Response<ResponseBody> + Send + Sync + 'static;
+
+impl<C: Concurrency> fmt::Debug for AppInner<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AppInner")
+            .field("recognizer", &self.recognizer)
+            .field("scopes", &self.scopes)
+            .field("reporter", &self.reporter)
+            .field("canonical_host", &self.canonical_host)
+            .field("header_limits", &self.header_limits)
+            .field("header_hygiene", &self.header_hygiene)
+            .field("clock", &self.clock)
+            .field("random", &self.random)
+            .field("drain", &self.drain)
+            .field("strict_tags", &self.strict_tags)
+            .field("problem_details", &self.problem_details)
+            .field("matrix_params", &self.matrix_params)
+            .finish()
+    }
 }
 
 impl<C: Concurrency> AppInner<C> {
@@ -158,6 +394,24 @@ impl<C: Concurrency> AppInner<C> {
             .next()
     }
 
+    /// Finds the nearest configuration value from the specified scope to the root.
+    fn find_scope_config<T: ?Sized>(
+        &self,
+        start: ScopeId,
+        f: impl Fn(&ScopeData<C>) -> Option<&T>,
+    ) -> Option<&T> {
+        let scope = self.scope(start);
+        if let Some(value) = f(&scope.data) {
+            return Some(value);
+        }
+        scope
+            .ancestors()
+            .iter()
+            .rev()
+            .filter_map(|&id| f(&self.scope(id).data))
+            .next()
+    }
+
     fn find_endpoint(
         &self,
         path: &str,
@@ -179,6 +433,16 @@ impl<C: Concurrency> AppInner<C> {
 struct ScopeData<C: Concurrency> {
     prefix: Uri,
     default_handler: Option<C::Handler>,
+    slow_request_log: Option<SlowRequestLog>,
+    log_override: Option<LogLevelOverride>,
+    locale_prefix: Option<LocalePrefix>,
+    response_size_limit: Option<ResponseSizeLimit>,
+    request_size_limit: Option<RequestSizeLimit>,
+    body_buffering: Option<BodyBuffering>,
+    trusted_proxies: Option<TrustedProxies>,
+    response_hooks: Vec<ResponseHook>,
+    allow_overrides: bool,
+    states: StateMap,
 }
 
 impl<C: Concurrency> fmt::Debug for ScopeData<C> {
@@ -189,6 +453,16 @@ impl<C: Concurrency> fmt::Debug for ScopeData<C> {
                 "default_handler",
                 &self.default_handler.as_ref().map(|_| "<default handler>"),
             )
+            .field("slow_request_log", &self.slow_request_log)
+            .field("log_override", &self.log_override)
+            .field("locale_prefix", &self.locale_prefix)
+            .field("response_size_limit", &self.response_size_limit)
+            .field("request_size_limit", &self.request_size_limit)
+            .field("body_buffering", &self.body_buffering)
+            .field("trusted_proxies", &self.trusted_proxies)
+            .field("response_hooks", &self.response_hooks)
+            .field("allow_overrides", &self.allow_overrides)
+            .field("states", &self.states)
             .finish()
     }
 }
@@ -198,7 +472,15 @@ struct Endpoint<C: Concurrency> {
     scope: ScopeId,
     ancestors: Vec<ScopeId>,
     uri: Uri,
-    handler: C::Handler,
+    methods: Option<AllowedMethods>,
+    handler: Arc<C::Handler>,
+    tags: Vec<Tag>,
+    /// The authorization policy attached by `Route::policy`.
+    policy: Option<Policy>,
+    /// The endpoint registered earlier at the same path and overridden by this one.
+    ///
+    /// It continues to handle the requests whose method is not accepted by this endpoint.
+    overridden: Option<Box<Endpoint<C>>>,
 }
 
 impl<C: Concurrency> fmt::Debug for Endpoint<C> {
@@ -207,6 +489,49 @@ impl<C: Concurrency> fmt::Debug for Endpoint<C> {
             .field("scope", &self.scope)
             .field("ancestors", &self.ancestors)
             .field("uri", &self.uri)
+            .field("methods", &self.methods)
+            .field("tags", &self.tags)
+            .field("policy", &self.policy)
+            .field("overridden", &self.overridden)
             .finish()
     }
 }
+
+impl<C: Concurrency> Endpoint<C> {
+    fn accepts(&self, method: &Method) -> bool {
+        match self.methods {
+            Some(ref methods) => methods.contains(method),
+            None => true,
+        }
+    }
+
+    /// Returns the methods accepted by this endpoint and the overridden ones.
+    ///
+    /// If some of them accept all methods, it returns a `None`.
+    fn allowed_methods(&self) -> Option<AllowedMethods> {
+        let mut allowed_methods = self.methods.clone()?;
+        let mut overridden = self.overridden.as_ref();
+        while let Some(endpoint) = overridden {
+            allowed_methods.extend(endpoint.methods.as_ref()?.iter().cloned());
+            overridden = endpoint.overridden.as_ref();
+        }
+        Some(allowed_methods)
+    }
+
+    /// Returns the endpoint that handles the requests with the specified method.
+    ///
+    /// If none of the overridden endpoints accepts the method, the latest one is
+    /// returned so that it creates the error response.
+    fn resolve(&self, method: &Method) -> &Self {
+        let mut endpoint = self;
+        loop {
+            if endpoint.accepts(method) {
+                return endpoint;
+            }
+            match endpoint.overridden {
+                Some(ref overridden) => endpoint = overridden,
+                None => return self,
+            }
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use {
+    super::config::{Concurrency, Config, Scope},
+    crate::{
+        input::localmap::{local_key, LocalData},
+        util::Never,
+    },
+    std::{
+        path::PathBuf,
+        sync::atomic::{AtomicUsize, Ordering},
+    },
+};
+
+static SPILLS: AtomicUsize = AtomicUsize::new(0);
+
+/// A configuration that specifies how the request bodies read entirely by the
+/// extractors are buffered.
+///
+/// The bodies are buffered in memory by default. With `SpillToDisk`, the part
+/// of a body exceeding the threshold is written into a temporary file in the
+/// directory, and the decoders of `extractor::body` read it back from the file.
+/// The file is unlinked as soon as it is created on Unix, and removed when the
+/// request ends on the other platforms, including when the handler panics or
+/// the request is cancelled.
+///
+/// The configuration is applied to the current scope and its descendants, and
+/// overridden by another `BodyBuffering` registered in a sub-scope.
+///
+/// ```
+/// # use tsukuyomi::{app::BodyBuffering, config::prelude::*, extractor, App};
+/// # use serde::Deserialize;
+/// # #[derive(Deserialize)] struct Document { title: String }
+/// let app = App::create(chain![
+///     BodyBuffering::SpillToDisk {
+///         threshold: 1024 * 1024,
+///         dir: std::env::temp_dir(),
+///     },
+///     path!("/documents").to(endpoint::post()
+///         .extract(extractor::body::json())
+///         .call(|doc: Document| doc.title)),
+/// ]);
+/// # drop(app);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum BodyBuffering {
+    /// Buffers the entire body in memory.
+    Memory,
+
+    /// Spills the body into a temporary file in `dir` once it exceeds `threshold` bytes.
+    SpillToDisk { threshold: usize, dir: PathBuf },
+}
+
+impl Default for BodyBuffering {
+    fn default() -> Self {
+        BodyBuffering::Memory
+    }
+}
+
+impl BodyBuffering {
+    /// Returns the number of the request bodies spilled into the temporary files
+    /// since the process started.
+    pub fn spill_count() -> usize {
+        SPILLS.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn record_spill() {
+        SPILLS.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl<M, C> Config<M, C> for BodyBuffering
+where
+    C: Concurrency,
+{
+    type Error = Never;
+
+    fn configure(self, cx: &mut Scope<'_, M, C>) -> Result<(), Self::Error> {
+        cx.data_mut().body_buffering = Some(self);
+        Ok(())
+    }
+}
+
+impl LocalData for BodyBuffering {
+    local_key! {
+        /// The local key for the buffering strategy applied to the scope of the matched route.
+        const KEY: Self;
+    }
+}
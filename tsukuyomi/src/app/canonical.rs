@@ -0,0 +1,197 @@
+use {
+    super::{config::Concurrency, AppBase},
+    crate::extractor::forwarded::{ForwardedInfo, TrustedProxies},
+    http::{header::HeaderValue, Request, Uri},
+    std::time::Duration,
+};
+
+/// The canonical origin of the application, to which the requests with another
+/// scheme or host are redirected.
+///
+/// The requests are checked before routing, so that the requests to unknown paths
+/// are also redirected with `301 Moved Permanently` to the same path and query on
+/// the canonical origin.
+///
+/// The host and the scheme of the request are taken from the forwarding headers
+/// if the peer is trusted by the `TrustedProxies` registered in the root scope.
+/// If the scheme is not given by them, the request is assumed to be sent with
+/// the canonical scheme, since the server cannot tell whether the connection
+/// is secured.
+#[derive(Debug, Clone)]
+pub struct CanonicalHost {
+    scheme: String,
+    host: String,
+    exempt_paths: Vec<String>,
+    hsts: Option<Hsts>,
+}
+
+#[derive(Debug, Clone)]
+struct Hsts {
+    max_age: Duration,
+    include_subdomains: bool,
+    preload: bool,
+    value: HeaderValue,
+}
+
+impl Hsts {
+    fn update(&mut self) {
+        let mut value = format!("max-age={}", self.max_age.as_secs());
+        if self.include_subdomains {
+            value.push_str("; includeSubDomains");
+        }
+        if self.preload {
+            value.push_str("; preload");
+        }
+        self.value = HeaderValue::from_str(&value).expect("should be a valid header value");
+    }
+}
+
+impl CanonicalHost {
+    /// Creates a `CanonicalHost` with the origin, such as `"https://www.example.com"`.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if the origin is not of the form `scheme://host[:port]`,
+    /// or the scheme is neither `http` nor `https`.
+    pub fn new(origin: &str) -> Self {
+        let uri: Uri = origin.parse().expect("the origin should be a valid URI");
+        let scheme = uri
+            .scheme_part()
+            .map(|scheme| scheme.as_str().to_ascii_lowercase())
+            .expect("the origin should contain the scheme");
+        assert!(
+            scheme == "http" || scheme == "https",
+            "the scheme of the origin should be `http' or `https'"
+        );
+        let host = uri
+            .authority_part()
+            .map(|authority| authority.as_str().to_ascii_lowercase())
+            .expect("the origin should contain the host");
+        assert!(
+            uri.path() == "/" && uri.query().is_none(),
+            "the origin should not contain the path or the query"
+        );
+        Self {
+            scheme,
+            host,
+            exempt_paths: vec![],
+            hsts: None,
+        }
+    }
+
+    /// Sets the paths of the requests which are never redirected, such as the health checks.
+    ///
+    /// The paths are compared with the path of the request exactly.
+    pub fn exempt_paths(self, paths: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            exempt_paths: paths.into_iter().map(Into::into).collect(),
+            ..self
+        }
+    }
+
+    /// Enables the header field `Strict-Transport-Security` with the specified `max-age`.
+    ///
+    /// The header is added only to the responses to the requests that are
+    /// already on the canonical origin, and only if its scheme is `https`.
+    pub fn hsts(self, max_age: Duration) -> Self {
+        let mut hsts = self.hsts.unwrap_or_else(|| Hsts {
+            max_age,
+            include_subdomains: false,
+            preload: false,
+            value: HeaderValue::from_static(""),
+        });
+        hsts.max_age = max_age;
+        hsts.update();
+        Self {
+            hsts: Some(hsts),
+            ..self
+        }
+    }
+
+    /// Sets whether to add the directive `includeSubDomains` to `Strict-Transport-Security`.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if HSTS is not enabled by `hsts`.
+    pub fn hsts_include_subdomains(mut self, enabled: bool) -> Self {
+        {
+            let hsts = self.hsts.as_mut().expect("HSTS is not enabled");
+            hsts.include_subdomains = enabled;
+            hsts.update();
+        }
+        self
+    }
+
+    /// Sets whether to add the directive `preload` to `Strict-Transport-Security`.
+    ///
+    /// Since the preload lists require `includeSubDomains`, it is also enabled if
+    /// `enabled` is `true`.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if HSTS is not enabled by `hsts`.
+    pub fn hsts_preload(mut self, enabled: bool) -> Self {
+        {
+            let hsts = self.hsts.as_mut().expect("HSTS is not enabled");
+            hsts.preload = enabled;
+            hsts.include_subdomains |= enabled;
+            hsts.update();
+        }
+        self
+    }
+
+    pub(super) fn check(
+        &self,
+        request: &Request<()>,
+        trusted: Option<&TrustedProxies>,
+    ) -> Decision<'_> {
+        let (info, explicit_scheme) = ForwardedInfo::resolve(request, trusted);
+        let scheme_matches = !explicit_scheme || info.scheme == self.scheme;
+        let host_matches = info
+            .host
+            .as_ref()
+            .map_or(false, |host| host.eq_ignore_ascii_case(&self.host));
+
+        if scheme_matches && host_matches {
+            let hsts = match self.hsts {
+                Some(ref hsts) if self.scheme == "https" => Some(&hsts.value),
+                _ => None,
+            };
+            return Decision::Pass(hsts);
+        }
+
+        let path = request.uri().path();
+        if self.exempt_paths.iter().any(|exempt| exempt == path) {
+            return Decision::Pass(None);
+        }
+
+        let path_and_query = request
+            .uri()
+            .path_and_query()
+            .map_or("/", |path_and_query| path_and_query.as_str());
+        Decision::Redirect(format!("{}://{}{}", self.scheme, self.host, path_and_query))
+    }
+}
+
+/// The result of checking the request against `CanonicalHost`.
+pub(super) enum Decision<'a> {
+    /// The request is processed as usual, with the value of `Strict-Transport-Security` if any.
+    Pass(Option<&'a HeaderValue>),
+    /// The request is redirected to the location on the canonical origin.
+    Redirect(String),
+}
+
+impl<C> AppBase<C>
+where
+    C: Concurrency,
+{
+    /// Registers the canonical origin of the application.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the application has already been cloned.
+    pub fn canonical_host(mut self, canonical_host: CanonicalHost) -> Self {
+        self.inner_mut().canonical_host = Some(canonical_host);
+        self
+    }
+}
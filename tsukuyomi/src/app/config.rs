@@ -2,13 +2,17 @@ use {
     super::{
         recognizer::Recognizer,
         scope::{ScopeId, Scopes},
-        AppBase, AppInner, Endpoint, ScopeData, Uri,
+        AppBase, AppInner, Endpoint, ScopeData, Tag, Uri,
     },
     crate::{
+        extractor::forwarded::TrustedProxies,
         handler::{Handler, ModifyHandler},
+        policy::Policy,
+        rt::{SystemClock, SystemRandom},
         util::{Chain, Never},
     },
     failure::Fail,
+    http::Method,
     std::{marker::PhantomData, rc::Rc, sync::Arc},
 };
 
@@ -97,10 +101,9 @@ mod thread_safe {
         }
     }
 
-    type BoxedHandle =
-        dyn FnMut(&mut Input<'_>) -> Poll<Response<ResponseBody>, crate::error::Error>
-            + Send
-            + 'static;
+    type BoxedHandle = dyn FnMut(&mut Input<'_>) -> Poll<Response<ResponseBody>, crate::error::Error>
+        + Send
+        + 'static;
 
     pub struct BoxedHandler(Box<dyn Fn() -> Box<BoxedHandle> + Send + Sync + 'static>);
 
@@ -249,6 +252,16 @@ where
         let mut scopes = Scopes::new(ScopeData {
             prefix: Uri::root(),
             default_handler: None,
+            slow_request_log: None,
+            log_override: None,
+            locale_prefix: None,
+            response_size_limit: None,
+            request_size_limit: None,
+            body_buffering: None,
+            trusted_proxies: None,
+            response_hooks: vec![],
+            allow_overrides: false,
+            states: Default::default(),
         });
         config
             .configure(&mut Scope {
@@ -259,9 +272,24 @@ where
                 _marker: PhantomData,
             })
             .map_err(Into::into)?;
+        super::locale_prefix::check_conflicts(&recognizer, &scopes)?;
 
         Ok(Self {
-            inner: Arc::new(AppInner { recognizer, scopes }),
+            inner: Arc::new(AppInner {
+                recognizer,
+                scopes,
+                reporter: Default::default(),
+                canonical_host: None,
+                header_limits: None,
+                header_hygiene: None,
+                clock: Arc::new(SystemClock),
+                random: Arc::new(SystemRandom::default()),
+                method_not_allowed: Arc::new(crate::fallback::method_not_allowed),
+                drain: Default::default(),
+                strict_tags: false,
+                problem_details: false,
+                matrix_params: false,
+            }),
         })
     }
 }
@@ -281,13 +309,110 @@ where
     T: Concurrency,
 {
     /// Adds a route onto the current scope.
+    ///
+    /// Registering a route at the path where another route has already been
+    /// registered is an error, unless the overrides are allowed in the current
+    /// scope by `allow_overrides`.
     pub fn route<H>(&mut self, path: impl AsRef<str>, handler: H) -> Result<()>
     where
         H: Handler,
         M: ModifyHandler<H>,
         M::Handler: Into<T::Handler>,
     {
-        let uri: Option<Uri> = match path.as_ref() {
+        let override_existing = self.allows_overrides();
+        self.add_route(path.as_ref(), handler, override_existing, vec![], None)
+    }
+
+    /// Adds a route onto the current scope, which overrides the route registered
+    /// earlier at the same path.
+    ///
+    /// The requests whose method is accepted by the new route are handled by it,
+    /// and the others are still handled by the overridden route.
+    /// If no route has been registered at the path, this method behaves the same
+    /// as `route`.
+    pub fn override_route<H>(&mut self, path: impl AsRef<str>, handler: H) -> Result<()>
+    where
+        H: Handler,
+        M: ModifyHandler<H>,
+        M::Handler: Into<T::Handler>,
+    {
+        self.add_route(path.as_ref(), handler, true, vec![], None)
+    }
+
+    /// Registers the route for the asterisk-form request `OPTIONS *`, which is
+    /// about the server as a whole rather than a specific resource.
+    ///
+    /// The route can be registered only once per application, in a scope without
+    /// prefix, and the handler must accept only `OPTIONS`.
+    /// See also `config::server_options`.
+    pub fn asterisk_route<H>(&mut self, handler: H) -> Result<()>
+    where
+        H: Handler,
+        M: ModifyHandler<H>,
+        M::Handler: Into<T::Handler>,
+    {
+        let scope = &self.scopes[self.scope_id];
+        if scope.data.prefix != Uri::root() {
+            return Err(Error::custom(failure::format_err!(
+                "the route for `OPTIONS *' cannot be registered in the scope with prefix `{}'",
+                scope.data.prefix
+            )));
+        }
+        if self.recognizer.get_mut_by_path("*").is_some() {
+            return Err(Error::custom(failure::format_err!(
+                "the route for `OPTIONS *' has already been registered"
+            )));
+        }
+
+        let handler = self.modifier.modify(handler);
+        let methods = handler.allowed_methods().cloned();
+        match methods {
+            Some(ref methods) if methods.iter().all(|method| method == Method::OPTIONS) => {}
+            _ => {
+                return Err(Error::custom(failure::format_err!(
+                    "the route for `OPTIONS *' must accept only OPTIONS"
+                )));
+            }
+        }
+
+        let endpoint = Endpoint {
+            scope: scope.id(),
+            ancestors: scope
+                .ancestors()
+                .into_iter()
+                .cloned()
+                .chain(Some(scope.id()))
+                .collect(),
+            uri: Uri::asterisk(),
+            methods,
+            handler: Arc::new(handler.into()),
+            tags: vec![],
+            policy: None,
+            overridden: None,
+        };
+        self.recognizer
+            .insert("*", Arc::new(endpoint))
+            .map_err(Error::custom)
+    }
+
+    pub(crate) fn allows_overrides(&self) -> bool {
+        self.scopes[self.scope_id].data.allow_overrides
+    }
+
+    pub(crate) fn add_route<H>(
+        &mut self,
+        path: &str,
+        handler: H,
+        override_existing: bool,
+        tags: Vec<Tag>,
+        policy: Option<Policy>,
+    ) -> Result<()>
+    where
+        H: Handler,
+        M: ModifyHandler<H>,
+        M::Handler: Into<T::Handler>,
+    {
+        let uri: Option<Uri> = match path {
             "*" => None,
             path => path.parse().map(Some).map_err(Error::custom)?,
         };
@@ -300,22 +425,41 @@ where
                 .map_err(Error::custom)?;
 
             let scope = &self.scopes[self.scope_id];
-            self.recognizer
-                .insert(
-                    uri.as_str(),
-                    Arc::new(Endpoint {
-                        scope: scope.id(),
-                        ancestors: scope
-                            .ancestors()
-                            .into_iter()
-                            .cloned()
-                            .chain(Some(scope.id()))
-                            .collect(),
-                        uri: uri.clone(),
-                        handler: self.modifier.modify(handler).into(),
-                    }),
-                )
-                .map_err(Error::custom)?;
+            let handler = self.modifier.modify(handler);
+            let mut endpoint = Endpoint {
+                scope: scope.id(),
+                ancestors: scope
+                    .ancestors()
+                    .into_iter()
+                    .cloned()
+                    .chain(Some(scope.id()))
+                    .collect(),
+                uri: uri.clone(),
+                methods: handler.allowed_methods().cloned(),
+                handler: Arc::new(handler.into()),
+                tags,
+                policy,
+                overridden: None,
+            };
+
+            match self.recognizer.get_mut_by_path(uri.as_str()) {
+                Some(ref mut existing) if override_existing => {
+                    let existing = Arc::get_mut(existing)
+                        .expect("the endpoint should not be shared during the configuration");
+                    std::mem::swap(existing, &mut endpoint);
+                    existing.overridden = Some(Box::new(endpoint));
+                }
+                Some(..) => {
+                    return Err(Error::custom(failure::format_err!(
+                        "the route `{}' has already been registered",
+                        uri
+                    )));
+                }
+                None => self
+                    .recognizer
+                    .insert(uri.as_str(), Arc::new(endpoint))
+                    .map_err(Error::custom)?,
+            }
         } else {
             self.scopes[self.scope_id].data.default_handler =
                 Some(self.modifier.modify(handler).into());
@@ -334,6 +478,16 @@ where
                 ScopeData {
                     prefix: parent.prefix.join(&prefix).map_err(Error::custom)?,
                     default_handler: None,
+                    slow_request_log: None,
+                    log_override: None,
+                    locale_prefix: None,
+                    response_size_limit: None,
+                    request_size_limit: None,
+                    body_buffering: None,
+                    trusted_proxies: None,
+                    response_hooks: vec![],
+                    allow_overrides: parent.allow_overrides,
+                    states: Default::default(),
                 }
             })
             .map_err(Error::custom)?;
@@ -351,6 +505,41 @@ where
         Ok(())
     }
 
+    /// Returns whether a route registered so far matches the specified path.
+    pub(super) fn has_route(&self, path: &str) -> bool {
+        self.recognizer.recognize(path, &mut None).is_ok()
+    }
+
+    pub(super) fn data_mut(&mut self) -> &mut ScopeData<T> {
+        &mut self.scopes[self.scope_id].data
+    }
+
+    /// Sets whether the routes registered after this call in the current scope
+    /// override the existing routes at the same path, instead of failing.
+    ///
+    /// The setting is inherited by the sub-scopes created after this call.
+    pub fn allow_overrides(&mut self, enabled: bool) {
+        self.data_mut().allow_overrides = enabled;
+    }
+
+    pub(crate) fn set_trusted_proxies(&mut self, proxies: TrustedProxies) {
+        self.data_mut().trusted_proxies = Some(proxies);
+    }
+
+    pub(crate) fn set_state<S>(&mut self, state: S)
+    where
+        S: Send + Sync + 'static,
+    {
+        self.data_mut().states.insert(state);
+    }
+
+    pub(crate) fn set_state_with_lifecycle<S>(&mut self, state: S)
+    where
+        S: super::Lifecycle,
+    {
+        self.data_mut().states.insert_with_lifecycle(state);
+    }
+
     /// Applies the specified configuration with a `ModifyHandler` on the current scope.
     pub fn modify<M2>(
         &mut self,
@@ -420,6 +609,21 @@ where
     }
 }
 
+impl<M, S, T> Config<M, T> for Vec<S>
+where
+    S: Config<M, T>,
+    T: Concurrency,
+{
+    type Error = S::Error;
+
+    fn configure(self, cx: &mut Scope<'_, M, T>) -> std::result::Result<(), Self::Error> {
+        for config in self {
+            config.configure(cx)?;
+        }
+        Ok(())
+    }
+}
+
 impl<M, S, E, T> Config<M, T> for std::result::Result<S, E>
 where
     S: Config<M, T>,
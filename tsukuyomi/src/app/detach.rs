@@ -0,0 +1,119 @@
+use {
+    super::{
+        config::ThreadSafe, recognizer::Captures, routes::ScopeRoutes, scope::ScopeId,
+        state::ScopeStates, AppInner,
+    },
+    crate::{
+        input::{
+            localmap::LocalMap, param::Params, response::ResponseHeaders, ConnectionInfo,
+            CookieState, Cookies, Input,
+        },
+        uri::CaptureNames,
+    },
+    http::Request,
+    std::{fmt, marker::PhantomData, mem, sync::Arc},
+};
+
+/// The data of a request moved out of `Input`, so that the handle can be polled
+/// on another thread.
+///
+/// The request is copied without the extensions other than `ConnectionInfo`.
+/// The local map, the cookie jar and the staged header fields are moved, and
+/// must be given back to the original `Input` by `attach`.
+pub(crate) struct DetachedInput {
+    inner: Arc<AppInner<ThreadSafe>>,
+    scope: ScopeId,
+    request: Request<()>,
+    params: Option<(String, Option<CaptureNames>, Option<Captures>)>,
+    cookies: CookieState,
+    locals: LocalMap,
+    response_headers: ResponseHeaders,
+}
+
+impl fmt::Debug for DetachedInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DetachedInput")
+            .field("scope", &self.scope)
+            .field("request", &self.request)
+            .finish()
+    }
+}
+
+impl DetachedInput {
+    pub(super) fn new(
+        inner: Arc<AppInner<ThreadSafe>>,
+        scope: ScopeId,
+        input: &mut Input<'_>,
+    ) -> Self {
+        let mut request = Request::new(());
+        *request.method_mut() = input.request.method().clone();
+        *request.uri_mut() = input.request.uri().clone();
+        *request.version_mut() = input.request.version();
+        *request.headers_mut() = input.request.headers().clone();
+        if let Some(info) = input.request.extensions().get::<ConnectionInfo>() {
+            request.extensions_mut().insert(info.clone());
+        }
+
+        let params = input.params.as_ref().map(|params| {
+            (
+                params.path.to_owned(),
+                params.names.cloned(),
+                params.captures.cloned(),
+            )
+        });
+
+        let mut detached = Self {
+            inner,
+            scope,
+            request,
+            params,
+            cookies: CookieState::default(),
+            locals: LocalMap::default(),
+            response_headers: ResponseHeaders::default(),
+        };
+        detached.swap(input);
+        detached
+    }
+
+    fn swap(&mut self, input: &mut Input<'_>) {
+        input.cookies.swap_state(&mut self.cookies);
+        mem::swap(input.locals, &mut self.locals);
+        mem::swap(input.response_headers, &mut self.response_headers);
+    }
+
+    /// Calls the function with an `Input` built from the detached data.
+    pub(crate) fn with_input<R>(&mut self, f: impl FnOnce(&mut Input<'_>) -> R) -> R {
+        let params = self
+            .params
+            .as_ref()
+            .map(|&(ref path, ref names, ref captures)| Params {
+                path,
+                names: names.as_ref(),
+                captures: captures.as_ref(),
+            });
+        f(&mut Input {
+            request: &self.request,
+            params: &params,
+            cookies: &mut Cookies::new(&mut self.cookies, &self.request),
+            locals: &mut self.locals,
+            response_headers: &mut self.response_headers,
+            states: &ScopeStates {
+                inner: &*self.inner,
+                scope: self.scope,
+            },
+            routes: &ScopeRoutes {
+                inner: &*self.inner,
+                scope: self.scope,
+            },
+            dispatch: &self.inner,
+            clock: &self.inner.clock,
+            random: &*self.inner.random,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Gives the local map, the cookie jar and the staged header fields back to `input`.
+    pub(crate) fn attach(mut self, input: &mut Input<'_>) {
+        self.swap(input);
+    }
+}
@@ -0,0 +1,46 @@
+use {
+    super::{config::ThreadSafe, detach::DetachedInput, service::AppFuture, AppInner, Concurrency},
+    crate::{
+        input::{body::RequestBody, localmap::LocalMap, Input},
+        output::ResponseBody,
+        util::Never,
+    },
+    futures01::Future,
+    http::{Request, Response},
+    std::{any::Any, fmt, sync::Arc},
+};
+
+pub(crate) type DispatchFuture =
+    Box<dyn Future<Item = Response<ResponseBody>, Error = Never> + Send + 'static>;
+
+/// The entry point of the requests made inside of the application, such as
+/// the sub-requests of a batch.
+pub(crate) trait Dispatch: fmt::Debug {
+    /// Creates a future that processes the request through the application,
+    /// starting from the routing.
+    ///
+    /// This method returns `None` if the application is not thread safe.
+    fn dispatch(&self, request: Request<RequestBody>, locals: LocalMap) -> Option<DispatchFuture>;
+
+    /// Moves the data of the request out of `input`, so that the handler can be
+    /// processed on another thread.
+    ///
+    /// This method returns `None` if the application is not thread safe.
+    fn detach(&self, _input: &mut Input<'_>) -> Option<DetachedInput> {
+        None
+    }
+}
+
+impl<C: Concurrency> Dispatch for Arc<AppInner<C>> {
+    fn dispatch(&self, request: Request<RequestBody>, locals: LocalMap) -> Option<DispatchFuture> {
+        // `AppFuture` is sendable only if the handlers are.
+        let inner = (self as &dyn Any).downcast_ref::<Arc<AppInner<ThreadSafe>>>()?;
+        Some(Box::new(AppFuture::new(inner.clone(), request, locals)))
+    }
+
+    fn detach(&self, input: &mut Input<'_>) -> Option<DetachedInput> {
+        let inner = (self as &dyn Any).downcast_ref::<Arc<AppInner<ThreadSafe>>>()?;
+        let scope = input.states.scope()?;
+        Some(DetachedInput::new(inner.clone(), scope, input))
+    }
+}
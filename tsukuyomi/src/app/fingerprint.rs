@@ -0,0 +1,89 @@
+use {
+    super::{config::Concurrency, AppBase, Endpoint},
+    http::Method,
+    std::fmt::Write,
+};
+
+impl<C> AppBase<C>
+where
+    C: Concurrency,
+{
+    /// Returns a canonical listing of the routing table, for detecting the changes
+    /// of the routes between builds.
+    ///
+    /// Each line describes an endpoint in the following form, and the lines are sorted
+    /// so that the order of the sibling scopes and routes does not matter:
+    ///
+    /// ```text
+    /// /api/posts/:id GET,PUT scopes=/,/api tags=admin
+    /// ```
+    ///
+    /// The endpoints overridden by `override_route` are listed with the suffix
+    /// ` (overridden)`. The default handlers registered at `*`, the handlers
+    /// themselves and the modifiers applied to them are not included.
+    pub fn manifest(&self) -> String {
+        let mut lines = vec![];
+        for endpoint in self.inner.recognizer.values() {
+            let mut current = Some(&**endpoint);
+            let mut overridden = false;
+            while let Some(endpoint) = current {
+                let mut line = self.manifest_line(endpoint);
+                if overridden {
+                    line.push_str(" (overridden)");
+                }
+                lines.push(line);
+                overridden = true;
+                current = endpoint.overridden.as_ref().map(|e| &**e);
+            }
+        }
+        lines.sort();
+
+        let mut manifest = String::new();
+        for line in lines {
+            manifest.push_str(&line);
+            manifest.push('\n');
+        }
+        manifest
+    }
+
+    /// Returns the digest of `manifest`, as a hexadecimal string.
+    ///
+    /// The digest is computed with the 64-bit FNV-1a hash, which is stable across
+    /// the compilations and the platforms.
+    pub fn fingerprint(&self) -> String {
+        let hash = self
+            .manifest()
+            .bytes()
+            .fold(0xcbf2_9ce4_8422_2325_u64, |hash, b| {
+                (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+            });
+        format!("{:016x}", hash)
+    }
+
+    fn manifest_line(&self, endpoint: &Endpoint<C>) -> String {
+        let methods = match endpoint.methods {
+            Some(ref methods) => {
+                let mut methods: Vec<_> = methods.iter().map(Method::as_str).collect();
+                methods.sort();
+                methods.join(",")
+            }
+            None => "*".into(),
+        };
+        let scopes: Vec<_> = endpoint
+            .ancestors
+            .iter()
+            .map(|&id| self.inner.scope(id).data.prefix.as_str())
+            .collect();
+
+        let mut line = format!("{} {} scopes={}", endpoint.uri, methods, scopes.join(","));
+        if !endpoint.tags.is_empty() {
+            let mut tags: Vec<_> = endpoint.tags.iter().map(|tag| tag.0).collect();
+            tags.sort();
+            let _ = write!(line, " tags={}", tags.join(","));
+        }
+        if let Some(ref policy) = endpoint.policy {
+            let _ = write!(line, " policy={}", policy);
+        }
+        line
+    }
+}
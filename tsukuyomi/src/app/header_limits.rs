@@ -0,0 +1,250 @@
+use {
+    super::{config::Concurrency, AppBase},
+    crate::{error::HttpError, input::accept::Accept, output::ResponseBody},
+    http::{
+        header::{HeaderName, HeaderValue, CONTENT_TYPE, VARY},
+        Request, Response, StatusCode,
+    },
+    std::{
+        fmt,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    },
+};
+
+/// The limits on the header fields of the requests, enforced by the application.
+///
+/// Unlike the limits of the HTTP parser, the requests exceeding these limits are
+/// rejected with `431 Request Header Fields Too Large` through the error rendering
+/// of the application. The requests are checked before routing, so the limits
+/// apply to all paths except the exempted prefixes.
+#[derive(Clone)]
+pub struct HeaderLimits {
+    max_count: Option<usize>,
+    max_sizes: Vec<(HeaderName, usize)>,
+    exempt_prefixes: Vec<String>,
+    render: Option<Arc<RenderHeaderLimitExceeded>>,
+    rejected: Arc<AtomicUsize>,
+}
+
+type RenderHeaderLimitExceeded =
+    dyn Fn(&Request<()>, &HeaderLimitExceeded) -> Response<ResponseBody> + Send + Sync + 'static;
+
+impl fmt::Debug for HeaderLimits {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HeaderLimits")
+            .field("max_count", &self.max_count)
+            .field("max_sizes", &self.max_sizes)
+            .field("exempt_prefixes", &self.exempt_prefixes)
+            .field("rejected", &self.rejected())
+            .finish()
+    }
+}
+
+impl Default for HeaderLimits {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HeaderLimits {
+    /// Creates a `HeaderLimits` without any limits.
+    pub fn new() -> Self {
+        Self {
+            max_count: None,
+            max_sizes: vec![],
+            exempt_prefixes: vec![],
+            render: None,
+            rejected: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Sets the maximum number of the header fields in a request.
+    pub fn max_count(self, max_count: usize) -> Self {
+        Self {
+            max_count: Some(max_count),
+            ..self
+        }
+    }
+
+    /// Sets the maximum size of the values of the specified header field in bytes.
+    ///
+    /// If the field occurs multiple times, the sum of the sizes of all values is limited.
+    pub fn max_size(mut self, name: HeaderName, max_size: usize) -> Self {
+        self.max_sizes.retain(|(n, _)| *n != name);
+        self.max_sizes.push((name, max_size));
+        self
+    }
+
+    /// Sets the prefixes of the paths whose requests are never checked, such as
+    /// the endpoints accepting large tokens from a trusted upstream.
+    pub fn exempt_prefixes(self, prefixes: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            exempt_prefixes: prefixes.into_iter().map(Into::into).collect(),
+            ..self
+        }
+    }
+
+    /// Replaces the renderer of the responses to the rejected requests.
+    ///
+    /// By default, the response is created by `HttpError::into_response` of `HeaderLimitExceeded`.
+    pub fn render<F>(self, render: F) -> Self
+    where
+        F: Fn(&Request<()>, &HeaderLimitExceeded) -> Response<ResponseBody> + Send + Sync + 'static,
+    {
+        Self {
+            render: Some(Arc::new(render)),
+            ..self
+        }
+    }
+
+    /// Returns the number of the requests rejected by these limits.
+    ///
+    /// The counter is shared among the clones of this value.
+    pub fn rejected(&self) -> usize {
+        self.rejected.load(Ordering::Relaxed)
+    }
+
+    pub(super) fn check(&self, request: &Request<()>) -> Result<(), HeaderLimitExceeded> {
+        let path = request.uri().path();
+        if self
+            .exempt_prefixes
+            .iter()
+            .any(|prefix| path.starts_with(&**prefix))
+        {
+            return Ok(());
+        }
+
+        let headers = request.headers();
+        if let Some(max_count) = self.max_count {
+            if headers.len() > max_count {
+                return Err(self.reject(HeaderLimitExceeded {
+                    name: None,
+                    actual: headers.len(),
+                    limit: max_count,
+                }));
+            }
+        }
+        for (name, max_size) in &self.max_sizes {
+            let size: usize = headers.get_all(name).iter().map(HeaderValue::len).sum();
+            if size > *max_size {
+                return Err(self.reject(HeaderLimitExceeded {
+                    name: Some(name.clone()),
+                    actual: size,
+                    limit: *max_size,
+                }));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn reject(&self, err: HeaderLimitExceeded) -> HeaderLimitExceeded {
+        self.rejected.fetch_add(1, Ordering::Relaxed);
+        err
+    }
+
+    pub(super) fn render_error(
+        &self,
+        request: &Request<()>,
+        err: &HeaderLimitExceeded,
+    ) -> Option<Response<ResponseBody>> {
+        self.render.as_ref().map(|render| render(request, err))
+    }
+}
+
+/// The error that a request exceeds `HeaderLimits`.
+#[derive(Debug, Clone)]
+pub struct HeaderLimitExceeded {
+    name: Option<HeaderName>,
+    actual: usize,
+    limit: usize,
+}
+
+impl HeaderLimitExceeded {
+    /// Returns the name of the header field which is too large, or `None` if
+    /// the request has too many header fields.
+    pub fn name(&self) -> Option<&HeaderName> {
+        self.name.as_ref()
+    }
+
+    /// Returns the number of the header fields, or the size of the header field in bytes.
+    pub fn actual(&self) -> usize {
+        self.actual
+    }
+
+    /// Returns the limit that the request has exceeded.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+}
+
+impl fmt::Display for HeaderLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name {
+            Some(ref name) => write!(
+                f,
+                "the header field `{}' is too large: {} bytes (limit: {})",
+                name, self.actual, self.limit
+            ),
+            None => write!(
+                f,
+                "too many header fields: {} (limit: {})",
+                self.actual, self.limit
+            ),
+        }
+    }
+}
+
+/// The response is a JSON object such as `{"error":"request_header_fields_too_large",...}`
+/// if the client accepts `application/json` in preference to `text/plain`, and
+/// a plain text otherwise.
+impl HttpError for HeaderLimitExceeded {
+    type Body = ResponseBody;
+
+    fn into_response(self, request: &Request<()>) -> Response<Self::Body> {
+        let available = [mime::TEXT_PLAIN_UTF_8, mime::APPLICATION_JSON];
+        let prefers_json = Accept::from_headers(request.headers())
+            .and_then(|accept| accept.negotiate(&available).first().cloned().cloned())
+            .map_or(false, |mime| mime == mime::APPLICATION_JSON);
+
+        let (content_type, body) = if prefers_json {
+            let body = serde_json::json!({
+                "error": "request_header_fields_too_large",
+                "message": self.to_string(),
+                "header": self.name.as_ref().map(HeaderName::as_str),
+                "limit": self.limit,
+            });
+            (mime::APPLICATION_JSON, body.to_string())
+        } else {
+            (mime::TEXT_PLAIN_UTF_8, format!("{}\n", self))
+        };
+
+        let mut response = Response::new(body.into());
+        *response.status_mut() = StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE;
+        let headers = response.headers_mut();
+        headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_str(content_type.as_ref()).expect("should be a valid header value"),
+        );
+        headers.insert(VARY, HeaderValue::from_static("accept"));
+        response
+    }
+}
+
+impl<C> AppBase<C>
+where
+    C: Concurrency,
+{
+    /// Registers the limits on the header fields of the requests.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the application has already been cloned.
+    pub fn header_limits(mut self, header_limits: HeaderLimits) -> Self {
+        self.inner_mut().header_limits = Some(header_limits);
+        self
+    }
+}
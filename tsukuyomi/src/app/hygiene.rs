@@ -0,0 +1,228 @@
+use {
+    super::{config::Concurrency, AppBase},
+    crate::{output::ResponseBody, rt::Clock},
+    http::{
+        header::{self, HeaderMap, HeaderName, HeaderValue},
+        Response, StatusCode,
+    },
+    hyper::body::Payload,
+    std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        time::{SystemTime, UNIX_EPOCH},
+    },
+};
+
+/// The hop-by-hop header fields, which must not be set by the handlers.
+const HOP_BY_HOP: &[&str] = &["connection", "transfer-encoding", "keep-alive"];
+
+/// How strictly `HeaderHygiene` treats the mismatches of `Content-Length`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strictness {
+    /// The mismatches are logged and counted, but left as they are.
+    Lenient,
+
+    /// The value is replaced with the actual length of the body.
+    Strict,
+
+    /// The response is replaced with `500 Internal Server Error`.
+    Paranoid,
+}
+
+impl Default for Strictness {
+    fn default() -> Self {
+        Strictness::Strict
+    }
+}
+
+/// The normalization of the header fields of the responses, applied just before
+/// the responses are returned from the application.
+///
+/// The pass corrects the following pathologies of the handler outputs:
+///
+/// * `Date` is missing or duplicated - it is replaced with the current time,
+///   which is formatted at most once per second.
+/// * The hop-by-hop fields (`Connection`, `Transfer-Encoding` and `Keep-Alive`)
+///   are set - they are removed, except for `101 Switching Protocols`.
+/// * `Content-Length` disagrees with the length of the body - it is treated
+///   according to `Strictness`.
+/// * `Content-Type` is duplicated - only the first one is kept.
+///
+/// Each correction is counted, and the counters are shared among the clones.
+#[derive(Debug, Clone, Default)]
+pub struct HeaderHygiene {
+    strictness: Strictness,
+    date: Arc<Mutex<Option<(u64, HeaderValue)>>>,
+    counters: Arc<Counters>,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    date: AtomicUsize,
+    hop_by_hop: AtomicUsize,
+    content_length: AtomicUsize,
+    content_type: AtomicUsize,
+}
+
+impl HeaderHygiene {
+    /// Creates a `HeaderHygiene` with the default strictness.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how the mismatches of `Content-Length` are treated.
+    ///
+    /// The default value is `Strictness::Strict`.
+    pub fn strictness(self, strictness: Strictness) -> Self {
+        Self { strictness, ..self }
+    }
+
+    /// Returns a snapshot of the numbers of the corrections.
+    pub fn metrics(&self) -> HygieneMetrics {
+        let counters = &*self.counters;
+        HygieneMetrics {
+            date: counters.date.load(Ordering::Relaxed) as u64,
+            hop_by_hop: counters.hop_by_hop.load(Ordering::Relaxed) as u64,
+            content_length: counters.content_length.load(Ordering::Relaxed) as u64,
+            content_type: counters.content_type.load(Ordering::Relaxed) as u64,
+        }
+    }
+
+    pub(super) fn apply(
+        &self,
+        output: &mut Response<ResponseBody>,
+        clock: &dyn Clock,
+    ) -> Result<(), crate::Error> {
+        let content_length = output.body().content_length();
+        let status = output.status();
+        let headers = output.headers_mut();
+
+        if headers.get_all(header::DATE).iter().count() != 1 {
+            if headers.contains_key(header::DATE) {
+                self.counters.date.fetch_add(1, Ordering::Relaxed);
+            }
+            headers.insert(header::DATE, self.date(clock.system_now()));
+        }
+
+        if status != StatusCode::SWITCHING_PROTOCOLS {
+            for name in HOP_BY_HOP {
+                if headers.remove(*name).is_some() {
+                    log::debug!(
+                        "removed the hop-by-hop header field set by the handler: {}",
+                        name
+                    );
+                    self.counters.hop_by_hop.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+
+        if keep_first(headers, header::CONTENT_TYPE) {
+            self.counters.content_type.fetch_add(1, Ordering::Relaxed);
+        }
+
+        if let Some(len) = content_length {
+            let actual = HeaderValue::from(len);
+            let mismatched = headers
+                .get_all(header::CONTENT_LENGTH)
+                .iter()
+                .any(|value| *value != actual);
+            if mismatched {
+                self.counters.content_length.fetch_add(1, Ordering::Relaxed);
+                let message = format!(
+                    "Content-Length set by the handler disagrees with the body ({} bytes)",
+                    len
+                );
+                match self.strictness {
+                    Strictness::Lenient => log::warn!("{}", message),
+                    Strictness::Strict => {
+                        log::warn!("{}", message);
+                        headers.insert(header::CONTENT_LENGTH, actual);
+                    }
+                    Strictness::Paranoid => {
+                        return Err(crate::error::internal_server_error(message));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the formatted value of `Date`, which is cached for a second.
+    fn date(&self, now: SystemTime) -> HeaderValue {
+        let secs = now
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let mut cached = self.date.lock().unwrap();
+        match *cached {
+            Some((cached_secs, ref value)) if cached_secs == secs => value.clone(),
+            _ => {
+                let tm = time::at_utc(time::Timespec::new(secs as i64, 0));
+                let value = time::strftime("%a, %d %b %Y %T GMT", &tm)
+                    .ok()
+                    .and_then(|s| HeaderValue::from_str(&s).ok())
+                    .expect("the formatted date should be a valid header value");
+                *cached = Some((secs, value.clone()));
+                value
+            }
+        }
+    }
+}
+
+/// Removes the values of the field other than the first one, and returns
+/// whether any of them has been removed.
+fn keep_first(headers: &mut HeaderMap, name: HeaderName) -> bool {
+    if headers.get_all(&name).iter().count() <= 1 {
+        return false;
+    }
+    let first = headers.get(&name).cloned().expect("the field should exist");
+    headers.insert(name, first);
+    true
+}
+
+/// A snapshot of the numbers of the corrections made by `HeaderHygiene`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct HygieneMetrics {
+    /// The number of the responses with the duplicated `Date`.
+    pub date: u64,
+
+    /// The number of the hop-by-hop header fields removed from the responses.
+    pub hop_by_hop: u64,
+
+    /// The number of the responses whose `Content-Length` disagrees with the body.
+    pub content_length: u64,
+
+    /// The number of the responses with the duplicated `Content-Type`.
+    pub content_type: u64,
+}
+
+impl HygieneMetrics {
+    /// Returns the pairs of the metric names prefixed with `header_hygiene_` and
+    /// their values, for exporting them to a metrics registry.
+    pub fn entries(&self) -> [(&'static str, u64); 4] {
+        [
+            ("header_hygiene_date_total", self.date),
+            ("header_hygiene_hop_by_hop_total", self.hop_by_hop),
+            ("header_hygiene_content_length_total", self.content_length),
+            ("header_hygiene_content_type_total", self.content_type),
+        ]
+    }
+}
+
+impl<C> AppBase<C>
+where
+    C: Concurrency,
+{
+    /// Registers the normalization of the header fields of the responses.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the application has already been cloned.
+    pub fn header_hygiene(mut self, header_hygiene: HeaderHygiene) -> Self {
+        self.inner_mut().header_hygiene = Some(header_hygiene);
+        self
+    }
+}
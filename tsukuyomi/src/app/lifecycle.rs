@@ -0,0 +1,190 @@
+use {
+    super::{config::Concurrency, AppInner},
+    crate::{rt::Delay, util::Never},
+    futures01::{task::AtomicTask, Async, Future, Poll},
+    std::{
+        any::Any,
+        fmt,
+        sync::{
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    },
+};
+
+/// A trait representing the components that need to clean up when the application
+/// shuts down, such as flushing buffers or closing connections to the backends.
+///
+/// The states implementing this trait are registered by `config::state_with_lifecycle`.
+pub trait Lifecycle: Send + Sync + 'static {
+    /// The name of the component, used in the logs and returned from `App::shutdown`
+    /// if the hook does not complete until the deadline.
+    const NAME: &'static str = "<unnamed>";
+
+    /// Creates a future that completes when the component has been cleaned up.
+    ///
+    /// This method is called at most once, after the in-flight requests have completed.
+    fn on_shutdown(&self) -> Box<dyn Future<Item = (), Error = ()> + Send + 'static>;
+}
+
+pub(super) type HookFuture = Box<dyn Future<Item = (), Error = ()> + Send + 'static>;
+
+/// The function that calls `Lifecycle::on_shutdown` of a type-erased state.
+pub(super) type ShutdownHook = fn(&(dyn Any + Send + Sync)) -> HookFuture;
+
+pub(super) fn shutdown_hook<T>(state: &(dyn Any + Send + Sync)) -> HookFuture
+where
+    T: Lifecycle,
+{
+    state
+        .downcast_ref::<T>()
+        .expect("the state should be the registered type")
+        .on_shutdown()
+}
+
+/// The counter of the requests being processed by the application.
+#[derive(Debug, Default)]
+pub(super) struct Drain {
+    in_flight: AtomicUsize,
+    task: AtomicTask,
+    shut_down: AtomicBool,
+}
+
+impl Drain {
+    pub(super) fn enter(self: &Arc<Self>) -> InFlight {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        InFlight(self.clone())
+    }
+
+    fn poll_idle(&self) -> Async<()> {
+        self.task.register();
+        if self.in_flight.load(Ordering::SeqCst) == 0 {
+            Async::Ready(())
+        } else {
+            Async::NotReady
+        }
+    }
+}
+
+/// A guard of an in-flight request, which notifies the shutdown when dropped.
+#[derive(Debug)]
+pub(super) struct InFlight(Arc<Drain>);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        if self.0.in_flight.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.task.notify();
+        }
+    }
+}
+
+/// A future returned from `App::shutdown`.
+///
+/// It waits for the in-flight requests to complete, and then calls the shutdown hooks
+/// of the registered states concurrently. The hooks not completed until the deadline
+/// are abandoned, and the future resolves with their names.
+#[must_use = "futures do nothing unless polled"]
+pub struct Shutdown<C: Concurrency> {
+    inner: Arc<AppInner<C>>,
+    timeout: Duration,
+    state: ShutdownState,
+}
+
+enum ShutdownState {
+    Draining,
+    Running {
+        hooks: Vec<(&'static str, HookFuture)>,
+        deadline: Delay,
+    },
+    Done,
+}
+
+impl<C: Concurrency> fmt::Debug for Shutdown<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Shutdown")
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+impl<C: Concurrency> Shutdown<C> {
+    pub(super) fn new(inner: Arc<AppInner<C>>, timeout: Duration) -> Self {
+        Self {
+            inner,
+            timeout,
+            state: ShutdownState::Draining,
+        }
+    }
+
+    fn start_hooks(&self) -> ShutdownState {
+        if self.inner.drain.shut_down.swap(true, Ordering::SeqCst) {
+            log::debug!("the shutdown hooks have already been called");
+            return ShutdownState::Done;
+        }
+        let hooks = self
+            .inner
+            .scopes
+            .iter()
+            .flat_map(|scope| scope.data.states.shutdown_hooks())
+            .collect();
+        let deadline = self
+            .inner
+            .clock
+            .delay(self.inner.clock.now() + self.timeout);
+        ShutdownState::Running { hooks, deadline }
+    }
+}
+
+impl<C: Concurrency> Future for Shutdown<C> {
+    type Item = Vec<&'static str>;
+    type Error = Never;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            self.state = match self.state {
+                ShutdownState::Draining => {
+                    if let Async::NotReady = self.inner.drain.poll_idle() {
+                        return Ok(Async::NotReady);
+                    }
+                    self.start_hooks()
+                }
+                ShutdownState::Running {
+                    ref mut hooks,
+                    ref mut deadline,
+                } => {
+                    let mut i = 0;
+                    while i < hooks.len() {
+                        match hooks[i].1.poll() {
+                            Ok(Async::NotReady) => i += 1,
+                            Ok(Async::Ready(())) => {
+                                drop(hooks.remove(i));
+                            }
+                            Err(()) => {
+                                log::error!("the shutdown hook of {} failed", hooks[i].0);
+                                drop(hooks.remove(i));
+                            }
+                        }
+                    }
+                    if hooks.is_empty() {
+                        self.state = ShutdownState::Done;
+                        return Ok(Async::Ready(vec![]));
+                    }
+
+                    match deadline.poll() {
+                        Ok(Async::NotReady) => return Ok(Async::NotReady),
+                        Ok(Async::Ready(())) | Err(..) => {
+                            let timed_out: Vec<_> = hooks.iter().map(|(name, _)| *name).collect();
+                            for name in &timed_out {
+                                log::warn!("the shutdown hook of {} timed out", name);
+                            }
+                            self.state = ShutdownState::Done;
+                            return Ok(Async::Ready(timed_out));
+                        }
+                    }
+                }
+                ShutdownState::Done => return Ok(Async::Ready(vec![])),
+            };
+        }
+    }
+}
@@ -0,0 +1,293 @@
+use {
+    super::{
+        config::{Concurrency, Config, Error, Scope},
+        recognizer::Recognizer,
+        scope::Scopes,
+        AppInner, Endpoint, ScopeData,
+    },
+    crate::{
+        i18n::Locale,
+        input::localmap::{local_key, LocalData},
+        output::ResponseBody,
+    },
+    http::{
+        header::{HeaderMap, HeaderValue, LOCATION, VARY},
+        Request, Response, StatusCode,
+    },
+    std::{fmt, sync::Arc},
+};
+
+/// A configuration that serves the routes in the current scope under the prefixes
+/// of the supported locales, such as `/en/about` and `/ja/about`.
+///
+/// The routes are registered once at the unprefixed paths, and the first segment
+/// after the prefix of the scope is removed before the routing if it is one of the
+/// supported locales. The matched locale is stored into the local map as `Locale`,
+/// which takes precedence over `Accept-Language` in `extractor::header::accept_language`
+/// and the localization of the error messages, along with `Localized` that generates
+/// the links in the locale.
+///
+/// ```
+/// # use tsukuyomi::{app::{LocalePrefix, Localized}, config::prelude::*, extractor, App};
+/// # use tsukuyomi::input::localmap::LocalData;
+/// let app = App::create(chain![
+///     LocalePrefix::new(&["en", "ja", "de"], "en"),
+///     path!("/about") //
+///         .to(endpoint::get()
+///             .extract(extractor::local::clone(&Localized::KEY))
+///             .call(|localized: Localized| localized.url_for("/contact"))),
+/// ]);
+/// # drop(app);
+/// ```
+///
+/// The requests to the unprefixed paths of the routes are redirected with `302 Found`
+/// to the prefix of the locale negotiated from `Accept-Language`, or the default
+/// locale if none of the supported ones is acceptable. If `rewrite` is enabled, they
+/// are handled in the negotiated locale without the redirection.
+///
+/// The prefix of the scope must not contain the parameters, and creating the
+/// application fails if a route in the application is registered at a path whose
+/// first segment after the prefix is a supported locale, since it would be hidden
+/// by the locale prefix. The configuration is applied to the current scope and its
+/// descendants, and overridden by another `LocalePrefix` registered in a sub-scope.
+#[derive(Debug, Clone)]
+pub struct LocalePrefix {
+    supported: Arc<[Locale]>,
+    default: Locale,
+    rewrite: bool,
+    scope_prefix: Arc<str>,
+}
+
+impl LocalePrefix {
+    /// Creates a `LocalePrefix` with the language tags of the supported locales and
+    /// the default one, which must be included in the supported ones.
+    pub fn new(supported: &[&str], default: &str) -> Self {
+        Self {
+            supported: supported.iter().map(|&tag| Locale::new(tag)).collect(),
+            default: Locale::new(default),
+            rewrite: false,
+            scope_prefix: "".into(),
+        }
+    }
+
+    /// Sets whether to handle the requests to the unprefixed paths in the negotiated
+    /// locale, instead of redirecting them.
+    ///
+    /// The responses are marked with `Vary: Accept-Language` in that case.
+    /// The default value is `false`.
+    pub fn rewrite(self, rewrite: bool) -> Self {
+        Self { rewrite, ..self }
+    }
+
+    fn validate(&self) -> Result<(), failure::Error> {
+        if self.supported.is_empty() {
+            failure::bail!("no locale is supported by the locale prefix");
+        }
+        if let Some(locale) = self.supported.iter().find(|locale| {
+            locale.as_str().is_empty()
+                || !locale
+                    .as_str()
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+        }) {
+            failure::bail!("invalid locale for the locale prefix: `{}'", locale);
+        }
+        if !self.supported.contains(&self.default) {
+            failure::bail!(
+                "the default locale `{}' is not one of the supported locales",
+                self.default
+            );
+        }
+        Ok(())
+    }
+
+    /// Returns the locale whose prefix is the first segment of `path`, along with the rest.
+    fn strip<'a>(&self, path: &'a str) -> Option<(&Locale, &'a str)> {
+        if !path.starts_with(&*self.scope_prefix) {
+            return None;
+        }
+        let rest = &path[self.scope_prefix.len()..];
+        if !rest.starts_with('/') {
+            return None;
+        }
+        let segment = rest[1..].split('/').next().unwrap_or("");
+        let locale = self
+            .supported
+            .iter()
+            .find(|locale| locale.as_str().eq_ignore_ascii_case(segment))?;
+        Some((locale, &rest[1 + segment.len()..]))
+    }
+
+    fn localized(&self, locale: Locale) -> Localized {
+        Localized {
+            locale,
+            scope_prefix: self.scope_prefix.clone(),
+        }
+    }
+
+    fn negotiate(&self, headers: &HeaderMap) -> Locale {
+        Locale::negotiate(headers, &self.supported).unwrap_or_else(|| self.default.clone())
+    }
+}
+
+impl<M, C> Config<M, C> for LocalePrefix
+where
+    C: Concurrency,
+{
+    type Error = Error;
+
+    fn configure(mut self, cx: &mut Scope<'_, M, C>) -> Result<(), Self::Error> {
+        self.validate().map_err(Error::custom)?;
+        let data = cx.data_mut();
+        if data.prefix.capture_names().is_some() {
+            return Err(Error::custom(failure::format_err!(
+                "the locale prefix cannot be applied to the scope with parameters `{}'",
+                data.prefix
+            )));
+        }
+        self.scope_prefix = data.prefix.as_str().trim_end_matches('/').into();
+        data.locale_prefix = Some(self);
+        Ok(())
+    }
+}
+
+/// The locale selected by `LocalePrefix` for the current request.
+///
+/// The value is stored in the request-local map and can be extracted by using
+/// `extractor::local::clone(&Localized::KEY)`.
+#[derive(Debug, Clone)]
+pub struct Localized {
+    locale: Locale,
+    scope_prefix: Arc<str>,
+}
+
+impl Localized {
+    /// Returns the locale of the current request.
+    pub fn locale(&self) -> &Locale {
+        &self.locale
+    }
+
+    /// Returns the path of the link to `path` in the current locale.
+    ///
+    /// `path` is relative to the scope where `LocalePrefix` is registered, such as
+    /// `"/about"`, and the result includes the prefixes of the scope and the locale.
+    pub fn url_for(&self, path: &str) -> String {
+        self.url_for_locale(&self.locale, path)
+    }
+
+    /// Returns the path of the link to `path` in the specified locale.
+    pub fn url_for_locale(&self, locale: &Locale, path: &str) -> String {
+        let path = path.trim_start_matches('/');
+        if path.is_empty() {
+            format!("{}/{}", self.scope_prefix, locale)
+        } else {
+            format!("{}/{}/{}", self.scope_prefix, locale, path)
+        }
+    }
+}
+
+impl fmt::Display for Localized {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.locale, f)
+    }
+}
+
+impl LocalData for Localized {
+    local_key! {
+        /// The local key for the locale selected by the locale prefix.
+        const KEY: Self;
+    }
+}
+
+/// The result of resolving the locale prefix of a request.
+pub(super) enum Resolution {
+    /// The request is handled in the negotiated locale.
+    Rewrite(Localized),
+    /// The request is redirected to the prefixed path.
+    Redirect(Response<ResponseBody>),
+}
+
+impl<C: Concurrency> AppInner<C> {
+    /// Removes the locale prefix from the path, if it is the one of a localized scope.
+    ///
+    /// If the scopes are nested, the one with the longest prefix is chosen.
+    pub(super) fn strip_locale_prefix(&self, path: &str) -> Option<(Localized, String)> {
+        let (config, locale, rest) = self
+            .scopes
+            .iter()
+            .filter_map(|scope| scope.data.locale_prefix.as_ref())
+            .filter_map(|config| {
+                let (locale, rest) = config.strip(path)?;
+                Some((config, locale, rest))
+            })
+            .max_by_key(|(config, ..)| config.scope_prefix.len())?;
+
+        let stripped = format!("{}{}", config.scope_prefix, rest);
+        let stripped = if stripped.is_empty() {
+            "/".into()
+        } else {
+            stripped
+        };
+        Some((config.localized(locale.clone()), stripped))
+    }
+
+    /// Returns whether the endpoint belongs to the localized scope, or its descendants.
+    pub(super) fn is_localized_by(&self, endpoint: &Endpoint<C>, localized: &Localized) -> bool {
+        self.find_scope_config(endpoint.scope, |data| data.locale_prefix.as_ref())
+            .map_or(false, |config| {
+                config.scope_prefix == localized.scope_prefix
+            })
+    }
+
+    /// Decides how to handle the request to the unprefixed path of a localized route.
+    pub(super) fn resolve_unprefixed(
+        &self,
+        endpoint: &Endpoint<C>,
+        request: &Request<()>,
+        path: &str,
+    ) -> Option<Resolution> {
+        let config = self.find_scope_config(endpoint.scope, |data| data.locale_prefix.as_ref())?;
+        let localized = config.localized(config.negotiate(request.headers()));
+        if config.rewrite {
+            return Some(Resolution::Rewrite(localized));
+        }
+
+        let mut location = localized.url_for(&path[config.scope_prefix.len()..]);
+        if let Some(query) = request.uri().query() {
+            location.push('?');
+            location.push_str(query);
+        }
+        let mut response = Response::new(ResponseBody::empty());
+        *response.status_mut() = StatusCode::FOUND;
+        if let Ok(location) = HeaderValue::from_shared(location.into()) {
+            response.headers_mut().insert(LOCATION, location);
+        }
+        response
+            .headers_mut()
+            .insert(VARY, HeaderValue::from_static("accept-language"));
+        Some(Resolution::Redirect(response))
+    }
+}
+
+/// Checks that no route is hidden by the locale prefixes.
+pub(super) fn check_conflicts<C: Concurrency>(
+    recognizer: &Recognizer<Arc<Endpoint<C>>>,
+    scopes: &Scopes<ScopeData<C>>,
+) -> Result<(), Error> {
+    for config in scopes
+        .iter()
+        .filter_map(|scope| scope.data.locale_prefix.as_ref())
+    {
+        for endpoint in recognizer.values() {
+            if let Some((locale, _)) = config.strip(endpoint.uri.as_str()) {
+                return Err(Error::custom(failure::format_err!(
+                    "the route `{}' conflicts with the locale prefix `{}/{}'",
+                    endpoint.uri,
+                    config.scope_prefix,
+                    locale
+                )));
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,385 @@
+use {
+    super::{
+        config::{Concurrency, Config, Scope},
+        slow_request::Extractors,
+    },
+    crate::{
+        dynamic::DynamicConfig, extractor::report::ExtractionReport, input::body::RequestBody,
+        util::Never,
+    },
+    futures01::{Async, Poll, Stream},
+    http::{
+        header::{HeaderMap, HeaderName, AUTHORIZATION, COOKIE, PROXY_AUTHORIZATION, SET_COOKIE},
+        Method, StatusCode,
+    },
+    hyper::body::Payload,
+    log::Level,
+    serde::{Deserialize, Deserializer},
+    std::{
+        fmt,
+        sync::{Arc, Mutex},
+        time::{Duration, Instant},
+    },
+};
+
+/// The target of the log records emitted for the overridden routes.
+pub const LOG_OVERRIDE_TARGET: &str = "tsukuyomi::override";
+
+/// The verbose logging of the routes, enabled at runtime through `LogLevelOverride`.
+///
+/// The value is typically read from a configuration file by `ConfigSet`:
+///
+/// ```json
+/// { "level": "debug", "routes": ["/posts/:id"], "capture_body": true }
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct LogOverride {
+    #[serde(deserialize_with = "deserialize_level")]
+    level: Level,
+    #[serde(default)]
+    routes: Vec<String>,
+    #[serde(default)]
+    capture_body: bool,
+}
+
+fn deserialize_level<'de, D>(deserializer: D) -> Result<Level, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let level = String::deserialize(deserializer)?;
+    level.parse().map_err(serde::de::Error::custom)
+}
+
+impl LogOverride {
+    /// Creates a `LogOverride` applied to all of the routes in the scope.
+    pub fn new(level: Level) -> Self {
+        Self {
+            level,
+            routes: vec![],
+            capture_body: false,
+        }
+    }
+
+    /// Restricts the override to the route with the specified pattern, such as `"/posts/:id"`.
+    ///
+    /// This method can be called multiple times to specify several routes.
+    pub fn route(mut self, pattern: impl Into<String>) -> Self {
+        self.routes.push(pattern.into());
+        self
+    }
+
+    /// Sets whether to log the header fields and the body of the requests.
+    pub fn capture_body(self, capture_body: bool) -> Self {
+        Self {
+            capture_body,
+            ..self
+        }
+    }
+
+    fn applies_to(&self, pattern: Option<&str>) -> bool {
+        self.routes.is_empty()
+            || pattern.map_or(false, |pattern| self.routes.iter().any(|r| r == pattern))
+    }
+}
+
+/// A configuration that raises the verbosity of the framework's logging for the
+/// routes in the current scope, without changing the global log level.
+///
+/// While a `LogOverride` is set to the `DynamicConfig`, the requests to the targeted
+/// routes are logged with the following records at the specified level, under the
+/// target `tsukuyomi::override`:
+///
+/// ```text
+/// access: method=GET path=/posts/42 pattern=/posts/:id status=200 elapsed=1.2ms extractors=[#1 ok 15µs]
+/// error: method=GET path=/posts/42 pattern=/posts/:id status=404 extractor=#1 message=not found
+/// request headers: accept: */*, authorization: <redacted>
+/// request body: 12 bytes: "{\"id\": 42}"
+/// ```
+///
+/// The logger should enable the target at the level, such as `RUST_LOG=info,tsukuyomi::override=trace`
+/// for `env_logger`. The slow requests to the routes are also logged regardless of
+/// the sample rate of `SlowRequestLog`.
+///
+/// The override expires after the duration specified by `expires_after`, measured from
+/// when the value of `DynamicConfig` is replaced, so that a forgotten override does not
+/// keep the verbose logging. The body capture replaces the request body with a stream
+/// copying its prefix, so the upgrade of the connection is not available for the
+/// requests whose body is captured.
+///
+/// The configuration is applied to the current scope and its descendants, and
+/// overridden by another `LogLevelOverride` registered in a sub-scope.
+#[derive(Debug, Clone)]
+pub struct LogLevelOverride {
+    setting: DynamicConfig<Option<LogOverride>>,
+    expires_after: Duration,
+    max_body_size: usize,
+    redacted_headers: Arc<Vec<HeaderName>>,
+    activation: Arc<Mutex<Option<Activation>>>,
+}
+
+/// The time when the current value of `DynamicConfig` has been observed first.
+#[derive(Debug)]
+struct Activation {
+    setting: Arc<Option<LogOverride>>,
+    activated: Instant,
+    expired: bool,
+}
+
+impl LogLevelOverride {
+    /// Creates a `LogLevelOverride` controlled by the specified value.
+    pub fn new(setting: DynamicConfig<Option<LogOverride>>) -> Self {
+        Self {
+            setting,
+            expires_after: Duration::from_secs(15 * 60),
+            max_body_size: 4096,
+            redacted_headers: Arc::new(vec![
+                AUTHORIZATION,
+                PROXY_AUTHORIZATION,
+                COOKIE,
+                SET_COOKIE,
+            ]),
+            activation: Arc::default(),
+        }
+    }
+
+    /// Sets the duration after which an override is ignored.
+    ///
+    /// The default value is 15 minutes.
+    pub fn expires_after(self, expires_after: Duration) -> Self {
+        Self {
+            expires_after,
+            ..self
+        }
+    }
+
+    /// Sets the maximum number of bytes of the request body to be logged.
+    ///
+    /// The default value is 4096.
+    pub fn max_body_size(self, max_body_size: usize) -> Self {
+        Self {
+            max_body_size,
+            ..self
+        }
+    }
+
+    /// Adds a header field whose value is replaced with `<redacted>` in the log.
+    ///
+    /// `Authorization`, `Proxy-Authorization`, `Cookie` and `Set-Cookie` are redacted by default.
+    pub fn redact_header(mut self, name: HeaderName) -> Self {
+        Arc::make_mut(&mut self.redacted_headers).push(name);
+        self
+    }
+
+    /// Returns the override applied to the request to the route, if active.
+    pub(super) fn resolve(&self, pattern: Option<&str>, now: Instant) -> Option<ActiveOverride> {
+        let setting = self.setting.get();
+        let current = match *setting {
+            Some(ref current) => current,
+            None => return None,
+        };
+
+        let mut activation = self
+            .activation
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        let replaced = activation.as_ref().map_or(true, |activation| {
+            !Arc::ptr_eq(&activation.setting, &setting)
+        });
+        if replaced {
+            *activation = Some(Activation {
+                setting: setting.clone(),
+                activated: now,
+                expired: false,
+            });
+        }
+        let activation = activation.as_mut().expect("should be activated");
+        if now.duration_since(activation.activated) >= self.expires_after {
+            if !activation.expired {
+                activation.expired = true;
+                log::info!(
+                    "the log level override has expired after {:?}",
+                    self.expires_after
+                );
+            }
+            return None;
+        }
+
+        if !current.applies_to(pattern) {
+            return None;
+        }
+        Some(ActiveOverride {
+            level: current.level,
+            started: now,
+            capture: if current.capture_body {
+                Some(Capture {
+                    max_body_size: self.max_body_size,
+                    redacted_headers: self.redacted_headers.clone(),
+                    body: Arc::default(),
+                })
+            } else {
+                None
+            },
+        })
+    }
+}
+
+impl<M, C> Config<M, C> for LogLevelOverride
+where
+    C: Concurrency,
+{
+    type Error = Never;
+
+    fn configure(self, cx: &mut Scope<'_, M, C>) -> Result<(), Self::Error> {
+        cx.data_mut().log_override = Some(self);
+        Ok(())
+    }
+}
+
+/// The override applied to a request.
+#[derive(Debug)]
+pub(super) struct ActiveOverride {
+    level: Level,
+    started: Instant,
+    capture: Option<Capture>,
+}
+
+#[derive(Debug)]
+struct Capture {
+    max_body_size: usize,
+    redacted_headers: Arc<Vec<HeaderName>>,
+    body: Arc<Mutex<CapturedBody>>,
+}
+
+#[derive(Debug, Default)]
+struct CapturedBody {
+    prefix: Vec<u8>,
+    len: u64,
+}
+
+/// The information about a request, logged for the overridden routes.
+pub(super) struct Record<'a> {
+    pub(super) method: &'a Method,
+    pub(super) path: &'a str,
+    pub(super) pattern: Option<&'a str>,
+    pub(super) headers: &'a HeaderMap,
+    pub(super) status: StatusCode,
+    pub(super) finished: Instant,
+    pub(super) extraction: Option<&'a ExtractionReport>,
+}
+
+impl ActiveOverride {
+    /// Replaces the request body with the one copying its prefix, if the body is captured.
+    pub(super) fn capture_body(&self, body: RequestBody) -> RequestBody {
+        match self.capture {
+            Some(ref capture) => RequestBody::from(hyper::Body::wrap_stream(Tee {
+                body,
+                max_body_size: capture.max_body_size,
+                captured: capture.body.clone(),
+            })),
+            None => body,
+        }
+    }
+
+    pub(super) fn log_error(&self, record: &Record<'_>, message: &dyn fmt::Display) {
+        log::log!(
+            target: LOG_OVERRIDE_TARGET,
+            self.level,
+            "error: method={} path={} pattern={} status={}{} message={}",
+            record.method,
+            record.path,
+            record.pattern.unwrap_or("-"),
+            record.status.as_u16(),
+            FailedExtractor(record.extraction),
+            message,
+        );
+    }
+
+    pub(super) fn log_access(&self, record: &Record<'_>) {
+        log::log!(
+            target: LOG_OVERRIDE_TARGET,
+            self.level,
+            "access: method={} path={} pattern={} status={} elapsed={:?}{}",
+            record.method,
+            record.path,
+            record.pattern.unwrap_or("-"),
+            record.status.as_u16(),
+            record.finished.duration_since(self.started),
+            Extractors(record.extraction),
+        );
+
+        if let Some(ref capture) = self.capture {
+            log::log!(
+                target: LOG_OVERRIDE_TARGET,
+                self.level,
+                "request headers: {}",
+                RedactedHeaders(record.headers, &capture.redacted_headers)
+            );
+            let body = capture.body.lock().unwrap_or_else(|err| err.into_inner());
+            log::log!(
+                target: LOG_OVERRIDE_TARGET,
+                self.level,
+                "request body: {} bytes{}: {:?}",
+                body.len,
+                if body.len > body.prefix.len() as u64 {
+                    " (truncated)"
+                } else {
+                    ""
+                },
+                String::from_utf8_lossy(&body.prefix),
+            );
+        }
+    }
+}
+
+struct FailedExtractor<'a>(Option<&'a ExtractionReport>);
+
+impl<'a> fmt::Display for FailedExtractor<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.and_then(ExtractionReport::failed) {
+            Some(entry) => write!(f, " extractor={}", entry.label()),
+            None => Ok(()),
+        }
+    }
+}
+
+struct RedactedHeaders<'a>(&'a HeaderMap, &'a [HeaderName]);
+
+impl<'a> fmt::Display for RedactedHeaders<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (name, value)) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            if self.1.contains(name) {
+                write!(f, "{}: <redacted>", name)?;
+            } else {
+                write!(f, "{}: {}", name, String::from_utf8_lossy(value.as_bytes()))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A stream of the request body copying its prefix into the buffer.
+struct Tee {
+    body: RequestBody,
+    max_body_size: usize,
+    captured: Arc<Mutex<CapturedBody>>,
+}
+
+impl Stream for Tee {
+    type Item = hyper::Chunk;
+    type Error = hyper::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        let chunk = futures01::try_ready!(self.body.poll_data());
+        if let Some(ref chunk) = chunk {
+            let mut captured = self.captured.lock().unwrap_or_else(|err| err.into_inner());
+            let remaining = self.max_body_size.saturating_sub(captured.prefix.len());
+            let n = remaining.min(chunk.len());
+            captured.prefix.extend_from_slice(&chunk[..n]);
+            captured.len += chunk.len() as u64;
+        }
+        Ok(Async::Ready(chunk))
+    }
+}
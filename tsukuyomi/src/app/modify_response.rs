@@ -0,0 +1,97 @@
+use {
+    super::config::{Concurrency, Config, Scope},
+    crate::{output::ResponseBody, util::Never},
+    http::{Request, Response},
+    std::{fmt, sync::Arc},
+};
+
+/// A trait representing the response-side effect of a scope, such as the header
+/// fields injected into every response.
+///
+/// Unlike `ModifyHandler`, which only sees the values returned from the handlers,
+/// it is applied by the application to every response produced within the scope:
+///
+/// * the responses from the handlers,
+/// * the error responses, including the rejections of the extractors and the
+///   errors returned from the handlers,
+/// * `404 Not Found` for the paths that matched no route, and `405 Method Not Allowed`.
+///
+/// The response passed to this trait has already received the cookies and the
+/// header fields staged in `Input::response`. The effects registered in a scope
+/// are applied in the order of registration, and before those of the outer scopes.
+pub trait ModifyResponse: Send + Sync + 'static {
+    /// Modifies the response to the specified request.
+    fn modify_response(&self, request: &Request<()>, response: &mut Response<ResponseBody>);
+}
+
+impl<F> ModifyResponse for F
+where
+    F: Fn(&Request<()>, &mut Response<ResponseBody>) + Send + Sync + 'static,
+{
+    fn modify_response(&self, request: &Request<()>, response: &mut Response<ResponseBody>) {
+        (*self)(request, response)
+    }
+}
+
+/// A `Config` that registers a `ModifyResponse` into the current scope.
+///
+/// The effect is applied to the responses within the current scope and its descendants.
+#[derive(Clone)]
+pub struct ResponseHook {
+    modifier: Arc<dyn ModifyResponse>,
+    skip_errors: bool,
+}
+
+impl fmt::Debug for ResponseHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResponseHook")
+            .field("skip_errors", &self.skip_errors)
+            .finish()
+    }
+}
+
+impl ResponseHook {
+    /// Creates a `ResponseHook` with the specified `ModifyResponse`.
+    pub fn new(modifier: impl ModifyResponse) -> Self {
+        Self {
+            modifier: Arc::new(modifier),
+            skip_errors: false,
+        }
+    }
+
+    /// Excludes the responses rendered from the errors, for the effects which must
+    /// not be applied to them (e.g. caching).
+    ///
+    /// The responses that the handlers return successfully are still modified,
+    /// regardless of their status code.
+    pub fn skip_errors(self) -> Self {
+        Self {
+            skip_errors: true,
+            ..self
+        }
+    }
+
+    pub(super) fn apply(
+        &self,
+        request: &Request<()>,
+        response: &mut Response<ResponseBody>,
+        is_error: bool,
+    ) {
+        if is_error && self.skip_errors {
+            return;
+        }
+        self.modifier.modify_response(request, response);
+    }
+}
+
+impl<M, C> Config<M, C> for ResponseHook
+where
+    C: Concurrency,
+{
+    type Error = Never;
+
+    fn configure(self, cx: &mut Scope<'_, M, C>) -> Result<(), Self::Error> {
+        cx.data_mut().response_hooks.push(self);
+        Ok(())
+    }
+}
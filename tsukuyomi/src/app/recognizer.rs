@@ -9,7 +9,7 @@ use {
     },
 };
 
-#[derive(Debug, Default, PartialEq)]
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct Captures {
     params: Vec<(usize, usize)>,
     wildcard: Option<(usize, usize)>,
@@ -104,6 +104,19 @@ impl<T> Recognizer<T> {
     pub fn get(&self, index: usize) -> Option<&T> {
         Some(self.inner.get_index(index)?.1)
     }
+
+    /// Returns a mutable reference to the value registered with exactly the same path.
+    pub fn get_mut_by_path(&mut self, path: &str) -> Option<&mut T> {
+        self.inner.get_mut(path)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &T> {
+        self.inner.values()
+    }
+
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.inner.values_mut()
+    }
 }
 
 #[derive(Clone, PartialEq)]
@@ -0,0 +1,468 @@
+//! A table of redirects, which can be replaced without redeploying the application.
+//!
+//! The table is registered to a scope by [`table`] or [`dynamic`], and handles
+//! the requests that matched no route in the scope. The source paths may include
+//! the parameters, which are substituted into the targets:
+//!
+//! ```
+//! # use tsukuyomi::{app::redirects::{self, Redirect, RedirectTable}, config::prelude::*, App};
+//! let table = RedirectTable::new()
+//!     .add(Redirect::new("/promo", "https://example.com/campaign", 302).unwrap())
+//!     .unwrap()
+//!     .add(Redirect::new("/p/:slug", "/posts/:slug", 301).unwrap())
+//!     .unwrap();
+//!
+//! let app = App::create(chain![
+//!     path!("/posts/:slug") //
+//!         .to(endpoint::get().call(|slug: String| slug)),
+//!     redirects::table(table),
+//! ]);
+//! # drop(app);
+//! ```
+//!
+//! The table can also be written in CSV and loaded by `RedirectTable::from_csv`
+//! or `RedirectTable::load_csv`, with the feature `csv` enabled.
+//!
+//! The table wrapped in a [`DynamicConfig`] is reloaded by [`ConfigSet`], from
+//! a JSON array of the objects such as `{"source":"/promo","target":"/","status":302}`.
+//!
+//! [`table`]: ./fn.table.html
+//! [`dynamic`]: ./fn.dynamic.html
+//! [`DynamicConfig`]: ../../dynamic/struct.DynamicConfig.html
+//! [`ConfigSet`]: ../../dynamic/struct.ConfigSet.html
+
+use {
+    super::{
+        config::{Concurrency, Config, Error, Scope},
+        Captures, Recognizer,
+    },
+    crate::{
+        dynamic::DynamicConfig,
+        future::{Async, Poll, TryFuture},
+        handler::{AllowedMethods, Handler, ModifyHandler},
+        input::{param::Params, Input},
+        output::{header::SafeHeaderValue, ResponseBody},
+        uri::Uri,
+    },
+    http::{header::LOCATION, Response, StatusCode},
+    serde::de::{Deserialize, Deserializer},
+    std::fmt,
+};
+
+#[cfg(feature = "csv")]
+use std::{fs, path::Path};
+
+/// An error occurred during building a `RedirectTable`.
+#[derive(Debug, failure::Fail)]
+pub struct RedirectError {
+    line: Option<usize>,
+    message: String,
+}
+
+impl RedirectError {
+    fn new(message: impl fmt::Display) -> Self {
+        Self {
+            line: None,
+            message: message.to_string(),
+        }
+    }
+
+    #[cfg(feature = "csv")]
+    fn at_line(self, line: usize) -> Self {
+        Self {
+            line: Some(line),
+            ..self
+        }
+    }
+
+    /// Returns the line number in the CSV where the error occurred, if any.
+    pub fn line(&self) -> Option<usize> {
+        self.line
+    }
+}
+
+impl fmt::Display for RedirectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {}: {}", line, self.message),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+/// An entry of `RedirectTable`.
+#[derive(Debug, Clone)]
+pub struct Redirect {
+    source: Uri,
+    target: String,
+    status: StatusCode,
+}
+
+impl Redirect {
+    /// Creates a `Redirect` from the source path pattern to the target.
+    ///
+    /// The target is a path or an absolute URL, whose segments of the form `:name`
+    /// or `*` are replaced with the values captured by the source pattern.
+    /// The status code must be one of `301`, `302`, `307` and `308`.
+    pub fn new(source: &str, target: &str, status: u16) -> Result<Self, RedirectError> {
+        let source: Uri = source
+            .parse()
+            .map_err(|err| RedirectError::new(format!("invalid source `{}': {}", source, err)))?;
+
+        let status = match status {
+            301 | 302 | 307 | 308 => StatusCode::from_u16(status).expect("valid status code"),
+            status => {
+                return Err(RedirectError::new(format!(
+                    "invalid status {} (expected one of 301, 302, 307 and 308)",
+                    status
+                )));
+            }
+        };
+
+        if target.is_empty() {
+            return Err(RedirectError::new("empty target"));
+        }
+        for segment in target.split('/') {
+            let name = match segment.as_bytes().first() {
+                Some(b':') => &segment[1..],
+                _ => continue,
+            };
+            let captured = source
+                .capture_names()
+                .map_or(false, |names| names.position(name).is_some());
+            if !captured {
+                return Err(RedirectError::new(format!(
+                    "the parameter `{}' in the target is not captured by the source",
+                    name
+                )));
+            }
+        }
+
+        Ok(Self {
+            source,
+            target: target.to_owned(),
+            status,
+        })
+    }
+
+    /// Returns the source path pattern.
+    pub fn source(&self) -> &str {
+        self.source.as_str()
+    }
+
+    /// Returns the target template.
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    /// Returns the status code of the redirect.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    fn location(&self, params: &Params<'_>) -> Option<String> {
+        let segments: Option<Vec<&str>> = self
+            .target
+            .split('/')
+            .map(|segment| match segment.as_bytes().first() {
+                Some(b':') => params.name(&segment[1..]),
+                Some(b'*') if segment.len() == 1 => params.catch_all(),
+                _ => Some(segment),
+            })
+            .collect();
+        segments.map(|segments| segments.join("/"))
+    }
+}
+
+/// A set of `Redirect`s matched against the request paths.
+#[derive(Debug, Default)]
+pub struct RedirectTable {
+    entries: Vec<Redirect>,
+    recognizer: Recognizer<usize>,
+}
+
+impl RedirectTable {
+    /// Creates an empty `RedirectTable`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an entry to this table.
+    ///
+    /// Adding an entry with the same source pattern as an existing one is an error.
+    pub fn add(mut self, redirect: Redirect) -> Result<Self, RedirectError> {
+        self.recognizer
+            .insert(redirect.source.as_str(), self.entries.len())
+            .map_err(|err| {
+                RedirectError::new(format!("invalid source `{}': {}", redirect.source(), err))
+            })?;
+        self.entries.push(redirect);
+        Ok(self)
+    }
+
+    /// Parses the table from the CSV rows of the form `source, target, status`.
+    ///
+    /// The fields containing commas, such as the URLs with query strings, must be
+    /// quoted, with the opening quote placed right after the preceding comma. The
+    /// surrounding whitespace of the fields, the empty lines and the lines starting
+    /// with `#` are ignored. The errors are reported with the line numbers starting from 1.
+    #[cfg(feature = "csv")]
+    pub fn from_csv(s: &str) -> Result<Self, RedirectError> {
+        let mut reader = csv_crate::ReaderBuilder::new()
+            .has_headers(false)
+            .comment(Some(b'#'))
+            .flexible(true)
+            .trim(csv_crate::Trim::All)
+            .from_reader(s.as_bytes());
+        let mut table = Self::new();
+        for record in reader.records() {
+            // The reader does not count the skipped empty lines and comments, so the
+            // line numbers are computed from the byte offsets, which point to the
+            // end of the previous row.
+            let line_at = |pos: &csv_crate::Position| {
+                let (before, after) = s.split_at(pos.byte() as usize);
+                let skipped = after
+                    .split('\n')
+                    .take_while(|line| {
+                        let line = line.trim();
+                        line.is_empty() || line.starts_with('#')
+                    })
+                    .count();
+                before.matches('\n').count() + skipped + 1
+            };
+            let record = record.map_err(|err| {
+                let line = err.position().map(line_at);
+                let err = RedirectError::new(err);
+                match line {
+                    Some(line) => err.at_line(line),
+                    None => err,
+                }
+            })?;
+            let line = record.position().map_or(0, line_at);
+            table = Self::parse_record(&record)
+                .and_then(|redirect| table.add(redirect))
+                .map_err(|err| err.at_line(line))?;
+        }
+        Ok(table)
+    }
+
+    /// Loads the table from the CSV file at the specified path.
+    ///
+    /// See `from_csv` for the format.
+    #[cfg(feature = "csv")]
+    pub fn load_csv(path: impl AsRef<Path>) -> Result<Self, failure::Error> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)
+            .map_err(|err| failure::format_err!("failed to read {}: {}", path.display(), err))?;
+        Ok(Self::from_csv(&content)?)
+    }
+
+    #[cfg(feature = "csv")]
+    fn parse_record(record: &csv_crate::StringRecord) -> Result<Redirect, RedirectError> {
+        let fields: Vec<&str> = record.iter().collect();
+        match fields[..] {
+            [source, target, status] => {
+                let status = status
+                    .parse()
+                    .map_err(|_| RedirectError::new(format!("invalid status `{}'", status)))?;
+                Redirect::new(source, target, status)
+            }
+            _ => Err(RedirectError::new(format!(
+                "expected 3 fields (source, target, status), found {}",
+                fields.len()
+            ))),
+        }
+    }
+
+    /// Returns an iterator over the entries.
+    pub fn iter(&self) -> impl Iterator<Item = &Redirect> {
+        self.entries.iter()
+    }
+
+    fn recognize(&self, path: &str) -> Option<(&Redirect, Option<Captures>)> {
+        let mut captures = None;
+        let &index = self.recognizer.recognize(path, &mut captures).ok()?;
+        Some((&self.entries[index], captures))
+    }
+}
+
+impl<'de> Deserialize<'de> for RedirectTable {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(serde::Deserialize)]
+        struct Entry {
+            source: String,
+            target: String,
+            status: u16,
+        }
+
+        let entries = Vec::<Entry>::deserialize(deserializer)?;
+        entries
+            .into_iter()
+            .enumerate()
+            .try_fold(Self::new(), |table, (i, entry)| {
+                Redirect::new(&entry.source, &entry.target, entry.status)
+                    .and_then(|redirect| table.add(redirect))
+                    .map_err(|err| serde::de::Error::custom(format_args!("entry #{}: {}", i, err)))
+            })
+    }
+}
+
+/// How the conflicts between the redirects and the routes are treated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnConflict {
+    /// The routes take precedence over the conflicting redirects.
+    Yield,
+
+    /// The conflicts are reported as an error when building the application.
+    Error,
+}
+
+/// Creates a `Config` that registers the redirect table onto the current scope.
+pub fn table(table: RedirectTable) -> Table {
+    dynamic(DynamicConfig::new(table))
+}
+
+/// Creates a `Config` that registers the redirect table which can be replaced
+/// while the application is running.
+pub fn dynamic(table: DynamicConfig<RedirectTable>) -> Table {
+    Table {
+        table,
+        on_conflict: OnConflict::Yield,
+    }
+}
+
+/// A `Config` that registers a redirect table, created by `table` or `dynamic`.
+///
+/// The table is registered as the default handler of the scope, so it only handles
+/// the requests that matched no route, and replies `404 Not Found` if no redirect
+/// matches either. The source patterns are relative to the prefix of the scope.
+///
+/// Note that the requests whose paths share no prefix with the routes are
+/// dispatched to the default handler of the root scope, so the table is usually
+/// registered onto the root scope.
+#[derive(Debug)]
+pub struct Table {
+    table: DynamicConfig<RedirectTable>,
+    on_conflict: OnConflict,
+}
+
+impl Table {
+    /// Sets how the redirects conflicting with the routes are treated.
+    ///
+    /// The conflicts are detected with the routes registered before the table and
+    /// the initial entries of the table. The default value is `OnConflict::Yield`.
+    pub fn on_conflict(self, on_conflict: OnConflict) -> Self {
+        Self {
+            on_conflict,
+            ..self
+        }
+    }
+}
+
+impl<M, C> Config<M, C> for Table
+where
+    M: ModifyHandler<RedirectHandler>,
+    M::Handler: Into<C::Handler>,
+    C: Concurrency,
+{
+    type Error = Error;
+
+    fn configure(self, scope: &mut Scope<'_, M, C>) -> Result<(), Self::Error> {
+        let prefix = scope.data_mut().prefix.clone();
+
+        for redirect in self.table.get().iter() {
+            let path = prefix.join(&redirect.source).map_err(Error::custom)?;
+            if !scope.has_route(path.as_str()) {
+                continue;
+            }
+            match self.on_conflict {
+                OnConflict::Yield => log::warn!(
+                    "the redirect from `{}' is shadowed by an existing route",
+                    path.as_str()
+                ),
+                OnConflict::Error => {
+                    return Err(Error::custom(failure::format_err!(
+                        "the redirect from `{}' conflicts with an existing route",
+                        path.as_str()
+                    )));
+                }
+            }
+        }
+
+        scope.route(
+            "*",
+            RedirectHandler {
+                table: self.table,
+                prefix,
+            },
+        )
+    }
+}
+
+/// The handler of the requests registered by `Table`.
+#[derive(Debug)]
+pub struct RedirectHandler {
+    table: DynamicConfig<RedirectTable>,
+    prefix: Uri,
+}
+
+impl Handler for RedirectHandler {
+    type Output = Response<ResponseBody>;
+    type Error = crate::Error;
+    type Handle = HandleRedirect; // private
+
+    fn allowed_methods(&self) -> Option<&AllowedMethods> {
+        None
+    }
+
+    fn handle(&self) -> Self::Handle {
+        HandleRedirect {
+            table: self.table.clone(),
+            prefix: self.prefix.clone(),
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct HandleRedirect {
+    table: DynamicConfig<RedirectTable>,
+    prefix: Uri,
+}
+
+impl TryFuture for HandleRedirect {
+    type Ok = Response<ResponseBody>;
+    type Error = crate::Error;
+
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        let path = input.request.uri().path();
+        let path = match self.prefix.as_str().trim_end_matches('/') {
+            "" => path,
+            prefix if path.starts_with(prefix) && path[prefix.len()..].starts_with('/') => {
+                &path[prefix.len()..]
+            }
+            _ => return Err(StatusCode::NOT_FOUND.into()),
+        };
+
+        let table = self.table.get();
+        let (redirect, captures) = table
+            .recognize(path)
+            .ok_or_else(|| crate::Error::from(StatusCode::NOT_FOUND))?;
+        let params = Params {
+            path,
+            names: redirect.source.capture_names(),
+            captures: captures.as_ref(),
+        };
+        let location = redirect
+            .location(&params)
+            .map(|location| SafeHeaderValue::uri(&location))
+            .ok_or_else(|| crate::error::internal_server_error("invalid redirect target"))?;
+
+        let mut response = Response::new(ResponseBody::empty());
+        *response.status_mut() = redirect.status;
+        response.headers_mut().insert(LOCATION, location.into());
+        Ok(Async::Ready(response))
+    }
+}
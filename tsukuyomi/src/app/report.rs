@@ -0,0 +1,272 @@
+use {
+    super::{config::Concurrency, AppBase},
+    crate::extractor::report::ExtractionReport,
+    http::{
+        header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, COOKIE, PROXY_AUTHORIZATION},
+        Method, Request, StatusCode,
+    },
+    std::{any::Any, fmt, sync::Arc},
+};
+
+/// The name of the header field whose value is reported as the request ID.
+const REQUEST_ID: &str = "x-request-id";
+
+type Hook<T> = Box<dyn Fn(T) + Send + Sync + 'static>;
+
+/// The hooks for reporting the errors and panics, registered to `App`.
+pub(super) struct Reporter {
+    on_error: Option<Hook<ErrorReport>>,
+    on_panic: Option<Hook<PanicReport>>,
+    pub(super) recover_panics: bool,
+    sensitive_headers: Vec<HeaderName>,
+}
+
+impl Default for Reporter {
+    fn default() -> Self {
+        Self {
+            on_error: None,
+            on_panic: None,
+            recover_panics: false,
+            sensitive_headers: vec![AUTHORIZATION, PROXY_AUTHORIZATION, COOKIE],
+        }
+    }
+}
+
+impl fmt::Debug for Reporter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Reporter")
+            .field("on_error", &self.on_error.as_ref().map(|_| "<hook>"))
+            .field("on_panic", &self.on_panic.as_ref().map(|_| "<hook>"))
+            .field("recover_panics", &self.recover_panics)
+            .field("sensitive_headers", &self.sensitive_headers)
+            .finish()
+    }
+}
+
+impl Reporter {
+    pub(super) fn reports_error(&self) -> bool {
+        self.on_error.is_some()
+    }
+
+    pub(super) fn report_error(
+        &self,
+        request: &Request<()>,
+        pattern: Option<&str>,
+        status: StatusCode,
+        message: String,
+        extraction: Option<&ExtractionReport>,
+    ) {
+        if let Some(ref on_error) = self.on_error {
+            on_error(ErrorReport {
+                request: self.request_info(request, pattern),
+                status,
+                message,
+                extraction: extraction.cloned(),
+            });
+        }
+    }
+
+    pub(super) fn report_panic(
+        &self,
+        request: &Request<()>,
+        pattern: Option<&str>,
+        payload: &(dyn Any + Send),
+    ) {
+        if let Some(ref on_panic) = self.on_panic {
+            let payload = if let Some(s) = payload.downcast_ref::<&str>() {
+                (*s).to_owned()
+            } else if let Some(s) = payload.downcast_ref::<String>() {
+                s.clone()
+            } else {
+                String::from("Box<dyn Any>")
+            };
+            on_panic(PanicReport {
+                request: self.request_info(request, pattern),
+                payload,
+            });
+        }
+    }
+
+    fn request_info(&self, request: &Request<()>, pattern: Option<&str>) -> RequestInfo {
+        let mut headers = request.headers().clone();
+        for name in &self.sensitive_headers {
+            if let http::header::Entry::Occupied(mut entry) =
+                headers.entry(name).expect("valid header name")
+            {
+                entry.insert(HeaderValue::from_static("[redacted]"));
+            }
+        }
+        RequestInfo {
+            method: request.method().clone(),
+            path: request.uri().path().to_owned(),
+            pattern: pattern.map(ToOwned::to_owned),
+            request_id: request
+                .headers()
+                .get(REQUEST_ID)
+                .and_then(|value| value.to_str().ok())
+                .map(ToOwned::to_owned),
+            headers,
+        }
+    }
+}
+
+/// The information about the request, included in the reports.
+#[derive(Debug, Clone)]
+pub struct RequestInfo {
+    method: Method,
+    path: String,
+    pattern: Option<String>,
+    request_id: Option<String>,
+    headers: HeaderMap,
+}
+
+impl RequestInfo {
+    /// Returns the HTTP method of the request.
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    /// Returns the path of the request URI.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Returns the path pattern of the matched route, if any.
+    pub fn pattern(&self) -> Option<&str> {
+        self.pattern.as_ref().map(String::as_str)
+    }
+
+    /// Returns the value of `X-Request-Id` in the request, if any.
+    pub fn request_id(&self) -> Option<&str> {
+        self.request_id.as_ref().map(String::as_str)
+    }
+
+    /// Returns the header map of the request, whose sensitive values are redacted.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+}
+
+/// A report of the request that resulted in a server error.
+#[derive(Debug, Clone)]
+pub struct ErrorReport {
+    request: RequestInfo,
+    status: StatusCode,
+    message: String,
+    extraction: Option<ExtractionReport>,
+}
+
+impl ErrorReport {
+    /// Returns the information about the request.
+    pub fn request(&self) -> &RequestInfo {
+        &self.request
+    }
+
+    /// Returns the status code of the error response.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// Returns the message of the error, formatted with `Display`.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Returns the report of the extractors of the endpoint.
+    pub fn extraction(&self) -> Option<&ExtractionReport> {
+        self.extraction.as_ref()
+    }
+
+    /// Returns the label of the extractor that has caused the error, if any.
+    pub fn failed_extractor(&self) -> Option<&str> {
+        self.extraction
+            .as_ref()
+            .and_then(ExtractionReport::failed)
+            .map(|entry| entry.label())
+    }
+}
+
+/// A report of the request whose handler has panicked.
+#[derive(Debug, Clone)]
+pub struct PanicReport {
+    request: RequestInfo,
+    payload: String,
+}
+
+impl PanicReport {
+    /// Returns the information about the request.
+    pub fn request(&self) -> &RequestInfo {
+        &self.request
+    }
+
+    /// Returns the panic payload if it is a string, or `"Box<dyn Any>"` otherwise.
+    pub fn payload(&self) -> &str {
+        &self.payload
+    }
+}
+
+impl<C> AppBase<C>
+where
+    C: Concurrency,
+{
+    fn reporter_mut(&mut self) -> &mut Reporter {
+        &mut Arc::get_mut(&mut self.inner)
+            .expect("the application has already been shared")
+            .reporter
+    }
+
+    /// Registers the function called with the report of each request that resulted
+    /// in a server error (`5xx`).
+    ///
+    /// The function is called synchronously after the response is determined and
+    /// before it is returned, so it should be cheap, for example by sending the
+    /// report to a channel. The errors with other status codes, such as
+    /// `404 Not Found`, are not reported.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the application has already been cloned.
+    pub fn on_error(mut self, f: impl Fn(ErrorReport) + Send + Sync + 'static) -> Self {
+        self.reporter_mut().on_error = Some(Box::new(f));
+        self
+    }
+
+    /// Registers the function called with the report of each request whose handler has panicked.
+    ///
+    /// The function is called only if the panic recovery is enabled by `recover_panics`.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the application has already been cloned.
+    pub fn on_panic(mut self, f: impl Fn(PanicReport) + Send + Sync + 'static) -> Self {
+        self.reporter_mut().on_panic = Some(Box::new(f));
+        self
+    }
+
+    /// Sets whether to catch the panics during handling requests.
+    ///
+    /// If enabled, a panic in the handler is converted into `500 Internal Server Error`
+    /// instead of tearing down the connection.
+    ///
+    /// The default value is `false`.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the application has already been cloned.
+    pub fn recover_panics(mut self, enabled: bool) -> Self {
+        self.reporter_mut().recover_panics = enabled;
+        self
+    }
+
+    /// Sets the names of header fields whose values are redacted in the reports.
+    ///
+    /// The default value is `Authorization`, `Proxy-Authorization` and `Cookie`.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the application has already been cloned.
+    pub fn sensitive_headers(mut self, names: impl IntoIterator<Item = HeaderName>) -> Self {
+        self.reporter_mut().sensitive_headers = names.into_iter().collect();
+        self
+    }
+}
@@ -0,0 +1,84 @@
+use {
+    super::config::{Concurrency, Config, Scope},
+    crate::{
+        i18n::Args,
+        input::localmap::{local_key, LocalData},
+        util::Never,
+    },
+    http::{header::CONTENT_LENGTH, Request, StatusCode},
+};
+
+/// A configuration that limits the length of the request bodies declared by `Content-Length`.
+///
+/// The declared length is checked after the endpoint is selected and before it
+/// starts extracting, so no part of the body is read if it is rejected with
+/// `413 Payload Too Large`. The bodies without `Content-Length` are not checked.
+///
+/// The configuration is applied to the current scope and its descendants, and
+/// overridden by another `RequestSizeLimit` registered in a sub-scope, or by
+/// `endpoint::Builder::max_declared_length` for a specific endpoint.
+///
+/// ```
+/// # use tsukuyomi::{app::RequestSizeLimit, config::prelude::*, extractor, App};
+/// let app = App::create(chain![
+///     RequestSizeLimit::new(1024 * 1024),
+///     path!("/upload").to(endpoint::post()
+///         .max_declared_length(64 * 1024 * 1024)
+///         .extract(extractor::body::read_all())
+///         .call(|body: bytes::Bytes| format!("{} bytes", body.len()))),
+/// ]);
+/// # drop(app);
+/// ```
+#[derive(Debug, Clone)]
+pub struct RequestSizeLimit {
+    limit: u64,
+}
+
+impl RequestSizeLimit {
+    /// Creates a `RequestSizeLimit` with the specified maximum length in bytes.
+    pub fn new(limit: u64) -> Self {
+        Self { limit }
+    }
+
+    /// Returns the maximum length in bytes.
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+}
+
+impl<M, C> Config<M, C> for RequestSizeLimit
+where
+    C: Concurrency,
+{
+    type Error = Never;
+
+    fn configure(self, cx: &mut Scope<'_, M, C>) -> Result<(), Self::Error> {
+        cx.data_mut().request_size_limit = Some(self);
+        Ok(())
+    }
+}
+
+impl LocalData for RequestSizeLimit {
+    local_key! {
+        /// The local key for the limit applied to the scope of the matched route.
+        const KEY: Self;
+    }
+}
+
+/// Checks the length declared by `Content-Length` against the limit.
+pub(crate) fn check_declared_length(request: &Request<()>, limit: u64) -> crate::Result<()> {
+    let declared = request
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.trim().parse::<u64>().ok());
+    match declared {
+        Some(length) if length > limit => Err(crate::error::localized(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "error.body.too_large",
+            Args::new().arg("limit", limit),
+            format_args!("the body exceeds the size limit (limit: {})", limit),
+        )),
+        _ => Ok(()),
+    }
+}
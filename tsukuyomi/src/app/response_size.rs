@@ -0,0 +1,173 @@
+use {
+    super::{
+        config::{Concurrency, Config, Scope},
+        Tag,
+    },
+    crate::{output::ResponseBody, util::Never},
+    bytes::Bytes,
+    futures01::{Async, Poll, Stream},
+    http::{Method, Response},
+    hyper::body::Payload,
+    std::{fmt, io},
+};
+
+/// A configuration that limits the size of the response bodies.
+///
+/// The size of a buffered body is determined without reading it. If it exceeds
+/// the limit, the response is replaced with a `500 Internal Server Error`. A
+/// streaming body is cut off at the limit, and then the connection is aborted
+/// since the response can no longer be completed. In both cases, a line of the
+/// following form is logged at the level `ERROR`:
+///
+/// ```text
+/// response size limit exceeded: method=GET pattern=/posts limit=1048576 size=2097152
+/// ```
+///
+/// For a streaming body, `size` is the number of bytes produced until the limit is detected.
+///
+/// The configuration is applied to the current scope and its descendants, and
+/// overridden by another `ResponseSizeLimit` registered in a sub-scope. The routes
+/// with one of the exempted tags, such as file downloads, are never limited.
+#[derive(Debug, Clone)]
+pub struct ResponseSizeLimit {
+    limit: u64,
+    exempt_tags: Vec<Tag>,
+}
+
+impl ResponseSizeLimit {
+    /// Creates a `ResponseSizeLimit` with the specified maximum size in bytes.
+    pub fn new(limit: u64) -> Self {
+        Self {
+            limit,
+            exempt_tags: vec![],
+        }
+    }
+
+    /// Exempts the routes attached with the specified tag from the limit.
+    ///
+    /// See also `Route::tag`.
+    pub fn exempt_tag(mut self, tag: impl Into<Tag>) -> Self {
+        self.exempt_tags.push(tag.into());
+        self
+    }
+
+    pub(super) fn is_exempted(&self, tags: &[Tag]) -> bool {
+        tags.iter().any(|tag| self.exempt_tags.contains(tag))
+    }
+
+    /// Applies the limit to the response.
+    ///
+    /// It returns an `Err` if the buffered body exceeds the limit, and wraps the
+    /// streaming body so that it is cut off at the limit.
+    pub(super) fn apply(
+        &self,
+        output: &mut Response<ResponseBody>,
+        audit: Audit,
+    ) -> Result<(), crate::Error> {
+        match output.body().content_length() {
+            Some(size) if size > self.limit => {
+                audit.report(self.limit, size);
+                Err(crate::error::internal_server_error(
+                    "the response body exceeds the size limit",
+                ))
+            }
+            Some(..) => Ok(()),
+            None => {
+                let body = std::mem::replace(output.body_mut(), ResponseBody::empty());
+                *output.body_mut() = ResponseBody::wrap_stream(LimitedBody {
+                    body,
+                    limit: self.limit,
+                    size: 0,
+                    audit: Some(audit),
+                });
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<M, C> Config<M, C> for ResponseSizeLimit
+where
+    C: Concurrency,
+{
+    type Error = Never;
+
+    fn configure(self, cx: &mut Scope<'_, M, C>) -> Result<(), Self::Error> {
+        cx.data_mut().response_size_limit = Some(self);
+        Ok(())
+    }
+}
+
+/// The information about a request, logged when its response exceeds the limit.
+#[derive(Debug)]
+pub(super) struct Audit {
+    pub(super) method: Method,
+    pub(super) pattern: Option<String>,
+}
+
+impl Audit {
+    fn report(&self, limit: u64, size: u64) {
+        log::error!(
+            "response size limit exceeded: method={} pattern={} limit={} size={}",
+            self.method,
+            self.pattern.as_ref().map_or("-", |pattern| &**pattern),
+            limit,
+            size,
+        );
+    }
+}
+
+/// A streaming body that fails after producing the bytes up to the limit.
+struct LimitedBody {
+    body: ResponseBody,
+    limit: u64,
+    size: u64,
+    // Taken when the limit is exceeded.
+    audit: Option<Audit>,
+}
+
+impl fmt::Debug for LimitedBody {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LimitedBody")
+            .field("limit", &self.limit)
+            .field("size", &self.size)
+            .finish()
+    }
+}
+
+impl Stream for LimitedBody {
+    type Item = Bytes;
+    type Error = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        if self.audit.is_none() {
+            return Err(exceeded());
+        }
+        if self.size > self.limit {
+            let audit = self.audit.take().expect("never fails");
+            audit.report(self.limit, self.size);
+            return Err(exceeded());
+        }
+
+        let mut chunk = match futures01::try_ready!(self.body.poll_data()) {
+            Some(chunk) => chunk.into_bytes(),
+            None => return Ok(Async::Ready(None)),
+        };
+        let remaining = self.limit - self.size;
+        self.size += chunk.len() as u64;
+        if self.size > self.limit {
+            // Send the bytes up to the limit, and fail at the next poll.
+            #[allow(clippy::cast_possible_truncation)]
+            chunk.truncate(remaining as usize);
+        }
+        Ok(Async::Ready(Some(chunk)))
+    }
+}
+
+fn exceeded() -> Box<dyn std::error::Error + Send + Sync + 'static> {
+    io::Error::new(
+        io::ErrorKind::Other,
+        "the response body exceeds the size limit",
+    )
+    .into()
+}
@@ -0,0 +1,66 @@
+use {
+    super::{config::Concurrency, scope::ScopeId, AppInner},
+    crate::handler::AllowedMethods,
+    std::fmt,
+};
+
+/// The lookup of the registered routes, used for suggesting the intended path of
+/// the request which matched no route.
+pub(crate) trait Routes: fmt::Debug {
+    /// Returns the URI patterns that should be compared with the specified path.
+    ///
+    /// The patterns are restricted to the ones registered in the scope which
+    /// the request belongs to, or sharing the first segment with the path.
+    fn candidates(&self, path: &str) -> Vec<&str>;
+
+    /// Returns the union of the methods accepted by all routes in the application
+    /// other than `OPTIONS *`, in order of registration.
+    ///
+    /// The routes accepting any method are not taken into account.
+    fn methods(&self) -> AllowedMethods;
+}
+
+pub(super) struct ScopeRoutes<'a, C: Concurrency> {
+    pub(super) inner: &'a AppInner<C>,
+    pub(super) scope: ScopeId,
+}
+
+impl<'a, C: Concurrency> fmt::Debug for ScopeRoutes<'a, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScopeRoutes")
+            .field("scope", &self.scope)
+            .finish()
+    }
+}
+
+impl<'a, C: Concurrency> Routes for ScopeRoutes<'a, C> {
+    fn candidates(&self, path: &str) -> Vec<&str> {
+        let first = first_segment(path);
+        self.inner
+            .recognizer
+            .values()
+            .filter(|endpoint| {
+                endpoint.scope == self.scope || first_segment(endpoint.uri.as_str()) == first
+            })
+            .map(|endpoint| endpoint.uri.as_str())
+            .collect()
+    }
+
+    fn methods(&self) -> AllowedMethods {
+        let mut methods: AllowedMethods = None.into_iter().collect();
+        for endpoint in self.inner.recognizer.values().filter(|e| e.uri != "*") {
+            let mut current = Some(&**endpoint);
+            while let Some(endpoint) = current {
+                if let Some(ref accepted) = endpoint.methods {
+                    methods.extend(accepted.iter().cloned());
+                }
+                current = endpoint.overridden.as_ref().map(|e| &**e);
+            }
+        }
+        methods
+    }
+}
+
+fn first_segment(path: &str) -> &str {
+    path.trim_start_matches('/').split('/').next().unwrap_or("")
+}
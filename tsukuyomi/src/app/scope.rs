@@ -4,7 +4,7 @@ use {
 };
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
-pub(super) struct ScopeId {
+pub(crate) struct ScopeId {
     inner: ScopeIdInner,
 }
 
@@ -58,6 +58,10 @@ impl<T> Scopes<T> {
 
         Ok(id)
     }
+
+    pub(super) fn iter(&self) -> impl Iterator<Item = &Scope<T>> {
+        Some(&self.root).into_iter().chain(&self.nodes)
+    }
 }
 
 impl<T> Index<ScopeId> for Scopes<T> {
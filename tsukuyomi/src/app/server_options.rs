@@ -0,0 +1,153 @@
+use {
+    super::config::{Concurrency, Config, Error, Scope},
+    crate::{
+        future::{Async, Poll, TryFuture},
+        handler::{AllowedMethods, Handler, ModifyHandler},
+        input::Input,
+        output::ResponseBody,
+    },
+    http::{
+        header::{HeaderMap, HeaderName, HeaderValue, ALLOW, LINK, SERVER},
+        Method, Response, StatusCode,
+    },
+    std::sync::Arc,
+};
+
+/// A `Config` that registers the route for `OPTIONS *`, created by `config::server_options`.
+///
+/// The route replies `200 OK` with the header field `Allow` listing the union of
+/// the methods accepted by the routes registered anywhere in the application.
+/// The routes accepting any method are not taken into account.
+///
+/// ```
+/// # use tsukuyomi::{config::prelude::*, App};
+/// let app = App::create(chain![
+///     tsukuyomi::config::server_options()
+///         .server("example/1.0")
+///         .api_description("/openapi.json"),
+///     path!("/posts").to(chain![
+///         endpoint::get().reply("list"),
+///         endpoint::post().reply("create"),
+///     ]),
+/// ]);
+/// # drop(app);
+/// ```
+#[derive(Debug, Default)]
+pub struct ServerOptions {
+    server: Option<String>,
+    api_description: Option<String>,
+    headers: Vec<(HeaderName, String)>,
+}
+
+impl ServerOptions {
+    /// Sets the value of the header field `Server`.
+    pub fn server(self, server: impl Into<String>) -> Self {
+        Self {
+            server: Some(server.into()),
+            ..self
+        }
+    }
+
+    /// Sets the URI of the API description, which is advertised with the header field
+    /// `Link` of the relation type `service-desc`.
+    pub fn api_description(self, uri: impl Into<String>) -> Self {
+        Self {
+            api_description: Some(uri.into()),
+            ..self
+        }
+    }
+
+    /// Appends a header field to the response.
+    pub fn header(mut self, name: HeaderName, value: impl Into<String>) -> Self {
+        self.headers.push((name, value.into()));
+        self
+    }
+
+    fn to_headers(&self) -> Result<HeaderMap, Error> {
+        fn parse(value: &str) -> Result<HeaderValue, Error> {
+            HeaderValue::from_str(value).map_err(Error::custom)
+        }
+
+        let mut headers = HeaderMap::new();
+        if let Some(ref server) = self.server {
+            headers.insert(SERVER, parse(server)?);
+        }
+        if let Some(ref uri) = self.api_description {
+            headers.append(LINK, parse(&format!("<{}>; rel=\"service-desc\"", uri))?);
+        }
+        for (name, value) in &self.headers {
+            headers.append(name.clone(), parse(value)?);
+        }
+        Ok(headers)
+    }
+}
+
+impl<M, C> Config<M, C> for ServerOptions
+where
+    M: ModifyHandler<ServerOptionsHandler>,
+    M::Handler: Into<C::Handler>,
+    C: Concurrency,
+{
+    type Error = Error;
+
+    fn configure(self, scope: &mut Scope<'_, M, C>) -> Result<(), Self::Error> {
+        let headers = self.to_headers()?;
+        scope.asterisk_route(ServerOptionsHandler {
+            headers: Arc::new(headers),
+            methods: AllowedMethods::from(Method::OPTIONS),
+        })
+    }
+}
+
+/// The handler of `OPTIONS *` registered by `ServerOptions`.
+#[derive(Debug)]
+pub struct ServerOptionsHandler {
+    headers: Arc<HeaderMap>,
+    methods: AllowedMethods,
+}
+
+impl Handler for ServerOptionsHandler {
+    type Output = Response<ResponseBody>;
+    type Error = crate::Error;
+    type Handle = HandleServerOptions; // private
+
+    fn allowed_methods(&self) -> Option<&AllowedMethods> {
+        Some(&self.methods)
+    }
+
+    fn handle(&self) -> Self::Handle {
+        HandleServerOptions {
+            headers: self.headers.clone(),
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct HandleServerOptions {
+    headers: Arc<HeaderMap>,
+}
+
+impl TryFuture for HandleServerOptions {
+    type Ok = Response<ResponseBody>;
+    type Error = crate::Error;
+
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        if input.request.method() != Method::OPTIONS {
+            return Err(StatusCode::METHOD_NOT_ALLOWED.into());
+        }
+
+        let mut methods = input.routes.methods();
+        methods.extend(Some(Method::OPTIONS));
+
+        let mut response = Response::new(ResponseBody::empty());
+        response.headers_mut().extend(
+            self.headers
+                .iter()
+                .map(|(name, value)| (name.clone(), value.clone())),
+        );
+        response
+            .headers_mut()
+            .insert(ALLOW, methods.to_header_value());
+        Ok(Async::Ready(response))
+    }
+}
@@ -1,23 +1,48 @@
 use {
-    super::{config::Concurrency, recognizer::Captures, AppInner, Endpoint},
+    super::{
+        canonical::Decision,
+        config::Concurrency,
+        header_limits::HeaderLimitExceeded,
+        lifecycle::InFlight,
+        locale_prefix::Resolution,
+        log_override::{self, ActiveOverride},
+        recognizer::Captures,
+        response_size::Audit,
+        routes::ScopeRoutes,
+        scope::ScopeId,
+        slow_request::{Record, SlowRequestLog},
+        state::{ScopeStates, States},
+        AppInner, Endpoint,
+    },
     crate::{
+        error::HttpError,
+        extractor::{report::ExtractionReport, vnd::MediaType},
+        i18n::{Locale, SharedTranslator},
         input::{
             body::RequestBody,
             localmap::{LocalData, LocalMap},
-            param::Params,
-            Cookies, Input,
+            param::{MatrixSegments, Params},
+            response::ResponseHeaders,
+            CookieState, Cookies, Input,
         },
-        output::ResponseBody,
+        output::{problem::Problem, IntoResponse, ResponseBody},
         util::Never,
     },
-    cookie::CookieJar,
     futures01::{Async, Future, Poll},
     http::{
-        header::{self, HeaderMap, HeaderValue},
+        header::{self, HeaderValue},
         Request, Response,
     },
     hyper::body::Payload,
-    std::{fmt, marker::PhantomData, sync::Arc},
+    std::{
+        any::TypeId,
+        fmt::{self, Write},
+        marker::PhantomData,
+        mem,
+        panic::{self, AssertUnwindSafe},
+        sync::Arc,
+        time::{Duration, Instant},
+    },
     tsukuyomi_service::Service,
 };
 
@@ -53,21 +78,11 @@ where
 
     #[inline]
     fn call(&mut self, request: Request<Bd>) -> Self::Future {
-        let (parts, body) = request.into_parts();
-
-        let mut locals = LocalMap::default();
-        RequestBody::from(body).insert_into(&mut locals);
-
-        AppFuture {
-            request: Request::from_parts(parts, ()),
-            inner: self.inner.clone(),
-            cookie_jar: None,
-            response_headers: None,
-            locals,
-            endpoint: None,
-            captures: None,
-            state: AppFutureState::Init,
-        }
+        AppFuture::new(
+            self.inner.clone(),
+            request.map(RequestBody::from),
+            LocalMap::default(),
+        )
     }
 }
 
@@ -76,15 +91,36 @@ where
 #[derive(Debug)]
 pub struct AppFuture<C: Concurrency> {
     request: Request<()>,
+    // Held while the request is being handled, so that the shutdown can wait for it.
+    _in_flight: InFlight,
     inner: Arc<AppInner<C>>,
-    cookie_jar: Option<CookieJar>,
-    response_headers: Option<HeaderMap>,
+    cookies: CookieState,
+    response_headers: ResponseHeaders,
     locals: LocalMap,
     endpoint: Option<Arc<Endpoint<C>>>,
     captures: Option<Captures>,
+    stripped_path: Option<String>,
+    scope_id: ScopeId,
+    timing: Option<Timing>,
+    log_override: Option<ActiveOverride>,
+    hsts: Option<HeaderValue>,
     state: AppFutureState<C>,
 }
 
+/// The timing information of the request, recorded only if `SlowRequestLog` is applied.
+#[derive(Debug)]
+struct Timing {
+    log: SlowRequestLog,
+    started: Instant,
+    recognize: Duration,
+}
+
+/// The result of the routing.
+enum Recognized<C: Concurrency> {
+    Handle(C::Handle),
+    Redirect(Response<ResponseBody>),
+}
+
 enum AppFutureState<C: Concurrency> {
     Init,
     InFlight(C::Handle),
@@ -108,7 +144,10 @@ macro_rules! input {
             params: {
                 &if let Some(ref endpoint) = $self.endpoint {
                     Some(Params {
-                        path: $self.request.uri().path(),
+                        path: $self
+                            .stripped_path
+                            .as_ref()
+                            .map_or($self.request.uri().path(), String::as_str),
                         names: endpoint.uri.capture_names(),
                         captures: $self.captures.as_ref(),
                     })
@@ -116,49 +155,403 @@ macro_rules! input {
                     None
                 }
             },
-            cookies: &mut Cookies::new(&mut $self.cookie_jar, &$self.request),
+            cookies: &mut Cookies::new(&mut $self.cookies, &$self.request),
             locals: &mut $self.locals,
             response_headers: &mut $self.response_headers,
+            states: &ScopeStates {
+                inner: &*$self.inner,
+                scope: $self.scope_id,
+            },
+            routes: &ScopeRoutes {
+                inner: &*$self.inner,
+                scope: $self.scope_id,
+            },
+            dispatch: &$self.inner,
+            clock: &$self.inner.clock,
+            random: &*$self.inner.random,
             _marker: PhantomData,
         }
     };
 }
 
 impl<C: Concurrency> AppFuture<C> {
-    fn process_recognize(&mut self) -> Result<C::Handle, crate::Error> {
+    pub(super) fn new(
+        inner: Arc<AppInner<C>>,
+        request: Request<RequestBody>,
+        mut locals: LocalMap,
+    ) -> Self {
+        let (parts, body) = request.into_parts();
+        body.insert_into(&mut locals);
+
+        Self {
+            request: Request::from_parts(parts, ()),
+            _in_flight: inner.drain.enter(),
+            inner,
+            cookies: CookieState::default(),
+            response_headers: ResponseHeaders::default(),
+            locals,
+            endpoint: None,
+            captures: None,
+            stripped_path: None,
+            scope_id: ScopeId::root(),
+            timing: None,
+            log_override: None,
+            hsts: None,
+            state: AppFutureState::Init,
+        }
+    }
+
+    /// Checks the request against `CanonicalHost`, and creates the redirect response if necessary.
+    fn process_canonical_host(&mut self) -> Option<Response<ResponseBody>> {
+        let canonical_host = self.inner.canonical_host.as_ref()?;
+        let trusted = self
+            .inner
+            .scope(ScopeId::root())
+            .data
+            .trusted_proxies
+            .as_ref();
+        match canonical_host.check(&self.request, trusted) {
+            Decision::Pass(hsts) => {
+                self.hsts = hsts.cloned();
+                None
+            }
+            Decision::Redirect(location) => Some(
+                crate::output::redirect::moved_permanently(location)
+                    .into_response(&self.request)
+                    .unwrap_or_else(|never| match never {})
+                    .map(Into::into),
+            ),
+        }
+    }
+
+    fn process_recognize(&mut self) -> Result<Recognized<C>, crate::Error> {
         self.endpoint = None;
         self.captures = None;
+        self.stripped_path = None;
+
+        let started = self.inner.clock.now();
+        if self.inner.matrix_params {
+            if let Some((path, segments)) = MatrixSegments::strip(self.request.uri().path()) {
+                self.stripped_path = Some(path);
+                segments.insert_into(&mut self.locals);
+            }
+        }
+        let path = self
+            .stripped_path
+            .as_ref()
+            .map_or(self.request.uri().path(), String::as_str);
+
+        // The locale prefix is removed only if the route belongs to the localized scope.
+        let mut localized = None;
+        if let Some((prefixed, stripped)) = self.inner.strip_locale_prefix(path) {
+            let in_scope = match self.inner.find_endpoint(&stripped, &mut None) {
+                Ok(endpoint) => self.inner.is_localized_by(endpoint, &prefixed),
+                Err(..) => true,
+            };
+            if in_scope {
+                self.stripped_path = Some(stripped);
+                localized = Some(prefixed);
+            }
+        }
+        let path = self
+            .stripped_path
+            .as_ref()
+            .map_or(self.request.uri().path(), String::as_str);
+        let found = self.inner.find_endpoint(path, &mut self.captures);
 
-        match self
+        if localized.is_none() {
+            if let Ok(endpoint) = found {
+                let endpoint = endpoint.resolve(self.request.method());
+                match self.inner.resolve_unprefixed(endpoint, &self.request, path) {
+                    Some(Resolution::Rewrite(negotiated)) => {
+                        self.response_headers
+                            .raw()
+                            .append(header::VARY, HeaderValue::from_static("accept-language"));
+                        localized = Some(negotiated);
+                    }
+                    Some(Resolution::Redirect(response)) => {
+                        return Ok(Recognized::Redirect(response))
+                    }
+                    None => {}
+                }
+            }
+        }
+        if let Some(localized) = localized {
+            localized.locale().clone().insert_into(&mut self.locals);
+            localized.insert_into(&mut self.locals);
+        }
+
+        let scope_id = match found {
+            Ok(endpoint) => endpoint.resolve(self.request.method()).scope,
+            Err(scope) => scope.id(),
+        };
+        self.scope_id = scope_id;
+        if let Some(proxies) = self
             .inner
-            .find_endpoint(self.request.uri().path(), &mut self.captures)
+            .find_scope_config(scope_id, |data| data.trusted_proxies.as_ref())
         {
+            proxies.clone().insert_into(&mut self.locals);
+        }
+        if let Some(limit) = self
+            .inner
+            .find_scope_config(scope_id, |data| data.request_size_limit.as_ref())
+        {
+            limit.clone().insert_into(&mut self.locals);
+        }
+        if let Some(buffering) = self
+            .inner
+            .find_scope_config(scope_id, |data| data.body_buffering.as_ref())
+        {
+            buffering.clone().insert_into(&mut self.locals);
+        }
+        self.timing = self
+            .inner
+            .find_scope_config(scope_id, |data| data.slow_request_log.as_ref())
+            .map(|log| Timing {
+                log: log.clone(),
+                started,
+                recognize: self.inner.clock.now().duration_since(started),
+            });
+        self.log_override = self
+            .inner
+            .find_scope_config(scope_id, |data| data.log_override.as_ref())
+            .and_then(|config| {
+                let pattern = found.as_ref().ok().map(|endpoint| endpoint.uri.as_str());
+                config.resolve(pattern, started)
+            });
+        if let Some(ref log_override) = self.log_override {
+            if let Some(body) = RequestBody::take_from(&mut self.locals) {
+                log_override
+                    .capture_body(body)
+                    .insert_into(&mut self.locals);
+            }
+        }
+        if self.inner.reporter.reports_error()
+            || self.timing.is_some()
+            || self.log_override.is_some()
+        {
+            ExtractionReport::default().insert_into(&mut self.locals);
+        }
+
+        match found {
             Ok(endpoint) => {
                 self.endpoint = Some(endpoint.clone());
-                Ok(C::handle(&endpoint.handler))
+                Ok(Recognized::Handle(C::handle(
+                    &endpoint.resolve(self.request.method()).handler,
+                )))
             }
             Err(scope) => match self.inner.find_default_handler(scope.id()) {
-                Some(fallback) => Ok(C::handle(fallback)),
+                Some(fallback) => Ok(Recognized::Handle(C::handle(fallback))),
                 None => Err(http::StatusCode::NOT_FOUND.into()),
             },
         }
     }
 
-    fn process_before_reply(&mut self, output: &mut Response<ResponseBody>) {
-        // append Cookie entries.
-        if let Some(ref jar) = self.cookie_jar {
-            for cookie in jar.delta() {
-                output.headers_mut().append(
-                    header::SET_COOKIE,
-                    cookie.encoded().to_string().parse().unwrap(),
-                );
+    fn report_timing(&self, output: &Response<ResponseBody>) {
+        if let Some(ref timing) = self.timing {
+            timing.log.report(&Record {
+                method: self.request.method(),
+                pattern: self.pattern(),
+                status: output.status(),
+                started: timing.started,
+                finished: self.inner.clock.now(),
+                recognize: timing.recognize,
+                forced: self.log_override.is_some(),
+                extraction: ExtractionReport::get(&self.locals),
+            });
+        }
+    }
+
+    fn log_record<'a>(&'a self, output: &Response<ResponseBody>) -> log_override::Record<'a> {
+        log_override::Record {
+            method: self.request.method(),
+            path: self.request.uri().path(),
+            pattern: self.pattern(),
+            headers: self.request.headers(),
+            status: output.status(),
+            finished: self.inner.clock.now(),
+            extraction: ExtractionReport::get(&self.locals),
+        }
+    }
+
+    fn poll_handler(&mut self) -> Poll<Response<ResponseBody>, crate::Error> {
+        loop {
+            self.state = match self.state {
+                AppFutureState::Init => {
+                    if let Some(ref header_limits) = self.inner.header_limits {
+                        header_limits.check(&self.request)?;
+                    }
+                    if let Some(redirect) = self.process_canonical_host() {
+                        return Ok(Async::Ready(redirect));
+                    }
+                    match self.process_recognize()? {
+                        Recognized::Handle(handle) => AppFutureState::InFlight(handle),
+                        Recognized::Redirect(redirect) => return Ok(Async::Ready(redirect)),
+                    }
+                }
+                AppFutureState::InFlight(ref mut in_flight) => {
+                    return C::poll_ready(in_flight, input!(self));
+                }
+                AppFutureState::Done => panic!("the future has already polled."),
+            };
+        }
+    }
+
+    fn pattern(&self) -> Option<&str> {
+        self.endpoint.as_ref().map(|endpoint| endpoint.uri.as_str())
+    }
+
+    fn render_error(&self, err: crate::Error) -> Response<ResponseBody> {
+        // `405 Method Not Allowed` returned by the matched route is rendered
+        // with the methods that the route accepts.
+        if err.downcast_ref::<http::StatusCode>() == Some(&http::StatusCode::METHOD_NOT_ALLOWED) {
+            if let Some(allowed_methods) = self
+                .endpoint
+                .as_ref()
+                .and_then(|endpoint| endpoint.allowed_methods())
+            {
+                if self.inner.problem_details {
+                    let mut response = Problem::method_not_allowed(&allowed_methods)
+                        .into_response(&self.request)
+                        .map(Into::into);
+                    response
+                        .headers_mut()
+                        .insert(header::ALLOW, allowed_methods.to_header_value());
+                    return response;
+                }
+                return (self.inner.method_not_allowed)(&self.request, &allowed_methods);
+            }
+        }
+        if self.inner.problem_details {
+            return crate::output::problem::render(err, &self.request).map(Into::into);
+        }
+        if let Some(ref header_limits) = self.inner.header_limits {
+            if let Some(exceeded) = err.downcast_ref::<HeaderLimitExceeded>() {
+                if let Some(response) = header_limits.render_error(&self.request, exceeded) {
+                    return response;
+                }
+            }
+        }
+        let translated = self.translate_error(&err);
+        let mut response = err.into_response(&self.request);
+        if let Some((locale, text)) = translated {
+            let headers = response.headers_mut();
+            headers.insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("text/plain; charset=utf-8"),
+            );
+            headers.remove(header::CONTENT_LENGTH);
+            if let Ok(value) = HeaderValue::from_str(locale.as_str()) {
+                headers.insert(header::CONTENT_LANGUAGE, value);
             }
+            *response.body_mut() = text.into();
         }
+        response
+    }
+
+    /// Translates the message of the error with the `Translator` registered in the scope.
+    ///
+    /// The locale negotiated by the extractor is preferred to the one in `Accept-Language`.
+    fn translate_error(&self, err: &crate::Error) -> Option<(Locale, String)> {
+        let key = err.message_key()?;
+        let states = ScopeStates {
+            inner: &*self.inner,
+            scope: self.scope_id,
+        };
+        let translator = states
+            .get(TypeId::of::<SharedTranslator>())?
+            .downcast_ref::<SharedTranslator>()?;
+        let locale = Locale::get(&self.locals)
+            .cloned()
+            .or_else(|| Locale::preferred(self.request.headers()))?;
+        let text = translator.translate(key, &locale, &err.message_args())?;
+        Some((locale, text))
+    }
 
-        // append supplemental response headers.
-        if let Some(mut hdrs) = self.response_headers.take() {
-            for (k, v) in hdrs.drain() {
-                output.headers_mut().extend(v.map(|v| (k.clone(), v)));
+    /// Applies the `ModifyResponse`s registered in the scope of the request and its ancestors,
+    /// from the innermost one.
+    fn process_response_hooks(&self, output: &mut Response<ResponseBody>, is_error: bool) {
+        let scope = self.inner.scope(self.scope_id);
+        let scopes = Some(scope.id())
+            .into_iter()
+            .chain(scope.ancestors().iter().rev().cloned());
+        for id in scopes {
+            for hook in &self.inner.scope(id).data.response_hooks {
+                hook.apply(&self.request, output, is_error);
+            }
+        }
+    }
+
+    /// Applies the `ResponseSizeLimit` registered in the scope of the request, unless
+    /// the matched route is exempted.
+    fn process_response_size_limit(
+        &self,
+        output: &mut Response<ResponseBody>,
+    ) -> Result<(), crate::Error> {
+        let limit = match self
+            .inner
+            .find_scope_config(self.scope_id, |data| data.response_size_limit.as_ref())
+        {
+            Some(limit) => limit,
+            None => return Ok(()),
+        };
+        if let Some(ref endpoint) = self.endpoint {
+            if limit.is_exempted(&endpoint.resolve(self.request.method()).tags) {
+                return Ok(());
+            }
+        }
+        limit.apply(
+            output,
+            Audit {
+                method: self.request.method().clone(),
+                pattern: self.pattern().map(ToOwned::to_owned),
+            },
+        )
+    }
+
+    fn process_before_reply(&mut self, output: &mut Response<ResponseBody>, mut is_error: bool) {
+        if let Err(err) = self.process_response_size_limit(output) {
+            *output = self.render_error(err);
+            is_error = true;
+        }
+
+        // append Cookie entries, merged from the layers of the jar.
+        // The buffer is shared among the cookies to avoid reallocating it for each one.
+        let mut buf: Option<String> = None;
+        self.cookies.for_each_delta(|cookie| {
+            let buf = buf.get_or_insert_with(|| String::with_capacity(128));
+            buf.clear();
+            let _ = write!(buf, "{}", cookie.encoded());
+            output
+                .headers_mut()
+                .append(header::SET_COOKIE, buf.parse().unwrap());
+        });
+
+        // merge the header fields staged by the components.
+        self.response_headers.merge_into(output.headers_mut());
+
+        // send the JSON responses with the vendor media type negotiated by `extractor::vnd`.
+        if !is_error {
+            if let Some(media_type) = MediaType::get(&self.locals) {
+                media_type.apply(output);
+            }
+        }
+
+        self.process_response_hooks(output, is_error);
+
+        if let Some(hsts) = self.hsts.take() {
+            output
+                .headers_mut()
+                .entry(header::STRICT_TRANSPORT_SECURITY)
+                .expect("never fails")
+                .or_insert(hsts);
+        }
+
+        // normalize the header fields set by the handlers.
+        if let Some(ref hygiene) = self.inner.header_hygiene {
+            if let Err(err) = hygiene.apply(output, &*self.inner.clock) {
+                *output = self.render_error(err);
+                let _ = hygiene.apply(output, &*self.inner.clock);
             }
         }
 
@@ -168,11 +561,7 @@ impl<C: Concurrency> AppFuture<C> {
                 .headers_mut()
                 .entry(header::CONTENT_LENGTH)
                 .expect("never fails")
-                .or_insert_with(|| {
-                    // safety: '0'-'9' is ascii.
-                    // TODO: more efficient
-                    unsafe { HeaderValue::from_shared_unchecked(len.to_string().into()) }
-                });
+                .or_insert_with(|| HeaderValue::from(len));
         }
     }
 }
@@ -182,26 +571,64 @@ impl<C: Concurrency> Future for AppFuture<C> {
     type Error = Never;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        let polled = loop {
-            self.state = match self.state {
-                AppFutureState::Init => match self.process_recognize() {
-                    Ok(in_flight) => AppFutureState::InFlight(in_flight),
-                    Err(err) => break Err(err),
-                },
-                AppFutureState::InFlight(ref mut in_flight) => {
-                    break ready!(C::poll_ready(in_flight, input!(self)));
+        let mut panicked = false;
+        let polled = if self.inner.reporter.recover_panics {
+            match panic::catch_unwind(AssertUnwindSafe(|| self.poll_handler())) {
+                Ok(polled) => ready!(polled),
+                Err(payload) => {
+                    self.inner
+                        .reporter
+                        .report_panic(&self.request, self.pattern(), &*payload);
+                    panicked = true;
+                    Err(crate::error::internal_server_error(
+                        "the handler has panicked",
+                    ))
                 }
-                AppFutureState::Done => panic!("the future has already polled."),
-            };
+            }
+        } else {
+            ready!(self.poll_handler())
         };
         self.state = AppFutureState::Done;
 
+        let is_error = polled.is_err();
         let mut output = match polled {
             Ok(output) => output,
-            Err(err) => err.into_response(&self.request),
+            Err(err) => {
+                let reports_error =
+                    self.inner.reporter.reports_error() || self.log_override.is_some();
+                let message = if reports_error && !panicked {
+                    Some(err.to_string())
+                } else {
+                    None
+                };
+                let output = self.render_error(err);
+                if let Some(message) = message {
+                    if let Some(ref log_override) = self.log_override {
+                        log_override.log_error(&self.log_record(&output), &message);
+                    }
+                    if self.inner.reporter.reports_error() && output.status().is_server_error() {
+                        self.inner.reporter.report_error(
+                            &self.request,
+                            self.pattern(),
+                            output.status(),
+                            message,
+                            ExtractionReport::get(&self.locals),
+                        );
+                    }
+                }
+                output
+            }
         };
 
-        self.process_before_reply(&mut output);
+        self.process_before_reply(&mut output, is_error);
+        self.report_timing(&output);
+        if let Some(ref log_override) = self.log_override {
+            log_override.log_access(&self.log_record(&output));
+        }
+
+        // The local data is dropped after the response body is sent.
+        let locals = mem::replace(&mut self.locals, LocalMap::default());
+        output.body_mut().attach_locals(locals);
 
         Ok(Async::Ready(output))
     }
@@ -0,0 +1,122 @@
+use {
+    super::config::{Concurrency, Config, Scope},
+    crate::{extractor::report::ExtractionReport, util::Never},
+    http::{Method, StatusCode},
+    std::{
+        fmt,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::{Duration, Instant},
+    },
+};
+
+/// A configuration that emits a warning for each request which takes longer than a threshold.
+///
+/// The elapsed time is measured from the start of routing to the completion of the
+/// response, including the requests that resulted in an error or were not matched
+/// to any route. When it exceeds the threshold, a line of the following form is
+/// logged at the level `WARN`:
+///
+/// ```text
+/// slow request: method=GET pattern=/posts/:id status=200 elapsed=1.2s recognize=15µs handle=1.2s
+/// ```
+///
+/// The pattern is `-` if no route is matched to the request. If the endpoint has
+/// extractors, the outcomes and the durations of them are appended as
+/// `extractors=[#1 ok 10µs, json-body ok 1.1s]`. See `extractor::report` for details.
+///
+/// The configuration is applied to the current scope and its descendants, and
+/// overridden by another `SlowRequestLog` registered in a sub-scope.
+#[derive(Debug, Clone)]
+pub struct SlowRequestLog {
+    threshold: Duration,
+    sample_rate: f64,
+    offenders: Arc<AtomicUsize>,
+}
+
+impl SlowRequestLog {
+    /// Creates a `SlowRequestLog` with the specified threshold.
+    pub fn new(threshold: Duration) -> Self {
+        Self {
+            threshold,
+            sample_rate: 1.0,
+            offenders: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Sets the ratio of slow requests to be logged, in the range `0.0..=1.0`.
+    ///
+    /// The default value is `1.0`, which means that all slow requests are logged.
+    pub fn sample_rate(self, sample_rate: f64) -> Self {
+        Self {
+            sample_rate: sample_rate.max(0.0).min(1.0),
+            ..self
+        }
+    }
+
+    /// Determines whether the current offender is logged, according to the sample rate.
+    #[allow(clippy::cast_precision_loss)]
+    fn sampled(&self) -> bool {
+        if self.sample_rate >= 1.0 {
+            return true;
+        }
+        let n = self.offenders.fetch_add(1, Ordering::Relaxed) as f64;
+        ((n + 1.0) * self.sample_rate).floor() > (n * self.sample_rate).floor()
+    }
+
+    pub(super) fn report(&self, record: &Record<'_>) {
+        let elapsed = record.finished.duration_since(record.started);
+        if elapsed <= self.threshold || (!record.forced && !self.sampled()) {
+            return;
+        }
+        log::warn!(
+            "slow request: method={} pattern={} status={} elapsed={:?} recognize={:?} handle={:?}{}",
+            record.method,
+            record.pattern.unwrap_or("-"),
+            record.status.as_u16(),
+            elapsed,
+            record.recognize,
+            elapsed - record.recognize,
+            Extractors(record.extraction),
+        );
+    }
+}
+
+impl<M, C> Config<M, C> for SlowRequestLog
+where
+    C: Concurrency,
+{
+    type Error = Never;
+
+    fn configure(self, cx: &mut Scope<'_, M, C>) -> Result<(), Self::Error> {
+        cx.data_mut().slow_request_log = Some(self);
+        Ok(())
+    }
+}
+
+/// The information about a request, reported to `SlowRequestLog`.
+pub(super) struct Record<'a> {
+    pub(super) method: &'a Method,
+    pub(super) pattern: Option<&'a str>,
+    pub(super) status: StatusCode,
+    pub(super) started: Instant,
+    pub(super) finished: Instant,
+    pub(super) recognize: Duration,
+    /// Whether the sampling is bypassed, for the routes whose log level is overridden.
+    pub(super) forced: bool,
+    pub(super) extraction: Option<&'a ExtractionReport>,
+}
+
+/// Formats the report of the extractors as a field of the log, if not empty.
+pub(super) struct Extractors<'a>(pub(super) Option<&'a ExtractionReport>);
+
+impl<'a> fmt::Display for Extractors<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(report) if !report.entries().is_empty() => write!(f, " extractors={}", report),
+            _ => Ok(()),
+        }
+    }
+}
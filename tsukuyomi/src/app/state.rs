@@ -0,0 +1,124 @@
+use {
+    super::{
+        config::Concurrency,
+        lifecycle::{HookFuture, Lifecycle, ShutdownHook},
+        scope::ScopeId,
+        AppInner,
+    },
+    std::{
+        any::{Any, TypeId},
+        collections::HashMap,
+        fmt,
+    },
+};
+
+/// A type-keyed map of the states registered in a scope.
+#[derive(Default)]
+pub(super) struct StateMap {
+    map: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
+    hooks: HashMap<TypeId, (&'static str, ShutdownHook)>,
+}
+
+impl fmt::Debug for StateMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StateMap")
+            .field("len", &self.map.len())
+            .field(
+                "hooks",
+                &self
+                    .hooks
+                    .values()
+                    .map(|(name, _)| name)
+                    .collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl StateMap {
+    pub(super) fn insert<T>(&mut self, value: T)
+    where
+        T: Send + Sync + 'static,
+    {
+        self.map.insert(TypeId::of::<T>(), Box::new(value));
+        self.hooks.remove(&TypeId::of::<T>());
+    }
+
+    pub(super) fn insert_with_lifecycle<T>(&mut self, value: T)
+    where
+        T: Lifecycle,
+    {
+        self.insert(value);
+        self.hooks.insert(
+            TypeId::of::<T>(),
+            (T::NAME, super::lifecycle::shutdown_hook::<T>),
+        );
+    }
+
+    /// Calls the shutdown hooks of the states in this map.
+    pub(super) fn shutdown_hooks(&self) -> impl Iterator<Item = (&'static str, HookFuture)> + '_ {
+        self.hooks.iter().map(move |(id, &(name, hook))| {
+            let state = self.get(*id).expect("the state should be registered");
+            (name, hook(state))
+        })
+    }
+
+    fn get(&self, id: TypeId) -> Option<&(dyn Any + Send + Sync)> {
+        self.map.get(&id).map(|value| &**value)
+    }
+}
+
+/// The lookup of the states visible from the scope which the request belongs to.
+pub(crate) trait States: fmt::Debug {
+    fn get(&self, id: TypeId) -> Option<&(dyn Any + Send + Sync)>;
+
+    /// Returns all states with the specified type along the ancestor chain, from the root scope.
+    fn get_all<'a>(
+        &'a self,
+        id: TypeId,
+    ) -> Box<dyn Iterator<Item = &'a (dyn Any + Send + Sync)> + 'a>;
+
+    /// Returns the scope which the states are looked up from.
+    fn scope(&self) -> Option<ScopeId> {
+        None
+    }
+}
+
+pub(super) struct ScopeStates<'a, C: Concurrency> {
+    pub(super) inner: &'a AppInner<C>,
+    pub(super) scope: ScopeId,
+}
+
+impl<'a, C: Concurrency> fmt::Debug for ScopeStates<'a, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScopeStates")
+            .field("scope", &self.scope)
+            .finish()
+    }
+}
+
+impl<'a, C: Concurrency> States for ScopeStates<'a, C> {
+    fn get(&self, id: TypeId) -> Option<&(dyn Any + Send + Sync)> {
+        self.inner
+            .find_scope_config(self.scope, |data| data.states.get(id))
+    }
+
+    fn get_all<'b>(
+        &'b self,
+        id: TypeId,
+    ) -> Box<dyn Iterator<Item = &'b (dyn Any + Send + Sync)> + 'b> {
+        let inner = self.inner;
+        Box::new(
+            inner
+                .scope(self.scope)
+                .ancestors()
+                .iter()
+                .chain(Some(&self.scope))
+                .filter_map(move |&scope| inner.scope(scope).data.states.get(id)),
+        )
+    }
+
+    fn scope(&self) -> Option<ScopeId> {
+        Some(self.scope)
+    }
+}
@@ -0,0 +1,141 @@
+use {
+    super::{
+        config::{Concurrency, Error, Result},
+        AppBase,
+    },
+    crate::{
+        future::{Poll, TryFuture},
+        handler::{AllowedMethods, Handler, ModifyHandler},
+        input::Input,
+        output::ResponseBody,
+    },
+    http::Response,
+    std::{fmt, sync::Arc},
+};
+
+/// A label attached to routes, used for applying the cross-cutting policies to
+/// the tagged routes regardless of their paths.
+///
+/// See also `Route::tag` and `AppBase::apply_to_tag`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Tag(pub &'static str);
+
+impl From<&'static str> for Tag {
+    fn from(name: &'static str) -> Self {
+        Tag(name)
+    }
+}
+
+impl fmt::Display for Tag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+/// The handler of a route in the constructed application, which is passed to
+/// the `ModifyHandler` applied by `AppBase::apply_to_tag`.
+pub struct RouteHandler<C: Concurrency> {
+    handler: Arc<C::Handler>,
+    methods: Option<AllowedMethods>,
+}
+
+impl<C: Concurrency> fmt::Debug for RouteHandler<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RouteHandler")
+            .field("methods", &self.methods)
+            .finish()
+    }
+}
+
+impl<C: Concurrency> Handler for RouteHandler<C> {
+    type Output = Response<ResponseBody>;
+    type Error = crate::Error;
+    type Handle = RouteHandle<C>; // private
+
+    fn allowed_methods(&self) -> Option<&AllowedMethods> {
+        self.methods.as_ref()
+    }
+
+    fn handle(&self) -> Self::Handle {
+        RouteHandle(C::handle(&self.handler))
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct RouteHandle<C: Concurrency>(C::Handle);
+
+impl<C: Concurrency> TryFuture for RouteHandle<C> {
+    type Ok = Response<ResponseBody>;
+    type Error = crate::Error;
+
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        C::poll_ready(&mut self.0, input)
+    }
+}
+
+impl<C> AppBase<C>
+where
+    C: Concurrency,
+{
+    /// Sets whether `apply_to_tag` with a tag attached to no route is an error.
+    ///
+    /// If disabled, such tags are reported as a warning.
+    /// The default value is `false`.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the application has already been cloned.
+    pub fn strict_tags(mut self, enabled: bool) -> Self {
+        self.inner_mut().strict_tags = enabled;
+        self
+    }
+
+    /// Applies the `ModifyHandler` to all routes with the specified tag.
+    ///
+    /// The modifier wraps the handlers of the routes, including the modifiers
+    /// applied by the scopes. The routes without the tag are left untouched.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the application has already been cloned.
+    pub fn apply_to_tag<M>(mut self, tag: impl Into<Tag>, modifier: M) -> Result<Self>
+    where
+        M: ModifyHandler<RouteHandler<C>>,
+        M::Handler: Into<C::Handler>,
+    {
+        let tag = tag.into();
+        let inner = self.inner_mut();
+
+        let mut applied = 0;
+        for endpoint in inner.recognizer.values_mut() {
+            let mut current = Some(
+                Arc::get_mut(endpoint)
+                    .expect("the endpoint should not be shared before starting the application"),
+            );
+            while let Some(endpoint) = current {
+                if endpoint.tags.contains(&tag) {
+                    let handler = modifier.modify(RouteHandler {
+                        handler: endpoint.handler.clone(),
+                        methods: endpoint.methods.clone(),
+                    });
+                    endpoint.methods = handler.allowed_methods().cloned();
+                    endpoint.handler = Arc::new(handler.into());
+                    applied += 1;
+                }
+                current = endpoint.overridden.as_mut().map(|e| &mut **e);
+            }
+        }
+
+        if applied == 0 {
+            if inner.strict_tags {
+                return Err(Error::custom(failure::format_err!(
+                    "no route is tagged with `{}'",
+                    tag
+                )));
+            }
+            log::warn!("no route is tagged with `{}'", tag);
+        }
+
+        Ok(self)
+    }
+}
@@ -0,0 +1,437 @@
+//! Batch requests, which process multiple sub-requests in one HTTP call.
+//!
+//! The client sends a JSON array of the sub-requests, such as
+//! `[{"method":"GET","path":"/users/42"},{"method":"POST","path":"/posts","body":{...}}]`,
+//! and receives a JSON array of the sub-responses in the same order,
+//! each of which has the fields `status`, `headers` and `body`.
+//!
+//! ```
+//! # use tsukuyomi::{batch, config::prelude::*, App};
+//! let app = App::create(chain![
+//!     path!("/users/:id").to(endpoint::get().call(|id: u32| format!("user {}", id))),
+//!     path!("/batch").to(batch::endpoint(batch::Limits::default().concurrency(4))),
+//! ])
+//! .unwrap();
+//! # drop(app);
+//! ```
+//!
+//! The sub-requests are dispatched to the application from the routing, as if
+//! they were sent by the client. They cannot target a batch endpoint again.
+
+use {
+    crate::{
+        endpoint::{ApplyContext, ApplyError, ApplyResult, Endpoint},
+        error::Error,
+        handler::AllowedMethods,
+        input::localmap::{LocalKey, LocalMap},
+        output::ResponseBody,
+    },
+    http::{header::HeaderName, Method, Response},
+    std::{fmt, sync::Arc},
+};
+
+/// The limits on a batch request.
+#[derive(Debug, Clone)]
+pub struct Limits {
+    max_requests: usize,
+    max_body_size: usize,
+    concurrency: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_requests: 20,
+            max_body_size: 64 * 1024,
+            concurrency: 1,
+        }
+    }
+}
+
+impl Limits {
+    /// Sets the maximum number of the sub-requests in a batch.
+    ///
+    /// The batch exceeding the limit is rejected with `413 Payload Too Large`.
+    /// The default value is `20`.
+    pub fn max_requests(self, max_requests: usize) -> Self {
+        Self {
+            max_requests,
+            ..self
+        }
+    }
+
+    /// Sets the maximum size of the body of each sub-response in bytes.
+    ///
+    /// The longer body is truncated, and the sub-response is marked with
+    /// `"truncated": true`. The default value is 64 KiB.
+    pub fn max_body_size(self, max_body_size: usize) -> Self {
+        Self {
+            max_body_size,
+            ..self
+        }
+    }
+
+    /// Sets the number of the sub-requests processed at the same time.
+    ///
+    /// The default value is `1`, which means that the sub-requests are processed sequentially.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `concurrency` is zero.
+    pub fn concurrency(self, concurrency: usize) -> Self {
+        assert!(concurrency > 0, "the concurrency must be positive");
+        Self {
+            concurrency,
+            ..self
+        }
+    }
+}
+
+/// Creates an `Endpoint` that processes the batch requests sent with `POST`.
+///
+/// The batch endpoint is available only in the thread safe application, since
+/// the sub-requests are processed as the futures that can be sent across threads.
+pub fn endpoint(limits: Limits) -> Batch {
+    Batch {
+        inner: Arc::new(Inner {
+            limits,
+            inherits: vec![],
+            capture_headers: vec![http::header::CONTENT_TYPE],
+        }),
+    }
+}
+
+/// An `Endpoint` created by `batch::endpoint`.
+#[derive(Debug, Clone)]
+pub struct Batch {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    limits: Limits,
+    inherits: Vec<Box<Inherit>>,
+    capture_headers: Vec<HeaderName>,
+}
+
+type Inherit = dyn Fn(&LocalMap, &mut LocalMap) + Send + Sync + 'static;
+
+impl fmt::Debug for Inner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Inner")
+            .field("limits", &self.limits)
+            .field("inherits", &self.inherits.len())
+            .field("capture_headers", &self.capture_headers)
+            .finish()
+    }
+}
+
+impl Batch {
+    fn inner_mut(&mut self) -> &mut Inner {
+        Arc::get_mut(&mut self.inner).expect("the endpoint has already been shared")
+    }
+
+    /// Makes the sub-requests inherit the request-local value of the batch request
+    /// associated with the specified key, such as the authenticated user.
+    ///
+    /// The other request-local values are not visible from the sub-requests.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the endpoint has already been cloned.
+    pub fn inherit<T>(mut self, key: &'static LocalKey<T>) -> Self
+    where
+        T: Clone + Send + 'static,
+    {
+        self.inner_mut().inherits.push(Box::new(move |from, to| {
+            if let Some(value) = from.get(key) {
+                to.insert(key, value.clone());
+            }
+        }));
+        self
+    }
+
+    /// Sets the names of the header fields copied into the sub-responses.
+    ///
+    /// By default, only `Content-Type` is copied.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the endpoint has already been cloned.
+    pub fn capture_headers(mut self, names: impl IntoIterator<Item = HeaderName>) -> Self {
+        self.inner_mut().capture_headers = names.into_iter().collect();
+        self
+    }
+}
+
+impl Endpoint<()> for Batch {
+    type Output = Response<ResponseBody>;
+    type Error = Error;
+    type Future = self::imp::BatchFuture; // private
+
+    fn apply(&self, _: (), cx: &mut ApplyContext<'_, '_>) -> ApplyResult<(), Self> {
+        if cx.method() != Method::POST {
+            return Err(((), ApplyError::method_not_allowed()));
+        }
+        Ok(self::imp::BatchFuture::new(self.inner.clone()))
+    }
+
+    fn allowed_methods(&self) -> Option<AllowedMethods> {
+        Some(Method::POST.into())
+    }
+}
+
+mod imp {
+    use {
+        super::Inner,
+        crate::{
+            app::DispatchFuture,
+            error::Error,
+            future::{Async, Poll, TryFuture},
+            input::{
+                body::RequestBody,
+                localmap::{local_key, LocalData, LocalMap},
+                Input,
+            },
+            output::ResponseBody,
+            util::Never,
+        },
+        futures01::{future, stream, Future, Stream},
+        http::{
+            header::{CONTENT_TYPE, HOST},
+            Request, Response, StatusCode,
+        },
+        hyper::body::Payload,
+        serde::Deserialize,
+        std::{collections::BTreeMap, sync::Arc},
+    };
+
+    /// The marker of the sub-requests, which prevents them from reaching a batch endpoint.
+    #[derive(Debug, Clone)]
+    struct SubRequestMarker(());
+
+    impl LocalData for SubRequestMarker {
+        local_key! {
+            const KEY: Self;
+        }
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct SubRequest {
+        #[serde(default = "default_method")]
+        method: String,
+        path: String,
+        #[serde(default)]
+        headers: BTreeMap<String, String>,
+        #[serde(default)]
+        body: Option<serde_json::Value>,
+    }
+
+    fn default_method() -> String {
+        "GET".into()
+    }
+
+    type SubResponse = Box<dyn Future<Item = serde_json::Value, Error = Never> + Send + 'static>;
+
+    #[allow(missing_debug_implementations)]
+    pub struct BatchFuture {
+        inner: Arc<Inner>,
+        state: State,
+    }
+
+    enum State {
+        Init,
+        Receiving(stream::Concat2<crate::input::body::BodyStream>),
+        Processing(Box<dyn Future<Item = Vec<serde_json::Value>, Error = Never> + Send + 'static>),
+    }
+
+    impl BatchFuture {
+        pub(super) fn new(inner: Arc<Inner>) -> Self {
+            Self {
+                inner,
+                state: State::Init,
+            }
+        }
+
+        fn start(&self, body: &[u8], input: &mut Input<'_>) -> Result<State, Error> {
+            let sub_requests: Vec<SubRequest> = serde_json::from_slice(body)
+                .map_err(|err| crate::error::bad_request(format!("invalid batch: {}", err)))?;
+            let limits = &self.inner.limits;
+            if sub_requests.len() > limits.max_requests {
+                return Err(crate::error::custom(
+                    StatusCode::PAYLOAD_TOO_LARGE,
+                    format!(
+                        "too many sub-requests: {} (limit: {})",
+                        sub_requests.len(),
+                        limits.max_requests
+                    ),
+                ));
+            }
+
+            let mut responses = Vec::with_capacity(sub_requests.len());
+            for sub_request in sub_requests {
+                let response: SubResponse = match self.dispatch(sub_request, input) {
+                    Ok(dispatched) => {
+                        let inner = self.inner.clone();
+                        Box::new(dispatched.and_then(move |response| capture(response, &inner)))
+                    }
+                    Err(message) => Box::new(future::ok(serde_json::json!({
+                        "status": StatusCode::BAD_REQUEST.as_u16(),
+                        "headers": {},
+                        "body": message,
+                        "truncated": false,
+                    }))),
+                };
+                responses.push(response);
+            }
+
+            Ok(State::Processing(Box::new(
+                stream::iter_ok(responses)
+                    .buffered(limits.concurrency)
+                    .collect(),
+            )))
+        }
+
+        fn dispatch(
+            &self,
+            sub_request: SubRequest,
+            input: &mut Input<'_>,
+        ) -> Result<DispatchFuture, String> {
+            if !sub_request.path.starts_with('/') {
+                return Err(format!(
+                    "the path of the sub-request must be absolute: {:?}",
+                    sub_request.path
+                ));
+            }
+
+            let mut builder = Request::builder();
+            builder
+                .method(sub_request.method.as_str())
+                .uri(sub_request.path.as_str());
+            for (name, value) in &sub_request.headers {
+                builder.header(name.as_str(), value.as_str());
+            }
+            let body = match sub_request.body {
+                None | Some(serde_json::Value::Null) => hyper::Body::empty(),
+                Some(serde_json::Value::String(body)) => body.into(),
+                Some(body) => {
+                    if !sub_request
+                        .headers
+                        .keys()
+                        .any(|name| name.eq_ignore_ascii_case("content-type"))
+                    {
+                        builder.header(CONTENT_TYPE, "application/json");
+                    }
+                    body.to_string().into()
+                }
+            };
+            let mut request = builder
+                .body(RequestBody::from(body))
+                .map_err(|err| format!("invalid sub-request: {}", err))?;
+            // The sub-requests are sent to the same host as the batch.
+            if let Some(host) = input.request.headers().get(HOST) {
+                request
+                    .headers_mut()
+                    .entry(HOST)
+                    .expect("never fails")
+                    .or_insert_with(|| host.clone());
+            }
+
+            let mut locals = LocalMap::default();
+            SubRequestMarker(()).insert_into(&mut locals);
+            for inherit in &self.inner.inherits {
+                inherit(input.locals, &mut locals);
+            }
+
+            input
+                .dispatch
+                .dispatch(request, locals)
+                .ok_or_else(|| "the application does not support the batch requests".into())
+        }
+    }
+
+    impl TryFuture for BatchFuture {
+        type Ok = Response<ResponseBody>;
+        type Error = Error;
+
+        fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+            loop {
+                self.state = match self.state {
+                    State::Init => {
+                        if input.locals.contains_key(&SubRequestMarker::KEY) {
+                            return Err(crate::error::bad_request(
+                                "the batch requests cannot be nested",
+                            ));
+                        }
+                        let body = RequestBody::take_from(input.locals)
+                            .ok_or_else(crate::extractor::body::stolen_payload)?;
+                        State::Receiving(body.into_stream().concat2())
+                    }
+                    State::Receiving(ref mut body) => {
+                        let body = futures01::try_ready!(body.poll());
+                        self.start(&body, input)?
+                    }
+                    State::Processing(ref mut responses) => {
+                        let responses = match responses.poll() {
+                            Ok(Async::Ready(responses)) => responses,
+                            Ok(Async::NotReady) => return Ok(Async::NotReady),
+                            Err(never) => match never {},
+                        };
+                        let body = serde_json::Value::Array(responses).to_string();
+                        return Ok(Async::Ready(
+                            Response::builder()
+                                .header(CONTENT_TYPE, "application/json")
+                                .body(body.into())
+                                .expect("should be a valid response"),
+                        ));
+                    }
+                };
+            }
+        }
+    }
+
+    /// Reads the body of the sub-response up to the size limit, and converts it into a JSON object.
+    fn capture(
+        response: Response<ResponseBody>,
+        inner: &Inner,
+    ) -> impl Future<Item = serde_json::Value, Error = Never> + Send + 'static {
+        let (parts, mut body) = response.into_parts();
+
+        let mut headers = serde_json::Map::new();
+        for name in &inner.capture_headers {
+            let values: Vec<&str> = parts
+                .headers
+                .get_all(name)
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .collect();
+            if !values.is_empty() {
+                headers.insert(name.as_str().into(), values.join(", ").into());
+            }
+        }
+        let status = parts.status.as_u16();
+
+        let max_body_size = inner.limits.max_body_size;
+        let mut buf = Vec::new();
+        future::poll_fn(move || {
+            while buf.len() <= max_body_size {
+                match body.poll_data() {
+                    Ok(Async::Ready(Some(chunk))) => buf.extend_from_slice(&chunk),
+                    Ok(Async::Ready(None)) => break,
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Err(err) => {
+                        log::error!("failed to read the body of the sub-response: {}", err);
+                        break;
+                    }
+                }
+            }
+            let truncated = buf.len() > max_body_size;
+            buf.truncate(max_body_size);
+            Ok(Async::Ready(serde_json::json!({
+                "status": status,
+                "headers": std::mem::replace(&mut headers, serde_json::Map::new()),
+                "body": String::from_utf8_lossy(&buf),
+                "truncated": truncated,
+            })))
+        })
+    }
+}
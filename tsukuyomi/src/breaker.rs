@@ -0,0 +1,408 @@
+//! A circuit breaker for the calls to the downstream services.
+//!
+//! [`CircuitBreaker`] records the failures of the calls within a sliding window,
+//! and stops the calls for a while once the failures reach the threshold, so
+//! that an outage of a downstream service does not tie up every request waiting
+//! for it. After the cooldown, a single probe call is let through, and its result
+//! decides whether the breaker closes again.
+//!
+//! The breaker can be used directly by the components calling the remote services,
+//! or wrapped around an extractor with `ExtractorExt::with_breaker`:
+//!
+//! ```
+//! # use tsukuyomi::{
+//! #     breaker::CircuitBreaker,
+//! #     config::prelude::*,
+//! #     extractor::{self, ExtractorExt},
+//! #     App,
+//! # };
+//! # use std::time::Duration;
+//! let breaker = CircuitBreaker::builder()
+//!     .failure_threshold(5)
+//!     .window(Duration::from_secs(10))
+//!     .cooldown(Duration::from_secs(30))
+//!     .build();
+//!
+//! # let lookup_user = || extractor::ready(|_| Ok::<_, tsukuyomi::Error>(("alice".to_owned(),)));
+//! let app = App::create(
+//!     path!("/") //
+//!         .to(endpoint::get()
+//!             .extract(lookup_user().with_breaker(breaker.clone()))
+//!             .call(|_user: String| "hello")),
+//! );
+//! # drop(app);
+//! ```
+//!
+//! [`CircuitBreaker`]: ./struct.CircuitBreaker.html
+
+use {
+    crate::rt::{Clock, SystemClock},
+    std::{
+        collections::VecDeque,
+        fmt,
+        sync::{Arc, Mutex},
+        time::{Duration, Instant},
+    },
+};
+
+/// A builder of `CircuitBreaker`.
+pub struct Builder {
+    failure_threshold: usize,
+    window: Duration,
+    cooldown: Duration,
+    clock: Arc<dyn Clock>,
+}
+
+impl fmt::Debug for Builder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Builder")
+            .field("failure_threshold", &self.failure_threshold)
+            .field("window", &self.window)
+            .field("cooldown", &self.cooldown)
+            .finish()
+    }
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            window: Duration::from_secs(10),
+            cooldown: Duration::from_secs(30),
+            clock: Arc::new(SystemClock),
+        }
+    }
+}
+
+impl Builder {
+    /// Sets the number of the failures within the window that opens the breaker.
+    ///
+    /// The default value is 5.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `failure_threshold` is zero.
+    pub fn failure_threshold(self, failure_threshold: usize) -> Self {
+        assert!(failure_threshold > 0, "the threshold must be positive");
+        Self {
+            failure_threshold,
+            ..self
+        }
+    }
+
+    /// Sets the length of the sliding window in which the failures are counted.
+    ///
+    /// The default value is 10 seconds.
+    pub fn window(self, window: Duration) -> Self {
+        Self { window, ..self }
+    }
+
+    /// Sets the duration for which the breaker stays open before letting a probe
+    /// call through.
+    ///
+    /// The default value is 30 seconds.
+    pub fn cooldown(self, cooldown: Duration) -> Self {
+        Self { cooldown, ..self }
+    }
+
+    /// Sets the clock used for measuring the window and the cooldown.
+    ///
+    /// The breaker is created independently of the application, so its clock must
+    /// be set separately. This is mainly intended for replacing it with
+    /// `rt::MockClock` in tests.
+    pub fn clock(self, clock: impl Clock) -> Self {
+        Self {
+            clock: Arc::new(clock),
+            ..self
+        }
+    }
+
+    /// Creates a `CircuitBreaker` with the current configuration.
+    pub fn build(self) -> CircuitBreaker {
+        CircuitBreaker {
+            inner: Arc::new(Inner {
+                failure_threshold: self.failure_threshold,
+                window: self.window,
+                cooldown: self.cooldown,
+                clock: self.clock,
+                state: Mutex::new(BreakerState {
+                    state: State::Closed,
+                    failures: VecDeque::new(),
+                    opened_at: None,
+                    probing: false,
+                    metrics: BreakerMetrics::default(),
+                }),
+            }),
+        }
+    }
+}
+
+/// The state of a `CircuitBreaker`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    /// The calls are let through, and their failures are counted.
+    Closed,
+
+    /// The calls are rejected until the cooldown elapses.
+    Open,
+
+    /// A probe call is let through, and the other calls are rejected until
+    /// it completes.
+    HalfOpen,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        State::Closed
+    }
+}
+
+impl State {
+    fn as_str(self) -> &'static str {
+        match self {
+            State::Closed => "closed",
+            State::Open => "open",
+            State::HalfOpen => "half_open",
+        }
+    }
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A circuit breaker shared by the calls to a downstream service.
+///
+/// The clones of this value share the state, so that all requests see the same breaker.
+#[derive(Debug, Clone)]
+pub struct CircuitBreaker {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    failure_threshold: usize,
+    window: Duration,
+    cooldown: Duration,
+    clock: Arc<dyn Clock>,
+    state: Mutex<BreakerState>,
+}
+
+#[derive(Debug)]
+struct BreakerState {
+    state: State,
+    failures: VecDeque<Instant>,
+    opened_at: Option<Instant>,
+    probing: bool,
+    metrics: BreakerMetrics,
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+impl CircuitBreaker {
+    /// Creates a builder of `CircuitBreaker`.
+    pub fn builder() -> Builder {
+        Builder::default()
+    }
+
+    /// Returns the current state of the breaker.
+    ///
+    /// The open breaker is reported as half-open once its cooldown has elapsed,
+    /// even before the probe call is made.
+    pub fn state(&self) -> State {
+        let now = self.inner.clock.now();
+        let state = self.inner.state.lock().unwrap();
+        match state.state {
+            State::Open if self.cooldown_elapsed(&state, now) => State::HalfOpen,
+            state => state,
+        }
+    }
+
+    /// Returns whether the breaker is closed.
+    ///
+    /// This is intended for reporting the health of the downstream service,
+    /// e.g. from a readiness check of the application.
+    pub fn is_closed(&self) -> bool {
+        self.state() == State::Closed
+    }
+
+    /// Asks the permission for a call to the downstream service.
+    ///
+    /// If the breaker is open, the call is rejected and `None` is returned.
+    /// Otherwise, the returned `Permit` must be consumed with the result of the call.
+    pub fn try_acquire(&self) -> Option<Permit> {
+        let now = self.inner.clock.now();
+        let mut state = self.inner.state.lock().unwrap();
+
+        let probe = match state.state {
+            State::Closed => false,
+            State::Open if self.cooldown_elapsed(&state, now) => {
+                state.state = State::HalfOpen;
+                state.probing = true;
+                true
+            }
+            State::HalfOpen if !state.probing => {
+                state.probing = true;
+                true
+            }
+            State::Open | State::HalfOpen => {
+                state.metrics.rejected += 1;
+                return None;
+            }
+        };
+
+        Some(Permit {
+            breaker: self.clone(),
+            probe,
+            completed: false,
+        })
+    }
+
+    /// Returns a snapshot of the statistics of the breaker.
+    pub fn metrics(&self) -> BreakerMetrics {
+        let state = self.state();
+        BreakerMetrics {
+            state,
+            ..self.inner.state.lock().unwrap().metrics
+        }
+    }
+
+    fn cooldown_elapsed(&self, state: &BreakerState, now: Instant) -> bool {
+        state
+            .opened_at
+            .map_or(true, |opened_at| now >= opened_at + self.inner.cooldown)
+    }
+
+    fn record(&self, probe: bool, success: bool) {
+        let now = self.inner.clock.now();
+        let mut state = self.inner.state.lock().unwrap();
+
+        if probe {
+            state.probing = false;
+        }
+
+        if success {
+            state.metrics.successes += 1;
+            if probe {
+                state.state = State::Closed;
+                state.opened_at = None;
+                state.failures.clear();
+            }
+            return;
+        }
+
+        state.metrics.failures += 1;
+        if probe {
+            self.open(&mut state, now);
+            return;
+        }
+
+        if state.state != State::Closed {
+            return;
+        }
+        state.failures.push_back(now);
+        while state
+            .failures
+            .front()
+            .map_or(false, |&failed_at| failed_at + self.inner.window <= now)
+        {
+            state.failures.pop_front();
+        }
+        if state.failures.len() >= self.inner.failure_threshold {
+            self.open(&mut state, now);
+        }
+    }
+
+    fn open(&self, state: &mut BreakerState, now: Instant) {
+        log::warn!("the circuit breaker is opened");
+        state.state = State::Open;
+        state.opened_at = Some(now);
+        state.failures.clear();
+        state.metrics.opened += 1;
+    }
+}
+
+/// The permission of a call granted by `CircuitBreaker::try_acquire`.
+///
+/// If the permit is dropped without reporting the result, e.g. when the call
+/// has been cancelled, the call is not counted.
+#[derive(Debug)]
+pub struct Permit {
+    breaker: CircuitBreaker,
+    probe: bool,
+    completed: bool,
+}
+
+impl Permit {
+    /// Returns whether this call is the probe of the half-open breaker.
+    pub fn is_probe(&self) -> bool {
+        self.probe
+    }
+
+    /// Reports that the call has succeeded.
+    pub fn succeed(mut self) {
+        self.completed = true;
+        self.breaker.record(self.probe, true);
+    }
+
+    /// Reports that the call has failed.
+    pub fn fail(mut self) {
+        self.completed = true;
+        self.breaker.record(self.probe, false);
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if !self.completed && self.probe {
+            // Let another call probe the downstream service.
+            self.breaker.inner.state.lock().unwrap().probing = false;
+        }
+    }
+}
+
+/// A snapshot of the statistics of a `CircuitBreaker`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BreakerMetrics {
+    /// The current state of the breaker.
+    pub state: State,
+
+    /// The number of the calls reported as succeeded.
+    pub successes: u64,
+
+    /// The number of the calls reported as failed.
+    pub failures: u64,
+
+    /// The number of the calls rejected while the breaker is open.
+    pub rejected: u64,
+
+    /// The number of the times the breaker has been opened.
+    pub opened: u64,
+}
+
+impl BreakerMetrics {
+    /// Returns the pairs of the metric names prefixed with `circuit_breaker_` and
+    /// their values, for exporting them to a metrics registry.
+    ///
+    /// The state is reported as `0` (closed), `1` (half-open) or `2` (open).
+    pub fn entries(&self) -> [(&'static str, u64); 5] {
+        let state = match self.state {
+            State::Closed => 0,
+            State::HalfOpen => 1,
+            State::Open => 2,
+        };
+        [
+            ("circuit_breaker_state", state),
+            ("circuit_breaker_successes_total", self.successes),
+            ("circuit_breaker_failures_total", self.failures),
+            ("circuit_breaker_rejected_total", self.rejected),
+            ("circuit_breaker_opened_total", self.opened),
+        ]
+    }
+}
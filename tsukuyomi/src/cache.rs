@@ -0,0 +1,623 @@
+//! A shared in-memory cache.
+//!
+//! [`MemoryCache`] is a bounded concurrent map whose entries expire after their
+//! TTL, and are evicted in the least-recently-used order when the capacity is
+//! exceeded. The cache is cheap to clone, and can be registered as a state of
+//! the scope by [`config::state`] to share it between the handlers and the
+//! extractors via [`extractor::state`]:
+//!
+//! ```
+//! # use tsukuyomi::{
+//! #     cache::MemoryCache,
+//! #     config::prelude::*,
+//! #     extractor,
+//! #     vendor::futures::Future,
+//! #     App,
+//! # };
+//! let cache: MemoryCache<u32, String> = MemoryCache::builder().capacity(1024).build();
+//!
+//! let app = App::create(chain![
+//!     tsukuyomi::config::state(cache),
+//!     path!("/users/:id") //
+//!         .to(endpoint::get()
+//!             .extract(extractor::state::<MemoryCache<u32, String>>())
+//!             .call_async(|id: u32, cache: MemoryCache<u32, String>| {
+//!                 cache
+//!                     .get_or_insert_with(id, move || Ok::<_, ()>(format!("user {}", id)))
+//!                     .map_err(|()| tsukuyomi::error::internal_server_error("failed to load"))
+//!             })),
+//! ]);
+//! # drop(app);
+//! ```
+//!
+//! [`MemoryCache`]: ./struct.MemoryCache.html
+//! [`config::state`]: ../config/fn.state.html
+//! [`extractor::state`]: ../extractor/state/fn.state.html
+
+use {
+    crate::rt::{Clock, SystemClock},
+    futures01::{
+        future::{IntoFuture, Shared},
+        sync::oneshot,
+        Async, Future, Poll,
+    },
+    std::{
+        any::Any,
+        collections::{hash_map::RandomState, HashMap},
+        fmt,
+        hash::{BuildHasher, Hash, Hasher},
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex, RwLock, Weak,
+        },
+        time::{Duration, Instant},
+    },
+};
+
+type Loader<V, E> = Box<dyn Future<Item = V, Error = E> + Send + 'static>;
+type Load<V, E> = Shared<Pending<V, E>>;
+
+/// A builder of `MemoryCache`.
+pub struct Builder {
+    capacity: usize,
+    shards: usize,
+    ttl: Option<Duration>,
+    clock: Arc<dyn Clock>,
+}
+
+impl fmt::Debug for Builder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Builder")
+            .field("capacity", &self.capacity)
+            .field("shards", &self.shards)
+            .field("ttl", &self.ttl)
+            .finish()
+    }
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self {
+            capacity: 10_000,
+            shards: 16,
+            ttl: None,
+            clock: Arc::new(SystemClock),
+        }
+    }
+}
+
+impl Builder {
+    /// Sets the maximum number of entries.
+    ///
+    /// The capacity is divided equally among the shards, and the least-recently-used
+    /// entry in the shard is evicted when the shard is full.
+    /// The default value is 10000.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the value of `capacity` is zero.
+    pub fn capacity(self, capacity: usize) -> Self {
+        assert!(capacity > 0, "the capacity must be greater than zero");
+        Self { capacity, ..self }
+    }
+
+    /// Sets the number of shards, each of which is guarded by its own lock.
+    ///
+    /// The default value is 16.
+    pub fn shards(self, shards: usize) -> Self {
+        Self { shards, ..self }
+    }
+
+    /// Sets the default TTL of the entries.
+    ///
+    /// By default, the entries never expire.
+    pub fn ttl(self, ttl: Duration) -> Self {
+        Self {
+            ttl: Some(ttl),
+            ..self
+        }
+    }
+
+    /// Sets the clock used for checking the expiration.
+    ///
+    /// The cache is created independently of the application, so its clock must
+    /// be set separately. This is mainly intended for replacing it with
+    /// `rt::MockClock` in tests.
+    pub fn clock(self, clock: impl Clock) -> Self {
+        Self {
+            clock: Arc::new(clock),
+            ..self
+        }
+    }
+
+    /// Creates a `MemoryCache` with the current configuration.
+    pub fn build<K, V>(self) -> MemoryCache<K, V>
+    where
+        K: Hash + Eq,
+    {
+        let shards = self.shards.min(self.capacity).max(1);
+        let shard_capacity = (self.capacity + shards - 1) / shards;
+        MemoryCache {
+            inner: Arc::new(Inner {
+                shards: (0..shards)
+                    .map(|_| Shard {
+                        entries: RwLock::new(HashMap::new()),
+                        inflight: Mutex::new(HashMap::new()),
+                    })
+                    .collect(),
+                shard_capacity,
+                ttl: self.ttl,
+                clock: self.clock,
+                hasher: RandomState::new(),
+                tick: AtomicUsize::new(0),
+                hits: AtomicUsize::new(0),
+                misses: AtomicUsize::new(0),
+                loads: AtomicUsize::new(0),
+                evictions: AtomicUsize::new(0),
+            }),
+        }
+    }
+}
+
+/// The statistics of a `MemoryCache`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CacheStats {
+    /// The number of lookups that found a live entry.
+    pub hits: usize,
+
+    /// The number of lookups that did not find a live entry.
+    pub misses: usize,
+
+    /// The number of loads started by `get_or_insert_with`.
+    pub loads: usize,
+
+    /// The number of entries evicted due to the capacity.
+    pub evictions: usize,
+}
+
+/// A bounded concurrent map with per-entry TTL and LRU eviction.
+pub struct MemoryCache<K, V> {
+    inner: Arc<Inner<K, V>>,
+}
+
+impl<K, V> Clone for MemoryCache<K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<K, V> fmt::Debug for MemoryCache<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemoryCache")
+            .field("shards", &self.inner.shards.len())
+            .field("shard_capacity", &self.inner.shard_capacity)
+            .field("ttl", &self.inner.ttl)
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+struct Inner<K, V> {
+    shards: Vec<Shard<K, V>>,
+    shard_capacity: usize,
+    ttl: Option<Duration>,
+    clock: Arc<dyn Clock>,
+    hasher: RandomState,
+    tick: AtomicUsize,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+    loads: AtomicUsize,
+    evictions: AtomicUsize,
+}
+
+struct Shard<K, V> {
+    entries: RwLock<HashMap<K, Entry<V>>>,
+    // The type of loads is erased since it depends on the error type of each call.
+    inflight: Mutex<HashMap<K, Box<dyn Any + Send>>>,
+}
+
+struct Entry<V> {
+    value: V,
+    expires_at: Option<Instant>,
+    // Updated under the read lock, in order not to serialize the lookups.
+    last_access: AtomicUsize,
+}
+
+impl<V> Entry<V> {
+    fn is_live(&self, now: Instant) -> bool {
+        match self.expires_at {
+            Some(expires_at) => now < expires_at,
+            None => true,
+        }
+    }
+}
+
+impl MemoryCache<(), ()> {
+    /// Creates a `Builder` to construct a `MemoryCache`.
+    pub fn builder() -> Builder {
+        Builder::default()
+    }
+}
+
+impl<K, V> MemoryCache<K, V> {
+    /// Returns the statistics of this cache.
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.inner.hits.load(Ordering::Relaxed),
+            misses: self.inner.misses.load(Ordering::Relaxed),
+            loads: self.inner.loads.load(Ordering::Relaxed),
+            evictions: self.inner.evictions.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl<K, V> MemoryCache<K, V>
+where
+    K: Hash + Eq,
+{
+    /// Returns a clone of the value corresponding to the key, if it is alive.
+    pub fn get(&self, key: &K) -> Option<V>
+    where
+        V: Clone,
+    {
+        let found = self.inner.peek(key);
+        let counter = if found.is_some() {
+            &self.inner.hits
+        } else {
+            &self.inner.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        found
+    }
+
+    /// Returns a clone of the value corresponding to the key, along with whether
+    /// it has expired, if it is alive or has expired within `max_stale`.
+    ///
+    /// The expired entries are kept until they are evicted or removed, so this
+    /// method may not find one even within `max_stale`. A lookup finding an expired
+    /// entry is counted as a miss.
+    pub fn get_or_stale(&self, key: &K, max_stale: Duration) -> Option<(V, bool)>
+    where
+        V: Clone,
+    {
+        let found = self.inner.peek_stale(key, max_stale);
+        let counter = match found {
+            Some((_, false)) => &self.inner.hits,
+            _ => &self.inner.misses,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        found
+    }
+
+    /// Inserts a value with the default TTL.
+    pub fn insert(&self, key: K, value: V) {
+        self.inner.insert(key, value, self.inner.ttl);
+    }
+
+    /// Inserts a value with the specified TTL.
+    pub fn insert_with_ttl(&self, key: K, value: V, ttl: Duration) {
+        self.inner.insert(key, value, Some(ttl));
+    }
+
+    /// Inserts a value with the specified TTL, unless a live entry with the key exists.
+    ///
+    /// The lookup and the insertion are performed atomically, so only one of the
+    /// concurrent calls with the same key inserts the value. Returns whether the
+    /// value has been inserted.
+    pub fn insert_if_absent_with_ttl(&self, key: K, value: V, ttl: Duration) -> bool {
+        let now = self.inner.clock.now();
+        let mut entries = self.inner.shard(&key).entries.write().unwrap();
+        if entries.get(&key).map_or(false, |entry| entry.is_live(now)) {
+            return false;
+        }
+        self.inner
+            .insert_locked(&mut entries, key, value, Some(ttl), now);
+        true
+    }
+
+    /// Removes the entry corresponding to the key and returns its value, if it is alive.
+    pub fn remove(&self, key: &K) -> Option<V> {
+        let now = self.inner.clock.now();
+        let mut entries = self.inner.shard(key).entries.write().unwrap();
+        entries
+            .remove(key)
+            .filter(|entry| entry.is_live(now))
+            .map(|entry| entry.value)
+    }
+
+    /// Removes the entries for which the predicate returns `false`.
+    ///
+    /// The expired entries are removed regardless of the predicate.
+    pub fn retain<F>(&self, mut f: F)
+    where
+        F: FnMut(&K, &V) -> bool,
+    {
+        let now = self.inner.clock.now();
+        for shard in &self.inner.shards {
+            let mut entries = shard.entries.write().unwrap();
+            entries.retain(|key, entry| entry.is_live(now) && f(key, &entry.value));
+        }
+    }
+
+    /// Removes all entries.
+    pub fn clear(&self) {
+        for shard in &self.inner.shards {
+            shard.entries.write().unwrap().clear();
+        }
+    }
+
+    /// Returns the number of the alive entries.
+    pub fn len(&self) -> usize {
+        let now = self.inner.clock.now();
+        self.inner
+            .shards
+            .iter()
+            .map(|shard| {
+                let entries = shard.entries.read().unwrap();
+                entries.values().filter(|entry| entry.is_live(now)).count()
+            })
+            .sum()
+    }
+
+    /// Returns `true` if there are no alive entries.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<K, V> MemoryCache<K, V>
+where
+    K: Hash + Eq + Clone + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    /// Returns a future that resolves to the value corresponding to the key,
+    /// loading it with the provided function if missing.
+    ///
+    /// The concurrent calls with the same key share a single load, and the function
+    /// is not called while another load of the key is in progress. The loaded value
+    /// is inserted with the default TTL, and the errors are delivered to all callers
+    /// without being cached. Note that a load is shared only among the calls whose
+    /// loaders have the same error type.
+    pub fn get_or_insert_with<F, R>(&self, key: K, f: F) -> GetOrInsert<V, R::Error>
+    where
+        F: FnOnce() -> R,
+        R: IntoFuture<Item = V>,
+        R::Future: Send + 'static,
+        R::Error: Clone + Send + Sync + 'static,
+    {
+        if let Some(value) = self.get(&key) {
+            return GetOrInsert(State::Ready(Some(value)));
+        }
+
+        let shard = self.inner.shard(&key);
+        let (tx, load) = {
+            let mut inflight = shard.inflight.lock().unwrap();
+            if let Some(load) = inflight
+                .get(&key)
+                .and_then(|load| load.downcast_ref::<Load<V, R::Error>>())
+            {
+                return GetOrInsert(State::Loading(load.clone()));
+            }
+
+            // The previous load may have been completed before acquiring the lock.
+            if let Some(value) = self.inner.peek(&key) {
+                return GetOrInsert(State::Ready(Some(value)));
+            }
+
+            self.inner.loads.fetch_add(1, Ordering::Relaxed);
+            let (tx, rx) = oneshot::channel();
+            let load = Pending { rx, loader: None }.shared();
+            inflight.insert(key.clone(), Box::new(load.clone()));
+            (tx, load)
+        };
+
+        // The loader is called after releasing the lock, since it may access
+        // the same shard of the cache.
+        let loader: Loader<V, R::Error> = {
+            let _guard = RemoveOnUnwind {
+                inner: &*self.inner,
+                key: &key,
+            };
+            let cache = Arc::downgrade(&self.inner);
+            let load_key = key.clone();
+            Box::new(
+                f().into_future()
+                    .then(move |result| complete(&cache, load_key, result)),
+            )
+        };
+        // The receiver is alive since it is owned by `load`.
+        let _ = tx.send(loader);
+
+        GetOrInsert(State::Loading(load))
+    }
+}
+
+/// The load registered before calling the loader, which drives the loader
+/// after it is sent from the caller.
+#[allow(missing_debug_implementations)]
+struct Pending<V, E> {
+    rx: oneshot::Receiver<Loader<V, E>>,
+    loader: Option<Loader<V, E>>,
+}
+
+impl<V, E> Future for Pending<V, E> {
+    type Item = V;
+    type Error = E;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            if let Some(ref mut loader) = self.loader {
+                return loader.poll();
+            }
+            match self.rx.poll() {
+                Ok(Async::Ready(loader)) => self.loader = Some(loader),
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Err(..) => panic!("the loader of the cache entry has panicked"),
+            }
+        }
+    }
+}
+
+/// Unregisters the pending load if the loader panics.
+struct RemoveOnUnwind<'a, K: Hash + Eq, V> {
+    inner: &'a Inner<K, V>,
+    key: &'a K,
+}
+
+impl<'a, K, V> Drop for RemoveOnUnwind<'a, K, V>
+where
+    K: Hash + Eq,
+{
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            if let Ok(mut inflight) = self.inner.shard(self.key).inflight.lock() {
+                inflight.remove(self.key);
+            }
+        }
+    }
+}
+
+fn complete<K, V, E>(cache: &Weak<Inner<K, V>>, key: K, result: Result<V, E>) -> Result<V, E>
+where
+    K: Hash + Eq + Clone,
+    V: Clone,
+{
+    if let Some(inner) = cache.upgrade() {
+        if let Ok(ref value) = result {
+            inner.insert(key.clone(), value.clone(), inner.ttl);
+        }
+        inner.shard(&key).inflight.lock().unwrap().remove(&key);
+    }
+    result
+}
+
+impl<K, V> Inner<K, V>
+where
+    K: Hash + Eq,
+{
+    fn shard(&self, key: &K) -> &Shard<K, V> {
+        let mut hasher = self.hasher.build_hasher();
+        key.hash(&mut hasher);
+        let index = hasher.finish() % self.shards.len() as u64;
+        &self.shards[index as usize]
+    }
+
+    fn next_tick(&self) -> usize {
+        self.tick.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Looks up the entry without updating the statistics.
+    fn peek(&self, key: &K) -> Option<V>
+    where
+        V: Clone,
+    {
+        let now = self.clock.now();
+        let entries = self.shard(key).entries.read().unwrap();
+        let entry = entries.get(key).filter(|entry| entry.is_live(now))?;
+        entry.last_access.store(self.next_tick(), Ordering::Relaxed);
+        Some(entry.value.clone())
+    }
+
+    /// Looks up the entry, including the one expired within `max_stale`.
+    fn peek_stale(&self, key: &K, max_stale: Duration) -> Option<(V, bool)>
+    where
+        V: Clone,
+    {
+        let now = self.clock.now();
+        let entries = self.shard(key).entries.read().unwrap();
+        let entry = entries.get(key)?;
+        let stale = match entry.expires_at {
+            Some(expires_at) if now >= expires_at + max_stale => return None,
+            Some(expires_at) => now >= expires_at,
+            None => false,
+        };
+        entry.last_access.store(self.next_tick(), Ordering::Relaxed);
+        Some((entry.value.clone(), stale))
+    }
+
+    fn insert(&self, key: K, value: V, ttl: Option<Duration>) {
+        let now = self.clock.now();
+        let mut entries = self.shard(&key).entries.write().unwrap();
+        self.insert_locked(&mut entries, key, value, ttl, now);
+    }
+
+    fn insert_locked(
+        &self,
+        entries: &mut HashMap<K, Entry<V>>,
+        key: K,
+        value: V,
+        ttl: Option<Duration>,
+        now: Instant,
+    ) {
+        if !entries.contains_key(&key) && entries.len() >= self.shard_capacity {
+            self.make_room(entries, now);
+        }
+        entries.insert(
+            key,
+            Entry {
+                value,
+                expires_at: ttl.map(|ttl| now + ttl),
+                last_access: AtomicUsize::new(self.next_tick()),
+            },
+        );
+    }
+
+    /// Removes the expired entries, or the least-recently-used one if all are alive.
+    fn make_room(&self, entries: &mut HashMap<K, Entry<V>>, now: Instant) {
+        entries.retain(|_, entry| entry.is_live(now));
+        if entries.len() < self.shard_capacity {
+            return;
+        }
+        let lru = entries
+            .values()
+            .map(|entry| entry.last_access.load(Ordering::Relaxed))
+            .min();
+        if let Some(lru) = lru {
+            // The ticks are unique, so exactly one entry is removed here.
+            entries.retain(|_, entry| entry.last_access.load(Ordering::Relaxed) != lru);
+            self.evictions.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// A future returned from `MemoryCache::get_or_insert_with`.
+#[must_use = "futures do nothing unless polled"]
+pub struct GetOrInsert<V, E>(State<V, E>);
+
+enum State<V, E> {
+    Ready(Option<V>),
+    Loading(Load<V, E>),
+}
+
+impl<V, E> fmt::Debug for GetOrInsert<V, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            State::Ready(..) => f.debug_tuple("Ready").finish(),
+            State::Loading(..) => f.debug_tuple("Loading").finish(),
+        }
+    }
+}
+
+impl<V, E> Future for GetOrInsert<V, E>
+where
+    V: Clone,
+    E: Clone,
+{
+    type Item = V;
+    type Error = E;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.0 {
+            State::Ready(ref mut value) => Ok(Async::Ready(
+                value.take().expect("the future has already polled"),
+            )),
+            State::Loading(ref mut load) => match load.poll() {
+                Ok(Async::Ready(value)) => Ok(Async::Ready((*value).clone())),
+                Ok(Async::NotReady) => Ok(Async::NotReady),
+                Err(err) => Err((*err).clone()),
+            },
+        }
+    }
+}
@@ -2,20 +2,31 @@
 
 pub mod endpoint;
 pub mod path;
+pub mod version;
 
 pub mod prelude {
     #[doc(no_inline)]
-    pub use crate::{chain, path};
+    pub use crate::{chain, path, route};
 
     #[doc(no_inline)]
     pub use super::{mount, Config, ConfigExt};
 
+    #[doc(no_inline)]
+    pub use crate::endpoint::EndpointExt;
+
     pub mod endpoint {
         #[doc(no_inline)]
         pub use super::super::endpoint::{
-            allow_only, any, call, call_async, connect, delete, get, head, options, patch, post,
-            put, reply, trace,
+            allow_only, any, call, call_async, connect, delete, get, head, options, patch, pipe,
+            post, put, reply, trace,
         };
+
+        #[doc(no_inline)]
+        pub use crate::endpoint::split;
+
+        #[cfg(feature = "async-await")]
+        #[doc(no_inline)]
+        pub use super::super::endpoint::call_async03;
     }
 }
 
@@ -24,8 +35,9 @@ pub use crate::app::config::{Config, Error, Result, Scope};
 
 use {
     crate::{
-        app::config::Concurrency,
+        app::{config::Concurrency, Lifecycle, ModifyResponse, ResponseHook, ServerOptions, Tag},
         handler::{Handler, ModifyHandler},
+        policy::{Authorized, Policy},
         util::Chain,
     },
     std::borrow::Cow,
@@ -95,6 +107,117 @@ where
     }
 }
 
+/// Creates a `Config` that registers a state into the current scope.
+///
+/// The registered state is visible from the routes in the scope and its
+/// descendants, and overridden by another state of the same type registered
+/// in a sub-scope.
+pub fn state<T>(state: T) -> State<T>
+where
+    T: Send + Sync + 'static,
+{
+    State(state)
+}
+
+/// A `Config` that registers a state into the current scope.
+#[derive(Debug)]
+pub struct State<T>(T);
+
+impl<T, M, C> Config<M, C> for State<T>
+where
+    T: Send + Sync + 'static,
+    C: Concurrency,
+{
+    type Error = crate::util::Never;
+
+    fn configure(self, cx: &mut Scope<'_, M, C>) -> std::result::Result<(), Self::Error> {
+        cx.set_state(self.0);
+        Ok(())
+    }
+}
+
+/// Creates a `Config` that registers a state into the current scope, along with
+/// its shutdown hook.
+///
+/// The state is visible in the same way as `state`, and `Lifecycle::on_shutdown`
+/// of it is called by `App::shutdown`.
+pub fn state_with_lifecycle<T>(state: T) -> StateWithLifecycle<T>
+where
+    T: Lifecycle,
+{
+    StateWithLifecycle(state)
+}
+
+/// A `Config` that registers a state with the shutdown hook into the current scope.
+#[derive(Debug)]
+pub struct StateWithLifecycle<T>(T);
+
+impl<T, M, C> Config<M, C> for StateWithLifecycle<T>
+where
+    T: Lifecycle,
+    C: Concurrency,
+{
+    type Error = crate::util::Never;
+
+    fn configure(self, cx: &mut Scope<'_, M, C>) -> std::result::Result<(), Self::Error> {
+        cx.set_state_with_lifecycle(self.0);
+        Ok(())
+    }
+}
+
+/// Creates a `Config` that applies the `ModifyResponse` to every response within
+/// the current scope and its descendants, including the error responses.
+///
+/// ```
+/// # use tsukuyomi::{config::prelude::*, App};
+/// # use http::header::HeaderValue;
+/// let app = App::create(chain![
+///     path!("/").to(endpoint::reply("index")),
+///     tsukuyomi::config::modify_response(|_: &http::Request<()>, response: &mut http::Response<_>| {
+///         response
+///             .headers_mut()
+///             .insert("x-frame-options", HeaderValue::from_static("DENY"));
+///     }),
+/// ])
+/// .unwrap();
+/// # drop(app);
+/// ```
+pub fn modify_response(modifier: impl ModifyResponse) -> ResponseHook {
+    ResponseHook::new(modifier)
+}
+
+/// Creates a `Config` that registers the route responding to `OPTIONS *` with
+/// the server-wide metadata.
+///
+/// See `app::ServerOptions` for details.
+pub fn server_options() -> ServerOptions {
+    ServerOptions::default()
+}
+
+/// Creates a `Config` that sets whether the routes registered after it in the
+/// current scope override the existing routes at the same path.
+///
+/// See also `Route::override_existing`.
+pub fn allow_overrides(enabled: bool) -> AllowOverrides {
+    AllowOverrides(enabled)
+}
+
+/// A `Config` that sets whether the routes override the existing ones.
+#[derive(Debug)]
+pub struct AllowOverrides(bool);
+
+impl<M, C> Config<M, C> for AllowOverrides
+where
+    C: Concurrency,
+{
+    type Error = crate::util::Never;
+
+    fn configure(self, cx: &mut Scope<'_, M, C>) -> std::result::Result<(), Self::Error> {
+        cx.allow_overrides(self.0);
+        Ok(())
+    }
+}
+
 pub trait ConfigExt: Sized {
     /// Creates a `Config` with the specified `ModifyHandler`
     fn modify<M>(self, modifier: M) -> Modify<M, Self> {
@@ -109,6 +232,9 @@ impl<T> ConfigExt for T {}
 pub struct Route<H> {
     path: Cow<'static, str>,
     handler: H,
+    override_existing: bool,
+    tags: Vec<Tag>,
+    policy: Option<Policy>,
 }
 
 impl<H> Route<H>
@@ -120,6 +246,48 @@ where
         Self {
             path: path.into(),
             handler,
+            override_existing: false,
+            tags: vec![],
+            policy: None,
+        }
+    }
+
+    /// Makes this route override the route registered earlier at the same path,
+    /// instead of failing.
+    ///
+    /// The methods accepted by this route are handled by it, and the other
+    /// methods are still handled by the overridden route.
+    pub fn override_existing(self) -> Self {
+        Self {
+            override_existing: true,
+            ..self
+        }
+    }
+
+    /// Attaches a tag to this route.
+    ///
+    /// The tags are used for applying the modifiers by `AppBase::apply_to_tag`.
+    pub fn tag(mut self, tag: impl Into<Tag>) -> Self {
+        self.tags.push(tag.into());
+        self
+    }
+
+    /// Restricts the access to this route by the authorization policy.
+    ///
+    /// The policy is evaluated for the principal identified by `policy::Authorize`
+    /// applied to the enclosing scope, before the handler of this route. If the
+    /// method is called more than once, all of the policies must allow the access.
+    pub fn policy(self, policy: Policy) -> Route<Authorized<H>> {
+        let described = match self.policy {
+            Some(existing) => crate::policy::all_of(vec![existing, policy.clone()]),
+            None => policy.clone(),
+        };
+        Route {
+            path: self.path,
+            handler: Authorized::new(self.handler, policy),
+            override_existing: self.override_existing,
+            tags: self.tags,
+            policy: Some(described),
         }
     }
 }
@@ -134,6 +302,13 @@ where
     type Error = Error;
 
     fn configure(self, scope: &mut Scope<'_, M, C>) -> std::result::Result<(), Self::Error> {
-        scope.route(self.path, self.handler)
+        let override_existing = self.override_existing || scope.allows_overrides();
+        scope.add_route(
+            &self.path,
+            self.handler,
+            override_existing,
+            self.tags,
+            self.policy,
+        )
     }
 }
@@ -1,14 +1,24 @@
 use {
     crate::{
-        endpoint::{ApplyContext, ApplyError, Endpoint},
+        endpoint::{ApplyContext, ApplyError, ApplyResult, Endpoint},
         error::Error,
-        extractor::Extractor,
+        extractor::{ext::Labeled, report::Label, Extractor},
         generic::{Combine, Func},
+        guard::{Guard, Guards, Verify},
         handler::AllowedMethods,
+        i18n::Args,
+        input::body::BodyStream,
+        output::ResponseBody,
         util::{Chain, Never, TryInto},
     },
-    futures01::IntoFuture,
-    http::Method,
+    bytes::Bytes,
+    futures01::{IntoFuture, Stream},
+    http::{
+        header::{HeaderValue, CONTENT_LENGTH, TRANSFER_ENCODING},
+        Method, Request, Response, StatusCode,
+    },
+    mime::Mime,
+    std::sync::Arc,
 };
 
 pub fn any() -> Builder {
@@ -28,7 +38,10 @@ macro_rules! define_builder_with_allowing_sigle_method {
         pub fn $name() -> Builder {
             Builder {
                 extractor: (),
+                extractors: 0,
                 allowed_methods: Some(Method::$METHOD.into()),
+                guards: Guards::default(),
+                body: BodyRules::default(),
             }
         }
     )*}
@@ -54,7 +67,10 @@ pub fn get_or_head() -> Builder {
 #[derive(Debug)]
 pub struct Builder<E: Extractor = ()> {
     extractor: E,
+    extractors: usize,
     allowed_methods: Option<AllowedMethods>,
+    guards: Guards,
+    body: BodyRules,
 }
 
 impl Builder {
@@ -62,7 +78,10 @@ impl Builder {
     pub fn allow_any() -> Self {
         Self {
             extractor: (),
+            extractors: 0,
             allowed_methods: None,
+            guards: Guards::default(),
+            body: BodyRules::default(),
         }
     }
 
@@ -70,7 +89,10 @@ impl Builder {
     pub fn allow_only(methods: impl TryInto<AllowedMethods>) -> super::Result<Self> {
         Ok(Self {
             extractor: (),
+            extractors: 0,
             allowed_methods: methods.try_into().map(Some).map_err(super::Error::custom)?,
+            guards: Guards::default(),
+            body: BodyRules::default(),
         })
     }
 }
@@ -80,14 +102,73 @@ where
     E: Extractor,
 {
     /// Appends a supplemental `Extractor` to this endpoint.
-    pub fn extract<E2>(self, other: E2) -> Builder<Chain<E, E2>>
+    ///
+    /// The extractor is labeled by its position in the chain (`#1`, `#2`, ...) in the
+    /// report of the extractors, unless labeled by `ExtractorExt::label`.
+    pub fn extract<E2>(self, other: E2) -> Builder<Chain<E, Labeled<E2>>>
     where
         E2: Extractor,
         E::Output: Combine<E2::Output>,
     {
+        let position = self.extractors + 1;
         Builder {
-            extractor: Chain::new(self.extractor, other),
+            extractor: Chain::new(
+                self.extractor,
+                Labeled::new(other, Label::Position(position)),
+            ),
+            extractors: position,
             allowed_methods: self.allowed_methods,
+            guards: self.guards,
+            body: self.body,
+        }
+    }
+
+    /// Appends a `Guard` to this endpoint.
+    ///
+    /// The endpoint is applied only if all of the registered guards pass.
+    pub fn guard(mut self, guard: impl Guard + Send + Sync + 'static) -> Self {
+        self.guards.push(guard);
+        self
+    }
+
+    /// Requires the requests to this endpoint to have a body.
+    ///
+    /// The requests with neither `Content-Length` nor `Transfer-Encoding` are rejected
+    /// with `411 Length Required`, and the ones with `Content-Length: 0` with
+    /// `400 Bad Request`.
+    pub fn body_required(self) -> Self {
+        Self {
+            body: BodyRules {
+                required: true,
+                ..self.body
+            },
+            ..self
+        }
+    }
+
+    /// Rejects the requests to this endpoint having a body with `400 Bad Request`.
+    ///
+    /// A request is regarded as having a body if it has `Transfer-Encoding` or
+    /// a non-zero `Content-Length`.
+    pub fn body_forbidden(self) -> Self {
+        Self {
+            body: BodyRules {
+                forbidden: true,
+                ..self.body
+            },
+            ..self
+        }
+    }
+
+    /// Sets the limit of the length of the request body declared by `Content-Length`,
+    /// overriding the one of `app::RequestSizeLimit` applied to the scope.
+    pub fn max_declared_length(self, limit: u64) -> Self {
+        Self {
+            body: BodyRules {
+                max_declared_length: Some(limit),
+                ..self.body
+            },
+            ..self
         }
     }
 
@@ -107,6 +188,8 @@ where
     {
         let apply_fn = {
             let allowed_methods = self.allowed_methods.clone();
+            let guards = self.guards.clone();
+            let body = self.body;
             let extractor = self.extractor;
             move |args: T, cx: &mut ApplyContext<'_, '_>| {
                 if allowed_methods
@@ -115,6 +198,10 @@ where
                 {
                     return Err((args, ApplyError::method_not_allowed()));
                 }
+                if let Err(err) = guards.check(cx) {
+                    return Err((args, err));
+                }
+                body.apply(cx);
                 Ok(self::call::CallFuture {
                     extract: extractor.extract(),
                     f: f.clone(),
@@ -122,7 +209,11 @@ where
                 })
             }
         };
-        crate::endpoint::endpoint(apply_fn, self.allowed_methods)
+        crate::endpoint::endpoint_with_produces(
+            apply_fn,
+            self.allowed_methods,
+            self.guards.produces(),
+        )
     }
 
     /// Creates an `Endpoint` that replies its result as a `Future`.
@@ -143,6 +234,8 @@ where
     {
         let apply_fn = {
             let allowed_methods = self.allowed_methods.clone();
+            let guards = self.guards.clone();
+            let body = self.body;
             let extractor = self.extractor;
             move |args: T, cx: &mut ApplyContext<'_, '_>| {
                 if allowed_methods
@@ -151,6 +244,10 @@ where
                 {
                     return Err((args, ApplyError::method_not_allowed()));
                 }
+                if let Err(err) = guards.check(cx) {
+                    return Err((args, err));
+                }
+                body.apply(cx);
 
                 Ok(self::call_async::CallAsyncFuture {
                     state: self::call_async::State::First(extractor.extract()),
@@ -159,7 +256,64 @@ where
                 })
             }
         };
-        crate::endpoint::endpoint(apply_fn, self.allowed_methods)
+        crate::endpoint::endpoint_with_produces(
+            apply_fn,
+            self.allowed_methods,
+            self.guards.produces(),
+        )
+    }
+
+    /// Creates an `Endpoint` that replies the result of an `async fn`.
+    ///
+    /// The returned `std::future::Future` is driven as a task of futures 0.1
+    /// through `future::Compat03`, so it must run on the tokio 0.1 runtime.
+    /// The futures 0.1 based futures awaited in it need to be converted with
+    /// `future::compat01_as_03`.
+    #[cfg(feature = "async-await")]
+    pub fn call_async03<T, F, R, U, Err>(
+        self,
+        f: F,
+    ) -> impl Endpoint<
+        T,
+        Output = U,
+        Error = Error,
+        Future = self::call_async03::CallAsync03Future<E, F, R, T>, // private
+    >
+    where
+        T: Combine<E::Output>,
+        F: Func<<T as Combine<E::Output>>::Out, Out = R> + Clone,
+        R: std::future::Future<Output = Result<U, Err>>,
+        Err: Into<Error>,
+    {
+        let apply_fn = {
+            let allowed_methods = self.allowed_methods.clone();
+            let guards = self.guards.clone();
+            let body = self.body;
+            let extractor = self.extractor;
+            move |args: T, cx: &mut ApplyContext<'_, '_>| {
+                if allowed_methods
+                    .as_ref()
+                    .map_or(false, |methods| !methods.contains(cx.method()))
+                {
+                    return Err((args, ApplyError::method_not_allowed()));
+                }
+                if let Err(err) = guards.check(cx) {
+                    return Err((args, err));
+                }
+                body.apply(cx);
+
+                Ok(self::call_async03::CallAsync03Future {
+                    state: self::call_async03::State::First(extractor.extract()),
+                    f: f.clone(),
+                    args: Some(args),
+                })
+            }
+        };
+        crate::endpoint::endpoint_with_produces(
+            apply_fn,
+            self.allowed_methods,
+            self.guards.produces(),
+        )
     }
 }
 
@@ -219,6 +373,25 @@ where
     any().call_async(f)
 }
 
+/// A shortcut to `endpoint::any().call_async03(f)`.
+#[cfg(feature = "async-await")]
+pub fn call_async03<T, F, R, U, Err>(
+    f: F,
+) -> impl Endpoint<
+    T,
+    Output = U,
+    Error = Error,
+    Future = self::call_async03::CallAsync03Future<(), F, R, T>, // private
+>
+where
+    T: Combine<()>,
+    F: Func<<T as Combine<()>>::Out, Out = R> + Clone,
+    R: std::future::Future<Output = Result<U, Err>>,
+    Err: Into<Error>,
+{
+    any().call_async03(f)
+}
+
 /// A shortcut to `endpoint::any().reply(output)`.
 #[inline]
 pub fn reply<R>(
@@ -235,6 +408,147 @@ where
     any().reply(output)
 }
 
+/// Creates an `Endpoint` that pipes the request body into the response body through `transform`.
+///
+/// The chunks of the request body are passed to `transform` as a `BodyStream`, and the
+/// returned stream is sent as the response body without buffering either side.
+/// A chunk is read from the request only when the transformed stream requests it,
+/// so the backpressure of the client that receives the response is propagated to the
+/// client that sends the request.
+///
+/// If the transformed stream returns an error, the response body is aborted at that point.
+///
+/// ```
+/// # use tsukuyomi::{config::prelude::*, App};
+/// # use tsukuyomi::vendor::futures::Stream;
+/// let app = App::create(
+///     path!("/echo").to(
+///         endpoint::pipe(|body| body.map(|chunk| chunk.to_ascii_uppercase().into()))
+///             .content_type(mime::TEXT_PLAIN),
+///     ),
+/// ).unwrap();
+/// # drop(app);
+/// ```
+pub fn pipe<F, S>(transform: F) -> Pipe<F>
+where
+    F: Fn(BodyStream) -> S,
+    S: Stream<Item = Bytes, Error = Error> + Send + 'static,
+{
+    Pipe {
+        transform: Arc::new(transform),
+        content_type: None,
+    }
+}
+
+/// An `Endpoint` created by `endpoint::pipe`.
+#[derive(Debug)]
+pub struct Pipe<F> {
+    transform: Arc<F>,
+    content_type: Option<HeaderValue>,
+}
+
+impl<F> Pipe<F> {
+    /// Sets the value of `Content-Type` of the response.
+    pub fn content_type(self, content_type: Mime) -> Self {
+        Self {
+            content_type: Some(
+                HeaderValue::from_shared(content_type.as_ref().into())
+                    .expect("should be a valid header value"),
+            ),
+            ..self
+        }
+    }
+}
+
+impl<F, S> Endpoint<()> for Pipe<F>
+where
+    F: Fn(BodyStream) -> S,
+    S: Stream<Item = Bytes, Error = Error> + Send + 'static,
+{
+    type Output = Response<ResponseBody>;
+    type Error = Error;
+    type Future = self::pipe::PipeFuture<F>; // private
+
+    fn apply(&self, _: (), _: &mut ApplyContext<'_, '_>) -> ApplyResult<(), Self> {
+        Ok(self::pipe::PipeFuture {
+            transform: self.transform.clone(),
+            content_type: self.content_type.clone(),
+        })
+    }
+
+    fn allowed_methods(&self) -> Option<AllowedMethods> {
+        None
+    }
+}
+
+/// The rules of the presence and the length of the request bodies checked before extracting.
+#[derive(Debug, Clone, Copy, Default)]
+struct BodyRules {
+    required: bool,
+    forbidden: bool,
+    max_declared_length: Option<u64>,
+}
+
+impl BodyRules {
+    fn apply(&self, cx: &mut ApplyContext<'_, '_>) {
+        if let Some(limit) = self.max_declared_length {
+            cx.set_max_declared_length(limit);
+        }
+        if let Err(err) = self.check(cx.request()) {
+            cx.defer(Some(Box::new(futures01::future::err(err)) as Verify));
+        }
+    }
+
+    fn check(&self, request: &Request<()>) -> Result<(), Error> {
+        if !self.required && !self.forbidden {
+            return Ok(());
+        }
+        let chunked = request.headers().contains_key(TRANSFER_ENCODING);
+        let length = match request.headers().get(CONTENT_LENGTH) {
+            Some(h) => Some(
+                h.to_str()
+                    .ok()
+                    .and_then(|h| h.trim().parse::<u64>().ok())
+                    .ok_or_else(|| crate::error::bad_request("invalid Content-Length"))?,
+            ),
+            None => None,
+        };
+
+        if self.required {
+            match length {
+                None if !chunked => {
+                    return Err(crate::error::localized(
+                        StatusCode::LENGTH_REQUIRED,
+                        "error.body.required",
+                        Args::new(),
+                        "the request body is required, but neither Content-Length nor Transfer-Encoding is present",
+                    ));
+                }
+                Some(0) if !chunked => {
+                    return Err(crate::error::localized(
+                        StatusCode::BAD_REQUEST,
+                        "error.body.required",
+                        Args::new(),
+                        "the request body is required, but it is empty",
+                    ));
+                }
+                _ => {}
+            }
+        }
+
+        if self.forbidden && (chunked || length.map_or(false, |length| length > 0)) {
+            return Err(crate::error::localized(
+                StatusCode::BAD_REQUEST,
+                "error.body.forbidden",
+                Args::new(),
+                format_args!("the request body is not allowed on {}", request.method()),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
 mod call {
     use crate::{
         extractor::Extractor,
@@ -324,3 +638,133 @@ mod call_async {
         }
     }
 }
+
+#[cfg(feature = "async-await")]
+mod call_async03 {
+    use crate::{
+        error::Error,
+        extractor::Extractor,
+        future::{Compat03, Poll, TryFuture},
+        generic::{Combine, Func},
+        input::Input,
+    };
+
+    #[allow(missing_debug_implementations)]
+    pub(super) enum State<Fut1, Fut2> {
+        First(Fut1),
+        Second(Compat03<Fut2>),
+    }
+
+    #[allow(missing_debug_implementations)]
+    pub struct CallAsync03Future<E: Extractor, F, R, T> {
+        pub(super) state: State<E::Extract, R>,
+        pub(super) f: F,
+        pub(super) args: Option<T>,
+    }
+
+    impl<E, F, R, T, U, Err> TryFuture for CallAsync03Future<E, F, R, T>
+    where
+        E: Extractor,
+        F: Func<<T as Combine<E::Output>>::Out, Out = R>,
+        R: std::future::Future<Output = Result<U, Err>>,
+        Err: Into<Error>,
+        T: Combine<E::Output>,
+    {
+        type Ok = U;
+        type Error = Error;
+
+        fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+            loop {
+                self.state = match self.state {
+                    State::First(ref mut extract) => {
+                        let args2 =
+                            futures01::try_ready!(extract.poll_ready(input).map_err(Into::into));
+                        let args = self
+                            .args
+                            .take()
+                            .expect("the future has already been polled.");
+                        State::Second(Compat03::from(self.f.call(args.combine(args2))))
+                    }
+                    State::Second(ref mut action) => {
+                        return action.poll_ready(input).map_err(Into::into)
+                    }
+                };
+            }
+        }
+    }
+}
+
+mod pipe {
+    use {
+        crate::{
+            error::Error,
+            future::{Async, Poll, TryFuture},
+            input::{
+                body::{BodyStream, RequestBody},
+                localmap::LocalData,
+                Input,
+            },
+            output::ResponseBody,
+        },
+        bytes::Bytes,
+        futures01::Stream,
+        http::{header::HeaderValue, Response},
+        std::{io, sync::Arc},
+    };
+
+    #[allow(missing_debug_implementations)]
+    pub struct PipeFuture<F> {
+        pub(super) transform: Arc<F>,
+        pub(super) content_type: Option<HeaderValue>,
+    }
+
+    impl<F, S> TryFuture for PipeFuture<F>
+    where
+        F: Fn(BodyStream) -> S,
+        S: Stream<Item = Bytes, Error = Error> + Send + 'static,
+    {
+        type Ok = Response<ResponseBody>;
+        type Error = Error;
+
+        fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+            let body = RequestBody::take_from(input.locals)
+                .ok_or_else(crate::extractor::body::stolen_payload)?;
+            let stream = (self.transform)(body.into_stream());
+
+            let mut response = Response::new(ResponseBody::wrap_stream(PipeBody {
+                stream: Some(stream),
+            }));
+            if let Some(content_type) = self.content_type.take() {
+                response
+                    .headers_mut()
+                    .insert(http::header::CONTENT_TYPE, content_type);
+            }
+            Ok(Async::Ready(response))
+        }
+    }
+
+    /// The response body that terminates at the first error of the transformed stream.
+    struct PipeBody<S> {
+        stream: Option<S>,
+    }
+
+    impl<S> Stream for PipeBody<S>
+    where
+        S: Stream<Item = Bytes, Error = Error>,
+    {
+        type Item = Bytes;
+        type Error = io::Error;
+
+        fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+            let polled = match self.stream {
+                Some(ref mut stream) => stream.poll(),
+                None => return Ok(Async::Ready(None)),
+            };
+            polled.map_err(|err| {
+                log::error!("aborting the piped response: {}", err);
+                self.stream = None;
+                io::Error::new(io::ErrorKind::Other, err.to_string())
+            })
+        }
+    }
+}
@@ -7,11 +7,12 @@ use {
         handler::Handler,
         input::param::Params,
     },
-    std::{marker::PhantomData, sync::Arc},
+    mime::Mime,
+    std::{borrow::Cow, marker::PhantomData, sync::Arc},
 };
 
 #[doc(hidden)]
-pub use tsukuyomi_macros::path_impl;
+pub use tsukuyomi_macros::{path_impl, route_impl};
 
 pub trait PathExtractor {
     type Output: Tuple;
@@ -43,6 +44,219 @@ macro_rules! path {
     }};
 }
 
+/// A macro for defining a route and its endpoints in a compact form.
+///
+/// Each endpoint is written as an HTTP method, a list of extractors and a handler
+/// function, and the macro expands to the equivalent builder calls:
+///
+/// ```ignore
+/// route!("/posts/:id", PUT, json(Post) => update_post)
+/// // is equivalent to
+/// path!("/posts/:id").to(endpoint::put()
+///     .extract(extractor::body::json::<Post>())
+///     .call(update_post))
+/// ```
+///
+/// Multiple endpoints registered at the same path are enclosed in braces, and are
+/// combined by `chain!`.
+///
+/// The method is one of `GET`, `POST`, `PUT`, `DELETE`, `HEAD`, `OPTIONS`,
+/// `CONNECT`, `PATCH`, `TRACE` or `ANY`. The following shorthands are
+/// available for the extractors:
+///
+/// * `query(T)` - `extractor::query::<T>()`
+/// * `json(T)` - `extractor::body::json::<T>()`
+/// * `urlencoded(T)` - `extractor::body::urlencoded::<T>()`
+/// * `plain(T)` - `extractor::body::plain::<T>()`
+/// * `param(name: T)` - `extractor::param::<T>("name")`
+/// * `extract(e)` - an arbitrary extractor `e`
+///
+/// The handler receives the path parameters followed by the values of the extractors,
+/// in order. The result is a `Route`, and hence the methods of `ConfigExt` such as
+/// `modify` can be chained to it.
+///
+/// # Example
+///
+/// ```
+/// # use tsukuyomi::{config::prelude::*, App};
+/// # use serde::Deserialize;
+/// #[derive(Debug, Deserialize)]
+/// struct Pagination {
+///     page: u32,
+/// }
+///
+/// #[derive(Debug, Deserialize)]
+/// struct Post {
+///     title: String,
+/// }
+///
+/// let app = App::create(chain![
+///     route!("/posts", {
+///         GET, query(Pagination) => |p: Pagination| format!("page {}", p.page),
+///         POST, json(Post) => |post: Post| format!("created {}", post.title),
+///     }),
+///     route!("/posts/:id", GET => |id: u32| format!("post {}", id)),
+/// ]);
+/// # drop(app);
+/// ```
+///
+/// # Compile errors
+///
+/// The path is checked at compile time in the same way as `path!`, and the
+/// names of the parameters must be valid identifiers:
+///
+/// ```compile_fail
+/// # use tsukuyomi::{config::prelude::*, App};
+/// let app = App::create(route!("/posts/:post-id", GET => |id: u32| format!("post {}", id)));
+/// # drop(app);
+/// ```
+///
+/// ```compile_fail
+/// # use tsukuyomi::{config::prelude::*, App};
+/// let app = App::create(route!("/:id/:id", GET => |a: u32, b: u32| format!("{} {}", a, b)));
+/// # drop(app);
+/// ```
+///
+/// ```compile_fail
+/// # use tsukuyomi::{config::prelude::*, App};
+/// let app = App::create(route!("posts/:id", GET => |id: u32| format!("post {}", id)));
+/// # drop(app);
+/// ```
+///
+/// A method cannot be registered twice at the same path, and `param(name: T)`
+/// must refer to a parameter in the path:
+///
+/// ```compile_fail
+/// # use tsukuyomi::{config::prelude::*, App};
+/// let app = App::create(route!("/posts", {
+///     GET => || "list",
+///     GET => || "another list",
+/// }));
+/// # drop(app);
+/// ```
+///
+/// ```compile_fail
+/// # use tsukuyomi::{config::prelude::*, App};
+/// let app = App::create(route!("/posts/:id", GET, param(slug: String) => |id: u32, slug: String| slug));
+/// # drop(app);
+/// ```
+///
+/// The arity of the handler is checked against the extracted values:
+///
+/// ```compile_fail
+/// # use tsukuyomi::{config::prelude::*, App};
+/// let app = App::create(
+///     route!("/posts/:id", GET => |id: u32, extra: u32| format!("{} {}", id, extra)),
+/// );
+/// # drop(app);
+/// ```
+#[macro_export]
+macro_rules! route {
+    ($($t:tt)*) => {{
+        use $crate::config::path::internal as __path_internal;
+        enum __Dummy {}
+        impl __Dummy {
+            $crate::config::path::route_impl!(__path_internal, $($t)*);
+        }
+        __Dummy::call().to($crate::__route_endpoints!($($t)*))
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __route_endpoints {
+    ($path:expr, {
+        $( $method:ident $(, $kind:ident ( $($args:tt)* ))* => $handler:expr ),+ $(,)*
+    }) => {
+        $crate::chain![
+            $( $crate::__route_endpoint!($method $(, $kind ( $($args)* ))* => $handler) ),+
+        ]
+    };
+    ($path:expr, $method:ident $(, $kind:ident ( $($args:tt)* ))* => $handler:expr $(,)*) => {
+        $crate::__route_endpoint!($method $(, $kind ( $($args)* ))* => $handler)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __route_endpoint {
+    ($method:ident $(, $kind:ident ( $($args:tt)* ))* => $handler:expr) => {
+        $crate::__route_method!($method)
+            $( .extract($crate::__route_extractor!($kind ( $($args)* ))) )*
+            .call($handler)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __route_method {
+    (GET) => {
+        $crate::config::endpoint::get()
+    };
+    (POST) => {
+        $crate::config::endpoint::post()
+    };
+    (PUT) => {
+        $crate::config::endpoint::put()
+    };
+    (DELETE) => {
+        $crate::config::endpoint::delete()
+    };
+    (HEAD) => {
+        $crate::config::endpoint::head()
+    };
+    (OPTIONS) => {
+        $crate::config::endpoint::options()
+    };
+    (CONNECT) => {
+        $crate::config::endpoint::connect()
+    };
+    (PATCH) => {
+        $crate::config::endpoint::patch()
+    };
+    (TRACE) => {
+        $crate::config::endpoint::trace()
+    };
+    (ANY) => {
+        $crate::config::endpoint::any()
+    };
+    ($other:ident) => {
+        compile_error!(concat!(
+            "unsupported method in route!: ",
+            stringify!($other)
+        ))
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __route_extractor {
+    (query ( $t:ty )) => {
+        $crate::extractor::query::<$t>()
+    };
+    (json ( $t:ty )) => {
+        $crate::extractor::body::json::<$t>()
+    };
+    (urlencoded ( $t:ty )) => {
+        $crate::extractor::body::urlencoded::<$t>()
+    };
+    (plain ( $t:ty )) => {
+        $crate::extractor::body::plain::<$t>()
+    };
+    (param ( $name:ident : $t:ty )) => {
+        $crate::extractor::param::<$t>(stringify!($name))
+    };
+    (extract ( $e:expr )) => {
+        $e
+    };
+    ($other:ident ( $($args:tt)* )) => {
+        compile_error!(concat!(
+            "unknown extractor shorthand in route!: ",
+            stringify!($other)
+        ))
+    };
+}
+
 #[doc(hidden)]
 pub mod internal {
     pub use {
@@ -56,7 +270,7 @@ pub mod internal {
 
 #[derive(Debug)]
 pub struct Path<E: PathExtractor = ()> {
-    path: &'static str,
+    path: Cow<'static, str>,
     _marker: PhantomData<E>,
 }
 
@@ -65,9 +279,13 @@ where
     E: PathExtractor,
 {
     /// Creates a new `Path` with the specified path and extractor.
-    pub fn new(path: &'static str) -> Self {
+    ///
+    /// The path may be constructed at runtime, for example when registering
+    /// `BoxedEndpoint`s from a table.  Unlike `path!`, the consistency between
+    /// the path and the extractor is not checked.
+    pub fn new(path: impl Into<Cow<'static, str>>) -> Self {
         Self {
-            path,
+            path: path.into(),
             _marker: PhantomData,
         }
     }
@@ -89,14 +307,15 @@ where
         let Self { path, .. } = self;
         let endpoint = Arc::new(endpoint);
         let allowed_methods = endpoint.allowed_methods();
+        let produces: Arc<[Mime]> = endpoint.produces().into();
 
-        Route {
-            path: path.into(),
-            handler: crate::handler::handler(
-                move || self::handle::RouteHandle::new(endpoint.clone()),
+        Route::new(
+            path,
+            crate::handler::handler(
+                move || self::handle::RouteHandle::new(endpoint.clone(), produces.clone()),
                 allowed_methods,
             ),
-        }
+        )
     }
 }
 
@@ -107,8 +326,11 @@ mod handle {
             endpoint::{ApplyContext, Endpoint},
             error::Error,
             future::{Poll, TryFuture},
-            input::Input,
+            guard::Pending,
+            input::{accept::Accept, Input},
         },
+        http::StatusCode,
+        mime::Mime,
         std::{marker::PhantomData, sync::Arc},
     };
 
@@ -128,9 +350,9 @@ mod handle {
         E: PathExtractor,
         T: Endpoint<E::Output>,
     {
-        pub fn new(endpoint: Arc<T>) -> Self {
+        pub fn new(endpoint: Arc<T>, produces: Arc<[Mime]>) -> Self {
             Self {
-                state: RouteHandleState::Init(endpoint),
+                state: RouteHandleState::Init(endpoint, produces),
                 _marker: PhantomData,
             }
         }
@@ -138,8 +360,8 @@ mod handle {
 
     #[allow(missing_debug_implementations)]
     enum RouteHandleState<T, Fut> {
-        Init(Arc<T>),
-        InFlight(Fut),
+        Init(Arc<T>, Arc<[Mime]>),
+        InFlight(Pending, Fut),
     }
 
     impl<E, T> TryFuture for RouteHandle<E, T>
@@ -154,19 +376,75 @@ mod handle {
         fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
             loop {
                 self.state = match self.state {
-                    RouteHandleState::Init(ref endpoint) => {
+                    RouteHandleState::Init(ref endpoint, ref produces) => {
                         let args = E::extract(input.params.as_ref())?;
-                        RouteHandleState::InFlight(
-                            endpoint
-                                .apply(args, &mut ApplyContext::new(input))
-                                .map_err(|(_args, err)| err)?,
-                        )
+                        let (pending, in_flight) = if produces.is_empty() {
+                            let mut cx = ApplyContext::new(input);
+                            let in_flight =
+                                endpoint.apply(args, &mut cx).map_err(|(_args, err)| err)?;
+                            (cx.take_pending(), in_flight)
+                        } else {
+                            apply_negotiated(&**endpoint, args, produces, input)?
+                        };
+                        RouteHandleState::InFlight(pending, in_flight)
                     }
-                    RouteHandleState::InFlight(ref mut in_flight) => {
+                    RouteHandleState::InFlight(ref mut pending, ref mut in_flight) => {
+                        futures01::try_ready!(pending.poll_ready());
                         return in_flight.poll_ready(input).map_err(Into::into);
                     }
                 };
             }
         }
     }
+
+    /// Applies the endpoint with the media types acceptable for the client, in order of preference.
+    fn apply_negotiated<T, A>(
+        endpoint: &T,
+        mut args: A,
+        produces: &[Mime],
+        input: &mut Input<'_>,
+    ) -> Result<(Pending, T::Future), Error>
+    where
+        T: Endpoint<A>,
+    {
+        add_vary_accept(input);
+
+        let candidates: Vec<Mime> = match Accept::from_headers(input.request.headers()) {
+            Some(accept) => accept.negotiate(produces).into_iter().cloned().collect(),
+            None => produces.to_vec(),
+        };
+
+        let mut last_err = None;
+        for mime in candidates {
+            let mut cx = ApplyContext::negotiated(input, mime);
+            match endpoint.apply(args, &mut cx) {
+                Ok(future) => return Ok((cx.take_pending(), future)),
+                Err((returned, err)) => {
+                    args = returned;
+                    last_err = Some(err);
+                }
+            }
+        }
+
+        match last_err {
+            Some(err) if err.status() != StatusCode::NOT_ACCEPTABLE => Err(err.into()),
+            _ => {
+                let available: Vec<_> = produces.iter().map(Mime::as_ref).collect();
+                Err(crate::error::custom(
+                    StatusCode::NOT_ACCEPTABLE,
+                    format!(
+                        "no acceptable representation (available: {})",
+                        available.join(", ")
+                    ),
+                ))
+            }
+        }
+    }
+
+    fn add_vary_accept(input: &mut Input<'_>) {
+        input
+            .response()
+            .append(http::header::VARY, "accept")
+            .expect("never fails");
+    }
 }
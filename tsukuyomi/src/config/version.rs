@@ -0,0 +1,339 @@
+//! Components for mounting versioned API scopes.
+//!
+//! # Example
+//!
+//! ```
+//! # use tsukuyomi::{App, config::prelude::*};
+//! use {
+//!     std::time::{Duration, UNIX_EPOCH},
+//!     tsukuyomi::config::version::versioned,
+//! };
+//!
+//! # fn main() -> tsukuyomi::config::Result<()> {
+//! let since = UNIX_EPOCH + Duration::from_secs(1_541_030_400); // 2018-11-01
+//! let sunset = UNIX_EPOCH + Duration::from_secs(1_546_300_800); // 2019-01-01
+//!
+//! let app = App::create(chain![
+//!     versioned("/v1")
+//!         .deprecated(since, sunset)
+//!         .successor("/v2")
+//!         .mount(path!("/users").to(endpoint::reply("users (v1)"))),
+//!     versioned("/v2")
+//!         .mount(path!("/users").to(endpoint::reply("users (v2)"))),
+//! ])?;
+//! # drop(app);
+//! # Ok(())
+//! # }
+//! ```
+
+use {
+    super::{modify, Error, Modify, Mount, Result},
+    crate::{
+        future::{Poll, TryFuture},
+        handler::{AllowedMethods, Handler, ModifyHandler},
+        input::{
+            localmap::{local_key, LocalData},
+            Input,
+        },
+    },
+    http::{
+        header::{HeaderMap, HeaderName, HeaderValue, LINK},
+        StatusCode,
+    },
+    std::{
+        fmt,
+        sync::Arc,
+        time::{SystemTime, UNIX_EPOCH},
+    },
+};
+
+/// Creates a builder of a versioned API scope mounted at the specified prefix.
+///
+/// The version string exposed to the handlers is the prefix without slashes
+/// (e.g. `"v1"` for `"/v1"`).
+pub fn versioned<P>(prefix: P) -> Versioned<P>
+where
+    P: AsRef<str>,
+{
+    Versioned {
+        prefix,
+        deprecation: None,
+        successor: None,
+        gone_after_sunset: false,
+        clock: None,
+    }
+}
+
+/// A builder of a versioned API scope.
+pub struct Versioned<P> {
+    prefix: P,
+    deprecation: Option<(SystemTime, SystemTime)>,
+    successor: Option<String>,
+    gone_after_sunset: bool,
+    clock: Option<Arc<dyn Fn() -> SystemTime + Send + Sync + 'static>>,
+}
+
+impl<P> fmt::Debug for Versioned<P>
+where
+    P: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Versioned")
+            .field("prefix", &self.prefix)
+            .field("deprecation", &self.deprecation)
+            .field("successor", &self.successor)
+            .field("gone_after_sunset", &self.gone_after_sunset)
+            .finish()
+    }
+}
+
+impl<P> Versioned<P>
+where
+    P: AsRef<str>,
+{
+    /// Marks this version as deprecated since `since`, and scheduled to be
+    /// unavailable at `sunset`.
+    ///
+    /// The responses from this scope will carry the header fields
+    /// `Deprecation` and `Sunset`.
+    pub fn deprecated(self, since: SystemTime, sunset: SystemTime) -> Self {
+        Self {
+            deprecation: Some((since, sunset)),
+            ..self
+        }
+    }
+
+    /// Sets the URI of the successor version, which will be notified
+    /// by the `Link` header field with `rel="successor-version"`.
+    pub fn successor(self, uri: impl Into<String>) -> Self {
+        Self {
+            successor: Some(uri.into()),
+            ..self
+        }
+    }
+
+    /// Sets whether to reply `410 Gone` to the requests after the sunset date.
+    ///
+    /// The default value is `false`.
+    pub fn gone_after_sunset(self, enabled: bool) -> Self {
+        Self {
+            gone_after_sunset: enabled,
+            ..self
+        }
+    }
+
+    /// Sets the function that returns the current time.
+    ///
+    /// By default, the current time is read from the clock of the application.
+    /// This is mainly used for replacing it in a particular scope in tests.
+    pub fn clock<F>(self, clock: F) -> Self
+    where
+        F: Fn() -> SystemTime + Send + Sync + 'static,
+    {
+        Self {
+            clock: Some(Arc::new(clock)),
+            ..self
+        }
+    }
+
+    /// Creates a `Config` that mounts the specified configuration
+    /// as the scope of this version.
+    ///
+    /// This method fails if the provided values cannot be
+    /// converted into header values.
+    pub fn mount<T>(self, config: T) -> Result<Mount<P, Modify<VersionHeaders, T>>> {
+        let version = self.prefix.as_ref().trim_matches('/').to_owned();
+
+        let mut headers = HeaderMap::new();
+        if let Some((since, sunset)) = self.deprecation {
+            headers.insert(
+                HeaderName::from_static("deprecation"),
+                http_date(since).map_err(Error::custom)?,
+            );
+            headers.insert(
+                HeaderName::from_static("sunset"),
+                http_date(sunset).map_err(Error::custom)?,
+            );
+        }
+        if let Some(ref successor) = self.successor {
+            headers.insert(
+                LINK,
+                format!("<{}>; rel=\"successor-version\"", successor)
+                    .parse::<HeaderValue>()
+                    .map_err(Error::custom)?,
+            );
+        }
+
+        let sunset = if self.gone_after_sunset {
+            self.deprecation.map(|(_, sunset)| sunset)
+        } else {
+            None
+        };
+
+        let modifier = VersionHeaders {
+            inner: Arc::new(Inner {
+                version: ApiVersion(version.into()),
+                headers,
+                sunset,
+                clock: self.clock,
+            }),
+        };
+
+        Ok(Mount {
+            prefix: self.prefix,
+            config: modify(modifier, config),
+        })
+    }
+}
+
+fn http_date(t: SystemTime) -> std::result::Result<HeaderValue, failure::Error> {
+    let secs = t.duration_since(UNIX_EPOCH)?.as_secs();
+    let tm = time::at_utc(time::Timespec::new(secs as i64, 0));
+    let s = time::strftime("%a, %d %b %Y %T GMT", &tm)?;
+    Ok(s.parse()?)
+}
+
+/// The version string of the API scope that handles the current request.
+///
+/// The value is stored in the request-local map and can be extracted by using
+/// `extractor::local::clone(&ApiVersion::KEY)`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApiVersion(Arc<str>);
+
+impl ApiVersion {
+    /// Returns the string representation of this version.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for ApiVersion {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl fmt::Display for ApiVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl LocalData for ApiVersion {
+    local_key! {
+        /// The local key to manage the API version of the current scope.
+        const KEY: Self;
+    }
+}
+
+/// A `ModifyHandler` that injects the version-specific header fields into
+/// all responses from a versioned scope.
+#[derive(Clone)]
+pub struct VersionHeaders {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    version: ApiVersion,
+    headers: HeaderMap,
+    sunset: Option<SystemTime>,
+    clock: Option<Arc<dyn Fn() -> SystemTime + Send + Sync + 'static>>,
+}
+
+impl fmt::Debug for VersionHeaders {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VersionHeaders")
+            .field("version", &self.inner.version)
+            .field("headers", &self.inner.headers)
+            .field("sunset", &self.inner.sunset)
+            .finish()
+    }
+}
+
+impl<H> ModifyHandler<H> for VersionHeaders
+where
+    H: Handler,
+{
+    type Output = H::Output;
+    type Handler = VersionHeadersHandler<H>; // private
+
+    fn modify(&self, inner: H) -> Self::Handler {
+        VersionHeadersHandler {
+            inner,
+            modifier: self.clone(),
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct VersionHeadersHandler<H> {
+    inner: H,
+    modifier: VersionHeaders,
+}
+
+impl<H> Handler for VersionHeadersHandler<H>
+where
+    H: Handler,
+{
+    type Output = H::Output;
+    type Error = crate::Error;
+    type Handle = HandleVersionHeaders<H::Handle>;
+
+    fn allowed_methods(&self) -> Option<&AllowedMethods> {
+        self.inner.allowed_methods()
+    }
+
+    fn handle(&self) -> Self::Handle {
+        HandleVersionHeaders {
+            inner: self.inner.handle(),
+            modifier: Some(self.modifier.clone()),
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct HandleVersionHeaders<H> {
+    inner: H,
+    modifier: Option<VersionHeaders>,
+}
+
+impl<H> TryFuture for HandleVersionHeaders<H>
+where
+    H: TryFuture,
+{
+    type Ok = H::Ok;
+    type Error = crate::Error;
+
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        if let Some(modifier) = self.modifier.take() {
+            let inner = &*modifier.inner;
+
+            // `Link` may have the other relations, so it is merged with the handler's one.
+            let response = input.response();
+            for (name, value) in &inner.headers {
+                if *name == LINK {
+                    response.append(name.clone(), value.clone())?;
+                } else {
+                    response.set(name.clone(), value.clone())?;
+                }
+            }
+
+            inner.version.clone().insert_into(input.locals);
+
+            if let Some(sunset) = inner.sunset {
+                let now = match inner.clock {
+                    Some(ref clock) => clock(),
+                    None => input.clock().system_now(),
+                };
+                if now >= sunset {
+                    return Err(crate::error::custom(
+                        StatusCode::GONE,
+                        format!("the API version '{}' is no longer available", inner.version),
+                    ));
+                }
+            }
+        }
+
+        self.inner.poll_ready(input).map_err(Into::into)
+    }
+}
@@ -0,0 +1,184 @@
+//! Configuration values which can be replaced while the application is running.
+//!
+//! A [`DynamicConfig`] is registered as a state of the scopes (or captured by the
+//! handlers and modifiers), and its current value is read on each request.
+//! The values are replaced by [`ConfigSet::reload`], which is typically called
+//! periodically by the server.
+//!
+//! [`DynamicConfig`]: ./struct.DynamicConfig.html
+//! [`ConfigSet::reload`]: ./struct.ConfigSet.html#method.reload
+
+use {
+    serde::de::DeserializeOwned,
+    serde_json::Value,
+    std::{
+        collections::HashMap,
+        fmt, fs,
+        path::{Path, PathBuf},
+        sync::{Arc, Mutex, RwLock},
+    },
+};
+
+/// A shared cell containing a configuration value.
+///
+/// The clones of this value refer to the same cell, so the value replaced
+/// through one of them is observed by all others.
+pub struct DynamicConfig<T> {
+    cell: Arc<RwLock<Arc<T>>>,
+}
+
+impl<T> fmt::Debug for DynamicConfig<T>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("DynamicConfig").field(&*self.get()).finish()
+    }
+}
+
+impl<T> Clone for DynamicConfig<T> {
+    fn clone(&self) -> Self {
+        Self {
+            cell: self.cell.clone(),
+        }
+    }
+}
+
+impl<T> DynamicConfig<T> {
+    /// Creates a `DynamicConfig` with the specified initial value.
+    pub fn new(value: T) -> Self {
+        Self {
+            cell: Arc::new(RwLock::new(Arc::new(value))),
+        }
+    }
+
+    /// Returns the current value.
+    ///
+    /// The returned value is not affected by the subsequent replacements.
+    pub fn get(&self) -> Arc<T> {
+        self.cell
+            .read()
+            .unwrap_or_else(|err| err.into_inner())
+            .clone()
+    }
+
+    /// Replaces the current value.
+    pub fn set(&self, value: T) {
+        *self.cell.write().unwrap_or_else(|err| err.into_inner()) = Arc::new(value);
+    }
+}
+
+trait Slot: Send + Sync + 'static {
+    /// Validates the new value, and returns the function to apply it.
+    ///
+    /// The returned function applies the value only at the first call.
+    fn prepare(&self, value: &Value) -> serde_json::Result<Box<dyn FnMut()>>;
+}
+
+impl<T> Slot for DynamicConfig<T>
+where
+    T: DeserializeOwned + Send + Sync + 'static,
+{
+    fn prepare(&self, value: &Value) -> serde_json::Result<Box<dyn FnMut()>> {
+        let mut value = Some(T::deserialize(value)?);
+        let cell = self.clone();
+        Ok(Box::new(move || {
+            if let Some(value) = value.take() {
+                cell.set(value);
+            }
+        }))
+    }
+}
+
+/// A set of `DynamicConfig`s associated with the keys in a configuration file.
+///
+/// The configuration file is a JSON object whose top-level keys correspond to
+/// the registered `DynamicConfig`s. The keys which are missing in the file are
+/// left unchanged.
+pub struct ConfigSet {
+    path: PathBuf,
+    slots: Vec<(String, Box<dyn Slot>)>,
+    applied: Mutex<HashMap<String, Value>>,
+}
+
+impl fmt::Debug for ConfigSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConfigSet")
+            .field("path", &self.path)
+            .field(
+                "keys",
+                &self.slots.iter().map(|(key, _)| key).collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl ConfigSet {
+    /// Creates an empty `ConfigSet` which reads the specified configuration file.
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            slots: vec![],
+            applied: Mutex::default(),
+        }
+    }
+
+    /// Associates a `DynamicConfig` with the specified key in the configuration file.
+    pub fn register<T>(mut self, key: impl Into<String>, config: &DynamicConfig<T>) -> Self
+    where
+        T: DeserializeOwned + Send + Sync + 'static,
+    {
+        self.slots.push((key.into(), Box::new(config.clone())));
+        self
+    }
+
+    /// Re-reads the configuration file and replaces the values which have been changed.
+    ///
+    /// If the file cannot be read or any of the values is invalid, none of the values
+    /// are replaced. The applied changes and the errors are logged, and the number of
+    /// the replaced values is returned.
+    pub fn reload(&self) -> Result<usize, failure::Error> {
+        let result = self.try_reload();
+        if let Err(ref err) = result {
+            log::error!(
+                "failed to reload the configuration file {}: {}",
+                self.path.display(),
+                err
+            );
+        }
+        result
+    }
+
+    fn try_reload(&self) -> Result<usize, failure::Error> {
+        let content = fs::read_to_string(&self.path)?;
+        let mut values = match serde_json::from_str(&content)? {
+            Value::Object(values) => values,
+            _ => failure::bail!("the configuration must be a JSON object"),
+        };
+
+        let mut applied = self.applied.lock().unwrap_or_else(|err| err.into_inner());
+        let mut changes = vec![];
+        for (key, slot) in &self.slots {
+            let value = match values.remove(key) {
+                Some(value) => value,
+                None => continue,
+            };
+            if applied.get(key) == Some(&value) {
+                continue;
+            }
+            let apply = slot.prepare(&value).map_err(|err| {
+                failure::format_err!("invalid value for the key `{}': {}", key, err)
+            })?;
+            changes.push((key, value, apply));
+        }
+
+        let num_changes = changes.len();
+        for (key, value, mut apply) in changes {
+            apply();
+            log::info!("applied the configuration `{}' = {}", key, value);
+            applied.insert(key.clone(), value);
+        }
+
+        Ok(num_changes)
+    }
+}
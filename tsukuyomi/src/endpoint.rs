@@ -1,8 +1,20 @@
 //! Definition of `Endpoint`.
 
 use {
-    crate::{error::Error, future::TryFuture, handler::AllowedMethods, input::Input},
-    http::{Method, StatusCode},
+    crate::{
+        app::RequestSizeLimit,
+        error::Error,
+        future::TryFuture,
+        guard::{Pending, Verify},
+        handler::AllowedMethods,
+        input::{localmap::LocalData, Input},
+        output::ResponseBody,
+        responder::Responder,
+        rt::Clock,
+    },
+    http::{Method, Request, Response, StatusCode},
+    mime::Mime,
+    std::sync::Arc,
 };
 
 /// A trait representing the process to be performed when a route matches.
@@ -23,16 +35,71 @@ pub trait Endpoint<T> {
     /// This method is called when constructing a `Handler` and used for implementation of
     /// `Handler::allowed_methods`.
     fn allowed_methods(&self) -> Option<AllowedMethods>;
+
+    /// Returns a list of media types that this endpoint produces.
+    ///
+    /// The list is assembled from the guards registered to the endpoint, and used
+    /// for negotiating the response type with `Accept` when the route is matched.
+    /// The default implementation returns an empty list, which disables the negotiation.
+    fn produces(&self) -> Vec<Mime> {
+        vec![]
+    }
 }
 
 #[derive(Debug)]
 pub struct ApplyContext<'a, 'task: 'a> {
     input: &'a mut Input<'task>,
+    negotiated_type: Option<Mime>,
+    pending: Pending,
+    max_declared_length: Option<u64>,
 }
 
 impl<'a, 'task> ApplyContext<'a, 'task> {
     pub(crate) fn new(input: &'a mut Input<'task>) -> Self {
-        Self { input }
+        Self {
+            input,
+            negotiated_type: None,
+            pending: Pending::default(),
+            max_declared_length: None,
+        }
+    }
+
+    pub(crate) fn negotiated(input: &'a mut Input<'task>, negotiated_type: Mime) -> Self {
+        Self {
+            input,
+            negotiated_type: Some(negotiated_type),
+            pending: Pending::default(),
+            max_declared_length: None,
+        }
+    }
+
+    /// Appends the asynchronous checks of the guards which passed.
+    pub(crate) fn defer(&mut self, verify: impl IntoIterator<Item = Verify>) {
+        self.pending.extend(verify);
+    }
+
+    /// Overrides the limit of the declared length of the request body set by `RequestSizeLimit`.
+    pub(crate) fn set_max_declared_length(&mut self, limit: u64) {
+        self.max_declared_length = Some(limit);
+    }
+
+    /// Takes the asynchronous checks of the guards, which have to be completed before
+    /// the applied endpoint is polled.
+    ///
+    /// The check of the declared length of the request body is appended to them.
+    pub(crate) fn take_pending(&mut self) -> Pending {
+        let limit = self.max_declared_length.take().or_else(|| {
+            self.input
+                .locals
+                .get(&RequestSizeLimit::KEY)
+                .map(RequestSizeLimit::limit)
+        });
+        if let Some(limit) = limit {
+            if let Err(err) = crate::app::check_declared_length(self.input.request, limit) {
+                self.defer(Some(Box::new(futures01::future::err(err)) as Verify));
+            }
+        }
+        std::mem::replace(&mut self.pending, Pending::default())
     }
 
     /// Returns HTTP method of the request.
@@ -40,21 +107,58 @@ impl<'a, 'task> ApplyContext<'a, 'task> {
     pub fn method(&self) -> &Method {
         self.input.request.method()
     }
+
+    /// Returns a reference to the incoming request.
+    #[inline]
+    pub fn request(&self) -> &Request<()> {
+        self.input.request
+    }
+
+    /// Returns the media type selected by the negotiation with `Accept`, if any.
+    ///
+    /// The value is available only if some of the endpoints on the route produce
+    /// specific media types.
+    #[inline]
+    pub fn negotiated_type(&self) -> Option<&Mime> {
+        self.negotiated_type.as_ref()
+    }
+
+    /// Returns the clock of the application.
+    #[inline]
+    pub fn clock(&self) -> &Arc<dyn Clock> {
+        self.input.clock()
+    }
 }
 
 #[derive(Debug)]
-pub struct ApplyError(());
+pub struct ApplyError(StatusCode);
 
 impl ApplyError {
     #[inline]
     pub fn method_not_allowed() -> ApplyError {
-        ApplyError(())
+        ApplyError(StatusCode::METHOD_NOT_ALLOWED)
+    }
+
+    #[inline]
+    pub fn not_acceptable() -> ApplyError {
+        ApplyError(StatusCode::NOT_ACCEPTABLE)
+    }
+
+    #[inline]
+    pub fn misdirected_request() -> ApplyError {
+        ApplyError(StatusCode::MISDIRECTED_REQUEST)
+    }
+
+    /// Returns the status code that this error will be converted into.
+    #[inline]
+    pub fn status(&self) -> StatusCode {
+        self.0
     }
 }
 
 impl From<ApplyError> for Error {
-    fn from(_err: ApplyError) -> Self {
-        StatusCode::METHOD_NOT_ALLOWED.into()
+    fn from(err: ApplyError) -> Self {
+        err.0.into()
     }
 }
 
@@ -65,6 +169,17 @@ pub fn endpoint<T, R>(
     apply: impl Fn(T, &mut ApplyContext<'_, '_>) -> Result<R, (T, ApplyError)>,
     allowed_methods: Option<AllowedMethods>,
 ) -> impl Endpoint<T, Output = R::Ok, Error = R::Error, Future = R>
+where
+    R: TryFuture,
+{
+    endpoint_with_produces(apply, allowed_methods, vec![])
+}
+
+pub(crate) fn endpoint_with_produces<T, R>(
+    apply: impl Fn(T, &mut ApplyContext<'_, '_>) -> Result<R, (T, ApplyError)>,
+    allowed_methods: Option<AllowedMethods>,
+    produces: Vec<Mime>,
+) -> impl Endpoint<T, Output = R::Ok, Error = R::Error, Future = R>
 where
     R: TryFuture,
 {
@@ -72,6 +187,7 @@ where
     struct ApplyFn<F> {
         apply: F,
         allowed_methods: Option<AllowedMethods>,
+        produces: Vec<Mime>,
     }
 
     impl<F, T, R> Endpoint<T> for ApplyFn<F>
@@ -92,11 +208,17 @@ where
         fn allowed_methods(&self) -> Option<AllowedMethods> {
             self.allowed_methods.clone()
         }
+
+        #[inline]
+        fn produces(&self) -> Vec<Mime> {
+            self.produces.clone()
+        }
     }
 
     ApplyFn {
         apply,
         allowed_methods,
+        produces,
     }
 }
 
@@ -117,6 +239,11 @@ where
     fn allowed_methods(&self) -> Option<AllowedMethods> {
         (**self).allowed_methods()
     }
+
+    #[inline]
+    fn produces(&self) -> Vec<Mime> {
+        (**self).produces()
+    }
 }
 
 impl<E, T> Endpoint<T> for std::sync::Arc<E>
@@ -136,6 +263,437 @@ where
     fn allowed_methods(&self) -> Option<AllowedMethods> {
         (**self).allowed_methods()
     }
+
+    #[inline]
+    fn produces(&self) -> Vec<Mime> {
+        (**self).produces()
+    }
+}
+
+/// A set of extension methods for composing `Endpoint`s.
+pub trait EndpointExt<T>: Endpoint<T> + Sized {
+    /// Creates an `Endpoint` that recovers from the errors occurred in this endpoint.
+    ///
+    /// The provided function is called with the error returned from the
+    /// extractors or the handler of this endpoint, and may convert it into
+    /// an output value.  If the function returns an `Err`, it is passed
+    /// through to the normal error path.
+    ///
+    /// Note that the errors that occur before starting the endpoint, such as
+    /// the method mismatch, are not handled by this function.
+    fn or_else<F>(self, f: F) -> OrElse<Self, F>
+    where
+        F: Fn(Error, &Input<'_>) -> Result<Self::Output, Error> + Clone,
+    {
+        OrElse { endpoint: self, f }
+    }
+
+    /// Creates an `Endpoint` that modifies the response of this endpoint.
+    ///
+    /// The output of this endpoint is converted into a `Response` first, and
+    /// then passed to the provided function with a reference to `Input`.
+    /// The returned response is passed to the modifiers of the enclosing scopes.
+    fn map_output<F>(self, f: F) -> MapOutput<Self, F>
+    where
+        Self::Output: Responder,
+        F: Fn(Response<ResponseBody>, &Input<'_>) -> Response<ResponseBody> + Clone,
+    {
+        MapOutput { endpoint: self, f }
+    }
+
+    /// Creates an `Endpoint` that modifies the response of this endpoint with a fallible function.
+    ///
+    /// This is the same as `map_output`, except that the error returned from the
+    /// provided function is passed through to the normal error path.
+    fn try_map_output<F>(self, f: F) -> TryMapOutput<Self, F>
+    where
+        Self::Output: Responder,
+        F: Fn(Response<ResponseBody>, &Input<'_>) -> Result<Response<ResponseBody>, Error> + Clone,
+    {
+        TryMapOutput { endpoint: self, f }
+    }
+
+    /// Converts this endpoint into a type-erased `BoxedEndpoint`.
+    ///
+    /// The output is converted into a `Response` after the completion, so that
+    /// the endpoints with different types can be stored in a collection.
+    fn boxed(self) -> BoxedEndpoint
+    where
+        Self: Endpoint<()> + Send + Sync + 'static,
+        <Self as Endpoint<()>>::Output: Responder,
+        <<Self as Endpoint<()>>::Output as Responder>::Respond: Send + 'static,
+        <Self as Endpoint<()>>::Future: Send + 'static,
+    {
+        BoxedEndpoint::new(self)
+    }
+}
+
+impl<E, T> EndpointExt<T> for E where E: Endpoint<T> {}
+
+pub use self::{
+    boxed::{BoxedEndpoint, BoxedEndpointFuture},
+    map_output::{MapOutput, TryMapOutput},
+    or_else::OrElse,
+    split::{split, Side, Split, SplitEndpoint},
+};
+
+mod split;
+
+mod or_else {
+    use {
+        super::{ApplyContext, ApplyResult, Endpoint},
+        crate::{
+            error::Error,
+            future::{Async, Poll, TryFuture},
+            handler::AllowedMethods,
+            input::Input,
+        },
+        mime::Mime,
+    };
+
+    #[derive(Debug)]
+    pub struct OrElse<E, F> {
+        pub(super) endpoint: E,
+        pub(super) f: F,
+    }
+
+    impl<E, F, T> Endpoint<T> for OrElse<E, F>
+    where
+        E: Endpoint<T>,
+        F: Fn(Error, &Input<'_>) -> Result<E::Output, Error> + Clone,
+    {
+        type Output = E::Output;
+        type Error = Error;
+        type Future = OrElseFuture<E::Future, F>;
+
+        #[inline]
+        fn apply(&self, args: T, cx: &mut ApplyContext<'_, '_>) -> ApplyResult<T, Self> {
+            self.endpoint.apply(args, cx).map(|future| OrElseFuture {
+                future,
+                f: self.f.clone(),
+            })
+        }
+
+        #[inline]
+        fn allowed_methods(&self) -> Option<AllowedMethods> {
+            self.endpoint.allowed_methods()
+        }
+
+        #[inline]
+        fn produces(&self) -> Vec<Mime> {
+            self.endpoint.produces()
+        }
+    }
+
+    #[allow(missing_debug_implementations)]
+    pub struct OrElseFuture<Fut, F> {
+        future: Fut,
+        f: F,
+    }
+
+    impl<Fut, F> TryFuture for OrElseFuture<Fut, F>
+    where
+        Fut: TryFuture,
+        F: Fn(Error, &Input<'_>) -> Result<Fut::Ok, Error>,
+    {
+        type Ok = Fut::Ok;
+        type Error = Error;
+
+        #[inline]
+        fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+            match self.future.poll_ready(input) {
+                Ok(async_) => Ok(async_),
+                Err(err) => (self.f)(err.into(), &*input).map(Async::Ready),
+            }
+        }
+    }
+}
+
+mod map_output {
+    use {
+        super::{ApplyContext, ApplyResult, Endpoint},
+        crate::{
+            error::Error,
+            future::{Async, Poll, TryFuture},
+            handler::AllowedMethods,
+            input::Input,
+            output::{IntoResponse, ResponseBody},
+            responder::Responder,
+        },
+        http::Response,
+        mime::Mime,
+    };
+
+    #[derive(Debug)]
+    pub struct MapOutput<E, F> {
+        pub(super) endpoint: E,
+        pub(super) f: F,
+    }
+
+    impl<E, F, T> Endpoint<T> for MapOutput<E, F>
+    where
+        E: Endpoint<T>,
+        E::Output: Responder,
+        F: Fn(Response<ResponseBody>, &Input<'_>) -> Response<ResponseBody> + Clone,
+    {
+        type Output = Response<ResponseBody>;
+        type Error = Error;
+        type Future = MapOutputFuture<E::Future, F>;
+
+        #[inline]
+        fn apply(&self, args: T, cx: &mut ApplyContext<'_, '_>) -> ApplyResult<T, Self> {
+            self.endpoint.apply(args, cx).map(|future| MapOutputFuture {
+                respond: RespondFuture::Endpoint(future),
+                f: self.f.clone(),
+            })
+        }
+
+        #[inline]
+        fn allowed_methods(&self) -> Option<AllowedMethods> {
+            self.endpoint.allowed_methods()
+        }
+
+        #[inline]
+        fn produces(&self) -> Vec<Mime> {
+            self.endpoint.produces()
+        }
+    }
+
+    #[allow(missing_debug_implementations)]
+    pub struct MapOutputFuture<Fut: TryFuture, F>
+    where
+        Fut::Ok: Responder,
+    {
+        respond: RespondFuture<Fut>,
+        f: F,
+    }
+
+    impl<Fut, F> TryFuture for MapOutputFuture<Fut, F>
+    where
+        Fut: TryFuture,
+        Fut::Ok: Responder,
+        F: Fn(Response<ResponseBody>, &Input<'_>) -> Response<ResponseBody>,
+    {
+        type Ok = Response<ResponseBody>;
+        type Error = Error;
+
+        #[inline]
+        fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+            let response = futures01::try_ready!(self.respond.poll_ready(input));
+            Ok(Async::Ready((self.f)(response, &*input)))
+        }
+    }
+
+    #[derive(Debug)]
+    pub struct TryMapOutput<E, F> {
+        pub(super) endpoint: E,
+        pub(super) f: F,
+    }
+
+    impl<E, F, T> Endpoint<T> for TryMapOutput<E, F>
+    where
+        E: Endpoint<T>,
+        E::Output: Responder,
+        F: Fn(Response<ResponseBody>, &Input<'_>) -> Result<Response<ResponseBody>, Error> + Clone,
+    {
+        type Output = Response<ResponseBody>;
+        type Error = Error;
+        type Future = TryMapOutputFuture<E::Future, F>;
+
+        #[inline]
+        fn apply(&self, args: T, cx: &mut ApplyContext<'_, '_>) -> ApplyResult<T, Self> {
+            self.endpoint
+                .apply(args, cx)
+                .map(|future| TryMapOutputFuture {
+                    respond: RespondFuture::Endpoint(future),
+                    f: self.f.clone(),
+                })
+        }
+
+        #[inline]
+        fn allowed_methods(&self) -> Option<AllowedMethods> {
+            self.endpoint.allowed_methods()
+        }
+
+        #[inline]
+        fn produces(&self) -> Vec<Mime> {
+            self.endpoint.produces()
+        }
+    }
+
+    #[allow(missing_debug_implementations)]
+    pub struct TryMapOutputFuture<Fut: TryFuture, F>
+    where
+        Fut::Ok: Responder,
+    {
+        respond: RespondFuture<Fut>,
+        f: F,
+    }
+
+    impl<Fut, F> TryFuture for TryMapOutputFuture<Fut, F>
+    where
+        Fut: TryFuture,
+        Fut::Ok: Responder,
+        F: Fn(Response<ResponseBody>, &Input<'_>) -> Result<Response<ResponseBody>, Error>,
+    {
+        type Ok = Response<ResponseBody>;
+        type Error = Error;
+
+        #[inline]
+        fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+            let response = futures01::try_ready!(self.respond.poll_ready(input));
+            (self.f)(response, &*input).map(Async::Ready)
+        }
+    }
+
+    /// A future that converts the output of the endpoint into a `Response`.
+    pub(super) enum RespondFuture<Fut: TryFuture>
+    where
+        Fut::Ok: Responder,
+    {
+        Endpoint(Fut),
+        Respond(<Fut::Ok as Responder>::Respond),
+    }
+
+    impl<Fut> RespondFuture<Fut>
+    where
+        Fut: TryFuture,
+        Fut::Ok: Responder,
+    {
+        pub(super) fn poll_ready(
+            &mut self,
+            input: &mut Input<'_>,
+        ) -> Poll<Response<ResponseBody>, Error> {
+            loop {
+                *self = match self {
+                    RespondFuture::Endpoint(future) => {
+                        let output =
+                            futures01::try_ready!(future.poll_ready(input).map_err(Into::into));
+                        RespondFuture::Respond(output.respond())
+                    }
+                    RespondFuture::Respond(respond) => {
+                        let output =
+                            futures01::try_ready!(respond.poll_ready(input).map_err(Into::into));
+                        let response = output
+                            .into_response(input.request)
+                            .map_err(Into::into)?
+                            .map(Into::into);
+                        return Ok(Async::Ready(response));
+                    }
+                };
+            }
+        }
+    }
+}
+
+mod boxed {
+    use {
+        super::{map_output::RespondFuture, ApplyContext, ApplyError, ApplyResult, Endpoint},
+        crate::{
+            error::Error,
+            future::{Poll, TryFuture},
+            handler::AllowedMethods,
+            input::Input,
+            output::ResponseBody,
+            responder::Responder,
+        },
+        http::Response,
+        mime::Mime,
+        std::fmt,
+    };
+
+    type BoxedPoll =
+        dyn FnMut(&mut Input<'_>) -> Poll<Response<ResponseBody>, Error> + Send + 'static;
+
+    type BoxedApply = dyn Fn(&mut ApplyContext<'_, '_>) -> Result<BoxedEndpointFuture, ApplyError>
+        + Send
+        + Sync
+        + 'static;
+
+    /// A type-erased `Endpoint` whose output is converted into a `Response`.
+    ///
+    /// The value is created by `EndpointExt::boxed`, and registered with a path
+    /// constructed at runtime by `Path::<()>::new(path).to(endpoint)`.  Since the
+    /// path parameters are not passed to the boxed endpoint, the original endpoint
+    /// must not take any arguments.
+    pub struct BoxedEndpoint {
+        apply: Box<BoxedApply>,
+        allowed_methods: Option<AllowedMethods>,
+        produces: Vec<Mime>,
+    }
+
+    impl fmt::Debug for BoxedEndpoint {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("BoxedEndpoint")
+                .field("allowed_methods", &self.allowed_methods)
+                .field("produces", &self.produces)
+                .finish()
+        }
+    }
+
+    impl BoxedEndpoint {
+        pub(super) fn new<E>(endpoint: E) -> Self
+        where
+            E: Endpoint<()> + Send + Sync + 'static,
+            E::Output: Responder,
+            <E::Output as Responder>::Respond: Send + 'static,
+            E::Future: Send + 'static,
+        {
+            let allowed_methods = endpoint.allowed_methods();
+            let produces = endpoint.produces();
+            Self {
+                apply: Box::new(move |cx| {
+                    let mut respond =
+                        RespondFuture::Endpoint(endpoint.apply((), cx).map_err(|((), err)| err)?);
+                    Ok(BoxedEndpointFuture(Box::new(move |input| {
+                        respond.poll_ready(input)
+                    })))
+                }),
+                allowed_methods,
+                produces,
+            }
+        }
+    }
+
+    impl Endpoint<()> for BoxedEndpoint {
+        type Output = Response<ResponseBody>;
+        type Error = Error;
+        type Future = BoxedEndpointFuture;
+
+        #[inline]
+        fn apply(&self, args: (), cx: &mut ApplyContext<'_, '_>) -> ApplyResult<(), Self> {
+            (self.apply)(cx).map_err(|err| (args, err))
+        }
+
+        #[inline]
+        fn allowed_methods(&self) -> Option<AllowedMethods> {
+            self.allowed_methods.clone()
+        }
+
+        #[inline]
+        fn produces(&self) -> Vec<Mime> {
+            self.produces.clone()
+        }
+    }
+
+    /// The type of future returned from `BoxedEndpoint`.
+    pub struct BoxedEndpointFuture(Box<BoxedPoll>);
+
+    impl fmt::Debug for BoxedEndpointFuture {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("BoxedEndpointFuture").finish()
+        }
+    }
+
+    impl TryFuture for BoxedEndpointFuture {
+        type Ok = Response<ResponseBody>;
+        type Error = Error;
+
+        #[inline]
+        fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+            (self.0)(input)
+        }
+    }
 }
 
 mod impl_chain {
@@ -148,6 +706,7 @@ mod impl_chain {
             input::Input,
             util::{Chain, Either},
         },
+        mime::Mime,
     };
 
     impl<L, R, T> Endpoint<T> for Chain<L, R>
@@ -171,6 +730,17 @@ mod impl_chain {
             let right = self.right.allowed_methods()?;
             Some(left.iter().chain(right.iter()).cloned().collect())
         }
+
+        #[inline]
+        fn produces(&self) -> Vec<Mime> {
+            let mut produces = self.left.produces();
+            for mime in self.right.produces() {
+                if !produces.contains(&mime) {
+                    produces.push(mime);
+                }
+            }
+            produces
+        }
     }
 
     #[derive(Debug)]
@@ -0,0 +1,260 @@
+//! Splitting the requests of a route between two versions of an endpoint.
+
+use {
+    super::{impl_chain::ChainFuture, ApplyContext, ApplyResult, Endpoint},
+    crate::{
+        dynamic::DynamicConfig,
+        featureflags::KeySource,
+        handler::AllowedMethods,
+        input::localmap::{local_key, LocalData},
+        util::Either,
+    },
+    http::header::{HeaderName, HeaderValue},
+    mime::Mime,
+};
+
+/// The number of the buckets used for the percentage of the new side.
+const BUCKETS: u64 = 10_000;
+
+/// The name hashed together with the keys, to make the assignment independent
+/// of the ones of the feature flags.
+const HASH_NAME: &str = "split";
+
+/// The side of a [`split`] endpoint which handled the request.
+///
+/// The value is stored into the local map when the endpoint is applied.
+///
+/// [`split`]: ./fn.split.html
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Side {
+    /// The current endpoint.
+    Old,
+    /// The endpoint being released.
+    New,
+}
+
+impl Side {
+    /// Returns the name of this side, `"old"` or `"new"`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Side::Old => "old",
+            Side::New => "new",
+        }
+    }
+}
+
+impl LocalData for Side {
+    local_key! {
+        /// The local key for the side of the split endpoint chosen for the request.
+        const KEY: Self;
+    }
+}
+
+/// The strategy to choose the side of a [`split`] endpoint for each request.
+///
+/// [`split`]: ./fn.split.html
+#[derive(Debug, Clone)]
+pub struct Split {
+    key: Option<KeySource>,
+    percent: DynamicConfig<f64>,
+    force: Option<(HeaderName, HeaderValue)>,
+    annotation: Option<HeaderName>,
+}
+
+impl Split {
+    /// Creates a `Split` which sends the specified percentage of the keys to the new side.
+    ///
+    /// The side is decided by the stable hash of the key, so the requests with the
+    /// same key are always handled by the same side as long as the percentage is
+    /// unchanged. The requests without the key are handled by the old side.
+    pub fn percentage(key: KeySource, percent: f64) -> Self {
+        Self::dynamic(key, DynamicConfig::new(percent))
+    }
+
+    /// Creates a `Split` whose percentage is read from the `DynamicConfig` on each request.
+    pub fn dynamic(key: KeySource, percent: DynamicConfig<f64>) -> Self {
+        Self {
+            key: Some(key),
+            percent,
+            force: None,
+            annotation: Some(HeaderName::from_static("x-split-side")),
+        }
+    }
+
+    /// Creates a `Split` which sends only the requests with the specified header
+    /// field, such as `X-Canary: always`, to the new side.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if the name or the value is invalid.
+    pub fn header(name: &'static str, value: &'static str) -> Self {
+        Self {
+            key: None,
+            percent: DynamicConfig::new(0.0),
+            force: None,
+            annotation: Some(HeaderName::from_static("x-split-side")),
+        }
+        .force_header(name, value)
+    }
+
+    /// Sets the header field which forces the new side regardless of the percentage.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if the name or the value is invalid.
+    pub fn force_header(self, name: &'static str, value: &'static str) -> Self {
+        Self {
+            force: Some((
+                HeaderName::from_static(name),
+                HeaderValue::from_static(value),
+            )),
+            ..self
+        }
+    }
+
+    /// Sets the name of the response header field to which the chosen side is added.
+    ///
+    /// The default value is `x-split-side`.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if the name is invalid.
+    pub fn annotate(self, name: &'static str) -> Self {
+        Self {
+            annotation: Some(HeaderName::from_static(name)),
+            ..self
+        }
+    }
+
+    /// Disables the response header field annotating the chosen side.
+    pub fn no_annotation(self) -> Self {
+        Self {
+            annotation: None,
+            ..self
+        }
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn decide(&self, cx: &mut ApplyContext<'_, '_>) -> Side {
+        if let Some((ref name, ref value)) = self.force {
+            if cx
+                .request()
+                .headers()
+                .get_all(name)
+                .iter()
+                .any(|v| v == value)
+            {
+                return Side::New;
+            }
+        }
+
+        let key = match self.key {
+            // The failure of parsing the cookies is regarded as the missing key.
+            Some(ref source) => source.extract(cx.input).ok().and_then(|key| key),
+            None => None,
+        };
+        let percent = *self.percent.get();
+        match key {
+            Some(ref key)
+                if ((crate::featureflags::hash(HASH_NAME, key) % BUCKETS) as f64)
+                    < percent * 100.0 =>
+            {
+                Side::New
+            }
+            _ => Side::Old,
+        }
+    }
+}
+
+/// Creates an `Endpoint` that sends each request to either `old` or `new`, as
+/// decided by `split`.
+///
+/// The chosen side is stored into the local map as [`Side`], and added to the
+/// response as the header field configured by `Split::annotate`.
+///
+/// ```
+/// # use tsukuyomi::{
+/// #     config::prelude::*,
+/// #     endpoint::Split,
+/// #     featureflags::KeySource,
+/// #     App,
+/// # };
+/// let app = App::create(
+///     path!("/checkout").to(endpoint::split(
+///         endpoint::get().reply("old checkout"),
+///         endpoint::get().reply("new checkout"),
+///         Split::percentage(KeySource::cookie("session-id"), 5.0)
+///             .force_header("x-canary", "always"),
+///     ))
+/// );
+/// # drop(app);
+/// ```
+///
+/// # Panics
+///
+/// The registration of the route panics if `old` and `new` do not accept the same methods.
+///
+/// [`Side`]: ./enum.Side.html
+pub fn split<L, R>(old: L, new: R, split: Split) -> SplitEndpoint<L, R> {
+    SplitEndpoint { old, new, split }
+}
+
+/// An `Endpoint` created by [`split`].
+///
+/// [`split`]: ./fn.split.html
+#[derive(Debug)]
+pub struct SplitEndpoint<L, R> {
+    old: L,
+    new: R,
+    split: Split,
+}
+
+impl<L, R, T> Endpoint<T> for SplitEndpoint<L, R>
+where
+    L: Endpoint<T>,
+    R: Endpoint<T>,
+{
+    type Output = Either<L::Output, R::Output>;
+    type Error = crate::error::Error;
+    type Future = ChainFuture<L::Future, R::Future>;
+
+    fn apply(&self, args: T, cx: &mut ApplyContext<'_, '_>) -> ApplyResult<T, Self> {
+        let side = self.split.decide(cx);
+        side.insert_into(cx.input.locals);
+        if let Some(ref name) = self.split.annotation {
+            // The value is always valid, and the name is checked when configured.
+            let _ = cx.input.response().set(name.clone(), side.as_str());
+        }
+        match side {
+            Side::Old => self.old.apply(args, cx).map(ChainFuture::Left),
+            Side::New => self.new.apply(args, cx).map(ChainFuture::Right),
+        }
+    }
+
+    fn allowed_methods(&self) -> Option<AllowedMethods> {
+        let old = self.old.allowed_methods();
+        let new = self.new.allowed_methods();
+        let same = match (&old, &new) {
+            (Some(old), Some(new)) => {
+                old.iter().all(|m| new.contains(m)) && new.iter().all(|m| old.contains(m))
+            }
+            (None, None) => true,
+            _ => false,
+        };
+        assert!(
+            same,
+            "the endpoints of `split` must accept the same methods"
+        );
+        old
+    }
+
+    fn produces(&self) -> Vec<Mime> {
+        let mut produces = self.old.produces();
+        for mime in self.new.produces() {
+            if !produces.contains(&mime) {
+                produces.push(mime);
+            }
+        }
+        produces
+    }
+}
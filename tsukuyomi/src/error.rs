@@ -8,7 +8,7 @@
 //! [`HttpError`]: ./trait.HttpError.html
 
 use {
-    crate::{output::ResponseBody, util::Never},
+    crate::{i18n::Args, output::ResponseBody, util::Never},
     http::{Request, Response, StatusCode},
     std::{any::Any, fmt, io},
 };
@@ -28,6 +28,19 @@ pub trait HttpError: fmt::Display + fmt::Debug + Send + 'static + Sized {
 
     /// Consumes itself and creates an HTTP response from its value.
     fn into_response(self, request: &Request<()>) -> Response<Self::Body>;
+
+    /// Returns the key of the message used for localizing the response body.
+    ///
+    /// If a `Translator` is registered and translates this key, the body of the
+    /// response is replaced with the translated text.
+    fn message_key(&self) -> Option<&str> {
+        None
+    }
+
+    /// Returns the arguments passed to the `Translator` with the message key.
+    fn message_args(&self) -> Args {
+        Args::new()
+    }
 }
 
 impl HttpError for StatusCode {
@@ -38,6 +51,18 @@ impl HttpError for StatusCode {
         *response.status_mut() = self;
         response
     }
+
+    fn message_key(&self) -> Option<&str> {
+        match *self {
+            StatusCode::BAD_REQUEST => Some("error.bad_request"),
+            StatusCode::NOT_FOUND => Some("error.not_found"),
+            StatusCode::METHOD_NOT_ALLOWED => Some("error.method_not_allowed"),
+            StatusCode::NOT_ACCEPTABLE => Some("error.not_acceptable"),
+            StatusCode::PAYLOAD_TOO_LARGE => Some("error.payload_too_large"),
+            StatusCode::INTERNAL_SERVER_ERROR => Some("error.internal_server_error"),
+            _ => None,
+        }
+    }
 }
 
 /// The implementation of `HttpError` for the standard I/O error.
@@ -72,8 +97,13 @@ impl HttpError for hyper::Error {
     type Body = String;
 
     fn into_response(self, _: &Request<()>) -> Response<Self::Body> {
+        let status = if crate::input::progress::is_stalled(&self) {
+            StatusCode::REQUEST_TIMEOUT
+        } else {
+            StatusCode::INTERNAL_SERVER_ERROR
+        };
         Response::builder()
-            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .status(status)
             .body(format!("hyper error: {}", self))
             .expect("should be a valid response")
     }
@@ -155,6 +185,55 @@ where
     error_response(response)
 }
 
+/// An error with the message key, created by `error::localized`.
+#[derive(Debug)]
+pub struct LocalizedError {
+    status: StatusCode,
+    key: &'static str,
+    args: Args,
+    text: String,
+}
+
+impl fmt::Display for LocalizedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+impl HttpError for LocalizedError {
+    type Body = String;
+
+    fn into_response(self, _: &Request<()>) -> Response<Self::Body> {
+        let mut response = Response::new(self.text);
+        *response.status_mut() = self.status;
+        response
+    }
+
+    fn message_key(&self) -> Option<&str> {
+        Some(self.key)
+    }
+
+    fn message_args(&self) -> Args {
+        self.args.clone()
+    }
+}
+
+/// Creates an error with the message key, which is rendered with `text` unless
+/// the registered `Translator` provides the translation.
+pub fn localized<D>(status: StatusCode, key: &'static str, args: Args, text: D) -> Error
+where
+    D: fmt::Display,
+{
+    debug_assert!(status.is_client_error() || status.is_server_error());
+    LocalizedError {
+        status,
+        key,
+        args,
+        text: text.to_string(),
+    }
+    .into()
+}
+
 macro_rules! define_errors {
     ($(
         $(#[$m:meta])*
@@ -201,17 +280,19 @@ pub struct Error {
     fmt_debug_fn: fn(&AnyObj, &mut fmt::Formatter<'_>) -> fmt::Result,
     fmt_display_fn: fn(&AnyObj, &mut fmt::Formatter<'_>) -> fmt::Result,
     into_response_fn: fn(Box<AnyObj>, &Request<()>) -> Response<ResponseBody>,
+    message_key_fn: fn(&AnyObj) -> Option<&str>,
+    message_args_fn: fn(&AnyObj) -> Args,
 }
 
 impl fmt::Debug for Error {
     fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
-        (self.fmt_debug_fn)(&self.obj, formatter)
+        (self.fmt_debug_fn)(&*self.obj, formatter)
     }
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
-        (self.fmt_display_fn)(&self.obj, formatter)
+        (self.fmt_display_fn)(&*self.obj, formatter)
     }
 }
 
@@ -244,11 +325,23 @@ impl Error {
             HttpError::into_response(this, request).map(Into::into)
         }
 
+        fn message_key<E: HttpError>(this: &AnyObj) -> Option<&str> {
+            let this = this.downcast_ref::<E>().expect("the wrong type id");
+            this.message_key()
+        }
+
+        fn message_args<E: HttpError>(this: &AnyObj) -> Args {
+            let this = this.downcast_ref::<E>().expect("the wrong type id");
+            this.message_args()
+        }
+
         Error {
             obj: Box::new(err),
             fmt_debug_fn: fmt_debug::<E>,
             fmt_display_fn: fmt_display::<E>,
             into_response_fn: into_response::<E>,
+            message_key_fn: message_key::<E>,
+            message_args_fn: message_args::<E>,
         }
     }
 
@@ -280,6 +373,16 @@ impl Error {
         }
     }
 
+    /// Returns the message key of the inner error value, if any.
+    pub fn message_key(&self) -> Option<&str> {
+        (self.message_key_fn)(&*self.obj)
+    }
+
+    /// Returns the arguments of the message of the inner error value.
+    pub fn message_args(&self) -> Args {
+        (self.message_args_fn)(&*self.obj)
+    }
+
     /// Consumes itself and creates an HTTP response from its value.
     pub fn into_response(self, request: &Request<()>) -> Response<ResponseBody> {
         (self.into_response_fn)(self.obj, request)
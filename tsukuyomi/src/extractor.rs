@@ -2,11 +2,25 @@
 
 pub mod body;
 pub mod ext;
+pub mod forwarded;
 pub mod header;
 pub mod local;
 pub mod method;
+pub mod pagination;
+pub mod path;
+pub mod replay;
+pub mod report;
+pub mod state;
+pub mod vnd;
+#[cfg(feature = "secure")]
+pub mod webhook;
+
+pub use self::{
+    ext::ExtractorExt, forwarded::forwarded, pagination::pagination, state::state, vnd::vnd,
+};
 
-pub use self::ext::ExtractorExt;
+#[doc(no_inline)]
+pub use crate::trace::trace_context;
 
 use {
     crate::{
@@ -186,6 +200,65 @@ mod ready {
     }
 }
 
+/// Creates an `Extractor` from a function that returns an `std::future::Future`.
+///
+/// The function is called with the input synchronously, and then the returned future
+/// is driven through `future::Compat03`. Since the future cannot borrow the input,
+/// the values required in it must be copied out of the input beforehand.
+#[cfg(feature = "async-await")]
+pub fn async03_fn<F, R, T, E>(
+    f: F,
+) -> impl Extractor<
+    Output = T, //
+    Error = E,
+    Extract = self::async03_fn::Async03Fn<F, R>, // private
+>
+where
+    F: Fn(&mut Input<'_>) -> R + Clone,
+    R: std::future::Future<Output = Result<T, E>>,
+    T: Tuple,
+    E: Into<Error>,
+{
+    self::extract(move || self::async03_fn::Async03Fn {
+        f: f.clone(),
+        future: None,
+    })
+}
+
+#[cfg(feature = "async-await")]
+mod async03_fn {
+    use crate::{
+        error::Error,
+        future::{Compat03, Poll, TryFuture},
+        generic::Tuple,
+        input::Input,
+    };
+
+    #[allow(missing_debug_implementations)]
+    pub struct Async03Fn<F, R> {
+        pub(super) f: F,
+        pub(super) future: Option<Compat03<R>>,
+    }
+
+    impl<F, R, T, E> TryFuture for Async03Fn<F, R>
+    where
+        F: Fn(&mut Input<'_>) -> R,
+        R: std::future::Future<Output = Result<T, E>>,
+        T: Tuple,
+        E: Into<Error>,
+    {
+        type Ok = T;
+        type Error = E;
+
+        fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+            let f = &self.f;
+            self.future
+                .get_or_insert_with(|| Compat03::from(f(input)))
+                .poll_ready(input)
+        }
+    }
+}
+
 /// Creates an `Extractor` that just clones and returns the provided value.
 pub fn value<T>(
     value: T,
@@ -244,7 +317,29 @@ pub fn version() -> impl Extractor<
     self::ready(|input| Ok((input.request.version(),)))
 }
 
+/// Creates an `Extractor` that returns the server name presented by the client with SNI.
+///
+/// The value is `None` if the request did not arrive on a TLS connection, or the
+/// server does not provide `ConnectionInfo`.
+pub fn sni() -> impl Extractor<
+    Output = (Option<String>,), //
+    Error = Never,
+    Extract = impl TryFuture<Ok = (Option<String>,), Error = Never> + Send + 'static,
+> {
+    self::ready(|input| {
+        Ok((input
+            .request
+            .extensions()
+            .get::<crate::input::ConnectionInfo>()
+            .and_then(|info| info.server_name())
+            .map(ToOwned::to_owned),))
+    })
+}
+
 /// Creates an `Extractor` that parses the value of query string to `T`.
+///
+/// The query string is parsed in the same way as `Input::query`, except that the
+/// invalid percent-encoded sequences are rejected with `400 Bad Request`.
 pub fn query<T>() -> impl Extractor<
     Output = (T,), //
     Error = Error,
@@ -254,17 +349,58 @@ where
     T: DeserializeOwned,
 {
     self::ready(move |input| {
-        let query_str = input
-            .request
-            .uri()
-            .query()
-            .ok_or_else(|| crate::error::bad_request("missing query"))?;
-        serde_urlencoded::from_str(query_str) //
+        if input.request.uri().query().is_none() {
+            return Err(crate::error::bad_request("missing query"));
+        }
+        crate::input::query::from_pairs(input.query()).map(|x| (x,))
+    })
+}
+
+/// Creates an `Extractor` that parses the value of path parameter with the specified name to `T`.
+///
+/// Unlike the parameters extracted by `path!`, the value is looked up by its name
+/// and only the requested parameter is parsed.
+pub fn param<T>(
+    name: &'static str,
+) -> impl Extractor<
+    Output = (T,), //
+    Error = Error,
+    Extract = impl TryFuture<Ok = (T,), Error = Error> + Send + 'static,
+>
+where
+    T: crate::input::param::FromPercentEncoded,
+{
+    use crate::input::param::PercentEncoded;
+    self::ready(move |input| {
+        let raw = input
+            .params
+            .as_ref()
+            .and_then(|params| params.name(name))
+            .ok_or_else(|| {
+                crate::error::internal_server_error(format!("missing parameter: {}", name))
+            })?;
+        T::from_percent_encoded(unsafe { PercentEncoded::new_unchecked(raw) })
             .map(|x| (x,))
-            .map_err(crate::error::bad_request)
+            .map_err(Into::into)
     })
 }
 
+/// Creates an `Extractor` that acquires a resource from the `PooledResource<P>`
+/// registered as a state.
+///
+/// The request fails with `503 Service Unavailable` if the resource is not
+/// acquired within the timeout. See the module `pool` for details.
+pub fn pooled<P>() -> impl Extractor<
+    Output = (crate::pool::Pooled<P>,), //
+    Error = Error,
+    Extract = crate::pool::ExtractPooled<P>,
+>
+where
+    P: crate::pool::Pool,
+{
+    self::extract(crate::pool::ExtractPooled::new)
+}
+
 /// Creates an `Extractor` that returns the value of extension of the specified type.
 pub fn extension<T>() -> impl Extractor<
     Output = (T,), //
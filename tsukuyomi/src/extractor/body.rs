@@ -3,17 +3,31 @@
 use {
     super::Extractor,
     crate::{
-        error::Error,
+        app::BodyBuffering,
+        error::{Error, HttpError},
         future::{Poll, TryFuture},
-        input::{body::RequestBody, header::ContentType, localmap::LocalData, Input},
+        i18n::Args,
+        input::{
+            body::{BufferedBody, ReadBuffered, RequestBody},
+            header::ContentType,
+            localmap::LocalData,
+            multipart::{self, Part, RelatedParts},
+            progress::UploadProgress,
+            Input,
+        },
     },
-    bytes::Bytes,
+    bytes::{Bytes, BytesMut},
     futures01::{Future, Stream},
+    http::{Request, Response, StatusCode},
+    hyper::body::Payload,
     mime::Mime,
     serde::de::DeserializeOwned,
-    std::{marker::PhantomData, str},
+    std::{io::Read, marker::PhantomData, str, time::Duration},
 };
 
+#[cfg(feature = "csv")]
+use crate::input::csv::{CsvOptions, CsvRows};
+
 #[derive(Debug, failure::Fail)]
 enum ExtractBodyError {
     #[fail(display = "missing the header field `Content-type`")]
@@ -32,12 +46,73 @@ enum ExtractBodyError {
     NotUtf8Charset,
 
     #[fail(display = "the content of message body is invalid: {}", cause)]
-    InvalidContent { cause: failure::Error },
+    InvalidContent {
+        key: &'static str,
+        cause: failure::Error,
+    },
+}
+
+impl HttpError for ExtractBodyError {
+    type Body = String;
+
+    fn into_response(self, _: &Request<()>) -> Response<Self::Body> {
+        let mut response = Response::new(self.to_string());
+        *response.status_mut() = StatusCode::BAD_REQUEST;
+        response
+    }
+
+    fn message_key(&self) -> Option<&str> {
+        Some(match self {
+            ExtractBodyError::MissingContentType => "error.body.missing_content_type",
+            ExtractBodyError::UnexpectedContentType { .. } => "error.body.unexpected_content_type",
+            ExtractBodyError::InvalidMime => "error.body.invalid_mime",
+            ExtractBodyError::NotUtf8Charset => "error.body.not_utf8_charset",
+            ExtractBodyError::InvalidContent { key, .. } => key,
+        })
+    }
+
+    fn message_args(&self) -> Args {
+        match self {
+            ExtractBodyError::UnexpectedContentType { expected } => {
+                Args::new().arg("expected", expected)
+            }
+            ExtractBodyError::InvalidContent { cause, .. } => Args::new().arg("cause", cause),
+            _ => Args::new(),
+        }
+    }
 }
 
 trait Decoder<T> {
     fn validate_mime(mime: Option<&Mime>) -> Result<(), ExtractBodyError>;
     fn decode(data: &[u8]) -> Result<T, ExtractBodyError>;
+
+    /// Decodes the data with the access to the context of the current request.
+    fn decode_with_input(data: &[u8], _: &mut Input<'_>) -> Result<T, Error> {
+        Self::decode(data).map_err(Into::into)
+    }
+
+    /// Decodes the data read from the body spilled into a temporary file.
+    ///
+    /// By default, the entire data is read into memory.
+    fn decode_reader(reader: &mut dyn Read, input: &mut Input<'_>) -> Result<T, Error> {
+        let mut data = vec![];
+        reader
+            .read_to_end(&mut data)
+            .map_err(crate::error::internal_server_error)?;
+        Self::decode_with_input(&data, input)
+    }
+}
+
+/// Creates the future that reads the entire request body according to `BodyBuffering`.
+fn read_buffered(input: &mut Input<'_>) -> Result<ReadBuffered, Error> {
+    let buffering = input
+        .locals
+        .get(&BodyBuffering::KEY)
+        .cloned()
+        .unwrap_or_default();
+    RequestBody::take_from(input.locals)
+        .map(|body| ReadBuffered::new(body, buffering))
+        .ok_or_else(stolen_payload)
 }
 
 fn decode<T, D>() -> impl Extractor<
@@ -73,7 +148,7 @@ where
     #[allow(missing_debug_implementations)]
     enum State {
         Init,
-        ReadAll(futures01::stream::Concat2<RequestBody>),
+        ReadAll(ReadBuffered),
     }
 
     #[allow(missing_debug_implementations)]
@@ -94,16 +169,20 @@ where
                 self.state = match self.state {
                     State::Init => {
                         let mime_opt = crate::input::header::parse::<ContentType>(input)?;
-                        D::validate_mime(mime_opt).map_err(crate::error::bad_request)?;
-                        RequestBody::take_from(input.locals)
-                            .map(|body| State::ReadAll(body.concat2()))
-                            .ok_or_else(stolen_payload)?
+                        D::validate_mime(mime_opt)?;
+                        State::ReadAll(read_buffered(input)?)
                     }
                     State::ReadAll(ref mut read_all) => {
-                        let data = futures01::try_ready!(read_all.poll());
-                        return D::decode(&*data)
-                            .map(|out| (out,).into())
-                            .map_err(crate::error::bad_request);
+                        let mut body = futures01::try_ready!(read_all.poll());
+                        let out = match body.as_bytes() {
+                            Some(data) => D::decode_with_input(&data[..], input)?,
+                            None => {
+                                let mut reader =
+                                    body.reader().map_err(crate::error::internal_server_error)?;
+                                D::decode_reader(&mut reader, input)?
+                            }
+                        };
+                        return Ok((out,).into());
                     }
                 };
             }
@@ -150,10 +229,12 @@ where
         fn decode(data: &[u8]) -> Result<T, ExtractBodyError> {
             let s = str::from_utf8(&*data) //
                 .map_err(|cause| ExtractBodyError::InvalidContent {
+                    key: "error.body.invalid_text",
                     cause: cause.into(),
                 })?;
             serde_plain::from_str(s) //
                 .map_err(|cause| ExtractBodyError::InvalidContent {
+                    key: "error.body.invalid_text",
                     cause: cause.into(),
                 })
         }
@@ -189,12 +270,45 @@ where
         }
 
         fn decode(data: &[u8]) -> Result<T, ExtractBodyError> {
-            serde_json::from_slice(&*data).map_err(|cause| ExtractBodyError::InvalidContent {
-                cause: cause.into(),
-            })
+            serde_json::from_slice(&*data).map_err(invalid_json)
+        }
+
+        /// Validates the parsed value against the schema registered by `schema::request`
+        /// before deserializing it.
+        fn decode_with_input(data: &[u8], input: &mut Input<'_>) -> Result<T, Error> {
+            if crate::schema::RequestSchema::get(input.locals).is_none() {
+                return Self::decode(data).map_err(Into::into);
+            }
+            let value = serde_json::from_slice(data).map_err(invalid_json)?;
+            validate_json(value, input)
+        }
+
+        fn decode_reader(reader: &mut dyn Read, input: &mut Input<'_>) -> Result<T, Error> {
+            if crate::schema::RequestSchema::get(input.locals).is_none() {
+                return serde_json::from_reader(reader).map_err(|cause| invalid_json(cause).into());
+            }
+            let value = serde_json::from_reader(reader).map_err(invalid_json)?;
+            validate_json(value, input)
+        }
+    }
+
+    fn invalid_json(cause: serde_json::Error) -> ExtractBodyError {
+        ExtractBodyError::InvalidContent {
+            key: "error.body.invalid_json",
+            cause: cause.into(),
         }
     }
 
+    fn validate_json<T>(value: serde_json::Value, input: &mut Input<'_>) -> Result<T, Error>
+    where
+        T: DeserializeOwned,
+    {
+        let schema = crate::schema::RequestSchema::get(input.locals)
+            .expect("the schema should be registered");
+        schema.validate(&value)?;
+        serde_json::from_value(value).map_err(|cause| invalid_json(cause).into())
+    }
+
     decode::<T, JsonDecoder>()
 }
 
@@ -225,9 +339,18 @@ where
         }
 
         fn decode(data: &[u8]) -> Result<T, ExtractBodyError> {
-            serde_urlencoded::from_bytes(&*data).map_err(|cause| ExtractBodyError::InvalidContent {
-                cause: cause.into(),
-            })
+            serde_urlencoded::from_bytes(&*data).map_err(invalid_urlencoded)
+        }
+
+        fn decode_reader(reader: &mut dyn Read, _: &mut Input<'_>) -> Result<T, Error> {
+            serde_urlencoded::from_reader(reader).map_err(|cause| invalid_urlencoded(cause).into())
+        }
+    }
+
+    fn invalid_urlencoded(cause: serde_urlencoded::de::Error) -> ExtractBodyError {
+        ExtractBodyError::InvalidContent {
+            key: "error.body.invalid_urlencoded",
+            cause: cause.into(),
         }
     }
 
@@ -258,6 +381,271 @@ pub fn read_all() -> impl Extractor<
     })
 }
 
+/// Creates an extractor that reads the entire of request body according to the
+/// `BodyBuffering` of the current scope.
+///
+/// Unlike `read_all`, the large bodies may be spilled into a temporary file, which
+/// is read through `BufferedBody::reader`.
+pub fn buffered() -> impl Extractor<
+    Output = (BufferedBody,),
+    Error = Error,
+    Extract = impl TryFuture<Ok = (BufferedBody,), Error = Error> + Send + 'static,
+> {
+    super::extract(|| {
+        let mut read_all: Option<ReadBuffered> = None;
+        crate::future::poll_fn(move |input| loop {
+            if let Some(ref mut read_all) = read_all {
+                return read_all.poll().map(|x| x.map(|body| (body,)));
+            }
+            read_all = Some(read_buffered(input)?);
+        })
+    })
+}
+
+/// The limits of the sizes of the `multipart/related` bodies.
+#[derive(Debug, Clone, Copy)]
+pub struct RelatedLimits {
+    max_part_size: usize,
+    max_total_size: usize,
+}
+
+impl Default for RelatedLimits {
+    fn default() -> Self {
+        Self {
+            max_part_size: 4 * 1024 * 1024,
+            max_total_size: 16 * 1024 * 1024,
+        }
+    }
+}
+
+impl RelatedLimits {
+    /// Sets the maximum size of each part in bytes, before decoding.
+    ///
+    /// The default value is 4 MiB.
+    pub fn max_part_size(self, max_part_size: usize) -> Self {
+        Self {
+            max_part_size,
+            ..self
+        }
+    }
+
+    /// Sets the maximum size of the entire body in bytes.
+    ///
+    /// The default value is 16 MiB.
+    pub fn max_total_size(self, max_total_size: usize) -> Self {
+        Self {
+            max_total_size,
+            ..self
+        }
+    }
+}
+
+/// Creates an `Extractor` that parses the request body as `multipart/related`
+/// with the default limits.
+pub fn related() -> impl Extractor<
+    Output = (RelatedParts,),
+    Error = Error,
+    Extract = impl TryFuture<Ok = (RelatedParts,), Error = Error> + Send + 'static,
+> {
+    related_with(RelatedLimits::default())
+}
+
+/// Creates an `Extractor` that parses the request body as `multipart/related`
+/// with the specified limits.
+///
+/// The root part is determined by the parameter `start` of `Content-Type`,
+/// and must be of the media type specified by the parameter `type`, if any.
+/// The contents encoded by `Content-Transfer-Encoding: base64` are decoded.
+/// The malformed bodies are rejected with `400 Bad Request`, and the ones
+/// exceeding the limits with `413 Payload Too Large`.
+pub fn related_with(
+    limits: RelatedLimits,
+) -> impl Extractor<
+    Output = (RelatedParts,),
+    Error = Error,
+    Extract = impl TryFuture<Ok = (RelatedParts,), Error = Error> + Send + 'static,
+> {
+    super::extract(move || {
+        let mut state: Option<(RequestBody, BytesMut, Mime)> = None;
+        crate::future::poll_fn(move |input| loop {
+            if let Some((ref mut body, ref mut buf, ref mime)) = state {
+                while let Some(chunk) = futures01::try_ready!(body.poll_data()) {
+                    if buf.len() + chunk.len() > limits.max_total_size {
+                        return Err(crate::error::localized(
+                            StatusCode::PAYLOAD_TOO_LARGE,
+                            "error.body.too_large",
+                            Args::new().arg("limit", limits.max_total_size),
+                            format_args!(
+                                "the body exceeds the size limit (limit: {})",
+                                limits.max_total_size
+                            ),
+                        ));
+                    }
+                    buf.extend_from_slice(&*chunk);
+                }
+                let body = std::mem::replace(buf, BytesMut::new()).freeze();
+                return parse_related(&body, mime, limits).map(|parts| (parts,).into());
+            }
+
+            let mime = crate::input::header::parse::<ContentType>(input)?
+                .ok_or_else(|| ExtractBodyError::MissingContentType)
+                .and_then(|mime| {
+                    if mime.type_() != mime::MULTIPART || mime.subtype() != "related" {
+                        return Err(ExtractBodyError::UnexpectedContentType {
+                            expected: "multipart/related",
+                        });
+                    }
+                    Ok(mime.clone())
+                })?;
+            let body = RequestBody::take_from(input.locals).ok_or_else(stolen_payload)?;
+            state = Some((body, BytesMut::new(), mime));
+        })
+    })
+}
+
+fn parse_related(body: &Bytes, mime: &Mime, limits: RelatedLimits) -> Result<RelatedParts, Error> {
+    let boundary = multipart::boundary(mime).map_err(crate::error::bad_request)?;
+    let parts = multipart::parse(body, boundary).map_err(crate::error::bad_request)?;
+
+    let parts = parts
+        .into_iter()
+        .enumerate()
+        .map(|(index, part)| {
+            if part.body().len() > limits.max_part_size {
+                return Err(crate::error::localized(
+                    StatusCode::PAYLOAD_TOO_LARGE,
+                    "error.body.part_too_large",
+                    Args::new()
+                        .arg("index", index)
+                        .arg("limit", limits.max_part_size),
+                    format_args!(
+                        "the part #{} exceeds the size limit (limit: {})",
+                        index, limits.max_part_size
+                    ),
+                ));
+            }
+            decode_transfer_encoding(part, index)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let root = match mime.get_param("start") {
+        Some(start) => {
+            let start = start.as_str();
+            let start = start.trim_start_matches('<').trim_end_matches('>');
+            parts
+                .iter()
+                .position(|part| part.content_id() == Some(start))
+                .ok_or_else(|| {
+                    crate::error::bad_request(format!(
+                        "missing the root part specified by `start`: <{}>",
+                        start
+                    ))
+                })?
+        }
+        None if parts.is_empty() => {
+            return Err(crate::error::bad_request("the body contains no part"));
+        }
+        None => 0,
+    };
+
+    if let Some(expected) = mime.get_param("type") {
+        let matched = parts[root].content_type().map_or(false, |actual| {
+            let actual = format!("{}/{}", actual.type_(), actual.subtype());
+            actual.eq_ignore_ascii_case(expected.as_str())
+        });
+        if !matched {
+            return Err(crate::error::bad_request(format!(
+                "the root part is not of the type specified by `type`: {}",
+                expected
+            )));
+        }
+    }
+
+    Ok(RelatedParts::new(parts, root))
+}
+
+fn decode_transfer_encoding(part: Part, index: usize) -> Result<Part, Error> {
+    let encoding = match part.headers().get(multipart::CONTENT_TRANSFER_ENCODING) {
+        Some(encoding) => encoding
+            .to_str()
+            .map_err(crate::error::bad_request)?
+            .trim()
+            .to_ascii_lowercase(),
+        None => return Ok(part),
+    };
+    match &*encoding {
+        "binary" | "8bit" | "7bit" => Ok(part),
+        "base64" => {
+            let encoded: Vec<u8> = part
+                .body()
+                .iter()
+                .cloned()
+                .filter(|b| !b.is_ascii_whitespace())
+                .collect();
+            let decoded = base64::decode(&encoded).map_err(|err| {
+                crate::error::bad_request(format!(
+                    "invalid base64 content in the part #{}: {}",
+                    index, err
+                ))
+            })?;
+            Ok(Part::new(part.headers().clone(), decoded.into()))
+        }
+        encoding => Err(crate::error::bad_request(format!(
+            "unsupported Content-Transfer-Encoding in the part #{}: {}",
+            index, encoding
+        ))),
+    }
+}
+
+/// Creates an `Extractor` that parses the request body as CSV with the default options.
+#[cfg(feature = "csv")]
+pub fn csv<T>() -> impl Extractor<
+    Output = (CsvRows<T>,),
+    Error = Error,
+    Extract = impl TryFuture<Ok = (CsvRows<T>,), Error = Error> + Send + 'static,
+>
+where
+    T: DeserializeOwned + 'static,
+{
+    csv_with(CsvOptions::default())
+}
+
+/// Creates an `Extractor` that parses the request body as CSV with the specified options.
+///
+/// The rows are deserialized into `T` incrementally as the body arrives, by using
+/// the returned `Stream`. The columns are mapped by the header row unless disabled
+/// in the options. If `Content-Type` is specified, it must be `text/csv` with
+/// the charset `utf-8`, if any. A leading byte order mark is ignored.
+#[cfg(feature = "csv")]
+pub fn csv_with<T>(
+    options: CsvOptions,
+) -> impl Extractor<
+    Output = (CsvRows<T>,),
+    Error = Error,
+    Extract = impl TryFuture<Ok = (CsvRows<T>,), Error = Error> + Send + 'static,
+>
+where
+    T: DeserializeOwned + 'static,
+{
+    super::ready(move |input| {
+        if let Some(mime) = crate::input::header::parse::<ContentType>(input)? {
+            if mime.type_() != mime::TEXT || mime.subtype() != "csv" {
+                return Err(ExtractBodyError::UnexpectedContentType {
+                    expected: "text/csv",
+                }
+                .into());
+            }
+            if let Some(charset) = mime.get_param("charset") {
+                if charset != "utf-8" {
+                    return Err(ExtractBodyError::NotUtf8Charset.into());
+                }
+            }
+        }
+        let body = RequestBody::take_from(input.locals).ok_or_else(stolen_payload)?;
+        Ok((CsvRows::new(body, options),))
+    })
+}
+
 /// Creates an `Extractor` that takes the raw instance of request body.
 pub fn stream() -> impl Extractor<
     Output = (RequestBody,), //
@@ -273,6 +661,34 @@ pub fn stream() -> impl Extractor<
     })
 }
 
-fn stolen_payload() -> crate::error::Error {
+/// Creates an `Extractor` that instruments the request body to track the progress of receiving it.
+///
+/// The request body stored in the current context is replaced with the instrumented one,
+/// so this extractor must be applied before the extractors that read the body.
+/// The returned `UploadProgress` is also stored in `Input::locals`.
+///
+/// If `stall_timeout` is specified, reading the body is aborted with `408 Request Timeout`
+/// when no chunk arrives within the duration.
+pub fn progress(
+    stall_timeout: Option<Duration>,
+) -> impl Extractor<
+    Output = (UploadProgress,), //
+    Error = Error,
+    Extract = impl TryFuture<Ok = (UploadProgress,), Error = Error> + Send + 'static,
+> {
+    super::ready(move |input| {
+        if let Some(progress) = input.locals.get(&UploadProgress::KEY) {
+            return Ok((progress.clone(),));
+        }
+        let body = RequestBody::take_from(input.locals).ok_or_else(stolen_payload)?;
+        let (body, progress) =
+            crate::input::progress::instrument(body, stall_timeout, input.clock());
+        body.insert_into(input.locals);
+        progress.clone().insert_into(input.locals);
+        Ok((progress,))
+    })
+}
+
+pub(crate) fn stolen_payload() -> crate::error::Error {
     crate::error::internal_server_error("The instance of raw RequestBody has already stolen.")
 }
@@ -1,20 +1,26 @@
 //! A set of extensions for `Extractor`s.
 
 use {
-    super::Extractor,
+    super::{replay::ReplayGuard, report::Label, Extractor},
     crate::{
+        breaker::CircuitBreaker,
         error::Error,
         generic::{Combine, Func},
         util::Chain, //
     },
+    http::StatusCode,
+    std::borrow::Cow,
 };
 
 pub use self::{
+    breaker::WithBreaker,
     fallible::Fallible, //
+    labeled::Labeled,
     map::Map,
     map_err::MapErr,
     optional::Optional,
     or::Or,
+    replay_guard::WithReplayGuard,
 };
 
 /// A set of extension methods for composing/formatting `Extractor`s.
@@ -72,6 +78,45 @@ pub trait ExtractorExt: Extractor + Sized {
     {
         MapErr { extractor: self, f }
     }
+
+    /// Labels this extractor in the report of the extractors.
+    ///
+    /// The outcome and the duration of the extractor are recorded under the label
+    /// while the report is consumed, instead of the position in the chain.
+    /// See the module `extractor::report` for details.
+    fn label(self, label: impl Into<Cow<'static, str>>) -> Labeled<Self> {
+        Labeled::new(self, Label::Explicit(label.into()))
+    }
+
+    /// Wraps this extractor with a `CircuitBreaker`.
+    ///
+    /// The errors of the extractor are reported to the breaker as the failures.
+    /// While the breaker is open, the extractor is not called and the request fails
+    /// fast with `503 Service Unavailable`, which can be replaced with another status
+    /// code or a default value by `WithBreaker::fallback_status` and
+    /// `WithBreaker::fallback_value`.
+    fn with_breaker(self, breaker: CircuitBreaker) -> WithBreaker<Self, Self::Output> {
+        WithBreaker {
+            extractor: self,
+            breaker,
+            fallback_status: StatusCode::SERVICE_UNAVAILABLE,
+            fallback_value: None,
+        }
+    }
+
+    /// Wraps this extractor with a `ReplayGuard`.
+    ///
+    /// After this extractor has succeeded, the replay key of the request is checked
+    /// and recorded, and the replayed requests are rejected. This extractor should
+    /// be the one verifying the signature of the request and storing it as
+    /// `replay::VerifiedSignature`, so that the keys of the unverified requests
+    /// are not recorded.
+    fn with_replay_guard(self, guard: ReplayGuard) -> WithReplayGuard<Self> {
+        WithReplayGuard {
+            extractor: self,
+            guard,
+        }
+    }
 }
 
 impl<E: Extractor> ExtractorExt for E {}
@@ -324,6 +369,105 @@ mod or {
     }
 }
 
+mod labeled {
+    use {
+        crate::{
+            error::Error,
+            extractor::{
+                report::{ExtractionReport, Label},
+                Extractor,
+            },
+            future::{Async, Poll, TryFuture},
+            input::{localmap::LocalData, Input},
+        },
+        std::time::Instant,
+    };
+
+    #[derive(Debug)]
+    pub struct Labeled<E> {
+        extractor: E,
+        label: Label,
+    }
+
+    impl<E> Labeled<E> {
+        pub(crate) fn new(extractor: E, label: Label) -> Self {
+            Self { extractor, label }
+        }
+    }
+
+    impl<E> Extractor for Labeled<E>
+    where
+        E: Extractor,
+    {
+        type Output = E::Output;
+        type Error = Error;
+        type Extract = LabeledFuture<E::Extract>;
+
+        fn extract(&self) -> Self::Extract {
+            LabeledFuture {
+                future: self.extractor.extract(),
+                label: self.label.clone(),
+                state: State::Init,
+            }
+        }
+    }
+
+    #[allow(missing_debug_implementations)]
+    pub struct LabeledFuture<Fut> {
+        future: Fut,
+        label: Label,
+        state: State,
+    }
+
+    enum State {
+        Init,
+        Recording { started: Instant, recorded: usize },
+        Disabled,
+    }
+
+    impl<Fut> TryFuture for LabeledFuture<Fut>
+    where
+        Fut: TryFuture,
+        Fut::Error: Into<Error>,
+    {
+        type Ok = Fut::Ok;
+        type Error = Error;
+
+        fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+            if let State::Init = self.state {
+                // the clock is read only if the report is consumed.
+                self.state = match ExtractionReport::get(input.locals) {
+                    Some(report) => State::Recording {
+                        started: input.clock().now(),
+                        recorded: report.len(),
+                    },
+                    None => State::Disabled,
+                };
+            }
+
+            let polled = match self.future.poll_ready(input) {
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Ok(Async::Ready(output)) => Ok(output),
+                Err(err) => Err(err.into()),
+            };
+
+            if let State::Recording { started, recorded } = self.state {
+                let elapsed = input.clock().now().duration_since(started);
+                if let Some(report) = ExtractionReport::get_mut(input.locals) {
+                    // the positional label is superseded by the labeled extractors inside.
+                    if self.label.is_explicit() || report.len() == recorded {
+                        let error = polled.as_ref().err().map(ToString::to_string);
+                        report.push(self.label.entry(elapsed, error));
+                    }
+                }
+            }
+            self.state = State::Disabled;
+
+            polled.map(Async::Ready)
+        }
+    }
+}
+
 mod map {
     use crate::{
         extractor::Extractor,
@@ -436,3 +580,197 @@ mod map_err {
         }
     }
 }
+
+mod breaker {
+    use {
+        crate::{
+            breaker::{CircuitBreaker, Permit},
+            error::Error,
+            extractor::Extractor,
+            future::{Async, Poll, TryFuture},
+            generic::Tuple,
+            input::Input,
+        },
+        http::StatusCode,
+    };
+
+    #[derive(Debug)]
+    pub struct WithBreaker<E, T> {
+        pub(super) extractor: E,
+        pub(super) breaker: CircuitBreaker,
+        pub(super) fallback_status: StatusCode,
+        pub(super) fallback_value: Option<T>,
+    }
+
+    impl<E, T> WithBreaker<E, (T,)>
+    where
+        E: Extractor<Output = (T,)>,
+    {
+        /// Sets the value extracted instead of calling the extractor while the
+        /// breaker is open.
+        pub fn fallback_value(self, value: T) -> Self {
+            Self {
+                fallback_value: Some((value,)),
+                ..self
+            }
+        }
+    }
+
+    impl<E, T> WithBreaker<E, T> {
+        /// Sets the status code of the error returned while the breaker is open.
+        ///
+        /// The default value is `503 Service Unavailable`.
+        pub fn fallback_status(self, status: StatusCode) -> Self {
+            Self {
+                fallback_status: status,
+                ..self
+            }
+        }
+    }
+
+    impl<E, T> Extractor for WithBreaker<E, T>
+    where
+        E: Extractor<Output = T>,
+        T: Tuple + Clone,
+    {
+        type Output = T;
+        type Error = Error;
+        type Extract = WithBreakerFuture<E::Extract, T>;
+
+        fn extract(&self) -> Self::Extract {
+            let state = match self.breaker.try_acquire() {
+                Some(permit) => State::Acquired(self.extractor.extract(), Some(permit)),
+                None => match self.fallback_value {
+                    Some(ref value) => State::Fallback(Some(value.clone())),
+                    None => State::Rejected(self.fallback_status),
+                },
+            };
+            WithBreakerFuture { state }
+        }
+    }
+
+    #[allow(missing_debug_implementations)]
+    pub struct WithBreakerFuture<Fut, T> {
+        state: State<Fut, T>,
+    }
+
+    enum State<Fut, T> {
+        Acquired(Fut, Option<Permit>),
+        Fallback(Option<T>),
+        Rejected(StatusCode),
+    }
+
+    impl<Fut, T> TryFuture for WithBreakerFuture<Fut, T>
+    where
+        Fut: TryFuture<Ok = T>,
+    {
+        type Ok = T;
+        type Error = Error;
+
+        fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+            match self.state {
+                State::Acquired(ref mut future, ref mut permit) => {
+                    let result = match future.poll_ready(input) {
+                        Ok(Async::NotReady) => return Ok(Async::NotReady),
+                        result => result,
+                    };
+                    let permit = permit.take().expect("the future has already been polled.");
+                    match result {
+                        Ok(output) => {
+                            permit.succeed();
+                            Ok(output)
+                        }
+                        Err(err) => {
+                            permit.fail();
+                            Err(err.into())
+                        }
+                    }
+                }
+                State::Fallback(ref mut value) => Ok(Async::Ready(
+                    value.take().expect("the future has already been polled."),
+                )),
+                State::Rejected(status) => Err(crate::error::custom(
+                    status,
+                    "the downstream service is temporarily unavailable",
+                )),
+            }
+        }
+    }
+}
+
+mod replay_guard {
+    use {
+        crate::{
+            error::Error,
+            extractor::{replay::ReplayGuard, Extractor},
+            future::{Async, Poll, TryFuture},
+            input::Input,
+            modifiers::StoreFuture,
+        },
+        futures01::Future,
+    };
+
+    #[derive(Debug)]
+    pub struct WithReplayGuard<E> {
+        pub(super) extractor: E,
+        pub(super) guard: ReplayGuard,
+    }
+
+    impl<E> Extractor for WithReplayGuard<E>
+    where
+        E: Extractor,
+    {
+        type Output = E::Output;
+        type Error = Error;
+        type Extract = WithReplayGuardFuture<E::Extract, E::Output>;
+
+        fn extract(&self) -> Self::Extract {
+            WithReplayGuardFuture {
+                state: State::Extracting(self.extractor.extract()),
+                guard: self.guard.clone(),
+            }
+        }
+    }
+
+    #[allow(missing_debug_implementations)]
+    pub struct WithReplayGuardFuture<Fut, T> {
+        state: State<Fut, T>,
+        guard: ReplayGuard,
+    }
+
+    enum State<Fut, T> {
+        Extracting(Fut),
+        Recording(Option<T>, StoreFuture<bool>),
+    }
+
+    impl<Fut> TryFuture for WithReplayGuardFuture<Fut, Fut::Ok>
+    where
+        Fut: TryFuture,
+    {
+        type Ok = Fut::Ok;
+        type Error = Error;
+
+        fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+            loop {
+                self.state = match self.state {
+                    State::Extracting(ref mut future) => {
+                        let output =
+                            futures01::try_ready!(future.poll_ready(input).map_err(Into::into));
+                        State::Recording(Some(output), self.guard.check(input)?)
+                    }
+                    State::Recording(ref mut output, ref mut recording) => {
+                        let recorded = futures01::try_ready!(recording
+                            .poll()
+                            .map_err(crate::error::internal_server_error));
+                        if !recorded {
+                            return Err(self.guard.duplicated());
+                        }
+                        return Ok(Async::Ready(
+                            output.take().expect("the future has already been polled."),
+                        ));
+                    }
+                };
+            }
+        }
+    }
+}
@@ -0,0 +1,364 @@
+//! Extractor for the information forwarded by the reverse proxies.
+//!
+//! The extractor recognizes the `Forwarded` header defined in [RFC 7239],
+//! and falls back to the de-facto standard headers `X-Forwarded-For`,
+//! `X-Forwarded-Proto` and `X-Forwarded-Host` if it is missing.
+//! These headers are trusted only if the direct peer of the connection
+//! is included in the [`TrustedProxies`] registered in the scope.
+//!
+//! [RFC 7239]: https://tools.ietf.org/html/rfc7239
+//! [`TrustedProxies`]: ./struct.TrustedProxies.html
+
+use {
+    super::Extractor,
+    crate::{
+        app::config::{Concurrency, Config, Scope},
+        future::TryFuture,
+        input::{
+            localmap::{local_key, LocalData},
+            ConnectionInfo, Input,
+        },
+        util::Never,
+    },
+    http::{header::HeaderValue, uri::Authority, Request},
+    std::{net::IpAddr, sync::Arc},
+};
+
+/// A set of the addresses of reverse proxies whose forwarding headers are trusted.
+///
+/// The value is registered to a scope as a `Config`, and used by the extractors
+/// in the scope and its descendants. A sub-scope may override it with another one.
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies {
+    networks: Arc<Vec<(IpAddr, u8)>>,
+}
+
+impl TrustedProxies {
+    /// Creates an empty `TrustedProxies`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an address to be trusted.
+    pub fn trust(self, addr: IpAddr) -> Self {
+        let prefix_len = if addr.is_ipv4() { 32 } else { 128 };
+        self.trust_network(addr, prefix_len)
+    }
+
+    /// Adds a network to be trusted, specified in the CIDR notation.
+    pub fn trust_network(mut self, addr: IpAddr, prefix_len: u8) -> Self {
+        let max_len = if addr.is_ipv4() { 32 } else { 128 };
+        Arc::make_mut(&mut self.networks).push((addr, prefix_len.min(max_len)));
+        self
+    }
+
+    /// Returns whether the specified address is trusted.
+    pub fn is_trusted(&self, addr: IpAddr) -> bool {
+        self.networks
+            .iter()
+            .any(|&(network, prefix_len)| match (network, addr) {
+                (IpAddr::V4(network), IpAddr::V4(addr)) => {
+                    let mask = (!0u32).checked_shl(32 - u32::from(prefix_len)).unwrap_or(0);
+                    u32::from(network) & mask == u32::from(addr) & mask
+                }
+                (IpAddr::V6(network), IpAddr::V6(addr)) => {
+                    let mask = (!0u128)
+                        .checked_shl(128 - u32::from(prefix_len))
+                        .unwrap_or(0);
+                    u128::from(network) & mask == u128::from(addr) & mask
+                }
+                _ => false,
+            })
+    }
+}
+
+impl LocalData for TrustedProxies {
+    local_key! {
+        /// The local key to pass the trusted proxies of the scope to the extractor.
+        const KEY: Self;
+    }
+}
+
+impl<M, C> Config<M, C> for TrustedProxies
+where
+    C: Concurrency,
+{
+    type Error = Never;
+
+    fn configure(self, cx: &mut Scope<'_, M, C>) -> Result<(), Self::Error> {
+        cx.set_trusted_proxies(self);
+        Ok(())
+    }
+}
+
+/// The original information of the request, restored from the forwarding headers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForwardedInfo {
+    /// The scheme used by the client, such as `"https"`.
+    pub scheme: String,
+
+    /// The host requested by the client.
+    pub host: Option<String>,
+
+    /// The IP address of the client.
+    ///
+    /// This value is `None` if the address of the direct peer is not available,
+    /// or the trusted proxy hides the address of its client.
+    pub client_ip: Option<IpAddr>,
+
+    /// The addresses of the trusted proxies between the client and the server,
+    /// in order from the nearest one to the client.
+    pub proxies: Vec<IpAddr>,
+}
+
+impl ForwardedInfo {
+    fn direct(request: &Request<()>, peer: Option<IpAddr>) -> Self {
+        let host = match request.headers().get(http::header::HOST) {
+            Some(host) => host.to_str().ok().map(ToOwned::to_owned),
+            None => request.uri().authority_part().map(ToString::to_string),
+        };
+        Self {
+            scheme: request
+                .uri()
+                .scheme_part()
+                .map_or("http", |scheme| scheme.as_str())
+                .to_owned(),
+            host,
+            client_ip: peer,
+            proxies: vec![],
+        }
+    }
+
+    fn from_input(input: &mut Input<'_>) -> Self {
+        Self::resolve(input.request, TrustedProxies::get(input.locals)).0
+    }
+
+    /// Restores the information of the request with the specified trusted proxies.
+    ///
+    /// The returned flag indicates whether the scheme is explicitly given, by the
+    /// request URI or the forwarding headers, rather than the default value `"http"`.
+    pub(crate) fn resolve(request: &Request<()>, trusted: Option<&TrustedProxies>) -> (Self, bool) {
+        let peer = request
+            .extensions()
+            .get::<ConnectionInfo>()
+            .map(|info| info.peer_addr().ip());
+        let mut info = Self::direct(request, peer);
+        let explicit_scheme = request.uri().scheme_part().is_some();
+
+        let (peer, trusted) = match (peer, trusted) {
+            (Some(peer), Some(trusted)) if trusted.is_trusted(peer) => (peer, trusted),
+            _ => return (info, explicit_scheme),
+        };
+
+        let forwarded = match parse_headers(request) {
+            Ok(forwarded) => forwarded,
+            Err(err) => {
+                log::debug!("ignoring the malformed forwarding headers: {}", err);
+                return (info, explicit_scheme);
+            }
+        };
+
+        // Follow the hops from the nearest one while the address which appended
+        // the hop is trusted.
+        let mut proxies = vec![];
+        let mut current = Some(peer);
+        let mut scheme = forwarded.proto;
+        let mut host = forwarded.host;
+        for hop in forwarded.hops.into_iter().rev() {
+            match current {
+                Some(addr) if trusted.is_trusted(addr) => proxies.push(addr),
+                _ => break,
+            }
+            scheme = hop.proto.or(scheme);
+            host = hop.host.or(host);
+            current = hop.addr;
+        }
+        proxies.reverse();
+
+        let explicit_scheme = explicit_scheme || scheme.is_some();
+        if let Some(scheme) = scheme {
+            info.scheme = scheme;
+        }
+        if host.is_some() {
+            info.host = host;
+        }
+        info.client_ip = current;
+        info.proxies = proxies;
+        (info, explicit_scheme)
+    }
+}
+
+/// Creates an `Extractor` that returns the `ForwardedInfo` of the request.
+///
+/// If the direct peer is not trusted, or the forwarding headers are malformed,
+/// the headers are ignored and the values of the direct connection are returned.
+pub fn forwarded() -> impl Extractor<
+    Output = (ForwardedInfo,), //
+    Error = Never,
+    Extract = impl TryFuture<Ok = (ForwardedInfo,), Error = Never> + Send + 'static,
+> {
+    super::ready(|input| Ok((ForwardedInfo::from_input(input),)))
+}
+
+// ==== parser ====
+
+#[derive(Debug, Default)]
+struct Hop {
+    addr: Option<IpAddr>,
+    proto: Option<String>,
+    host: Option<String>,
+}
+
+/// The parsed forwarding headers.
+#[derive(Debug, Default)]
+struct Forwarded {
+    hops: Vec<Hop>,
+    // The values set by the direct peer, used in the `X-Forwarded-*` style.
+    proto: Option<String>,
+    host: Option<String>,
+}
+
+fn parse_headers(request: &Request<()>) -> Result<Forwarded, String> {
+    let headers = request.headers();
+
+    if headers.contains_key(http::header::FORWARDED) {
+        let mut hops = vec![];
+        for value in joined(headers.get_all(http::header::FORWARDED))? {
+            let mut hop = Hop::default();
+            for pair in split_unquoted(value, ';') {
+                let mut parts = pair.splitn(2, '=');
+                let name = parts.next().unwrap_or("").trim();
+                let value = unquote(
+                    parts
+                        .next()
+                        .ok_or_else(|| format!("missing value in Forwarded: {:?}", pair))?
+                        .trim(),
+                );
+                match &*name.to_ascii_lowercase() {
+                    "for" => hop.addr = parse_node(&value)?,
+                    "proto" => hop.proto = Some(parse_proto(&value)?),
+                    "host" => hop.host = Some(parse_host(&value)?),
+                    _ => {}
+                }
+            }
+            hops.push(hop);
+        }
+        return Ok(Forwarded {
+            hops,
+            ..Forwarded::default()
+        });
+    }
+
+    let mut forwarded = Forwarded::default();
+    for node in joined(headers.get_all("x-forwarded-for"))? {
+        forwarded.hops.push(Hop {
+            addr: parse_node(node)?,
+            ..Hop::default()
+        });
+    }
+    if let Some(proto) = joined(headers.get_all("x-forwarded-proto"))?.pop() {
+        forwarded.proto = Some(parse_proto(proto)?);
+    }
+    if let Some(host) = joined(headers.get_all("x-forwarded-host"))?.pop() {
+        forwarded.host = Some(parse_host(host)?);
+    }
+    Ok(forwarded)
+}
+
+/// Returns the non-empty elements of the comma-separated list across all field values.
+fn joined<'a>(values: http::header::GetAll<'a, HeaderValue>) -> Result<Vec<&'a str>, String> {
+    let mut elements = vec![];
+    for value in values {
+        let value = value
+            .to_str()
+            .map_err(|_| "the forwarding header contains non-ASCII characters".to_owned())?;
+        elements.extend(
+            split_unquoted(value, ',')
+                .into_iter()
+                .map(str::trim)
+                .filter(|s| !s.is_empty()),
+        );
+    }
+    Ok(elements)
+}
+
+/// Splits the string with the separator, except ones in the quoted strings.
+fn split_unquoted(s: &str, sep: char) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut start = 0;
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            c if c == sep && !quoted => {
+                parts.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+fn unquote(s: &str) -> String {
+    if s.len() < 2 || !s.starts_with('"') || !s.ends_with('"') {
+        return s.to_owned();
+    }
+    let mut unquoted = String::with_capacity(s.len() - 2);
+    let mut escaped = false;
+    for c in s[1..s.len() - 1].chars() {
+        match c {
+            '\\' if !escaped => escaped = true,
+            c => {
+                unquoted.push(c);
+                escaped = false;
+            }
+        }
+    }
+    unquoted
+}
+
+/// Parses a node identifier, such as `192.0.2.43:4711` or `[2001:db8::1]`.
+///
+/// The unknown or obfuscated identifiers are parsed as `None`.
+fn parse_node(node: &str) -> Result<Option<IpAddr>, String> {
+    if node.eq_ignore_ascii_case("unknown") || node.starts_with('_') {
+        return Ok(None);
+    }
+    let addr = if node.starts_with('[') {
+        // "[" IPv6 "]" [ ":" port ]
+        let bracketed = &node[1..];
+        match bracketed.find(']') {
+            Some(end) => bracketed[..end].parse().map(IpAddr::V6),
+            None => return Err(format!("invalid node identifier: {:?}", node)),
+        }
+    } else {
+        // IPv4 [ ":" port ], or IPv6 without the brackets as sent in `X-Forwarded-For`
+        node.parse()
+            .or_else(|_| node.split(':').next().unwrap_or("").parse().map(IpAddr::V4))
+    };
+    addr.map(Some)
+        .map_err(|_| format!("invalid node identifier: {:?}", node))
+}
+
+fn parse_proto(proto: &str) -> Result<String, String> {
+    let valid = proto.starts_with(|c: char| c.is_ascii_alphabetic())
+        && proto
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.');
+    if valid {
+        Ok(proto.to_ascii_lowercase())
+    } else {
+        Err(format!("invalid scheme: {:?}", proto))
+    }
+}
+
+fn parse_host(host: &str) -> Result<String, String> {
+    host.parse::<Authority>()
+        .map(|_| host.to_owned())
+        .map_err(|_| format!("invalid host: {:?}", host))
+}
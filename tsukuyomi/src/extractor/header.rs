@@ -2,7 +2,14 @@
 
 use {
     super::Extractor,
-    crate::{error::Error, future::TryFuture, input::header::HeaderField, util::Never},
+    crate::{
+        app::Localized,
+        error::Error,
+        future::TryFuture,
+        i18n::Locale,
+        input::{header::HeaderField, localmap::LocalData},
+        util::Never,
+    },
     http::header::{HeaderMap, HeaderName, HeaderValue},
 };
 
@@ -57,3 +64,34 @@ pub fn headers() -> impl Extractor<
 > {
     super::ready(|input| Ok((input.request.headers().clone(),)))
 }
+
+/// Creates an `Extractor` that negotiates the locale of the response from `Accept-Language`.
+///
+/// The first element of `available` is chosen if the header field is missing or
+/// no locale is acceptable. The negotiated `Locale` is also stored into the local map,
+/// and used for localizing the error messages.
+///
+/// If the locale has been selected by the path prefix with `app::LocalePrefix`,
+/// it is returned instead of negotiating, even if it is not in `available`.
+///
+/// # Panics
+///
+/// This function panics if `available` is empty.
+pub fn accept_language(
+    available: Vec<Locale>,
+) -> impl Extractor<
+    Output = (Locale,), //
+    Error = Never,
+    Extract = impl TryFuture<Ok = (Locale,), Error = Never> + Send + 'static,
+> {
+    assert!(!available.is_empty(), "no available locale");
+    super::ready(move |input| {
+        if let Some(localized) = Localized::get(input.locals) {
+            return Ok((localized.locale().clone(),));
+        }
+        let locale = Locale::negotiate(input.request.headers(), &available)
+            .unwrap_or_else(|| available[0].clone());
+        locale.clone().insert_into(input.locals);
+        Ok((locale,))
+    })
+}
@@ -0,0 +1,149 @@
+//! Extractor for the pagination parameters in the query string.
+//!
+//! The extractor recognizes the parameters `page` and `per_page` for
+//! page-based pagination, and `cursor` for cursor-based pagination.
+//! The response corresponding to the extracted `Pagination` can be built
+//! with [`Paginated`].
+//!
+//! [`Paginated`]: ../../output/struct.Paginated.html
+
+use {
+    super::Extractor,
+    crate::{error::Error, future::TryFuture},
+    serde::Deserialize,
+};
+
+/// The default values and limits used by the pagination extractor.
+#[derive(Debug, Clone)]
+pub struct Defaults {
+    per_page: u32,
+    max_per_page: u32,
+    reject_excess: bool,
+}
+
+impl Default for Defaults {
+    fn default() -> Self {
+        Self {
+            per_page: 20,
+            max_per_page: 100,
+            reject_excess: false,
+        }
+    }
+}
+
+impl Defaults {
+    /// Creates a `Defaults` with the default values.
+    ///
+    /// The default value of `per_page` is 20, and the maximum is 100.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the value of `per_page` used when the parameter is omitted.
+    pub fn per_page(self, per_page: u32) -> Self {
+        Self { per_page, ..self }
+    }
+
+    /// Sets the maximum value of `per_page`.
+    pub fn max_per_page(self, max_per_page: u32) -> Self {
+        Self {
+            max_per_page,
+            ..self
+        }
+    }
+
+    /// Sets whether to reject the requests whose `per_page` exceeds the maximum
+    /// with `400 Bad Request`.
+    ///
+    /// By default, such values are clamped to the maximum.
+    pub fn reject_excess(self, enabled: bool) -> Self {
+        Self {
+            reject_excess: enabled,
+            ..self
+        }
+    }
+}
+
+/// The pagination parameters extracted from the query string.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pagination {
+    /// Page-based pagination, specified by `?page=&per_page=`.
+    ///
+    /// The page number starts at 1.
+    Page {
+        page: u32,
+        per_page: u32,
+        offset: u64,
+    },
+
+    /// Cursor-based pagination, specified by `?cursor=&per_page=`.
+    Cursor { cursor: String, per_page: u32 },
+}
+
+impl Pagination {
+    /// Returns the number of items per page.
+    pub fn per_page(&self) -> u32 {
+        match *self {
+            Pagination::Page { per_page, .. } | Pagination::Cursor { per_page, .. } => per_page,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Query {
+    page: Option<u32>,
+    per_page: Option<u32>,
+    cursor: Option<String>,
+}
+
+/// Creates an `Extractor` that parses the pagination parameters in the query string.
+pub fn pagination(
+    defaults: Defaults,
+) -> impl Extractor<
+    Output = (Pagination,), //
+    Error = Error,
+    Extract = impl TryFuture<Ok = (Pagination,), Error = Error> + Send + 'static,
+> {
+    super::ready(move |input| {
+        let query: Query = match input.request.uri().query() {
+            Some(query) => serde_urlencoded::from_str(query).map_err(crate::error::bad_request)?,
+            None => Query {
+                page: None,
+                per_page: None,
+                cursor: None,
+            },
+        };
+
+        let per_page = match query.per_page {
+            Some(0) => return Err(crate::error::bad_request("per_page must be positive")),
+            Some(n) if n > defaults.max_per_page => {
+                if defaults.reject_excess {
+                    return Err(crate::error::bad_request(format!(
+                        "per_page must be less than or equal to {}",
+                        defaults.max_per_page
+                    )));
+                }
+                defaults.max_per_page
+            }
+            Some(n) => n,
+            None => defaults.per_page,
+        };
+
+        if let Some(cursor) = query.cursor {
+            return Ok((Pagination::Cursor { cursor, per_page },));
+        }
+
+        let page = match query.page {
+            Some(0) => return Err(crate::error::bad_request("page must be positive")),
+            Some(page) => page,
+            None => 1,
+        };
+        let offset = u64::from(page - 1) * u64::from(per_page);
+
+        Ok((Pagination::Page {
+            page,
+            per_page,
+            offset,
+        },))
+    })
+}
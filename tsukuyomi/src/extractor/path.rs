@@ -0,0 +1,55 @@
+//! Extractors for accessing the request path.
+
+use {
+    super::Extractor,
+    crate::{
+        error::Error,
+        future::TryFuture,
+        input::{
+            localmap::LocalData,
+            param::{FromPercentEncoded, MatrixSegments},
+        },
+    },
+};
+
+/// Creates an `Extractor` that parses the value of the matrix parameter with the
+/// specified name.
+///
+/// The value is taken from the first path segment containing the parameter, and
+/// from its first occurrence if it is repeated. The matrix parameters are available
+/// only if `AppBase::matrix_params` is enabled.
+///
+/// ```
+/// # use tsukuyomi::{config::prelude::*, extractor, App};
+/// let app = App::create(
+///     path!("/map/tiles").to(endpoint::get()
+///         .extract(extractor::path::matrix::<f64>("lat"))
+///         .call(|lat: f64| format!("lat={}", lat))),
+/// )
+/// .unwrap()
+/// .matrix_params(true);
+/// # drop(app);
+/// ```
+pub fn matrix<T>(
+    name: &'static str,
+) -> impl Extractor<
+    Output = (T,), //
+    Error = Error,
+    Extract = impl TryFuture<Ok = (T,), Error = Error> + Send + 'static,
+>
+where
+    T: FromPercentEncoded + Send + 'static,
+{
+    super::ready(move |input| {
+        let value = input
+            .locals
+            .get(&MatrixSegments::KEY)
+            .and_then(|segments| segments.iter().find_map(|params| params.get_raw(name)))
+            .ok_or_else(|| {
+                crate::error::bad_request(format!("missing matrix parameter: {}", name))
+            })?;
+        T::from_percent_encoded(value)
+            .map(|value| (value,))
+            .map_err(Into::into)
+    })
+}
@@ -0,0 +1,293 @@
+//! The protection of the signed requests against the replay.
+//!
+//! A valid signature proves that the request has been made by the partner, but
+//! not that it has been sent only once. [`ReplayGuard`] records the replay key of
+//! each request whose signature has been verified, and rejects the requests with
+//! the already recorded key within the window. It is wrapped around the extractor
+//! verifying the signature with `ExtractorExt::with_replay_guard`, so that the keys
+//! of the forged requests are never recorded:
+//!
+//! ```
+//! # use tsukuyomi::{
+//! #     cache::MemoryCache,
+//! #     config::prelude::*,
+//! #     extractor::{self, replay::{ReplayGuard, VerifiedSignature}, ExtractorExt},
+//! #     input::localmap::LocalData,
+//! #     App,
+//! # };
+//! # let verify = || extractor::ready(|input| {
+//! #     VerifiedSignature::new(&b"mac"[..]).insert_into(input.locals);
+//! #     Ok::<_, tsukuyomi::Error>(())
+//! # });
+//! let guard = ReplayGuard::new(MemoryCache::builder().build());
+//!
+//! let app = App::create(
+//!     path!("/hooks") //
+//!         .to(endpoint::post()
+//!             .extract(verify().with_replay_guard(guard))
+//!             .call(|| "accepted")),
+//! );
+//! # drop(app);
+//! ```
+//!
+//! The replay key is derived from the [`VerifiedSignature`] stored by the verifying
+//! extractor, rather than the text of the header field. The verifiers usually accept
+//! several forms of the same signature (e.g. the hex digits in the upper case, or
+//! the additional signatures listed in the header field), which would produce the
+//! distinct keys for a replayed request.
+//!
+//! [`ReplayGuard`]: ./struct.ReplayGuard.html
+//! [`VerifiedSignature`]: ./struct.VerifiedSignature.html
+
+use {
+    crate::{
+        cache::MemoryCache,
+        error::Error,
+        input::{
+            localmap::{local_key, LocalData},
+            Input,
+        },
+        modifiers::StoreFuture,
+    },
+    futures01::future,
+    http::{header::HeaderName, StatusCode},
+    std::{
+        fmt,
+        sync::Arc,
+        time::{Duration, UNIX_EPOCH},
+    },
+};
+
+/// The maximum length of the replay keys.
+const MAX_KEY_LEN: usize = 1024;
+
+/// A trait representing the storage of the replay keys used by `ReplayGuard`.
+///
+/// The operations are asynchronous so that the keys can be shared among the
+/// processes with an external storage, such as Redis (e.g. `SET key 1 NX PX ttl`).
+/// `MemoryCache` is provided as the in-memory implementation.
+pub trait ReplayStore: Send + Sync + 'static {
+    /// Records the key, which expires after `ttl`, and returns whether it has not
+    /// been recorded yet.
+    ///
+    /// The check and the record must be atomic, so that only one of the concurrent
+    /// calls with the same key resolves to `true`.
+    fn check_and_record(&self, key: &str, ttl: Duration) -> StoreFuture<bool>;
+}
+
+impl ReplayStore for MemoryCache<String, ()> {
+    fn check_and_record(&self, key: &str, ttl: Duration) -> StoreFuture<bool> {
+        Box::new(future::ok(self.insert_if_absent_with_ttl(
+            key.to_owned(),
+            (),
+            ttl,
+        )))
+    }
+}
+
+/// The signature that has matched the request, stored by the verifying extractor.
+///
+/// The bytes must be the normalized form of the signature, such as the decoded MAC,
+/// so that all the textual forms of the signature accepted by the verifier map to
+/// the same value. `webhook::Verify` stores the MAC that has matched.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifiedSignature(Vec<u8>);
+
+impl VerifiedSignature {
+    /// Creates a `VerifiedSignature` from the bytes of the signature.
+    pub fn new(signature: impl Into<Vec<u8>>) -> Self {
+        VerifiedSignature(signature.into())
+    }
+
+    /// Returns the bytes of the signature.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl LocalData for VerifiedSignature {
+    local_key! {
+        /// The local key for the signature verified in the current request.
+        const KEY: Self;
+    }
+}
+
+type KeyFn = dyn Fn(&Input<'_>) -> Option<String> + Send + Sync + 'static;
+
+/// The protection against the replay of the signed requests.
+///
+/// The replay key of a request is the `VerifiedSignature` stored by the wrapped
+/// extractor by default, and the requests without it are rejected with `500 Internal
+/// Server Error`. The key is recorded into the store for the window, and the requests
+/// with a recorded key are rejected with `409 Conflict`, which can be replaced by
+/// `duplicate_status` (e.g. with `401 Unauthorized` in order not to tell the reason
+/// to the client).
+///
+/// The timestamp header field contains the UNIX time in seconds at which the
+/// request has been signed, and the requests whose timestamp differs from the
+/// current time by more than the maximum clock skew are rejected with
+/// `401 Unauthorized`, as well as the ones without the header fields. Since the
+/// requests older than the skew are rejected anyway, the window should be at
+/// least twice as long as the skew so that the key of a request is kept as long
+/// as the request is acceptable.
+///
+/// The timestamp should be covered by the signature; otherwise, an attacker may
+/// replay the request with a fresh timestamp after the window.
+#[derive(Clone)]
+pub struct ReplayGuard {
+    store: Arc<dyn ReplayStore>,
+    timestamp_header: Option<HeaderName>,
+    window: Duration,
+    max_skew: Duration,
+    duplicate_status: StatusCode,
+    key: Option<Arc<KeyFn>>,
+}
+
+impl fmt::Debug for ReplayGuard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReplayGuard")
+            .field("timestamp_header", &self.timestamp_header)
+            .field("window", &self.window)
+            .field("max_skew", &self.max_skew)
+            .field("duplicate_status", &self.duplicate_status)
+            .finish()
+    }
+}
+
+impl ReplayGuard {
+    /// Creates a `ReplayGuard` that records the replay keys into the specified storage.
+    pub fn new(store: impl ReplayStore) -> Self {
+        Self {
+            store: Arc::new(store),
+            timestamp_header: Some(HeaderName::from_static("x-timestamp")),
+            window: Duration::from_secs(10 * 60),
+            max_skew: Duration::from_secs(5 * 60),
+            duplicate_status: StatusCode::CONFLICT,
+            key: None,
+        }
+    }
+
+    /// Sets the name of the header field containing the timestamp.
+    ///
+    /// The default value is `x-timestamp`.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the name is invalid.
+    pub fn timestamp_header(self, name: &'static str) -> Self {
+        Self {
+            timestamp_header: Some(HeaderName::from_static(name)),
+            ..self
+        }
+    }
+
+    /// Disables the check of the timestamp header field.
+    ///
+    /// This is intended for the schemes that carry the timestamp in the signature
+    /// header field and check it by themselves, such as `webhook::stripe`.
+    pub fn without_timestamp(self) -> Self {
+        Self {
+            timestamp_header: None,
+            ..self
+        }
+    }
+
+    /// Sets the duration for which the replay keys are recorded.
+    ///
+    /// The default value is 10 minutes.
+    pub fn window(self, window: Duration) -> Self {
+        Self { window, ..self }
+    }
+
+    /// Sets the maximum difference between the timestamp and the current time.
+    ///
+    /// The default value is 5 minutes.
+    pub fn max_skew(self, max_skew: Duration) -> Self {
+        Self { max_skew, ..self }
+    }
+
+    /// Sets the status code of the error returned for the replayed requests.
+    ///
+    /// The default value is `409 Conflict`.
+    pub fn duplicate_status(self, status: StatusCode) -> Self {
+        Self {
+            duplicate_status: status,
+            ..self
+        }
+    }
+
+    /// Sets the function deriving the replay key from the request.
+    ///
+    /// The key must not depend on the textual form of the values accepted by the
+    /// verifier, such as the raw value of the signature header field. The requests
+    /// for which the function returns `None` are rejected with `401 Unauthorized`.
+    /// The timestamp is still checked unless it is disabled.
+    pub fn key<F>(self, f: F) -> Self
+    where
+        F: Fn(&Input<'_>) -> Option<String> + Send + Sync + 'static,
+    {
+        Self {
+            key: Some(Arc::new(f)),
+            ..self
+        }
+    }
+
+    /// Checks the timestamp of the request and starts recording its replay key.
+    pub(crate) fn check(&self, input: &mut Input<'_>) -> Result<StoreFuture<bool>, Error> {
+        if let Some(ref name) = self.timestamp_header {
+            let timestamp = header_str(input, name)?.parse::<u64>().map_err(|_| {
+                crate::error::unauthorized(format!("the header field `{}' is malformed", name))
+            })?;
+            let now = input
+                .clock()
+                .system_now()
+                .duration_since(UNIX_EPOCH)
+                .map(|now| now.as_secs())
+                .unwrap_or(0);
+            if now.max(timestamp) - now.min(timestamp) > self.max_skew.as_secs() {
+                return Err(crate::error::unauthorized(
+                    "the timestamp is out of the allowed clock skew",
+                ));
+            }
+        }
+
+        let key = match self.key {
+            Some(ref f) => {
+                f(input).ok_or_else(|| crate::error::unauthorized("missing the replay key"))?
+            }
+            None => {
+                let signature = input.locals.get(&VerifiedSignature::KEY).ok_or_else(|| {
+                    crate::error::internal_server_error(
+                        "the verified signature is not stored by the extractor",
+                    )
+                })?;
+                signature
+                    .as_bytes()
+                    .iter()
+                    .map(|b| format!("{:02x}", b))
+                    .collect()
+            }
+        };
+        if key.len() > MAX_KEY_LEN {
+            return Err(crate::error::unauthorized("the replay key is too long"));
+        }
+
+        Ok(self.store.check_and_record(&key, self.window))
+    }
+
+    pub(crate) fn duplicated(&self) -> Error {
+        crate::error::custom(
+            self.duplicate_status,
+            "the request has already been accepted",
+        )
+    }
+}
+
+fn header_str<'a>(input: &'a Input<'_>, name: &HeaderName) -> Result<&'a str, Error> {
+    let value = input.request.headers().get(name).ok_or_else(|| {
+        crate::error::unauthorized(format!("missing the header field `{}'", name))
+    })?;
+    value.to_str().map_err(|_| {
+        crate::error::unauthorized(format!("the header field `{}' is malformed", name))
+    })
+}
@@ -0,0 +1,131 @@
+//! The per-request report of the outcomes of the extractors.
+//!
+//! The extractors appended to an endpoint by `Builder::extract` are labeled by
+//! their positions in the chain (`#1`, `#2`, ...), or by the label given with
+//! `ExtractorExt::label`. While some of the consumers of the report (the error hook
+//! registered by `App::on_error`, `SlowRequestLog` or `LogLevelOverride`) are active
+//! for the request, the labeled extractors record their outcomes and durations into
+//! an `ExtractionReport` in the local map, which the consumers include in their
+//! reports. Otherwise, nothing is recorded.
+
+use {
+    crate::input::localmap::{local_key, LocalData},
+    std::{borrow::Cow, fmt, time::Duration},
+};
+
+/// The outcomes of the labeled extractors, in the order of their completion.
+#[derive(Debug, Clone, Default)]
+pub struct ExtractionReport {
+    entries: Vec<ExtractionEntry>,
+}
+
+impl ExtractionReport {
+    /// Returns the recorded entries.
+    pub fn entries(&self) -> &[ExtractionEntry] {
+        &self.entries
+    }
+
+    /// Returns the entry of the extractor that has failed, if any.
+    pub fn failed(&self) -> Option<&ExtractionEntry> {
+        self.entries.iter().find(|entry| !entry.is_ok())
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub(crate) fn push(&mut self, entry: ExtractionEntry) {
+        self.entries.push(entry);
+    }
+}
+
+impl LocalData for ExtractionReport {
+    local_key! {
+        /// The local key for the report of the extractors.
+        const KEY: Self;
+    }
+}
+
+impl fmt::Display for ExtractionReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[")?;
+        for (i, entry) in self.entries.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(
+                f,
+                "{} {} {:?}",
+                entry.label,
+                if entry.is_ok() { "ok" } else { "failed" },
+                entry.elapsed
+            )?;
+        }
+        f.write_str("]")
+    }
+}
+
+/// The outcome of a labeled extractor.
+#[derive(Debug, Clone)]
+pub struct ExtractionEntry {
+    label: Cow<'static, str>,
+    elapsed: Duration,
+    error: Option<String>,
+}
+
+impl ExtractionEntry {
+    pub(crate) fn new(label: Cow<'static, str>, elapsed: Duration, error: Option<String>) -> Self {
+        Self {
+            label,
+            elapsed,
+            error,
+        }
+    }
+
+    /// Returns the label of the extractor.
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    /// Returns the time elapsed from the first poll to the completion of the extractor.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// Returns whether the extractor has succeeded.
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+
+    /// Returns the message of the error, formatted with `Display`, if the extractor has failed.
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_ref().map(String::as_str)
+    }
+}
+
+/// The label of an extractor.
+#[derive(Debug, Clone)]
+pub(crate) enum Label {
+    Explicit(Cow<'static, str>),
+    Position(usize),
+}
+
+impl Label {
+    fn to_cow(&self) -> Cow<'static, str> {
+        match *self {
+            Label::Explicit(ref label) => label.clone(),
+            Label::Position(position) => format!("#{}", position).into(),
+        }
+    }
+
+    pub(crate) fn entry(&self, elapsed: Duration, error: Option<String>) -> ExtractionEntry {
+        ExtractionEntry::new(self.to_cow(), elapsed, error)
+    }
+
+    pub(crate) fn is_explicit(&self) -> bool {
+        match self {
+            Label::Explicit(..) => true,
+            Label::Position(..) => false,
+        }
+    }
+}
@@ -0,0 +1,48 @@
+//! Extractors for accessing the states registered in the scopes.
+//!
+//! The states are registered by [`config::state`].
+//!
+//! [`config::state`]: ../../config/fn.state.html
+
+use {
+    super::Extractor,
+    crate::{error::Error, future::TryFuture, util::Never},
+};
+
+/// Creates an `Extractor` that clones the state of type `T` registered in the nearest scope.
+///
+/// If no state of type `T` is registered, the extractor returns an internal server error.
+pub fn state<T>() -> impl Extractor<
+    Output = (T,), //
+    Error = Error,
+    Extract = impl TryFuture<Ok = (T,), Error = Error> + Send + 'static,
+>
+where
+    T: Clone + Send + Sync + 'static,
+{
+    super::ready(|input| {
+        input
+            .state::<T>()
+            .cloned()
+            .map(|state| (state,))
+            .ok_or_else(|| {
+                crate::error::internal_server_error("the requested state is not registered")
+            })
+    })
+}
+
+/// Creates an `Extractor` that clones all states of type `T` registered in the
+/// scopes along the path from the root scope to the current one, outermost first.
+///
+/// Unlike `state`, the extractor never fails and returns an empty `Vec` if no
+/// state of type `T` is registered.
+pub fn collect<T>() -> impl Extractor<
+    Output = (Vec<T>,), //
+    Error = Never,
+    Extract = impl TryFuture<Ok = (Vec<T>,), Error = Never> + Send + 'static,
+>
+where
+    T: Clone + Send + Sync + 'static,
+{
+    super::ready(|input| Ok((input.states::<T>().cloned().collect(),)))
+}
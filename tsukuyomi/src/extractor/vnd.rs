@@ -0,0 +1,183 @@
+//! Extractor for the API version negotiated with the vendor media types in `Accept`.
+//!
+//! The versions are represented as the media types of the form
+//! `application/vnd.<vendor>.v<N>+json`, e.g. `application/vnd.myapp.v2+json`.
+
+use {
+    super::Extractor,
+    crate::{
+        error::Error,
+        future::TryFuture,
+        input::{
+            accept::Accept,
+            localmap::{local_key, LocalData},
+        },
+    },
+    http::header::{HeaderValue, CONTENT_TYPE, VARY},
+    mime::Mime,
+    std::sync::Arc,
+};
+
+/// The version of the API negotiated by [`vnd`].
+///
+/// The value is also stored into the local map so that it can be used by the
+/// other components, such as the access logs.
+///
+/// [`vnd`]: ./fn.vnd.html
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ApiVersion(pub u32);
+
+impl LocalData for ApiVersion {
+    local_key! {
+        /// The local key for the API version negotiated by the extractor.
+        const KEY: Self;
+    }
+}
+
+/// The vendor media type negotiated by the extractor, which replaces the
+/// `Content-Type` of the JSON responses.
+#[derive(Debug, Clone)]
+pub(crate) struct MediaType(HeaderValue);
+
+impl MediaType {
+    /// Replaces `Content-Type` of the response with the vendor media type if
+    /// its essence is `application/json`.
+    pub(crate) fn apply<T>(&self, response: &mut http::Response<T>) {
+        let is_json = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<Mime>().ok())
+            .map_or(false, |mime| {
+                mime.type_() == mime::APPLICATION && mime.subtype() == mime::JSON
+            });
+        if is_json {
+            response.headers_mut().insert(CONTENT_TYPE, self.0.clone());
+        }
+    }
+}
+
+impl LocalData for MediaType {
+    local_key! {
+        const KEY: Self;
+    }
+}
+
+#[derive(Debug)]
+struct Vendor {
+    // ordered by the version, from the highest one.
+    versions: Vec<(u32, Mime, HeaderValue)>,
+}
+
+impl Vendor {
+    fn new(vendor: &str, mut versions: Vec<u32>) -> Self {
+        assert!(!versions.is_empty(), "no supported version");
+        versions.sort_by(|a, b| b.cmp(a));
+        versions.dedup();
+        let versions = versions
+            .into_iter()
+            .map(|version| {
+                let media_type = format!("application/vnd.{}.v{}+json", vendor, version);
+                let mime = media_type
+                    .parse()
+                    .unwrap_or_else(|_| panic!("invalid vendor name: {:?}", vendor));
+                let value = HeaderValue::from_str(&media_type)
+                    .unwrap_or_else(|_| panic!("invalid vendor name: {:?}", vendor));
+                (version, mime, value)
+            })
+            .collect();
+        Vendor { versions }
+    }
+
+    /// Selects the version with the highest quality value in `Accept`.
+    ///
+    /// The ties are broken by preferring the higher version, so the highest
+    /// version is chosen for `*/*`. A bare `application/json` is regarded as
+    /// the highest version.
+    fn negotiate(&self, accept: Option<&Accept>) -> Option<&(u32, Mime, HeaderValue)> {
+        let accept = match accept {
+            Some(accept) => accept,
+            None => return self.versions.first(),
+        };
+        let json = accept.quality(&mime::APPLICATION_JSON);
+        self.versions
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| {
+                let q = accept.quality(&entry.1);
+                (entry, if i == 0 { q.max(json) } else { q })
+            })
+            .filter(|&(_, q)| q > 0)
+            .fold(None, |selected, (entry, q)| match selected {
+                Some((_, max)) if max >= q => selected,
+                _ => Some((entry, q)),
+            })
+            .map(|(entry, _)| entry)
+    }
+
+    fn not_acceptable(&self) -> Error {
+        crate::error::custom(
+            http::StatusCode::NOT_ACCEPTABLE,
+            format!(
+                "no acceptable representation (available: {})",
+                self.versions
+                    .iter()
+                    .map(|(_, mime, _)| mime.as_ref())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        )
+    }
+}
+
+/// Creates an `Extractor` that negotiates the version of the API from the vendor
+/// media types in `Accept`.
+///
+/// The version with the highest quality value is chosen among `versions`, and
+/// the highest version is chosen if `Accept` is missing or only matches the
+/// versions with `*/*` or `application/json`. If no version is acceptable, the
+/// extractor fails with `406 Not Acceptable` listing the supported media types.
+///
+/// The negotiated version is stored into the local map, and the JSON responses
+/// of the request are sent with the corresponding vendor media type as `Content-Type`.
+///
+/// ```
+/// # use tsukuyomi::{config::prelude::*, extractor, App};
+/// # use tsukuyomi::extractor::vnd::ApiVersion;
+/// let app = App::create(
+///     path!("/users").to(endpoint::get()
+///         .extract(extractor::vnd("myapp", vec![1, 2]))
+///         .call(|version: ApiVersion| match version {
+///             ApiVersion(1) => "v1",
+///             _ => "v2",
+///         }))
+/// );
+/// # drop(app);
+/// ```
+///
+/// # Panics
+///
+/// This function panics if `versions` is empty or `vendor` contains characters
+/// not allowed in the media types.
+pub fn vnd(
+    vendor: &str,
+    versions: Vec<u32>,
+) -> impl Extractor<
+    Output = (ApiVersion,), //
+    Error = Error,
+    Extract = impl TryFuture<Ok = (ApiVersion,), Error = Error> + Send + 'static,
+> {
+    let vendor = Arc::new(Vendor::new(vendor, versions));
+    super::ready(move |input| {
+        // The field is staged so that it is also added to the error responses.
+        input.response().append(VARY, "accept")?;
+
+        let accept = Accept::from_headers(input.request.headers());
+        let (version, _, value) = vendor
+            .negotiate(accept.as_ref())
+            .ok_or_else(|| vendor.not_acceptable())?;
+        ApiVersion(*version).insert_into(input.locals);
+        MediaType(value.clone()).insert_into(input.locals);
+        Ok((ApiVersion(*version),))
+    })
+}
@@ -0,0 +1,340 @@
+//! Extractors for verifying the signatures of webhook requests.
+//!
+//! The signature is an HMAC-SHA256 over the raw bytes of the request body.
+//! The extractors read the entire body in order to verify it and put the
+//! buffered bytes back into the context, so that the body extractors placed
+//! after them (such as `body::json()`) parse the same bytes. The signature that
+//! has matched is stored as `replay::VerifiedSignature`, which is used as the key
+//! by `ReplayGuard`.
+
+use {
+    super::{replay::VerifiedSignature, Extractor},
+    crate::{
+        error::Error,
+        future::{Poll, TryFuture},
+        input::{body::RequestBody, localmap::LocalData, Input},
+    },
+    futures01::{Future, Stream},
+    http::header::HeaderName,
+    ring::{constant_time, digest, hmac},
+    std::{
+        borrow::Cow,
+        sync::Arc,
+        time::{Duration, UNIX_EPOCH},
+    },
+};
+
+#[derive(Debug, failure::Fail)]
+enum VerifyError {
+    #[fail(display = "missing the header field `{}'", name)]
+    MissingHeader { name: HeaderName },
+
+    #[fail(display = "the header field `{}' is malformed", name)]
+    MalformedHeader { name: HeaderName },
+
+    #[fail(display = "the timestamp of the signature is out of the tolerance")]
+    StaleTimestamp,
+
+    #[fail(display = "the secret for verifying the signature is not available")]
+    MissingSecret,
+
+    #[fail(display = "none of the signatures matches the request body")]
+    SignatureMismatch,
+}
+
+/// A trait representing the source of the secret used for verifying the signatures.
+///
+/// This trait is implemented for the static secrets (`&'static str`, `String`,
+/// `&'static [u8]` and `Vec<u8>`) and the functions which take a reference to
+/// `Input` and return the secret, which may be used for looking up per-tenant
+/// secrets from the request and the application state.
+pub trait SecretSource: Send + Sync + 'static {
+    /// Returns the secret for the current request, or `None` if it is not available.
+    fn secret(&self, input: &mut Input<'_>) -> Option<Cow<'_, [u8]>>;
+}
+
+impl SecretSource for &'static str {
+    fn secret(&self, _: &mut Input<'_>) -> Option<Cow<'_, [u8]>> {
+        Some(Cow::Borrowed(self.as_bytes()))
+    }
+}
+
+impl SecretSource for String {
+    fn secret(&self, _: &mut Input<'_>) -> Option<Cow<'_, [u8]>> {
+        Some(Cow::Borrowed(self.as_bytes()))
+    }
+}
+
+impl SecretSource for &'static [u8] {
+    fn secret(&self, _: &mut Input<'_>) -> Option<Cow<'_, [u8]>> {
+        Some(Cow::Borrowed(self))
+    }
+}
+
+impl SecretSource for Vec<u8> {
+    fn secret(&self, _: &mut Input<'_>) -> Option<Cow<'_, [u8]>> {
+        Some(Cow::Borrowed(&self[..]))
+    }
+}
+
+impl<F> SecretSource for F
+where
+    F: Fn(&mut Input<'_>) -> Option<Vec<u8>> + Send + Sync + 'static,
+{
+    fn secret(&self, input: &mut Input<'_>) -> Option<Cow<'_, [u8]>> {
+        (*self)(input).map(Cow::Owned)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Scheme {
+    /// The header value is a comma-separated list of the hex-encoded
+    /// signatures, optionally prefixed by `sha256=`.
+    Plain,
+
+    /// The header value is in the form `t=<timestamp>,v1=<signature>,...`
+    /// and the signed payload is `<timestamp>.<body>`.
+    Stripe { tolerance: Duration },
+}
+
+/// An `Extractor` that verifies the HMAC-SHA256 signature of the request body.
+///
+/// The value of this type is created by [`hmac_sha256`], [`github`] or [`stripe`].
+///
+/// [`hmac_sha256`]: ./fn.hmac_sha256.html
+/// [`github`]: ./fn.github.html
+/// [`stripe`]: ./fn.stripe.html
+#[derive(Debug)]
+pub struct Verify<S> {
+    inner: Arc<Inner<S>>,
+}
+
+#[derive(Debug)]
+struct Inner<S> {
+    header_name: HeaderName,
+    secret: S,
+    scheme: Scheme,
+}
+
+impl<S> Clone for Verify<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+/// Creates an `Extractor` that verifies the signature of the request body
+/// stored in the specified header field.
+///
+/// The header value is a comma-separated list of the hex-encoded signatures,
+/// each of which may be prefixed by `sha256=`. The verification succeeds if
+/// any of them matches the HMAC-SHA256 of the request body, and the request
+/// is rejected with `401 Unauthorized` otherwise.
+pub fn hmac_sha256<S>(header_name: HeaderName, secret: S) -> Verify<S>
+where
+    S: SecretSource,
+{
+    Verify::new(header_name, secret, Scheme::Plain)
+}
+
+/// Creates an `Extractor` that verifies the signature in the format of GitHub,
+/// stored in the header field `X-Hub-Signature-256`.
+pub fn github<S>(secret: S) -> Verify<S>
+where
+    S: SecretSource,
+{
+    hmac_sha256(HeaderName::from_static("x-hub-signature-256"), secret)
+}
+
+/// Creates an `Extractor` that verifies the signature in the format of Stripe,
+/// stored in the header field `Stripe-Signature`.
+///
+/// In addition to the signatures, the requests whose timestamp differs from the
+/// current time of the application clock by more than `tolerance` are rejected.
+pub fn stripe<S>(secret: S, tolerance: Duration) -> Verify<S>
+where
+    S: SecretSource,
+{
+    Verify::new(
+        HeaderName::from_static("stripe-signature"),
+        secret,
+        Scheme::Stripe { tolerance },
+    )
+}
+
+impl<S> Verify<S>
+where
+    S: SecretSource,
+{
+    fn new(header_name: HeaderName, secret: S, scheme: Scheme) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                header_name,
+                secret,
+                scheme,
+            }),
+        }
+    }
+}
+
+impl<S> Extractor for Verify<S>
+where
+    S: SecretSource,
+{
+    type Output = ();
+    type Error = Error;
+    type Extract = VerifyFuture<S>;
+
+    fn extract(&self) -> Self::Extract {
+        VerifyFuture {
+            inner: self.inner.clone(),
+            state: State::Init,
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+enum State {
+    Init,
+    ReadAll(futures01::stream::Concat2<RequestBody>),
+}
+
+#[allow(missing_debug_implementations)]
+pub struct VerifyFuture<S> {
+    inner: Arc<Inner<S>>,
+    state: State,
+}
+
+impl<S> TryFuture for VerifyFuture<S>
+where
+    S: SecretSource,
+{
+    type Ok = ();
+    type Error = Error;
+
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        loop {
+            self.state = match self.state {
+                State::Init => RequestBody::take_from(input.locals)
+                    .map(|body| State::ReadAll(body.concat2()))
+                    .ok_or_else(super::body::stolen_payload)?,
+                State::ReadAll(ref mut read_all) => {
+                    let data = futures01::try_ready!(read_all.poll()).into_bytes();
+                    input.locals.insert(
+                        &RequestBody::KEY,
+                        RequestBody::from(hyper::Body::from(data.clone())),
+                    );
+                    return self
+                        .inner
+                        .verify(&data, input)
+                        .map(Into::into)
+                        .map_err(crate::error::unauthorized);
+                }
+            };
+        }
+    }
+}
+
+impl<S> Inner<S>
+where
+    S: SecretSource,
+{
+    fn verify(&self, body: &[u8], input: &mut Input<'_>) -> Result<(), VerifyError> {
+        let header = input
+            .request
+            .headers()
+            .get(&self.header_name)
+            .ok_or_else(|| VerifyError::MissingHeader {
+                name: self.header_name.clone(),
+            })?
+            .to_str()
+            .map_err(|_| self.malformed())?
+            .to_owned();
+
+        let (prefix, signatures) = match self.scheme {
+            Scheme::Plain => {
+                let signatures = header
+                    .split(',')
+                    .map(|s| s.trim().trim_start_matches("sha256="))
+                    .map(|s| decode_hex(s).ok_or_else(|| self.malformed()))
+                    .collect::<Result<Vec<_>, _>>()?;
+                (None, signatures)
+            }
+            Scheme::Stripe { tolerance } => {
+                let mut timestamp = None;
+                let mut signatures = vec![];
+                for pair in header.split(',') {
+                    let mut kv = pair.trim().splitn(2, '=');
+                    match (kv.next(), kv.next()) {
+                        (Some("t"), Some(t)) => {
+                            timestamp = Some(t.parse::<u64>().map_err(|_| self.malformed())?)
+                        }
+                        (Some("v1"), Some(sig)) => {
+                            signatures.push(decode_hex(sig).ok_or_else(|| self.malformed())?)
+                        }
+                        (Some(_), Some(_)) => {}
+                        _ => return Err(self.malformed()),
+                    }
+                }
+                let timestamp = timestamp.ok_or_else(|| self.malformed())?;
+
+                let now = input
+                    .clock()
+                    .system_now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|now| now.as_secs())
+                    .unwrap_or(0);
+                if now.max(timestamp) - now.min(timestamp) > tolerance.as_secs() {
+                    return Err(VerifyError::StaleTimestamp);
+                }
+
+                (Some(format!("{}.", timestamp)), signatures)
+            }
+        };
+
+        let secret = self
+            .secret
+            .secret(input)
+            .ok_or(VerifyError::MissingSecret)?;
+        let key = hmac::SigningKey::new(&digest::SHA256, &secret);
+        let mut context = hmac::SigningContext::with_key(&key);
+        if let Some(prefix) = prefix {
+            context.update(prefix.as_bytes());
+        }
+        context.update(body);
+        let expected = context.sign();
+
+        if signatures
+            .iter()
+            .any(|sig| constant_time::verify_slices_are_equal(expected.as_ref(), sig).is_ok())
+        {
+            // store the MAC instead of the matched entry of the header field, since
+            // the same signature can be written in several forms.
+            VerifiedSignature::new(expected.as_ref()).insert_into(input.locals);
+            Ok(())
+        } else {
+            Err(VerifyError::SignatureMismatch)
+        }
+    }
+
+    fn malformed(&self) -> VerifyError {
+        VerifyError::MalformedHeader {
+            name: self.header_name.clone(),
+        }
+    }
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    s.as_bytes()
+        .chunks(2)
+        .map(|pair| match pair {
+            [hi, lo] => Some((hex_digit(*hi)? << 4) | hex_digit(*lo)?),
+            _ => None,
+        })
+        .collect()
+}
+
+fn hex_digit(c: u8) -> Option<u8> {
+    (c as char).to_digit(16).map(|d| d as u8)
+}
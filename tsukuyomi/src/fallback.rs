@@ -0,0 +1,340 @@
+//! Endpoints for the requests that matched no route.
+//!
+//! ```
+//! # use tsukuyomi::{config::prelude::*, fallback, App};
+//! let app = App::create(chain![
+//!     path!("/users/:id").to(endpoint::get().call(|id: u32| format!("user {}", id))),
+//!     path!("*").to(fallback::suggestions(3)),
+//! ])
+//! .unwrap();
+//! # drop(app);
+//! ```
+
+use {
+    crate::{
+        endpoint::{ApplyContext, ApplyResult, Endpoint},
+        handler::AllowedMethods,
+        input::accept::Accept,
+        output::ResponseBody,
+        util::Never,
+    },
+    http::{
+        header::{HeaderValue, ALLOW, CONTENT_TYPE, VARY},
+        Request, Response, StatusCode,
+    },
+    std::{fmt, sync::Arc},
+};
+
+/// Creates the response of `405 Method Not Allowed` with the methods that the resource accepts.
+///
+/// The methods are listed in `Allow`, and also in the body so that the clients which
+/// do not surface the header fields can show them. The body is a JSON object such as
+/// `{"error":"method_not_allowed","allowed":["GET","POST"]}` if the client accepts
+/// `application/json` in preference to `text/plain`, and a plain text otherwise.
+///
+/// This function is the default renderer of the application, which can be replaced
+/// by `App::with_method_not_allowed`. It is also available to the custom fallbacks.
+pub fn method_not_allowed(
+    request: &Request<()>,
+    allowed_methods: &AllowedMethods,
+) -> Response<ResponseBody> {
+    let available = [mime::TEXT_PLAIN_UTF_8, mime::APPLICATION_JSON];
+    let prefers_json = Accept::from_headers(request.headers())
+        .and_then(|accept| accept.negotiate(&available).first().cloned().cloned())
+        .map_or(false, |mime| mime == mime::APPLICATION_JSON);
+
+    let methods: Vec<_> = allowed_methods
+        .iter()
+        .map(|method| method.as_str())
+        .collect();
+    let (content_type, body) = if prefers_json {
+        let body = serde_json::json!({
+            "error": "method_not_allowed",
+            "allowed": methods,
+        });
+        (mime::APPLICATION_JSON, body.to_string())
+    } else {
+        let body = format!("Method Not Allowed (allowed: {})\n", methods.join(", "));
+        (mime::TEXT_PLAIN_UTF_8, body)
+    };
+
+    let mut response = Response::new(body.into());
+    *response.status_mut() = StatusCode::METHOD_NOT_ALLOWED;
+    let headers = response.headers_mut();
+    headers.insert(ALLOW, allowed_methods.to_header_value());
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_str(content_type.as_ref()).expect("should be a valid header value"),
+    );
+    headers.insert(VARY, HeaderValue::from_static("accept"));
+    response
+}
+
+/// Creates an `Endpoint` that replies `404 Not Found` with up to `max_suggestions`
+/// paths that the client likely intended.
+///
+/// The requested path is compared with the URI patterns registered in the scope
+/// which the request belongs to, or sharing the first segment with the path, so
+/// that the cost of the comparison does not grow with the size of the whole application.
+/// The parameters in the patterns are filled with the corresponding segments of
+/// the requested path.
+///
+/// The suggestions are sent as a JSON array or a plain text, depending on `Accept`,
+/// and as the header fields `Link` with `rel="alternate"`.
+pub fn suggestions(max_suggestions: usize) -> Suggestions {
+    Suggestions {
+        max_suggestions,
+        max_distance: 2,
+        comparator: Arc::new(distance),
+    }
+}
+
+/// An `Endpoint` created by `fallback::suggestions`.
+#[derive(Clone)]
+pub struct Suggestions {
+    max_suggestions: usize,
+    max_distance: usize,
+    comparator: Arc<Comparator>,
+}
+
+type Comparator = dyn Fn(&str, &str, usize) -> Option<usize> + Send + Sync + 'static;
+
+impl fmt::Debug for Suggestions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Suggestions")
+            .field("max_suggestions", &self.max_suggestions)
+            .field("max_distance", &self.max_distance)
+            .finish()
+    }
+}
+
+impl Suggestions {
+    /// Sets the maximum edit distance between the requested path and the suggested one.
+    ///
+    /// The default value is `2`.
+    pub fn max_distance(self, max_distance: usize) -> Self {
+        Self {
+            max_distance,
+            ..self
+        }
+    }
+
+    /// Sets the function that compares the requested path with a URI pattern.
+    ///
+    /// The function receives the path, the pattern and the maximum distance, and
+    /// returns the distance between them if the pattern should be suggested.
+    /// The default comparator is `fallback::distance`.
+    pub fn comparator(
+        self,
+        comparator: impl Fn(&str, &str, usize) -> Option<usize> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            comparator: Arc::new(comparator),
+            ..self
+        }
+    }
+}
+
+impl Endpoint<()> for Suggestions {
+    type Output = Response<ResponseBody>;
+    type Error = Never;
+    type Future = self::imp::SuggestionsFuture; // private
+
+    fn apply(&self, _: (), _: &mut ApplyContext<'_, '_>) -> ApplyResult<(), Self> {
+        Ok(self::imp::SuggestionsFuture(Some(self.clone())))
+    }
+
+    fn allowed_methods(&self) -> Option<AllowedMethods> {
+        None
+    }
+}
+
+/// Computes the edit distance between the requested path and a URI pattern.
+///
+/// The path and the pattern are compared segment by segment, and the sum of the
+/// Levenshtein distances of the static segments is returned. The parameters in
+/// the pattern match any segment, and a catch-all parameter matches the rest of the path.
+/// This function returns `None` if the numbers of segments differ, or the distance
+/// exceeds `max_distance`.
+pub fn distance(path: &str, pattern: &str, max_distance: usize) -> Option<usize> {
+    let mut segments = split(path);
+    let mut total = 0;
+    for pattern_segment in split(pattern) {
+        if pattern_segment.starts_with('*') {
+            return Some(total);
+        }
+        let segment = segments.next()?;
+        if !pattern_segment.starts_with(':') {
+            total += levenshtein(segment, pattern_segment, max_distance - total)?;
+        }
+    }
+    if segments.next().is_some() {
+        return None;
+    }
+    Some(total)
+}
+
+fn split(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|segment| !segment.is_empty())
+}
+
+/// Computes the Levenshtein distance between two strings, giving up if it exceeds `max`.
+fn levenshtein(a: &str, b: &str, max: usize) -> Option<usize> {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len().max(b.len()) - a.len().min(b.len()) > max {
+        return None;
+    }
+
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, &ca) in a.iter().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = prev + if ca == cb { 0 } else { 1 };
+            prev = row[j + 1];
+            row[j + 1] = substitution.min(prev + 1).min(row[j] + 1);
+        }
+        if row.iter().all(|&d| d > max) {
+            return None;
+        }
+    }
+
+    Some(row[b.len()]).filter(|&d| d <= max)
+}
+
+/// Fills the parameters in the pattern with the corresponding segments of the path.
+fn fill(path: &str, pattern: &str) -> String {
+    let mut segments = split(path);
+    let mut filled = String::new();
+    for pattern_segment in split(pattern) {
+        if pattern_segment.starts_with('*') {
+            for segment in segments.by_ref() {
+                filled += "/";
+                filled += segment;
+            }
+            break;
+        }
+        let segment = segments.next().unwrap_or("");
+        filled += "/";
+        filled += if pattern_segment.starts_with(':') {
+            segment
+        } else {
+            pattern_segment
+        };
+    }
+    if filled.is_empty() || (pattern.ends_with('/') && pattern.len() > 1) {
+        filled += "/";
+    }
+    filled
+}
+
+mod imp {
+    use {
+        super::Suggestions,
+        crate::{
+            future::{Async, Poll, TryFuture},
+            input::{accept::Accept, Input},
+            output::ResponseBody,
+            util::Never,
+        },
+        http::{
+            header::{HeaderValue, CONTENT_TYPE, LINK},
+            Response, StatusCode,
+        },
+    };
+
+    #[allow(missing_debug_implementations)]
+    pub struct SuggestionsFuture(pub(super) Option<Suggestions>);
+
+    impl TryFuture for SuggestionsFuture {
+        type Ok = Response<ResponseBody>;
+        type Error = Never;
+
+        fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+            let suggestions = self.0.take().expect("the future has already been polled.");
+            let path = input.request.uri().path();
+
+            let mut found: Vec<(usize, &str)> = input
+                .routes
+                .candidates(path)
+                .into_iter()
+                .filter_map(|pattern| {
+                    (suggestions.comparator)(path, pattern, suggestions.max_distance)
+                        .map(|distance| (distance, pattern))
+                })
+                .collect();
+            // The stable sort keeps the patterns with the same distance in order of registration.
+            found.sort_by_key(|&(distance, _)| distance);
+            let paths: Vec<String> = found
+                .into_iter()
+                .take(suggestions.max_suggestions)
+                .map(|(_, pattern)| super::fill(path, pattern))
+                .collect();
+
+            input
+                .response()
+                .append(http::header::VARY, "accept")
+                .expect("never fails");
+            let available = [mime::APPLICATION_JSON, mime::TEXT_PLAIN_UTF_8];
+            let content_type = Accept::from_headers(input.request.headers())
+                .and_then(|accept| accept.negotiate(&available).first().cloned().cloned())
+                .unwrap_or(mime::APPLICATION_JSON);
+
+            let body = if content_type == mime::APPLICATION_JSON {
+                serde_json::json!({ "suggestions": paths }).to_string()
+            } else {
+                let mut body = String::from("Not Found\n");
+                if !paths.is_empty() {
+                    body += "\nDid you mean:\n";
+                    for path in &paths {
+                        body += "  ";
+                        body += path;
+                        body += "\n";
+                    }
+                }
+                body
+            };
+
+            let mut response = Response::new(body.into());
+            *response.status_mut() = StatusCode::NOT_FOUND;
+            response.headers_mut().insert(
+                CONTENT_TYPE,
+                HeaderValue::from_str(content_type.as_ref())
+                    .expect("should be a valid header value"),
+            );
+            for path in &paths {
+                // The suggested paths consist of the segments in the request URI,
+                // so that they are valid as a header value.
+                if let Ok(link) = HeaderValue::from_str(&format!("<{}>; rel=\"alternate\"", path)) {
+                    response.headers_mut().append(LINK, link);
+                }
+            }
+
+            Ok(Async::Ready(response))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distance_of_paths() {
+        assert_eq!(distance("/user/42", "/users/:id", 2), Some(1));
+        assert_eq!(distance("/users/42", "/users/:id", 2), Some(0));
+        assert_eq!(distance("/usr/42/psots", "/users/:id/posts", 2), None);
+        assert_eq!(distance("/usr/42/psots", "/users/:id/posts", 4), Some(4));
+        assert_eq!(distance("/user/42", "/users", 2), None);
+        assert_eq!(distance("/file/a/b/c", "/files/*path", 2), Some(1));
+        assert_eq!(distance("/xyz", "/users", 2), None);
+    }
+
+    #[test]
+    fn fill_parameters() {
+        assert_eq!(fill("/user/42", "/users/:id"), "/users/42");
+        assert_eq!(fill("/file/a/b", "/files/*path"), "/files/a/b");
+        assert_eq!(fill("/api/v1/post", "/api/v1/posts/"), "/api/v1/posts/");
+        assert_eq!(fill("/x", "/"), "/");
+    }
+}
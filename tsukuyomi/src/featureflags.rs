@@ -0,0 +1,346 @@
+//! Feature flags decided on the server side for each request.
+//!
+//! A [`Flags`] is a registry of the named flags, each of which has a [`Strategy`]
+//! deciding whether the flag is enabled (or which variant is assigned) for a request.
+//! The registry is registered as a state of the scope by [`config::state`], and the
+//! flags are evaluated by the extractors [`enabled`] and [`variant`]:
+//!
+//! ```
+//! # use tsukuyomi::{
+//! #     config::prelude::*,
+//! #     featureflags::{self as flags, Flags, KeySource, Strategy},
+//! #     App,
+//! # };
+//! let flags = Flags::new()
+//!     .key(KeySource::header("x-user-id"))
+//!     .flag("new-checkout", Strategy::Percentage { percent: 10.0 });
+//!
+//! let app = App::create(chain![
+//!     tsukuyomi::config::state(flags),
+//!     path!("/checkout") //
+//!         .to(endpoint::get()
+//!             .extract(flags::enabled("new-checkout"))
+//!             .call(|enabled: bool| if enabled { "new" } else { "old" })),
+//! ]);
+//! # drop(app);
+//! ```
+//!
+//! The strategies which depend on the client are decided by the stable hash of
+//! the flag name and the key of the request, such as a header or the session cookie,
+//! so the decision for a key does not change across the restarts of the server.
+//! The strategies are stored in [`DynamicConfig`]s and can be replaced while the
+//! application is running.
+//!
+//! The decisions made during a request are recorded in the local map as [`Decisions`],
+//! for segmenting the logs and the metrics by the flags.
+//!
+//! [`Flags`]: ./struct.Flags.html
+//! [`Strategy`]: ./enum.Strategy.html
+//! [`Decisions`]: ./struct.Decisions.html
+//! [`DynamicConfig`]: ../dynamic/struct.DynamicConfig.html
+//! [`enabled`]: ./fn.enabled.html
+//! [`variant`]: ./fn.variant.html
+//! [`config::state`]: ../config/fn.state.html
+
+use {
+    crate::{
+        dynamic::DynamicConfig,
+        error::Error,
+        extractor::Extractor,
+        future::TryFuture,
+        input::{
+            localmap::{local_key, LocalData},
+            Input,
+        },
+    },
+    http::header::HeaderName,
+    serde::Deserialize,
+    std::{collections::HashMap, fmt, sync::Arc},
+};
+
+/// The decision of the flags which are disabled.
+const OFF: &str = "off";
+
+/// The decision of the flags which are enabled without variants.
+const ON: &str = "on";
+
+/// The number of the buckets used by the percentage strategy.
+const BUCKETS: u64 = 10_000;
+
+/// A strategy to decide the state of a flag for each request.
+///
+/// The value can be deserialized for `ConfigSet`, from the forms such as
+/// `{"strategy": "percentage", "percent": 25.0}`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "strategy", rename_all = "kebab-case")]
+pub enum Strategy {
+    /// The flag is always enabled.
+    On,
+
+    /// The flag is always disabled.
+    Off,
+
+    /// The flag is enabled for the specified percentage of the keys.
+    Percentage {
+        /// The percentage in the range `0.0..=100.0`.
+        percent: f64,
+    },
+
+    /// The flag is enabled only for the listed keys.
+    AllowList {
+        /// The keys for which the flag is enabled.
+        keys: Vec<String>,
+    },
+
+    /// One of the variants is assigned to each key, in proportion to their weights.
+    ///
+    /// The flag is regarded as enabled unless the assigned variant is named `off`.
+    Variants {
+        /// The candidates of the variants.
+        variants: Vec<Variant>,
+    },
+}
+
+/// A candidate of the variants of `Strategy::Variants`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Variant {
+    /// The name of this variant.
+    pub name: String,
+    /// The relative weight of this variant.
+    pub weight: u32,
+}
+
+impl Variant {
+    /// Creates a `Variant` with the specified name and weight.
+    pub fn new(name: impl Into<String>, weight: u32) -> Self {
+        Self {
+            name: name.into(),
+            weight,
+        }
+    }
+}
+
+impl Strategy {
+    /// Decides the state of the flag with the specified name for the key.
+    ///
+    /// The returned value is `"on"` or `"off"`, or the name of the assigned variant.
+    /// If the key is missing, the strategies depending on it are decided as `"off"`,
+    /// and the first variant is assigned.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn decide(&self, flag: &str, key: Option<&str>) -> &str {
+        match self {
+            Strategy::On => ON,
+            Strategy::Off => OFF,
+            Strategy::Percentage { percent } => match key {
+                Some(key) if ((hash(flag, key) % BUCKETS) as f64) < percent * 100.0 => ON,
+                _ => OFF,
+            },
+            Strategy::AllowList { keys } => match key {
+                Some(key) if keys.iter().any(|k| k == key) => ON,
+                _ => OFF,
+            },
+            Strategy::Variants { variants } => {
+                let total: u64 = variants.iter().map(|v| u64::from(v.weight)).sum();
+                let mut point = match key {
+                    Some(key) if total > 0 => hash(flag, key) % total,
+                    _ => return variants.first().map_or(OFF, |v| &*v.name),
+                };
+                for variant in variants {
+                    if point < u64::from(variant.weight) {
+                        return &variant.name;
+                    }
+                    point -= u64::from(variant.weight);
+                }
+                unreachable!("the point is less than the total weight")
+            }
+        }
+    }
+}
+
+/// Computes the 64-bit FNV-1a hash of the flag name and the key, which is stable
+/// across the restarts and the releases.
+pub(crate) fn hash(flag: &str, key: &str) -> u64 {
+    flag.as_bytes()
+        .iter()
+        .chain(&[0])
+        .chain(key.as_bytes())
+        .fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+            (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+        })
+}
+
+/// The source of the key used for deciding the flags of a request.
+#[derive(Debug, Clone)]
+pub enum KeySource {
+    /// The value of the header field.
+    Header(HeaderName),
+    /// The value of the cookie entry, such as the session ID.
+    Cookie(String),
+}
+
+impl KeySource {
+    /// Creates a `KeySource` from the value of the specified header field.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if the name is not a valid header name.
+    pub fn header(name: &'static str) -> Self {
+        KeySource::Header(HeaderName::from_static(name))
+    }
+
+    /// Creates a `KeySource` from the value of the specified cookie entry.
+    pub fn cookie(name: impl Into<String>) -> Self {
+        KeySource::Cookie(name.into())
+    }
+
+    pub(crate) fn extract(&self, input: &mut Input<'_>) -> Result<Option<String>, Error> {
+        match self {
+            KeySource::Header(name) => Ok(input
+                .request
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(ToOwned::to_owned)),
+            KeySource::Cookie(name) => Ok(input
+                .cookies
+                .jar()?
+                .get(name)
+                .map(|cookie| cookie.value().to_owned())),
+        }
+    }
+}
+
+/// A registry of the feature flags.
+#[derive(Debug, Clone, Default)]
+pub struct Flags {
+    key: Option<KeySource>,
+    flags: HashMap<String, DynamicConfig<Strategy>>,
+}
+
+impl Flags {
+    /// Creates an empty `Flags`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the source of the key used for deciding the flags.
+    ///
+    /// By default, no key is extracted from the requests.
+    pub fn key(self, key: KeySource) -> Self {
+        Self {
+            key: Some(key),
+            ..self
+        }
+    }
+
+    /// Registers a flag with the fixed strategy.
+    pub fn flag(self, name: impl Into<String>, strategy: Strategy) -> Self {
+        self.dynamic_flag(name, DynamicConfig::new(strategy))
+    }
+
+    /// Registers a flag whose strategy is read from the `DynamicConfig` on each request.
+    pub fn dynamic_flag(
+        mut self,
+        name: impl Into<String>,
+        strategy: DynamicConfig<Strategy>,
+    ) -> Self {
+        self.flags.insert(name.into(), strategy);
+        self
+    }
+}
+
+/// The decisions of the flags made during the current request.
+///
+/// The `Display` implementation formats the decisions as `name=decision` pairs
+/// separated by commas, in the order that they were made.
+#[derive(Debug, Clone, Default)]
+pub struct Decisions(Vec<(String, String)>);
+
+impl Decisions {
+    /// Returns the decision of the specified flag, if it has been made.
+    pub fn decision(&self, flag: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(name, _)| name == flag)
+            .map(|(_, decision)| &**decision)
+    }
+
+    /// Returns an iterator of the pairs of the flag name and its decision.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(name, decision)| (&**name, &**decision))
+    }
+
+    fn record(&mut self, flag: &str, decision: &str) {
+        if self.decision(flag).is_none() {
+            self.0.push((flag.to_owned(), decision.to_owned()));
+        }
+    }
+}
+
+impl fmt::Display for Decisions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (name, decision)) in self.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{}={}", name, decision)?;
+        }
+        Ok(())
+    }
+}
+
+impl LocalData for Decisions {
+    local_key! {
+        /// The local key to manage the decisions of the feature flags.
+        const KEY: Self;
+    }
+}
+
+/// Decides the flag for the current request, and records the decision.
+fn decide(input: &mut Input<'_>, flag: &str) -> Result<String, Error> {
+    let (key_source, strategy): (Option<KeySource>, Arc<Strategy>) = {
+        let flags = input.state::<Flags>().ok_or_else(|| {
+            crate::error::internal_server_error("the state of type `Flags` is not registered")
+        })?;
+        let strategy = flags.flags.get(flag).ok_or_else(|| {
+            crate::error::internal_server_error(format!("unknown feature flag: {}", flag))
+        })?;
+        (flags.key.clone(), strategy.get())
+    };
+
+    let key = match key_source {
+        Some(ref source) => source.extract(input)?,
+        None => None,
+    };
+    let decision = strategy.decide(flag, key.as_ref().map(|key| &**key));
+
+    Decisions::entry(input.locals)
+        .or_insert_with(Decisions::default)
+        .record(flag, decision);
+
+    Ok(decision.to_owned())
+}
+
+/// Creates an `Extractor` that returns whether the specified flag is enabled.
+pub fn enabled(
+    flag: &'static str,
+) -> impl Extractor<
+    Output = (bool,), //
+    Error = Error,
+    Extract = impl TryFuture<Ok = (bool,), Error = Error> + Send + 'static,
+> {
+    crate::extractor::ready(move |input| decide(input, flag).map(|decision| (decision != OFF,)))
+}
+
+/// Creates an `Extractor` that returns the variant of the specified flag assigned to the request.
+///
+/// For the flags without variants, the returned value is `"on"` or `"off"`.
+pub fn variant(
+    flag: &'static str,
+) -> impl Extractor<
+    Output = (String,), //
+    Error = Error,
+    Extract = impl TryFuture<Ok = (String,), Error = Error> + Send + 'static,
+> {
+    crate::extractor::ready(move |input| decide(input, flag).map(|decision| (decision,)))
+}
@@ -5,9 +5,13 @@ use {
         error::Error,
         future::TryFuture,
         handler::ModifyHandler,
-        input::Input,
-        output::{IntoResponse, ResponseBody},
+        input::{
+            encoding::{AcceptEncoding, Encoding},
+            Input,
+        },
+        output::{vary, IntoResponse, ResponseBody},
         responder::Responder,
+        uri::Uri,
     },
     bytes::{BufMut, Bytes, BytesMut},
     filetime::FileTime,
@@ -20,7 +24,9 @@ use {
     mime::Mime,
     std::{
         borrow::Cow,
-        cmp, fmt,
+        cmp,
+        collections::HashSet,
+        fmt,
         fs::{File, Metadata},
         io::{self, Read as _Read},
         mem,
@@ -32,6 +38,7 @@ use {
     },
     time::Timespec,
     tokio_threadpool::blocking as poll_blocking,
+    url::percent_encoding::percent_decode,
 };
 
 // ==== headers ====
@@ -112,7 +119,7 @@ impl fmt::Display for ETag {
 // ==== Config ====
 
 /// A set of configuration used in `NamedFile`.
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Clone)]
 pub struct OpenConfig {
     /// The size of chunked buffers.
     ///
@@ -124,6 +131,65 @@ pub struct OpenConfig {
     /// If this field is set, the generated HTTP response will include a "Cache-Control" header
     /// that includes the parameter max-age.
     pub max_age: Option<Duration>,
+
+    /// Whether to serve the pre-compressed files if available.
+    ///
+    /// If this field is set to `true`, the sibling file with the extension
+    /// `.br` or `.gz` (e.g. `app.js.br` for `app.js`) is served instead of
+    /// the original file when the client accepts the corresponding coding.
+    pub precompressed: bool,
+
+    /// Whether to serve the files by memory-mapping them instead of reading them
+    /// on the blocking pool.
+    ///
+    /// This field takes effect only if the feature `mmap` is enabled, and only for
+    /// the files whose size is at least `mmap_threshold`. If the mapping fails, the
+    /// file is read in the normal way. The mapped files also support the requests
+    /// with a single byte range in `Range`.
+    ///
+    /// The mapped files must not be truncated while they are served: accessing the
+    /// pages beyond the new end of a mapped file raises `SIGBUS` on most platforms.
+    /// The length of the file is captured when it is opened, so the data appended
+    /// later is never exposed, but the files that may be truncated or rewritten in
+    /// place should not be served in this mode.
+    pub mmap: bool,
+
+    /// The minimum size of the files served by memory-mapping, in bytes.
+    ///
+    /// The default value is 1 MiB.
+    pub mmap_threshold: u64,
+}
+
+impl Default for OpenConfig {
+    fn default() -> Self {
+        Self {
+            chunk_size: None,
+            max_age: None,
+            precompressed: false,
+            mmap: false,
+            mmap_threshold: 1024 * 1024,
+        }
+    }
+}
+
+impl OpenConfig {
+    /// Sets whether to serve the large files by memory-mapping them.
+    ///
+    /// See the documentation of the field `mmap` for details.
+    pub fn mmap(self, enabled: bool) -> Self {
+        Self {
+            mmap: enabled,
+            ..self
+        }
+    }
+
+    /// Sets the minimum size of the files served by memory-mapping.
+    pub fn mmap_threshold(self, threshold: u64) -> Self {
+        Self {
+            mmap_threshold: threshold,
+            ..self
+        }
+    }
 }
 
 // ==== NamedFile ====
@@ -133,6 +199,8 @@ pub struct OpenConfig {
 pub struct NamedFile<P> {
     path: P,
     config: Option<OpenConfig>,
+    #[cfg(feature = "notify")]
+    index: Option<Arc<self::watch::Index>>,
 }
 
 impl<P> NamedFile<P>
@@ -141,7 +209,12 @@ where
 {
     /// Open a specified file with the default configuration.
     pub fn open(path: P) -> Self {
-        Self { path, config: None }
+        Self {
+            path,
+            config: None,
+            #[cfg(feature = "notify")]
+            index: None,
+        }
     }
 
     /// Open a specified file with the provided configuration.
@@ -149,8 +222,16 @@ where
         Self {
             path,
             config: Some(config),
+            #[cfg(feature = "notify")]
+            index: None,
         }
     }
+
+    /// Uses the metadata cached in the index instead of querying the filesystem.
+    #[cfg(feature = "notify")]
+    fn with_index(self, index: Option<Arc<self::watch::Index>>) -> Self {
+        Self { index, ..self }
+    }
 }
 
 impl<P> Responder for NamedFile<P>
@@ -166,6 +247,8 @@ where
         OpenNamedFile {
             path: self.path,
             config: self.config,
+            #[cfg(feature = "notify")]
+            index: self.index,
         }
     }
 }
@@ -175,6 +258,21 @@ where
 pub struct OpenNamedFile<P> {
     path: P,
     config: Option<OpenConfig>,
+    #[cfg(feature = "notify")]
+    index: Option<Arc<self::watch::Index>>,
+}
+
+impl<P> OpenNamedFile<P> {
+    #[cfg_attr(not(feature = "notify"), allow(unused_variables))]
+    fn lookup(&self, path: &Path) -> Lookup {
+        #[cfg(feature = "notify")]
+        {
+            if let Some(ref index) = self.index {
+                return index.lookup(path);
+            }
+        }
+        Lookup::Unknown
+    }
 }
 
 impl<P> TryFuture for OpenNamedFile<P>
@@ -185,10 +283,34 @@ where
     type Error = crate::Error;
 
     fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
-        let (file, meta) = futures01::try_ready!(blocking_io(|| {
-            let file = File::open(&self.path)?;
-            let meta = file.metadata()?;
-            Ok((file, meta))
+        let precompressed = self
+            .config
+            .as_ref()
+            .map_or(false, |config| config.precompressed);
+        let encodings = if precompressed {
+            AcceptEncoding::from_headers(input.request.headers())
+                .map(|accept| {
+                    accept.negotiate(&[Encoding::Brotli, Encoding::Gzip, Encoding::Identity])
+                })
+                .unwrap_or_default()
+        } else {
+            vec![]
+        };
+
+        let mmap_threshold = self
+            .config
+            .as_ref()
+            .filter(|config| config.mmap)
+            .map(|config| config.mmap_threshold);
+
+        let (file, meta, encoding, mapped) = futures01::try_ready!(blocking_io(|| {
+            let (file, meta, encoding) =
+                open_file(self.path.as_ref(), &encodings, &|path| self.lookup(path))?;
+            let mapped = match mmap_threshold {
+                Some(threshold) if meta.len() >= threshold => Mapped::open(&file, &meta),
+                _ => None,
+            };
+            Ok((file, meta, encoding, mapped))
         }));
 
         let config = self.config.take().unwrap_or_default();
@@ -201,9 +323,11 @@ where
         let response = NamedFileResponse {
             file,
             meta,
+            mapped,
             content_type,
             last_modified,
             etag,
+            encoding,
             config,
         }
         .into_response(input.request)?;
@@ -212,13 +336,70 @@ where
     }
 }
 
+/// The result of looking up a file in the index of the watched directory.
+#[derive(Debug)]
+enum Lookup {
+    /// The file does not exist.
+    Missing,
+
+    /// The file exists, with the cached metadata.
+    Found(Metadata),
+
+    /// The filesystem needs to be queried.
+    Unknown,
+}
+
+/// Opens the file at `path`, or its pre-compressed sibling with the first available encoding.
+fn open_file(
+    path: &Path,
+    encodings: &[Encoding],
+    lookup: &dyn Fn(&Path) -> Lookup,
+) -> io::Result<(File, Metadata, Option<Encoding>)> {
+    for &encoding in encodings {
+        let extension = match encoding.extension() {
+            Some(extension) => extension,
+            None => break,
+        };
+        let mut path = path.as_os_str().to_owned();
+        path.push(".");
+        path.push(extension);
+        match lookup(Path::new(&path)) {
+            Lookup::Missing => continue,
+            Lookup::Found(meta) => match File::open(&path) {
+                Ok(file) => return Ok((file, meta, Some(encoding))),
+                Err(ref err) if err.kind() == io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(err),
+            },
+            Lookup::Unknown => {}
+        }
+        match File::open(&path) {
+            Ok(file) => {
+                let meta = file.metadata()?;
+                if meta.is_file() {
+                    return Ok((file, meta, Some(encoding)));
+                }
+            }
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+    }
+    let file = File::open(path)?;
+    let meta = match lookup(path) {
+        Lookup::Found(meta) => meta,
+        _ => file.metadata()?,
+    };
+    Ok((file, meta, None))
+}
+
 #[derive(Debug)]
 struct NamedFileResponse {
     file: File,
     meta: Metadata,
+    mapped: Option<Mapped>,
     content_type: Mime,
     etag: ETag,
     last_modified: FileTime,
+    encoding: Option<Encoding>,
     config: OpenConfig,
 }
 
@@ -302,15 +483,27 @@ impl IntoResponse for NamedFileResponse {
         let last_modified = self
             .last_modified()
             .map_err(crate::error::internal_server_error)?;
-        let stream = ReadStream::new(self.file, self.meta, self.config.chunk_size);
 
-        Ok(Response::builder()
+        let mut response = Response::builder();
+        response
             .header(header::CONTENT_TYPE, self.content_type.as_ref())
             .header(header::CACHE_CONTROL, &*cache_control)
             .header(header::LAST_MODIFIED, &*last_modified)
-            .header(header::ETAG, &*self.etag.to_string())
-            .body(ResponseBody::wrap_stream(stream))
-            .unwrap())
+            .header(header::ETAG, &*self.etag.to_string());
+        if let Some(encoding) = self.encoding {
+            response.header(header::CONTENT_ENCODING, encoding.as_str());
+        }
+        let mut response = match self.mapped {
+            Some(mapped) => mapped.respond(request, &mut response, self.config.chunk_size),
+            None => {
+                let stream = ReadStream::new(self.file, self.meta, self.config.chunk_size);
+                response.body(ResponseBody::wrap_stream(stream)).unwrap()
+            }
+        };
+        if self.config.precompressed {
+            vary::add(response.headers_mut(), "accept-encoding");
+        }
+        Ok(response)
     }
 }
 
@@ -379,6 +572,136 @@ impl Stream for ReadStream {
     }
 }
 
+// ==== Mapped ====
+
+/// A memory-mapped file, whose length is fixed to the one when it was opened.
+#[cfg(feature = "mmap")]
+#[derive(Debug)]
+struct Mapped(memmap::Mmap);
+
+/// The default size of the chunks sent from a memory-mapped file.
+#[cfg(feature = "mmap")]
+const MMAP_CHUNK_SIZE: usize = 64 * 1024;
+
+#[cfg(feature = "mmap")]
+impl Mapped {
+    #[allow(clippy::cast_possible_truncation)]
+    fn open(file: &File, meta: &Metadata) -> Option<Self> {
+        if meta.len() == 0 || meta.len() > std::usize::MAX as u64 {
+            return None;
+        }
+        // The mapping is unsafe since the file may be modified by other processes.
+        // See the documentation of `OpenConfig::mmap`.
+        let mapped = unsafe {
+            memmap::MmapOptions::new()
+                .len(meta.len() as usize)
+                .map(file)
+        };
+        match mapped {
+            Ok(map) => Some(Mapped(map)),
+            Err(err) => {
+                log::debug!(
+                    "failed to map the file, falling back to reading it: {}",
+                    err
+                );
+                None
+            }
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn respond(
+        self,
+        request: &Request<()>,
+        response: &mut http::response::Builder,
+        chunk_size: Option<usize>,
+    ) -> Response<ResponseBody> {
+        let len = self.0.len() as u64;
+        response.header(header::ACCEPT_RANGES, "bytes");
+        let range = match crate::output::range::from_request(request).map(|r| r.resolve(len)) {
+            None => 0..len,
+            Some(Some(range)) => {
+                response.status(StatusCode::PARTIAL_CONTENT).header(
+                    header::CONTENT_RANGE,
+                    crate::output::range::content_range(Some(&range), len),
+                );
+                range
+            }
+            Some(None) => {
+                return response
+                    .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                    .header(
+                        header::CONTENT_RANGE,
+                        crate::output::range::content_range(None, len),
+                    )
+                    .body(ResponseBody::empty())
+                    .unwrap();
+            }
+        };
+        response.header(
+            header::CONTENT_LENGTH,
+            header::HeaderValue::from(range.end - range.start),
+        );
+        let stream = MmapStream {
+            map: self.0,
+            pos: range.start as usize,
+            end: range.end as usize,
+            chunk_size: cmp::max(chunk_size.unwrap_or(MMAP_CHUNK_SIZE), 1),
+        };
+        response.body(ResponseBody::wrap_stream(stream)).unwrap()
+    }
+}
+
+#[cfg(not(feature = "mmap"))]
+#[derive(Debug)]
+enum Mapped {}
+
+#[cfg(not(feature = "mmap"))]
+impl Mapped {
+    fn open(_: &File, _: &Metadata) -> Option<Self> {
+        None
+    }
+
+    fn respond(
+        self,
+        _: &Request<()>,
+        _: &mut http::response::Builder,
+        _: Option<usize>,
+    ) -> Response<ResponseBody> {
+        match self {}
+    }
+}
+
+/// A stream that sends a range of the memory-mapped file.
+///
+/// The chunks are copied out of the map, since `Bytes` cannot refer to the
+/// memory owned by others, but neither the system calls nor the blocking
+/// pool are involved.
+#[cfg(feature = "mmap")]
+#[derive(Debug)]
+struct MmapStream {
+    map: memmap::Mmap,
+    pos: usize,
+    end: usize,
+    chunk_size: usize,
+}
+
+#[cfg(feature = "mmap")]
+impl Stream for MmapStream {
+    type Item = Bytes;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        if self.pos >= self.end {
+            return Ok(Async::Ready(None));
+        }
+        let end = cmp::min(self.pos + self.chunk_size, self.end);
+        let chunk = Bytes::from(&self.map[self.pos..end]);
+        self.pos = end;
+        Ok(Async::Ready(Some(chunk)))
+    }
+}
+
 #[allow(dead_code)]
 const DEFAULT_BUF_SIZE: u64 = 8192;
 
@@ -444,6 +767,69 @@ struct ServeFileInner {
     path: ArcPath,
     config: Option<OpenConfig>,
     extract_path: bool,
+    #[cfg(feature = "notify")]
+    index: Option<Arc<self::watch::Index>>,
+}
+
+impl ServeFileInner {
+    fn new(path: ArcPath, config: Option<OpenConfig>, extract_path: bool) -> Self {
+        Self {
+            path,
+            config,
+            extract_path,
+            #[cfg(feature = "notify")]
+            index: None,
+        }
+    }
+
+    fn resolve(&self, input: &mut Input<'_>) -> crate::error::Result<ArcPath> {
+        if !self.extract_path {
+            return Ok(self.path.clone());
+        }
+
+        let path = input
+            .params
+            .as_ref()
+            .and_then(|params| params.catch_all())
+            .ok_or_else(|| crate::error::internal_server_error("missing params"))?;
+        let path = percent_decode(path.as_bytes())
+            .decode_utf8()
+            .map_err(crate::error::bad_request)?;
+        let path = join_segments(&self.path, &path) //
+            .ok_or_else(|| crate::error::not_found("invalid path"))?;
+
+        #[cfg(feature = "notify")]
+        {
+            if let Some(ref index) = self.index {
+                if !index.contains(&path) {
+                    return Err(crate::error::not_found("no such file"));
+                }
+            }
+        }
+
+        Ok(path.into())
+    }
+}
+
+/// Joins the segments in `path` to the root directory, rejecting the segments
+/// which may point to outside of the root directory.
+fn join_segments(root: &Path, path: &str) -> Option<PathBuf> {
+    let mut joined = root.to_path_buf();
+    let mut is_empty = true;
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => continue,
+            ".." => return None,
+            s if s.contains('\\') || s.contains('\0') => return None,
+            s if cfg!(windows) && s.contains(':') => return None,
+            s => joined.push(s),
+        }
+        is_empty = false;
+    }
+    if is_empty {
+        return None;
+    }
+    Some(joined)
 }
 
 mod impl_handler_for_serve_file {
@@ -477,30 +863,38 @@ mod impl_handler_for_serve_file {
         type Error = Error;
 
         fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
-            let path = if self.inner.extract_path {
-                let path = input
-                    .params
-                    .as_ref()
-                    .and_then(|params| params.catch_all())
-                    .ok_or_else(|| crate::error::internal_server_error("missing params"))?;
-                self.inner.path.join(path).into()
-            } else {
-                self.inner.path.clone()
-            };
+            let path = self.inner.resolve(input)?;
 
-            Ok(Async::Ready(match self.inner.config {
+            let named_file = match self.inner.config {
                 Some(ref config) => NamedFile::open_with_config(path, config.clone()),
                 None => NamedFile::open(path),
-            }))
+            };
+            #[cfg(feature = "notify")]
+            let named_file = named_file.with_index(self.inner.index.clone());
+
+            Ok(Async::Ready(named_file))
         }
     }
 }
 
 /// A configuration type for adding entries in the directory to the route.
+///
+/// By default, the entries in the directory are enumerated when configuring
+/// the application and a route is registered for each entry.  The entries
+/// created after that point are not served unless the dynamic mode is enabled.
+///
+/// The routes are registered under the current scope, or under the prefix
+/// specified by `mount_at`.  Multiple `Staticfiles` can be registered in a
+/// scope as long as the generated routes do not conflict.
 #[derive(Debug)]
 pub struct Staticfiles<P> {
     root_dir: P,
     config: Option<OpenConfig>,
+    dynamic: bool,
+    url_prefix: Option<String>,
+    aliases: Vec<(String, PathBuf)>,
+    #[cfg(feature = "notify")]
+    watch: bool,
 }
 
 impl<P> Staticfiles<P>
@@ -512,9 +906,38 @@ where
         Self {
             root_dir,
             config: None,
+            dynamic: false,
+            url_prefix: None,
+            aliases: vec![],
+            #[cfg(feature = "notify")]
+            watch: false,
+        }
+    }
+
+    /// Sets the URL prefix under which the entries are served.
+    ///
+    /// The prefix is prepended to the route of each entry, including the aliases,
+    /// so that `Staticfiles::new("./frontend/dist").mount_at("/assets")` serves
+    /// `./frontend/dist/app.js` at `/assets/app.js`.
+    pub fn mount_at(self, url_prefix: impl Into<String>) -> Self {
+        Self {
+            url_prefix: Some(url_prefix.into()),
+            ..self
         }
     }
 
+    /// Serves the file at `fs_path` at the specified URL path, instead of the entry name.
+    ///
+    /// The relative `fs_path` is resolved from the root directory.  If the root
+    /// directory contains an entry at the same URL path, the alias takes
+    /// precedence over it.  Registering multiple aliases with the same URL path
+    /// is reported as an error at configuration time.
+    pub fn alias(mut self, url_path: impl Into<String>, fs_path: impl AsRef<Path>) -> Self {
+        self.aliases
+            .push((url_path.into(), fs_path.as_ref().to_path_buf()));
+        self
+    }
+
     /// Sets the value of `OpenConfig` used in handlers.
     pub fn open_config(self, config: OpenConfig) -> Self {
         Self {
@@ -522,6 +945,38 @@ where
             ..self
         }
     }
+
+    /// Sets whether to resolve the file paths at request time.
+    ///
+    /// If enabled, a single catch-all route is registered for the whole
+    /// directory instead of the routes for each entry, so that the files
+    /// created after starting the server can also be served.
+    ///
+    /// The default value is `false`.
+    pub fn dynamic(self, enabled: bool) -> Self {
+        Self {
+            dynamic: enabled,
+            ..self
+        }
+    }
+
+    /// Sets whether to watch the directory for changes.
+    ///
+    /// If enabled, the set of existing files and their metadata are kept in
+    /// memory and updated by the filesystem events, so that the requests to
+    /// missing files are rejected without accessing the filesystem and the
+    /// files are served without querying their metadata.  This mode implies
+    /// `dynamic(true)`.
+    ///
+    /// The default value is `false`.
+    #[cfg(feature = "notify")]
+    pub fn watch(self, enabled: bool) -> Self {
+        Self {
+            watch: enabled,
+            dynamic: self.dynamic || enabled,
+            ..self
+        }
+    }
 }
 
 impl<P, M, C> crate::config::Config<M, C> for Staticfiles<P>
@@ -534,7 +989,19 @@ where
     type Error = crate::config::Error;
 
     fn configure(self, scope: &mut crate::app::config::Scope<'_, M, C>) -> crate::app::Result<()> {
-        let Self { root_dir, config } = self;
+        let prefix = match self.url_prefix {
+            Some(ref prefix) => prefix.parse().map_err(crate::config::Error::custom)?,
+            None => Uri::root(),
+        };
+        let aliased = self.configure_aliases(&prefix, scope)?;
+
+        if self.dynamic {
+            return self.configure_dynamic(&prefix, scope);
+        }
+
+        let Self {
+            root_dir, config, ..
+        } = self;
 
         for entry in std::fs::read_dir(root_dir).map_err(crate::config::Error::custom)? {
             let entry = entry.map_err(crate::config::Error::custom)?;
@@ -554,25 +1021,21 @@ where
 
             let file_type = entry.file_type().map_err(crate::config::Error::custom)?;
             if file_type.is_file() {
+                let url_path = join_url_path(&prefix, &format!("/{}", name))?;
+                if aliased.contains(&url_path) {
+                    continue;
+                }
                 scope.route(
-                    format!("/{}", name),
+                    url_path,
                     ServeFile {
-                        inner: Arc::new(ServeFileInner {
-                            path,
-                            config: config.clone(),
-                            extract_path: false,
-                        }),
+                        inner: Arc::new(ServeFileInner::new(path, config.clone(), false)),
                     },
                 )?;
             } else if file_type.is_dir() {
                 scope.route(
-                    format!("/{}/*path", name),
+                    join_url_path(&prefix, &format!("/{}/*path", name))?,
                     ServeFile {
-                        inner: Arc::new(ServeFileInner {
-                            path,
-                            config: config.clone(),
-                            extract_path: true,
-                        }),
+                        inner: Arc::new(ServeFileInner::new(path, config.clone(), true)),
                     },
                 )?;
             } else {
@@ -585,3 +1048,413 @@ where
         Ok(())
     }
 }
+
+/// Prepends the URL prefix to the path of a route.
+fn join_url_path(prefix: &Uri, path: &str) -> crate::app::Result<String> {
+    let path: Uri = path.parse().map_err(crate::config::Error::custom)?;
+    prefix
+        .join(&path)
+        .map(|uri| uri.as_str().to_owned())
+        .map_err(crate::config::Error::custom)
+}
+
+impl<P> Staticfiles<P>
+where
+    P: AsRef<Path>,
+{
+    /// Registers the routes for the aliases, and returns their URL paths.
+    fn configure_aliases<M, C>(
+        &self,
+        prefix: &Uri,
+        scope: &mut crate::app::config::Scope<'_, M, C>,
+    ) -> crate::app::Result<HashSet<String>>
+    where
+        M: ModifyHandler<ServeFile>,
+        M::Handler: Into<C::Handler>,
+        C: crate::app::config::Concurrency,
+    {
+        let mut aliased = HashSet::new();
+        for (url_path, fs_path) in &self.aliases {
+            let url_path = join_url_path(prefix, url_path)?;
+            if url_path.contains(&[':', '*'][..]) {
+                return Err(crate::config::Error::custom(failure::format_err!(
+                    "the alias must be a static path: {}",
+                    url_path
+                )));
+            }
+            if !aliased.insert(url_path.clone()) {
+                return Err(crate::config::Error::custom(failure::format_err!(
+                    "conflicting aliases: {}",
+                    url_path
+                )));
+            }
+
+            let path = self
+                .root_dir
+                .as_ref()
+                .join(fs_path)
+                .canonicalize()
+                .map_err(crate::config::Error::custom)?;
+            if !path.is_file() {
+                return Err(crate::config::Error::custom(failure::format_err!(
+                    "the alias must point to a file: {}",
+                    path.display()
+                )));
+            }
+
+            scope.route(
+                url_path,
+                ServeFile {
+                    inner: Arc::new(ServeFileInner::new(
+                        ArcPath(Arc::new(path)),
+                        self.config.clone(),
+                        false,
+                    )),
+                },
+            )?;
+        }
+        Ok(aliased)
+    }
+
+    fn configure_dynamic<M, C>(
+        self,
+        prefix: &Uri,
+        scope: &mut crate::app::config::Scope<'_, M, C>,
+    ) -> crate::app::Result<()>
+    where
+        M: ModifyHandler<ServeFile>,
+        M::Handler: Into<C::Handler>,
+        C: crate::app::config::Concurrency,
+    {
+        let root_dir = self
+            .root_dir
+            .as_ref()
+            .canonicalize()
+            .map_err(crate::config::Error::custom)?;
+
+        #[cfg_attr(not(feature = "notify"), allow(unused_mut))]
+        let mut inner = ServeFileInner::new(root_dir.into(), self.config, true);
+
+        #[cfg(feature = "notify")]
+        {
+            if self.watch {
+                inner.index = Some(Arc::new(
+                    self::watch::Index::watch(inner.path.to_path_buf())
+                        .map_err(crate::config::Error::custom)?,
+                ));
+            }
+        }
+
+        scope.route(
+            join_url_path(prefix, "/*path")?,
+            ServeFile {
+                inner: Arc::new(inner),
+            },
+        )
+    }
+}
+
+// ==== StaticRoutes ====
+
+#[doc(hidden)]
+pub use tsukuyomi_macros::static_routes_impl;
+
+/// A macro for embedding the files in a directory into the binary at compile time.
+///
+/// The macro expands to a [`StaticRoutes`], which registers a route for each
+/// file in the directory.  The path of the directory is relative to the root
+/// of the crate (the directory containing `Cargo.toml`).  The content types
+/// and the entity tags are computed at compile time.
+///
+/// The following options can be specified after the path:
+///
+/// * `exclude = ["pattern", ...]` - the glob patterns of the files that should
+///   not be embedded.  The patterns without `/` are matched against the filename,
+///   and the others are matched against the relative path from the directory.
+/// * `index = "index.html"` - the name of the files that are also served at the
+///   path of the parent directory.  Specify `false` to disable this behavior.
+///
+/// ```ignore
+/// use tsukuyomi::{App, static_routes};
+///
+/// let app = App::create(static_routes!(
+///     "./public",
+///     exclude = ["*.map", "drafts/**"],
+/// ))?;
+/// ```
+///
+/// [`StaticRoutes`]: ./fs/struct.StaticRoutes.html
+#[macro_export]
+macro_rules! static_routes {
+    ($($t:tt)*) => {{
+        use $crate::fs::internal as __fs_internal;
+        enum __Dummy {}
+        impl __Dummy {
+            $crate::fs::static_routes_impl!(__fs_internal, $($t)*);
+        }
+        __Dummy::call()
+    }};
+}
+
+#[doc(hidden)]
+pub mod internal {
+    pub use super::{EmbeddedFile, StaticRoutes};
+}
+
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct EmbeddedFile {
+    pub routes: &'static [&'static str],
+    pub data: &'static [u8],
+    pub content_type: &'static str,
+    pub etag: &'static str,
+}
+
+/// A configuration type for serving the files embedded by `static_routes!`.
+#[derive(Debug)]
+pub struct StaticRoutes {
+    files: &'static [EmbeddedFile],
+}
+
+impl StaticRoutes {
+    #[doc(hidden)]
+    pub fn new(files: &'static [EmbeddedFile]) -> Self {
+        Self { files }
+    }
+}
+
+impl<M, C> crate::config::Config<M, C> for StaticRoutes
+where
+    M: ModifyHandler<ServeEmbedded>,
+    M::Handler: Into<C::Handler>,
+    C: crate::app::config::Concurrency,
+{
+    type Error = crate::config::Error;
+
+    fn configure(self, scope: &mut crate::app::config::Scope<'_, M, C>) -> crate::app::Result<()> {
+        for file in self.files {
+            for route in file.routes {
+                scope.route(*route, ServeEmbedded { file })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A `Handler` that serves a file embedded by `static_routes!`.
+///
+/// The conditional requests with `If-None-Match` and the single byte range
+/// requests are supported, as in `output::ranged`.
+#[derive(Debug, Clone, Copy)]
+pub struct ServeEmbedded {
+    file: &'static EmbeddedFile,
+}
+
+impl ServeEmbedded {
+    fn is_modified(&self, headers: &HeaderMap) -> Result<bool, Error> {
+        match headers.get(header::IF_NONE_MATCH) {
+            Some(h) => {
+                let etag: ETag = h
+                    .to_str()
+                    .map_err(crate::error::bad_request)?
+                    .parse()
+                    .map_err(crate::error::bad_request)?;
+                Ok(self.file.etag.get(1..self.file.etag.len() - 1) != Some(&*etag.tag))
+            }
+            None => Ok(true),
+        }
+    }
+}
+
+mod impl_handler_for_serve_embedded {
+    use {
+        super::ServeEmbedded,
+        crate::{
+            error::Error,
+            future::TryFuture,
+            handler::{AllowedMethods, Handler},
+            input::Input,
+            output::IntoResponse,
+        },
+        bytes::Bytes,
+        futures01::{Async, Poll},
+        http::{
+            header::{self, HeaderValue},
+            Response, StatusCode,
+        },
+    };
+
+    impl Handler for ServeEmbedded {
+        type Output = Response<Bytes>;
+        type Error = Error;
+        type Handle = Self;
+
+        fn allowed_methods(&self) -> Option<&AllowedMethods> {
+            Some(&AllowedMethods::get())
+        }
+
+        fn handle(&self) -> Self::Handle {
+            *self
+        }
+    }
+
+    impl TryFuture for ServeEmbedded {
+        type Ok = Response<Bytes>;
+        type Error = Error;
+
+        fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+            let etag = HeaderValue::from_static(self.file.etag);
+            if !self.is_modified(input.request.headers())? {
+                let mut response = Response::new(Bytes::new());
+                *response.status_mut() = StatusCode::NOT_MODIFIED;
+                response.headers_mut().insert(header::ETAG, etag);
+                return Ok(Async::Ready(response));
+            }
+
+            let content_type = self
+                .file
+                .content_type
+                .parse()
+                .map_err(crate::error::internal_server_error)?;
+            let mut response =
+                crate::output::ranged(Bytes::from_static(self.file.data), content_type)
+                    .into_response(input.request)?;
+            response.headers_mut().insert(header::ETAG, etag);
+            Ok(Async::Ready(response))
+        }
+    }
+}
+
+#[cfg(feature = "notify")]
+mod watch {
+    use {
+        super::Lookup,
+        notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher},
+        std::{
+            collections::HashMap,
+            fmt,
+            fs::Metadata,
+            io,
+            path::{Path, PathBuf},
+            sync::{mpsc, Arc, Mutex, RwLock},
+            thread,
+            time::Duration,
+        },
+    };
+
+    /// The set of files in a directory along with their metadata, updated by
+    /// the filesystem events.
+    ///
+    /// The metadata of a file is discarded as soon as a modification is noticed,
+    /// and cached again when the debounced event is delivered.
+    pub(super) struct Index {
+        entries: Arc<RwLock<Entries>>,
+        _watcher: Mutex<RecommendedWatcher>,
+    }
+
+    type Entries = HashMap<PathBuf, Option<Metadata>>;
+
+    impl fmt::Debug for Index {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("Index").finish()
+        }
+    }
+
+    impl Index {
+        pub(super) fn watch(root: PathBuf) -> Result<Self, failure::Error> {
+            let entries = Arc::new(RwLock::new(HashMap::new()));
+            scan(&root, &mut entries.write().unwrap())?;
+
+            let (tx, rx) = mpsc::channel();
+            let mut watcher: RecommendedWatcher = Watcher::new(tx, Duration::from_millis(50))?;
+            watcher.watch(&root, RecursiveMode::Recursive)?;
+
+            thread::Builder::new()
+                .name("tsukuyomi-staticfiles-watcher".into())
+                .spawn({
+                    let entries = entries.clone();
+                    move || {
+                        // The loop ends when the watcher is dropped.
+                        for event in rx {
+                            let mut entries = entries.write().unwrap();
+                            handle_event(&root, event, &mut entries);
+                        }
+                    }
+                })?;
+
+            Ok(Self {
+                entries,
+                _watcher: Mutex::new(watcher),
+            })
+        }
+
+        pub(super) fn contains(&self, path: &Path) -> bool {
+            self.entries.read().unwrap().contains_key(path)
+        }
+
+        pub(super) fn lookup(&self, path: &Path) -> Lookup {
+            match self.entries.read().unwrap().get(path) {
+                Some(Some(metadata)) => Lookup::Found(metadata.clone()),
+                Some(None) => Lookup::Unknown,
+                None => Lookup::Missing,
+            }
+        }
+    }
+
+    fn scan(path: &Path, entries: &mut Entries) -> io::Result<()> {
+        let metadata = path.metadata()?;
+        if metadata.is_file() {
+            entries.insert(path.to_path_buf(), Some(metadata));
+        } else if metadata.is_dir() {
+            for entry in path.read_dir()? {
+                scan(&entry?.path(), entries)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn remove(path: &Path, entries: &mut Entries) {
+        entries.retain(|entry, _| !entry.starts_with(path));
+    }
+
+    fn invalidate(path: &Path, entries: &mut Entries) {
+        for (_, metadata) in entries
+            .iter_mut()
+            .filter(|&(entry, _)| entry.starts_with(path))
+        {
+            *metadata = None;
+        }
+    }
+
+    fn handle_event(root: &Path, event: DebouncedEvent, entries: &mut Entries) {
+        log::trace!("staticfiles: {:?}", event);
+        let result = match event {
+            DebouncedEvent::NoticeWrite(path) | DebouncedEvent::NoticeRemove(path) => {
+                invalidate(&path, entries);
+                Ok(())
+            }
+            DebouncedEvent::Create(path)
+            | DebouncedEvent::Write(path)
+            | DebouncedEvent::Chmod(path) => scan(&path, entries),
+            DebouncedEvent::Remove(path) => {
+                remove(&path, entries);
+                Ok(())
+            }
+            DebouncedEvent::Rename(from, to) => {
+                remove(&from, entries);
+                scan(&to, entries)
+            }
+            DebouncedEvent::Rescan => {
+                entries.clear();
+                scan(root, entries)
+            }
+            DebouncedEvent::Error(err, _) => {
+                log::warn!("staticfiles: failed to watch the directory: {}", err);
+                Ok(())
+            }
+        };
+        if let Err(err) = result {
+            log::warn!("staticfiles: failed to scan the directory: {}", err);
+        }
+    }
+}
@@ -1,10 +1,16 @@
 //! Compatible layer of asynchronous tasks used within the framework.
 
+#[cfg(feature = "async-await")]
+mod compat03;
+
 use crate::{error::Error, input::Input, util::Either};
 
 #[doc(no_inline)]
 pub use futures01::{try_ready, Async, Poll};
 
+#[cfg(feature = "async-await")]
+pub use self::compat03::{compat01_as_03, Compat01As03, Compat03};
+
 /// A trait that abstracts the general asynchronous tasks within the framework.
 pub trait TryFuture {
     type Ok;
@@ -0,0 +1,117 @@
+//! The compatibility layer between `std::future::Future` and the futures 0.1 based tasks.
+//!
+//! The futures converted by this module are driven by the tasks of futures 0.1, and
+//! hence they must run on the tokio 0.1 runtime that drives the application.
+//! The wakeups of `std::future::Future` are forwarded to the current futures 0.1 task,
+//! and the futures 0.1 based ones such as the timers of `tokio-timer` must be converted
+//! with `compat01_as_03` before being awaited in `async fn`.
+
+use {
+    super::{Async, Poll, TryFuture},
+    crate::{error::Error, input::Input},
+    futures01::executor::Notify,
+    std::{
+        fmt,
+        future::Future,
+        pin::Pin,
+        sync::Arc,
+        task::{Context, Poll as Poll03, Wake, Waker},
+    },
+};
+
+/// A wrapper struct that provides the implementation of `TryFuture` for
+/// implementors of `std::future::Future`.
+#[must_use = "futures do nothing unless polled."]
+pub struct Compat03<F>(Pin<Box<F>>);
+
+impl<F> fmt::Debug for Compat03<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Compat03").finish()
+    }
+}
+
+impl<F, T, E> From<F> for Compat03<F>
+where
+    F: Future<Output = Result<T, E>>,
+    E: Into<Error>,
+{
+    fn from(future: F) -> Self {
+        Compat03(Box::pin(future))
+    }
+}
+
+impl<F, T, E> TryFuture for Compat03<F>
+where
+    F: Future<Output = Result<T, E>>,
+    E: Into<Error>,
+{
+    type Ok = T;
+    type Error = E;
+
+    fn poll_ready(&mut self, _: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        let waker = Waker::from(Arc::new(TaskWaker(futures01::task::current())));
+        match self.0.as_mut().poll(&mut Context::from_waker(&waker)) {
+            Poll03::Ready(result) => result.map(Async::Ready),
+            Poll03::Pending => Ok(Async::NotReady),
+        }
+    }
+}
+
+/// The `Waker` that notifies the futures 0.1 task polling the future.
+struct TaskWaker(futures01::task::Task);
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.notify();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.notify();
+    }
+}
+
+/// Converts a futures 0.1 `Future` into `std::future::Future`, so that it can be awaited.
+pub fn compat01_as_03<F>(future: F) -> Compat01As03<F>
+where
+    F: futures01::Future,
+{
+    Compat01As03(futures01::executor::spawn(future))
+}
+
+/// A `std::future::Future` created by `compat01_as_03`.
+#[must_use = "futures do nothing unless polled."]
+pub struct Compat01As03<F>(futures01::executor::Spawn<F>);
+
+impl<F> fmt::Debug for Compat01As03<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Compat01As03").finish()
+    }
+}
+
+// The futures 0.1 `Future`s are never pinned.
+impl<F> Unpin for Compat01As03<F> {}
+
+impl<F> Future for Compat01As03<F>
+where
+    F: futures01::Future,
+{
+    type Output = Result<F::Item, F::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll03<Self::Output> {
+        let notify = Arc::new(WakerNotify(cx.waker().clone()));
+        match self.get_mut().0.poll_future_notify(&notify, 0) {
+            Ok(Async::Ready(item)) => Poll03::Ready(Ok(item)),
+            Ok(Async::NotReady) => Poll03::Pending,
+            Err(err) => Poll03::Ready(Err(err)),
+        }
+    }
+}
+
+/// The futures 0.1 `Notify` that wakes the `std::future::Future` awaiting the future.
+struct WakerNotify(Waker);
+
+impl Notify for WakerNotify {
+    fn notify(&self, _: usize) {
+        self.0.wake_by_ref();
+    }
+}
@@ -0,0 +1,226 @@
+//! Guards for selecting the endpoint that handles the request on a resource.
+//!
+//! ```
+//! # use tsukuyomi::{config::prelude::*, guard, App};
+//! let app = App::create(
+//!     path!("/") //
+//!         .to(chain![
+//!             endpoint::get()
+//!                 .guard(guard::accepts(mime::APPLICATION_JSON))
+//!                 .reply(r#"{"message":"hello"}"#),
+//!             endpoint::get()
+//!                 .guard(guard::accepts(mime::TEXT_HTML))
+//!                 .reply("<p>hello</p>"),
+//!         ]),
+//! );
+//! # drop(app);
+//! ```
+
+#[cfg(feature = "reverse-dns")]
+pub use self::reverse_dns::reverse_dns;
+
+pub mod reverse_dns;
+
+use {
+    crate::{
+        endpoint::{ApplyContext, ApplyError},
+        error::Error,
+        input::{accept::Accept, ConnectionInfo},
+    },
+    futures01::{Async, Future, Poll},
+    http::header::HOST,
+    mime::Mime,
+    std::{collections::VecDeque, fmt, sync::Arc},
+};
+
+/// A future that performs the asynchronous part of the check of a `Guard`.
+pub type Verify = Box<dyn Future<Item = (), Error = Error> + Send + 'static>;
+
+/// A trait representing the condition of the request for applying an endpoint.
+pub trait Guard {
+    /// Checks if the endpoint can be applied to the request.
+    fn check(&self, cx: &ApplyContext<'_, '_>) -> Result<(), ApplyError>;
+
+    /// Returns the asynchronous part of the check, which requires some I/O such as
+    /// the DNS lookups, if any.
+    ///
+    /// This method is called after `check` passes. The returned futures of the guards
+    /// are completed in order after the endpoint is selected and before it is polled,
+    /// and the request is rejected with the error if any of them fails. Unlike the
+    /// one returned from `check`, the error does not lead to trying the other endpoints.
+    fn verify(&self, cx: &ApplyContext<'_, '_>) -> Option<Verify> {
+        let _ = cx;
+        None
+    }
+
+    /// Returns the media type produced by the endpoint guarded by this guard, if any.
+    ///
+    /// The returned value is used for negotiating the response type on the route.
+    fn produces(&self) -> Option<&Mime> {
+        None
+    }
+}
+
+/// Creates a `Guard` that checks if the specified media type is acceptable for the client.
+///
+/// When several endpoints on a route are guarded by this guard, the one that
+/// produces the media type preferred by `Accept` is applied, and `Vary: Accept` is
+/// added to the response. If none of them is acceptable, the route responds
+/// with `406 Not Acceptable` listing the producible media types.
+pub fn accepts(mime: Mime) -> Accepts {
+    Accepts { mime }
+}
+
+/// A `Guard` created by `accepts`.
+#[derive(Debug, Clone)]
+pub struct Accepts {
+    mime: Mime,
+}
+
+impl Guard for Accepts {
+    fn check(&self, cx: &ApplyContext<'_, '_>) -> Result<(), ApplyError> {
+        let acceptable = match cx.negotiated_type() {
+            Some(negotiated) => *negotiated == self.mime,
+            None => match Accept::from_headers(cx.request().headers()) {
+                Some(accept) => accept.quality(&self.mime) > 0,
+                None => true,
+            },
+        };
+        if acceptable {
+            Ok(())
+        } else {
+            Err(ApplyError::not_acceptable())
+        }
+    }
+
+    fn produces(&self) -> Option<&Mime> {
+        Some(&self.mime)
+    }
+}
+
+/// Creates a `Guard` that checks if the client presented the specified server name with SNI.
+///
+/// The requests arriving on the connections with another server name, or without
+/// TLS, are rejected with `421 Misdirected Request`. The comparison is case-insensitive.
+pub fn sni(server_name: impl Into<String>) -> Sni {
+    Sni {
+        server_name: server_name.into().to_ascii_lowercase(),
+        strict: false,
+    }
+}
+
+/// A `Guard` created by `sni`.
+#[derive(Debug, Clone)]
+pub struct Sni {
+    server_name: String,
+    strict: bool,
+}
+
+impl Sni {
+    /// Sets whether to reject the requests whose `Host` disagrees with the server name.
+    ///
+    /// A client may reuse a connection for another host covered by the same
+    /// certificate, in which case the request is routed by the server name of
+    /// the first one unless this flag is enabled. The default value is `false`.
+    pub fn strict(self, strict: bool) -> Self {
+        Self { strict, ..self }
+    }
+}
+
+impl Guard for Sni {
+    fn check(&self, cx: &ApplyContext<'_, '_>) -> Result<(), ApplyError> {
+        let request = cx.request();
+        let matched = request
+            .extensions()
+            .get::<ConnectionInfo>()
+            .and_then(|info| info.server_name())
+            .map_or(false, |name| name.eq_ignore_ascii_case(&self.server_name));
+        if !matched {
+            return Err(ApplyError::misdirected_request());
+        }
+
+        if self.strict {
+            let host = request
+                .headers()
+                .get(HOST)
+                .and_then(|host| host.to_str().ok())
+                .or_else(|| request.uri().host());
+            let host = host.map(|host| match host.rfind(':') {
+                Some(pos) if !host.ends_with(']') => &host[..pos],
+                _ => host,
+            });
+            if !host.map_or(false, |host| host.eq_ignore_ascii_case(&self.server_name)) {
+                return Err(ApplyError::misdirected_request());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A set of guards registered to an endpoint.
+#[derive(Clone, Default)]
+pub(crate) struct Guards(Vec<Arc<dyn Guard + Send + Sync + 'static>>);
+
+impl fmt::Debug for Guards {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Guards")
+            .field("len", &self.0.len())
+            .finish()
+    }
+}
+
+impl Guards {
+    pub(crate) fn push(&mut self, guard: impl Guard + Send + Sync + 'static) {
+        self.0.push(Arc::new(guard));
+    }
+
+    /// Checks all guards, and defers their asynchronous checks to the endpoint
+    /// applied with `cx` if all of them pass.
+    pub(crate) fn check(&self, cx: &mut ApplyContext<'_, '_>) -> Result<(), ApplyError> {
+        let mut verify = vec![];
+        for guard in &self.0 {
+            guard.check(cx)?;
+            verify.extend(guard.verify(cx));
+        }
+        cx.defer(verify);
+        Ok(())
+    }
+
+    pub(crate) fn produces(&self) -> Vec<Mime> {
+        let mut produces: Vec<Mime> = vec![];
+        for mime in self.0.iter().filter_map(|guard| guard.produces()) {
+            if !produces.contains(mime) {
+                produces.push(mime.clone());
+            }
+        }
+        produces
+    }
+}
+
+/// The asynchronous checks deferred by the guards of the applied endpoint.
+#[derive(Default)]
+pub(crate) struct Pending(VecDeque<Verify>);
+
+impl fmt::Debug for Pending {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pending")
+            .field("len", &self.0.len())
+            .finish()
+    }
+}
+
+impl Pending {
+    pub(crate) fn extend(&mut self, verify: impl IntoIterator<Item = Verify>) {
+        self.0.extend(verify);
+    }
+
+    /// Completes the checks in order, and returns the error of the first failed one.
+    pub(crate) fn poll_ready(&mut self) -> Poll<(), Error> {
+        while let Some(verify) = self.0.front_mut() {
+            futures01::try_ready!(verify.poll());
+            self.0.pop_front();
+        }
+        Ok(Async::Ready(()))
+    }
+}
@@ -0,0 +1,327 @@
+//! A guard verifying the client by the forward-confirmed reverse DNS (FCrDNS).
+//!
+//! Some providers of webhooks ask the receivers to verify the caller by resolving
+//! the address of the peer into a host name within their domain. Since the PTR
+//! records are controlled by the owner of the address, the host name is resolved
+//! again and accepted only if it includes the original address.
+//!
+//! ```
+//! # use tsukuyomi::{config::prelude::*, guard::reverse_dns::{Lookup, Resolver, ReverseDns}, App};
+//! # use std::{net::IpAddr, time::Duration};
+//! # struct MyResolver;
+//! # impl Resolver for MyResolver {
+//! #     fn reverse(&self, _: IpAddr) -> Lookup<Vec<String>> { unimplemented!() }
+//! #     fn forward(&self, _: &str) -> Lookup<Vec<IpAddr>> { unimplemented!() }
+//! # }
+//! let app = App::create(
+//!     path!("/webhook").to(endpoint::post()
+//!         .guard(ReverseDns::with_resolver(MyResolver, vec!["payments.example.com"])
+//!             .timeout(Duration::from_secs(1)))
+//!         .reply("accepted")),
+//! );
+//! # drop(app);
+//! ```
+
+use {
+    super::{Guard, Verify},
+    crate::{
+        endpoint::{ApplyContext, ApplyError},
+        input::ConnectionInfo,
+        rt::{Clock, Delay},
+    },
+    futures01::{Async, Future, Poll, Stream},
+    http::StatusCode,
+    std::{
+        collections::HashMap,
+        fmt,
+        net::IpAddr,
+        sync::{Arc, Mutex},
+        time::{Duration, Instant},
+    },
+};
+
+/// A future that resolves the result of a DNS lookup.
+pub type Lookup<T> = Box<dyn Future<Item = T, Error = failure::Error> + Send + 'static>;
+
+/// A trait abstracting the DNS lookups performed by `ReverseDns`.
+pub trait Resolver: Send + Sync + 'static {
+    /// Looks up the host names of the address, from its PTR records.
+    fn reverse(&self, addr: IpAddr) -> Lookup<Vec<String>>;
+
+    /// Looks up the addresses of the host name, from its A and AAAA records.
+    fn forward(&self, host: &str) -> Lookup<Vec<IpAddr>>;
+}
+
+/// Creates a `ReverseDns` that resolves the addresses with the system configuration
+/// of `trust-dns-resolver`.
+///
+/// The background task of the resolver is spawned onto the default executor at
+/// the first lookup, so the guard must be used on the runtime.
+#[cfg(feature = "reverse-dns")]
+pub fn reverse_dns<I>(suffixes: I) -> ReverseDns
+where
+    I: IntoIterator,
+    I::Item: Into<String>,
+{
+    ReverseDns::with_resolver(self::trust_dns::TrustDns::default(), suffixes)
+}
+
+/// A `Guard` that accepts the clients whose address is verified by the forward-confirmed
+/// reverse DNS.
+///
+/// The check passes if one of the host names in the PTR records of the peer address
+/// ends with one of the allowed domain suffixes, and the A or AAAA records of that
+/// host name include the peer address. The suffixes match at the label boundaries,
+/// so `example.com` accepts `api.example.com` but not `badexample.com`.
+///
+/// The verdicts are cached per address for the TTL. The lookup failures and the
+/// timeouts reject the request, but are not cached.
+#[derive(Clone)]
+pub struct ReverseDns {
+    resolver: Arc<dyn Resolver>,
+    suffixes: Arc<[String]>,
+    timeout: Duration,
+    ttl: Duration,
+    rejection: StatusCode,
+    cache: Arc<Mutex<HashMap<IpAddr, (bool, Instant)>>>,
+}
+
+impl fmt::Debug for ReverseDns {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReverseDns")
+            .field("suffixes", &self.suffixes)
+            .field("timeout", &self.timeout)
+            .field("ttl", &self.ttl)
+            .field("rejection", &self.rejection)
+            .finish()
+    }
+}
+
+impl ReverseDns {
+    /// Creates a `ReverseDns` that performs the lookups with the specified `Resolver`.
+    pub fn with_resolver<I>(resolver: impl Resolver, suffixes: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        Self {
+            resolver: Arc::new(resolver),
+            suffixes: suffixes
+                .into_iter()
+                .map(|suffix| normalize(&suffix.into()).to_owned())
+                .collect::<Vec<_>>()
+                .into(),
+            timeout: Duration::from_secs(3),
+            ttl: Duration::from_secs(600),
+            rejection: StatusCode::FORBIDDEN,
+            cache: Default::default(),
+        }
+    }
+
+    /// Sets the time limit of the entire lookups for an address.
+    ///
+    /// The default value is 3 seconds.
+    pub fn timeout(self, timeout: Duration) -> Self {
+        Self { timeout, ..self }
+    }
+
+    /// Sets the duration for which the verdicts are cached.
+    ///
+    /// The default value is 10 minutes.
+    pub fn ttl(self, ttl: Duration) -> Self {
+        Self { ttl, ..self }
+    }
+
+    /// Sets the status code of the response to the rejected requests.
+    ///
+    /// The default value is `403 Forbidden`.
+    pub fn rejection(self, rejection: StatusCode) -> Self {
+        Self { rejection, ..self }
+    }
+
+    fn is_allowed(&self, host: &str) -> bool {
+        let host = normalize(host);
+        self.suffixes.iter().any(|suffix| {
+            host.len() >= suffix.len()
+                && host[host.len() - suffix.len()..].eq_ignore_ascii_case(suffix)
+                && (host.len() == suffix.len()
+                    || host.as_bytes()[host.len() - suffix.len() - 1] == b'.')
+        })
+    }
+
+    fn cached(&self, addr: IpAddr, now: Instant) -> Option<bool> {
+        let cache = self.cache.lock().unwrap();
+        cache
+            .get(&addr)
+            .filter(|&&(_, expires)| expires > now)
+            .map(|&(verified, _)| verified)
+    }
+
+    fn verdict(&self, verified: bool) -> Result<(), crate::Error> {
+        if verified {
+            Ok(())
+        } else {
+            Err(crate::error::custom(
+                self.rejection,
+                "the client address is not verified by the reverse DNS",
+            ))
+        }
+    }
+
+    /// Creates the future that resolves whether the address passes the FCrDNS check.
+    fn lookup(&self, addr: IpAddr) -> Lookup<bool> {
+        let this = self.clone();
+        Box::new(self.resolver.reverse(addr).and_then(move |hosts| {
+            let candidates: Vec<String> = hosts
+                .into_iter()
+                .filter(|host| this.is_allowed(host))
+                .collect();
+            futures01::stream::iter_ok(candidates)
+                .and_then(move |host| {
+                    // A host name which does not resolve just fails the confirmation.
+                    this.resolver
+                        .forward(normalize(&host))
+                        .or_else(|_| Ok(vec![]))
+                })
+                .filter(move |addrs| addrs.contains(&addr))
+                .into_future()
+                .map(|(confirmed, _rest)| confirmed.is_some())
+                .map_err(|(err, _rest)| err)
+        }))
+    }
+}
+
+impl Guard for ReverseDns {
+    fn check(&self, _: &ApplyContext<'_, '_>) -> Result<(), ApplyError> {
+        Ok(())
+    }
+
+    fn verify(&self, cx: &ApplyContext<'_, '_>) -> Option<Verify> {
+        let addr = match cx.request().extensions().get::<ConnectionInfo>() {
+            Some(info) => info.peer_addr().ip(),
+            None => return Some(Box::new(futures01::future::result(self.verdict(false)))),
+        };
+
+        let clock = cx.clock().clone();
+        let now = clock.now();
+        if let Some(verified) = self.cached(addr, now) {
+            return Some(Box::new(futures01::future::result(self.verdict(verified))));
+        }
+
+        Some(Box::new(Verifying {
+            lookup: self.lookup(addr),
+            deadline: clock.delay(now + self.timeout),
+            guard: self.clone(),
+            addr,
+            clock,
+        }))
+    }
+}
+
+/// The lookups of an address that is not cached, bounded by the timeout.
+#[allow(missing_debug_implementations)]
+struct Verifying {
+    lookup: Lookup<bool>,
+    deadline: Delay,
+    guard: ReverseDns,
+    addr: IpAddr,
+    clock: Arc<dyn Clock>,
+}
+
+impl Future for Verifying {
+    type Item = ();
+    type Error = crate::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.lookup.poll() {
+            Ok(Async::Ready(verified)) => {
+                let now = self.clock.now();
+                let mut cache = self.guard.cache.lock().unwrap();
+                cache.retain(|_, &mut (_, expires)| expires > now);
+                cache.insert(self.addr, (verified, now + self.guard.ttl));
+                drop(cache);
+                return self.guard.verdict(verified).map(Async::Ready);
+            }
+            Ok(Async::NotReady) => {}
+            Err(err) => {
+                log::debug!(
+                    "failed to look up the reverse DNS of {}: {}",
+                    self.addr,
+                    err
+                );
+                return self.guard.verdict(false).map(Async::Ready);
+            }
+        }
+
+        match self.deadline.poll() {
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Ok(Async::Ready(())) => {
+                log::debug!("timed out looking up the reverse DNS of {}", self.addr);
+                self.guard.verdict(false).map(Async::Ready)
+            }
+            Err(err) => Err(crate::error::internal_server_error(err)),
+        }
+    }
+}
+
+/// Removes the trailing dot of the fully qualified domain name and the leading
+/// dot of the suffix.
+fn normalize(name: &str) -> &str {
+    name.trim_end_matches('.').trim_start_matches('.')
+}
+
+#[cfg(feature = "reverse-dns")]
+mod trust_dns {
+    use {
+        super::{Lookup, Resolver},
+        futures01::Future,
+        std::{net::IpAddr, sync::Mutex},
+        tokio_executor::Executor,
+        trust_dns_resolver::AsyncResolver,
+    };
+
+    /// The `Resolver` backed by `trust-dns-resolver`, created at the first lookup.
+    #[derive(Debug, Default)]
+    pub(super) struct TrustDns {
+        resolver: Mutex<Option<AsyncResolver>>,
+    }
+
+    impl TrustDns {
+        fn resolver(&self) -> Result<AsyncResolver, failure::Error> {
+            let mut resolver = self.resolver.lock().unwrap();
+            if let Some(ref resolver) = *resolver {
+                return Ok(resolver.clone());
+            }
+            let (created, background) = AsyncResolver::from_system_conf()?;
+            tokio_executor::DefaultExecutor::current().spawn(Box::new(background))?;
+            *resolver = Some(created.clone());
+            Ok(created)
+        }
+    }
+
+    impl Resolver for TrustDns {
+        fn reverse(&self, addr: IpAddr) -> Lookup<Vec<String>> {
+            match self.resolver() {
+                Ok(resolver) => Box::new(
+                    resolver
+                        .reverse_lookup(addr)
+                        .map(|names| names.iter().map(|name| name.to_ascii()).collect())
+                        .map_err(Into::into),
+                ),
+                Err(err) => Box::new(futures01::future::err(err)),
+            }
+        }
+
+        fn forward(&self, host: &str) -> Lookup<Vec<IpAddr>> {
+            match self.resolver() {
+                Ok(resolver) => Box::new(
+                    resolver
+                        .lookup_ip(host)
+                        .map(|addrs| addrs.iter().collect())
+                        .map_err(Into::into),
+                ),
+                Err(err) => Box::new(futures01::future::err(err)),
+            }
+        }
+    }
+}
@@ -228,6 +228,13 @@ where
 }
 
 /// A trait representing a type for modifying the instance of `Handler`.
+///
+/// The errors from the inner handler, such as the rejections of the extractors,
+/// pass through the modified handler as they are, and the application renders
+/// them into the responses after all modifiers have completed. The requests that
+/// matched no route do not reach the modifiers at all. The effects that must be
+/// applied to every response should be registered by `config::modify_response`,
+/// or staged in `Input::response` which is merged into the error responses.
 pub trait ModifyHandler<H: Handler> {
     type Output;
     type Handler: Handler<Output = Self::Output>;
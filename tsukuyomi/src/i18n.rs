@@ -0,0 +1,303 @@
+//! Localization of the error messages generated by the framework.
+//!
+//! The errors that have message keys, such as the ones returned from the built-in
+//! extractors, are rendered with the text provided by the `Translator` registered
+//! as a state. The locale is taken from the value negotiated by
+//! `extractor::header::accept_language`, or from `Accept-Language` if the
+//! extractor is not used. If no translation is found, the default English text is used.
+//!
+//! ```
+//! # use tsukuyomi::{config::prelude::*, i18n::{self, Args, Locale, Translator}, App};
+//! struct French;
+//!
+//! impl Translator for French {
+//!     fn translate(&self, key: &str, locale: &Locale, args: &Args) -> Option<String> {
+//!         match (key, locale.language()) {
+//!             ("error.not_found", "fr") => Some("introuvable".into()),
+//!             ("error.body.too_large", "fr") => Some(i18n::interpolate(
+//!                 "le corps dépasse la limite ({limit} octets)",
+//!                 args,
+//!             )),
+//!             _ => None,
+//!         }
+//!     }
+//! }
+//!
+//! let app = App::create(chain![
+//!     i18n::translator(French),
+//!     path!("/") //
+//!         .to(endpoint::get().reply("bonjour")),
+//! ]);
+//! # drop(app);
+//! ```
+//!
+//! The message keys of the framework are as follows:
+//!
+//! | key | arguments |
+//! |-----|-----------|
+//! | `error.bad_request`, `error.not_found`, `error.method_not_allowed`, `error.not_acceptable`, `error.payload_too_large`, `error.internal_server_error` | - |
+//! | `error.body.missing_content_type` | - |
+//! | `error.body.unexpected_content_type` | `expected` |
+//! | `error.body.invalid_mime` | - |
+//! | `error.body.not_utf8_charset` | - |
+//! | `error.body.invalid_json`, `error.body.invalid_urlencoded`, `error.body.invalid_text` | `cause` |
+//! | `error.body.too_large` | `limit` |
+//! | `error.body.part_too_large` | `index`, `limit` |
+//! | `error.body.required`, `error.body.forbidden` | - |
+//! | `error.body.content_mismatch` | `declared`, `detected` |
+//! | `error.auth.unauthenticated`, `error.auth.forbidden` | - |
+
+use {
+    crate::{
+        config::State,
+        input::{
+            encoding::parse_qvalue,
+            localmap::{local_key, LocalData},
+        },
+    },
+    http::header::{HeaderMap, ACCEPT_LANGUAGE},
+    std::{fmt, sync::Arc},
+};
+
+/// A language tag, such as `fr` or `fr-CA`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Locale(String);
+
+impl Locale {
+    /// Creates a `Locale` from the language tag.
+    ///
+    /// The tag is normalized to lower case.
+    pub fn new(tag: impl Into<String>) -> Self {
+        Locale(tag.into().to_ascii_lowercase())
+    }
+
+    /// Returns the language tag.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Returns the primary language subtag, such as `fr` of `fr-CA`.
+    pub fn language(&self) -> &str {
+        self.0.split('-').next().unwrap_or("")
+    }
+
+    /// Returns whether this locale is covered by the language range in `Accept-Language`.
+    fn matches(&self, range: &str) -> bool {
+        range == "*"
+            || self.0 == range
+            || (self.0.starts_with(range) && self.0.as_bytes().get(range.len()) == Some(&b'-'))
+    }
+
+    /// Parses the language ranges in `Accept-Language`, ordered by the preference.
+    ///
+    /// The malformed elements and the ones with `q=0` are ignored.
+    fn parse_ranges(headers: &HeaderMap) -> Vec<String> {
+        let mut ranges: Vec<(String, u16)> = headers
+            .get_all(ACCEPT_LANGUAGE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(|item| {
+                let mut params = item.split(';').map(str::trim);
+                let range = params.next().filter(|range| !range.is_empty())?;
+                let mut qvalue = 1000;
+                for param in params {
+                    let mut kv = param.splitn(2, '=').map(str::trim);
+                    if kv.next()?.eq_ignore_ascii_case("q") {
+                        qvalue = parse_qvalue(kv.next()?)?;
+                    }
+                }
+                Some((range.to_ascii_lowercase(), qvalue))
+            })
+            .filter(|&(_, q)| q > 0)
+            .collect();
+        ranges.sort_by_key(|&(_, q)| std::cmp::Reverse(q));
+        ranges.into_iter().map(|(range, _)| range).collect()
+    }
+
+    /// Selects the locale in `available` preferred by `Accept-Language`.
+    ///
+    /// If no locale matches a language range, the range is truncated from the end
+    /// (e.g. `fr-BE` to `fr`) and looked up again before trying the next one.
+    /// It returns `None` if the header field is missing or no locale is acceptable.
+    pub fn negotiate(headers: &HeaderMap, available: &[Locale]) -> Option<Locale> {
+        Self::parse_ranges(headers).into_iter().find_map(|range| {
+            let mut range = &*range;
+            loop {
+                if let Some(locale) = available.iter().find(|locale| locale.matches(range)) {
+                    return Some(locale.clone());
+                }
+                range = &range[..range.rfind('-')?];
+            }
+        })
+    }
+
+    /// Returns the most preferred locale in `Accept-Language`, other than the wildcard.
+    pub fn preferred(headers: &HeaderMap) -> Option<Locale> {
+        Self::parse_ranges(headers)
+            .into_iter()
+            .find(|range| range != "*")
+            .map(Locale)
+    }
+}
+
+impl fmt::Display for Locale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl LocalData for Locale {
+    local_key! {
+        /// The local key for the locale negotiated for the current request.
+        const KEY: Self;
+    }
+}
+
+/// The named arguments of a message.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Args(Vec<(&'static str, String)>);
+
+impl Args {
+    /// Creates an empty `Args`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends an argument.
+    pub fn arg(mut self, name: &'static str, value: impl fmt::Display) -> Self {
+        self.0.push((name, value.to_string()));
+        self
+    }
+
+    /// Returns the value of the argument with the specified name.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|&&(n, _)| n == name)
+            .map(|(_, value)| &**value)
+    }
+
+    /// Returns an iterator over the pairs of the names and the values.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, &str)> + '_ {
+        self.0.iter().map(|(name, value)| (*name, &**value))
+    }
+}
+
+/// Replaces the placeholders of the form `{name}` in the template with the arguments.
+///
+/// The placeholders with unknown names are left as they are.
+pub fn interpolate(template: &str, args: &Args) -> String {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let value = rest
+            .find('}')
+            .and_then(|end| args.get(&rest[1..end]).map(|value| (end, value)));
+        match value {
+            Some((end, value)) => {
+                output.push_str(value);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                output.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+/// A trait for translating the messages of the errors.
+pub trait Translator {
+    /// Returns the text of the message with the specified key in the locale,
+    /// or `None` if it is not translated.
+    fn translate(&self, key: &str, locale: &Locale, args: &Args) -> Option<String>;
+}
+
+impl<F> Translator for F
+where
+    F: Fn(&str, &Locale, &Args) -> Option<String>,
+{
+    fn translate(&self, key: &str, locale: &Locale, args: &Args) -> Option<String> {
+        (*self)(key, locale, args)
+    }
+}
+
+/// A `Translator` registered as a state, created by `i18n::translator`.
+#[derive(Clone)]
+pub struct SharedTranslator(Arc<dyn Translator + Send + Sync + 'static>);
+
+impl fmt::Debug for SharedTranslator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedTranslator").finish()
+    }
+}
+
+impl SharedTranslator {
+    /// Translates the message in the locale, falling back to its primary language.
+    pub(crate) fn translate(&self, key: &str, locale: &Locale, args: &Args) -> Option<String> {
+        self.0.translate(key, locale, args).or_else(|| {
+            if locale.language() == locale.as_str() {
+                return None;
+            }
+            let language = Locale::new(locale.language());
+            self.0.translate(key, &language, args)
+        })
+    }
+}
+
+/// Creates a `Config` that registers the translator of the error messages into the scope.
+///
+/// The translator registered in a sub-scope overrides the outer one.
+pub fn translator<T>(translator: T) -> State<SharedTranslator>
+where
+    T: Translator + Send + Sync + 'static,
+{
+    crate::config::state(SharedTranslator(Arc::new(translator)))
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, http::header::HeaderValue};
+
+    #[test]
+    fn interpolate_args() {
+        let args = Args::new().arg("limit", 1024).arg("index", 2);
+        assert_eq!(
+            interpolate("part #{index} exceeds {limit} bytes {unknown}", &args),
+            "part #2 exceeds 1024 bytes {unknown}"
+        );
+        assert_eq!(interpolate("{", &args), "{");
+    }
+
+    #[test]
+    fn negotiate_locale() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            ACCEPT_LANGUAGE,
+            HeaderValue::from_static("de;q=0.5, fr-CA, en;q=0.8, ja;q=0"),
+        );
+        let available = [Locale::new("en"), Locale::new("fr-CA"), Locale::new("ja")];
+        assert_eq!(
+            Locale::negotiate(&headers, &available),
+            Some(Locale::new("fr-ca"))
+        );
+        assert_eq!(Locale::preferred(&headers), Some(Locale::new("fr-CA")));
+
+        let available = [Locale::new("ja"), Locale::new("en-US")];
+        assert_eq!(
+            Locale::negotiate(&headers, &available),
+            Some(Locale::new("en-US"))
+        );
+
+        let available = [Locale::new("en"), Locale::new("fr")];
+        assert_eq!(
+            Locale::negotiate(&headers, &available),
+            Some(Locale::new("fr"))
+        );
+    }
+}
@@ -1,15 +1,34 @@
 //! Components for accessing the incoming request data.
 
+pub mod accept;
 pub mod body;
+#[cfg(feature = "csv")]
+pub mod csv;
+#[cfg(feature = "chrono")]
+pub mod datetime;
+pub mod encoding;
 pub mod header;
 pub mod localmap;
+pub mod multipart;
 pub mod param;
+pub mod progress;
+pub mod query;
+pub mod response;
 
 use {
-    self::{localmap::LocalMap, param::Params},
+    self::{
+        localmap::{LocalData, LocalMap},
+        param::{MatrixParams, MatrixSegments, Params},
+        query::{ParsedQuery, QueryPairs},
+        response::ResponseHeaders,
+    },
+    crate::{
+        app::{Dispatch, Routes, States},
+        rt::{Clock, Random},
+    },
     cookie::{Cookie, CookieJar},
     http::{header::HeaderMap, Request},
-    std::{marker::PhantomData, rc::Rc},
+    std::{any::TypeId, borrow::Cow, marker::PhantomData, rc::Rc, sync::Arc},
 };
 
 /// A proxy object for accessing the incoming HTTP request data.
@@ -27,48 +46,302 @@ pub struct Input<'task> {
     /// An any-map that contains arbitrary request-local data.
     pub locals: &'task mut LocalMap,
 
-    /// A map of header fields that will be inserted at reply to the client.
-    pub response_headers: &'task mut Option<HeaderMap>,
+    pub(crate) response_headers: &'task mut ResponseHeaders,
+
+    pub(crate) states: &'task dyn States,
+
+    pub(crate) routes: &'task dyn Routes,
+
+    pub(crate) dispatch: &'task dyn Dispatch,
+
+    pub(crate) clock: &'task Arc<dyn Clock>,
+
+    pub(crate) random: &'task dyn Random,
 
     pub(crate) _marker: PhantomData<Rc<()>>,
 }
 
+impl<'task> Input<'task> {
+    /// Returns a reference to the state of type `T` registered in the nearest scope.
+    ///
+    /// The states are looked up from the scope which the request belongs to,
+    /// towards the root scope.
+    pub fn state<T>(&self) -> Option<&T>
+    where
+        T: Send + Sync + 'static,
+    {
+        self.states
+            .get(TypeId::of::<T>())
+            .and_then(|state| state.downcast_ref())
+    }
+
+    /// Returns an iterator over all states of type `T` visible from the scope
+    /// which the request belongs to.
+    ///
+    /// Unlike `state`, the states registered in the outer scopes are not hidden
+    /// by the inner ones. They are returned in order from the root scope.
+    pub fn states<T>(&self) -> impl Iterator<Item = &T>
+    where
+        T: Send + Sync + 'static,
+    {
+        self.states
+            .get_all(TypeId::of::<T>())
+            .filter_map(|state| state.downcast_ref())
+    }
+
+    /// Returns the clock of the application.
+    ///
+    /// The components that depend on the current time should read it from
+    /// this clock rather than `Instant::now`, so that it can be replaced in tests.
+    pub fn clock(&self) -> &Arc<dyn Clock> {
+        self.clock
+    }
+
+    /// Returns the source of random numbers of the application.
+    pub fn random(&self) -> &dyn Random {
+        self.random
+    }
+
+    /// Returns the matrix parameters of the path segment at `index`, counted from zero.
+    ///
+    /// This method always returns `None` unless `AppBase::matrix_params` is enabled
+    /// and the request path contains the matrix parameters.
+    pub fn matrix_params(&self, index: usize) -> Option<&MatrixParams> {
+        self.locals
+            .get(&MatrixSegments::KEY)
+            .and_then(|segments| segments.get(index))
+    }
+
+    /// Returns an iterator over the percent-decoded key-value pairs in the query string.
+    ///
+    /// The query string is parsed at the first call, and the positions of the pairs
+    /// are cached in the local map so that the subsequent calls are cheap.
+    pub fn query(&mut self) -> QueryPairs<'_> {
+        let query = self.request.uri().query().unwrap_or("");
+        let parsed = self
+            .locals
+            .entry(&ParsedQuery::KEY)
+            .or_insert_with(|| ParsedQuery::parse(query));
+        QueryPairs::new(query, parsed)
+    }
+
+    /// Returns the mutable reference to the layer of the cookie jar with the specified name.
+    ///
+    /// This is a shortcut to `self.cookies.layer(name)`.
+    pub fn cookies_layer(
+        &mut self,
+        name: impl Into<Cow<'static, str>>,
+    ) -> crate::error::Result<&mut CookieJar> {
+        self.cookies.layer(name)
+    }
+
+    /// Returns the cookies that will be sent as `Set-Cookie` in the response.
+    ///
+    /// This is a shortcut to `self.cookies.pending()`.
+    pub fn pending_set_cookies(&self) -> Vec<Cookie<'static>> {
+        self.cookies.pending()
+    }
+
+    /// Returns a mutable reference to the header fields that will be inserted into the response.
+    ///
+    /// See the documentation of `ResponseHeaders` for how they are merged with
+    /// the header fields set by the handler.
+    pub fn response(&mut self) -> &mut ResponseHeaders {
+        self.response_headers
+    }
+
+    /// Returns a mutable reference to the raw map of header fields that will be
+    /// appended to the response.
+    ///
+    /// The values of `Vary` in this map are merged into the one in the response
+    /// rather than appended to it.
+    #[doc(hidden)]
+    #[deprecated(
+        since = "0.5.3",
+        note = "use `Input::response` instead, which validates the values."
+    )]
+    pub fn response_headers(&mut self) -> &mut HeaderMap {
+        self.response_headers.raw()
+    }
+}
+
+#[doc(inline)]
+pub use tsukuyomi_service::ConnectionInfo;
+
+/// The cookie jars of the request, kept by the application across the calls of the handler.
+#[derive(Debug, Default)]
+pub(crate) struct CookieState {
+    jar: Option<CookieJar>,
+    layers: Vec<(Cow<'static, str>, CookieJar)>,
+    frozen: bool,
+}
+
+impl CookieState {
+    /// Calls the function with each of the cookies to be sent as `Set-Cookie`,
+    /// merged from all of the jars.
+    ///
+    /// The later layers win over the earlier ones, and the main jar wins over all layers.
+    pub(crate) fn for_each_delta(&self, mut f: impl FnMut(&Cookie<'static>)) {
+        if self.layers.is_empty() {
+            // fast path: no need to merge the changes.
+            if let Some(ref jar) = self.jar {
+                jar.delta().for_each(f);
+            }
+            return;
+        }
+
+        let mut merged: Vec<(&str, &Cookie<'static>)> = vec![];
+        let jars = self
+            .layers
+            .iter()
+            .map(|(name, jar)| (&**name, jar))
+            .chain(self.jar.as_ref().map(|jar| (MAIN_LAYER, jar)));
+        for (layer, jar) in jars {
+            for cookie in jar.delta() {
+                match merged.iter_mut().find(|(_, c)| c.name() == cookie.name()) {
+                    Some(entry) => {
+                        log::warn!(
+                            "the cookie {:?} set by the layer {:?} is overridden by the layer {:?}",
+                            cookie.name(),
+                            entry.0,
+                            layer
+                        );
+                        *entry = (layer, cookie);
+                    }
+                    None => merged.push((layer, cookie)),
+                }
+            }
+        }
+        merged.into_iter().for_each(|(_, cookie)| f(cookie));
+    }
+}
+
+/// The name of the main jar in the log messages.
+const MAIN_LAYER: &str = "(main)";
+
+/// Creates a `CookieJar` with the cookies in the request as the original ones.
+fn parse_cookies(request: &Request<()>) -> crate::error::Result<CookieJar> {
+    let mut jar = CookieJar::new();
+    for raw in request.headers().get_all(http::header::COOKIE) {
+        let raw_s = raw.to_str().map_err(crate::error::bad_request)?;
+        for s in raw_s.split(';').map(|s| s.trim()) {
+            let cookie = Cookie::parse_encoded(s)
+                .map_err(crate::error::bad_request)?
+                .into_owned();
+            jar.add_original(cookie);
+        }
+    }
+    Ok(jar)
+}
+
 /// A proxy object for accessing Cookie values.
+///
+/// In addition to the main jar returned by `jar`, the components can create
+/// the named layers of the jar by `layer`, so that the cookies set by the
+/// modifiers and the handler do not overwrite each other in one jar. The
+/// changes in all of the jars are merged into `Set-Cookie` when the response
+/// is sent: the changes in the main jar win over the ones in the layers, and
+/// the ones in the layers created later win over the earlier ones.
 #[derive(Debug)]
 pub struct Cookies<'task> {
-    jar: &'task mut Option<CookieJar>,
+    state: &'task mut CookieState,
     request: &'task Request<()>,
     _marker: PhantomData<Rc<()>>,
 }
 
 impl<'task> Cookies<'task> {
-    pub(crate) fn new(jar: &'task mut Option<CookieJar>, request: &'task Request<()>) -> Self {
+    pub(crate) fn new(state: &'task mut CookieState, request: &'task Request<()>) -> Self {
         Self {
-            jar,
+            state,
             request,
             _marker: PhantomData,
         }
     }
 
+    /// Exchanges the inner cookie jars with the specified ones.
+    pub(crate) fn swap_state(&mut self, state: &mut CookieState) {
+        std::mem::swap(self.state, state);
+    }
+
+    fn check_frozen(&self) -> crate::error::Result<()> {
+        if self.state.frozen {
+            return Err(crate::error::internal_server_error(
+                "the cookies have already been frozen",
+            ));
+        }
+        Ok(())
+    }
+
     /// Returns the mutable reference to the inner `CookieJar` if available.
+    ///
+    /// This method returns an error after the cookies are frozen by `freeze`.
     pub fn jar(&mut self) -> crate::error::Result<&mut CookieJar> {
-        if let Some(ref mut jar) = self.jar {
-            return Ok(jar);
-        }
+        self.check_frozen()?;
+        self.main_jar()
+    }
 
-        let jar = self.jar.get_or_insert_with(CookieJar::new);
+    fn main_jar(&mut self) -> crate::error::Result<&mut CookieJar> {
+        if self.state.jar.is_none() {
+            self.state.jar = Some(parse_cookies(self.request)?);
+        }
+        Ok(self
+            .state
+            .jar
+            .as_mut()
+            .expect("the jar should be initialized"))
+    }
 
-        for raw in self.request.headers().get_all(http::header::COOKIE) {
-            let raw_s = raw.to_str().map_err(crate::error::bad_request)?;
-            for s in raw_s.split(';').map(|s| s.trim()) {
-                let cookie = Cookie::parse_encoded(s)
-                    .map_err(crate::error::bad_request)?
-                    .into_owned();
-                jar.add_original(cookie);
+    /// Returns the mutable reference to the layer of the jar with the specified name,
+    /// creating it if it does not exist.
+    ///
+    /// A layer contains the cookies in the request, and holds its own changes
+    /// separately from the main jar and the other layers.
+    /// This method returns an error after the cookies are frozen by `freeze`.
+    pub fn layer(
+        &mut self,
+        name: impl Into<Cow<'static, str>>,
+    ) -> crate::error::Result<&mut CookieJar> {
+        self.check_frozen()?;
+        let name = name.into();
+        let pos = match self.state.layers.iter().position(|(n, _)| *n == name) {
+            Some(pos) => pos,
+            None => {
+                let jar = parse_cookies(self.request)?;
+                self.state.layers.push((name, jar));
+                self.state.layers.len() - 1
             }
-        }
+        };
+        Ok(&mut self.state.layers[pos].1)
+    }
+
+    /// Returns the cookie with the specified name in the main jar.
+    ///
+    /// Unlike `jar`, this method is available after the cookies are frozen.
+    pub fn get(&mut self, name: &str) -> crate::error::Result<Option<&Cookie<'static>>> {
+        Ok(self.main_jar()?.get(name))
+    }
+
+    /// Prevents the subsequent modifications of the cookies.
+    ///
+    /// After calling this method, `jar` and `layer` return an error, so that the
+    /// security-sensitive cookies already set cannot be overwritten by the
+    /// components called later.
+    pub fn freeze(&mut self) {
+        self.state.frozen = true;
+    }
+
+    /// Returns whether the cookies are frozen by `freeze`.
+    pub fn is_frozen(&self) -> bool {
+        self.state.frozen
+    }
 
-        Ok(jar)
+    /// Returns the cookies that will be sent as `Set-Cookie` in the response,
+    /// with the changes in all of the jars merged.
+    pub fn pending(&self) -> Vec<Cookie<'static>> {
+        let mut cookies = vec![];
+        self.state
+            .for_each_delta(|cookie| cookies.push(cookie.clone()));
+        cookies
     }
 }
 
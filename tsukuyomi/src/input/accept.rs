@@ -0,0 +1,176 @@
+//! Negotiation of the media type based on `Accept`.
+
+use {
+    super::encoding::parse_qvalue,
+    http::header::{HeaderMap, ACCEPT},
+    mime::Mime,
+};
+
+/// A media range in `Accept`, such as `text/html`, `text/*` or `*/*`.
+#[derive(Debug, Clone)]
+struct MediaRange {
+    // `None` represents the wildcard `*`.
+    type_: Option<String>,
+    subtype: Option<String>,
+}
+
+impl MediaRange {
+    /// Returns the precedence of this range if it matches the media type.
+    ///
+    /// The more specific range has the higher precedence.
+    fn matches(&self, mime: &Mime) -> Option<u8> {
+        match (&self.type_, &self.subtype) {
+            (None, _) => Some(0),
+            (Some(type_), None) if type_ == mime.type_().as_str() => Some(1),
+            (Some(type_), Some(subtype))
+                if type_ == mime.type_().as_str() && subtype_matches(subtype, mime) =>
+            {
+                Some(2)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Returns whether the subtype in the media range equals to the one of the media type,
+/// including the structured syntax suffix such as `+json`.
+fn subtype_matches(subtype: &str, mime: &Mime) -> bool {
+    match mime.suffix() {
+        Some(suffix) => {
+            let mut parts = subtype.rsplitn(2, '+');
+            parts.next() == Some(suffix.as_str()) && parts.next() == Some(mime.subtype().as_str())
+        }
+        None => subtype == mime.subtype().as_str(),
+    }
+}
+
+/// The parsed value of `Accept`.
+#[derive(Debug, Clone, Default)]
+pub struct Accept {
+    items: Vec<(MediaRange, u16)>,
+}
+
+impl Accept {
+    /// Parses the value of `Accept`.
+    ///
+    /// The malformed elements are ignored.
+    pub fn parse(s: &str) -> Self {
+        let items = s
+            .split(',')
+            .filter_map(|item| {
+                let mut params = item.split(';').map(str::trim);
+                let range = params.next()?.to_ascii_lowercase();
+                let mut parts = range.split('/');
+                let range = match (parts.next()?, parts.next()?, parts.next()) {
+                    ("*", "*", None) => MediaRange {
+                        type_: None,
+                        subtype: None,
+                    },
+                    ("*", _, _) | ("", _, _) | (_, "", _) | (_, _, Some(..)) => return None,
+                    (type_, "*", None) => MediaRange {
+                        type_: Some(type_.to_owned()),
+                        subtype: None,
+                    },
+                    (type_, subtype, None) => MediaRange {
+                        type_: Some(type_.to_owned()),
+                        subtype: Some(subtype.to_owned()),
+                    },
+                };
+                let mut qvalue = 1000;
+                for param in params {
+                    let mut kv = param.splitn(2, '=').map(str::trim);
+                    if kv.next()?.eq_ignore_ascii_case("q") {
+                        qvalue = parse_qvalue(kv.next()?)?;
+                    }
+                }
+                Some((range, qvalue))
+            })
+            .collect();
+        Accept { items }
+    }
+
+    /// Extracts the value of `Accept` from the header map.
+    ///
+    /// If the header field is missing, it returns a `None`.
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let mut values = headers.get_all(ACCEPT).iter().peekable();
+        values.peek()?;
+        let mut accept = Accept::default();
+        for value in values {
+            if let Ok(value) = value.to_str() {
+                accept.items.extend(Self::parse(value).items);
+            }
+        }
+        Some(accept)
+    }
+
+    /// Returns the quality value of the specified media type, in the range of `0..=1000`.
+    ///
+    /// The value is taken from the most specific media range matching the type.
+    pub fn quality(&self, mime: &Mime) -> u16 {
+        self.items
+            .iter()
+            .filter_map(|(range, q)| range.matches(mime).map(|precedence| (precedence, *q)))
+            .max_by_key(|&(precedence, _)| precedence)
+            .map_or(0, |(_, q)| q)
+    }
+
+    /// Returns the acceptable media types in `available`, ordered by the preference.
+    ///
+    /// The types with the same quality value are ordered as in `available`.
+    /// The result is empty if no type is acceptable, which should be
+    /// responded with `406 Not Acceptable`.
+    pub fn negotiate<'a>(&self, available: &'a [Mime]) -> Vec<&'a Mime> {
+        let mut acceptable: Vec<_> = available
+            .iter()
+            .map(|mime| (mime, self.quality(mime)))
+            .filter(|&(_, q)| q > 0)
+            .collect();
+        acceptable.sort_by_key(|&(_, q)| std::cmp::Reverse(q));
+        acceptable.into_iter().map(|(mime, _)| mime).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negotiate_browser() {
+        let accept =
+            Accept::parse("text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8");
+        let available = [mime::APPLICATION_JSON, mime::TEXT_HTML];
+        assert_eq!(
+            accept.negotiate(&available),
+            vec![&mime::TEXT_HTML, &mime::APPLICATION_JSON]
+        );
+    }
+
+    #[test]
+    fn most_specific_range() {
+        let accept = Accept::parse("text/*;q=0.5, text/plain;q=0, */*;q=0.1");
+        assert_eq!(accept.quality(&mime::TEXT_HTML), 500);
+        assert_eq!(accept.quality(&mime::TEXT_PLAIN), 0);
+        assert_eq!(accept.quality(&mime::IMAGE_PNG), 100);
+    }
+
+    #[test]
+    fn structured_syntax_suffix() {
+        let vnd: Mime = "application/vnd.myapp.v2+json".parse().unwrap();
+        let accept = Accept::parse("application/vnd.myapp.v2+json;q=0.5, application/json");
+        assert_eq!(accept.quality(&vnd), 500);
+        assert_eq!(accept.quality(&mime::APPLICATION_JSON), 1000);
+        assert_eq!(
+            Accept::parse("application/vnd.myapp.v1+json").quality(&vnd),
+            0
+        );
+    }
+
+    #[test]
+    fn negotiate_not_acceptable() {
+        let accept = Accept::parse("image/png, image/*;q=0.5, invalid");
+        assert!(accept
+            .negotiate(&[mime::APPLICATION_JSON, mime::TEXT_HTML])
+            .is_empty());
+    }
+}
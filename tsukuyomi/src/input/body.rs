@@ -2,11 +2,21 @@
 
 use {
     super::localmap::{local_key, LocalData},
+    crate::app::BodyBuffering,
     bytes::{Buf, BufMut, Bytes, BytesMut},
     futures01::{Async, Future, Poll, Stream},
     http::header::HeaderMap,
     hyper::body::{Body, Payload},
-    std::{fmt, io, mem},
+    std::{
+        fmt,
+        fs::{File, OpenOptions},
+        io::{self, Read, Seek, SeekFrom, Write},
+        mem,
+        path::Path,
+        process,
+        sync::atomic::{AtomicUsize, Ordering},
+    },
+    tokio_threadpool::blocking as poll_blocking,
 };
 
 #[derive(Debug)]
@@ -18,6 +28,12 @@ impl RequestBody {
         OnUpgrade(self.0.on_upgrade())
     }
 
+    /// Converts this value into a `Stream` of `Bytes`.
+    #[inline]
+    pub fn into_stream(self) -> BodyStream {
+        BodyStream(self)
+    }
+
     pub(crate) fn into_inner(self) -> Body {
         self.0
     }
@@ -82,6 +98,24 @@ impl Stream for RequestBody {
     }
 }
 
+/// A `Stream` that yields the chunks of the request body as `Bytes`.
+///
+/// The chunks are received from the connection on demand, so the peer is not
+/// read ahead of the consumer of this stream.
+#[derive(Debug)]
+pub struct BodyStream(RequestBody);
+
+impl Stream for BodyStream {
+    type Item = Bytes;
+    type Error = crate::error::Error;
+
+    #[inline]
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        let chunk = futures01::try_ready!(self.0.poll_data());
+        Ok(Async::Ready(chunk.map(hyper::Chunk::into_bytes)))
+    }
+}
+
 /// An asynchronous I/O upgraded from HTTP connection.
 ///
 /// Currenly, this type is implemented as a thin wrapper of `hyper::upgrade::Upgraded`.
@@ -143,6 +177,238 @@ impl Future for OnUpgrade {
     }
 }
 
+// ==== BufferedBody ====
+
+/// The entire request body, buffered in memory or spilled into a temporary file
+/// according to `BodyBuffering`.
+#[derive(Debug)]
+pub struct BufferedBody {
+    kind: BufferedKind,
+}
+
+#[derive(Debug)]
+enum BufferedKind {
+    Memory(Bytes),
+    Spilled(SpillFile, u64),
+}
+
+impl BufferedBody {
+    /// Returns the length of the body in bytes.
+    pub fn len(&self) -> u64 {
+        match self.kind {
+            BufferedKind::Memory(ref bytes) => bytes.len() as u64,
+            BufferedKind::Spilled(_, len) => len,
+        }
+    }
+
+    /// Returns `true` if the body is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns `true` if the body has been spilled into a temporary file.
+    pub fn is_spilled(&self) -> bool {
+        match self.kind {
+            BufferedKind::Memory(..) => false,
+            BufferedKind::Spilled(..) => true,
+        }
+    }
+
+    /// Returns the content of the body if it is buffered in memory.
+    pub fn as_bytes(&self) -> Option<&Bytes> {
+        match self.kind {
+            BufferedKind::Memory(ref bytes) => Some(bytes),
+            BufferedKind::Spilled(..) => None,
+        }
+    }
+
+    /// Creates a reader of the body from the beginning.
+    ///
+    /// Reading a spilled body performs the blocking I/O on the file.
+    pub fn reader(&mut self) -> io::Result<BufferedBodyReader<'_>> {
+        match self.kind {
+            BufferedKind::Memory(ref bytes) => {
+                Ok(BufferedBodyReader::Memory(io::Cursor::new(&bytes[..])))
+            }
+            BufferedKind::Spilled(ref mut spill, len) => {
+                spill.file.seek(SeekFrom::Start(0))?;
+                Ok(BufferedBodyReader::File((&mut spill.file).take(len)))
+            }
+        }
+    }
+
+    /// Reads the entire body into memory.
+    pub fn into_bytes(mut self) -> io::Result<Bytes> {
+        if let BufferedKind::Memory(bytes) = self.kind {
+            return Ok(bytes);
+        }
+        let mut buf = Vec::with_capacity(self.len() as usize);
+        self.reader()?.read_to_end(&mut buf)?;
+        Ok(buf.into())
+    }
+}
+
+/// A reader of `BufferedBody`.
+#[allow(missing_debug_implementations)]
+pub enum BufferedBodyReader<'a> {
+    #[doc(hidden)]
+    Memory(io::Cursor<&'a [u8]>),
+    #[doc(hidden)]
+    File(io::Take<&'a mut File>),
+}
+
+impl<'a> Read for BufferedBodyReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            BufferedBodyReader::Memory(ref mut reader) => reader.read(buf),
+            BufferedBodyReader::File(ref mut reader) => reader.read(buf),
+        }
+    }
+}
+
+/// A temporary file holding a spilled body, removed when dropped.
+///
+/// The file is unlinked immediately after creation on Unix, so it disappears
+/// along with its descriptor even if the process is killed.
+#[derive(Debug)]
+struct SpillFile {
+    file: File,
+    #[cfg(not(unix))]
+    path: std::path::PathBuf,
+}
+
+impl SpillFile {
+    fn create(dir: &Path) -> io::Result<Self> {
+        static SEQ: AtomicUsize = AtomicUsize::new(0);
+        let path = dir.join(format!(
+            "tsukuyomi-body-{}-{}",
+            process::id(),
+            SEQ.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        #[cfg(unix)]
+        {
+            std::fs::remove_file(&path)?;
+            Ok(Self { file })
+        }
+        #[cfg(not(unix))]
+        Ok(Self { file, path })
+    }
+}
+
+#[cfg(not(unix))]
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Runs the blocking function on the blocking section of the thread pool, or
+/// directly if the current thread is not a worker of the thread pool.
+fn run_blocking<T>(f: impl FnOnce() -> T) -> Async<T> {
+    let mut f = Some(f);
+    match poll_blocking(|| (f.take().expect("the function has been called"))()) {
+        Ok(Async::Ready(ready)) => Async::Ready(ready),
+        Ok(Async::NotReady) => Async::NotReady,
+        Err(..) => Async::Ready((f.take().expect("the function has been called"))()),
+    }
+}
+
+/// A future that reads the entire request body into `BufferedBody`.
+#[derive(Debug)]
+pub(crate) struct ReadBuffered {
+    body: RequestBody,
+    buffering: BodyBuffering,
+    buf: BytesMut,
+    spill: Option<(SpillFile, u64)>,
+    pending: Option<Bytes>,
+}
+
+impl ReadBuffered {
+    pub(crate) fn new(body: RequestBody, buffering: BodyBuffering) -> Self {
+        Self {
+            body,
+            buffering,
+            buf: BytesMut::new(),
+            spill: None,
+            pending: None,
+        }
+    }
+
+    /// Writes the chunk into the temporary file, creating it with the data
+    /// buffered so far if the threshold is exceeded.
+    fn poll_spill(&mut self) -> Poll<(), crate::Error> {
+        let chunk = match self.pending.take() {
+            Some(chunk) => chunk,
+            None => return Ok(Async::Ready(())),
+        };
+        let (threshold, dir) = match self.buffering {
+            BodyBuffering::SpillToDisk { threshold, ref dir } => (threshold, dir),
+            BodyBuffering::Memory => unreachable!(),
+        };
+
+        if self.spill.is_none() && self.buf.len() + chunk.len() <= threshold {
+            self.buf.extend_from_slice(&chunk);
+            return Ok(Async::Ready(()));
+        }
+
+        let buf = &mut self.buf;
+        let spill = &mut self.spill;
+        let written = run_blocking(|| -> io::Result<()> {
+            if spill.is_none() {
+                let mut file = SpillFile::create(dir)?;
+                file.file.write_all(buf)?;
+                *spill = Some((file, buf.len() as u64));
+                buf.clear();
+                BodyBuffering::record_spill();
+            }
+            let (ref mut file, ref mut len) = spill.as_mut().expect("should be spilled");
+            file.file.write_all(&chunk)?;
+            *len += chunk.len() as u64;
+            Ok(())
+        });
+        match written {
+            Async::Ready(result) => {
+                result.map_err(crate::error::internal_server_error)?;
+                Ok(Async::Ready(()))
+            }
+            Async::NotReady => {
+                self.pending = Some(chunk);
+                Ok(Async::NotReady)
+            }
+        }
+    }
+}
+
+impl Future for ReadBuffered {
+    type Item = BufferedBody;
+    type Error = crate::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            futures01::try_ready!(self.poll_spill());
+            let chunk = match futures01::try_ready!(self.body.poll_data()) {
+                Some(chunk) => chunk.into_bytes(),
+                None => break,
+            };
+            match self.buffering {
+                BodyBuffering::Memory => self.buf.extend_from_slice(&chunk),
+                BodyBuffering::SpillToDisk { .. } => self.pending = Some(chunk),
+            }
+        }
+
+        let kind = match self.spill.take() {
+            Some((file, len)) => BufferedKind::Spilled(file, len),
+            None => BufferedKind::Memory(mem::replace(&mut self.buf, BytesMut::new()).freeze()),
+        };
+        Ok(Async::Ready(BufferedBody { kind }))
+    }
+}
+
 // ==== ReadAll ====
 
 #[doc(hidden)]
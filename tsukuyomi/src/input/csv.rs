@@ -0,0 +1,399 @@
+//! Incremental parsing of the CSV bodies.
+//!
+//! The rows are parsed as the chunks of the request body arrive, so that the
+//! memory usage is bounded by the size of a row rather than the entire body.
+//! The parser is used by `extractor::body::csv`.
+
+use {
+    super::body::{BodyStream, RequestBody},
+    crate::error::Error,
+    bytes::{Bytes, BytesMut},
+    csv_core::{ReadRecordResult, Reader, ReaderBuilder},
+    futures01::{Async, Poll, Stream},
+    serde::de::DeserializeOwned,
+    std::{fmt, marker::PhantomData},
+};
+
+/// The byte order mark of UTF-8, prepended by some spreadsheet applications.
+const BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// The behavior when a row fails to be parsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RowErrorMode {
+    /// The error is yielded, and the parsing continues with the next row.
+    Skip,
+
+    /// The error is yielded, and the stream ends.
+    Abort,
+}
+
+/// The options for parsing the CSV bodies.
+#[derive(Debug, Clone, Copy)]
+pub struct CsvOptions {
+    delimiter: u8,
+    has_headers: bool,
+    max_row_size: usize,
+    on_row_error: RowErrorMode,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            has_headers: true,
+            max_row_size: 64 * 1024,
+            on_row_error: RowErrorMode::Abort,
+        }
+    }
+}
+
+impl CsvOptions {
+    /// Sets the field delimiter, such as `b';'` or `b'\t'`.
+    ///
+    /// The default value is `b','`.
+    pub fn delimiter(self, delimiter: u8) -> Self {
+        Self { delimiter, ..self }
+    }
+
+    /// Sets whether the first row is the header row.
+    ///
+    /// The columns are mapped to the fields by the names in the header row if enabled,
+    /// and by their positions otherwise. The default value is `true`.
+    pub fn has_headers(self, has_headers: bool) -> Self {
+        Self {
+            has_headers,
+            ..self
+        }
+    }
+
+    /// Sets the maximum size of each row in bytes.
+    ///
+    /// The larger rows are reported as the row errors without being buffered.
+    /// The default value is 64 KiB.
+    pub fn max_row_size(self, max_row_size: usize) -> Self {
+        Self {
+            max_row_size,
+            ..self
+        }
+    }
+
+    /// Sets the behavior when a row fails to be parsed.
+    ///
+    /// The default value is `RowErrorMode::Abort`.
+    pub fn on_row_error(self, mode: RowErrorMode) -> Self {
+        Self {
+            on_row_error: mode,
+            ..self
+        }
+    }
+}
+
+/// An error in a row of the CSV body.
+#[derive(Debug, failure::Fail)]
+#[fail(display = "line {}: {}", line, message)]
+pub struct CsvRowError {
+    line: u64,
+    message: String,
+}
+
+impl CsvRowError {
+    /// Returns the line number at which the row starts, counted from `1`.
+    pub fn line(&self) -> u64 {
+        self.line
+    }
+
+    /// Returns the description of the error.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+/// A `Stream` of the rows in the CSV body, created by `extractor::body::csv`.
+///
+/// Each row is deserialized into `T`, and the rows failing to be parsed are yielded
+/// as `CsvRowError` along with the line numbers. The stream ends after the first
+/// row error if `RowErrorMode::Abort` is specified. The errors while receiving the
+/// body and the malformed header row are returned as the error of the stream.
+pub struct CsvRows<T> {
+    body: BodyStream,
+    buf: Bytes,
+    eof: bool,
+    started: bool,
+    done: bool,
+    reader: Reader,
+    options: CsvOptions,
+    headers: Option<csv_crate::StringRecord>,
+    row: PartialRow,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> fmt::Debug for CsvRows<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CsvRows")
+            .field("options", &self.options)
+            .field("headers", &self.headers)
+            .field("line", &self.reader.line())
+            .finish()
+    }
+}
+
+/// The state of the row being parsed.
+#[derive(Debug, Default)]
+struct PartialRow {
+    fields: Vec<u8>,
+    ends: Vec<usize>,
+    nfields: usize,
+    nends: usize,
+    size: usize,
+    oversized: bool,
+    // The number of the newlines in the quoted fields, used to locate the start of the row.
+    newlines: u64,
+    last_byte: u8,
+}
+
+impl<T> CsvRows<T> {
+    pub(crate) fn new(body: RequestBody, options: CsvOptions) -> Self {
+        Self {
+            body: body.into_stream(),
+            buf: Bytes::new(),
+            eof: false,
+            started: false,
+            done: false,
+            reader: ReaderBuilder::new().delimiter(options.delimiter).build(),
+            options,
+            headers: None,
+            row: PartialRow {
+                fields: vec![0; 1024],
+                ends: vec![0; 64],
+                ..PartialRow::default()
+            },
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns the header row, if it has been received.
+    pub fn headers(&self) -> Option<&csv_crate::StringRecord> {
+        self.headers.as_ref()
+    }
+
+    /// Receives the next chunk of the body.
+    fn poll_chunk(&mut self) -> Poll<(), Error> {
+        match futures01::try_ready!(self.body.poll()) {
+            Some(chunk) if self.buf.is_empty() => self.buf = chunk,
+            Some(chunk) => {
+                let mut buf = BytesMut::from(self.buf.clone());
+                buf.extend_from_slice(&chunk);
+                self.buf = buf.freeze();
+            }
+            None => self.eof = true,
+        }
+        Ok(Async::Ready(()))
+    }
+
+    /// Parses the next row, along with the line number at which it starts.
+    fn poll_record(&mut self) -> Poll<Option<(u64, Result<csv_crate::ByteRecord, String>)>, Error> {
+        loop {
+            if self.buf.is_empty() && !self.eof {
+                futures01::try_ready!(self.poll_chunk());
+                continue;
+            }
+
+            if !self.started {
+                if self.buf.len() < BOM.len() && !self.eof && BOM.starts_with(&self.buf) {
+                    futures01::try_ready!(self.poll_chunk());
+                    continue;
+                }
+                if self.buf.starts_with(BOM) {
+                    self.buf.advance(BOM.len());
+                }
+                self.started = true;
+            }
+
+            let row = &mut self.row;
+            let (result, nin, nout, nend) = self.reader.read_record(
+                &self.buf,
+                &mut row.fields[row.nfields..],
+                &mut row.ends[row.nends..],
+            );
+            if nin > 0 {
+                row.last_byte = self.buf[nin - 1];
+            }
+            self.buf.advance(nin);
+            row.newlines += row.fields[row.nfields..row.nfields + nout]
+                .iter()
+                .filter(|&&b| b == b'\n')
+                .count() as u64;
+            row.nfields += nout;
+            row.nends += nend;
+            row.size += nin;
+            if row.size > self.options.max_row_size {
+                // discard the content of the oversized row, and skip to its end.
+                row.oversized = true;
+                row.nfields = 0;
+                row.nends = 0;
+            }
+
+            match result {
+                ReadRecordResult::InputEmpty => {}
+                ReadRecordResult::OutputFull => {
+                    let len = row.fields.len() * 2;
+                    row.fields.resize(len, 0);
+                }
+                ReadRecordResult::OutputEndsFull => {
+                    let len = row.ends.len() * 2;
+                    row.ends.resize(len, 0);
+                }
+                ReadRecordResult::Record => {
+                    // The line counter has passed the newlines in the quoted fields,
+                    // and the terminator if it has been consumed.
+                    let line = self.reader.line()
+                        - row.newlines
+                        - if row.last_byte == b'\n' { 1 } else { 0 };
+                    let record = if row.oversized {
+                        Err(format!(
+                            "the row exceeds the size limit ({} bytes)",
+                            self.options.max_row_size
+                        ))
+                    } else {
+                        let mut record =
+                            csv_crate::ByteRecord::with_capacity(row.nfields, row.nends);
+                        let mut start = 0;
+                        for &end in &row.ends[..row.nends] {
+                            record.push_field(&row.fields[start..end]);
+                            start = end;
+                        }
+                        Ok(record)
+                    };
+                    row.nfields = 0;
+                    row.nends = 0;
+                    row.size = 0;
+                    row.oversized = false;
+                    row.newlines = 0;
+                    return Ok(Async::Ready(Some((line, record))));
+                }
+                ReadRecordResult::End => return Ok(Async::Ready(None)),
+            }
+        }
+    }
+}
+
+impl<T> Stream for CsvRows<T>
+where
+    T: DeserializeOwned,
+{
+    type Item = Result<T, CsvRowError>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            if self.done {
+                return Ok(Async::Ready(None));
+            }
+            let (line, record) = match futures01::try_ready!(self.poll_record()) {
+                Some(parsed) => parsed,
+                None => {
+                    self.done = true;
+                    continue;
+                }
+            };
+            let record = record.and_then(|record| {
+                csv_crate::StringRecord::from_byte_record(record)
+                    .map_err(|err| format!("the row is not valid UTF-8: {}", err.utf8_error()))
+            });
+
+            if self.options.has_headers && self.headers.is_none() {
+                match record {
+                    Ok(headers) => {
+                        self.headers = Some(headers);
+                        continue;
+                    }
+                    Err(message) => {
+                        self.done = true;
+                        return Err(crate::error::bad_request(format!(
+                            "invalid header row: {}",
+                            CsvRowError { line, message }
+                        )));
+                    }
+                }
+            }
+
+            let row = record
+                .and_then(|record| {
+                    record
+                        .deserialize(self.headers.as_ref())
+                        .map_err(|err| match err.kind() {
+                            csv_crate::ErrorKind::Deserialize { err, .. } => err.to_string(),
+                            _ => err.to_string(),
+                        })
+                })
+                .map_err(|message| CsvRowError { line, message });
+            if row.is_err() && self.options.on_row_error == RowErrorMode::Abort {
+                self.done = true;
+            }
+            return Ok(Async::Ready(Some(row)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, futures01::stream, hyper::Body};
+
+    #[derive(Debug, serde::Deserialize, PartialEq)]
+    struct Row {
+        id: u32,
+        name: String,
+    }
+
+    fn rows<I>(chunks: I, options: CsvOptions) -> CsvRows<Row>
+    where
+        I: IntoIterator<Item = Vec<u8>>,
+        I::IntoIter: Send + 'static,
+    {
+        let body = Body::wrap_stream(stream::iter_ok::<_, hyper::Error>(chunks));
+        CsvRows::new(RequestBody::from(body), options)
+    }
+
+    #[test]
+    fn line_numbers() {
+        let body = b"id,name\r\n\r\n1,\"a\nb\"\r\nx,c\n\n3,d\nfoo".to_vec();
+        // split into the single bytes in order to cross the chunk boundaries everywhere.
+        let chunks: Vec<_> = body.into_iter().map(|b| vec![b]).collect();
+        let lines: Vec<_> = rows(
+            chunks,
+            CsvOptions::default().on_row_error(RowErrorMode::Skip),
+        )
+        .wait()
+        .map(|row| match row.unwrap() {
+            Ok(row) => Ok(row.id),
+            Err(err) => Err(err.line()),
+        })
+        .collect();
+        assert_eq!(lines, vec![Ok(1), Err(5), Ok(3), Err(8)]);
+    }
+
+    #[test]
+    fn oversized_rows_are_not_buffered() {
+        let max_row_size = 1024;
+        let mut chunks = vec![b"id,name\n1,a\n2,".to_vec()];
+        chunks.extend((0..4096).map(|_| vec![b'x'; 1024]));
+        chunks.push(b"\n3,c\n".to_vec());
+
+        let mut rows = rows(
+            chunks,
+            CsvOptions::default()
+                .max_row_size(max_row_size)
+                .on_row_error(RowErrorMode::Skip),
+        );
+        let mut parsed = vec![];
+        loop {
+            match rows.poll().unwrap() {
+                Async::Ready(Some(row)) => parsed.push(row.map(|row| row.id).map_err(|e| e.line())),
+                Async::Ready(None) => break,
+                Async::NotReady => panic!("the body should be ready"),
+            }
+            assert!(rows.row.fields.len() <= 2 * max_row_size);
+        }
+        assert_eq!(parsed, vec![Ok(1), Err(3), Ok(3)]);
+    }
+}
@@ -0,0 +1,199 @@
+//! Conversions of date/time values, powered by `chrono`.
+//!
+//! The types `DateTime<Utc>`, `NaiveDate` and `Duration` can be used directly
+//! as the path parameters:
+//!
+//! * `DateTime<Utc>` accepts an RFC 3339 string (the offset is normalized to UTC)
+//!   or a date in the form `YYYY-MM-DD` (interpreted as midnight in UTC).
+//! * `NaiveDate` accepts a date in the form `YYYY-MM-DD`.
+//! * `Duration` accepts a non-negative integer representing the number of seconds.
+//!
+//! A value that cannot be parsed is treated as a mismatch of the route and
+//! results in `404 Not Found`.
+//!
+//! The submodules `utc`, `date` and `seconds` provide the same conversions
+//! for `serde`, to be used with `#[serde(with = "..")]` in query structs:
+//!
+//! ```
+//! # use serde::Deserialize;
+//! use tsukuyomi::vendor::chrono::{DateTime, Duration, Utc};
+//!
+//! #[derive(Debug, Deserialize)]
+//! struct Query {
+//!     #[serde(with = "tsukuyomi::input::datetime::utc")]
+//!     until: DateTime<Utc>,
+//!     #[serde(with = "tsukuyomi::input::datetime::seconds")]
+//!     step: Duration,
+//! }
+//! # fn main() {}
+//! ```
+
+use {
+    super::param::{FromPercentEncoded, PercentEncoded},
+    chrono::{DateTime, Duration, NaiveDate, TimeZone, Utc},
+    std::fmt,
+};
+
+/// The error type which will be returned when the date/time value is invalid.
+#[derive(Debug)]
+pub struct ParseError {
+    input: String,
+    expected: &'static str,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid value `{}': expected {}",
+            self.input, self.expected
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+const EXPECTED_DATETIME: &str = "an RFC 3339 datetime or a date of the form YYYY-MM-DD";
+const EXPECTED_DATE: &str = "a date of the form YYYY-MM-DD";
+const EXPECTED_SECONDS: &str = "a non-negative number of seconds";
+
+/// Parses a string as an RFC 3339 datetime or a `YYYY-MM-DD` date.
+pub fn parse_datetime(s: &str) -> Result<DateTime<Utc>, ParseError> {
+    DateTime::parse_from_rfc3339(s)
+        .map(|dt| dt.with_timezone(&Utc))
+        .or_else(|_| {
+            parse_date(s)
+                .ok()
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+                .map(|midnight| Utc.from_utc_datetime(&midnight))
+                .ok_or(())
+        })
+        .map_err(|_| ParseError {
+            input: s.into(),
+            expected: EXPECTED_DATETIME,
+        })
+}
+
+/// Parses a string as a `YYYY-MM-DD` date.
+pub fn parse_date(s: &str) -> Result<NaiveDate, ParseError> {
+    NaiveDate::parse_from_str(s, "%Y-%m-%d").map_err(|_| ParseError {
+        input: s.into(),
+        expected: EXPECTED_DATE,
+    })
+}
+
+/// Parses a string as a duration in seconds.
+pub fn parse_seconds(s: &str) -> Result<Duration, ParseError> {
+    s.parse::<u32>()
+        .map(|secs| Duration::seconds(i64::from(secs)))
+        .map_err(|_| ParseError {
+            input: s.into(),
+            expected: EXPECTED_SECONDS,
+        })
+}
+
+/// Formats a datetime in the form used by HTTP header fields (IMF-fixdate).
+pub fn to_http_date(dt: &DateTime<Utc>) -> String {
+    dt.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+macro_rules! impl_from_percent_encoded_for_datetime {
+    ($($t:ty => $parse:ident,)*) => {$(
+        impl FromPercentEncoded for $t {
+            type Error = crate::Error;
+
+            fn from_percent_encoded(s: &PercentEncoded) -> Result<Self, Self::Error> {
+                let s = s.decode_utf8().map_err(crate::error::not_found)?;
+                $parse(&*s).map_err(crate::error::not_found)
+            }
+        }
+    )*};
+}
+
+impl_from_percent_encoded_for_datetime! {
+    DateTime<Utc> => parse_datetime,
+    NaiveDate => parse_date,
+    Duration => parse_seconds,
+}
+
+/// (De)serialization of `DateTime<Utc>` as an RFC 3339 string.
+///
+/// The deserializer also accepts a date of the form `YYYY-MM-DD`.
+pub mod utc {
+    use {
+        chrono::{DateTime, Utc},
+        serde::{de, Deserialize, Deserializer, Serializer},
+        std::borrow::Cow,
+    };
+
+    #[allow(missing_docs)]
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = Cow::<'de, str>::deserialize(deserializer)?;
+        super::parse_datetime(&s).map_err(de::Error::custom)
+    }
+
+    #[allow(missing_docs)]
+    pub fn serialize<S>(dt: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&dt.to_rfc3339())
+    }
+}
+
+/// (De)serialization of `NaiveDate` as a string of the form `YYYY-MM-DD`.
+pub mod date {
+    use {
+        chrono::NaiveDate,
+        serde::{de, Deserialize, Deserializer, Serializer},
+        std::borrow::Cow,
+    };
+
+    #[allow(missing_docs)]
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<NaiveDate, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = Cow::<'de, str>::deserialize(deserializer)?;
+        super::parse_date(&s).map_err(de::Error::custom)
+    }
+
+    #[allow(missing_docs)]
+    pub fn serialize<S>(date: &NaiveDate, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(&date.format("%Y-%m-%d"))
+    }
+}
+
+/// (De)serialization of `Duration` as a string of the number of seconds.
+///
+/// The fractional part of the duration is truncated on serialization.
+pub mod seconds {
+    use {
+        chrono::Duration,
+        serde::{de, Deserialize, Deserializer, Serializer},
+        std::borrow::Cow,
+    };
+
+    #[allow(missing_docs)]
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = Cow::<'de, str>::deserialize(deserializer)?;
+        super::parse_seconds(&s).map_err(de::Error::custom)
+    }
+
+    #[allow(missing_docs)]
+    pub fn serialize<S>(d: &Duration, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(&d.num_seconds())
+    }
+}
@@ -0,0 +1,212 @@
+//! Negotiation of the content coding based on `Accept-Encoding`.
+
+use {
+    http::header::{HeaderMap, ACCEPT_ENCODING},
+    std::{fmt, str::FromStr},
+};
+
+/// A content coding.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Encoding {
+    /// No encoding.
+    Identity,
+    /// The GZIP format (RFC 1952).
+    Gzip,
+    /// The zlib format (RFC 1950).
+    Deflate,
+    /// The Brotli format (RFC 7932).
+    Brotli,
+}
+
+impl Encoding {
+    /// Returns the name of this coding, used in the header fields.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Encoding::Identity => "identity",
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+            Encoding::Brotli => "br",
+        }
+    }
+
+    /// Returns the file extension used for the pre-compressed files
+    /// with this coding, if available.
+    pub fn extension(self) -> Option<&'static str> {
+        match self {
+            Encoding::Identity | Encoding::Deflate => None,
+            Encoding::Gzip => Some("gz"),
+            Encoding::Brotli => Some("br"),
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Encoding {
+    type Err = failure::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match &*s.to_ascii_lowercase() {
+            "identity" => Ok(Encoding::Identity),
+            "gzip" | "x-gzip" => Ok(Encoding::Gzip),
+            "deflate" => Ok(Encoding::Deflate),
+            "br" => Ok(Encoding::Brotli),
+            _ => failure::bail!("unknown content coding: {}", s),
+        }
+    }
+}
+
+/// The parsed value of `Accept-Encoding`.
+#[derive(Debug, Clone, Default)]
+pub struct AcceptEncoding {
+    // `None` represents the wildcard `*`.
+    items: Vec<(Option<Encoding>, u16)>,
+}
+
+impl AcceptEncoding {
+    /// Parses the value of `Accept-Encoding`.
+    ///
+    /// The unknown codings and the malformed elements are ignored.
+    pub fn parse(s: &str) -> Self {
+        let items = s
+            .split(',')
+            .filter_map(|item| {
+                let mut params = item.split(';').map(str::trim);
+                let coding = match params.next()? {
+                    "" => return None,
+                    "*" => None,
+                    coding => Some(coding.parse().ok()?),
+                };
+                let mut qvalue = 1000;
+                for param in params {
+                    let mut kv = param.splitn(2, '=').map(str::trim);
+                    if kv.next()?.eq_ignore_ascii_case("q") {
+                        qvalue = parse_qvalue(kv.next()?)?;
+                    }
+                }
+                Some((coding, qvalue))
+            })
+            .collect();
+        AcceptEncoding { items }
+    }
+
+    /// Extracts the value of `Accept-Encoding` from the header map.
+    ///
+    /// If the header field is missing, it returns a `None`.
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let mut values = headers.get_all(ACCEPT_ENCODING).iter().peekable();
+        values.peek()?;
+        let mut accept = AcceptEncoding::default();
+        for value in values {
+            if let Ok(value) = value.to_str() {
+                accept.items.extend(Self::parse(value).items);
+            }
+        }
+        Some(accept)
+    }
+
+    /// Returns the quality value of the specified coding, in the range of `0..=1000`.
+    pub fn quality(&self, encoding: Encoding) -> u16 {
+        let find = |coding| {
+            self.items
+                .iter()
+                .find(|&&(c, _)| c == coding)
+                .map(|&(_, q)| q)
+        };
+        let default = match encoding {
+            Encoding::Identity => 1000,
+            _ => 0,
+        };
+        find(Some(encoding))
+            .or_else(|| find(None))
+            .unwrap_or(default)
+    }
+
+    /// Returns the acceptable codings in `available`, ordered by the preference.
+    ///
+    /// The codings with the same quality value are ordered as in `available`.
+    /// The result is empty if no coding is acceptable, which should be
+    /// responded with `406 Not Acceptable`.
+    pub fn negotiate(&self, available: &[Encoding]) -> Vec<Encoding> {
+        let mut acceptable: Vec<_> = available
+            .iter()
+            .map(|&encoding| (encoding, self.quality(encoding)))
+            .filter(|&(_, q)| q > 0)
+            .collect();
+        acceptable.sort_by_key(|&(_, q)| std::cmp::Reverse(q));
+        acceptable
+            .into_iter()
+            .map(|(encoding, _)| encoding)
+            .collect()
+    }
+}
+
+pub(crate) fn parse_qvalue(s: &str) -> Option<u16> {
+    let mut parts = s.splitn(2, '.');
+    let int = parts.next()?;
+    let frac = parts.next().unwrap_or("");
+    if frac.len() > 3 || !frac.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let frac = format!("{:0<3}", frac).parse::<u16>().ok()?;
+    match int {
+        "0" => Some(frac),
+        "1" if frac == 0 => Some(1000),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn qvalue() {
+        assert_eq!(parse_qvalue("1"), Some(1000));
+        assert_eq!(parse_qvalue("1.000"), Some(1000));
+        assert_eq!(parse_qvalue("0.5"), Some(500));
+        assert_eq!(parse_qvalue("0.25"), Some(250));
+        assert_eq!(parse_qvalue("0"), Some(0));
+        assert_eq!(parse_qvalue("1.5"), None);
+        assert_eq!(parse_qvalue("0.1234"), None);
+    }
+
+    #[test]
+    fn negotiate_by_qvalue() {
+        let accept = AcceptEncoding::parse("gzip;q=0.5, br;q=1.0");
+        assert_eq!(
+            accept.negotiate(&[Encoding::Gzip, Encoding::Brotli, Encoding::Identity]),
+            vec![Encoding::Brotli, Encoding::Identity, Encoding::Gzip]
+        );
+    }
+
+    #[test]
+    fn negotiate_wildcard() {
+        let accept = AcceptEncoding::parse("gzip;q=0, *;q=0.5");
+        assert_eq!(
+            accept.negotiate(&[Encoding::Gzip, Encoding::Brotli]),
+            vec![Encoding::Brotli]
+        );
+    }
+
+    #[test]
+    fn negotiate_not_acceptable() {
+        let accept = AcceptEncoding::parse("identity;q=0, *;q=0");
+        assert!(accept
+            .negotiate(&[Encoding::Gzip, Encoding::Identity])
+            .is_empty());
+    }
+
+    #[test]
+    fn empty_value_accepts_only_identity() {
+        let accept = AcceptEncoding::parse("");
+        assert_eq!(
+            accept.negotiate(&[Encoding::Gzip, Encoding::Identity]),
+            vec![Encoding::Identity]
+        );
+    }
+}
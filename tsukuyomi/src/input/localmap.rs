@@ -1,11 +1,27 @@
 //! An implementation of typemap for managing request-local data.
-
-use std::{
-    any::TypeId,
-    collections::{hash_map, HashMap},
-    fmt,
-    hash::{BuildHasherDefault, Hasher},
-    marker::PhantomData,
+//!
+//! # Teardown
+//!
+//! The local map of a request is moved into the response body after the handler
+//! completes, and is dropped when the body has been completely written or the
+//! connection has been aborted. The values are dropped in reverse order of the
+//! insertion, and a panic in the destructor of a value is caught and logged so
+//! that the remaining values are still dropped.
+//! The cleanup that needs to be performed asynchronously is registered by
+//! `LocalMap::defer`, and is spawned after all the values are dropped.
+
+use {
+    futures01::{Future, IntoFuture},
+    std::{
+        any::{Any, TypeId},
+        collections::{hash_map, HashMap},
+        fmt,
+        hash::{BuildHasherDefault, Hasher},
+        marker::PhantomData,
+        mem,
+        panic::{self, AssertUnwindSafe},
+    },
+    tokio_executor::Executor,
 };
 
 pub use crate::local_key;
@@ -149,10 +165,31 @@ impl BoxDowncastExt for Box<dyn Opaque> {
     }
 }
 
+struct Slot {
+    // The order of the insertion, used for dropping the values in reverse order.
+    seq: u64,
+    value: Box<dyn Opaque>,
+}
+
+impl Slot {
+    fn new<T: Send + 'static>(seq: u64, value: T) -> Self {
+        Self {
+            seq,
+            value: Box::new(value),
+        }
+    }
+}
+
+type DeferredFuture = Box<dyn Future<Item = (), Error = ()> + Send>;
+// The function is called only once, and returns `None` afterwards.
+type Deferred = Box<dyn FnMut() -> Option<DeferredFuture> + Send>;
+
 /// A typed map storing request-local data.
 #[derive(Default)]
 pub struct LocalMap {
-    inner: HashMap<TypeId, Box<dyn Opaque>, BuildHasherDefault<IdentHasher>>,
+    inner: HashMap<TypeId, Slot, BuildHasherDefault<IdentHasher>>,
+    next_seq: u64,
+    deferred: Vec<Deferred>,
 }
 
 #[cfg_attr(tarpaulin, skip)]
@@ -163,12 +200,23 @@ impl fmt::Debug for LocalMap {
 }
 
 impl LocalMap {
+    fn next_seq(&mut self) -> u64 {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        seq
+    }
+
     /// Returns a shared reference to the value corresponding to the provided `LocalKey`.
     pub fn get<T>(&self, key: &'static LocalKey<T>) -> Option<&T>
     where
         T: Send + 'static,
     {
-        Some(unsafe { self.inner.get(&key.type_id())?.downcast_ref_unchecked() })
+        Some(unsafe {
+            self.inner
+                .get(&key.type_id())?
+                .value
+                .downcast_ref_unchecked()
+        })
     }
 
     /// Returns a mutable reference to the value corresponding to the provided `LocalKey`.
@@ -176,7 +224,12 @@ impl LocalMap {
     where
         T: Send + 'static,
     {
-        Some(unsafe { self.inner.get_mut(&key.type_id())?.downcast_mut_unchecked() })
+        Some(unsafe {
+            self.inner
+                .get_mut(&key.type_id())?
+                .value
+                .downcast_mut_unchecked()
+        })
     }
 
     /// Returns `true` if the map contains a value for the specified `LocalKey`.
@@ -188,14 +241,19 @@ impl LocalMap {
     }
 
     /// Inserts a value corresponding to the provided `LocalKey` into the map.
+    ///
+    /// The replaced value is regarded as removed, and the new value is dropped
+    /// as the most recently inserted one.
     pub fn insert<T>(&mut self, key: &'static LocalKey<T>, value: T) -> Option<T>
     where
         T: Send + 'static,
     {
+        let slot = Slot::new(self.next_seq(), value);
         Some(unsafe {
             *self
                 .inner
-                .insert(key.type_id(), Box::new(value))?
+                .insert(key.type_id(), slot)?
+                .value
                 .downcast_unchecked()
         })
     }
@@ -205,7 +263,13 @@ impl LocalMap {
     where
         T: Send + 'static,
     {
-        Some(unsafe { *self.inner.remove(&key.type_id())?.downcast_unchecked() })
+        Some(unsafe {
+            *self
+                .inner
+                .remove(&key.type_id())?
+                .value
+                .downcast_unchecked()
+        })
     }
 
     /// Create a `Entry` for in-place manipulation corresponds to an entry in the map.
@@ -213,6 +277,7 @@ impl LocalMap {
     where
         T: Send + 'static,
     {
+        let seq = self.next_seq();
         match self.inner.entry(key.type_id()) {
             hash_map::Entry::Occupied(entry) => Entry::Occupied(OccupiedEntry {
                 inner: entry,
@@ -221,11 +286,74 @@ impl LocalMap {
             }),
             hash_map::Entry::Vacant(entry) => Entry::Vacant(VacantEntry {
                 inner: entry,
+                seq,
                 #[cfg_attr(tarpaulin, skip)]
                 _marker: PhantomData,
             }),
         }
     }
+
+    /// Registers a cleanup to be performed asynchronously after the values in
+    /// this map are dropped.
+    ///
+    /// The function is called when the map is dropped, and the returned future is
+    /// spawned onto the default executor. The cleanup is performed on a best-effort
+    /// basis: it is discarded if the executor is not available, and the completion
+    /// is not awaited by anyone, including the graceful shutdown of the server.
+    pub fn defer<F, R>(&mut self, f: F)
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: IntoFuture<Item = (), Error = ()>,
+        R::Future: Send + 'static,
+    {
+        let mut f = Some(f);
+        self.deferred.push(Box::new(move || {
+            f.take()
+                .map(|f| Box::new(f().into_future()) as DeferredFuture)
+        }));
+    }
+}
+
+impl Drop for LocalMap {
+    fn drop(&mut self) {
+        let mut slots: Vec<Slot> = self.inner.drain().map(|(_, slot)| slot).collect();
+        slots.sort_by(|a, b| b.seq.cmp(&a.seq));
+        for Slot { value, .. } in slots {
+            if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(move || drop(value))) {
+                log::error!(
+                    "the destructor of a local data has panicked: {}",
+                    panic_message(&*payload)
+                );
+            }
+        }
+
+        for mut deferred in mem::replace(&mut self.deferred, vec![]) {
+            let future = match panic::catch_unwind(AssertUnwindSafe(|| deferred())) {
+                Ok(Some(future)) => future,
+                Ok(None) => continue,
+                Err(payload) => {
+                    log::error!(
+                        "the deferred cleanup has panicked: {}",
+                        panic_message(&*payload)
+                    );
+                    continue;
+                }
+            };
+            if let Err(err) = tokio_executor::DefaultExecutor::current().spawn(future) {
+                log::debug!("failed to spawn the deferred cleanup: {}", err);
+            }
+        }
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s
+    } else {
+        "Box<dyn Any>"
+    }
 }
 
 /// A view into a single entry in a `LocalMap`.
@@ -274,7 +402,7 @@ where
 
 /// An occupied entry.
 pub struct OccupiedEntry<'a, T: Send + 'static> {
-    inner: hash_map::OccupiedEntry<'a, TypeId, Box<dyn Opaque>>,
+    inner: hash_map::OccupiedEntry<'a, TypeId, Slot>,
     _marker: PhantomData<T>,
 }
 
@@ -294,29 +422,31 @@ where
     T: Send + 'static,
 {
     pub fn get(&self) -> &T {
-        unsafe { self.inner.get().downcast_ref_unchecked() }
+        unsafe { self.inner.get().value.downcast_ref_unchecked() }
     }
 
     pub fn get_mut(&mut self) -> &mut T {
-        unsafe { self.inner.get_mut().downcast_mut_unchecked() }
+        unsafe { self.inner.get_mut().value.downcast_mut_unchecked() }
     }
 
     pub fn into_mut(self) -> &'a mut T {
-        unsafe { self.inner.into_mut().downcast_mut_unchecked() }
+        unsafe { self.inner.into_mut().value.downcast_mut_unchecked() }
     }
 
     pub fn insert(&mut self, value: T) -> T {
-        unsafe { *self.inner.insert(Box::new(value)).downcast_unchecked() }
+        let old = mem::replace(&mut self.inner.get_mut().value, Box::new(value));
+        unsafe { *old.downcast_unchecked() }
     }
 
     pub fn remove(self) -> T {
-        unsafe { *self.inner.remove().downcast_unchecked() }
+        unsafe { *self.inner.remove().value.downcast_unchecked() }
     }
 }
 
 /// A vacant entry.
 pub struct VacantEntry<'a, T: Send + 'static> {
-    inner: hash_map::VacantEntry<'a, TypeId, Box<dyn Opaque>>,
+    inner: hash_map::VacantEntry<'a, TypeId, Slot>,
+    seq: u64,
     _marker: PhantomData<T>,
 }
 
@@ -335,7 +465,8 @@ where
     pub fn insert(self, default: T) -> &'a mut T {
         unsafe {
             self.inner
-                .insert(Box::new(default))
+                .insert(Slot::new(self.seq, default))
+                .value
                 .downcast_mut_unchecked()
         }
     }
@@ -440,4 +571,71 @@ mod tests {
         map.insert(&KEY, "foo".into());
         assert!(map.contains_key(&KEY));
     }
+
+    struct Recorder {
+        name: &'static str,
+        log: std::sync::Arc<std::sync::Mutex<Vec<&'static str>>>,
+        panics: bool,
+    }
+
+    impl Drop for Recorder {
+        fn drop(&mut self) {
+            self.log.lock().unwrap().push(self.name);
+            if self.panics {
+                panic!("explicit panic in {}", self.name);
+            }
+        }
+    }
+
+    #[test]
+    fn drop_in_reverse_order_of_insertion() {
+        local_key! {
+            static A: Recorder;
+            static B: Recorder;
+            static C: Recorder;
+        }
+
+        let log = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let recorder = |name| Recorder {
+            name,
+            log: log.clone(),
+            panics: false,
+        };
+
+        let mut map = LocalMap::default();
+        map.insert(&A, recorder("a"));
+        map.entry(&B).or_insert_with(|| recorder("b"));
+        map.insert(&C, recorder("c"));
+        // the replaced value is dropped immediately, and the new one is regarded
+        // as the most recently inserted.
+        map.insert(&A, recorder("a2"));
+        assert_eq!(*log.lock().unwrap(), vec!["a"]);
+
+        drop(map);
+        assert_eq!(*log.lock().unwrap(), vec!["a", "a2", "c", "b"]);
+    }
+
+    #[test]
+    fn panicking_destructor_is_isolated() {
+        local_key! {
+            static A: Recorder;
+            static B: Recorder;
+            static C: Recorder;
+        }
+
+        let log = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let recorder = |name, panics| Recorder {
+            name,
+            log: log.clone(),
+            panics,
+        };
+
+        let mut map = LocalMap::default();
+        map.insert(&A, recorder("a", false));
+        map.insert(&B, recorder("b", true));
+        map.insert(&C, recorder("c", true));
+
+        drop(map);
+        assert_eq!(*log.lock().unwrap(), vec!["c", "b", "a"]);
+    }
 }
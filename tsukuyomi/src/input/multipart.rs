@@ -0,0 +1,305 @@
+//! Parsing of the multipart message bodies.
+//!
+//! The parser is shared by the extractors of the multipart bodies, such as
+//! `extractor::body::related`. It splits the buffered body at the boundary
+//! and parses the header fields of each part, without interpreting them.
+
+use {
+    bytes::Bytes,
+    http::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE},
+    mime::Mime,
+    std::slice,
+    url::percent_encoding::percent_decode,
+};
+
+/// The header field `Content-ID`.
+pub const CONTENT_ID: &str = "content-id";
+
+/// The header field `Content-Transfer-Encoding`.
+pub const CONTENT_TRANSFER_ENCODING: &str = "content-transfer-encoding";
+
+/// An error occurred during parsing a multipart body.
+#[derive(Debug, failure::Fail)]
+pub enum MultipartError {
+    #[fail(display = "missing the parameter `boundary` in `Content-type`")]
+    MissingBoundary,
+
+    #[fail(display = "the boundary is invalid: {:?}", boundary)]
+    InvalidBoundary { boundary: String },
+
+    #[fail(display = "the body does not contain the delimiter")]
+    MissingDelimiter,
+
+    #[fail(display = "the delimiter is not followed by CRLF")]
+    MalformedDelimiter,
+
+    #[fail(display = "the body is not terminated by the close delimiter")]
+    MissingCloseDelimiter,
+
+    #[fail(display = "the header fields of the part #{} are malformed", index)]
+    MalformedHeaders { index: usize },
+}
+
+/// A part in a multipart body.
+#[derive(Debug, Clone)]
+pub struct Part {
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl Part {
+    /// Creates a `Part` from the header fields and the content.
+    pub fn new(headers: HeaderMap, body: Bytes) -> Self {
+        Self { headers, body }
+    }
+
+    /// Returns the header fields of this part.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// Returns the content of this part.
+    pub fn body(&self) -> &Bytes {
+        &self.body
+    }
+
+    /// Consumes itself and returns the content of this part.
+    pub fn into_body(self) -> Bytes {
+        self.body
+    }
+
+    /// Returns the media type of this part, if it is specified and valid.
+    pub fn content_type(&self) -> Option<Mime> {
+        self.headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse().ok())
+    }
+
+    /// Returns the value of `Content-ID` without the enclosing angle brackets.
+    pub fn content_id(&self) -> Option<&str> {
+        self.headers
+            .get(CONTENT_ID)
+            .and_then(|value| value.to_str().ok())
+            .map(strip_angle_brackets)
+    }
+}
+
+/// The parts of a `multipart/related` body, extracted by `extractor::body::related`.
+///
+/// The parts are addressed by their `Content-ID`, and the contents are already
+/// decoded according to `Content-Transfer-Encoding`.
+#[derive(Debug, Clone)]
+pub struct RelatedParts {
+    parts: Vec<Part>,
+    root: usize,
+}
+
+impl RelatedParts {
+    pub(crate) fn new(parts: Vec<Part>, root: usize) -> Self {
+        debug_assert!(root < parts.len());
+        Self { parts, root }
+    }
+
+    /// Returns the root part.
+    ///
+    /// The root is the part specified by the parameter `start` of `Content-Type`,
+    /// or the first part if the parameter is omitted.
+    pub fn root(&self) -> &Part {
+        &self.parts[self.root]
+    }
+
+    /// Returns the part with the specified `Content-ID`.
+    ///
+    /// The ID can also be specified in the form of a `cid:` URL, as referenced
+    /// from the root part.
+    pub fn get(&self, id: &str) -> Option<&Part> {
+        let id = strip_angle_brackets(id);
+        let id = if id.len() > 4 && id[..4].eq_ignore_ascii_case("cid:") {
+            percent_decode(id[4..].as_bytes()).decode_utf8().ok()?
+        } else {
+            id.into()
+        };
+        self.parts
+            .iter()
+            .find(|part| part.content_id() == Some(&*id))
+    }
+
+    /// Returns an iterator over all parts, including the root one, in the order
+    /// of appearance.
+    pub fn iter(&self) -> slice::Iter<'_, Part> {
+        self.parts.iter()
+    }
+}
+
+/// Removes the angle brackets enclosing a message ID, such as `<root@example.com>`.
+fn strip_angle_brackets(id: &str) -> &str {
+    let id = id.trim();
+    if id.starts_with('<') && id.ends_with('>') && id.len() >= 2 {
+        &id[1..id.len() - 1]
+    } else {
+        id
+    }
+}
+
+/// Extracts the boundary from the media type of a multipart body.
+pub fn boundary(mime: &Mime) -> Result<&str, MultipartError> {
+    let boundary = mime
+        .get_param(mime::BOUNDARY)
+        .ok_or_else(|| MultipartError::MissingBoundary)?
+        .as_str();
+    // RFC 2046, Section 5.1.1
+    let valid = !boundary.is_empty()
+        && boundary.len() <= 70
+        && !boundary.ends_with(' ')
+        && boundary
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"'()+_,-./:=? ".contains(&b));
+    if !valid {
+        return Err(MultipartError::InvalidBoundary {
+            boundary: boundary.to_owned(),
+        });
+    }
+    Ok(boundary)
+}
+
+/// Splits the multipart body into the parts.
+///
+/// The preamble and the epilogue are discarded.
+pub fn parse(body: &Bytes, boundary: &str) -> Result<Vec<Part>, MultipartError> {
+    let delimiter = format!("--{}", boundary);
+    let next_delimiter = format!("\r\n--{}", boundary);
+
+    let mut pos = if body.starts_with(delimiter.as_bytes()) {
+        0
+    } else {
+        find(body, next_delimiter.as_bytes(), 0).ok_or(MultipartError::MissingDelimiter)? + 2
+    };
+
+    let mut parts = vec![];
+    loop {
+        pos += delimiter.len();
+        if body[pos..].starts_with(b"--") {
+            return Ok(parts);
+        }
+
+        // skip the transport padding.
+        while body.get(pos).map_or(false, |&b| b == b' ' || b == b'\t') {
+            pos += 1;
+        }
+        if !body[pos..].starts_with(b"\r\n") {
+            return Err(MultipartError::MalformedDelimiter);
+        }
+        pos += 2;
+
+        let end = find(body, next_delimiter.as_bytes(), pos)
+            .ok_or(MultipartError::MissingCloseDelimiter)?;
+        parts.push(parse_part(body.slice(pos, end), parts.len())?);
+        pos = end + 2;
+    }
+}
+
+fn parse_part(part: Bytes, index: usize) -> Result<Part, MultipartError> {
+    let malformed = || MultipartError::MalformedHeaders { index };
+
+    let (header_len, body_start) = if part.starts_with(b"\r\n") {
+        (0, 2)
+    } else {
+        let pos = find(&part, b"\r\n\r\n", 0).ok_or_else(malformed)?;
+        (pos, pos + 4)
+    };
+
+    let mut headers = HeaderMap::new();
+    let mut last: Option<(HeaderName, Vec<u8>)> = None;
+    let lines = part[..header_len]
+        .split(|&b| b == b'\n')
+        .filter(|_| header_len > 0);
+    for line in lines {
+        let line = if line.ends_with(b"\r") {
+            &line[..line.len() - 1]
+        } else {
+            line
+        };
+        if line.starts_with(b" ") || line.starts_with(b"\t") {
+            // folded line
+            let (_, value) = last.as_mut().ok_or_else(malformed)?;
+            value.push(b' ');
+            value.extend_from_slice(trim(line));
+            continue;
+        }
+        if let Some((name, value)) = last.take() {
+            let value = HeaderValue::from_bytes(&value).map_err(|_| malformed())?;
+            headers.append(name, value);
+        }
+        let colon = line.iter().position(|&b| b == b':').ok_or_else(malformed)?;
+        let name = HeaderName::from_bytes(&line[..colon]).map_err(|_| malformed())?;
+        last = Some((name, trim(&line[colon + 1..]).to_vec()));
+    }
+    if let Some((name, value)) = last.take() {
+        let value = HeaderValue::from_bytes(&value).map_err(|_| malformed())?;
+        headers.append(name, value);
+    }
+
+    Ok(Part::new(headers, part.slice_from(body_start)))
+}
+
+fn find(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    haystack
+        .get(from..)?
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .map(|pos| pos + from)
+}
+
+fn trim(s: &[u8]) -> &[u8] {
+    let start = s
+        .iter()
+        .position(|&b| b != b' ' && b != b'\t')
+        .unwrap_or_else(|| s.len());
+    let end = s
+        .iter()
+        .rposition(|&b| b != b' ' && b != b'\t')
+        .map_or(start, |pos| pos + 1);
+    &s[start..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let body = Bytes::from_static(
+            b"preamble\r\n\
+              --abc\r\n\
+              Content-Type: text/plain\r\n\
+              Content-ID:\r\n <a@example.com>\r\n\
+              \r\n\
+              hello\r\n\
+              --abc  \r\n\
+              \r\n\
+              world\r\n\
+              --abc--\r\n\
+              epilogue",
+        );
+        let parts = parse(&body, "abc").unwrap();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].content_type(), Some(mime::TEXT_PLAIN));
+        assert_eq!(parts[0].content_id(), Some("a@example.com"));
+        assert_eq!(&parts[0].body()[..], b"hello");
+        assert!(parts[1].headers().is_empty());
+        assert_eq!(&parts[1].body()[..], b"world");
+    }
+
+    #[test]
+    fn test_parse_malformed() {
+        let body = Bytes::from_static(b"--abc\r\n\r\nhello\r\n");
+        assert!(parse(&body, "abc").is_err());
+
+        let body = Bytes::from_static(b"--abcd\r\n\r\nhello\r\n--abcd--");
+        assert!(parse(&body, "abc").is_err());
+
+        let body = Bytes::from_static(b"hello");
+        assert!(parse(&body, "abc").is_err());
+    }
+}
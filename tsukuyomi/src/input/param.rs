@@ -1,9 +1,11 @@
 use {
+    super::localmap::{local_key, LocalData},
     crate::{app::Captures, uri::CaptureNames},
     std::borrow::Cow,
+    std::fmt,
     std::ops::Index,
     std::str::Utf8Error,
-    url::percent_encoding::percent_decode,
+    url::percent_encoding::{percent_decode, utf8_percent_encode, EncodeSet, DEFAULT_ENCODE_SET},
 };
 
 /// A proxy object for accessing extracted parameters.
@@ -123,3 +125,204 @@ impl FromPercentEncoded for std::path::PathBuf {
             .map_err(crate::error::bad_request)
     }
 }
+
+/// The characters percent-encoded in the names and the values of matrix parameters.
+#[derive(Debug, Clone, Copy)]
+struct MatrixEncodeSet;
+
+impl EncodeSet for MatrixEncodeSet {
+    fn contains(&self, b: u8) -> bool {
+        DEFAULT_ENCODE_SET.contains(b) || b == b';' || b == b'=' || b == b'/' || b == b'%'
+    }
+}
+
+/// The matrix parameters attached to a path segment, such as `lat=10;long=20`
+/// of `/map;lat=10;long=20/tiles`.
+///
+/// The names are percent-decoded, and the values are kept percent-encoded until
+/// they are accessed. A name may appear more than once.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MatrixParams {
+    params: Vec<(String, String)>,
+}
+
+impl MatrixParams {
+    /// Creates an empty `MatrixParams`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses the matrix parameters, separated by `;`.
+    ///
+    /// The parameters without `=` have the empty values, and the empty ones are ignored.
+    pub fn parse(s: &str) -> Self {
+        let params = s
+            .split(';')
+            .filter(|param| !param.is_empty())
+            .map(|param| {
+                let mut kv = param.splitn(2, '=');
+                let name = kv.next().unwrap_or("");
+                let value = kv.next().unwrap_or("");
+                (
+                    percent_decode(name.as_bytes())
+                        .decode_utf8_lossy()
+                        .into_owned(),
+                    value.to_owned(),
+                )
+            })
+            .collect();
+        Self { params }
+    }
+
+    /// Appends a parameter, percent-encoding the name and the value.
+    pub fn append(mut self, name: &str, value: &str) -> Self {
+        self.params.push((
+            name.to_owned(),
+            utf8_percent_encode(value, MatrixEncodeSet).to_string(),
+        ));
+        self
+    }
+
+    /// Returns the percent-decoded value of the first parameter with the name.
+    pub fn get(&self, name: &str) -> Option<Cow<'_, str>> {
+        self.get_raw(name).map(PercentEncoded::decode_utf8_lossy)
+    }
+
+    /// Returns the raw value of the first parameter with the name.
+    pub fn get_raw(&self, name: &str) -> Option<&PercentEncoded> {
+        self.params
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| unsafe { PercentEncoded::new_unchecked(value) })
+    }
+
+    /// Returns an iterator over the percent-decoded values of the parameters with the name.
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = Cow<'a, str>> + 'a {
+        self.get_all_raw(name)
+            .map(PercentEncoded::decode_utf8_lossy)
+    }
+
+    fn get_all_raw<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a PercentEncoded> + 'a {
+        self.params
+            .iter()
+            .filter(move |(n, _)| n == name)
+            .map(|(_, value)| unsafe { PercentEncoded::new_unchecked(value) })
+    }
+
+    /// Returns an iterator over the names and the percent-decoded values of the parameters.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, Cow<'_, str>)> + '_ {
+        self.params.iter().map(|(name, value)| {
+            (
+                &**name,
+                percent_decode(value.as_bytes()).decode_utf8_lossy(),
+            )
+        })
+    }
+
+    /// Returns the number of parameters.
+    pub fn len(&self) -> usize {
+        self.params.len()
+    }
+
+    /// Returns `true` if there is no parameter.
+    pub fn is_empty(&self) -> bool {
+        self.params.is_empty()
+    }
+}
+
+/// Formats the parameters as the suffix of a path segment, such as `;lat=10;long=20`,
+/// which is parsed back to the same parameters.
+impl fmt::Display for MatrixParams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, value) in &self.params {
+            write!(f, ";{}", utf8_percent_encode(name, MatrixEncodeSet))?;
+            if !value.is_empty() {
+                write!(f, "={}", value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The matrix parameters of each segment of the request path, stored in the local
+/// map if `AppBase::matrix_params` is enabled.
+#[derive(Debug, Clone, Default)]
+pub struct MatrixSegments(Vec<MatrixParams>);
+
+impl MatrixSegments {
+    /// Removes the matrix parameters from the segments of the path.
+    ///
+    /// This function returns `None` if the path contains no matrix parameter.
+    pub(crate) fn strip(path: &str) -> Option<(String, Self)> {
+        if !path.contains(';') {
+            return None;
+        }
+        let mut stripped = String::with_capacity(path.len());
+        let mut segments = vec![];
+        for (i, segment) in path.split('/').enumerate() {
+            let (name, params) = match segment.find(';') {
+                Some(pos) => (&segment[..pos], MatrixParams::parse(&segment[pos + 1..])),
+                None => (segment, MatrixParams::new()),
+            };
+            if i > 0 {
+                stripped.push('/');
+                segments.push(params);
+            }
+            stripped.push_str(name);
+        }
+        Some((stripped, MatrixSegments(segments)))
+    }
+
+    /// Returns the matrix parameters of the segment at `index`, counted from zero.
+    pub fn get(&self, index: usize) -> Option<&MatrixParams> {
+        self.0.get(index)
+    }
+
+    /// Returns an iterator over the matrix parameters of the segments.
+    pub fn iter(&self) -> impl Iterator<Item = &MatrixParams> + '_ {
+        self.0.iter()
+    }
+}
+
+impl LocalData for MatrixSegments {
+    local_key! {
+        /// The local key for the matrix parameters of the request path.
+        const KEY: Self;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strip_matrix_params() {
+        assert!(MatrixSegments::strip("/map/tiles").is_none());
+
+        let (path, segments) =
+            MatrixSegments::strip("/map;lat=10;long=20;tag=a;tag=b%20c/tiles;/x;%6Be=v%3B")
+                .unwrap();
+        assert_eq!(path, "/map/tiles/x");
+
+        let map = segments.get(0).unwrap();
+        assert_eq!(map.get("lat").as_ref().map(|s| &**s), Some("10"));
+        assert_eq!(map.get("long").as_ref().map(|s| &**s), Some("20"));
+        assert_eq!(map.get_all("tag").collect::<Vec<_>>(), vec!["a", "b c"]);
+        assert!(segments.get(1).unwrap().is_empty());
+        assert_eq!(
+            segments.get(2).unwrap().get("ke").as_ref().map(|s| &**s),
+            Some("v;")
+        );
+        assert!(segments.get(3).is_none());
+    }
+
+    #[test]
+    fn matrix_params_round_trip() {
+        let params = MatrixParams::new()
+            .append("lat", "10")
+            .append("name", "a;b=c/d")
+            .append("name", "e");
+        assert_eq!(params.to_string(), ";lat=10;name=a%3Bb%3Dc%2Fd;name=e");
+        assert_eq!(MatrixParams::parse(&params.to_string()[1..]), params);
+    }
+}
@@ -0,0 +1,197 @@
+//! Tracking the progress of receiving the request body.
+//!
+//! The request body is instrumented only when the route opts in by using
+//! [`extractor::body::progress`], so that the other routes are not charged
+//! for counting the bytes.
+//!
+//! [`extractor::body::progress`]: ../../extractor/body/fn.progress.html
+
+use {
+    super::{
+        body::RequestBody,
+        localmap::{local_key, LocalData},
+    },
+    crate::rt::{Clock, Delay},
+    futures01::{Async, Future, Poll, Stream},
+    hyper::{body::Payload, Body, Chunk},
+    std::{
+        error::Error as StdError,
+        fmt,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc, Mutex,
+        },
+        time::{Duration, Instant},
+    },
+};
+
+/// A handle for observing the progress of receiving the request body.
+///
+/// The handle is cheap to clone and can be sent to other tasks, which can
+/// read the progress while the body is being received.
+#[derive(Debug, Clone)]
+pub struct UploadProgress {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    clock: Arc<dyn Clock>,
+    // The 64-bit counter, which must not wrap around even on 32-bit platforms.
+    received: Mutex<u64>,
+    content_length: Option<u64>,
+    started: Instant,
+    last_chunk: Mutex<Option<Instant>>,
+    finished: AtomicBool,
+}
+
+impl UploadProgress {
+    fn new(content_length: Option<u64>, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                received: Mutex::new(0),
+                content_length,
+                started: clock.now(),
+                clock,
+                last_chunk: Mutex::new(None),
+                finished: AtomicBool::new(false),
+            }),
+        }
+    }
+
+    /// Returns the number of bytes received so far.
+    pub fn received(&self) -> u64 {
+        *self.inner.received.lock().unwrap()
+    }
+
+    /// Returns the value of `Content-Length`, if known.
+    pub fn content_length(&self) -> Option<u64> {
+        self.inner.content_length
+    }
+
+    /// Returns the time when the instrumentation started.
+    pub fn started_at(&self) -> Instant {
+        self.inner.started
+    }
+
+    /// Returns the time when the last chunk arrived.
+    pub fn last_chunk_at(&self) -> Option<Instant> {
+        *self.inner.last_chunk.lock().unwrap()
+    }
+
+    /// Returns whether the whole of request body has been received.
+    pub fn is_finished(&self) -> bool {
+        self.inner.finished.load(Ordering::Acquire)
+    }
+
+    fn record(&self, len: usize) {
+        *self.inner.last_chunk.lock().unwrap() = Some(self.inner.clock.now());
+        *self.inner.received.lock().unwrap() += len as u64;
+    }
+}
+
+impl LocalData for UploadProgress {
+    local_key! {
+        /// The local key to manage the progress of the request body.
+        const KEY: Self;
+    }
+}
+
+/// The error that aborts reading the request body when no chunk arrives
+/// within the configured window.
+///
+/// The `Error` converted from a `hyper::Error` caused by this error
+/// is responded with `408 Request Timeout`.
+#[derive(Debug)]
+pub struct UploadStalled {
+    window: Duration,
+}
+
+impl UploadStalled {
+    /// Returns the length of the window which the stall was detected with.
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+}
+
+impl fmt::Display for UploadStalled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "no chunk of the request body arrived within {:?}",
+            self.window
+        )
+    }
+}
+
+impl StdError for UploadStalled {}
+
+/// Replaces the request body with the instrumented one and returns the progress handle.
+pub(crate) fn instrument(
+    body: RequestBody,
+    stall_timeout: Option<Duration>,
+    clock: &Arc<dyn Clock>,
+) -> (RequestBody, UploadProgress) {
+    let progress = UploadProgress::new(body.content_length(), clock.clone());
+    let stream = Instrumented {
+        body: body.into_inner(),
+        stall: stall_timeout.map(|window| (window, clock.delay(progress.started_at() + window))),
+        progress: progress.clone(),
+    };
+    (RequestBody::from(Body::wrap_stream(stream)), progress)
+}
+
+struct Instrumented {
+    body: Body,
+    progress: UploadProgress,
+    stall: Option<(Duration, Delay)>,
+}
+
+impl Stream for Instrumented {
+    type Item = Chunk;
+    type Error = Box<dyn StdError + Send + Sync>;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        match self.body.poll_data()? {
+            Async::Ready(Some(chunk)) => {
+                self.progress.record(chunk.len());
+                if let Some((window, ref mut delay)) = self.stall {
+                    *delay = self
+                        .progress
+                        .inner
+                        .clock
+                        .delay(self.progress.inner.clock.now() + window);
+                }
+                Ok(Async::Ready(Some(chunk)))
+            }
+            Async::Ready(None) => {
+                self.progress.inner.finished.store(true, Ordering::Release);
+                log::debug!(
+                    "received the request body: {} bytes",
+                    self.progress.received()
+                );
+                Ok(Async::Ready(None))
+            }
+            Async::NotReady => {
+                if let Some((window, ref mut delay)) = self.stall {
+                    if let Async::Ready(()) = delay.poll()? {
+                        log::warn!(
+                            "the request body stalled after {} bytes",
+                            self.progress.received()
+                        );
+                        return Err(Box::new(UploadStalled { window }));
+                    }
+                }
+                Ok(Async::NotReady)
+            }
+        }
+    }
+}
+
+/// Returns whether the error is caused by the stalled request body.
+pub(crate) fn is_stalled(err: &hyper::Error) -> bool {
+    match err.source() {
+        Some(cause) => cause.is::<UploadStalled>(),
+        None => false,
+    }
+}
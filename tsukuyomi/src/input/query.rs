@@ -0,0 +1,498 @@
+//! Access to the key-value pairs in the query string without serde.
+//!
+//! The pairs are separated by `&`, and the key and value are separated by the
+//! first `=` in a pair. A pair without `=`, such as `debug` in `?debug&page=2`,
+//! is a bare key whose value is `None`. Both of the keys and the values are
+//! percent-decoded, with `+` decoded as a space.
+
+use {
+    super::localmap::{local_key, LocalData},
+    crate::error::Error,
+    serde::{
+        de::{
+            self, DeserializeOwned, DeserializeSeed, IntoDeserializer, MapAccess, SeqAccess,
+            Visitor,
+        },
+        forward_to_deserialize_any,
+    },
+    std::{borrow::Cow, ops::Range},
+};
+
+/// The positions of the pairs in the query string, parsed once per request.
+#[derive(Debug)]
+pub(crate) struct ParsedQuery {
+    spans: Vec<Span>,
+}
+
+#[derive(Debug)]
+struct Span {
+    key: Range<usize>,
+    value: Option<Range<usize>>,
+}
+
+impl ParsedQuery {
+    pub(crate) fn parse(query: &str) -> Self {
+        let mut spans = vec![];
+        let mut start = 0;
+        for pair in query.split('&') {
+            let end = start + pair.len();
+            if !pair.is_empty() {
+                spans.push(match pair.find('=') {
+                    Some(pos) => Span {
+                        key: start..start + pos,
+                        value: Some(start + pos + 1..end),
+                    },
+                    None => Span {
+                        key: start..end,
+                        value: None,
+                    },
+                });
+            }
+            start = end + 1;
+        }
+        ParsedQuery { spans }
+    }
+}
+
+impl LocalData for ParsedQuery {
+    local_key! {
+        const KEY: Self;
+    }
+}
+
+/// An iterator over the percent-decoded key-value pairs in the query string,
+/// created by `Input::query`.
+///
+/// The pairs are yielded in order of appearance, including the ones with
+/// duplicate keys. The invalid percent-encoded sequences and the bytes not
+/// forming UTF-8 are replaced with `U+FFFD REPLACEMENT CHARACTER`.
+#[derive(Debug, Clone)]
+pub struct QueryPairs<'a> {
+    query: &'a str,
+    spans: std::slice::Iter<'a, Span>,
+}
+
+impl<'a> QueryPairs<'a> {
+    pub(crate) fn new(query: &'a str, parsed: &'a ParsedQuery) -> Self {
+        Self {
+            query,
+            spans: parsed.spans.iter(),
+        }
+    }
+
+    /// Returns the value of the first pair with the specified key among the remaining ones.
+    ///
+    /// The value of a bare key is returned as an empty string.
+    pub fn first(&self, key: &str) -> Option<Cow<'a, str>> {
+        self.all(key).next()
+    }
+
+    /// Returns an iterator over the values of the remaining pairs with the specified key.
+    ///
+    /// The values of the bare keys are yielded as empty strings.
+    pub fn all<'k>(&self, key: &'k str) -> impl Iterator<Item = Cow<'a, str>> + 'k
+    where
+        'a: 'k,
+    {
+        self.clone()
+            .filter(move |(k, _)| k == key)
+            .map(|(_, value)| value.unwrap_or(Cow::Borrowed("")))
+    }
+
+    /// Returns `true` if the specified key appears as a bare key among the remaining pairs.
+    ///
+    /// A key followed by `=`, such as `debug` in `?debug=`, is not a flag.
+    pub fn has_flag(&self, key: &str) -> bool {
+        self.clone().any(|(k, value)| value.is_none() && k == key)
+    }
+
+    fn next_raw(&mut self) -> Option<(&'a str, Option<&'a str>)> {
+        let span = self.spans.next()?;
+        let value = span.value.clone().map(|value| &self.query[value]);
+        Some((&self.query[span.key.clone()], value))
+    }
+}
+
+impl<'a> Iterator for QueryPairs<'a> {
+    type Item = (Cow<'a, str>, Option<Cow<'a, str>>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, value) = self.next_raw()?;
+        Some((decode(key).0, value.map(|value| decode(value).0)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.spans.size_hint()
+    }
+}
+
+/// Decodes a key or a value in the query string.
+///
+/// The returned flag indicates whether the input contains an invalid
+/// percent-encoded sequence or the decoded bytes are not valid UTF-8.
+fn decode(s: &str) -> (Cow<'_, str>, bool) {
+    if !s.contains(|c| c == '%' || c == '+') {
+        return (Cow::Borrowed(s), false);
+    }
+
+    fn hex(b: u8) -> Option<u8> {
+        (b as char).to_digit(16).map(|d| d as u8)
+    }
+
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut malformed = false;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' => match (
+                bytes.get(i + 1).cloned().and_then(hex),
+                bytes.get(i + 2).cloned().and_then(hex),
+            ) {
+                (Some(hi), Some(lo)) => {
+                    decoded.push(hi << 4 | lo);
+                    i += 2;
+                }
+                _ => {
+                    decoded.extend_from_slice("\u{FFFD}".as_bytes());
+                    malformed = true;
+                }
+            },
+            b => decoded.push(b),
+        }
+        i += 1;
+    }
+
+    match String::from_utf8(decoded) {
+        Ok(decoded) => (Cow::Owned(decoded), malformed),
+        Err(err) => (
+            Cow::Owned(String::from_utf8_lossy(err.as_bytes()).into_owned()),
+            true,
+        ),
+    }
+}
+
+/// Deserializes the pairs into `T`, rejecting the malformed keys and values.
+///
+/// The value of a bare key is deserialized as an empty string.
+pub(crate) fn from_pairs<T>(mut pairs: QueryPairs<'_>) -> Result<T, Error>
+where
+    T: DeserializeOwned,
+{
+    let mut decoded = Vec::with_capacity(pairs.size_hint().0);
+    while let Some((key, value)) = pairs.next_raw() {
+        let (key, malformed_key) = decode(key);
+        let (value, malformed_value) = decode(value.unwrap_or(""));
+        if malformed_key || malformed_value {
+            return Err(crate::error::bad_request(
+                "invalid percent-encoded sequence in the query",
+            ));
+        }
+        decoded.push((key, value));
+    }
+
+    let deserializer = Pairs {
+        iter: decoded.iter().map(|(key, value)| (&**key, &**value)),
+        value: None,
+    };
+    T::deserialize(deserializer).map_err(crate::error::bad_request)
+}
+
+/// A deserializer of the pairs as a map or a sequence of the key-value tuples,
+/// which reports the errors in the values along with their keys.
+struct Pairs<'de, I> {
+    iter: I,
+    value: Option<(&'de str, &'de str)>,
+}
+
+impl<'de, I> de::Deserializer<'de> for Pairs<'de, I>
+where
+    I: Iterator<Item = (&'de str, &'de str)>,
+{
+    type Error = serde_plain::Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_map(self)
+    }
+
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_seq(self)
+    }
+
+    fn deserialize_tuple<V>(self, _len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_seq(self)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+impl<'de, I> SeqAccess<'de> for Pairs<'de, I>
+where
+    I: Iterator<Item = (&'de str, &'de str)>,
+{
+    type Error = serde_plain::Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        match self.iter.next() {
+            Some((key, value)) => seed.deserialize(Pair::new(key, value)).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        MapAccess::size_hint(self)
+    }
+}
+
+impl<'de, I> MapAccess<'de> for Pairs<'de, I>
+where
+    I: Iterator<Item = (&'de str, &'de str)>,
+{
+    type Error = serde_plain::Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        match self.iter.next() {
+            Some((key, value)) => {
+                self.value = Some((key, value));
+                seed.deserialize(Value(key)).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Self::Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let (key, value) = self
+            .value
+            .take()
+            .expect("next_value_seed is called before next_key_seed");
+        seed.deserialize(Value(value))
+            .map_err(|err| de::Error::custom(format_args!("query parameter `{}': {}", key, err)))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        match self.iter.size_hint() {
+            (lower, Some(upper)) if lower == upper => Some(upper),
+            _ => None,
+        }
+    }
+}
+
+/// A deserializer of a pair as a tuple of the key and value.
+struct Pair<'de> {
+    key: &'de str,
+    value: &'de str,
+    remaining: usize,
+}
+
+impl<'de> Pair<'de> {
+    fn new(key: &'de str, value: &'de str) -> Self {
+        Self {
+            key,
+            value,
+            remaining: 2,
+        }
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Pair<'de> {
+    type Error = serde_plain::Error;
+
+    fn deserialize_any<V>(mut self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        let value = visitor.visit_seq(&mut self)?;
+        if self.remaining > 0 {
+            return Err(de::Error::invalid_length(
+                2 - self.remaining,
+                &"a tuple of the key and value",
+            ));
+        }
+        Ok(value)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+impl<'de> SeqAccess<'de> for &mut Pair<'de> {
+    type Error = serde_plain::Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        match self.remaining {
+            2 => {
+                self.remaining = 1;
+                seed.deserialize(Value(self.key)).map(Some)
+            }
+            1 => {
+                self.remaining = 0;
+                let key = self.key;
+                seed.deserialize(Value(self.value))
+                    .map(Some)
+                    .map_err(|err| {
+                        de::Error::custom(format_args!("query parameter `{}': {}", key, err))
+                    })
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining)
+    }
+}
+
+/// A deserializer of a key or a value, which parses the primitive types from
+/// the string as `serde_plain` does.
+struct Value<'de>(&'de str);
+
+impl<'de> IntoDeserializer<'de, serde_plain::Error> for Value<'de> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self::Deserializer {
+        self
+    }
+}
+
+macro_rules! forward_to_serde_plain {
+    ($($method:ident ( $($arg:ident : $t:ty),* );)*) => {$(
+        fn $method<V>(self, $($arg: $t,)* visitor: V) -> Result<V::Value, Self::Error>
+        where
+            V: Visitor<'de>,
+        {
+            serde_plain::Deserializer::from_str(self.0).$method($($arg,)* visitor)
+        }
+    )*};
+}
+
+impl<'de> de::Deserializer<'de> for Value<'de> {
+    type Error = serde_plain::Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_borrowed_str(self.0)
+    }
+
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+
+    // An empty value is `Some("")`, as in `serde_urlencoded`.
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    forward_to_serde_plain! {
+        deserialize_bool();
+        deserialize_i8();
+        deserialize_i16();
+        deserialize_i32();
+        deserialize_i64();
+        deserialize_u8();
+        deserialize_u16();
+        deserialize_u32();
+        deserialize_u64();
+        deserialize_f32();
+        deserialize_f64();
+        deserialize_char();
+        deserialize_str();
+        deserialize_string();
+        deserialize_bytes();
+        deserialize_byte_buf();
+        deserialize_unit();
+        deserialize_unit_struct(name: &'static str);
+        deserialize_seq();
+        deserialize_tuple(len: usize);
+        deserialize_tuple_struct(name: &'static str, len: usize);
+        deserialize_map();
+        deserialize_struct(name: &'static str, fields: &'static [&'static str]);
+        deserialize_enum(name: &'static str, variants: &'static [&'static str]);
+        deserialize_identifier();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pairs(query: &str) -> Vec<(String, Option<String>)> {
+        let parsed = ParsedQuery::parse(query);
+        QueryPairs::new(query, &parsed)
+            .map(|(k, v)| (k.into_owned(), v.map(Cow::into_owned)))
+            .collect()
+    }
+
+    #[test]
+    fn parse_pairs() {
+        assert_eq!(
+            pairs("a=1&&b&c=&=d&a=2=3"),
+            vec![
+                ("a".into(), Some("1".into())),
+                ("b".into(), None),
+                ("c".into(), Some("".into())),
+                ("".into(), Some("d".into())),
+                ("a".into(), Some("2=3".into())),
+            ]
+        );
+        assert!(pairs("").is_empty());
+    }
+
+    #[test]
+    fn decode_lossy() {
+        assert_eq!(decode("a+b%2Bc"), (Cow::Owned("a b+c".into()), false));
+        assert_eq!(decode("plain"), (Cow::Borrowed("plain"), false));
+        assert_eq!(
+            decode("%zz%4"),
+            (Cow::Owned("\u{FFFD}zz\u{FFFD}4".into()), true)
+        );
+        assert_eq!(decode("%FF"), (Cow::Owned("\u{FFFD}".into()), true));
+    }
+}
@@ -0,0 +1,175 @@
+//! Staging of the header fields inserted into the response.
+
+use {
+    crate::{error::Error, output::header::SafeHeaderValue},
+    http::{
+        header::{HeaderMap, HeaderName, HeaderValue, CONTENT_LENGTH, TRANSFER_ENCODING, VARY},
+        HttpTryFrom,
+    },
+};
+
+/// The header fields staged by the components before the response is created.
+///
+/// The staged fields are merged into every response, including the error ones,
+/// with the following rules:
+///
+/// * The fields added by `append` are appended to the ones set by the handler.
+///   The values of `Vary` are merged into a single field as in `output::vary::add`.
+/// * The fields added by `set` and `set_if_absent` are inserted only if the
+///   handler has not set the field of the same name.
+///
+/// The values are validated when they are staged, and `Content-Length` and
+/// `Transfer-Encoding` are rejected. The values containing the control characters,
+/// which could be used for the response splitting, are rejected with
+/// `output::header::UnsafeHeaderValue` even if the `HeaderValue` has been created
+/// without the validation. The raw map returned from `raw` bypasses these checks.
+///
+/// # Example
+///
+/// ```
+/// # use tsukuyomi::{config::prelude::*, extractor, App};
+/// # use http::header::LINK;
+/// let app = App::create(
+///     path!("/").to(endpoint::get()
+///         .extract(extractor::ready(|input| {
+///             input.response().append(LINK, "</style.css>; rel=preload")
+///         }))
+///         .reply("hello"))
+/// );
+/// # drop(app);
+/// ```
+#[derive(Debug, Default)]
+pub struct ResponseHeaders {
+    set: HeaderMap,
+    appended: HeaderMap,
+}
+
+impl ResponseHeaders {
+    /// Appends a header field to the response.
+    ///
+    /// The value is appended to the other values of the same name staged by the
+    /// other components or set by the handler.
+    pub fn append<V>(&mut self, name: HeaderName, value: V) -> Result<(), Error>
+    where
+        HeaderValue: HttpTryFrom<V>,
+    {
+        let value = validate(&name, value)?;
+        if name == VARY {
+            let value = value.to_str().map_err(|_| {
+                crate::error::internal_server_error("the value of Vary must be visible ASCII")
+            })?;
+            crate::output::vary::add(&mut self.appended, value);
+        } else {
+            self.appended.append(name, value);
+        }
+        Ok(())
+    }
+
+    /// Sets a header field to the response, replacing the values of the same name
+    /// staged by the other components.
+    ///
+    /// The value is discarded if the handler sets the field of the same name.
+    pub fn set<V>(&mut self, name: HeaderName, value: V) -> Result<(), Error>
+    where
+        HeaderValue: HttpTryFrom<V>,
+    {
+        let value = validate(&name, value)?;
+        self.appended.remove(&name);
+        self.set.insert(name, value);
+        Ok(())
+    }
+
+    /// Sets a header field to the response if no value of the same name has been staged.
+    ///
+    /// This method returns `true` if the value is staged. As with `set`, the value
+    /// is discarded if the handler sets the field of the same name.
+    pub fn set_if_absent<V>(&mut self, name: HeaderName, value: V) -> Result<bool, Error>
+    where
+        HeaderValue: HttpTryFrom<V>,
+    {
+        let value = validate(&name, value)?;
+        if self.contains(&name) {
+            return Ok(false);
+        }
+        self.set.insert(name, value);
+        Ok(true)
+    }
+
+    /// Removes the staged values of the specified header field.
+    ///
+    /// This method returns `true` if any value has been staged.
+    pub fn remove(&mut self, name: &HeaderName) -> bool {
+        let set = self.set.remove(name).is_some();
+        let appended = self.appended.remove(name).is_some();
+        set || appended
+    }
+
+    /// Returns the first staged value of the specified header field.
+    pub fn get(&self, name: &HeaderName) -> Option<&HeaderValue> {
+        self.set.get(name).or_else(|| self.appended.get(name))
+    }
+
+    /// Returns `true` if any value of the specified header field has been staged.
+    pub fn contains(&self, name: &HeaderName) -> bool {
+        self.set.contains_key(name) || self.appended.contains_key(name)
+    }
+
+    /// Returns `true` if no header field has been staged.
+    pub fn is_empty(&self) -> bool {
+        self.set.is_empty() && self.appended.is_empty()
+    }
+
+    /// Returns the raw map of the appended header fields, without any validation.
+    ///
+    /// The values inserted into this map are appended to the response as they are.
+    pub fn raw(&mut self) -> &mut HeaderMap {
+        &mut self.appended
+    }
+
+    /// Merges the staged header fields into the ones of the response.
+    pub(crate) fn merge_into(&mut self, headers: &mut HeaderMap) {
+        for (name, mut values) in self.set.drain() {
+            // `set` stores a single value per name.
+            if let Some(value) = values.next() {
+                if !headers.contains_key(&name) {
+                    headers.insert(name, value);
+                }
+            }
+        }
+
+        for (name, values) in self.appended.drain() {
+            if name == VARY {
+                for value in values {
+                    if let Ok(value) = value.to_str() {
+                        crate::output::vary::add(headers, value);
+                    }
+                }
+            } else {
+                headers.extend(values.map(|value| (name.clone(), value)));
+            }
+        }
+    }
+}
+
+fn validate<V>(name: &HeaderName, value: V) -> Result<HeaderValue, Error>
+where
+    HeaderValue: HttpTryFrom<V>,
+{
+    // These fields are determined by the framework and the server from the message body.
+    if *name == CONTENT_LENGTH || *name == TRANSFER_ENCODING {
+        return Err(crate::error::internal_server_error(format!(
+            "the header field `{}' cannot be staged",
+            name
+        )));
+    }
+    let value = HeaderValue::try_from(value).map_err(|err| {
+        let err: http::Error = err.into();
+        crate::error::internal_server_error(format!(
+            "invalid value of the header field `{}': {}",
+            name, err
+        ))
+    })?;
+    SafeHeaderValue::from_header_value(value)
+        .map(Into::into)
+        .map_err(Into::into)
+}
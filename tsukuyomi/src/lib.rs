@@ -17,18 +17,37 @@ pub mod util;
 mod generic;
 mod uri;
 
+#[cfg(feature = "acme")]
+pub mod acme;
 pub mod app;
+pub mod batch;
+pub mod breaker;
+pub mod cache;
 pub mod config;
+pub mod dynamic;
 pub mod endpoint;
 pub mod error;
 pub mod extractor;
+pub mod fallback;
+pub mod featureflags;
 pub mod fs;
 pub mod future;
+pub mod guard;
 pub mod handler;
+pub mod i18n;
 pub mod input;
 pub mod modifiers;
 pub mod output;
+pub mod policy;
+pub mod poll;
+pub mod pool;
+pub mod resource;
 pub mod responder;
+pub mod rt;
+pub mod schema;
+pub mod test;
+pub mod trace;
+pub mod upgrade;
 
 #[doc(inline)]
 pub use crate::{
@@ -48,6 +67,10 @@ pub use crate::{
 
 /// Re-export of crates used within the framework and frequently used on the user side.
 pub mod vendor {
+    pub use erased_serde;
     pub use futures01 as futures;
     pub use http;
+
+    #[cfg(feature = "chrono")]
+    pub use chrono;
 }
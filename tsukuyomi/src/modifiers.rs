@@ -1,6 +1,112 @@
 //! A set of built-in `ModifyHandler`s.
 
-pub use self::{default_options::DefaultOptions, map_output::MapOutput};
+#[cfg(feature = "compression")]
+mod compression;
+mod content_sniff;
+mod csp_nonce;
+mod dedicated_pool;
+mod envelope;
+mod idempotency;
+mod maintenance_mode;
+mod queue_limit;
+mod response_cache;
+mod shadow;
+
+pub use self::{
+    content_sniff::{ContentSniff, Detector, Mismatch},
+    csp_nonce::{CspNonce, Nonce, WithCspNonce, WithCspNonceResponse},
+    dedicated_pool::{DedicatedPool, DedicatedPoolMetrics},
+    default_options::DefaultOptions,
+    envelope::{Envelope, WithEnvelope, WithEnvelopeResponse},
+    idempotency::{
+        Idempotency, IdempotencyStore, Idempotent, IdempotentResponse, StoreFuture, StoredResponse,
+    },
+    maintenance_mode::MaintenanceMode,
+    map_output::MapOutput,
+    queue_limit::QueueLimit,
+    response_cache::{
+        CacheEntry, Cached, CachedResponse, CoalesceTimeout, QueryMatch, ResponseCache,
+        ResponseCacheMetrics,
+    },
+    shadow::{Shadow, ShadowMetrics, WithShadow, WithShadowResponse},
+};
+
+#[cfg(feature = "compression")]
+pub use self::compression::{
+    Compressed, CompressedResponse, Compression, CompressionMetrics, EncodingMetrics,
+};
+
+#[cfg(feature = "compression-dictionary")]
+pub use self::compression::Dictionary;
+
+/// Creates a `ModifyHandler` that compresses the response bodies
+/// with the negotiated content coding.
+#[cfg(feature = "compression")]
+pub fn compression() -> Compression {
+    Compression::new()
+}
+
+/// Creates a `ModifyHandler` that rejects the request bodies whose content does
+/// not match the declared `Content-Type`.
+pub fn content_sniff() -> ContentSniff {
+    ContentSniff::new()
+}
+
+/// Creates a `ModifyHandler` that attaches `Content-Security-Policy` with a per-request
+/// nonce to the HTML responses.
+///
+/// This function returns an error if `template` does not contain `{nonce}`.
+pub fn csp_nonce(template: impl Into<String>) -> crate::config::Result<CspNonce> {
+    CspNonce::new(template)
+}
+
+/// Creates a `ModifyHandler` that processes the requests on a dedicated thread pool
+/// whose threads are named with the specified prefix.
+pub fn dedicated_pool(name: impl Into<String>) -> DedicatedPool {
+    DedicatedPool::new(name)
+}
+
+/// Creates a `ModifyHandler` that wraps the successful JSON responses into
+/// an envelope object with the metadata of the request.
+pub fn envelope() -> Envelope {
+    Envelope::new()
+}
+
+/// Creates a `ModifyHandler` that replays the stored responses to the retried
+/// requests with the same `Idempotency-Key`.
+pub fn idempotency(store: impl IdempotencyStore) -> Idempotency {
+    Idempotency::new(store)
+}
+
+/// Creates a `ModifyHandler` that responds with `503 Service Unavailable`
+/// while `enabled` is `true`.
+pub fn maintenance_mode(enabled: crate::dynamic::DynamicConfig<bool>) -> MaintenanceMode {
+    MaintenanceMode::new(enabled)
+}
+
+/// Creates a `ModifyHandler` that processes at most `concurrency` requests at the same time,
+/// and queues up to `queue_depth` requests for at most `max_wait`.
+pub fn queue_limit(
+    concurrency: usize,
+    queue_depth: usize,
+    max_wait: std::time::Duration,
+) -> QueueLimit {
+    QueueLimit::new(concurrency, queue_depth, max_wait)
+}
+
+/// Creates a `ModifyHandler` that caches the responses to `GET` requests
+/// in the specified cache.
+pub fn response_cache(cache: crate::cache::MemoryCache<String, CacheEntry>) -> ResponseCache {
+    ResponseCache::new(cache)
+}
+
+/// Creates a `ModifyHandler` that mirrors the requests to the paths prefixed with `target`
+/// in the background.
+///
+/// This function returns an error if `target` is not an absolute path.
+pub fn shadow(target: impl Into<String>) -> crate::config::Result<Shadow> {
+    Shadow::new(target)
+}
 
 /// Creates a `ModifyHandler` that overwrites the handling when receiving `OPTIONS`.
 pub fn default_options() -> DefaultOptions {
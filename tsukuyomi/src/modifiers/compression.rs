@@ -0,0 +1,719 @@
+use {
+    crate::{
+        error::Error,
+        future::{Async, Poll, TryFuture},
+        handler::{AllowedMethods, Handler, ModifyHandler},
+        input::{
+            encoding::{AcceptEncoding, Encoding},
+            Input,
+        },
+        output::{vary, IntoResponse, ResponseBody},
+        responder::Responder,
+    },
+    bytes::Bytes,
+    futures01::Stream,
+    http::{
+        header::{HeaderMap, HeaderValue, CONTENT_ENCODING, CONTENT_LENGTH},
+        Request, Response, StatusCode,
+    },
+    hyper::body::Payload,
+    indexmap::IndexMap,
+    std::{
+        io::{self, Write},
+        mem,
+        sync::{Arc, Mutex},
+    },
+};
+
+#[cfg(feature = "compression-dictionary")]
+use {
+    crate::input::encoding::parse_qvalue,
+    http::header::{ACCEPT_ENCODING, CONTENT_TYPE},
+    mime::Mime,
+};
+
+/// A `ModifyHandler` that compresses the response bodies.
+///
+/// The content coding is negotiated with the value of `Accept-Encoding`.
+/// The responses that already have `Content-Encoding` are passed through.
+///
+/// The clones of a `Compression` share the statistics reported by `metrics`.
+#[derive(Debug, Clone)]
+pub struct Compression {
+    inner: Arc<Inner>,
+    stats: Arc<Stats>,
+}
+
+#[derive(Debug, Clone)]
+struct Inner {
+    levels: Vec<(Encoding, u32)>,
+    fallback_identity: bool,
+    #[cfg(feature = "compression-dictionary")]
+    dictionary: Option<Dictionary>,
+}
+
+#[derive(Debug, Default)]
+struct Stats {
+    encodings: Mutex<IndexMap<String, EncodingMetrics>>,
+}
+
+impl Stats {
+    fn record(&self, coding: &str, uncompressed: u64, compressed: u64) {
+        let mut encodings = self.encodings.lock().unwrap();
+        if !encodings.contains_key(coding) {
+            encodings.insert(coding.to_owned(), EncodingMetrics::default());
+        }
+        let metrics = &mut encodings[coding];
+        metrics.responses += 1;
+        metrics.uncompressed_bytes += uncompressed;
+        metrics.compressed_bytes += compressed;
+    }
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Compression {
+    /// Creates a `Compression` with the default configuration.
+    ///
+    /// The available codings are `br` (if the feature `brotli` is enabled),
+    /// `gzip` and `deflate`, preferred in that order.
+    pub fn new() -> Self {
+        let mut levels = vec![];
+        if cfg!(feature = "brotli") {
+            levels.push((Encoding::Brotli, 4));
+        }
+        levels.push((Encoding::Gzip, 6));
+        levels.push((Encoding::Deflate, 6));
+        Self {
+            inner: Arc::new(Inner {
+                levels,
+                fallback_identity: false,
+                #[cfg(feature = "compression-dictionary")]
+                dictionary: None,
+            }),
+            stats: Arc::default(),
+        }
+    }
+
+    fn inner_mut(&mut self) -> &mut Inner {
+        Arc::make_mut(&mut self.inner)
+    }
+
+    /// Sets the compression level of the specified coding and enables it.
+    ///
+    /// The level ranges from 0 to 9 for `gzip` and `deflate`, and from 0 to 11
+    /// for `br`.  `Encoding::Identity` is ignored, as well as `Encoding::Brotli`
+    /// if the feature `brotli` is disabled.
+    pub fn level(mut self, encoding: Encoding, level: u32) -> Self {
+        match encoding {
+            Encoding::Identity => return self,
+            Encoding::Brotli if !cfg!(feature = "brotli") => return self,
+            _ => {}
+        }
+        let levels = &mut self.inner_mut().levels;
+        match levels.iter_mut().find(|(e, _)| *e == encoding) {
+            Some(entry) => entry.1 = level,
+            None => levels.push((encoding, level)),
+        }
+        self
+    }
+
+    /// Disables the specified coding.
+    pub fn disable(mut self, encoding: Encoding) -> Self {
+        self.inner_mut().levels.retain(|(e, _)| *e != encoding);
+        self
+    }
+
+    /// Sets whether to return the uncompressed responses when no coding
+    /// is acceptable for the client.
+    ///
+    /// By default, such requests are rejected with `406 Not Acceptable`.
+    pub fn fallback_identity(mut self, enabled: bool) -> Self {
+        self.inner_mut().fallback_identity = enabled;
+        self
+    }
+
+    /// Enables the compression with the shared dictionary.
+    ///
+    /// The responses whose `Content-Type` matches the dictionary are encoded with
+    /// it if the client advertises its content coding in `Accept-Encoding` with a
+    /// quality value not less than the standard codings.  The other responses and
+    /// clients are served with the standard codings.
+    #[cfg(feature = "compression-dictionary")]
+    pub fn dictionary(mut self, dictionary: Dictionary) -> Self {
+        self.inner_mut().dictionary = Some(dictionary);
+        self
+    }
+
+    /// Returns the snapshot of the statistics of the compressed responses,
+    /// grouped by the content coding.
+    pub fn metrics(&self) -> CompressionMetrics {
+        CompressionMetrics {
+            encodings: self.stats.encodings.lock().unwrap().clone(),
+        }
+    }
+
+    fn negotiate(&self, request: &Request<()>) -> Result<Coding, Error> {
+        let accept = match AcceptEncoding::from_headers(request.headers()) {
+            Some(accept) => accept,
+            None => return Ok(self.coding(None)),
+        };
+
+        let mut available: Vec<_> = self.inner.levels.iter().map(|&(e, _)| e).collect();
+        available.push(Encoding::Identity);
+        let acceptable = accept.negotiate(&available);
+
+        #[cfg(feature = "compression-dictionary")]
+        {
+            let standard_quality = acceptable
+                .iter()
+                .find(|&&e| e != Encoding::Identity)
+                .map_or(0, |&e| accept.quality(e));
+            if let Some(ref dictionary) = self.inner.dictionary {
+                if dictionary
+                    .quality(request.headers())
+                    .map_or(false, |q| q > 0 && q >= standard_quality)
+                {
+                    let standard = self.find_level(acceptable.first().cloned());
+                    return Ok(Coding {
+                        dictionary: Some(dictionary.clone()),
+                        ..self.coding(standard)
+                    });
+                }
+            }
+        }
+
+        match acceptable.first() {
+            Some(&encoding) => Ok(self.coding(self.find_level(Some(encoding)))),
+            None if self.inner.fallback_identity => Ok(self.coding(None)),
+            None => Err(crate::error::custom(
+                StatusCode::NOT_ACCEPTABLE,
+                "no acceptable content coding",
+            )),
+        }
+    }
+
+    fn find_level(&self, encoding: Option<Encoding>) -> Option<(Encoding, u32)> {
+        self.inner
+            .levels
+            .iter()
+            .find(|&&(e, _)| Some(e) == encoding)
+            .cloned()
+    }
+
+    fn coding(&self, standard: Option<(Encoding, u32)>) -> Coding {
+        Coding {
+            standard,
+            #[cfg(feature = "compression-dictionary")]
+            dictionary: None,
+            stats: self.stats.clone(),
+        }
+    }
+}
+
+/// The content coding negotiated for a response.
+#[derive(Debug, Clone)]
+struct Coding {
+    standard: Option<(Encoding, u32)>,
+    #[cfg(feature = "compression-dictionary")]
+    dictionary: Option<Dictionary>,
+    stats: Arc<Stats>,
+}
+
+impl Coding {
+    /// Selects the encoder for the response, and returns it with the name of the coding.
+    #[cfg_attr(not(feature = "compression-dictionary"), allow(unused_variables))]
+    fn select(&self, headers: &HeaderMap) -> io::Result<Option<(Encoder, String)>> {
+        #[cfg(feature = "compression-dictionary")]
+        {
+            if let Some(dictionary) = self.dictionary.as_ref().filter(|d| d.matches(headers)) {
+                return Ok(Some((dictionary.encoder()?, dictionary.token.clone())));
+            }
+        }
+        Ok(self
+            .standard
+            .map(|(encoding, level)| (Encoder::new(encoding, level), encoding.as_str().to_owned())))
+    }
+}
+
+/// A shared dictionary used for compressing the responses, created by `Dictionary::new`.
+///
+/// The response bodies are encoded in the zlib format (RFC 1950) with the preset
+/// dictionary, and labeled with the content coding specified by the operator.
+/// The clients decode them by supplying the same dictionary to the zlib decoder.
+///
+/// ```
+/// # use tsukuyomi::{config::prelude::*, modifiers::{self, Dictionary}, App};
+/// let dictionary = Dictionary::new(
+///     "x-json-dict",
+///     &br#"{"id":,"name":"","created_at":"","tags":[]}"#[..],
+/// );
+///
+/// let app = App::create(dictionary.map(|dictionary| {
+///     path!("/users")
+///         .to(endpoint::get().reply(r#"{"id":1,"name":"alice","tags":[]}"#))
+///         .modify(modifiers::compression().dictionary(dictionary))
+/// }));
+/// # drop(app);
+/// ```
+#[cfg(feature = "compression-dictionary")]
+#[derive(Debug, Clone)]
+pub struct Dictionary {
+    token: String,
+    bytes: Bytes,
+    level: u32,
+    content_types: Vec<Mime>,
+}
+
+#[cfg(feature = "compression-dictionary")]
+impl Dictionary {
+    /// Creates a `Dictionary` from the raw bytes, negotiated with the content coding `token`.
+    ///
+    /// By default, the dictionary is applied to `application/json` at level 6.
+    ///
+    /// This function returns an error if `token` is not a valid content coding,
+    /// if it is one of the standard codings, or if the bytes cannot be loaded
+    /// as a dictionary.
+    pub fn new(token: impl Into<String>, bytes: impl Into<Bytes>) -> crate::config::Result<Self> {
+        let token = token.into().to_ascii_lowercase();
+        let is_tchar = |b: u8| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b);
+        if token.is_empty() || !token.bytes().all(is_tchar) {
+            return Err(crate::config::Error::custom(failure::format_err!(
+                "invalid content coding: {:?}",
+                token
+            )));
+        }
+        if token == "*" || token == "compress" || token.parse::<Encoding>().is_ok() {
+            return Err(crate::config::Error::custom(failure::format_err!(
+                "the content coding for the dictionary must not be a standard one: {:?}",
+                token
+            )));
+        }
+
+        let bytes = bytes.into();
+        if bytes.is_empty() {
+            return Err(crate::config::Error::custom(failure::format_err!(
+                "the dictionary must not be empty"
+            )));
+        }
+
+        let dictionary = Self {
+            token,
+            bytes,
+            level: 6,
+            content_types: vec![mime::APPLICATION_JSON],
+        };
+        dictionary.encoder().map_err(crate::config::Error::custom)?;
+        Ok(dictionary)
+    }
+
+    /// Sets the compression level, ranging from 0 to 9.
+    pub fn level(self, level: u32) -> Self {
+        Self { level, ..self }
+    }
+
+    /// Sets the list of the media types to which the dictionary is applied.
+    ///
+    /// The parameters such as `charset` are ignored on matching, and the media
+    /// types with the subtype `*` match all of the subtypes.
+    pub fn content_types(self, content_types: impl IntoIterator<Item = Mime>) -> Self {
+        Self {
+            content_types: content_types.into_iter().collect(),
+            ..self
+        }
+    }
+
+    /// Returns the content coding of this dictionary.
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    /// Returns the quality value of the content coding explicitly listed in `Accept-Encoding`.
+    ///
+    /// The wildcard `*` is not taken into account, since the clients not advertising
+    /// the coding cannot decode the body.
+    fn quality(&self, headers: &HeaderMap) -> Option<u16> {
+        headers
+            .get_all(ACCEPT_ENCODING)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .find_map(|item| {
+                let mut params = item.split(';').map(str::trim);
+                if !params.next()?.eq_ignore_ascii_case(&self.token) {
+                    return None;
+                }
+                let mut qvalue = 1000;
+                for param in params {
+                    let mut kv = param.splitn(2, '=').map(str::trim);
+                    if kv.next()?.eq_ignore_ascii_case("q") {
+                        qvalue = parse_qvalue(kv.next()?)?;
+                    }
+                }
+                Some(qvalue)
+            })
+    }
+
+    fn matches(&self, headers: &HeaderMap) -> bool {
+        let mime = match headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<Mime>().ok())
+        {
+            Some(mime) => mime,
+            None => return false,
+        };
+        self.content_types.iter().any(|content_type| {
+            content_type.type_() == mime.type_()
+                && (content_type.subtype() == mime::STAR
+                    || content_type.subtype() == mime.subtype())
+        })
+    }
+
+    fn encoder(&self) -> io::Result<Encoder> {
+        let mut compress = flate2::Compress::new(flate2::Compression::new(self.level), true);
+        compress
+            .set_dictionary(&self.bytes)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        Ok(Encoder::Deflate(
+            flate2::write::ZlibEncoder::new_with_compress(Vec::new(), compress),
+        ))
+    }
+}
+
+/// The snapshot of the statistics of the responses compressed by `Compression`.
+#[derive(Debug, Clone, Default)]
+pub struct CompressionMetrics {
+    encodings: IndexMap<String, EncodingMetrics>,
+}
+
+impl CompressionMetrics {
+    /// Returns the statistics of the specified content coding, such as `gzip`
+    /// or the one of the shared dictionary.
+    pub fn get(&self, coding: &str) -> Option<&EncodingMetrics> {
+        self.encodings.get(coding)
+    }
+
+    /// Returns an iterator over the content codings and their statistics,
+    /// in the order of the first use.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &EncodingMetrics)> + '_ {
+        self.encodings
+            .iter()
+            .map(|(coding, metrics)| (&**coding, metrics))
+    }
+}
+
+/// The statistics of the responses compressed with a content coding.
+///
+/// The responses are counted when the whole of their bodies are compressed.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct EncodingMetrics {
+    /// The number of the compressed responses.
+    pub responses: u64,
+
+    /// The total size of the response bodies before the compression, in bytes.
+    pub uncompressed_bytes: u64,
+
+    /// The total size of the response bodies after the compression, in bytes.
+    pub compressed_bytes: u64,
+}
+
+impl EncodingMetrics {
+    /// Returns the compression ratio, the compressed size divided by the uncompressed one.
+    ///
+    /// It returns `None` if no bytes have been compressed.
+    pub fn ratio(&self) -> Option<f64> {
+        if self.uncompressed_bytes == 0 {
+            return None;
+        }
+        Some(self.compressed_bytes as f64 / self.uncompressed_bytes as f64)
+    }
+}
+
+impl<H> ModifyHandler<H> for Compression
+where
+    H: Handler,
+    H::Output: Responder,
+{
+    type Output = Compressed<H::Output>;
+    type Handler = CompressionHandler<H>; // private
+
+    fn modify(&self, inner: H) -> Self::Handler {
+        CompressionHandler {
+            inner,
+            modifier: self.clone(),
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct CompressionHandler<H> {
+    inner: H,
+    modifier: Compression,
+}
+
+impl<H> Handler for CompressionHandler<H>
+where
+    H: Handler,
+    H::Output: Responder,
+{
+    type Output = Compressed<H::Output>;
+    type Error = Error;
+    type Handle = HandleCompression<H::Handle>;
+
+    fn allowed_methods(&self) -> Option<&AllowedMethods> {
+        self.inner.allowed_methods()
+    }
+
+    fn handle(&self) -> Self::Handle {
+        HandleCompression {
+            inner: self.inner.handle(),
+            modifier: self.modifier.clone(),
+            coding: None,
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct HandleCompression<H> {
+    inner: H,
+    modifier: Compression,
+    coding: Option<Coding>,
+}
+
+impl<H> TryFuture for HandleCompression<H>
+where
+    H: TryFuture,
+{
+    type Ok = Compressed<H::Ok>;
+    type Error = Error;
+
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        if self.coding.is_none() {
+            self.coding = Some(self.modifier.negotiate(input.request)?);
+        }
+        let output = futures01::try_ready!(self.inner.poll_ready(input).map_err(Into::into));
+        Ok(Async::Ready(Compressed {
+            inner: output,
+            coding: self
+                .coding
+                .take()
+                .expect("the encoding should be negotiated"),
+        }))
+    }
+}
+
+/// A `Responder` which compresses the response body.
+#[derive(Debug)]
+pub struct Compressed<T> {
+    inner: T,
+    coding: Coding,
+}
+
+impl<T> Responder for Compressed<T>
+where
+    T: Responder,
+{
+    type Response = CompressedResponse<T::Response>;
+    type Error = T::Error;
+    type Respond = CompressedRespond<T::Respond>; // private
+
+    fn respond(self) -> Self::Respond {
+        CompressedRespond {
+            inner: self.inner.respond(),
+            coding: self.coding,
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct CompressedRespond<R> {
+    inner: R,
+    coding: Coding,
+}
+
+impl<R> TryFuture for CompressedRespond<R>
+where
+    R: TryFuture,
+{
+    type Ok = CompressedResponse<R::Ok>;
+    type Error = R::Error;
+
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        let inner = futures01::try_ready!(self.inner.poll_ready(input));
+        Ok(Async::Ready(CompressedResponse {
+            inner,
+            coding: self.coding.clone(),
+        }))
+    }
+}
+
+/// An `IntoResponse` which compresses the response body.
+#[derive(Debug)]
+pub struct CompressedResponse<T> {
+    inner: T,
+    coding: Coding,
+}
+
+impl<T> IntoResponse for CompressedResponse<T>
+where
+    T: IntoResponse,
+{
+    type Body = ResponseBody;
+    type Error = Error;
+
+    fn into_response(self, request: &Request<()>) -> Result<Response<Self::Body>, Self::Error> {
+        let response = self
+            .inner
+            .into_response(request)
+            .map_err(Into::into)?
+            .map(Into::<ResponseBody>::into);
+        let (mut parts, body) = response.into_parts();
+
+        if parts.headers.contains_key(CONTENT_ENCODING) {
+            return Ok(Response::from_parts(parts, body));
+        }
+        vary::add(&mut parts.headers, "accept-encoding");
+
+        if parts.status == StatusCode::NO_CONTENT
+            || parts.status == StatusCode::NOT_MODIFIED
+            || body.content_length() == Some(0)
+        {
+            return Ok(Response::from_parts(parts, body));
+        }
+
+        let (encoder, name) = match self.coding.select(&parts.headers)? {
+            Some(selected) => selected,
+            None => return Ok(Response::from_parts(parts, body)),
+        };
+
+        parts.headers.remove(CONTENT_LENGTH);
+        parts.headers.insert(
+            CONTENT_ENCODING,
+            HeaderValue::from_str(&name).expect("should be a valid content coding"),
+        );
+        let stream = CompressStream {
+            body,
+            encoder: Some(encoder),
+            coding: name,
+            stats: self.coding.stats,
+            uncompressed: 0,
+            compressed: 0,
+        };
+        Ok(Response::from_parts(
+            parts,
+            ResponseBody::wrap_stream(stream),
+        ))
+    }
+}
+
+#[allow(missing_debug_implementations)]
+enum Encoder {
+    Gzip(flate2::write::GzEncoder<Vec<u8>>),
+    Deflate(flate2::write::ZlibEncoder<Vec<u8>>),
+    #[cfg(feature = "brotli")]
+    Brotli(Box<brotli::CompressorWriter<Vec<u8>>>),
+}
+
+impl Encoder {
+    fn new(encoding: Encoding, level: u32) -> Self {
+        match encoding {
+            Encoding::Gzip => Encoder::Gzip(flate2::write::GzEncoder::new(
+                Vec::new(),
+                flate2::Compression::new(level),
+            )),
+            Encoding::Deflate => Encoder::Deflate(flate2::write::ZlibEncoder::new(
+                Vec::new(),
+                flate2::Compression::new(level),
+            )),
+            #[cfg(feature = "brotli")]
+            Encoding::Brotli => Encoder::Brotli(Box::new(brotli::CompressorWriter::new(
+                Vec::new(),
+                4096,
+                level,
+                22,
+            ))),
+            encoding => unreachable!("unsupported encoding: {}", encoding),
+        }
+    }
+
+    /// Compresses the chunk and returns the available output.
+    fn write(&mut self, chunk: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Encoder::Gzip(encoder) => {
+                encoder.write_all(chunk)?;
+                Ok(mem::replace(encoder.get_mut(), Vec::new()))
+            }
+            Encoder::Deflate(encoder) => {
+                encoder.write_all(chunk)?;
+                Ok(mem::replace(encoder.get_mut(), Vec::new()))
+            }
+            #[cfg(feature = "brotli")]
+            Encoder::Brotli(encoder) => {
+                encoder.write_all(chunk)?;
+                Ok(mem::replace(encoder.get_mut(), Vec::new()))
+            }
+        }
+    }
+
+    /// Flushes the remaining output and terminates the stream.
+    fn finish(self) -> io::Result<Vec<u8>> {
+        match self {
+            Encoder::Gzip(encoder) => encoder.finish(),
+            Encoder::Deflate(encoder) => encoder.finish(),
+            #[cfg(feature = "brotli")]
+            Encoder::Brotli(encoder) => Ok(encoder.into_inner()),
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+struct CompressStream {
+    body: ResponseBody,
+    encoder: Option<Encoder>,
+    coding: String,
+    stats: Arc<Stats>,
+    uncompressed: u64,
+    compressed: u64,
+}
+
+impl Stream for CompressStream {
+    type Item = Bytes;
+    type Error = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            let encoder = match self.encoder {
+                Some(ref mut encoder) => encoder,
+                None => return Ok(Async::Ready(None)),
+            };
+            match futures01::try_ready!(self.body.poll_data()) {
+                Some(chunk) => {
+                    let compressed = encoder.write(&chunk)?;
+                    self.uncompressed += chunk.len() as u64;
+                    self.compressed += compressed.len() as u64;
+                    if !compressed.is_empty() {
+                        return Ok(Async::Ready(Some(compressed.into())));
+                    }
+                }
+                None => {
+                    let encoder = self
+                        .encoder
+                        .take()
+                        .expect("the encoder should be available");
+                    let compressed = encoder.finish()?;
+                    self.stats.record(
+                        &self.coding,
+                        self.uncompressed,
+                        self.compressed + compressed.len() as u64,
+                    );
+                    return Ok(Async::Ready(Some(compressed.into())));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,378 @@
+use {
+    crate::{
+        error::Error,
+        future::{Poll, TryFuture},
+        handler::{AllowedMethods, Handler, ModifyHandler},
+        i18n::Args,
+        input::{
+            body::{BodyStream, RequestBody},
+            header::ContentType,
+            localmap::LocalData,
+            multipart, Input,
+        },
+    },
+    bytes::Bytes,
+    futures01::{stream::Concat2, Future, Stream},
+    http::StatusCode,
+    mime::Mime,
+    std::{borrow::Cow, fmt, sync::Arc},
+};
+
+/// A trait representing a check of the content of the request bodies against
+/// the declared media type, used by `ContentSniff`.
+pub trait Detector: Send + Sync + 'static {
+    /// Returns whether this detector checks the contents declared with `mime`.
+    fn applies_to(&self, mime: &Mime) -> bool;
+
+    /// Checks whether `body` looks like the content of the declared media type.
+    fn check(&self, mime: &Mime, body: &[u8]) -> Result<(), Mismatch>;
+}
+
+/// The mismatch between the declared media type and the actual content,
+/// reported by a `Detector`.
+#[derive(Debug)]
+pub struct Mismatch {
+    detected: Cow<'static, str>,
+}
+
+impl Mismatch {
+    /// Creates a `Mismatch` with the description of the detected content.
+    pub fn new(detected: impl Into<Cow<'static, str>>) -> Self {
+        Self {
+            detected: detected.into(),
+        }
+    }
+
+    /// Creates a `Mismatch` whose content is described by the well-known
+    /// magic numbers, such as `"PNG image"` or `"binary data"`.
+    pub fn describe(body: &[u8]) -> Self {
+        Self::new(describe(body))
+    }
+
+    /// Returns the description of the detected content.
+    pub fn detected(&self) -> &str {
+        &self.detected
+    }
+}
+
+/// A `ModifyHandler` that rejects the request bodies whose content does not match
+/// the declared `Content-Type`, with `415 Unsupported Media Type`.
+///
+/// The body is buffered before the handler is invoked and handed over to it, and
+/// the parts of the multipart bodies are checked against their own media types.
+/// Only the media types which some of the registered detectors apply to are
+/// checked, and the bodies without `Content-Type` are passed through as they are.
+///
+/// The built-in detectors check the following:
+///
+/// * `application/json` (and `+json`) - valid UTF-8 starting with `{` or `[`
+/// * `text/*` without a charset other than UTF-8 - valid UTF-8
+/// * `image/png`, `image/jpeg`, `image/gif` and `image/webp` - the magic numbers
+#[derive(Clone)]
+pub struct ContentSniff {
+    detectors: Arc<Vec<Arc<dyn Detector>>>,
+}
+
+impl fmt::Debug for ContentSniff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ContentSniff")
+            .field("detectors", &self.detectors.len())
+            .finish()
+    }
+}
+
+impl Default for ContentSniff {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ContentSniff {
+    /// Creates a `ContentSniff` with the built-in detectors.
+    pub fn new() -> Self {
+        Self {
+            detectors: Arc::new(vec![Arc::new(Json), Arc::new(Text), Arc::new(Image)]),
+        }
+    }
+
+    /// Registers an additional detector.
+    ///
+    /// A body is checked by all of the detectors applied to its media type.
+    pub fn detector(self, detector: impl Detector) -> Self {
+        let mut detectors: Vec<_> = self.detectors.iter().cloned().collect();
+        detectors.push(Arc::new(detector));
+        Self {
+            detectors: Arc::new(detectors),
+        }
+    }
+
+    fn applies_to(&self, mime: &Mime) -> bool {
+        mime.type_() == mime::MULTIPART
+            || self
+                .detectors
+                .iter()
+                .any(|detector| detector.applies_to(mime))
+    }
+
+    fn check(&self, mime: &Mime, body: &Bytes) -> Result<(), Error> {
+        if mime.type_() == mime::MULTIPART {
+            // the malformed multipart bodies are left to the extractors.
+            let parts = match multipart::boundary(mime)
+                .ok()
+                .and_then(|boundary| multipart::parse(body, boundary).ok())
+            {
+                Some(parts) => parts,
+                None => return Ok(()),
+            };
+            for part in &parts {
+                if part
+                    .headers()
+                    .contains_key(multipart::CONTENT_TRANSFER_ENCODING)
+                {
+                    continue;
+                }
+                if let Some(mime) = part.content_type() {
+                    self.check_content(&mime, part.body())?;
+                }
+            }
+            return Ok(());
+        }
+        self.check_content(mime, body)
+    }
+
+    fn check_content(&self, mime: &Mime, body: &[u8]) -> Result<(), Error> {
+        if body.is_empty() {
+            return Ok(());
+        }
+        for detector in self.detectors.iter().filter(|d| d.applies_to(mime)) {
+            if let Err(mismatch) = detector.check(mime, body) {
+                return Err(crate::error::localized(
+                    StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                    "error.body.content_mismatch",
+                    Args::new()
+                        .arg("declared", mime.essence_str())
+                        .arg("detected", mismatch.detected()),
+                    format_args!(
+                        "the content declared as {} looks like {}",
+                        mime.essence_str(),
+                        mismatch.detected()
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<H> ModifyHandler<H> for ContentSniff
+where
+    H: Handler,
+{
+    type Output = H::Output;
+    type Handler = ContentSniffHandler<H>; // private
+
+    fn modify(&self, inner: H) -> Self::Handler {
+        ContentSniffHandler {
+            inner,
+            modifier: self.clone(),
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct ContentSniffHandler<H> {
+    inner: H,
+    modifier: ContentSniff,
+}
+
+impl<H> Handler for ContentSniffHandler<H>
+where
+    H: Handler,
+{
+    type Output = H::Output;
+    type Error = Error;
+    type Handle = HandleContentSniff<H::Handle>; // private
+
+    fn allowed_methods(&self) -> Option<&AllowedMethods> {
+        self.inner.allowed_methods()
+    }
+
+    fn handle(&self) -> Self::Handle {
+        HandleContentSniff {
+            inner: self.inner.handle(),
+            modifier: self.modifier.clone(),
+            state: State::Init,
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct HandleContentSniff<H> {
+    inner: H,
+    modifier: ContentSniff,
+    state: State,
+}
+
+enum State {
+    Init,
+    Buffering {
+        mime: Mime,
+        body: Concat2<BodyStream>,
+    },
+    Running,
+}
+
+impl<H> TryFuture for HandleContentSniff<H>
+where
+    H: TryFuture,
+    H::Error: Into<Error>,
+{
+    type Ok = H::Ok;
+    type Error = Error;
+
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        loop {
+            self.state = match self.state {
+                State::Init => match crate::input::header::parse::<ContentType>(input)? {
+                    Some(mime) if self.modifier.applies_to(mime) => {
+                        let mime = mime.clone();
+                        match RequestBody::take_from(input.locals) {
+                            Some(body) => State::Buffering {
+                                mime,
+                                body: body.into_stream().concat2(),
+                            },
+                            None => State::Running,
+                        }
+                    }
+                    _ => State::Running,
+                },
+                State::Buffering {
+                    ref mime,
+                    ref mut body,
+                } => {
+                    let body = futures01::try_ready!(body.poll());
+                    self.modifier.check(mime, &body)?;
+                    // hand the buffered body over to the handler.
+                    RequestBody::from(hyper::Body::from(body)).insert_into(input.locals);
+                    State::Running
+                }
+                State::Running => return self.inner.poll_ready(input).map_err(Into::into),
+            };
+        }
+    }
+}
+
+struct Json;
+
+impl Detector for Json {
+    fn applies_to(&self, mime: &Mime) -> bool {
+        mime.type_() == mime::APPLICATION
+            && (mime.subtype() == mime::JSON || mime.suffix() == Some(mime::JSON))
+    }
+
+    fn check(&self, _: &Mime, body: &[u8]) -> Result<(), Mismatch> {
+        let text = std::str::from_utf8(body).map_err(|_| Mismatch::describe(body))?;
+        match text
+            .trim_start_matches('\u{feff}')
+            .trim_start()
+            .chars()
+            .next()
+        {
+            Some('{') | Some('[') | None => Ok(()),
+            Some(..) => Err(Mismatch::new("text which is not a JSON object or array")),
+        }
+    }
+}
+
+struct Text;
+
+impl Detector for Text {
+    fn applies_to(&self, mime: &Mime) -> bool {
+        mime.type_() == mime::TEXT
+            && mime
+                .get_param(mime::CHARSET)
+                .map_or(true, |charset| charset == mime::UTF_8)
+    }
+
+    fn check(&self, _: &Mime, body: &[u8]) -> Result<(), Mismatch> {
+        std::str::from_utf8(body)
+            .map(|_| ())
+            .map_err(|_| Mismatch::describe(body))
+    }
+}
+
+struct Image;
+
+impl Detector for Image {
+    fn applies_to(&self, mime: &Mime) -> bool {
+        mime.type_() == mime::IMAGE && image_format(mime.subtype().as_str()).is_some()
+    }
+
+    fn check(&self, mime: &Mime, body: &[u8]) -> Result<(), Mismatch> {
+        match image_format(mime.subtype().as_str()) {
+            Some(format) if format.matches(body) => Ok(()),
+            _ => Err(Mismatch::describe(body)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ImageFormat {
+    Png,
+    Jpeg,
+    Gif,
+    Webp,
+}
+
+fn image_format(subtype: &str) -> Option<ImageFormat> {
+    match subtype {
+        "png" => Some(ImageFormat::Png),
+        "jpeg" | "jpg" => Some(ImageFormat::Jpeg),
+        "gif" => Some(ImageFormat::Gif),
+        "webp" => Some(ImageFormat::Webp),
+        _ => None,
+    }
+}
+
+impl ImageFormat {
+    fn matches(self, body: &[u8]) -> bool {
+        match self {
+            ImageFormat::Png => body.starts_with(b"\x89PNG\r\n\x1a\n"),
+            ImageFormat::Jpeg => body.starts_with(b"\xff\xd8\xff"),
+            ImageFormat::Gif => body.starts_with(b"GIF87a") || body.starts_with(b"GIF89a"),
+            ImageFormat::Webp => {
+                body.len() >= 12 && body.starts_with(b"RIFF") && &body[8..12] == b"WEBP"
+            }
+        }
+    }
+}
+
+/// Describes the content by the well-known magic numbers.
+fn describe(body: &[u8]) -> &'static str {
+    const MAGICS: &[(&[u8], &str)] = &[
+        (b"%PDF-", "PDF document"),
+        (b"PK\x03\x04", "ZIP archive"),
+        (b"\x1f\x8b", "gzip data"),
+        (b"\x7fELF", "ELF executable"),
+        (b"MZ", "Windows executable"),
+    ];
+    let image = [
+        (ImageFormat::Png, "PNG image"),
+        (ImageFormat::Jpeg, "JPEG image"),
+        (ImageFormat::Gif, "GIF image"),
+        (ImageFormat::Webp, "WebP image"),
+    ]
+    .iter()
+    .find(|(format, _)| format.matches(body))
+    .map(|&(_, name)| name);
+    if let Some(name) = image {
+        return name;
+    }
+    if let Some(&(_, name)) = MAGICS.iter().find(|(magic, _)| body.starts_with(magic)) {
+        return name;
+    }
+    match std::str::from_utf8(body) {
+        Ok(..) => "text",
+        Err(..) => "binary data",
+    }
+}
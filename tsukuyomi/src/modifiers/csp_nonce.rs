@@ -0,0 +1,329 @@
+use {
+    crate::{
+        error::Error,
+        future::{Async, Poll, TryFuture},
+        handler::{AllowedMethods, Handler, ModifyHandler},
+        input::{
+            localmap::{local_key, LocalData},
+            Input,
+        },
+        output::{IntoResponse, ResponseBody},
+        responder::Responder,
+    },
+    bytes::Bytes,
+    http::{
+        header::{HeaderMap, HeaderValue, CONTENT_LENGTH, CONTENT_SECURITY_POLICY, CONTENT_TYPE},
+        Request, Response,
+    },
+    std::{fmt, sync::Arc},
+};
+
+/// A `ModifyHandler` that attaches `Content-Security-Policy` with a per-request nonce
+/// to the HTML responses.
+///
+/// The nonce is generated from `Input::random` before the inner handler is called,
+/// and is stored in the request-local map so that the handler can embed it into
+/// the inline `<script>` and `<style>` elements.
+///
+/// The header is added only to the responses whose `Content-Type` is `text/html`,
+/// and not if the handler has already set one.
+#[derive(Debug, Clone)]
+pub struct CspNonce {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    template: String,
+    placeholder: Option<String>,
+}
+
+impl CspNonce {
+    /// Creates a `CspNonce` with the template of the policy.
+    ///
+    /// The occurrences of `{nonce}` in the template are replaced with the nonce,
+    /// e.g. `"script-src 'nonce-{nonce}'"`. This function returns an error if the
+    /// template does not contain `{nonce}` or cannot be a header value.
+    pub fn new(template: impl Into<String>) -> crate::config::Result<Self> {
+        let template = template.into();
+        if !template.contains("{nonce}") {
+            return Err(crate::config::Error::custom(failure::format_err!(
+                "the CSP template must contain `{{nonce}}`: {:?}",
+                template
+            )));
+        }
+        HeaderValue::from_str(&render(&template, "0")).map_err(crate::config::Error::custom)?;
+
+        Ok(Self {
+            inner: Arc::new(Inner {
+                template,
+                placeholder: None,
+            }),
+        })
+    }
+
+    /// Enables to replace the occurrences of `placeholder` in the HTML response
+    /// bodies with the nonce.
+    ///
+    /// Only the bodies buffered in memory are rewritten. The streaming bodies
+    /// are sent as they are, with the header.
+    pub fn rewrite(self, placeholder: impl Into<String>) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                template: self.inner.template.clone(),
+                placeholder: Some(placeholder.into()).filter(|p| !p.is_empty()),
+            }),
+        }
+    }
+}
+
+fn render(template: &str, nonce: &str) -> String {
+    template.replace("{nonce}", nonce)
+}
+
+/// The nonce generated for the current request by `CspNonce`.
+///
+/// The value is stored in the request-local map and can be extracted by using
+/// `extractor::local::clone(&Nonce::KEY)`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Nonce(Arc<str>);
+
+impl Nonce {
+    fn generate(input: &Input<'_>) -> Self {
+        let random = input.random();
+        let nonce = format!("{:016x}{:016x}", random.next_u64(), random.next_u64());
+        Nonce(nonce.into())
+    }
+
+    /// Returns the string representation of this nonce.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for Nonce {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl fmt::Display for Nonce {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl LocalData for Nonce {
+    local_key! {
+        /// The local key to manage the CSP nonce of the current request.
+        const KEY: Self;
+    }
+}
+
+impl<H> ModifyHandler<H> for CspNonce
+where
+    H: Handler,
+    H::Output: Responder,
+{
+    type Output = WithCspNonce<H::Output>;
+    type Handler = CspNonceHandler<H>; // private
+
+    fn modify(&self, inner: H) -> Self::Handler {
+        CspNonceHandler {
+            inner,
+            modifier: self.clone(),
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct CspNonceHandler<H> {
+    inner: H,
+    modifier: CspNonce,
+}
+
+impl<H> Handler for CspNonceHandler<H>
+where
+    H: Handler,
+    H::Output: Responder,
+{
+    type Output = WithCspNonce<H::Output>;
+    type Error = H::Error;
+    type Handle = HandleCspNonce<H::Handle>; // private
+
+    fn allowed_methods(&self) -> Option<&AllowedMethods> {
+        self.inner.allowed_methods()
+    }
+
+    fn handle(&self) -> Self::Handle {
+        HandleCspNonce {
+            inner: self.inner.handle(),
+            modifier: self.modifier.clone(),
+            nonce: None,
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct HandleCspNonce<H> {
+    inner: H,
+    modifier: CspNonce,
+    nonce: Option<Nonce>,
+}
+
+impl<H> TryFuture for HandleCspNonce<H>
+where
+    H: TryFuture,
+{
+    type Ok = WithCspNonce<H::Ok>;
+    type Error = H::Error;
+
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        if self.nonce.is_none() {
+            // share the nonce with the outer modifiers, if any.
+            let nonce = match input.locals.get(&Nonce::KEY) {
+                Some(nonce) => nonce.clone(),
+                None => {
+                    let nonce = Nonce::generate(input);
+                    nonce.clone().insert_into(input.locals);
+                    nonce
+                }
+            };
+            self.nonce = Some(nonce);
+        }
+        let output = futures01::try_ready!(self.inner.poll_ready(input));
+        Ok(Async::Ready(WithCspNonce {
+            inner: output,
+            modifier: self.modifier.clone(),
+            nonce: self.nonce.take().expect("the nonce should be generated"),
+        }))
+    }
+}
+
+/// A `Responder` which attaches the CSP nonce to the HTML response.
+#[derive(Debug)]
+pub struct WithCspNonce<T> {
+    inner: T,
+    modifier: CspNonce,
+    nonce: Nonce,
+}
+
+impl<T> Responder for WithCspNonce<T>
+where
+    T: Responder,
+{
+    type Response = WithCspNonceResponse<T::Response>;
+    type Error = T::Error;
+    type Respond = WithCspNonceRespond<T::Respond>; // private
+
+    fn respond(self) -> Self::Respond {
+        WithCspNonceRespond {
+            inner: self.inner.respond(),
+            context: Some((self.modifier, self.nonce)),
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct WithCspNonceRespond<R> {
+    inner: R,
+    context: Option<(CspNonce, Nonce)>,
+}
+
+impl<R> TryFuture for WithCspNonceRespond<R>
+where
+    R: TryFuture,
+{
+    type Ok = WithCspNonceResponse<R::Ok>;
+    type Error = R::Error;
+
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        let inner = futures01::try_ready!(self.inner.poll_ready(input));
+        let (modifier, nonce) = self
+            .context
+            .take()
+            .expect("the future has already been polled.");
+        Ok(Async::Ready(WithCspNonceResponse {
+            inner,
+            modifier,
+            nonce,
+        }))
+    }
+}
+
+/// An `IntoResponse` which attaches the CSP nonce to the HTML response.
+#[derive(Debug)]
+pub struct WithCspNonceResponse<T> {
+    inner: T,
+    modifier: CspNonce,
+    nonce: Nonce,
+}
+
+impl<T> IntoResponse for WithCspNonceResponse<T>
+where
+    T: IntoResponse,
+{
+    type Body = ResponseBody;
+    type Error = Error;
+
+    fn into_response(self, request: &Request<()>) -> Result<Response<Self::Body>, Self::Error> {
+        let response = self
+            .inner
+            .into_response(request)
+            .map_err(Into::into)?
+            .map(Into::<ResponseBody>::into);
+        let (mut parts, mut body) = response.into_parts();
+
+        if !is_html(&parts.headers) {
+            return Ok(Response::from_parts(parts, body));
+        }
+
+        if !parts.headers.contains_key(CONTENT_SECURITY_POLICY) {
+            let policy = render(&self.modifier.inner.template, self.nonce.as_str());
+            parts.headers.insert(
+                CONTENT_SECURITY_POLICY,
+                HeaderValue::from_str(&policy).expect("the template should be validated"),
+            );
+        }
+
+        if let Some(ref placeholder) = self.modifier.inner.placeholder {
+            body = match body.try_into_bytes() {
+                Ok(content) => {
+                    parts.headers.remove(CONTENT_LENGTH);
+                    replace(
+                        &content,
+                        placeholder.as_bytes(),
+                        self.nonce.as_str().as_bytes(),
+                    )
+                    .into()
+                }
+                Err(body) => body,
+            };
+        }
+
+        Ok(Response::from_parts(parts, body))
+    }
+}
+
+fn is_html(headers: &HeaderMap) -> bool {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<mime::Mime>().ok())
+        .map_or(false, |mime| {
+            mime.type_() == mime::TEXT && mime.subtype() == mime::HTML
+        })
+}
+
+/// Replaces all occurrences of `from` in `content` with `to`.
+fn replace(content: &[u8], from: &[u8], to: &[u8]) -> Bytes {
+    let mut replaced = Vec::with_capacity(content.len());
+    let mut rest = content;
+    while let Some(pos) = rest.windows(from.len()).position(|window| window == from) {
+        replaced.extend_from_slice(&rest[..pos]);
+        replaced.extend_from_slice(to);
+        rest = &rest[pos + from.len()..];
+    }
+    replaced.extend_from_slice(rest);
+    replaced.into()
+}
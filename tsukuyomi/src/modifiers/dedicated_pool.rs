@@ -0,0 +1,460 @@
+use {
+    crate::{
+        app::{DetachedInput, Lifecycle},
+        error::Error,
+        future::{Async, Poll, TryFuture},
+        handler::{AllowedMethods, Handler, ModifyHandler},
+        input::Input,
+    },
+    futures01::{future, sync::oneshot, Future},
+    http::{header::RETRY_AFTER, Response, StatusCode},
+    std::{
+        mem,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex, MutexGuard,
+        },
+        time::Duration,
+    },
+    tokio_threadpool::ThreadPool,
+};
+
+/// A `ModifyHandler` that processes the requests on a dedicated thread pool.
+///
+/// The extraction and the handler of the modified routes are polled on the
+/// worker threads of the pool instead of the threads of the runtime, so a slow
+/// or blocking handler does not delay the other requests. The response is sent
+/// back to the task of the request when the handler completes.
+///
+/// The pool accepts at most `threads + queue_bound` requests at the same time,
+/// and the requests beyond that are rejected with `503 Service Unavailable` and
+/// `Retry-After`. The pool is shared among all handlers modified by the clones of
+/// this value, and its threads are started when the application is built.
+///
+/// The request is processed with a copy of its header, in which the extensions
+/// other than `ConnectionInfo` are not available. The modified routes must belong
+/// to a thread-safe application, otherwise the requests fail with
+/// `500 Internal Server Error`.
+///
+/// In order to wait for the worker threads to stop on shutdown, the pool should
+/// also be registered with `config::state_with_lifecycle`:
+///
+/// ```
+/// # use tsukuyomi::{config::prelude::*, modifiers::DedicatedPool, App};
+/// # fn render_pdf() -> String { String::new() }
+/// let pdf_pool = DedicatedPool::new("pdf").threads(2).queue_bound(8);
+///
+/// let app = App::create(chain![
+///     tsukuyomi::config::state_with_lifecycle(pdf_pool.clone()),
+///     path!("/report.pdf")
+///         .to(endpoint::get().call(render_pdf))
+///         .modify(pdf_pool),
+/// ]);
+/// # drop(app);
+/// ```
+#[derive(Debug, Clone)]
+pub struct DedicatedPool {
+    name: Arc<str>,
+    threads: usize,
+    queue_bound: usize,
+    retry_after: Duration,
+    shared: Arc<Shared>,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    executor: Mutex<Executor>,
+    workers: AtomicUsize,
+    outstanding: AtomicUsize,
+    busy: AtomicUsize,
+    completed: AtomicUsize,
+    rejected: AtomicUsize,
+}
+
+#[derive(Debug)]
+enum Executor {
+    Pending,
+    Running(ThreadPool),
+    Stopped,
+}
+
+impl Default for Executor {
+    fn default() -> Self {
+        Executor::Pending
+    }
+}
+
+impl Drop for Shared {
+    fn drop(&mut self) {
+        // The threads are not waited for, since the last reference may be dropped
+        // on a worker thread of the pool itself.
+        let executor = self
+            .executor
+            .get_mut()
+            .unwrap_or_else(|err| err.into_inner());
+        if let Executor::Running(pool) = mem::replace(executor, Executor::Stopped) {
+            let _ = pool.shutdown_now();
+        }
+    }
+}
+
+impl Shared {
+    fn executor(&self) -> MutexGuard<'_, Executor> {
+        self.executor.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Reserves a slot for a request, or returns `false` if the pool is full.
+    fn reserve(&self, capacity: usize) -> bool {
+        if self.outstanding.fetch_add(1, Ordering::SeqCst) < capacity {
+            true
+        } else {
+            self.outstanding.fetch_sub(1, Ordering::SeqCst);
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+            false
+        }
+    }
+}
+
+impl DedicatedPool {
+    /// Creates a `DedicatedPool` whose threads are named with the specified prefix.
+    ///
+    /// By default, the pool has one thread and queues up to 16 requests.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into().into(),
+            threads: 1,
+            queue_bound: 16,
+            retry_after: Duration::from_secs(1),
+            shared: Arc::new(Shared::default()),
+        }
+    }
+
+    /// Sets the number of the worker threads.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `threads` is zero.
+    pub fn threads(self, threads: usize) -> Self {
+        assert!(threads > 0, "the number of threads must be positive");
+        Self { threads, ..self }
+    }
+
+    /// Sets the number of the requests that can wait for a free worker thread.
+    pub fn queue_bound(self, queue_bound: usize) -> Self {
+        Self {
+            queue_bound,
+            ..self
+        }
+    }
+
+    /// Sets the value of `Retry-After` in the responses to the rejected requests.
+    ///
+    /// The default value is one second.
+    pub fn retry_after(self, retry_after: Duration) -> Self {
+        Self {
+            retry_after,
+            ..self
+        }
+    }
+
+    /// Returns a snapshot of the utilization of the pool.
+    ///
+    /// The clones of this value share the pool.
+    pub fn metrics(&self) -> DedicatedPoolMetrics {
+        let shared = &*self.shared;
+        let outstanding = shared.outstanding.load(Ordering::SeqCst);
+        let busy = shared.busy.load(Ordering::SeqCst);
+        DedicatedPoolMetrics {
+            threads: shared.workers.load(Ordering::SeqCst) as u64,
+            busy: busy as u64,
+            queued: outstanding.saturating_sub(busy) as u64,
+            completed: shared.completed.load(Ordering::Relaxed) as u64,
+            rejected: shared.rejected.load(Ordering::Relaxed) as u64,
+        }
+    }
+
+    /// Starts the worker threads unless they have been started.
+    fn start(&self) {
+        let mut executor = self.shared.executor();
+        if let Executor::Pending = *executor {
+            let started = Arc::downgrade(&self.shared);
+            let stopped = started.clone();
+            let pool = tokio_threadpool::Builder::new()
+                .pool_size(self.threads)
+                .name_prefix(format!("{}-", self.name))
+                .after_start(move || {
+                    if let Some(shared) = started.upgrade() {
+                        shared.workers.fetch_add(1, Ordering::SeqCst);
+                    }
+                })
+                .before_stop(move || {
+                    if let Some(shared) = stopped.upgrade() {
+                        shared.workers.fetch_sub(1, Ordering::SeqCst);
+                    }
+                })
+                .build();
+            *executor = Executor::Running(pool);
+        }
+    }
+
+    fn overloaded(&self) -> Error {
+        let retry_after = std::cmp::max(
+            1,
+            self.retry_after.as_secs() + u64::from(self.retry_after.subsec_nanos() > 0),
+        );
+        crate::error::error_response(
+            Response::builder()
+                .status(StatusCode::SERVICE_UNAVAILABLE)
+                .header(RETRY_AFTER, retry_after)
+                .body("the server is overloaded")
+                .expect("should be a valid response"),
+        )
+    }
+}
+
+impl Lifecycle for DedicatedPool {
+    const NAME: &'static str = "DedicatedPool";
+
+    /// Stops the pool after the running handlers complete, and waits for the worker
+    /// threads to exit.
+    fn on_shutdown(&self) -> Box<dyn Future<Item = (), Error = ()> + Send + 'static> {
+        match mem::replace(&mut *self.shared.executor(), Executor::Stopped) {
+            Executor::Running(pool) => Box::new(pool.shutdown_on_idle()),
+            Executor::Pending | Executor::Stopped => Box::new(future::ok(())),
+        }
+    }
+}
+
+/// A snapshot of the utilization of a `DedicatedPool`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DedicatedPoolMetrics {
+    /// The number of the running worker threads.
+    pub threads: u64,
+
+    /// The number of the requests being processed on the worker threads.
+    pub busy: u64,
+
+    /// The number of the requests waiting for a free worker thread.
+    pub queued: u64,
+
+    /// The number of the requests whose handler has completed on the pool.
+    pub completed: u64,
+
+    /// The number of the requests rejected because the pool was full.
+    pub rejected: u64,
+}
+
+impl<H> ModifyHandler<H> for DedicatedPool
+where
+    H: Handler,
+    H::Handle: Send + 'static,
+    H::Output: Send + 'static,
+{
+    type Output = H::Output;
+    type Handler = DedicatedPoolHandler<H>; // private
+
+    fn modify(&self, inner: H) -> Self::Handler {
+        self.start();
+        DedicatedPoolHandler {
+            inner,
+            modifier: self.clone(),
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct DedicatedPoolHandler<H> {
+    inner: H,
+    modifier: DedicatedPool,
+}
+
+impl<H> Handler for DedicatedPoolHandler<H>
+where
+    H: Handler,
+    H::Handle: Send + 'static,
+    H::Output: Send + 'static,
+{
+    type Output = H::Output;
+    type Error = Error;
+    type Handle = HandleDedicatedPool<H::Handle>; // private
+
+    fn allowed_methods(&self) -> Option<&AllowedMethods> {
+        self.inner.allowed_methods()
+    }
+
+    fn handle(&self) -> Self::Handle {
+        HandleDedicatedPool {
+            modifier: self.modifier.clone(),
+            state: HandleState::Init(self.inner.handle()),
+        }
+    }
+}
+
+/// The result of the handler sent back from the pool, along with the request data.
+type Completed<T> = (Result<T, Error>, DetachedInput);
+
+#[allow(missing_debug_implementations)]
+pub struct HandleDedicatedPool<H: TryFuture> {
+    modifier: DedicatedPool,
+    state: HandleState<H>,
+}
+
+enum HandleState<H: TryFuture> {
+    Init(H),
+    Waiting(oneshot::Receiver<Completed<H::Ok>>),
+    Done,
+}
+
+impl<H> HandleDedicatedPool<H>
+where
+    H: TryFuture + Send + 'static,
+    H::Ok: Send + 'static,
+{
+    fn submit(&self, handle: H, input: &mut Input<'_>) -> Result<HandleState<H>, Error> {
+        let shared = &self.modifier.shared;
+        if !shared.reserve(self.modifier.threads + self.modifier.queue_bound) {
+            return Err(self.modifier.overloaded());
+        }
+        let slot = Slot {
+            shared: shared.clone(),
+            busy: false,
+        };
+
+        let executor = shared.executor();
+        let pool = match *executor {
+            Executor::Running(ref pool) => pool,
+            Executor::Pending | Executor::Stopped => {
+                shared.rejected.fetch_add(1, Ordering::Relaxed);
+                return Err(self.modifier.overloaded());
+            }
+        };
+
+        let dispatch = input.dispatch;
+        let detached = dispatch.detach(input).ok_or_else(|| {
+            crate::error::internal_server_error(
+                "DedicatedPool requires the application to be thread safe",
+            )
+        })?;
+
+        let (tx, rx) = oneshot::channel();
+        let job = Job {
+            handle,
+            input: Some(detached),
+            tx: Some(tx),
+            slot: Some(slot),
+        };
+        if let Err(err) = pool.sender().spawn(job) {
+            // The request data has been dropped along with the job.
+            log::error!("failed to spawn the handler on the dedicated pool: {}", err);
+            return Err(self.modifier.overloaded());
+        }
+        Ok(HandleState::Waiting(rx))
+    }
+}
+
+impl<H> TryFuture for HandleDedicatedPool<H>
+where
+    H: TryFuture + Send + 'static,
+    H::Ok: Send + 'static,
+{
+    type Ok = H::Ok;
+    type Error = Error;
+
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        loop {
+            self.state = match mem::replace(&mut self.state, HandleState::Done) {
+                HandleState::Init(handle) => self.submit(handle, input)?,
+                HandleState::Waiting(mut rx) => match rx.poll() {
+                    Ok(Async::Ready((result, detached))) => {
+                        detached.attach(input);
+                        return result.map(Async::Ready);
+                    }
+                    Ok(Async::NotReady) => {
+                        self.state = HandleState::Waiting(rx);
+                        return Ok(Async::NotReady);
+                    }
+                    Err(oneshot::Canceled) => {
+                        return Err(crate::error::internal_server_error(
+                            "the handler on the dedicated pool has been aborted",
+                        ));
+                    }
+                },
+                HandleState::Done => panic!("the future has already polled."),
+            };
+        }
+    }
+}
+
+/// The slot of a request accepted by the pool, released on drop.
+struct Slot {
+    shared: Arc<Shared>,
+    busy: bool,
+}
+
+impl Slot {
+    fn start(&mut self) {
+        if !self.busy {
+            self.busy = true;
+            self.shared.busy.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        if self.busy {
+            self.shared.busy.fetch_sub(1, Ordering::SeqCst);
+        }
+        self.shared.outstanding.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// The task polling the handle on a worker thread.
+struct Job<H: TryFuture> {
+    handle: H,
+    input: Option<DetachedInput>,
+    tx: Option<oneshot::Sender<Completed<H::Ok>>>,
+    slot: Option<Slot>,
+}
+
+impl<H> Future for Job<H>
+where
+    H: TryFuture,
+{
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> futures01::Poll<(), ()> {
+        let slot = self.slot.as_mut().expect("the job has already completed");
+        slot.start();
+
+        // The request has been abandoned, e.g. the client has disconnected.
+        let tx = self.tx.as_mut().expect("the job has already completed");
+        if let Ok(Async::Ready(())) = tx.poll_cancel() {
+            return Ok(Async::Ready(()));
+        }
+
+        let handle = &mut self.handle;
+        let input = self.input.as_mut().expect("the job has already completed");
+        let result = match input.with_input(|input| handle.poll_ready(input)) {
+            Ok(Async::Ready(output)) => Ok(output),
+            Ok(Async::NotReady) => return Ok(Async::NotReady),
+            Err(err) => Err(err.into()),
+        };
+
+        // release the slot before the request resumes on its task.
+        if let Some(slot) = self.slot.take() {
+            slot.shared.completed.fetch_add(1, Ordering::Relaxed);
+        }
+        let completed = (
+            result,
+            self.input.take().expect("the job has already completed"),
+        );
+        let _ = self
+            .tx
+            .take()
+            .expect("the job has already completed")
+            .send(completed);
+        Ok(Async::Ready(()))
+    }
+}
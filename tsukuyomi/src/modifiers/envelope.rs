@@ -0,0 +1,415 @@
+use {
+    crate::{
+        error::Error,
+        future::{Async, Poll, TryFuture},
+        handler::{AllowedMethods, Handler, ModifyHandler},
+        input::{
+            localmap::{local_key, LocalData},
+            Input,
+        },
+        output::{IntoResponse, PageInfo, ResponseBody},
+        responder::Responder,
+        trace::TraceContext,
+    },
+    http::{
+        header::{HeaderMap, HeaderValue, CONTENT_LENGTH, CONTENT_TYPE},
+        Request, Response,
+    },
+    serde_json::{Map, Value},
+    std::{sync::Arc, time::Instant},
+};
+
+const REQUEST_ID: &str = "x-request-id";
+
+/// A `ModifyHandler` that wraps the JSON responses into an envelope object
+/// together with the metadata of the request.
+///
+/// The body of a successful (`2xx`) response whose `Content-Type` is
+/// `application/json` is moved under the data key, and the meta object is
+/// added beside it:
+///
+/// ```json
+/// {"data": [1, 2, 3], "meta": {"request_id": "...", "pagination": {...}, "elapsed_ms": 3}}
+/// ```
+///
+/// The meta object contains the following members, each of which is omitted if not available:
+///
+/// * `request_id` - the value of `X-Request-Id` in the request, or the trace ID
+///   of the `TraceContext` stored by `trace::Trace`.
+/// * `pagination` - the `PageInfo` of the response created by `output::Paginated`.
+/// * `elapsed_ms` - the time spent on the handler in milliseconds.
+///
+/// The streaming bodies are not buffered and sent as they are. The errors are
+/// rendered by the application unless `wrap_errors` is enabled.
+///
+/// If the modifier is applied to the nested scopes, only the innermost one
+/// wraps the response and the others leave it as it is.
+#[derive(Debug, Clone)]
+pub struct Envelope {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug, Clone)]
+struct Inner {
+    data_key: String,
+    meta_key: String,
+    error_key: String,
+    wrap_errors: bool,
+}
+
+impl Default for Envelope {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Envelope {
+    /// Creates an `Envelope` with the default configuration.
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                data_key: "data".into(),
+                meta_key: "meta".into(),
+                error_key: "error".into(),
+                wrap_errors: false,
+            }),
+        }
+    }
+
+    /// Sets the key under which the original body is placed.
+    ///
+    /// The default value is `"data"`.
+    pub fn data_key(mut self, key: impl Into<String>) -> Self {
+        Arc::make_mut(&mut self.inner).data_key = key.into();
+        self
+    }
+
+    /// Sets the key of the meta object.
+    ///
+    /// The default value is `"meta"`.
+    pub fn meta_key(mut self, key: impl Into<String>) -> Self {
+        Arc::make_mut(&mut self.inner).meta_key = key.into();
+        self
+    }
+
+    /// Sets the key under which the error is placed if `wrap_errors` is enabled.
+    ///
+    /// The default value is `"error"`.
+    pub fn error_key(mut self, key: impl Into<String>) -> Self {
+        Arc::make_mut(&mut self.inner).error_key = key.into();
+        self
+    }
+
+    /// Sets whether to wrap the errors as well as the successful responses.
+    ///
+    /// If enabled, the errors returned from the handler are rendered as the
+    /// problem details by `output::problem::render`, and the problem object is
+    /// placed under the error key with the meta object. The status code and the
+    /// header fields of the error response are kept.
+    ///
+    /// The default value is `false`.
+    pub fn wrap_errors(mut self, enabled: bool) -> Self {
+        Arc::make_mut(&mut self.inner).wrap_errors = enabled;
+        self
+    }
+
+    fn wrap(&self, key: &str, value: Value, meta: Map<String, Value>) -> Vec<u8> {
+        let mut envelope = Map::new();
+        envelope.insert(key.to_owned(), value);
+        envelope.insert(self.inner.meta_key.clone(), Value::Object(meta));
+        serde_json::to_vec(&envelope).expect("a JSON value should always be serialized")
+    }
+}
+
+/// The marker stored by the `Envelope` which wraps the response of the current request.
+#[derive(Debug)]
+struct Claimed(());
+
+impl LocalData for Claimed {
+    local_key! {
+        const KEY: Self;
+    }
+}
+
+impl<H> ModifyHandler<H> for Envelope
+where
+    H: Handler,
+    H::Output: Responder,
+{
+    type Output = WithEnvelope<H::Output>;
+    type Handler = EnvelopeHandler<H>; // private
+
+    fn modify(&self, inner: H) -> Self::Handler {
+        EnvelopeHandler {
+            inner,
+            modifier: self.clone(),
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct EnvelopeHandler<H> {
+    inner: H,
+    modifier: Envelope,
+}
+
+impl<H> Handler for EnvelopeHandler<H>
+where
+    H: Handler,
+    H::Output: Responder,
+{
+    type Output = WithEnvelope<H::Output>;
+    type Error = H::Error;
+    type Handle = HandleEnvelope<H::Handle>; // private
+
+    fn allowed_methods(&self) -> Option<&AllowedMethods> {
+        self.inner.allowed_methods()
+    }
+
+    fn handle(&self) -> Self::Handle {
+        HandleEnvelope {
+            inner: self.inner.handle(),
+            modifier: self.modifier.clone(),
+            context: None,
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct HandleEnvelope<H> {
+    inner: H,
+    modifier: Envelope,
+    context: Option<Option<Context>>,
+}
+
+impl<H> TryFuture for HandleEnvelope<H>
+where
+    H: TryFuture,
+    H::Error: Into<Error>,
+{
+    type Ok = WithEnvelope<H::Ok>;
+    type Error = H::Error;
+
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        if self.context.is_none() {
+            // The modifiers of the inner scopes wrap the handlers modified by
+            // the outer ones, so the innermost envelope is the first to be entered.
+            self.context = Some(if Claimed::contains(input.locals) {
+                None
+            } else {
+                Claimed(()).insert_into(input.locals);
+                Some(Context {
+                    modifier: self.modifier.clone(),
+                    start: input.clock().now(),
+                })
+            });
+        }
+
+        let inner = match self.inner.poll_ready(input) {
+            Ok(Async::Ready(output)) => Ok(output),
+            Ok(Async::NotReady) => return Ok(Async::NotReady),
+            Err(err) => {
+                if !self.context.as_ref().map_or(false, wraps_errors) {
+                    return Err(err);
+                }
+                Err(err.into())
+            }
+        };
+        Ok(Async::Ready(WithEnvelope {
+            inner,
+            context: self.context.take().expect("the handle should be entered"),
+        }))
+    }
+}
+
+fn wraps_errors(context: &Option<Context>) -> bool {
+    context
+        .as_ref()
+        .map_or(false, |context| context.modifier.inner.wrap_errors)
+}
+
+#[derive(Debug)]
+struct Context {
+    modifier: Envelope,
+    start: Instant,
+}
+
+impl Context {
+    fn meta(&self, input: &mut Input<'_>) -> Map<String, Value> {
+        let mut meta = Map::new();
+        let request_id = input
+            .request
+            .headers()
+            .get(REQUEST_ID)
+            .and_then(|value| value.to_str().ok())
+            .map(ToOwned::to_owned)
+            .or_else(|| {
+                input
+                    .locals
+                    .get(&TraceContext::KEY)
+                    .map(|cx| format!("{:032x}", cx.trace_id()))
+            });
+        if let Some(request_id) = request_id {
+            meta.insert("request_id".into(), request_id.into());
+        }
+        let elapsed = input.clock().now().duration_since(self.start);
+        meta.insert(
+            "elapsed_ms".into(),
+            (elapsed.as_secs() * 1000 + u64::from(elapsed.subsec_millis())).into(),
+        );
+        meta
+    }
+}
+
+/// A `Responder` which wraps the JSON response into the envelope.
+#[derive(Debug)]
+pub struct WithEnvelope<T> {
+    inner: Result<T, Error>,
+    context: Option<Context>,
+}
+
+impl<T> Responder for WithEnvelope<T>
+where
+    T: Responder,
+{
+    type Response = WithEnvelopeResponse<T::Response>;
+    type Error = T::Error;
+    type Respond = WithEnvelopeRespond<T::Respond>; // private
+
+    fn respond(self) -> Self::Respond {
+        WithEnvelopeRespond {
+            inner: match self.inner {
+                Ok(inner) => Ok(inner.respond()),
+                Err(err) => Err(Some(err)),
+            },
+            context: self.context,
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct WithEnvelopeRespond<R> {
+    inner: Result<R, Option<Error>>,
+    context: Option<Context>,
+}
+
+impl<R> TryFuture for WithEnvelopeRespond<R>
+where
+    R: TryFuture,
+    R::Error: Into<Error>,
+{
+    type Ok = WithEnvelopeResponse<R::Ok>;
+    type Error = R::Error;
+
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        let inner = match self.inner {
+            Ok(ref mut respond) => match respond.poll_ready(input) {
+                Ok(Async::Ready(response)) => Ok(response),
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Err(err) => {
+                    if !wraps_errors(&self.context) {
+                        return Err(err);
+                    }
+                    Err(err.into())
+                }
+            },
+            Err(ref mut err) => Err(err.take().expect("the future has already been polled.")),
+        };
+        let envelope = self
+            .context
+            .take()
+            .map(|context| (context.meta(input), context.modifier));
+        Ok(Async::Ready(WithEnvelopeResponse { inner, envelope }))
+    }
+}
+
+/// An `IntoResponse` which wraps the JSON response into the envelope.
+#[derive(Debug)]
+pub struct WithEnvelopeResponse<T> {
+    inner: Result<T, Error>,
+    envelope: Option<(Map<String, Value>, Envelope)>,
+}
+
+impl<T> IntoResponse for WithEnvelopeResponse<T>
+where
+    T: IntoResponse,
+{
+    type Body = ResponseBody;
+    type Error = Error;
+
+    fn into_response(self, request: &Request<()>) -> Result<Response<Self::Body>, Self::Error> {
+        let (mut meta, modifier) = match self.envelope {
+            Some(envelope) => envelope,
+            None => {
+                return self
+                    .inner?
+                    .into_response(request)
+                    .map(|response| response.map(Into::into))
+                    .map_err(Into::into)
+            }
+        };
+        let response = match self
+            .inner
+            .and_then(|inner| inner.into_response(request).map_err(Into::into))
+        {
+            Ok(response) => response.map(Into::<ResponseBody>::into),
+            Err(err) if modifier.inner.wrap_errors => {
+                return Ok(wrap_error(err, request, meta, &modifier))
+            }
+            Err(err) => return Err(err),
+        };
+        if !response.status().is_success() || !is_json(response.headers()) {
+            return Ok(response);
+        }
+
+        let (mut parts, body) = response.into_parts();
+        let content = match body.try_into_bytes() {
+            Ok(content) => content,
+            Err(body) => return Ok(Response::from_parts(parts, body)),
+        };
+        let data = match serde_json::from_slice::<Value>(&content) {
+            Ok(data) => data,
+            Err(..) => return Ok(Response::from_parts(parts, content.into())),
+        };
+
+        if let Some(page_info) = parts.extensions.get::<PageInfo>() {
+            meta.insert(
+                "pagination".into(),
+                serde_json::to_value(page_info).map_err(crate::error::internal_server_error)?,
+            );
+        }
+        let body = modifier.wrap(&modifier.inner.data_key, data, meta);
+        parts.headers.remove(CONTENT_LENGTH);
+        Ok(Response::from_parts(parts, body.into()))
+    }
+}
+
+/// Renders the error as the problem details, and wraps it under the error key.
+fn wrap_error(
+    err: Error,
+    request: &Request<()>,
+    meta: Map<String, Value>,
+    modifier: &Envelope,
+) -> Response<ResponseBody> {
+    let (mut parts, problem) = crate::output::problem::render(err, request).into_parts();
+    let problem =
+        serde_json::from_str(&problem).expect("the problem details should be a valid JSON");
+    let body = modifier.wrap(&modifier.inner.error_key, problem, meta);
+    parts.headers.remove(CONTENT_LENGTH);
+    parts
+        .headers
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    Response::from_parts(parts, body.into())
+}
+
+fn is_json(headers: &HeaderMap) -> bool {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<mime::Mime>().ok())
+        .map_or(false, |mime| {
+            mime.type_() == mime::APPLICATION
+                && mime.subtype() == mime::JSON
+                && mime.suffix().is_none()
+        })
+}
@@ -0,0 +1,752 @@
+use {
+    crate::{
+        cache::MemoryCache,
+        error::Error,
+        future::{Async, Poll, TryFuture},
+        handler::{AllowedMethods, Handler, ModifyHandler},
+        i18n::Args,
+        input::{
+            body::{BodyStream, RequestBody},
+            localmap::LocalData,
+            Input,
+        },
+        output::{IntoResponse, ResponseBody},
+        responder::Responder,
+    },
+    bytes::{Bytes, BytesMut},
+    futures01::{future, task::AtomicTask, Future, Stream},
+    http::{
+        header::{HeaderName, HeaderValue, WARNING},
+        HeaderMap, Method, Request, Response, StatusCode,
+    },
+    std::{
+        collections::HashMap,
+        fmt,
+        sync::{Arc, Mutex, MutexGuard},
+        time::Duration,
+    },
+    tokio_executor::Executor,
+};
+
+/// The maximum length of the idempotency keys.
+const MAX_KEY_LEN: usize = 255;
+
+/// The type of futures returned from the operations of `IdempotencyStore`.
+pub type StoreFuture<T> = Box<dyn Future<Item = T, Error = failure::Error> + Send + 'static>;
+
+/// A trait representing the storage of the responses used by `Idempotency`.
+///
+/// The operations are asynchronous so that the responses can be shared among
+/// the processes with an external storage, such as Redis. `MemoryCache` is
+/// provided as the in-memory implementation.
+pub trait IdempotencyStore: Send + Sync + 'static {
+    /// Returns the response stored with the key, if it has not expired.
+    fn get(&self, key: &str) -> StoreFuture<Option<StoredResponse>>;
+
+    /// Stores the response with the key, which expires after `ttl`.
+    fn put(&self, key: &str, response: StoredResponse, ttl: Duration) -> StoreFuture<()>;
+}
+
+impl IdempotencyStore for MemoryCache<String, StoredResponse> {
+    fn get(&self, key: &str) -> StoreFuture<Option<StoredResponse>> {
+        Box::new(future::ok(MemoryCache::get(self, &key.to_owned())))
+    }
+
+    fn put(&self, key: &str, response: StoredResponse, ttl: Duration) -> StoreFuture<()> {
+        self.insert_with_ttl(key.to_owned(), response, ttl);
+        Box::new(future::ok(()))
+    }
+}
+
+/// A response stored in `IdempotencyStore`, along with the fingerprint of the request.
+#[derive(Debug, Clone)]
+pub struct StoredResponse {
+    fingerprint: String,
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl StoredResponse {
+    /// Creates a `StoredResponse` from its components, e.g. deserialized from an external storage.
+    pub fn new(fingerprint: String, status: StatusCode, headers: HeaderMap, body: Bytes) -> Self {
+        Self {
+            fingerprint,
+            status,
+            headers,
+            body,
+        }
+    }
+
+    /// Returns the fingerprint of the request that has produced the response.
+    pub fn fingerprint(&self) -> &str {
+        &self.fingerprint
+    }
+
+    /// Returns the status code of the response.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// Returns the header fields of the response.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// Returns the body of the response.
+    pub fn body(&self) -> &Bytes {
+        &self.body
+    }
+
+    fn to_response(&self) -> Response<ResponseBody> {
+        let mut response = Response::new(self.body.clone().into());
+        *response.status_mut() = self.status;
+        *response.headers_mut() = self.headers.clone();
+        response.headers_mut().insert(
+            HeaderName::from_static("idempotent-replayed"),
+            HeaderValue::from_static("true"),
+        );
+        response
+    }
+}
+
+type Fingerprint = dyn Fn(&Request<()>, &[u8]) -> String + Send + Sync + 'static;
+
+/// A `ModifyHandler` that makes the requests with `Idempotency-Key` safely retryable.
+///
+/// The first request with a key calls the inner handler, and its response is
+/// stored with the key for the TTL. The subsequent requests with the same key
+/// are responded with the stored response, marked by `Idempotent-Replayed: true`,
+/// without calling the handler. If the fingerprint of the request differs from
+/// the one that has produced the stored response, the request is rejected with
+/// `422 Unprocessable Entity`.
+///
+/// The concurrent requests with the same key are single-flighted in the process:
+/// while the first one is being handled, the others wait for its response
+/// instead of calling the handler.
+///
+/// The responses are not stored if the handler has failed, the status code is
+/// `5xx`, or the body is streamed or larger than `max_response_size`, so that
+/// the retries call the handler again. In the last two cases, the response is
+/// sent with a `Warning` header field.
+///
+/// Only the requests with the configured methods (`POST` by default) and the
+/// key are processed. The request body is buffered to compute the fingerprint,
+/// and the requests whose body is larger than `max_request_size` are rejected
+/// with `413 Payload Too Large`.
+#[derive(Clone)]
+pub struct Idempotency {
+    store: Arc<dyn IdempotencyStore>,
+    header: HeaderName,
+    ttl: Duration,
+    methods: Arc<[Method]>,
+    max_request_size: usize,
+    max_response_size: usize,
+    fingerprint: Arc<Fingerprint>,
+    shared: Arc<Shared>,
+}
+
+impl fmt::Debug for Idempotency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Idempotency")
+            .field("header", &self.header)
+            .field("ttl", &self.ttl)
+            .field("methods", &self.methods)
+            .field("max_request_size", &self.max_request_size)
+            .field("max_response_size", &self.max_response_size)
+            .finish()
+    }
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    inflight: Mutex<HashMap<String, Arc<Flight>>>,
+}
+
+impl Shared {
+    fn inflight(&self) -> MutexGuard<'_, HashMap<String, Arc<Flight>>> {
+        self.inflight.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl Idempotency {
+    /// Creates an `Idempotency` that stores the responses into the specified storage.
+    ///
+    /// The clones of an `Idempotency` share the single-flight of the requests.
+    pub fn new(store: impl IdempotencyStore) -> Self {
+        Self {
+            store: Arc::new(store),
+            header: HeaderName::from_static("idempotency-key"),
+            ttl: Duration::from_secs(24 * 60 * 60),
+            methods: Arc::new([Method::POST]),
+            max_request_size: 1024 * 1024,
+            max_response_size: 1024 * 1024,
+            fingerprint: Arc::new(default_fingerprint),
+            shared: Arc::default(),
+        }
+    }
+
+    /// Sets the name of the header field containing the idempotency key.
+    ///
+    /// The default value is `idempotency-key`.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the name is invalid.
+    pub fn header(self, name: &'static str) -> Self {
+        Self {
+            header: HeaderName::from_static(name),
+            ..self
+        }
+    }
+
+    /// Sets the duration for which the responses are stored.
+    ///
+    /// The default value is 24 hours.
+    pub fn ttl(self, ttl: Duration) -> Self {
+        Self { ttl, ..self }
+    }
+
+    /// Sets the methods of the requests processed by this modifier.
+    ///
+    /// The default value is `POST` only.
+    pub fn methods(self, methods: &[Method]) -> Self {
+        Self {
+            methods: methods.into(),
+            ..self
+        }
+    }
+
+    /// Sets the maximum size of the request bodies to be buffered.
+    ///
+    /// The default value is 1 MiB.
+    pub fn max_request_size(self, size: usize) -> Self {
+        Self {
+            max_request_size: size,
+            ..self
+        }
+    }
+
+    /// Sets the maximum size of the response bodies to be stored.
+    ///
+    /// The default value is 1 MiB.
+    pub fn max_response_size(self, size: usize) -> Self {
+        Self {
+            max_response_size: size,
+            ..self
+        }
+    }
+
+    /// Sets the function computing the fingerprint of the request from the
+    /// request head and the body.
+    ///
+    /// The default fingerprint is the hash of the method, the path and the body.
+    pub fn fingerprint<F>(self, f: F) -> Self
+    where
+        F: Fn(&Request<()>, &[u8]) -> String + Send + Sync + 'static,
+    {
+        Self {
+            fingerprint: Arc::new(f),
+            ..self
+        }
+    }
+
+    fn key(&self, input: &Input<'_>) -> Result<Option<String>, Error> {
+        if !self.methods.contains(input.request.method()) {
+            return Ok(None);
+        }
+        let value = match input.request.headers().get(&self.header) {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        match value.to_str() {
+            Ok(key) if !key.is_empty() && key.len() <= MAX_KEY_LEN => Ok(Some(key.to_owned())),
+            _ => Err(crate::error::bad_request(format!(
+                "invalid {}",
+                self.header
+            ))),
+        }
+    }
+
+    /// Starts to process the request with the key, or waits for the request in flight.
+    fn acquire(&self, key: String, fingerprint: String) -> State {
+        let mut inflight = self.shared.inflight();
+        match inflight.get(&key) {
+            Some(flight) => State::Waiting {
+                follower: Follower::new(flight.clone()),
+                key,
+                fingerprint,
+            },
+            None => {
+                let flight = Arc::new(Flight::default());
+                inflight.insert(key.clone(), flight.clone());
+                State::Lookup {
+                    lookup: self.store.get(&key),
+                    leader: Leader {
+                        shared: self.shared.clone(),
+                        key,
+                        fingerprint,
+                        flight,
+                        done: false,
+                    },
+                }
+            }
+        }
+    }
+
+    fn replay(&self, entry: StoredResponse, fingerprint: &str) -> Result<StoredResponse, Error> {
+        if entry.fingerprint != fingerprint {
+            return Err(crate::error::custom(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                format!("the {} has been used for another request", self.header),
+            ));
+        }
+        Ok(entry)
+    }
+}
+
+/// Computes the FNV-1a hash of the method, the path and the body.
+fn default_fingerprint(request: &Request<()>, body: &[u8]) -> String {
+    let hash = request
+        .method()
+        .as_str()
+        .as_bytes()
+        .iter()
+        .chain(&[0])
+        .chain(request.uri().path().as_bytes())
+        .chain(&[0])
+        .chain(body)
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, &b| {
+            (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+        });
+    format!("{:016x}", hash)
+}
+
+/// The request being handled with a key.
+#[derive(Debug, Default)]
+struct Flight {
+    state: Mutex<FlightState>,
+}
+
+#[derive(Debug, Default)]
+struct FlightState {
+    // `Some(None)` if the response has not been stored.
+    result: Option<Option<StoredResponse>>,
+    waiters: Vec<Arc<AtomicTask>>,
+}
+
+impl Flight {
+    fn state(&self) -> MutexGuard<'_, FlightState> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+/// The request calling the handler, which notifies the waiters on completion or drop.
+#[derive(Debug)]
+struct Leader {
+    shared: Arc<Shared>,
+    key: String,
+    fingerprint: String,
+    flight: Arc<Flight>,
+    done: bool,
+}
+
+impl Leader {
+    fn finish(&mut self, entry: Option<StoredResponse>) {
+        if self.done {
+            return;
+        }
+        self.done = true;
+        // The response has already been stored, so the subsequent requests find it.
+        self.shared.inflight().remove(&self.key);
+        let waiters = {
+            let mut state = self.flight.state();
+            state.result = Some(entry);
+            state.waiters.split_off(0)
+        };
+        for waiter in waiters {
+            waiter.notify();
+        }
+    }
+
+    /// Stores the response, and notifies the waiters after it is stored.
+    fn store(mut self, modifier: &Idempotency, entry: StoredResponse) {
+        let mut put = modifier.store.put(&self.key, entry.clone(), modifier.ttl);
+        match put.poll() {
+            Ok(Async::Ready(())) => return self.finish(Some(entry)),
+            Ok(Async::NotReady) => {}
+            Err(err) => {
+                log::warn!(
+                    "failed to store the response for the idempotency key: {}",
+                    err
+                );
+                return self.finish(None);
+            }
+        }
+        // The waiters are notified when the task completes, or is dropped on failure.
+        let task = put.then(move |result| {
+            match result {
+                Ok(()) => self.finish(Some(entry)),
+                Err(err) => {
+                    log::warn!(
+                        "failed to store the response for the idempotency key: {}",
+                        err
+                    );
+                    self.finish(None);
+                }
+            }
+            Ok(())
+        });
+        if let Err(err) = tokio_executor::DefaultExecutor::current().spawn(Box::new(task)) {
+            log::warn!("failed to spawn the task to store the response: {:?}", err);
+        }
+    }
+}
+
+impl Drop for Leader {
+    fn drop(&mut self) {
+        self.finish(None);
+    }
+}
+
+/// A request waiting for the response of the leader, deregistered on drop.
+struct Follower {
+    flight: Arc<Flight>,
+    task: Arc<AtomicTask>,
+}
+
+impl Follower {
+    fn new(flight: Arc<Flight>) -> Self {
+        let task = Arc::new(AtomicTask::new());
+        flight.state().waiters.push(task.clone());
+        Self { flight, task }
+    }
+
+    fn poll_entry(&mut self) -> Async<Option<StoredResponse>> {
+        self.task.register();
+        match self.flight.state().result {
+            Some(ref result) => Async::Ready(result.clone()),
+            None => Async::NotReady,
+        }
+    }
+}
+
+impl Drop for Follower {
+    fn drop(&mut self) {
+        let task = &self.task;
+        self.flight
+            .state()
+            .waiters
+            .retain(|waiter| !Arc::ptr_eq(waiter, task));
+    }
+}
+
+impl<H> ModifyHandler<H> for Idempotency
+where
+    H: Handler,
+    H::Output: Responder,
+{
+    type Output = Idempotent<H::Output>;
+    type Handler = IdempotencyHandler<H>; // private
+
+    fn modify(&self, inner: H) -> Self::Handler {
+        IdempotencyHandler {
+            inner,
+            modifier: self.clone(),
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct IdempotencyHandler<H> {
+    inner: H,
+    modifier: Idempotency,
+}
+
+impl<H> Handler for IdempotencyHandler<H>
+where
+    H: Handler,
+    H::Output: Responder,
+{
+    type Output = Idempotent<H::Output>;
+    type Error = Error;
+    type Handle = HandleIdempotency<H::Handle>; // private
+
+    fn allowed_methods(&self) -> Option<&AllowedMethods> {
+        self.inner.allowed_methods()
+    }
+
+    fn handle(&self) -> Self::Handle {
+        HandleIdempotency {
+            inner: self.inner.handle(),
+            modifier: self.modifier.clone(),
+            state: State::Init,
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct HandleIdempotency<H> {
+    inner: H,
+    modifier: Idempotency,
+    state: State,
+}
+
+enum State {
+    Init,
+    Buffering {
+        key: String,
+        body: BodyStream,
+        buf: BytesMut,
+    },
+    Lookup {
+        lookup: StoreFuture<Option<StoredResponse>>,
+        leader: Leader,
+    },
+    Waiting {
+        follower: Follower,
+        key: String,
+        fingerprint: String,
+    },
+    Running(Option<Leader>),
+    Done,
+}
+
+impl<H> TryFuture for HandleIdempotency<H>
+where
+    H: TryFuture,
+    H::Error: Into<Error>,
+{
+    type Ok = Idempotent<H::Ok>;
+    type Error = Error;
+
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        loop {
+            self.state = match self.state {
+                State::Init => match self.modifier.key(input)? {
+                    Some(key) => match RequestBody::take_from(input.locals) {
+                        Some(body) => State::Buffering {
+                            key,
+                            body: body.into_stream(),
+                            buf: BytesMut::new(),
+                        },
+                        None => {
+                            let fingerprint = (self.modifier.fingerprint)(input.request, &[]);
+                            self.modifier.acquire(key, fingerprint)
+                        }
+                    },
+                    None => State::Running(None),
+                },
+                State::Buffering {
+                    ref key,
+                    ref mut body,
+                    ref mut buf,
+                } => {
+                    let limit = self.modifier.max_request_size;
+                    while let Some(chunk) = futures01::try_ready!(body.poll()) {
+                        if buf.len() + chunk.len() > limit {
+                            self.state = State::Done;
+                            return Err(crate::error::localized(
+                                StatusCode::PAYLOAD_TOO_LARGE,
+                                "error.body.too_large",
+                                Args::new().arg("limit", limit),
+                                format_args!("the body exceeds the size limit (limit: {})", limit),
+                            ));
+                        }
+                        buf.extend_from_slice(&chunk);
+                    }
+                    let body = std::mem::replace(buf, BytesMut::new()).freeze();
+                    let fingerprint = (self.modifier.fingerprint)(input.request, &body);
+                    // hand the buffered body over to the handler.
+                    RequestBody::from(hyper::Body::from(body)).insert_into(input.locals);
+                    self.modifier.acquire(key.clone(), fingerprint)
+                }
+                State::Lookup {
+                    ref mut lookup,
+                    ref mut leader,
+                } => match lookup.poll() {
+                    Ok(Async::Ready(Some(entry))) => {
+                        leader.finish(Some(entry.clone()));
+                        let fingerprint = std::mem::replace(&mut leader.fingerprint, String::new());
+                        self.state = State::Done;
+                        let entry = self.modifier.replay(entry, &fingerprint)?;
+                        return Ok(Async::Ready(Idempotent(Inner::Replay(entry))));
+                    }
+                    Ok(Async::Ready(None)) => {
+                        match std::mem::replace(&mut self.state, State::Done) {
+                            State::Lookup { leader, .. } => State::Running(Some(leader)),
+                            _ => unreachable!(),
+                        }
+                    }
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Err(err) => {
+                        self.state = State::Done;
+                        return Err(crate::error::internal_server_error(err));
+                    }
+                },
+                State::Waiting {
+                    ref mut follower,
+                    ref key,
+                    ref fingerprint,
+                } => match follower.poll_entry() {
+                    Async::Ready(Some(entry)) => {
+                        let replayed = self.modifier.replay(entry, fingerprint);
+                        self.state = State::Done;
+                        return Ok(Async::Ready(Idempotent(Inner::Replay(replayed?))));
+                    }
+                    // The response of the leader has not been stored.
+                    Async::Ready(None) => self.modifier.acquire(key.clone(), fingerprint.clone()),
+                    Async::NotReady => return Ok(Async::NotReady),
+                },
+                State::Running(..) => {
+                    let output =
+                        futures01::try_ready!(self.inner.poll_ready(input).map_err(Into::into));
+                    let leader = match std::mem::replace(&mut self.state, State::Done) {
+                        State::Running(leader) => leader,
+                        _ => unreachable!(),
+                    };
+                    return Ok(Async::Ready(Idempotent(Inner::Run {
+                        inner: output,
+                        modifier: self.modifier.clone(),
+                        leader,
+                    })));
+                }
+                State::Done => panic!("the future has already polled."),
+            };
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Inner<T> {
+    Replay(StoredResponse),
+    Run {
+        inner: T,
+        modifier: Idempotency,
+        leader: Option<Leader>,
+    },
+}
+
+/// A `Responder` which replays the stored response, or stores the response.
+#[derive(Debug)]
+pub struct Idempotent<T>(Inner<T>);
+
+impl<T> Responder for Idempotent<T>
+where
+    T: Responder,
+{
+    type Response = IdempotentResponse<T::Response>;
+    type Error = Error;
+    type Respond = IdempotentRespond<T::Respond>; // private
+
+    fn respond(self) -> Self::Respond {
+        IdempotentRespond(Some(match self.0 {
+            Inner::Replay(entry) => Inner::Replay(entry),
+            Inner::Run {
+                inner,
+                modifier,
+                leader,
+            } => Inner::Run {
+                inner: inner.respond(),
+                modifier,
+                leader,
+            },
+        }))
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct IdempotentRespond<R>(Option<Inner<R>>);
+
+impl<R> TryFuture for IdempotentRespond<R>
+where
+    R: TryFuture,
+{
+    type Ok = IdempotentResponse<R::Ok>;
+    type Error = Error;
+
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        match self.0.take().expect("the future has already been polled.") {
+            Inner::Replay(entry) => Ok(Async::Ready(IdempotentResponse(Inner::Replay(entry)))),
+            Inner::Run {
+                mut inner,
+                modifier,
+                leader,
+            } => match inner.poll_ready(input).map_err(Into::into)? {
+                Async::Ready(response) => Ok(Async::Ready(IdempotentResponse(Inner::Run {
+                    inner: response,
+                    modifier,
+                    leader,
+                }))),
+                Async::NotReady => {
+                    self.0 = Some(Inner::Run {
+                        inner,
+                        modifier,
+                        leader,
+                    });
+                    Ok(Async::NotReady)
+                }
+            },
+        }
+    }
+}
+
+/// An `IntoResponse` which replays the stored response, or stores the response.
+#[derive(Debug)]
+pub struct IdempotentResponse<T>(Inner<T>);
+
+impl<T> IntoResponse for IdempotentResponse<T>
+where
+    T: IntoResponse,
+{
+    type Body = ResponseBody;
+    type Error = Error;
+
+    fn into_response(self, request: &Request<()>) -> Result<Response<Self::Body>, Self::Error> {
+        let (inner, modifier, leader) = match self.0 {
+            Inner::Replay(entry) => return Ok(entry.to_response()),
+            Inner::Run {
+                inner,
+                modifier,
+                leader,
+            } => (inner, modifier, leader),
+        };
+
+        let response = inner
+            .into_response(request)
+            .map_err(Into::into)?
+            .map(Into::<ResponseBody>::into);
+        let leader = match leader {
+            Some(leader) => leader,
+            None => return Ok(response),
+        };
+        if response.status().is_server_error() {
+            return Ok(response);
+        }
+
+        let (mut parts, body) = response.into_parts();
+        let body = match body.try_into_bytes() {
+            Ok(body) if body.len() <= modifier.max_response_size => body,
+            Ok(body) => {
+                parts.headers.append(WARNING, not_stored());
+                return Ok(Response::from_parts(parts, body.into()));
+            }
+            Err(body) => {
+                parts.headers.append(WARNING, not_stored());
+                return Ok(Response::from_parts(parts, body));
+            }
+        };
+        let entry = StoredResponse {
+            fingerprint: leader.fingerprint.clone(),
+            status: parts.status,
+            headers: parts.headers.clone(),
+            body: body.clone(),
+        };
+        leader.store(&modifier, entry);
+        Ok(Response::from_parts(parts, body.into()))
+    }
+}
+
+fn not_stored() -> HeaderValue {
+    HeaderValue::from_static("299 - \"the response is not stored for the idempotency key\"")
+}
@@ -0,0 +1,142 @@
+use {
+    crate::{
+        dynamic::DynamicConfig,
+        error::Error,
+        future::{Poll, TryFuture},
+        handler::{AllowedMethods, Handler, ModifyHandler},
+        input::Input,
+    },
+    http::{
+        header::{HeaderValue, CONTENT_TYPE},
+        Response, StatusCode,
+    },
+    mime::Mime,
+    std::sync::Arc,
+};
+
+/// A `ModifyHandler` that rejects all requests with `503 Service Unavailable`
+/// while the maintenance mode is enabled.
+///
+/// The flag is read on each request, so the maintenance mode can be switched
+/// without restarting the server by reloading the `DynamicConfig`.
+#[derive(Debug, Clone)]
+pub struct MaintenanceMode {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    enabled: DynamicConfig<bool>,
+    content_type: Mime,
+    template: String,
+}
+
+impl MaintenanceMode {
+    /// Creates a `MaintenanceMode` controlled by the specified flag.
+    pub fn new(enabled: DynamicConfig<bool>) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                enabled,
+                content_type: mime::TEXT_PLAIN_UTF_8,
+                template: "the service is under maintenance".into(),
+            }),
+        }
+    }
+
+    /// Sets the template of the response body.
+    ///
+    /// The occurrences of `{path}` in the template are replaced with the path of the request.
+    pub fn template(self, content_type: Mime, template: impl Into<String>) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                enabled: self.inner.enabled.clone(),
+                content_type,
+                template: template.into(),
+            }),
+        }
+    }
+}
+
+impl Inner {
+    fn unavailable(&self, input: &Input<'_>) -> Error {
+        let body = self.template.replace("{path}", input.request.uri().path());
+        crate::error::error_response(
+            Response::builder()
+                .status(StatusCode::SERVICE_UNAVAILABLE)
+                .header(
+                    CONTENT_TYPE,
+                    HeaderValue::from_str(self.content_type.as_ref())
+                        .expect("should be a valid header value"),
+                )
+                .body(body)
+                .expect("should be a valid response"),
+        )
+    }
+}
+
+impl<H> ModifyHandler<H> for MaintenanceMode
+where
+    H: Handler,
+{
+    type Output = H::Output;
+    type Handler = MaintenanceModeHandler<H>; // private
+
+    fn modify(&self, inner: H) -> Self::Handler {
+        MaintenanceModeHandler {
+            inner,
+            modifier: self.inner.clone(),
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct MaintenanceModeHandler<H> {
+    inner: H,
+    modifier: Arc<Inner>,
+}
+
+impl<H> Handler for MaintenanceModeHandler<H>
+where
+    H: Handler,
+{
+    type Output = H::Output;
+    type Error = Error;
+    type Handle = HandleMaintenanceMode<H::Handle>; // private
+
+    fn allowed_methods(&self) -> Option<&AllowedMethods> {
+        self.inner.allowed_methods()
+    }
+
+    fn handle(&self) -> Self::Handle {
+        HandleMaintenanceMode {
+            inner: self.inner.handle(),
+            modifier: self.modifier.clone(),
+            checked: false,
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct HandleMaintenanceMode<H> {
+    inner: H,
+    modifier: Arc<Inner>,
+    checked: bool,
+}
+
+impl<H> TryFuture for HandleMaintenanceMode<H>
+where
+    H: TryFuture,
+{
+    type Ok = H::Ok;
+    type Error = Error;
+
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        if !self.checked {
+            self.checked = true;
+            if *self.modifier.enabled.get() {
+                return Err(self.modifier.unavailable(input));
+            }
+        }
+        self.inner.poll_ready(input).map_err(Into::into)
+    }
+}
@@ -0,0 +1,291 @@
+use {
+    crate::{
+        error::Error,
+        future::{Async, Poll, TryFuture},
+        handler::{AllowedMethods, Handler, ModifyHandler},
+        input::Input,
+        rt::Delay,
+    },
+    futures01::{task::AtomicTask, Future},
+    http::{header::RETRY_AFTER, Response, StatusCode},
+    std::{
+        collections::VecDeque,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc, Mutex, MutexGuard,
+        },
+        time::Duration,
+    },
+};
+
+/// A `ModifyHandler` that limits the number of the requests processed concurrently.
+///
+/// The requests beyond the concurrency wait in a FIFO queue until a running one
+/// completes. If the queue is full, or the request has waited longer than
+/// `max_wait`, it is rejected with `503 Service Unavailable` and `Retry-After`.
+/// A waiting request that is dropped, for example because the client has
+/// disconnected, is removed from the queue immediately.
+///
+/// The limit is shared among all handlers modified by the clones of this value,
+/// so applying it to a scope limits the requests to the whole scope.
+#[derive(Debug, Clone)]
+pub struct QueueLimit {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    concurrency: usize,
+    queue_depth: usize,
+    max_wait: Duration,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    running: usize,
+    queue: VecDeque<Arc<Waiter>>,
+}
+
+#[derive(Debug, Default)]
+struct Waiter {
+    granted: AtomicBool,
+    task: AtomicTask,
+}
+
+impl QueueLimit {
+    /// Creates a `QueueLimit` with the specified configuration.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `concurrency` is zero.
+    pub fn new(concurrency: usize, queue_depth: usize, max_wait: Duration) -> Self {
+        assert!(concurrency > 0, "the concurrency must be positive");
+        Self {
+            inner: Arc::new(Inner {
+                concurrency,
+                queue_depth,
+                max_wait,
+                state: Mutex::default(),
+            }),
+        }
+    }
+
+    /// Returns the number of the requests being processed.
+    pub fn running(&self) -> usize {
+        self.inner.state().running
+    }
+
+    /// Returns the number of the requests waiting in the queue.
+    pub fn queued(&self) -> usize {
+        self.inner.state().queue.len()
+    }
+}
+
+impl Inner {
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn acquire(self: &Arc<Self>) -> Option<Acquire> {
+        let mut state = self.state();
+        if state.running < self.concurrency {
+            state.running += 1;
+            Some(Acquire::Permit(Permit(self.clone())))
+        } else if state.queue.len() < self.queue_depth {
+            let waiter = Arc::new(Waiter::default());
+            state.queue.push_back(waiter.clone());
+            Some(Acquire::Wait(Ticket {
+                inner: self.clone(),
+                waiter: Some(waiter),
+            }))
+        } else {
+            None
+        }
+    }
+
+    /// Hands over the slot of a completed request to the oldest waiter, or frees it.
+    fn release(&self) {
+        let mut state = self.state();
+        match state.queue.pop_front() {
+            Some(waiter) => {
+                waiter.granted.store(true, Ordering::SeqCst);
+                waiter.task.notify();
+            }
+            None => state.running -= 1,
+        }
+    }
+
+    fn overloaded(&self) -> Error {
+        let retry_after = std::cmp::max(
+            1,
+            self.max_wait.as_secs() + u64::from(self.max_wait.subsec_nanos() > 0),
+        );
+        crate::error::error_response(
+            Response::builder()
+                .status(StatusCode::SERVICE_UNAVAILABLE)
+                .header(RETRY_AFTER, retry_after)
+                .body("the server is overloaded")
+                .expect("should be a valid response"),
+        )
+    }
+}
+
+enum Acquire {
+    Permit(Permit),
+    Wait(Ticket),
+}
+
+/// The slot of a running request, released on drop.
+struct Permit(Arc<Inner>);
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.0.release();
+    }
+}
+
+/// The position of a waiting request in the queue, removed on drop.
+struct Ticket {
+    inner: Arc<Inner>,
+    waiter: Option<Arc<Waiter>>,
+}
+
+impl Ticket {
+    fn poll_granted(&mut self, deadline: &mut Delay) -> Poll<Permit, ()> {
+        let waiter = self
+            .waiter
+            .as_ref()
+            .expect("the ticket has already been used");
+        waiter.task.register();
+        if waiter.granted.load(Ordering::SeqCst) {
+            return Ok(Async::Ready(self.take_permit()));
+        }
+
+        match deadline.poll() {
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            // The slot handed over after the deadline is released when the ticket is dropped.
+            Ok(Async::Ready(())) | Err(..) => Err(()),
+        }
+    }
+
+    fn take_permit(&mut self) -> Permit {
+        self.waiter = None;
+        Permit(self.inner.clone())
+    }
+}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        if let Some(waiter) = self.waiter.take() {
+            let mut state = self.inner.state();
+            if waiter.granted.load(Ordering::SeqCst) {
+                drop(state);
+                self.inner.release();
+            } else {
+                state.queue.retain(|w| !Arc::ptr_eq(w, &waiter));
+            }
+        }
+    }
+}
+
+impl<H> ModifyHandler<H> for QueueLimit
+where
+    H: Handler,
+{
+    type Output = H::Output;
+    type Handler = QueueLimitHandler<H>; // private
+
+    fn modify(&self, inner: H) -> Self::Handler {
+        QueueLimitHandler {
+            inner,
+            modifier: self.clone(),
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct QueueLimitHandler<H> {
+    inner: H,
+    modifier: QueueLimit,
+}
+
+impl<H> Handler for QueueLimitHandler<H>
+where
+    H: Handler,
+{
+    type Output = H::Output;
+    type Error = Error;
+    type Handle = HandleQueueLimit<H::Handle>; // private
+
+    fn allowed_methods(&self) -> Option<&AllowedMethods> {
+        self.inner.allowed_methods()
+    }
+
+    fn handle(&self) -> Self::Handle {
+        HandleQueueLimit {
+            inner: self.inner.handle(),
+            limit: self.modifier.inner.clone(),
+            state: HandleState::Init,
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct HandleQueueLimit<H> {
+    inner: H,
+    limit: Arc<Inner>,
+    state: HandleState,
+}
+
+enum HandleState {
+    Init,
+    Waiting(Ticket, Delay),
+    // The permit is held until the handler completes.
+    Running { _permit: Permit },
+    Done,
+}
+
+impl<H> TryFuture for HandleQueueLimit<H>
+where
+    H: TryFuture,
+{
+    type Ok = H::Ok;
+    type Error = Error;
+
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        loop {
+            self.state = match self.state {
+                HandleState::Init => match self.limit.acquire() {
+                    Some(Acquire::Permit(permit)) => HandleState::Running { _permit: permit },
+                    Some(Acquire::Wait(ticket)) => {
+                        let deadline = input.clock().now() + self.limit.max_wait;
+                        HandleState::Waiting(ticket, input.clock().delay(deadline))
+                    }
+                    None => return Err(self.limit.overloaded()),
+                },
+                HandleState::Waiting(ref mut ticket, ref mut deadline) => {
+                    match ticket.poll_granted(deadline) {
+                        Ok(Async::Ready(permit)) => HandleState::Running { _permit: permit },
+                        Ok(Async::NotReady) => return Ok(Async::NotReady),
+                        Err(()) => {
+                            // leave the queue immediately rather than when the handle is dropped.
+                            self.state = HandleState::Done;
+                            return Err(self.limit.overloaded());
+                        }
+                    }
+                }
+                HandleState::Running { .. } => {
+                    let polled = self.inner.poll_ready(input).map_err(Into::into);
+                    if let Ok(Async::NotReady) = polled {
+                        return Ok(Async::NotReady);
+                    }
+                    // release the slot as soon as the handler completes.
+                    self.state = HandleState::Done;
+                    return polled;
+                }
+                HandleState::Done => panic!("the future has already polled."),
+            };
+        }
+    }
+}
@@ -0,0 +1,793 @@
+use {
+    crate::{
+        cache::MemoryCache,
+        error::Error,
+        future::{Async, Poll, TryFuture},
+        handler::{AllowedMethods, Handler, ModifyHandler},
+        input::{
+            body::RequestBody,
+            localmap::{local_key, LocalData, LocalMap},
+            Input,
+        },
+        output::{IntoResponse, ResponseBody},
+        responder::Responder,
+        rt::Delay,
+    },
+    bytes::Bytes,
+    futures01::{task::AtomicTask, Future},
+    http::{header::RETRY_AFTER, HeaderMap, Method, Request, Response, StatusCode},
+    std::{
+        collections::{HashMap, HashSet},
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex, MutexGuard,
+        },
+        time::Duration,
+    },
+    tokio_executor::Executor,
+};
+
+/// A `ModifyHandler` that caches the responses to `GET` requests in a `MemoryCache`.
+///
+/// The responses are stored under the keys of the form `path?query`, and only
+/// the successful (`2xx`) responses whose bodies are buffered in memory are cached.
+/// On a hit, the stored response is returned without calling the inner handler.
+///
+/// The routes which modify the resources can declare the keys they invalidate
+/// with `invalidates`. After a successful response to a request with a method
+/// other than `GET`, `HEAD`, `OPTIONS` and `TRACE`, the entries matching the
+/// patterns are purged from the cache:
+///
+/// ```
+/// # use tsukuyomi::{cache::MemoryCache, config::prelude::*, modifiers::ResponseCache, App};
+/// let cache = ResponseCache::new(MemoryCache::builder().capacity(1024).build());
+///
+/// let app = App::create(chain![
+///     path!("/posts")
+///         .to(endpoint::get().reply("posts"))
+///         .modify(cache.clone()),
+///     path!("/posts/:id")
+///         .to(chain![
+///             endpoint::get().call(|id: u32| format!("post {}", id)),
+///             endpoint::put().call(|id: u32| format!("updated {}", id)),
+///         ])
+///         .modify(cache.invalidates(&["/posts/:id", "/posts"])),
+/// ]);
+/// # drop(app);
+/// ```
+///
+/// # Coalescing
+///
+/// With `coalesce`, the concurrent misses of the same key are coalesced: the
+/// first one calls the inner handler, and the others wait for its response
+/// instead of calling the handler by themselves. A waiting request gives up
+/// after the specified duration, and then calls the handler or is rejected with
+/// `503 Service Unavailable`, according to `CoalesceTimeout`. If the response
+/// is not cached, for example because the handler has failed or the client has
+/// disconnected, the waiting requests call the handler by themselves. A waiting
+/// request that is dropped stops waiting immediately.
+///
+/// With `stale_while_revalidate`, an entry expired within the specified window
+/// is returned as it is, and a single request to refresh the entry is dispatched
+/// in the background. The refreshing request is processed through the
+/// application from the routing, with the same URI and header fields as the
+/// request that found the expired entry.
+#[derive(Debug, Clone)]
+pub struct ResponseCache {
+    cache: MemoryCache<String, CacheEntry>,
+    query_match: QueryMatch,
+    invalidates: Arc<Vec<String>>,
+    coalesce: Option<(Duration, CoalesceTimeout)>,
+    stale_while_revalidate: Option<Duration>,
+    shared: Arc<Shared>,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    inflight: Mutex<HashMap<String, Arc<Flight>>>,
+    refreshing: Mutex<HashSet<String>>,
+    coalesced: AtomicUsize,
+    timeouts: AtomicUsize,
+    stale_served: AtomicUsize,
+    revalidated: AtomicUsize,
+}
+
+impl Shared {
+    fn inflight(&self) -> MutexGuard<'_, HashMap<String, Arc<Flight>>> {
+        self.inflight.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn refreshing(&self) -> MutexGuard<'_, HashSet<String>> {
+        self.refreshing
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl ResponseCache {
+    /// Creates a `ResponseCache` backed by the specified cache.
+    ///
+    /// The capacity and the TTL of the responses are configured by the builder
+    /// of the cache. The clones of a `ResponseCache` share the cache.
+    pub fn new(cache: MemoryCache<String, CacheEntry>) -> Self {
+        Self {
+            cache,
+            query_match: QueryMatch::Ignore,
+            invalidates: Arc::new(vec![]),
+            coalesce: None,
+            stale_while_revalidate: None,
+            shared: Arc::default(),
+        }
+    }
+
+    /// Enables the coalescing of the concurrent misses of the same key.
+    ///
+    /// The waiting requests give up after `max_wait`, and then are handled
+    /// according to `on_timeout`.
+    pub fn coalesce(self, max_wait: Duration, on_timeout: CoalesceTimeout) -> Self {
+        Self {
+            coalesce: Some((max_wait, on_timeout)),
+            ..self
+        }
+    }
+
+    /// Enables returning the entries expired within `window`, while refreshing
+    /// them in the background.
+    pub fn stale_while_revalidate(self, window: Duration) -> Self {
+        Self {
+            stale_while_revalidate: Some(window),
+            ..self
+        }
+    }
+
+    /// Returns a snapshot of the counters of the coalesced requests and the stale responses.
+    ///
+    /// The clones of this value share the counters.
+    pub fn metrics(&self) -> ResponseCacheMetrics {
+        let shared = &*self.shared;
+        ResponseCacheMetrics {
+            coalesced: shared.coalesced.load(Ordering::Relaxed) as u64,
+            timeouts: shared.timeouts.load(Ordering::Relaxed) as u64,
+            stale_served: shared.stale_served.load(Ordering::Relaxed) as u64,
+            revalidated: shared.revalidated.load(Ordering::Relaxed) as u64,
+        }
+    }
+
+    /// Sets how the invalidation patterns are matched against the query
+    /// component of the cache keys.
+    ///
+    /// The default value is `QueryMatch::Ignore`.
+    pub fn query_match(self, query_match: QueryMatch) -> Self {
+        Self {
+            query_match,
+            ..self
+        }
+    }
+
+    /// Returns a `ResponseCache` sharing the cache, which purges the entries
+    /// matching the specified patterns after the successful mutations.
+    ///
+    /// The segments of the form `:name` or `*` in the patterns are replaced with
+    /// the values of the parameters captured from the path of the mutating request.
+    pub fn invalidates(&self, patterns: &[&str]) -> Self {
+        Self {
+            invalidates: Arc::new(patterns.iter().map(|&pattern| pattern.to_owned()).collect()),
+            ..self.clone()
+        }
+    }
+
+    /// Returns the underlying cache.
+    pub fn cache(&self) -> &MemoryCache<String, CacheEntry> {
+        &self.cache
+    }
+
+    fn lookup(&self, input: &mut Input<'_>) -> Lookup {
+        let key = input
+            .request
+            .uri()
+            .path_and_query()
+            .map_or("/", |path_and_query| path_and_query.as_str())
+            .to_owned();
+        if input.locals.contains_key(&Revalidation::KEY) {
+            return Lookup::Run(Action::Revalidate(key));
+        }
+
+        match self.stale_while_revalidate {
+            Some(window) => match self.cache.get_or_stale(&key, window) {
+                Some((entry, false)) => return Lookup::Hit(entry),
+                Some((entry, true)) => {
+                    self.shared.stale_served.fetch_add(1, Ordering::Relaxed);
+                    self.revalidate(&key, input);
+                    return Lookup::Hit(entry);
+                }
+                None => {}
+            },
+            None => {
+                if let Some(entry) = self.cache.get(&key) {
+                    return Lookup::Hit(entry);
+                }
+            }
+        }
+
+        if self.coalesce.is_none() {
+            return Lookup::Run(Action::Store(key, None));
+        }
+        let mut inflight = self.shared.inflight();
+        match inflight.get(&key) {
+            Some(flight) => {
+                self.shared.coalesced.fetch_add(1, Ordering::Relaxed);
+                Lookup::Wait(key, Follower::new(flight.clone()))
+            }
+            None => {
+                let flight = Arc::new(Flight::default());
+                inflight.insert(key.clone(), flight.clone());
+                let leader = Leader {
+                    shared: self.shared.clone(),
+                    key: key.clone(),
+                    flight,
+                    done: false,
+                };
+                Lookup::Run(Action::Store(key, Some(leader)))
+            }
+        }
+    }
+
+    /// Dispatches the request to refresh the entry in the background, unless
+    /// another one is in progress.
+    fn revalidate(&self, key: &str, input: &mut Input<'_>) {
+        if !self.shared.refreshing().insert(key.to_owned()) {
+            return;
+        }
+
+        let mut request = Request::new(RequestBody::from(hyper::Body::empty()));
+        *request.uri_mut() = input.request.uri().clone();
+        *request.version_mut() = input.request.version();
+        *request.headers_mut() = input.request.headers().clone();
+
+        let mut locals = LocalMap::default();
+        Revalidation(()).insert_into(&mut locals);
+
+        let spawned = input.dispatch.dispatch(request, locals).map(|future| {
+            let shared = self.shared.clone();
+            let key = key.to_owned();
+            // The entry is updated before the response body is produced.
+            let task = future.then(move |_| {
+                shared.refreshing().remove(&key);
+                Ok(())
+            });
+            tokio_executor::DefaultExecutor::current().spawn(Box::new(task))
+        });
+        if let Some(Ok(())) = spawned {
+            return;
+        }
+        log::warn!(
+            "failed to dispatch the request to refresh the cache entry {:?}",
+            key
+        );
+        self.shared.refreshing().remove(key);
+    }
+
+    fn unavailable(&self, max_wait: Duration) -> Error {
+        let retry_after = std::cmp::max(
+            1,
+            max_wait.as_secs() + u64::from(max_wait.subsec_nanos() > 0),
+        );
+        crate::error::error_response(
+            Response::builder()
+                .status(StatusCode::SERVICE_UNAVAILABLE)
+                .header(RETRY_AFTER, retry_after)
+                .body("the response is being generated by another request")
+                .expect("should be a valid response"),
+        )
+    }
+
+    /// Replaces the parameters in the patterns with the captured values.
+    fn render_patterns(&self, input: &Input<'_>) -> Result<Vec<String>, Error> {
+        self.invalidates
+            .iter()
+            .map(|pattern| {
+                let rendered: Result<Vec<&str>, Error> = pattern
+                    .split('/')
+                    .map(|segment| {
+                        let name = match segment.as_bytes().first() {
+                            Some(b':') => &segment[1..],
+                            Some(b'*') => "*",
+                            _ => return Ok(segment),
+                        };
+                        input
+                            .params
+                            .as_ref()
+                            .and_then(|params| params.name(name))
+                            .ok_or_else(|| {
+                                crate::error::internal_server_error(format!(
+                                    "the parameter `{}' in the invalidation pattern is not captured",
+                                    name
+                                ))
+                            })
+                    })
+                    .collect();
+                rendered.map(|segments| segments.join("/"))
+            })
+            .collect()
+    }
+
+    fn purge(&self, patterns: &[String]) {
+        let query_match = self.query_match;
+        self.cache.retain(|key, _| {
+            !patterns
+                .iter()
+                .any(|pattern| query_match.matches(pattern, key))
+        });
+    }
+}
+
+/// The behavior of a coalesced request that has waited longer than the limit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CoalesceTimeout {
+    /// Calls the inner handler without waiting any more.
+    Compute,
+
+    /// Rejects the request with `503 Service Unavailable` and `Retry-After`.
+    Unavailable,
+}
+
+/// A snapshot of the counters of a `ResponseCache`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ResponseCacheMetrics {
+    /// The number of the requests which have waited for the response of another request.
+    pub coalesced: u64,
+
+    /// The number of the coalesced requests which have given up waiting.
+    pub timeouts: u64,
+
+    /// The number of the expired entries returned by `stale_while_revalidate`.
+    pub stale_served: u64,
+
+    /// The number of the entries refreshed in the background.
+    pub revalidated: u64,
+}
+
+/// The marker of the requests refreshing the expired entries.
+#[derive(Debug, Clone)]
+struct Revalidation(());
+
+impl LocalData for Revalidation {
+    local_key! {
+        const KEY: Self;
+    }
+}
+
+/// The response being generated by the request that has missed first.
+#[derive(Debug, Default)]
+struct Flight {
+    state: Mutex<FlightState>,
+}
+
+#[derive(Debug, Default)]
+struct FlightState {
+    // `Some(None)` if the response has not been cached.
+    result: Option<Option<CacheEntry>>,
+    waiters: Vec<Arc<AtomicTask>>,
+}
+
+impl Flight {
+    fn state(&self) -> MutexGuard<'_, FlightState> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+/// The request generating the response, which notifies the waiters on completion or drop.
+#[derive(Debug)]
+struct Leader {
+    shared: Arc<Shared>,
+    key: String,
+    flight: Arc<Flight>,
+    done: bool,
+}
+
+impl Leader {
+    fn finish(&mut self, entry: Option<CacheEntry>) {
+        if self.done {
+            return;
+        }
+        self.done = true;
+        // The entry has already been inserted, so the subsequent requests find it.
+        self.shared.inflight().remove(&self.key);
+        let waiters = {
+            let mut state = self.flight.state();
+            state.result = Some(entry);
+            state.waiters.split_off(0)
+        };
+        for waiter in waiters {
+            waiter.notify();
+        }
+    }
+}
+
+impl Drop for Leader {
+    fn drop(&mut self) {
+        self.finish(None);
+    }
+}
+
+/// A request waiting for the response of the leader, deregistered on drop.
+struct Follower {
+    flight: Arc<Flight>,
+    task: Arc<AtomicTask>,
+}
+
+impl Follower {
+    fn new(flight: Arc<Flight>) -> Self {
+        let task = Arc::new(AtomicTask::new());
+        flight.state().waiters.push(task.clone());
+        Self { flight, task }
+    }
+
+    fn poll_entry(&mut self, deadline: &mut Delay) -> Poll<Option<CacheEntry>, ()> {
+        self.task.register();
+        if let Some(ref result) = self.flight.state().result {
+            return Ok(Async::Ready(result.clone()));
+        }
+        match deadline.poll() {
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Ok(Async::Ready(())) | Err(..) => Err(()),
+        }
+    }
+}
+
+impl Drop for Follower {
+    fn drop(&mut self) {
+        let task = &self.task;
+        self.flight
+            .state()
+            .waiters
+            .retain(|waiter| !Arc::ptr_eq(waiter, task));
+    }
+}
+
+/// The rule for matching the query component of the cache keys with the invalidation patterns.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QueryMatch {
+    /// The query components are ignored, so that a pattern purges the entries
+    /// of the path regardless of their queries.
+    Ignore,
+
+    /// The keys must be equal to the patterns including the query components.
+    Exact,
+}
+
+impl QueryMatch {
+    fn matches(self, pattern: &str, key: &str) -> bool {
+        match self {
+            QueryMatch::Ignore => strip_query(pattern) == strip_query(key),
+            QueryMatch::Exact => pattern == key,
+        }
+    }
+}
+
+fn strip_query(s: &str) -> &str {
+    s.split('?').next().unwrap_or(s)
+}
+
+/// A response stored in `ResponseCache`.
+#[derive(Debug, Clone)]
+pub struct CacheEntry {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl CacheEntry {
+    fn to_response(&self) -> Response<ResponseBody> {
+        let mut response = Response::new(self.body.clone().into());
+        *response.status_mut() = self.status;
+        *response.headers_mut() = self.headers.clone();
+        response
+    }
+}
+
+impl<H> ModifyHandler<H> for ResponseCache
+where
+    H: Handler,
+    H::Output: Responder,
+{
+    type Output = Cached<H::Output>;
+    type Handler = ResponseCacheHandler<H>; // private
+
+    fn modify(&self, inner: H) -> Self::Handler {
+        ResponseCacheHandler {
+            inner,
+            modifier: self.clone(),
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct ResponseCacheHandler<H> {
+    inner: H,
+    modifier: ResponseCache,
+}
+
+impl<H> Handler for ResponseCacheHandler<H>
+where
+    H: Handler,
+    H::Output: Responder,
+{
+    type Output = Cached<H::Output>;
+    type Error = Error;
+    type Handle = HandleResponseCache<H::Handle>; // private
+
+    fn allowed_methods(&self) -> Option<&AllowedMethods> {
+        self.inner.allowed_methods()
+    }
+
+    fn handle(&self) -> Self::Handle {
+        HandleResponseCache {
+            inner: self.inner.handle(),
+            modifier: self.modifier.clone(),
+            state: HandleState::Init,
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct HandleResponseCache<H> {
+    inner: H,
+    modifier: ResponseCache,
+    state: HandleState,
+}
+
+enum HandleState {
+    Init,
+    Waiting {
+        key: String,
+        follower: Follower,
+        deadline: Delay,
+    },
+    Running(Action),
+    Done,
+}
+
+enum Lookup {
+    Hit(CacheEntry),
+    Wait(String, Follower),
+    Run(Action),
+}
+
+impl<H> TryFuture for HandleResponseCache<H>
+where
+    H: TryFuture,
+{
+    type Ok = Cached<H::Ok>;
+    type Error = Error;
+
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        loop {
+            self.state = match self.state {
+                HandleState::Init => match *input.request.method() {
+                    Method::GET => match self.modifier.lookup(input) {
+                        Lookup::Hit(entry) => {
+                            self.state = HandleState::Done;
+                            return Ok(Async::Ready(Cached(Inner::Hit(entry))));
+                        }
+                        Lookup::Wait(key, follower) => {
+                            let (max_wait, _) = self
+                                .modifier
+                                .coalesce
+                                .expect("coalescing should be enabled");
+                            let deadline = input.clock().now() + max_wait;
+                            HandleState::Waiting {
+                                key,
+                                follower,
+                                deadline: input.clock().delay(deadline),
+                            }
+                        }
+                        Lookup::Run(action) => HandleState::Running(action),
+                    },
+                    Method::HEAD | Method::OPTIONS | Method::TRACE => {
+                        HandleState::Running(Action::Pass)
+                    }
+                    _ if self.modifier.invalidates.is_empty() => HandleState::Running(Action::Pass),
+                    _ => HandleState::Running(Action::Purge(self.modifier.render_patterns(input)?)),
+                },
+                HandleState::Waiting {
+                    ref key,
+                    ref mut follower,
+                    ref mut deadline,
+                } => match follower.poll_entry(deadline) {
+                    Ok(Async::Ready(Some(entry))) => {
+                        self.state = HandleState::Done;
+                        return Ok(Async::Ready(Cached(Inner::Hit(entry))));
+                    }
+                    // The leader has not cached the response.
+                    Ok(Async::Ready(None)) => {
+                        HandleState::Running(Action::Store(key.clone(), None))
+                    }
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Err(()) => {
+                        self.modifier
+                            .shared
+                            .timeouts
+                            .fetch_add(1, Ordering::Relaxed);
+                        let (max_wait, on_timeout) = self
+                            .modifier
+                            .coalesce
+                            .expect("coalescing should be enabled");
+                        match on_timeout {
+                            CoalesceTimeout::Compute => {
+                                HandleState::Running(Action::Store(key.clone(), None))
+                            }
+                            CoalesceTimeout::Unavailable => {
+                                // stop waiting immediately rather than when the handle is dropped.
+                                self.state = HandleState::Done;
+                                return Err(self.modifier.unavailable(max_wait));
+                            }
+                        }
+                    }
+                },
+                HandleState::Running(..) => {
+                    let output =
+                        futures01::try_ready!(self.inner.poll_ready(input).map_err(Into::into));
+                    let action = match std::mem::replace(&mut self.state, HandleState::Done) {
+                        HandleState::Running(action) => action,
+                        _ => unreachable!(),
+                    };
+                    return Ok(Async::Ready(Cached(Inner::Miss {
+                        inner: output,
+                        modifier: self.modifier.clone(),
+                        action,
+                    })));
+                }
+                HandleState::Done => panic!("the future has already polled."),
+            };
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Action {
+    Pass,
+    Store(String, Option<Leader>),
+    Revalidate(String),
+    Purge(Vec<String>),
+}
+
+#[derive(Debug)]
+enum Inner<T> {
+    Hit(CacheEntry),
+    Miss {
+        inner: T,
+        modifier: ResponseCache,
+        action: Action,
+    },
+}
+
+/// A `Responder` which returns the cached response, or updates the cache with the response.
+#[derive(Debug)]
+pub struct Cached<T>(Inner<T>);
+
+impl<T> Responder for Cached<T>
+where
+    T: Responder,
+{
+    type Response = CachedResponse<T::Response>;
+    type Error = Error;
+    type Respond = CachedRespond<T::Respond>; // private
+
+    fn respond(self) -> Self::Respond {
+        CachedRespond(Some(match self.0 {
+            Inner::Hit(entry) => Inner::Hit(entry),
+            Inner::Miss {
+                inner,
+                modifier,
+                action,
+            } => Inner::Miss {
+                inner: inner.respond(),
+                modifier,
+                action,
+            },
+        }))
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct CachedRespond<R>(Option<Inner<R>>);
+
+impl<R> TryFuture for CachedRespond<R>
+where
+    R: TryFuture,
+{
+    type Ok = CachedResponse<R::Ok>;
+    type Error = Error;
+
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        match self.0.take().expect("the future has already been polled.") {
+            Inner::Hit(entry) => Ok(Async::Ready(CachedResponse(Inner::Hit(entry)))),
+            Inner::Miss {
+                mut inner,
+                modifier,
+                action,
+            } => match inner.poll_ready(input).map_err(Into::into)? {
+                Async::Ready(response) => Ok(Async::Ready(CachedResponse(Inner::Miss {
+                    inner: response,
+                    modifier,
+                    action,
+                }))),
+                Async::NotReady => {
+                    self.0 = Some(Inner::Miss {
+                        inner,
+                        modifier,
+                        action,
+                    });
+                    Ok(Async::NotReady)
+                }
+            },
+        }
+    }
+}
+
+/// An `IntoResponse` which returns the cached response, or updates the cache with the response.
+#[derive(Debug)]
+pub struct CachedResponse<T>(Inner<T>);
+
+impl<T> IntoResponse for CachedResponse<T>
+where
+    T: IntoResponse,
+{
+    type Body = ResponseBody;
+    type Error = Error;
+
+    fn into_response(self, request: &Request<()>) -> Result<Response<Self::Body>, Self::Error> {
+        let (inner, modifier, action) = match self.0 {
+            Inner::Hit(entry) => return Ok(entry.to_response()),
+            Inner::Miss {
+                inner,
+                modifier,
+                action,
+            } => (inner, modifier, action),
+        };
+
+        let response = inner
+            .into_response(request)
+            .map_err(Into::into)?
+            .map(Into::<ResponseBody>::into);
+        if !response.status().is_success() {
+            return Ok(response);
+        }
+
+        match action {
+            Action::Pass => Ok(response),
+            Action::Purge(patterns) => {
+                modifier.purge(&patterns);
+                Ok(response)
+            }
+            Action::Store(..) | Action::Revalidate(..) => {
+                let (parts, body) = response.into_parts();
+                let body = match body.try_into_bytes() {
+                    Ok(body) => body,
+                    Err(body) => return Ok(Response::from_parts(parts, body)),
+                };
+                let entry = CacheEntry {
+                    status: parts.status,
+                    headers: parts.headers,
+                    body,
+                };
+                let response = entry.to_response();
+                match action {
+                    Action::Store(key, leader) => {
+                        modifier.cache.insert(key, entry.clone());
+                        if let Some(mut leader) = leader {
+                            leader.finish(Some(entry));
+                        }
+                    }
+                    Action::Revalidate(key) => {
+                        modifier.cache.insert(key, entry);
+                        modifier.shared.revalidated.fetch_add(1, Ordering::Relaxed);
+                    }
+                    _ => unreachable!(),
+                }
+                Ok(response)
+            }
+        }
+    }
+}
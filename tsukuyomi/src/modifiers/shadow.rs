@@ -0,0 +1,538 @@
+use {
+    crate::{
+        app::DispatchFuture,
+        error::Error,
+        future::{Async, Poll, TryFuture},
+        handler::{AllowedMethods, Handler, ModifyHandler},
+        input::{
+            body::{BodyStream, RequestBody},
+            localmap::{local_key, LocalData, LocalMap},
+            Input,
+        },
+        output::{IntoResponse, ResponseBody},
+        responder::Responder,
+        rt::Clock,
+    },
+    bytes::Bytes,
+    futures01::{future, stream::Concat2, Future, Stream},
+    http::{Method, Request, Response, StatusCode, Uri},
+    hyper::body::Payload,
+    std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        time::Duration,
+    },
+    tokio_executor::Executor,
+};
+
+/// A `ModifyHandler` that mirrors a sample of the requests to another route in
+/// the application, so that a new implementation can be compared with the
+/// current one against the production traffic.
+///
+/// The mirrored request has the same method, header fields and body as the
+/// original one, and its path is prefixed with the target: with the target
+/// `/shadow`, the request to `/users/42?full=1` is mirrored to
+/// `/shadow/users/42?full=1`. It is dispatched in the background after the
+/// primary response is produced, so the client never waits for it. The response
+/// of the shadow route is discarded after its status code is compared with the
+/// primary one.
+///
+/// The request body is duplicated only if its length is known in advance and
+/// does not exceed `max_body_size`, because it has to be buffered before the
+/// handler reads it. The other requests with a body, such as chunked uploads,
+/// are not mirrored.
+///
+/// The mirrored requests are processed by the route as usual, so their side
+/// effects happen twice. The routes with side effects should be mirrored to
+/// the shadow routes that are safe to call.
+#[derive(Debug, Clone)]
+pub struct Shadow {
+    prefix: Arc<str>,
+    sample_rate: f64,
+    max_body_size: u64,
+    log_divergence: bool,
+    stats: Arc<Stats>,
+}
+
+#[derive(Debug, Default)]
+struct Stats {
+    mirrored: AtomicUsize,
+    skipped: AtomicUsize,
+    matched: AtomicUsize,
+    diverged: AtomicUsize,
+    failed: AtomicUsize,
+    latency: Mutex<Duration>,
+}
+
+impl Shadow {
+    /// Creates a `Shadow` that mirrors the requests to the paths prefixed with `target`.
+    ///
+    /// This function returns an error if `target` is not an absolute path.
+    pub fn new(target: impl Into<String>) -> crate::config::Result<Self> {
+        let target = target.into();
+        if !target.starts_with('/') {
+            return Err(crate::config::Error::custom(failure::format_err!(
+                "the shadow target must be an absolute path: {:?}",
+                target
+            )));
+        }
+        target
+            .parse::<Uri>()
+            .map_err(crate::config::Error::custom)?;
+
+        Ok(Self {
+            prefix: target.trim_end_matches('/').into(),
+            sample_rate: 1.0,
+            max_body_size: 64 * 1024,
+            log_divergence: false,
+            stats: Arc::default(),
+        })
+    }
+
+    /// Sets the ratio of the requests to be mirrored, from `0.0` to `1.0`.
+    ///
+    /// The requests are sampled by using `Input::random`.
+    /// The default value is `1.0`.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `sample_rate` is out of the range.
+    pub fn sample_rate(self, sample_rate: f64) -> Self {
+        assert!(
+            sample_rate >= 0.0 && sample_rate <= 1.0,
+            "the sample rate must be between 0.0 and 1.0"
+        );
+        Self {
+            sample_rate,
+            ..self
+        }
+    }
+
+    /// Sets the maximum size of the request bodies to be duplicated.
+    ///
+    /// The default value is 64 KiB.
+    pub fn max_body_size(self, max_body_size: u64) -> Self {
+        Self {
+            max_body_size,
+            ..self
+        }
+    }
+
+    /// Sets whether to emit a warning when the status code of the shadow response
+    /// differs from the primary one.
+    ///
+    /// The default value is `false`.
+    pub fn log_divergence(self, enabled: bool) -> Self {
+        Self {
+            log_divergence: enabled,
+            ..self
+        }
+    }
+
+    /// Returns a snapshot of the results of the mirrored requests.
+    ///
+    /// The clones of this value share the results.
+    pub fn metrics(&self) -> ShadowMetrics {
+        let stats = &*self.stats;
+        ShadowMetrics {
+            mirrored: stats.mirrored.load(Ordering::Relaxed) as u64,
+            skipped: stats.skipped.load(Ordering::Relaxed) as u64,
+            matched: stats.matched.load(Ordering::Relaxed) as u64,
+            diverged: stats.diverged.load(Ordering::Relaxed) as u64,
+            failed: stats.failed.load(Ordering::Relaxed) as u64,
+            latency: *stats.latency.lock().unwrap(),
+        }
+    }
+
+    fn is_sampled(&self, input: &Input<'_>) -> bool {
+        if self.sample_rate >= 1.0 {
+            return true;
+        }
+        // the upper 53 bits are enough for the precision of `f64`.
+        let sample = (input.random().next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+        sample < self.sample_rate
+    }
+
+    fn prepare(&self, body: Bytes, input: &mut Input<'_>) -> Option<Mirror> {
+        let path_and_query = input
+            .request
+            .uri()
+            .path_and_query()
+            .map_or("/", |path_and_query| path_and_query.as_str());
+        let uri = match format!("{}{}", self.prefix, path_and_query).parse::<Uri>() {
+            Ok(uri) => uri,
+            Err(..) => {
+                self.stats.failed.fetch_add(1, Ordering::Relaxed);
+                return None;
+            }
+        };
+
+        let mut request = Request::new(RequestBody::from(hyper::Body::from(body)));
+        *request.method_mut() = input.request.method().clone();
+        *request.uri_mut() = uri.clone();
+        *request.version_mut() = input.request.version();
+        *request.headers_mut() = input.request.headers().clone();
+
+        let mut locals = LocalMap::default();
+        ShadowMarker(()).insert_into(&mut locals);
+
+        match input.dispatch.dispatch(request, locals) {
+            Some(future) => Some(Mirror {
+                future,
+                shadow: self.clone(),
+                clock: input.clock().clone(),
+                method: input.request.method().clone(),
+                uri,
+            }),
+            None => {
+                self.stats.failed.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    fn record(
+        &self,
+        method: &Method,
+        uri: &Uri,
+        elapsed: Duration,
+        primary: Option<StatusCode>,
+        shadow: StatusCode,
+    ) {
+        let stats = &*self.stats;
+        *stats.latency.lock().unwrap() += elapsed;
+
+        // The primary status is unknown if the handler has failed, in which case
+        // only the error response is regarded as the same result.
+        let matched = match primary {
+            Some(primary) => primary == shadow,
+            None => shadow.is_client_error() || shadow.is_server_error(),
+        };
+        if matched {
+            stats.matched.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+
+        stats.diverged.fetch_add(1, Ordering::Relaxed);
+        if self.log_divergence {
+            log::warn!(
+                "shadow response diverged: {} {} (primary: {}, shadow: {})",
+                method,
+                uri,
+                primary.map_or_else(|| "error".into(), |status| status.as_u16().to_string()),
+                shadow.as_u16(),
+            );
+        }
+    }
+}
+
+/// A snapshot of the results of the requests mirrored by `Shadow`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ShadowMetrics {
+    /// The number of the requests dispatched to the shadow route.
+    pub mirrored: u64,
+
+    /// The number of the sampled requests that were not mirrored because
+    /// their bodies could not be duplicated.
+    pub skipped: u64,
+
+    /// The number of the shadow responses with the same status code as the primary one.
+    pub matched: u64,
+
+    /// The number of the shadow responses with a different status code from the primary one.
+    pub diverged: u64,
+
+    /// The number of the sampled requests that could not be dispatched.
+    pub failed: u64,
+
+    /// The total time until the shadow responses are produced.
+    pub latency: Duration,
+}
+
+impl ShadowMetrics {
+    /// Returns the pairs of the metric names prefixed with `shadow_` and their
+    /// values, for exporting them to a metrics registry.
+    ///
+    /// The latency is reported in microseconds.
+    pub fn entries(&self) -> [(&'static str, u64); 6] {
+        [
+            ("shadow_requests_total", self.mirrored),
+            ("shadow_skipped_total", self.skipped),
+            ("shadow_matched_total", self.matched),
+            ("shadow_diverged_total", self.diverged),
+            ("shadow_failed_total", self.failed),
+            ("shadow_latency_microseconds_total", as_micros(self.latency)),
+        ]
+    }
+}
+
+fn as_micros(duration: Duration) -> u64 {
+    duration.as_secs() * 1_000_000 + u64::from(duration.subsec_micros())
+}
+
+/// The marker of the mirrored requests, which prevents them from being mirrored again.
+#[derive(Debug, Clone)]
+struct ShadowMarker(());
+
+impl LocalData for ShadowMarker {
+    local_key! {
+        const KEY: Self;
+    }
+}
+
+/// A mirrored request waiting for the primary response.
+struct Mirror {
+    future: DispatchFuture,
+    shadow: Shadow,
+    clock: Arc<dyn Clock>,
+    method: Method,
+    uri: Uri,
+}
+
+impl Mirror {
+    /// Spawns the mirrored request onto the default executor, with the status
+    /// code of the primary response or `None` if it has failed.
+    fn spawn(self, primary: Option<StatusCode>) {
+        let Mirror {
+            future,
+            shadow,
+            clock,
+            method,
+            uri,
+        } = self;
+        let stats = shadow.stats.clone();
+        let start = clock.now();
+        let task = future.then(move |result| {
+            let response = match result {
+                Ok(response) => response,
+                Err(never) => match never {},
+            };
+            let elapsed = clock.now().duration_since(start);
+            shadow.record(&method, &uri, elapsed, primary, response.status());
+            discard(response.into_body())
+        });
+
+        match tokio_executor::DefaultExecutor::current().spawn(Box::new(task)) {
+            Ok(()) => stats.mirrored.fetch_add(1, Ordering::Relaxed),
+            Err(..) => stats.failed.fetch_add(1, Ordering::Relaxed),
+        };
+    }
+}
+
+/// Reads the body of the shadow response to the end, so that the handler runs to completion.
+fn discard(mut body: ResponseBody) -> impl Future<Item = (), Error = ()> + Send + 'static {
+    future::poll_fn(move || loop {
+        match body.poll_data() {
+            Ok(Async::Ready(Some(..))) => continue,
+            Ok(Async::Ready(None)) | Err(..) => return Ok(Async::Ready(())),
+            Ok(Async::NotReady) => return Ok(Async::NotReady),
+        }
+    })
+}
+
+impl<H> ModifyHandler<H> for Shadow
+where
+    H: Handler,
+    H::Output: Responder,
+{
+    type Output = WithShadow<H::Output>;
+    type Handler = ShadowHandler<H>; // private
+
+    fn modify(&self, inner: H) -> Self::Handler {
+        ShadowHandler {
+            inner,
+            shadow: self.clone(),
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct ShadowHandler<H> {
+    inner: H,
+    shadow: Shadow,
+}
+
+impl<H> Handler for ShadowHandler<H>
+where
+    H: Handler,
+    H::Output: Responder,
+{
+    type Output = WithShadow<H::Output>;
+    type Error = Error;
+    type Handle = HandleShadow<H::Handle>; // private
+
+    fn allowed_methods(&self) -> Option<&AllowedMethods> {
+        self.inner.allowed_methods()
+    }
+
+    fn handle(&self) -> Self::Handle {
+        HandleShadow {
+            inner: self.inner.handle(),
+            shadow: self.shadow.clone(),
+            state: State::Init,
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct HandleShadow<H> {
+    inner: H,
+    shadow: Shadow,
+    state: State,
+}
+
+enum State {
+    Init,
+    Buffering(Concat2<BodyStream>),
+    Handling(Option<Mirror>),
+}
+
+impl<H> HandleShadow<H> {
+    fn start(&self, input: &mut Input<'_>) -> State {
+        if input.locals.contains_key(&ShadowMarker::KEY) || !self.shadow.is_sampled(input) {
+            return State::Handling(None);
+        }
+
+        let content_length = match input.locals.get(&RequestBody::KEY) {
+            Some(body) if body.is_end_stream() => Some(0),
+            Some(body) => body.content_length(),
+            None => return self.skip(),
+        };
+        match content_length {
+            Some(0) => State::Handling(self.shadow.prepare(Bytes::new(), input)),
+            Some(len) if len <= self.shadow.max_body_size => {
+                match RequestBody::take_from(input.locals) {
+                    Some(body) => State::Buffering(body.into_stream().concat2()),
+                    None => self.skip(),
+                }
+            }
+            _ => self.skip(),
+        }
+    }
+
+    fn skip(&self) -> State {
+        self.shadow.stats.skipped.fetch_add(1, Ordering::Relaxed);
+        State::Handling(None)
+    }
+}
+
+impl<H> TryFuture for HandleShadow<H>
+where
+    H: TryFuture,
+    H::Error: Into<Error>,
+{
+    type Ok = WithShadow<H::Ok>;
+    type Error = Error;
+
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        loop {
+            self.state = match self.state {
+                State::Init => self.start(input),
+                State::Buffering(ref mut body) => {
+                    let body = futures01::try_ready!(body.poll());
+                    // hand the buffered body over to the primary handler.
+                    RequestBody::from(hyper::Body::from(body.clone())).insert_into(input.locals);
+                    State::Handling(self.shadow.prepare(body, input))
+                }
+                State::Handling(ref mut mirror) => {
+                    return match self.inner.poll_ready(input) {
+                        Ok(Async::Ready(output)) => Ok(Async::Ready(WithShadow {
+                            inner: output,
+                            mirror: mirror.take(),
+                        })),
+                        Ok(Async::NotReady) => Ok(Async::NotReady),
+                        Err(err) => {
+                            if let Some(mirror) = mirror.take() {
+                                mirror.spawn(None);
+                            }
+                            Err(err.into())
+                        }
+                    };
+                }
+            };
+        }
+    }
+}
+
+/// A `Responder` which dispatches the mirrored request after the primary response is produced.
+#[allow(missing_debug_implementations)]
+pub struct WithShadow<T> {
+    inner: T,
+    mirror: Option<Mirror>,
+}
+
+impl<T> Responder for WithShadow<T>
+where
+    T: Responder,
+{
+    type Response = WithShadowResponse<T::Response>;
+    type Error = Error;
+    type Respond = WithShadowRespond<T::Respond>; // private
+
+    fn respond(self) -> Self::Respond {
+        WithShadowRespond {
+            inner: self.inner.respond(),
+            mirror: self.mirror,
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct WithShadowRespond<R> {
+    inner: R,
+    mirror: Option<Mirror>,
+}
+
+impl<R> TryFuture for WithShadowRespond<R>
+where
+    R: TryFuture,
+    R::Error: Into<Error>,
+{
+    type Ok = WithShadowResponse<R::Ok>;
+    type Error = Error;
+
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        match self.inner.poll_ready(input) {
+            Ok(Async::Ready(inner)) => Ok(Async::Ready(WithShadowResponse {
+                inner,
+                mirror: self.mirror.take(),
+            })),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(err) => {
+                if let Some(mirror) = self.mirror.take() {
+                    mirror.spawn(None);
+                }
+                Err(err.into())
+            }
+        }
+    }
+}
+
+/// An `IntoResponse` which dispatches the mirrored request after the primary response is produced.
+#[allow(missing_debug_implementations)]
+pub struct WithShadowResponse<T> {
+    inner: T,
+    mirror: Option<Mirror>,
+}
+
+impl<T> IntoResponse for WithShadowResponse<T>
+where
+    T: IntoResponse,
+{
+    type Body = ResponseBody;
+    type Error = Error;
+
+    fn into_response(self, request: &Request<()>) -> Result<Response<Self::Body>, Self::Error> {
+        let response = self
+            .inner
+            .into_response(request)
+            .map(|response| response.map(Into::<ResponseBody>::into))
+            .map_err(Into::into);
+        if let Some(mirror) = self.mirror {
+            mirror.spawn(response.as_ref().ok().map(Response::status));
+        }
+        response
+    }
+}
@@ -1,16 +1,45 @@
 //! Components for constructing HTTP responses.
 
+mod blocking;
+mod connection;
+#[cfg(feature = "csv")]
+mod csv;
+pub mod header;
+mod paginated;
+mod partial;
+pub mod problem;
+pub mod range;
 pub mod redirect;
+mod serialize;
+pub mod vary;
+
+pub use {
+    self::{
+        blocking::{stream_blocking, stream_blocking_with, StreamBlocking},
+        connection::{with_connection_close, WithConnectionClose},
+        paginated::{PageInfo, Paginated},
+        partial::JsonPartial,
+        serialize::{Encoder, Encoders, Serialize, SerializeRespond},
+    },
+    tsukuyomi_macros::IntoResponse,
+};
 
-pub use tsukuyomi_macros::IntoResponse;
+#[cfg(feature = "csv")]
+pub use self::csv::Csv;
 
 use {
-    crate::{error::Error, input::body::RequestBody, util::Never},
+    crate::{
+        error::Error,
+        input::{body::RequestBody, localmap::LocalMap},
+        util::Never,
+    },
     bytes::{Buf, Bytes, IntoBuf},
-    futures01::{Poll, Stream},
-    http::{header::HeaderMap, Request, Response, StatusCode},
+    futures01::{Async, Poll, Stream},
+    http::{
+        header::{HeaderMap, HeaderValue},
+        HttpTryFrom, Request, Response, StatusCode, Uri,
+    },
     hyper::body::{Body, Payload},
-    serde::Serialize,
 };
 
 // the private API for custom derive.
@@ -27,9 +56,17 @@ pub mod internal {
 
 /// A type representing the message body in an HTTP response.
 #[derive(Debug, Default)]
-pub struct ResponseBody(Body);
+pub struct ResponseBody {
+    body: Body,
+    // The local map of the request, dropped together with the body.
+    locals: Option<LocalMap>,
+}
 
 impl ResponseBody {
+    fn new(body: Body) -> Self {
+        Self { body, locals: None }
+    }
+
     /// Creates an empty `ResponseBody`.
     #[inline]
     pub fn empty() -> Self {
@@ -43,21 +80,52 @@ impl ResponseBody {
         S::Error: Into<Box<dyn std::error::Error + Send + Sync + 'static>>,
         S::Item: IntoBuf,
     {
-        ResponseBody(Body::wrap_stream(
+        Self::new(Body::wrap_stream(
             stream.map(|chunk| chunk.into_buf().collect::<Bytes>()),
         ))
     }
+
+    /// Takes the content of this body if it is entirely buffered in memory.
+    ///
+    /// The streaming body is returned as it is.
+    pub(crate) fn try_into_bytes(mut self) -> Result<Bytes, Self> {
+        if self.body.content_length().is_none() {
+            return Err(self);
+        }
+        match self.body.poll_data() {
+            Ok(Async::Ready(None)) => Ok(Bytes::new()),
+            Ok(Async::Ready(Some(chunk))) => {
+                if self.body.is_end_stream() {
+                    Ok(chunk.into_bytes())
+                } else {
+                    // put back the chunk already taken from the stream.
+                    let ResponseBody { body: rest, locals } = self;
+                    let mut body =
+                        Self::wrap_stream(futures01::stream::once(Ok(chunk)).chain(rest));
+                    body.locals = locals;
+                    Err(body)
+                }
+            }
+            _ => Err(self),
+        }
+    }
+
+    /// Moves the local map of the request into this body, so that the values are
+    /// dropped after the body has been completely sent or discarded.
+    pub(crate) fn attach_locals(&mut self, locals: LocalMap) {
+        self.locals = Some(locals);
+    }
 }
 
 impl From<()> for ResponseBody {
     fn from(_: ()) -> Self {
-        ResponseBody(Body::empty())
+        Self::new(Body::empty())
     }
 }
 
 impl From<RequestBody> for ResponseBody {
     fn from(body: RequestBody) -> Self {
-        ResponseBody(body.into_inner())
+        Self::new(body.into_inner())
     }
 }
 
@@ -65,7 +133,7 @@ macro_rules! impl_response_body {
     ($($t:ty,)*) => {$(
         impl From<$t> for ResponseBody {
             fn from(body: $t) -> Self {
-                ResponseBody(Body::from(body))
+                ResponseBody::new(Body::from(body))
             }
         }
     )*};
@@ -89,25 +157,25 @@ impl Payload for ResponseBody {
     #[inline]
     #[cfg_attr(tarpaulin, skip)]
     fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
-        self.0.poll_data()
+        self.body.poll_data()
     }
 
     #[inline]
     #[cfg_attr(tarpaulin, skip)]
     fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, Self::Error> {
-        self.0.poll_trailers()
+        self.body.poll_trailers()
     }
 
     #[inline]
     #[cfg_attr(tarpaulin, skip)]
     fn is_end_stream(&self) -> bool {
-        self.0.is_end_stream()
+        self.body.is_end_stream()
     }
 
     #[inline]
     #[cfg_attr(tarpaulin, skip)]
     fn content_length(&self) -> Option<u64> {
-        self.0.content_length()
+        self.body.content_length()
     }
 }
 
@@ -231,6 +299,77 @@ impl IntoResponse for serde_json::Value {
     }
 }
 
+impl IntoResponse for StatusCode {
+    type Body = ();
+    type Error = Error;
+
+    fn into_response(self, _: &Request<()>) -> Result<Response<Self::Body>, Self::Error> {
+        validate_status(self)?;
+        let mut response = Response::new(());
+        *response.status_mut() = self;
+        Ok(response)
+    }
+}
+
+/// Overrides the status code of the response of `T`.
+///
+/// The message body is discarded if the status code is `204 No Content`
+/// or `304 Not Modified`, since such responses cannot have a body.
+impl<T> IntoResponse for (StatusCode, T)
+where
+    T: IntoResponse,
+{
+    type Body = ResponseBody;
+    type Error = Error;
+
+    fn into_response(self, request: &Request<()>) -> Result<Response<Self::Body>, Self::Error> {
+        let (status, t) = self;
+        validate_status(status)?;
+        let mut response = t
+            .into_response(request)
+            .map(|response| response.map(Into::into))
+            .map_err(Into::into)?;
+        *response.status_mut() = status;
+        if status == StatusCode::NO_CONTENT || status == StatusCode::NOT_MODIFIED {
+            response.headers_mut().remove(http::header::CONTENT_LENGTH);
+            *response.body_mut() = ResponseBody::empty();
+        }
+        Ok(response)
+    }
+}
+
+/// Overrides the status code and the header fields of the response of `T`.
+///
+/// The header fields in the provided `HeaderMap` replace all values of the
+/// same name set by `T`, and the others are left as they are.
+impl<T> IntoResponse for (StatusCode, HeaderMap, T)
+where
+    T: IntoResponse,
+{
+    type Body = ResponseBody;
+    type Error = Error;
+
+    fn into_response(self, request: &Request<()>) -> Result<Response<Self::Body>, Self::Error> {
+        let (status, headers, t) = self;
+        let mut response = (status, t).into_response(request)?;
+        for name in headers.keys() {
+            response.headers_mut().remove(name);
+        }
+        response.headers_mut().extend(headers);
+        Ok(response)
+    }
+}
+
+fn validate_status(status: StatusCode) -> Result<(), Error> {
+    if status.is_informational() {
+        return Err(crate::error::internal_server_error(format!(
+            "the informational status code cannot be used as the final response: {}",
+            status
+        )));
+    }
+    Ok(())
+}
+
 /// A function to create a `IntoResponse` using the specified function.
 pub fn into_response<T, E>(
     f: impl FnOnce(&Request<()>) -> Result<Response<T>, E>,
@@ -268,7 +407,7 @@ where
 #[inline]
 pub fn json<T>(data: T) -> impl IntoResponse<Body = Vec<u8>, Error = Error>
 where
-    T: Serialize,
+    T: serde::Serialize,
 {
     self::into_response(move |request| self::into_response::json(data, request))
 }
@@ -278,11 +417,41 @@ where
 #[inline]
 pub fn json_pretty<T>(data: T) -> impl IntoResponse<Body = Vec<u8>, Error = Error>
 where
-    T: Serialize,
+    T: serde::Serialize,
 {
     self::into_response(move |request| self::into_response::json_pretty(data, request))
 }
 
+/// Creates a JSON responder restricted to the fields selected by the client,
+/// such as the value of `?fields=id,author.name`.
+///
+/// See [`JsonPartial`] for details.
+///
+/// [`JsonPartial`]: ./struct.JsonPartial.html
+#[inline]
+pub fn json_partial<T>(data: T, fields: Option<&str>) -> JsonPartial<T>
+where
+    T: serde::Serialize,
+{
+    JsonPartial::new(data, fields)
+}
+
+/// Creates a responder that streams the items of `stream` as the rows of CSV,
+/// with the header row generated from the field names.
+///
+/// See [`Csv`] for details.
+///
+/// [`Csv`]: ./struct.Csv.html
+#[cfg(feature = "csv")]
+#[inline]
+pub fn csv<S>(stream: S) -> Csv<S>
+where
+    S: Stream,
+    S::Item: serde::Serialize,
+{
+    Csv::new(stream)
+}
+
 /// Creates an HTML responder with the specified response body.
 #[allow(deprecated)]
 #[inline]
@@ -293,6 +462,150 @@ where
     self::into_response(move |request| self::into_response::html(body, request))
 }
 
+/// Creates a responder that returns an empty response with the specified status code.
+#[inline]
+pub fn status(status: StatusCode) -> impl IntoResponse<Body = (), Error = Error> {
+    status
+}
+
+/// Equivalent to `status(StatusCode::NO_CONTENT)`.
+#[inline]
+pub fn no_content() -> impl IntoResponse<Body = (), Error = Error> {
+    self::status(StatusCode::NO_CONTENT)
+}
+
+/// Creates a responder that returns `201 Created` with the specified `Location`.
+///
+/// The location is validated when the response is created, and the invalid
+/// one is reported as an internal server error.
+pub fn created<U>(location: U) -> impl IntoResponse<Body = (), Error = Error>
+where
+    Uri: HttpTryFrom<U>,
+{
+    self::into_response(move |_| {
+        let location = Uri::try_from(location)
+            .map_err(|err| crate::error::internal_server_error(err.into()))?;
+        let location = HeaderValue::from_shared(location.to_string().into())
+            .map_err(crate::error::internal_server_error)?;
+        let mut response = Response::new(());
+        *response.status_mut() = StatusCode::CREATED;
+        response
+            .headers_mut()
+            .insert(http::header::LOCATION, location);
+        Ok(response)
+    })
+}
+
+/// Creates an `IntoResponse` that asks the client to save the response of `t`
+/// as a file with the specified name.
+///
+/// The filename is sanitized by `header::SafeHeaderValue::attachment`, so the
+/// names taken from the request can be passed as they are.
+pub fn attachment<T>(filename: &str, t: T) -> impl IntoResponse<Body = T::Body, Error = T::Error>
+where
+    T: IntoResponse,
+{
+    let disposition = self::header::SafeHeaderValue::attachment(filename);
+    self::into_response(move |request| {
+        let mut response = t.into_response(request)?;
+        response
+            .headers_mut()
+            .insert(http::header::CONTENT_DISPOSITION, disposition.into());
+        Ok(response)
+    })
+}
+
+/// Creates an `IntoResponse` that appends a header field containing the
+/// specified datetime in the HTTP-date format to the response of `t`.
+#[cfg(feature = "chrono")]
+pub fn http_date<T>(
+    name: http::header::HeaderName,
+    datetime: chrono::DateTime<chrono::Utc>,
+    t: T,
+) -> impl IntoResponse<Body = T::Body, Error = Error>
+where
+    T: IntoResponse,
+{
+    self::into_response(move |request| {
+        let mut response = t.into_response(request).map_err(Into::into)?;
+        let value = crate::input::datetime::to_http_date(&datetime)
+            .parse::<http::header::HeaderValue>()
+            .map_err(crate::error::internal_server_error)?;
+        response.headers_mut().insert(name, value);
+        Ok(response)
+    })
+}
+
+/// Equivalent to `http_date(LAST_MODIFIED, modified, t)`.
+#[cfg(feature = "chrono")]
+#[inline]
+pub fn last_modified<T>(
+    modified: chrono::DateTime<chrono::Utc>,
+    t: T,
+) -> impl IntoResponse<Body = T::Body, Error = Error>
+where
+    T: IntoResponse,
+{
+    self::http_date(http::header::LAST_MODIFIED, modified, t)
+}
+
+/// Creates a responder that serves the body with support for the single byte range requests.
+///
+/// The response advertises `Accept-Ranges: bytes`. If a `GET` request has a
+/// valid `Range` with a single range, the part of the body is returned with
+/// `206 Partial Content`, or `416 Range Not Satisfiable` is returned if the range
+/// is out of the body. The other values of `Range` are ignored and the whole
+/// body is returned.
+pub fn ranged<T>(
+    body: T,
+    content_type: mime::Mime,
+) -> impl IntoResponse<Body = Bytes, Error = Error>
+where
+    T: Into<Bytes>,
+{
+    self::into_response(move |request| {
+        let body = body.into();
+        let len = body.len() as u64;
+        let range = self::range::from_request(request);
+
+        let mut response = match range.map(|range| range.resolve(len)) {
+            None => Response::new(body),
+            Some(Some(range)) => {
+                #[allow(clippy::cast_possible_truncation)]
+                let part = body.slice(range.start as usize, range.end as usize);
+                let mut response = Response::new(part);
+                *response.status_mut() = StatusCode::PARTIAL_CONTENT;
+                response.headers_mut().insert(
+                    http::header::CONTENT_RANGE,
+                    self::range::content_range(Some(&range), len),
+                );
+                response
+            }
+            Some(None) => {
+                let mut response = Response::new(Bytes::new());
+                *response.status_mut() = StatusCode::RANGE_NOT_SATISFIABLE;
+                response.headers_mut().insert(
+                    http::header::CONTENT_RANGE,
+                    self::range::content_range(None, len),
+                );
+                response
+            }
+        };
+        response.headers_mut().insert(
+            http::header::ACCEPT_RANGES,
+            HeaderValue::from_static("bytes"),
+        );
+        if response.status() != StatusCode::RANGE_NOT_SATISFIABLE {
+            let content_type = HeaderValue::from_str(content_type.as_ref())
+                .map_err(crate::error::internal_server_error)?;
+            response
+                .headers_mut()
+                .insert(http::header::CONTENT_TYPE, content_type);
+        }
+        Ok::<_, Error>(response)
+    })
+}
+
 /// Create an instance of `Response<T>` with the provided body and content type.
 fn make_response<T>(body: T, content_type: &'static str) -> Response<T> {
     let mut response = Response::new(body);
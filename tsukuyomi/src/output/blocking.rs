@@ -0,0 +1,212 @@
+use {
+    super::{IntoResponse, ResponseBody},
+    crate::{error::Error, util::Never},
+    bytes::Bytes,
+    futures01::{sync::mpsc, Async, Future, Poll, Sink, Stream},
+    http::{Request, Response},
+    std::{
+        any::Any,
+        fmt, io,
+        panic::{self, AssertUnwindSafe},
+    },
+    tokio_executor::Executor,
+    tokio_threadpool::blocking as poll_blocking,
+};
+
+/// The default number of chunks buffered between the iterator and the connection.
+const DEFAULT_CAPACITY: usize = 16;
+
+/// Creates an `IntoResponse` that streams the items of a blocking iterator as the response body.
+///
+/// The function and the iterator returned from it are run on the blocking section
+/// of the thread pool, so it is fine to perform blocking I/O there, such as reading
+/// rows from a database cursor. The next item is not pulled from the iterator until
+/// a slot of the channel to the connection is free, and hence a slow client does not
+/// cause the unbounded buffering of the items.
+///
+/// If the iterator panics, the panic is logged and the response is aborted.
+///
+/// The response must be created in a task running on `tokio_threadpool`, as in
+/// the default runtime of `tsukuyomi-server`.
+#[allow(clippy::type_complexity)]
+pub fn stream_blocking<F, I>(f: F) -> StreamBlocking<F, fn(I::Item) -> Result<Bytes, Never>>
+where
+    F: FnOnce() -> I,
+    I: IntoIterator,
+    I::Item: Into<Bytes>,
+{
+    stream_blocking_with(f, into_chunk as fn(_) -> _)
+}
+
+fn into_chunk<T: Into<Bytes>>(item: T) -> Result<Bytes, Never> {
+    Ok(item.into())
+}
+
+/// Creates an `IntoResponse` that streams the items of a blocking iterator, converted
+/// into chunks by the provided function.
+///
+/// If the function returns an error, the error is logged and the response is aborted.
+/// See the documentation of `stream_blocking` for details.
+pub fn stream_blocking_with<F, I, S, T, E>(f: F, serialize: S) -> StreamBlocking<F, S>
+where
+    F: FnOnce() -> I,
+    I: IntoIterator,
+    S: FnMut(I::Item) -> Result<T, E>,
+    T: Into<Bytes>,
+    E: fmt::Display,
+{
+    StreamBlocking {
+        f,
+        serialize,
+        capacity: DEFAULT_CAPACITY,
+    }
+}
+
+/// An `IntoResponse` that streams the items of a blocking iterator, created by `stream_blocking`.
+#[derive(Debug)]
+pub struct StreamBlocking<F, S> {
+    f: F,
+    serialize: S,
+    capacity: usize,
+}
+
+impl<F, S> StreamBlocking<F, S> {
+    /// Sets the maximum number of chunks buffered between the iterator and the connection.
+    ///
+    /// The default value is `16`.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `capacity` is zero.
+    pub fn capacity(self, capacity: usize) -> Self {
+        assert!(capacity > 0, "the capacity must be greater than zero");
+        Self { capacity, ..self }
+    }
+}
+
+impl<F, I, S, T, E> IntoResponse for StreamBlocking<F, S>
+where
+    F: FnOnce() -> I + Send + 'static,
+    I: IntoIterator,
+    I::IntoIter: Send + 'static,
+    S: FnMut(I::Item) -> Result<T, E> + Send + 'static,
+    T: Into<Bytes>,
+    E: fmt::Display,
+{
+    type Body = ResponseBody;
+    type Error = Error;
+
+    fn into_response(self, _: &Request<()>) -> Result<Response<Self::Body>, Self::Error> {
+        // The sender has its own slot in addition to the buffer of the channel.
+        let (tx, rx) = mpsc::channel(self.capacity - 1);
+        let producer = Producer {
+            f: Some(self.f),
+            iter: None,
+            serialize: self.serialize,
+            tx,
+        };
+        tokio_executor::DefaultExecutor::current()
+            .spawn(Box::new(producer))
+            .map_err(crate::error::internal_server_error)?;
+
+        let body = rx.then(|chunk| chunk.expect("the receiver never fails"));
+        Ok(Response::new(ResponseBody::wrap_stream(body)))
+    }
+}
+
+/// The task that pulls the items from the iterator and sends them to the connection.
+struct Producer<F, I, S> {
+    f: Option<F>,
+    iter: Option<I>,
+    serialize: S,
+    tx: mpsc::Sender<io::Result<Bytes>>,
+}
+
+impl<F, I, S, T, E> Producer<F, I::IntoIter, S>
+where
+    F: FnOnce() -> I,
+    I: IntoIterator,
+    S: FnMut(I::Item) -> Result<T, E>,
+    T: Into<Bytes>,
+    E: fmt::Display,
+{
+    /// Pulls the next item in the blocking section, and converts it into a chunk.
+    fn poll_next(&mut self) -> Poll<Option<Bytes>, String> {
+        let f = &mut self.f;
+        let iter = &mut self.iter;
+        let serialize = &mut self.serialize;
+        let polled = poll_blocking(|| {
+            panic::catch_unwind(AssertUnwindSafe(|| {
+                let iter = iter.get_or_insert_with(|| {
+                    let f = f.take().expect("the iterator has already been created");
+                    f().into_iter()
+                });
+                iter.next().map(serialize)
+            }))
+        });
+        match polled {
+            Ok(Async::Ready(Ok(Some(Ok(chunk))))) => Ok(Async::Ready(Some(chunk.into()))),
+            Ok(Async::Ready(Ok(Some(Err(err))))) => {
+                Err(format!("failed to convert the item into a chunk: {}", err))
+            }
+            Ok(Async::Ready(Ok(None))) => Ok(Async::Ready(None)),
+            Ok(Async::Ready(Err(payload))) => Err(format!(
+                "the iterator has panicked: {}",
+                panic_message(&*payload)
+            )),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(err) => Err(format!("failed to enter the blocking section: {}", err)),
+        }
+    }
+}
+
+impl<F, I, S, T, E> Future for Producer<F, I::IntoIter, S>
+where
+    F: FnOnce() -> I,
+    I: IntoIterator,
+    S: FnMut(I::Item) -> Result<T, E>,
+    T: Into<Bytes>,
+    E: fmt::Display,
+{
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            // Wait for a free slot before pulling the next item, so that the number
+            // of items taken from the iterator does not exceed the capacity.
+            match self.tx.poll_ready() {
+                Ok(Async::Ready(())) => {}
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Err(..) => {
+                    log::debug!("the response body has been dropped before completion");
+                    return Ok(Async::Ready(()));
+                }
+            }
+
+            let chunk = match self.poll_next() {
+                Ok(Async::Ready(Some(chunk))) => Ok(chunk),
+                Ok(Async::Ready(None)) => return Ok(Async::Ready(())),
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Err(msg) => {
+                    log::error!("aborting the streamed response: {}", msg);
+                    Err(io::Error::new(io::ErrorKind::Other, msg))
+                }
+            };
+            let is_err = chunk.is_err();
+            if self.tx.start_send(chunk).is_err() || is_err {
+                return Ok(Async::Ready(()));
+            }
+        }
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s
+    } else {
+        "Box<dyn Any>"
+    }
+}
@@ -0,0 +1,67 @@
+use {
+    crate::{
+        future::{Poll, TryFuture},
+        input::Input,
+        responder::Responder,
+    },
+    http::header::{HeaderValue, CONNECTION},
+};
+
+/// Creates a `Responder` that closes the connection after sending the response of `responder`.
+///
+/// The response has `Connection: close`, which makes the server close the
+/// connection instead of waiting for the next request. The header field is
+/// staged in `Input::response` before `responder` is polled, so the
+/// connection is also closed if `responder` or its response fails.
+pub fn with_connection_close<R>(responder: R) -> WithConnectionClose<R>
+where
+    R: Responder,
+{
+    WithConnectionClose(responder)
+}
+
+/// A `Responder` that closes the connection after sending the response.
+#[derive(Debug, Clone)]
+pub struct WithConnectionClose<R>(R);
+
+impl<R> Responder for WithConnectionClose<R>
+where
+    R: Responder,
+{
+    type Response = R::Response;
+    type Error = R::Error;
+    type Respond = WithConnectionCloseRespond<R::Respond>; // private
+
+    fn respond(self) -> Self::Respond {
+        WithConnectionCloseRespond {
+            inner: self.0.respond(),
+            staged: false,
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct WithConnectionCloseRespond<R> {
+    inner: R,
+    staged: bool,
+}
+
+impl<R> TryFuture for WithConnectionCloseRespond<R>
+where
+    R: TryFuture,
+{
+    type Ok = R::Ok;
+    type Error = R::Error;
+
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        if !self.staged {
+            // appended so that the handler cannot cancel it by setting `Connection`.
+            input
+                .response()
+                .append(CONNECTION, HeaderValue::from_static("close"))
+                .expect("never fails");
+            self.staged = true;
+        }
+        self.inner.poll_ready(input)
+    }
+}
@@ -0,0 +1,207 @@
+use {
+    super::{header::SafeHeaderValue, IntoResponse, ResponseBody},
+    crate::error::Error,
+    bytes::Bytes,
+    futures01::{Async, Poll, Stream},
+    http::{
+        header::{HeaderValue, CONTENT_DISPOSITION, CONTENT_TYPE},
+        Request, Response,
+    },
+    serde::Serialize,
+    std::{fmt, io},
+};
+
+/// The size of the chunks sent to the connection, at which the rows are flushed.
+const CHUNK_SIZE: usize = 8 * 1024;
+
+/// The byte order mark of UTF-8.
+const BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// An `IntoResponse` that serializes the items of a `Stream` into the rows of CSV,
+/// created by `output::csv`.
+///
+/// The header row is generated from the field names of the first item, and the
+/// fields containing the delimiter, the quotes or the newlines are quoted. The rows
+/// are sent as they are serialized, without buffering the entire response. Since
+/// the header row is derived from an item, an empty stream results in an empty body.
+///
+/// The items must be the flat structs (or the tuples) of the scalar values, as
+/// required by the `csv` crate. If an item fails to be serialized or the stream
+/// returns an error, the error is logged and the response is aborted.
+#[derive(Debug)]
+pub struct Csv<S> {
+    stream: S,
+    delimiter: u8,
+    bom: bool,
+    filename: Option<SafeHeaderValue>,
+}
+
+impl<S> Csv<S>
+where
+    S: Stream,
+    S::Item: Serialize,
+{
+    pub(super) fn new(stream: S) -> Self {
+        Self {
+            stream,
+            delimiter: b',',
+            bom: false,
+            filename: None,
+        }
+    }
+
+    /// Sets the field delimiter, such as `b';'` or `b'\t'`.
+    ///
+    /// The default value is `b','`.
+    pub fn delimiter(self, delimiter: u8) -> Self {
+        Self { delimiter, ..self }
+    }
+
+    /// Sets whether to prepend the byte order mark to the body.
+    ///
+    /// Excel requires the byte order mark to open the UTF-8 encoded files correctly.
+    /// The default value is `false`.
+    pub fn bom(self, bom: bool) -> Self {
+        Self { bom, ..self }
+    }
+
+    /// Asks the client to save the response as a file with the specified name.
+    ///
+    /// The filename is sanitized as in `output::attachment`.
+    pub fn filename(self, filename: &str) -> Self {
+        Self {
+            filename: Some(SafeHeaderValue::attachment(filename)),
+            ..self
+        }
+    }
+}
+
+impl<S> IntoResponse for Csv<S>
+where
+    S: Stream + Send + 'static,
+    S::Item: Serialize,
+    S::Error: fmt::Display,
+{
+    type Body = ResponseBody;
+    type Error = Error;
+
+    fn into_response(self, _: &Request<()>) -> Result<Response<Self::Body>, Self::Error> {
+        let body = CsvBody {
+            stream: self.stream,
+            delimiter: self.delimiter,
+            writer: None,
+            bom: self.bom,
+            rows: 0,
+            done: false,
+        };
+        let mut response = Response::new(ResponseBody::wrap_stream(body));
+        response.headers_mut().insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("text/csv; charset=utf-8"),
+        );
+        if let Some(filename) = self.filename {
+            response
+                .headers_mut()
+                .insert(CONTENT_DISPOSITION, filename.into());
+        }
+        Ok(response)
+    }
+}
+
+/// The response body that serializes the items into the chunks of rows.
+#[allow(missing_debug_implementations)]
+struct CsvBody<S> {
+    stream: S,
+    delimiter: u8,
+    // The writer of the current chunk, recreated for each chunk since
+    // `csv::Writer` only returns its buffer by consuming itself.
+    writer: Option<csv_crate::Writer<Vec<u8>>>,
+    bom: bool,
+    rows: usize,
+    done: bool,
+}
+
+impl<S> CsvBody<S>
+where
+    S: Stream,
+    S::Item: Serialize,
+    S::Error: fmt::Display,
+{
+    fn writer(&mut self) -> &mut csv_crate::Writer<Vec<u8>> {
+        let Self {
+            delimiter,
+            bom,
+            rows,
+            ..
+        } = *self;
+        self.writer.get_or_insert_with(|| {
+            let mut buf = Vec::with_capacity(CHUNK_SIZE);
+            if bom && rows == 0 {
+                buf.extend_from_slice(BOM);
+            }
+            csv_crate::WriterBuilder::new()
+                .delimiter(delimiter)
+                // the header row is written with the first row only.
+                .has_headers(rows == 0)
+                .from_writer(buf)
+        })
+    }
+
+    fn poll_chunk(&mut self) -> Poll<Option<Bytes>, String> {
+        while !self.done {
+            match self.stream.poll() {
+                Ok(Async::Ready(Some(item))) => {
+                    let writer = self.writer();
+                    writer
+                        .serialize(item)
+                        .map_err(|err| format!("failed to serialize a row: {}", err))?;
+                    writer
+                        .flush()
+                        .map_err(|err| format!("failed to serialize a row: {}", err))?;
+                    let full = writer.get_ref().len() >= CHUNK_SIZE;
+                    self.rows += 1;
+                    if full {
+                        break;
+                    }
+                }
+                Ok(Async::Ready(None)) => {
+                    self.done = true;
+                    if self.bom && self.rows == 0 {
+                        // the byte order mark is sent even if there is no row.
+                        self.writer();
+                    }
+                }
+                Ok(Async::NotReady) if self.writer.is_none() => return Ok(Async::NotReady),
+                Ok(Async::NotReady) => break,
+                Err(err) => return Err(format!("failed to receive a row: {}", err)),
+            }
+        }
+
+        match self.writer.take() {
+            Some(writer) => {
+                let buf = writer
+                    .into_inner()
+                    .map_err(|err| format!("failed to serialize a row: {}", err))?;
+                Ok(Async::Ready(Some(buf.into())))
+            }
+            None => Ok(Async::Ready(None)),
+        }
+    }
+}
+
+impl<S> Stream for CsvBody<S>
+where
+    S: Stream,
+    S::Item: Serialize,
+    S::Error: fmt::Display,
+{
+    type Item = Bytes;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        self.poll_chunk().map_err(|msg| {
+            log::error!("aborting the CSV response: {}", msg);
+            io::Error::new(io::ErrorKind::Other, msg)
+        })
+    }
+}
@@ -0,0 +1,223 @@
+//! Construction of the header values from the untrusted data.
+//!
+//! A header value containing CR or LF could terminate the header field and inject
+//! another field or the message body into the response (response splitting).
+//! [`SafeHeaderValue`] guarantees that the value contains no control character,
+//! either by rejecting such values or by sanitizing them, and the helpers of the
+//! framework which put the data influenced by the client into the header fields,
+//! such as the redirects, use it.
+//!
+//! [`SafeHeaderValue`]: ./struct.SafeHeaderValue.html
+
+use {
+    crate::error::HttpError,
+    http::{header::HeaderValue, Request, Response, StatusCode},
+    std::fmt,
+    url::percent_encoding::{percent_encode, utf8_percent_encode, EncodeSet, DEFAULT_ENCODE_SET},
+};
+
+/// The error returned when a header value contains a control character.
+///
+/// This error is regarded as a bug of the server, and is mapped to
+/// `500 Internal Server Error`.
+#[derive(Debug, failure::Fail)]
+#[fail(
+    display = "the header value contains the control character {:#04x} at {}",
+    byte, position
+)]
+pub struct UnsafeHeaderValue {
+    byte: u8,
+    position: usize,
+}
+
+impl UnsafeHeaderValue {
+    /// Returns the control character found in the value.
+    pub fn byte(&self) -> u8 {
+        self.byte
+    }
+
+    /// Returns the position of the control character in the value.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+}
+
+impl HttpError for UnsafeHeaderValue {
+    type Body = String;
+
+    fn into_response(self, _: &Request<()>) -> Response<Self::Body> {
+        let mut response = Response::new(self.to_string());
+        *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+        response
+    }
+}
+
+/// Returns `true` if the byte is not allowed in the header values.
+///
+/// The horizontal tab is allowed as in RFC 7230.
+fn is_control(b: u8) -> bool {
+    (b < 0x20 && b != b'\t') || b == 0x7f
+}
+
+/// The characters percent-encoded in the URIs: the controls, the space, the
+/// non-ASCII bytes, and the ones which cannot appear in a quoted string.
+#[derive(Debug, Clone, Copy)]
+struct UriEncodeSet;
+
+impl EncodeSet for UriEncodeSet {
+    fn contains(&self, b: u8) -> bool {
+        is_control(b) || b == b' ' || b == b'"' || b >= 0x80
+    }
+}
+
+/// The characters percent-encoded in the parameter `filename*` (RFC 5987).
+#[derive(Debug, Clone, Copy)]
+struct AttrCharEncodeSet;
+
+impl EncodeSet for AttrCharEncodeSet {
+    fn contains(&self, b: u8) -> bool {
+        DEFAULT_ENCODE_SET.contains(b)
+            || b"'%(),/:;<=>?@[\\]{}*".contains(&b)
+            || b == b'\t'
+            || b == 0x7f
+    }
+}
+
+/// A header value which is guaranteed to contain no control character except
+/// the horizontal tab.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SafeHeaderValue(HeaderValue);
+
+impl SafeHeaderValue {
+    /// Creates a `SafeHeaderValue` from the string, rejecting the control characters.
+    pub fn new(value: &str) -> Result<Self, UnsafeHeaderValue> {
+        Self::check(value.as_bytes())?;
+        let value = HeaderValue::from_str(value).expect("the value should have been validated");
+        Ok(SafeHeaderValue(value))
+    }
+
+    /// Creates a `SafeHeaderValue` from the existing `HeaderValue`.
+    ///
+    /// The value is validated again, since a `HeaderValue` created without the
+    /// validation may contain any byte.
+    pub fn from_header_value(value: HeaderValue) -> Result<Self, UnsafeHeaderValue> {
+        Self::check(value.as_bytes())?;
+        Ok(SafeHeaderValue(value))
+    }
+
+    /// Creates a `SafeHeaderValue` from the string with the control characters removed.
+    pub fn stripped(value: &str) -> Self {
+        let value: String = value
+            .chars()
+            .filter(|&c| !c.is_control() || c == '\t')
+            .collect();
+        SafeHeaderValue(HeaderValue::from_str(&value).expect("should be a valid header value"))
+    }
+
+    /// Creates a `SafeHeaderValue` from the URI, such as the one of `Location`.
+    ///
+    /// The control characters, the spaces and the non-ASCII characters are
+    /// percent-encoded, and the other characters are left as they are.
+    pub fn uri(uri: &str) -> Self {
+        let encoded = utf8_percent_encode(uri, UriEncodeSet).to_string();
+        SafeHeaderValue(HeaderValue::from_shared(encoded.into()).expect("should be visible ASCII"))
+    }
+
+    /// Creates the value of `Content-Disposition` that asks the client to save
+    /// the content as a file with the specified name.
+    ///
+    /// The control characters and the path separators are removed from the
+    /// filename. The non-ASCII characters are replaced with `_` in the parameter
+    /// `filename`, and the original name is provided by `filename*` instead.
+    pub fn attachment(filename: &str) -> Self {
+        let filename: String = filename
+            .chars()
+            .filter(|&c| !c.is_control() && c != '/' && c != '\\')
+            .collect();
+
+        let mut value = String::from("attachment");
+        if !filename.is_empty() {
+            let fallback: String = filename
+                .chars()
+                .map(|c| match c {
+                    '"' => '\'',
+                    c if c.is_ascii() => c,
+                    _ => '_',
+                })
+                .collect();
+            value.push_str(&format!("; filename=\"{}\"", fallback));
+            if !filename.is_ascii() {
+                value.push_str(&format!(
+                    "; filename*=UTF-8''{}",
+                    percent_encode(filename.as_bytes(), AttrCharEncodeSet)
+                ));
+            }
+        }
+        SafeHeaderValue(HeaderValue::from_shared(value.into()).expect("should be visible ASCII"))
+    }
+
+    fn check(bytes: &[u8]) -> Result<(), UnsafeHeaderValue> {
+        match bytes.iter().position(|&b| is_control(b)) {
+            Some(position) => Err(UnsafeHeaderValue {
+                byte: bytes[position],
+                position,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns a reference to the underlying `HeaderValue`.
+    pub fn as_header_value(&self) -> &HeaderValue {
+        &self.0
+    }
+
+    /// Consumes itself and returns the underlying `HeaderValue`.
+    pub fn into_header_value(self) -> HeaderValue {
+        self.0
+    }
+}
+
+impl From<SafeHeaderValue> for HeaderValue {
+    fn from(value: SafeHeaderValue) -> Self {
+        value.0
+    }
+}
+
+impl fmt::Display for SafeHeaderValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&String::from_utf8_lossy(self.0.as_bytes()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reject_control_characters() {
+        let err = SafeHeaderValue::new("foo\r\nSet-Cookie: x=1").unwrap_err();
+        assert_eq!(err.byte(), b'\r');
+        assert_eq!(err.position(), 3);
+        assert!(SafeHeaderValue::new("foo\tbar").is_ok());
+    }
+
+    #[test]
+    fn sanitize_values() {
+        assert_eq!(
+            SafeHeaderValue::stripped("foo\r\nbar").as_header_value(),
+            "foobar"
+        );
+        assert_eq!(
+            SafeHeaderValue::uri("/a b?q=\r\nx&r=caf\u{e9}").as_header_value(),
+            "/a%20b?q=%0D%0Ax&r=caf%C3%A9"
+        );
+        assert_eq!(
+            SafeHeaderValue::attachment("../report\r\n\".csv").as_header_value(),
+            "attachment; filename=\"..report'.csv\""
+        );
+        assert_eq!(
+            SafeHeaderValue::attachment("r\u{e9}sum\u{e9}.pdf").as_header_value(),
+            "attachment; filename=\"r_sum_.pdf\"; filename*=UTF-8''r%C3%A9sum%C3%A9.pdf"
+        );
+    }
+}
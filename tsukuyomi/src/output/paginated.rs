@@ -0,0 +1,210 @@
+use {
+    super::IntoResponse,
+    crate::{error::Error, extractor::pagination::Pagination},
+    http::{
+        header::{HeaderName, HeaderValue, LINK},
+        Request, Response,
+    },
+    serde::Serialize,
+    std::cmp,
+    url::form_urlencoded,
+};
+
+/// An `IntoResponse` representing a page of the collection.
+///
+/// The items are serialized as a JSON array, and the links to the adjacent
+/// pages are provided by the header field `Link` (RFC 5988).  The header field
+/// `X-Total-Count` is also provided if the total number of items is specified.
+/// The information about the page is stored in the response extensions as [`PageInfo`].
+///
+/// [`PageInfo`]: ./struct.PageInfo.html
+#[derive(Debug)]
+pub struct Paginated<T> {
+    items: T,
+    pagination: Pagination,
+    total: Option<u64>,
+    next_cursor: Option<String>,
+}
+
+impl<T> Paginated<T>
+where
+    T: Serialize,
+{
+    /// Creates a `Paginated` with the items in the current page.
+    pub fn new(items: T, pagination: Pagination) -> Self {
+        Self {
+            items,
+            pagination,
+            total: None,
+            next_cursor: None,
+        }
+    }
+
+    /// Sets the total number of items in the collection.
+    ///
+    /// The links to the next and last page are provided only if this value is set.
+    pub fn total(self, total: u64) -> Self {
+        Self {
+            total: Some(total),
+            ..self
+        }
+    }
+
+    /// Sets the cursor that points to the next page, used in cursor-based pagination.
+    pub fn next_cursor(self, cursor: impl Into<String>) -> Self {
+        Self {
+            next_cursor: Some(cursor.into()),
+            ..self
+        }
+    }
+
+    fn page_info(&self) -> PageInfo {
+        let (page, cursor) = match self.pagination {
+            Pagination::Page { page, .. } => (Some(page), None),
+            Pagination::Cursor { ref cursor, .. } => (None, Some(cursor.clone())),
+        };
+        PageInfo {
+            page,
+            cursor,
+            per_page: self.pagination.per_page(),
+            total: self.total,
+            next_cursor: self.next_cursor.clone(),
+        }
+    }
+
+    fn links(&self, request: &Request<()>) -> Vec<(String, &'static str)> {
+        let mut links = vec![];
+        match self.pagination {
+            Pagination::Page { page, per_page, .. } => {
+                let link = |page: u64| {
+                    page_uri(
+                        request,
+                        &[
+                            ("page", page.to_string()),
+                            ("per_page", per_page.to_string()),
+                        ],
+                    )
+                };
+                let page = u64::from(page);
+                let last = self.total.map(|total| {
+                    let per_page = u64::from(per_page);
+                    cmp::max(1, (total + per_page - 1) / per_page)
+                });
+
+                links.push((link(1), "first"));
+                if page > 1 {
+                    let prev = last.map_or(page - 1, |last| cmp::min(page - 1, last));
+                    links.push((link(prev), "prev"));
+                }
+                if let Some(last) = last {
+                    if page < last {
+                        links.push((link(page + 1), "next"));
+                    }
+                    links.push((link(last), "last"));
+                }
+            }
+            Pagination::Cursor { per_page, .. } => {
+                let per_page = per_page.to_string();
+                links.push((
+                    page_uri(request, &[("per_page", per_page.clone())]),
+                    "first",
+                ));
+                if let Some(ref cursor) = self.next_cursor {
+                    links.push((
+                        page_uri(
+                            request,
+                            &[("cursor", cursor.clone()), ("per_page", per_page)],
+                        ),
+                        "next",
+                    ));
+                }
+            }
+        }
+        links
+    }
+}
+
+/// The information about the page returned by a [`Paginated`].
+///
+/// The value is stored in the extensions of the response, so that the modifiers
+/// such as `modifiers::Envelope` can report it along with the items. The absent
+/// fields are skipped in the serialized form.
+///
+/// [`Paginated`]: ./struct.Paginated.html
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PageInfo {
+    /// The current page number, in page-based pagination.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page: Option<u32>,
+    /// The cursor of the current page, in cursor-based pagination.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>,
+    /// The number of items per page.
+    pub per_page: u32,
+    /// The total number of items in the collection, if specified.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total: Option<u64>,
+    /// The cursor that points to the next page, if specified.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+/// Creates the URI of the current path with the pagination parameters replaced.
+///
+/// The other parameters in the query string are preserved in their original order.
+fn page_uri(request: &Request<()>, params: &[(&'static str, String)]) -> String {
+    let mut used = vec![false; params.len()];
+    let mut serializer = form_urlencoded::Serializer::new(String::new());
+    for (key, value) in form_urlencoded::parse(request.uri().query().unwrap_or("").as_bytes()) {
+        match params.iter().position(|&(k, _)| k == key) {
+            Some(i) if !used[i] => {
+                serializer.append_pair(params[i].0, &params[i].1);
+                used[i] = true;
+            }
+            Some(..) => {}
+            None if key == "page" || key == "per_page" || key == "cursor" => {}
+            None => {
+                serializer.append_pair(&key, &value);
+            }
+        }
+    }
+    for (&(key, ref value), used) in params.iter().zip(used) {
+        if !used {
+            serializer.append_pair(key, value);
+        }
+    }
+    format!("{}?{}", request.uri().path(), serializer.finish())
+}
+
+impl<T> IntoResponse for Paginated<T>
+where
+    T: Serialize,
+{
+    type Body = Vec<u8>;
+    type Error = Error;
+
+    fn into_response(self, request: &Request<()>) -> Result<Response<Self::Body>, Self::Error> {
+        let links = self
+            .links(request)
+            .into_iter()
+            .map(|(uri, rel)| format!("<{}>; rel=\"{}\"", uri, rel))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let page_info = self.page_info();
+
+        let body = serde_json::to_vec(&self.items).map_err(crate::error::internal_server_error)?;
+        let mut response = super::make_response(body, "application/json");
+        response.headers_mut().insert(
+            LINK,
+            HeaderValue::from_shared(links.into()).map_err(crate::error::internal_server_error)?,
+        );
+        if let Some(total) = self.total {
+            response.headers_mut().insert(
+                HeaderName::from_static("x-total-count"),
+                HeaderValue::from(total),
+            );
+        }
+        response.extensions_mut().insert(page_info);
+        Ok(response)
+    }
+}
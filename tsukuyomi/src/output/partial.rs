@@ -0,0 +1,215 @@
+use {
+    super::IntoResponse,
+    crate::error::Error,
+    http::{
+        header::{HeaderValue, ETAG, IF_NONE_MATCH},
+        Method, Request, Response, StatusCode,
+    },
+    serde::Serialize,
+    serde_json::{Map, Value},
+    std::collections::{BTreeMap, BTreeSet},
+};
+
+/// An `IntoResponse` that serializes the value as JSON, restricted to the fields
+/// selected by the client, along with an `ETag`.
+///
+/// The selection is the value of a query parameter such as `?fields=id,author.name`,
+/// which lists the names of the fields separated by commas. The dotted names select
+/// the fields of the nested objects, and the arrays of objects are filtered element-wise.
+/// If the selection is absent or empty, the whole value is returned.
+///
+/// The strong `ETag` is computed over the filtered output, so that the different
+/// selections have the distinct validators. If the value of `If-None-Match` in a
+/// `GET` or `HEAD` request matches it, `304 Not Modified` is returned without the body.
+///
+/// The unknown fields are ignored by default, and rejected with `400 Bad Request`
+/// if `strict` is enabled. A field is unknown if none of the objects at its position
+/// has the field.
+#[derive(Debug)]
+pub struct JsonPartial<T> {
+    data: T,
+    fields: Option<Selection>,
+    strict: bool,
+}
+
+impl<T> JsonPartial<T>
+where
+    T: Serialize,
+{
+    /// Creates a `JsonPartial` from the value and the list of the selected fields.
+    pub fn new(data: T, fields: Option<&str>) -> Self {
+        Self {
+            data,
+            fields: fields.and_then(Selection::parse),
+            strict: false,
+        }
+    }
+
+    /// Sets whether to reject the selection containing the unknown fields.
+    ///
+    /// The default value is `false`.
+    pub fn strict(self, strict: bool) -> Self {
+        Self { strict, ..self }
+    }
+
+    fn to_vec(&self) -> Result<Vec<u8>, Error> {
+        let selection = match self.fields {
+            Some(ref selection) => selection,
+            None => {
+                return serde_json::to_vec(&self.data).map_err(crate::error::internal_server_error)
+            }
+        };
+        let value =
+            serde_json::to_value(&self.data).map_err(crate::error::internal_server_error)?;
+
+        let mut found = BTreeSet::new();
+        let value = selection.filter(value, "", &mut found);
+        if self.strict {
+            let unknown: Vec<_> = selection
+                .paths("")
+                .into_iter()
+                .filter(|path| !found.contains(path))
+                .collect();
+            if !unknown.is_empty() {
+                return Err(crate::error::bad_request(format!(
+                    "unknown fields: {}",
+                    unknown.join(",")
+                )));
+            }
+        }
+
+        serde_json::to_vec(&value).map_err(crate::error::internal_server_error)
+    }
+}
+
+/// The tree of the selected fields.
+#[derive(Debug, Default)]
+struct Selection {
+    children: BTreeMap<String, Selection>,
+    whole: bool,
+}
+
+impl Selection {
+    fn parse(fields: &str) -> Option<Self> {
+        let mut selection = Selection::default();
+        for field in fields.split(',').map(str::trim).filter(|f| !f.is_empty()) {
+            let mut node = &mut selection;
+            for name in field.split('.').filter(|name| !name.is_empty()) {
+                node = node.children.entry(name.to_owned()).or_default();
+            }
+            node.whole = true;
+        }
+        if selection.children.is_empty() {
+            None
+        } else {
+            Some(selection)
+        }
+    }
+
+    /// Returns the dotted names of the selected fields, including the intermediate ones.
+    fn paths(&self, prefix: &str) -> Vec<String> {
+        let mut paths = vec![];
+        for (name, child) in &self.children {
+            let path = join(prefix, name);
+            paths.extend(child.paths(&path));
+            paths.push(path);
+        }
+        paths
+    }
+
+    /// Retains the selected fields in the value, and records the found ones into `found`.
+    fn filter(&self, value: Value, prefix: &str, found: &mut BTreeSet<String>) -> Value {
+        match value {
+            Value::Object(mut map) => {
+                let mut filtered = Map::new();
+                for (name, child) in &self.children {
+                    if let Some(value) = map.remove(name) {
+                        let path = join(prefix, name);
+                        let value = if child.whole {
+                            found.extend(child.paths(&path));
+                            value
+                        } else {
+                            child.filter(value, &path, found)
+                        };
+                        found.insert(path);
+                        filtered.insert(name.clone(), value);
+                    }
+                }
+                Value::Object(filtered)
+            }
+            Value::Array(items) => {
+                if items.is_empty() {
+                    // nothing to tell the unknown fields from.
+                    found.extend(self.paths(prefix));
+                }
+                Value::Array(
+                    items
+                        .into_iter()
+                        .map(|item| self.filter(item, prefix, found))
+                        .collect(),
+                )
+            }
+            Value::Null => {
+                found.extend(self.paths(prefix));
+                Value::Null
+            }
+            value => value,
+        }
+    }
+}
+
+fn join(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.to_owned()
+    } else {
+        format!("{}.{}", prefix, name)
+    }
+}
+
+/// Computes the strong entity tag from the length and the FNV-1a hash of the body.
+fn entity_tag(body: &[u8]) -> String {
+    let hash = body.iter().fold(0xcbf2_9ce4_8422_2325_u64, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+    });
+    format!("\"{:x}-{:016x}\"", body.len(), hash)
+}
+
+/// Returns whether `If-None-Match` matches the entity tag, by the weak comparison.
+fn if_none_match(request: &Request<()>, etag: &str) -> bool {
+    request
+        .headers()
+        .get_all(IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+impl<T> IntoResponse for JsonPartial<T>
+where
+    T: Serialize,
+{
+    type Body = Vec<u8>;
+    type Error = Error;
+
+    fn into_response(self, request: &Request<()>) -> Result<Response<Self::Body>, Self::Error> {
+        let body = self.to_vec()?;
+        let etag = entity_tag(&body);
+
+        let mut response = if (request.method() == Method::GET || request.method() == Method::HEAD)
+            && if_none_match(request, &etag)
+        {
+            let mut response = Response::new(vec![]);
+            *response.status_mut() = StatusCode::NOT_MODIFIED;
+            response
+        } else {
+            super::make_response(body, "application/json")
+        };
+        response.headers_mut().insert(
+            ETAG,
+            HeaderValue::from_shared(etag.into()).map_err(crate::error::internal_server_error)?,
+        );
+        Ok(response)
+    }
+}
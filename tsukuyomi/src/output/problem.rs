@@ -0,0 +1,291 @@
+//! Error responses in the format of "Problem Details for HTTP APIs" (RFC 7807).
+//!
+//! A [`Problem`] is an `HttpError` rendered as `application/problem+json`:
+//!
+//! ```
+//! # use tsukuyomi::{config::prelude::*, output::problem::Problem, App};
+//! # use http::StatusCode;
+//! let app = App::create(
+//!     path!("/accounts/:id") //
+//!         .to(endpoint::get().call(|id: u32| -> Result<String, Problem> {
+//!             Err(Problem::new(StatusCode::FORBIDDEN)
+//!                 .type_uri("https://example.com/probs/out-of-credit")
+//!                 .detail("your current balance is 30, but that costs 50")
+//!                 .instance(format!("/accounts/{}", id))
+//!                 .extension("balance", 30))
+//!         })),
+//! )
+//! .unwrap()
+//! .problem_details(true);
+//! # drop(app);
+//! ```
+//!
+//! The members are always serialized in the same order: `type`, `title`, `status`,
+//! `detail` and `instance`, followed by the extension members sorted by name.
+//! With `AppBase::problem_details`, the other errors generated in the application,
+//! such as `404 Not Found` for unknown paths or the rejections by the extractors,
+//! are also converted into this format.
+//!
+//! [`Problem`]: ./struct.Problem.html
+
+use {
+    crate::{error::HttpError, handler::AllowedMethods},
+    http::{
+        header::{HeaderValue, CONTENT_LENGTH, CONTENT_TYPE},
+        Request, Response, StatusCode,
+    },
+    serde::{ser::SerializeMap, Serialize, Serializer},
+    std::{borrow::Cow, collections::BTreeMap, fmt},
+};
+
+/// The media type of the problem details in JSON.
+pub const CONTENT_TYPE_PROBLEM_JSON: &str = "application/problem+json";
+
+const STANDARD_MEMBERS: &[&str] = &["type", "title", "status", "detail", "instance"];
+
+/// A problem detail object, defined in RFC 7807.
+#[derive(Debug, Clone)]
+pub struct Problem {
+    type_uri: Cow<'static, str>,
+    title: Cow<'static, str>,
+    status: StatusCode,
+    detail: Option<String>,
+    instance: Option<String>,
+    extensions: BTreeMap<String, serde_json::Value>,
+}
+
+impl Problem {
+    /// Creates a `Problem` with the specified status code.
+    ///
+    /// The type is `about:blank`, and the title is the reason phrase of the status code.
+    pub fn new(status: StatusCode) -> Self {
+        Self {
+            type_uri: "about:blank".into(),
+            title: status.canonical_reason().unwrap_or("Unknown Error").into(),
+            status,
+            detail: None,
+            instance: None,
+            extensions: BTreeMap::new(),
+        }
+    }
+
+    /// Sets the URI reference that identifies the problem type.
+    pub fn type_uri(self, type_uri: impl Into<Cow<'static, str>>) -> Self {
+        Self {
+            type_uri: type_uri.into(),
+            ..self
+        }
+    }
+
+    /// Sets the short summary of the problem type.
+    pub fn title(self, title: impl Into<Cow<'static, str>>) -> Self {
+        Self {
+            title: title.into(),
+            ..self
+        }
+    }
+
+    /// Sets the explanation specific to this occurrence of the problem.
+    pub fn detail(self, detail: impl Into<String>) -> Self {
+        Self {
+            detail: Some(detail.into()),
+            ..self
+        }
+    }
+
+    /// Sets the URI reference that identifies this occurrence of the problem.
+    pub fn instance(self, instance: impl Into<String>) -> Self {
+        Self {
+            instance: Some(instance.into()),
+            ..self
+        }
+    }
+
+    /// Adds an extension member.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `name` is one of the standard members, or the value
+    /// cannot be serialized into JSON.
+    pub fn extension(mut self, name: impl Into<String>, value: impl Serialize) -> Self {
+        let name = name.into();
+        assert!(
+            !STANDARD_MEMBERS.contains(&name.as_str()),
+            "the extension member `{}' conflicts with the standard member",
+            name
+        );
+        let value = serde_json::to_value(value).expect("the extension member must be JSON");
+        self.extensions.insert(name, value);
+        self
+    }
+
+    /// Returns the status code of this problem.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// Returns the value of the extension member with the specified name.
+    pub fn get_extension(&self, name: &str) -> Option<&serde_json::Value> {
+        self.extensions.get(name)
+    }
+
+    /// Creates a `Problem` of `405 Method Not Allowed` with the extension member
+    /// `allowed`, which lists the methods that the resource accepts.
+    pub fn method_not_allowed(allowed_methods: &AllowedMethods) -> Self {
+        let allowed: Vec<_> = allowed_methods.iter().map(|m| m.as_str()).collect();
+        Self::new(StatusCode::METHOD_NOT_ALLOWED).extension("allowed", allowed)
+    }
+
+    fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("should be a valid JSON")
+    }
+}
+
+impl Serialize for Problem {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("type", &*self.type_uri)?;
+        map.serialize_entry("title", &*self.title)?;
+        map.serialize_entry("status", &self.status.as_u16())?;
+        if let Some(ref detail) = self.detail {
+            map.serialize_entry("detail", detail)?;
+        }
+        if let Some(ref instance) = self.instance {
+            map.serialize_entry("instance", instance)?;
+        }
+        for (name, value) in &self.extensions {
+            map.serialize_entry(name, value)?;
+        }
+        map.end()
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.status.as_u16(), self.title)?;
+        if let Some(ref detail) = self.detail {
+            write!(f, ": {}", detail)?;
+        }
+        Ok(())
+    }
+}
+
+impl HttpError for Problem {
+    type Body = String;
+
+    fn into_response(self, _: &Request<()>) -> Response<Self::Body> {
+        let mut response = Response::new(self.to_json());
+        *response.status_mut() = self.status;
+        response.headers_mut().insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static(CONTENT_TYPE_PROBLEM_JSON),
+        );
+        response
+    }
+}
+
+/// Renders an error in the application as a problem detail object.
+///
+/// The status code and the header fields of the error response, such as
+/// `Allow` or `Retry-After`, are kept. The message of the error is used as
+/// the detail of the client errors, but not of the server errors so that
+/// their internals are not exposed.
+pub fn render(err: crate::Error, request: &Request<()>) -> Response<String> {
+    let err = match err.downcast::<Problem>() {
+        Ok(problem) => return problem.into_response(request),
+        Err(err) => err,
+    };
+    let detail = if err.is::<StatusCode>() {
+        None
+    } else {
+        Some(err.to_string())
+    };
+    let (mut parts, _) = err.into_response(request).into_parts();
+
+    let mut problem = Problem::new(parts.status);
+    if let Some(detail) = detail {
+        if parts.status.is_client_error() {
+            problem = problem.detail(detail);
+        }
+    }
+
+    parts.headers.remove(CONTENT_LENGTH);
+    parts.headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static(CONTENT_TYPE_PROBLEM_JSON),
+    );
+    Response::from_parts(parts, problem.to_json())
+}
+
+/// A trait for converting the custom error types into `Problem`.
+///
+/// Only `status` is required, so the implementation for an error enum is
+/// typically a `match` on its variants:
+///
+/// ```
+/// # use tsukuyomi::output::problem::{IntoProblem, Problem};
+/// # use http::StatusCode;
+/// # use std::fmt;
+/// #[derive(Debug)]
+/// enum AccountError {
+///     NotFound(u32),
+///     Frozen,
+/// }
+///
+/// impl fmt::Display for AccountError {
+///     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+///         match self {
+///             AccountError::NotFound(id) => write!(f, "no such account: {}", id),
+///             AccountError::Frozen => f.write_str("the account is frozen"),
+///         }
+///     }
+/// }
+///
+/// impl IntoProblem for AccountError {
+///     fn status(&self) -> StatusCode {
+///         match self {
+///             AccountError::NotFound(..) => StatusCode::NOT_FOUND,
+///             AccountError::Frozen => StatusCode::FORBIDDEN,
+///         }
+///     }
+/// }
+///
+/// let problem: Problem = AccountError::Frozen.into_problem();
+/// assert_eq!(problem.status(), StatusCode::FORBIDDEN);
+/// ```
+pub trait IntoProblem: fmt::Display + Sized {
+    /// Returns the status code of the error.
+    fn status(&self) -> StatusCode;
+
+    /// Returns the URI reference that identifies the type of the error, if any.
+    fn type_uri(&self) -> Option<Cow<'static, str>> {
+        None
+    }
+
+    /// Adds the extension members of the error to the problem.
+    fn extensions(&self, problem: Problem) -> Problem {
+        problem
+    }
+
+    /// Converts the error into a `Problem`, whose detail is the message of the error.
+    fn into_problem(self) -> Problem {
+        let mut problem = Problem::new(self.status()).detail(self.to_string());
+        if let Some(type_uri) = self.type_uri() {
+            problem = problem.type_uri(type_uri);
+        }
+        self.extensions(problem)
+    }
+}
+
+impl IntoProblem for Problem {
+    fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    fn into_problem(self) -> Problem {
+        self
+    }
+}
@@ -0,0 +1,191 @@
+//! Parsing of the header field `Range` (RFC 7233).
+//!
+//! Only a single range in the unit `bytes` is supported. The requests with
+//! multiple ranges are served with the whole representation, which is allowed
+//! since a server may ignore `Range`.
+
+use {
+    http::{
+        header::{HeaderValue, RANGE},
+        Method, Request,
+    },
+    std::{fmt, ops::Range},
+};
+
+/// A byte range specified in `Range`, before being resolved with the length of the representation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteRange {
+    /// `bytes=first-last`
+    FromTo(u64, u64),
+    /// `bytes=first-`
+    From(u64),
+    /// `bytes=-suffix_len`, the last `suffix_len` bytes.
+    Last(u64),
+}
+
+/// The error that occurs when the value of `Range` is not acceptable.
+///
+/// The header field should be ignored in either case.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeError {
+    /// The value is not a valid byte range specifier.
+    InvalidSyntax,
+    /// The value contains multiple ranges, which are not supported.
+    MultipleRanges,
+}
+
+impl fmt::Display for RangeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RangeError::InvalidSyntax => f.write_str("invalid byte range specifier"),
+            RangeError::MultipleRanges => f.write_str("multiple byte ranges are not supported"),
+        }
+    }
+}
+
+impl std::error::Error for RangeError {}
+
+/// Parses the value of `Range`.
+pub fn parse(value: &str) -> Result<ByteRange, RangeError> {
+    let value = value.trim();
+    if value.len() < 6 || !value[..6].eq_ignore_ascii_case("bytes=") {
+        return Err(RangeError::InvalidSyntax);
+    }
+
+    let mut specs = value[6..]
+        .split(',')
+        .map(str::trim)
+        .filter(|spec| !spec.is_empty());
+    let spec = specs.next().ok_or(RangeError::InvalidSyntax)?;
+    if specs.next().is_some() {
+        return Err(RangeError::MultipleRanges);
+    }
+
+    let pos = spec.find('-').ok_or(RangeError::InvalidSyntax)?;
+    let (first, last) = (&spec[..pos], &spec[pos + 1..]);
+    match (first.is_empty(), last.is_empty()) {
+        (false, false) => {
+            let (first, last) = (parse_pos(first)?, parse_pos(last)?);
+            if first > last {
+                return Err(RangeError::InvalidSyntax);
+            }
+            Ok(ByteRange::FromTo(first, last))
+        }
+        (false, true) => parse_pos(first).map(ByteRange::From),
+        (true, false) => parse_pos(last).map(ByteRange::Last),
+        (true, true) => Err(RangeError::InvalidSyntax),
+    }
+}
+
+fn parse_pos(s: &str) -> Result<u64, RangeError> {
+    // `u64::from_str` accepts a leading `+`, which is not allowed here.
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(RangeError::InvalidSyntax);
+    }
+    s.parse().map_err(|_| RangeError::InvalidSyntax)
+}
+
+/// Returns the range requested by a `GET` request, or `None` if `Range` is missing or should be ignored.
+pub(crate) fn from_request(request: &Request<()>) -> Option<ByteRange> {
+    match request.headers().get(RANGE) {
+        Some(value) if request.method() == Method::GET => value
+            .to_str()
+            .map_err(|_| RangeError::InvalidSyntax)
+            .and_then(parse)
+            .map_err(|err| log::debug!("ignoring the header field Range: {}", err))
+            .ok(),
+        _ => None,
+    }
+}
+
+impl ByteRange {
+    /// Resolves the range with the length of the representation.
+    ///
+    /// The last position beyond the end is truncated to the length.
+    /// This method returns `None` if the range is not satisfiable.
+    pub fn resolve(self, len: u64) -> Option<Range<u64>> {
+        match self {
+            ByteRange::FromTo(first, _) | ByteRange::From(first) if first >= len => None,
+            ByteRange::FromTo(first, last) => Some(first..last.min(len - 1) + 1),
+            ByteRange::From(first) => Some(first..len),
+            ByteRange::Last(0) => None,
+            ByteRange::Last(_) if len == 0 => None,
+            ByteRange::Last(suffix_len) => Some(len.saturating_sub(suffix_len)..len),
+        }
+    }
+}
+
+/// Creates the value of `Content-Range` for the resolved range.
+///
+/// If `range` is `None`, the value for the unsatisfiable range (`bytes */len`) is returned.
+pub fn content_range(range: Option<&Range<u64>>, len: u64) -> HeaderValue {
+    let value = match range {
+        Some(range) => format!("bytes {}-{}/{}", range.start, range.end - 1, len),
+        None => format!("bytes */{}", len),
+    };
+    HeaderValue::from_str(&value).expect("should be a valid header value")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_ranges() {
+        assert_eq!(parse("bytes=0-499"), Ok(ByteRange::FromTo(0, 499)));
+        assert_eq!(parse("bytes=100-"), Ok(ByteRange::From(100)));
+        assert_eq!(parse("bytes=-100"), Ok(ByteRange::Last(100)));
+        assert_eq!(parse("BYTES=5-5"), Ok(ByteRange::FromTo(5, 5)));
+        assert_eq!(parse("bytes=5-5,"), Ok(ByteRange::FromTo(5, 5)));
+    }
+
+    #[test]
+    fn parse_invalid_syntax() {
+        for value in &[
+            "",
+            "bytes",
+            "bytes=",
+            "bytes=-",
+            "bytes=abc",
+            "bytes=10",
+            "bytes=10-5",
+            "bytes=+1-2",
+            "bytes=1--2",
+            "bytes=0x10-",
+            "items=0-10",
+            "bytes=18446744073709551616-",
+            "bytes=-99999999999999999999",
+        ] {
+            assert_eq!(parse(value), Err(RangeError::InvalidSyntax), "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn parse_multiple_ranges() {
+        assert_eq!(parse("bytes=0-1,3-4"), Err(RangeError::MultipleRanges));
+        assert_eq!(parse("bytes=0-1, -5"), Err(RangeError::MultipleRanges));
+    }
+
+    #[test]
+    fn resolve_ranges() {
+        assert_eq!(ByteRange::FromTo(0, 499).resolve(1000), Some(0..500));
+        assert_eq!(ByteRange::FromTo(900, 1999).resolve(1000), Some(900..1000));
+        assert_eq!(ByteRange::FromTo(1000, 1999).resolve(1000), None);
+        assert_eq!(ByteRange::From(100).resolve(1000), Some(100..1000));
+        assert_eq!(ByteRange::From(1000).resolve(1000), None);
+        assert_eq!(ByteRange::Last(100).resolve(1000), Some(900..1000));
+        assert_eq!(ByteRange::Last(5000).resolve(1000), Some(0..1000));
+        assert_eq!(ByteRange::Last(0).resolve(1000), None);
+        assert_eq!(ByteRange::Last(10).resolve(0), None);
+        assert_eq!(
+            ByteRange::FromTo(0, std::u64::MAX).resolve(std::u64::MAX),
+            Some(0..std::u64::MAX)
+        );
+    }
+
+    #[test]
+    fn format_content_range() {
+        assert_eq!(content_range(Some(&(0..500)), 1000), "bytes 0-499/1000");
+        assert_eq!(content_range(None, 1000), "bytes */1000");
+    }
+}
@@ -1,9 +1,15 @@
 use {
-    super::*,
-    http::{Response, StatusCode},
+    super::{header::SafeHeaderValue, *},
+    http::{header::LOCATION, Response, StatusCode},
     std::borrow::Cow,
+    url::form_urlencoded,
 };
 
+/// A response of redirection.
+///
+/// The control characters in the location are percent-encoded by
+/// `SafeHeaderValue::uri` when the response is created, so they never
+/// terminate the header field `Location`.
 #[derive(Debug, Clone)]
 pub struct Redirect {
     status: StatusCode,
@@ -20,6 +26,26 @@ impl Redirect {
             location: location.into(),
         }
     }
+
+    /// Appends a parameter to the query string of the location.
+    ///
+    /// The name and the value are encoded as `application/x-www-form-urlencoded`,
+    /// so the values taken from the request can be passed as they are.
+    pub fn query(self, name: &str, value: &str) -> Self {
+        let mut location = self.location.into_owned();
+        let fragment = location.find('#').map(|pos| location.split_off(pos));
+        location.push(if location.contains('?') { '&' } else { '?' });
+        location.push_str(
+            &form_urlencoded::Serializer::new(String::new())
+                .append_pair(name, value)
+                .finish(),
+        );
+        location.extend(fragment);
+        Self {
+            location: location.into(),
+            ..self
+        }
+    }
 }
 
 impl IntoResponse for Redirect {
@@ -28,11 +54,12 @@ impl IntoResponse for Redirect {
 
     #[inline]
     fn into_response(self, _: &Request<()>) -> Result<Response<Self::Body>, Self::Error> {
-        Ok(Response::builder()
-            .status(self.status)
-            .header("location", &*self.location)
-            .body(())
-            .expect("should be a valid response"))
+        let mut response = Response::new(());
+        *response.status_mut() = self.status;
+        response
+            .headers_mut()
+            .insert(LOCATION, SafeHeaderValue::uri(&self.location).into());
+        Ok(response)
     }
 }
 
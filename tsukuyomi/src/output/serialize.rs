@@ -0,0 +1,230 @@
+use {
+    crate::{
+        error::Error,
+        future::{Async, Poll, TryFuture},
+        input::{accept::Accept, Input},
+        responder::Responder,
+    },
+    http::{
+        header::{HeaderValue, CONTENT_TYPE, VARY},
+        Response, StatusCode,
+    },
+    mime::Mime,
+    std::{fmt, sync::Arc},
+};
+
+lazy_static::lazy_static! {
+    static ref DEFAULT_ENCODERS: Encoders = Encoders::new();
+}
+
+/// A trait representing the encoders of the values serialized by `Serialize`.
+///
+/// This trait is implemented for the functions with the appropriate signature.
+pub trait Encoder: Send + Sync + 'static {
+    /// Encodes the value into the bytes.
+    fn encode(&self, value: &dyn erased_serde::Serialize) -> Result<Vec<u8>, Error>;
+}
+
+impl<F> Encoder for F
+where
+    F: Fn(&dyn erased_serde::Serialize) -> Result<Vec<u8>, Error> + Send + Sync + 'static,
+{
+    fn encode(&self, value: &dyn erased_serde::Serialize) -> Result<Vec<u8>, Error> {
+        (*self)(value)
+    }
+}
+
+/// A registry of the encoders used by `Serialize`, keyed by the media type.
+///
+/// The registry is looked up from the states of the current scope, so that the
+/// available encoders can be configured per scope by `config::state`. If it is
+/// not registered, the default one created by `Encoders::new` is used.
+#[derive(Clone)]
+pub struct Encoders {
+    encoders: Vec<(Mime, Arc<dyn Encoder>)>,
+}
+
+impl fmt::Debug for Encoders {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.encoders.iter().map(|(mime, _)| mime))
+            .finish()
+    }
+}
+
+impl Default for Encoders {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Encoders {
+    /// Creates an `Encoders` with the built-in encoders.
+    ///
+    /// `application/json` is always available, and preferred when the client
+    /// does not send `Accept`. The encoders of `application/msgpack`,
+    /// `application/cbor` and `application/xml` are added if the features
+    /// `msgpack`, `cbor` and `xml` are enabled, respectively.
+    pub fn new() -> Self {
+        #[allow(unused_mut)]
+        let mut encoders = Self { encoders: vec![] }.register(
+            mime::APPLICATION_JSON,
+            |value: &dyn erased_serde::Serialize| {
+                serde_json::to_vec(value).map_err(crate::error::internal_server_error)
+            },
+        );
+
+        #[cfg(feature = "msgpack")]
+        {
+            encoders = encoders.register(
+                "application/msgpack".parse().expect("valid mime"),
+                |value: &dyn erased_serde::Serialize| {
+                    rmp_serde::to_vec_named(value).map_err(crate::error::internal_server_error)
+                },
+            );
+        }
+
+        #[cfg(feature = "cbor")]
+        {
+            encoders = encoders.register(
+                "application/cbor".parse().expect("valid mime"),
+                |value: &dyn erased_serde::Serialize| {
+                    serde_cbor::to_vec(&value).map_err(crate::error::internal_server_error)
+                },
+            );
+        }
+
+        #[cfg(feature = "xml")]
+        {
+            encoders = encoders.register(
+                "application/xml".parse().expect("valid mime"),
+                |value: &dyn erased_serde::Serialize| {
+                    serde_xml_rs::to_string(&value)
+                        .map(String::into_bytes)
+                        .map_err(|err| crate::error::internal_server_error(err.to_string()))
+                },
+            );
+        }
+
+        encoders
+    }
+
+    /// Registers an encoder of the specified media type.
+    ///
+    /// The encoder replaces the existing one of the same type, and otherwise
+    /// is preferred after the already registered ones.
+    pub fn register(mut self, mime: Mime, encoder: impl Encoder) -> Self {
+        let encoder = Arc::new(encoder);
+        match self.encoders.iter_mut().find(|(m, _)| *m == mime) {
+            Some(entry) => entry.1 = encoder,
+            None => self.encoders.push((mime, encoder)),
+        }
+        self
+    }
+
+    /// Removes the encoder of the specified media type.
+    pub fn remove(mut self, mime: &Mime) -> Self {
+        self.encoders.retain(|(m, _)| m != mime);
+        self
+    }
+
+    /// Selects the encoder based on `Accept`.
+    fn negotiate(&self, accept: Option<&Accept>) -> Result<&(Mime, Arc<dyn Encoder>), Error> {
+        let selected = match accept {
+            Some(accept) => {
+                let available: Vec<Mime> =
+                    self.encoders.iter().map(|(mime, _)| mime.clone()).collect();
+                let negotiated = accept.negotiate(&available).first().cloned().cloned();
+                negotiated.and_then(|mime| self.encoders.iter().find(|(m, _)| *m == mime))
+            }
+            None => self.encoders.first(),
+        };
+        selected.ok_or_else(|| {
+            crate::error::custom(
+                StatusCode::NOT_ACCEPTABLE,
+                format!(
+                    "no acceptable representation (available: {})",
+                    self.encoders
+                        .iter()
+                        .map(|(mime, _)| mime.as_ref())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            )
+        })
+    }
+}
+
+/// A `Responder` that serializes the value with the encoder negotiated with `Accept`.
+///
+/// The value is serialized when the response is created, by the encoder selected
+/// from the `Encoders` of the current scope. `Content-Type` is set to the media type
+/// of the encoder, and `Vary: Accept` is added to the response. If no encoder is
+/// acceptable for the client, the request is rejected with `406 Not Acceptable`.
+///
+/// # Example
+///
+/// ```
+/// # use tsukuyomi::{config::prelude::*, output::Serialize, App};
+/// #[derive(serde::Serialize)]
+/// struct User {
+///     name: String,
+/// }
+///
+/// let app = App::create(
+///     path!("/user") //
+///         .to(endpoint::get().call_async(|| {
+///             Ok::<_, tsukuyomi::Error>(Serialize(User {
+///                 name: "alice".into(),
+///             }))
+///         })),
+/// );
+/// # drop(app);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Serialize<T>(pub T);
+
+impl<T> Responder for Serialize<T>
+where
+    T: serde::Serialize,
+{
+    type Response = Response<Vec<u8>>;
+    type Error = Error;
+    type Respond = SerializeRespond<T>; // private
+
+    fn respond(self) -> Self::Respond {
+        SerializeRespond(Some(self.0))
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct SerializeRespond<T>(Option<T>);
+
+impl<T> TryFuture for SerializeRespond<T>
+where
+    T: serde::Serialize,
+{
+    type Ok = Response<Vec<u8>>;
+    type Error = Error;
+
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        let value = self.0.take().expect("the future has already been polled.");
+
+        // The field is staged so that it is also added to the error responses.
+        input.response().append(VARY, "accept")?;
+
+        let accept = Accept::from_headers(input.request.headers());
+        let encoders = input
+            .state::<Encoders>()
+            .unwrap_or_else(|| &*DEFAULT_ENCODERS);
+        let (mime, encoder) = encoders.negotiate(accept.as_ref())?;
+
+        let body = encoder.encode(&value)?;
+        let mut response = Response::new(body);
+        response.headers_mut().insert(
+            CONTENT_TYPE,
+            HeaderValue::from_str(mime.as_ref()).map_err(crate::error::internal_server_error)?,
+        );
+        Ok(Async::Ready(response))
+    }
+}
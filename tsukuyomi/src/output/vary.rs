@@ -0,0 +1,96 @@
+//! Management of the `Vary` header field.
+//!
+//! The components that select the representation based on the request headers,
+//! such as the compression or the content negotiation, add the name of the header
+//! field to `Vary` via `add` so that they do not overwrite each other.
+
+use http::header::{HeaderMap, HeaderValue, VARY};
+
+/// Adds the header name(s) to `Vary` in the header map.
+///
+/// The value of `name` may be a comma-separated list of header names.
+/// All values of `Vary` in the map are merged into a single field, in which the
+/// names are deduplicated case-insensitively while keeping the order of their
+/// first occurrence. If either side contains `*`, the result is `*`.
+///
+/// # Panics
+///
+/// This function panics if `name` is not a valid header value.
+pub fn add(headers: &mut HeaderMap, name: &str) {
+    let mut members: Vec<String> = vec![];
+    let candidates = headers
+        .get_all(VARY)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .chain(name.split(','))
+        .map(str::trim)
+        .filter(|member| !member.is_empty());
+    for member in candidates {
+        if !members.iter().any(|m| m.eq_ignore_ascii_case(member)) {
+            members.push(member.to_owned());
+        }
+    }
+
+    if members.is_empty() {
+        return;
+    }
+    let value = if members.iter().any(|m| m == "*") {
+        HeaderValue::from_static("*")
+    } else {
+        HeaderValue::from_str(&members.join(", ")).expect("invalid header name in Vary")
+    };
+    headers.insert(VARY, value);
+}
+
+/// Returns whether `Vary` in the header map contains the specified header name.
+pub fn contains(headers: &HeaderMap, name: &str) -> bool {
+    headers
+        .get_all(VARY)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|member| {
+            let member = member.trim();
+            member == "*" || member.eq_ignore_ascii_case(name)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vary(headers: &HeaderMap) -> Vec<&str> {
+        headers
+            .get_all(VARY)
+            .iter()
+            .map(|value| value.to_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn merge_and_deduplicate() {
+        let mut headers = HeaderMap::new();
+        headers.append(VARY, HeaderValue::from_static("Accept"));
+        headers.append(VARY, HeaderValue::from_static("origin, accept"));
+        add(&mut headers, "accept-encoding");
+        add(&mut headers, "ACCEPT-ENCODING, Origin");
+        assert_eq!(vary(&headers), vec!["Accept, origin, accept-encoding"]);
+        assert!(contains(&headers, "Accept-Encoding"));
+        assert!(!contains(&headers, "cookie"));
+    }
+
+    #[test]
+    fn asterisk_dominates() {
+        let mut headers = HeaderMap::new();
+        headers.insert(VARY, HeaderValue::from_static("*"));
+        add(&mut headers, "accept");
+        assert_eq!(vary(&headers), vec!["*"]);
+        assert!(contains(&headers, "cookie"));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(VARY, HeaderValue::from_static("accept"));
+        add(&mut headers, "*");
+        assert_eq!(vary(&headers), vec!["*"]);
+    }
+}
@@ -0,0 +1,420 @@
+//! Declarative authorization policies of the routes.
+//!
+//! The caller of a request is identified as a `Principal` by the extractor passed
+//! to the `Authorize` modifier, and the policies attached to the routes by
+//! `Route::policy` decide whether the principal may access them. The policies are
+//! evaluated before the handler of the route, so the request body is not read
+//! if the access is denied.
+//!
+//! ```
+//! # use tsukuyomi::{config::prelude::*, extractor, policy::{self, Authorize, Principal}, App};
+//! # use tsukuyomi::util::Never;
+//! let authorize = Authorize::new(extractor::ready(|input| {
+//!     let principal = input
+//!         .request
+//!         .headers()
+//!         .get("x-user")
+//!         .and_then(|h| h.to_str().ok())
+//!         .map(|user| Principal::new(user).role("admin"));
+//!     Ok::<_, Never>((principal,))
+//! }));
+//!
+//! let app = App::create(
+//!     chain![
+//!         path!("/admin")
+//!             .to(endpoint::get().reply("admin"))
+//!             .policy(policy::require_role("admin")),
+//!         path!("/reports")
+//!             .to(endpoint::get().reply("reports"))
+//!             .policy(policy::any_of(vec![
+//!                 policy::require_role("admin"),
+//!                 policy::require_role("auditor"),
+//!             ])),
+//!     ]
+//!     .modify(authorize),
+//! );
+//! # drop(app);
+//! ```
+
+use {
+    crate::{
+        error::Error,
+        extractor::Extractor,
+        future::{Poll, TryFuture},
+        handler::{AllowedMethods, Handler, ModifyHandler},
+        i18n::Args,
+        input::{
+            localmap::{local_key, LocalData},
+            Input,
+        },
+    },
+    futures01::{Future, IntoFuture},
+    http::StatusCode,
+    std::{fmt, sync::Arc},
+};
+
+/// The identity of the caller of a request, along with its roles.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Principal {
+    id: String,
+    roles: Vec<String>,
+}
+
+impl Principal {
+    /// Creates a `Principal` with the specified identifier and no role.
+    pub fn new(id: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            roles: vec![],
+        }
+    }
+
+    /// Appends a role to this principal.
+    pub fn role(mut self, role: impl Into<String>) -> Self {
+        self.roles.push(role.into());
+        self
+    }
+
+    /// Returns the identifier of this principal.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Returns the roles of this principal.
+    pub fn roles(&self) -> &[String] {
+        &self.roles
+    }
+
+    /// Returns `true` if this principal has the specified role.
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|r| r == role)
+    }
+}
+
+impl LocalData for Principal {
+    local_key! {
+        /// The local key for the principal identified by `Authorize`.
+        const KEY: Self;
+    }
+}
+
+/// A future that resolves whether a policy allows the access.
+pub type Decision = Box<dyn Future<Item = bool, Error = Error> + Send + 'static>;
+
+type CustomCheck = Box<dyn Fn(&Principal, &mut Input<'_>) -> Decision + Send + Sync + 'static>;
+
+/// A rule deciding whether a principal may access a route.
+///
+/// The `Display` implementation describes the policy, such as
+/// `any_of(role(admin), role(auditor))`, and is shown in `AppBase::debug_routes`.
+#[derive(Clone)]
+pub struct Policy(Arc<Kind>);
+
+enum Kind {
+    Role(String),
+    AnyOf(Vec<Policy>),
+    AllOf(Vec<Policy>),
+    Custom {
+        description: String,
+        check: CustomCheck,
+    },
+}
+
+/// Creates a `Policy` that requires the principal to have the specified role.
+pub fn require_role(role: impl Into<String>) -> Policy {
+    Policy(Arc::new(Kind::Role(role.into())))
+}
+
+/// Creates a `Policy` that allows the access if any of the policies allows it.
+pub fn any_of(policies: impl IntoIterator<Item = Policy>) -> Policy {
+    Policy(Arc::new(Kind::AnyOf(policies.into_iter().collect())))
+}
+
+/// Creates a `Policy` that allows the access if all of the policies allow it.
+pub fn all_of(policies: impl IntoIterator<Item = Policy>) -> Policy {
+    Policy(Arc::new(Kind::AllOf(policies.into_iter().collect())))
+}
+
+/// Creates a `Policy` decided by the specified function, which may consult the
+/// states or the other resources asynchronously.
+///
+/// The description is used as the display of the policy.
+pub fn custom<F, R>(description: impl Into<String>, check: F) -> Policy
+where
+    F: Fn(&Principal, &mut Input<'_>) -> R + Send + Sync + 'static,
+    R: IntoFuture<Item = bool, Error = Error>,
+    R::Future: Send + 'static,
+{
+    Policy(Arc::new(Kind::Custom {
+        description: description.into(),
+        check: Box::new(move |principal, input| Box::new(check(principal, input).into_future())),
+    }))
+}
+
+impl Policy {
+    /// Evaluates this policy for the principal.
+    fn evaluate(&self, principal: &Principal, input: &mut Input<'_>) -> Decision {
+        match *self.0 {
+            Kind::Role(ref role) => Box::new(futures01::future::ok(principal.has_role(role))),
+            Kind::AnyOf(ref policies) => {
+                let decisions: Vec<_> = policies
+                    .iter()
+                    .map(|policy| policy.evaluate(principal, input))
+                    .collect();
+                Box::new(
+                    futures01::future::join_all(decisions)
+                        .map(|decisions| decisions.into_iter().any(|allowed| allowed)),
+                )
+            }
+            Kind::AllOf(ref policies) => {
+                let decisions: Vec<_> = policies
+                    .iter()
+                    .map(|policy| policy.evaluate(principal, input))
+                    .collect();
+                Box::new(
+                    futures01::future::join_all(decisions)
+                        .map(|decisions| decisions.into_iter().all(|allowed| allowed)),
+                )
+            }
+            Kind::Custom { ref check, .. } => check(principal, input),
+        }
+    }
+}
+
+impl fmt::Display for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn fmt_list(f: &mut fmt::Formatter<'_>, name: &str, policies: &[Policy]) -> fmt::Result {
+            write!(f, "{}(", name)?;
+            for (i, policy) in policies.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(", ")?;
+                }
+                write!(f, "{}", policy)?;
+            }
+            f.write_str(")")
+        }
+        match *self.0 {
+            Kind::Role(ref role) => write!(f, "role({})", role),
+            Kind::AnyOf(ref policies) => fmt_list(f, "any_of", policies),
+            Kind::AllOf(ref policies) => fmt_list(f, "all_of", policies),
+            Kind::Custom {
+                ref description, ..
+            } => f.write_str(description),
+        }
+    }
+}
+
+impl fmt::Debug for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Policy")
+            .field(&format_args!("{}", self))
+            .finish()
+    }
+}
+
+fn unauthenticated() -> Error {
+    crate::error::localized(
+        StatusCode::UNAUTHORIZED,
+        "error.auth.unauthenticated",
+        Args::new(),
+        "the request is not authenticated",
+    )
+}
+
+fn forbidden() -> Error {
+    crate::error::localized(
+        StatusCode::FORBIDDEN,
+        "error.auth.forbidden",
+        Args::new(),
+        "the access to the resource is not allowed",
+    )
+}
+
+// ==== Authorize ====
+
+/// A `ModifyHandler` that identifies the caller with the extractor, before the
+/// policies of the routes in the scope are evaluated.
+///
+/// The extracted principal is stored in the local map, so the handlers can
+/// obtain it by `policy::principal`. The requests without the principal are
+/// rejected with `401 Unauthorized` by the routes with a policy, and allowed by
+/// the other routes.
+#[derive(Debug, Clone)]
+pub struct Authorize<E> {
+    extractor: Arc<E>,
+}
+
+impl<E> Authorize<E>
+where
+    E: Extractor<Output = (Option<Principal>,)>,
+{
+    /// Creates an `Authorize` with the extractor of the principal.
+    pub fn new(extractor: E) -> Self {
+        Self {
+            extractor: Arc::new(extractor),
+        }
+    }
+}
+
+impl<E, H> ModifyHandler<H> for Authorize<E>
+where
+    E: Extractor<Output = (Option<Principal>,)>,
+    H: Handler,
+{
+    type Output = H::Output;
+    type Handler = AuthorizeHandler<E, H>; // private
+
+    fn modify(&self, inner: H) -> Self::Handler {
+        AuthorizeHandler {
+            inner,
+            extractor: self.extractor.clone(),
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct AuthorizeHandler<E, H> {
+    inner: H,
+    extractor: Arc<E>,
+}
+
+impl<E, H> Handler for AuthorizeHandler<E, H>
+where
+    E: Extractor<Output = (Option<Principal>,)>,
+    H: Handler,
+{
+    type Output = H::Output;
+    type Error = Error;
+    type Handle = AuthorizeHandle<E::Extract, H::Handle>; // private
+
+    fn allowed_methods(&self) -> Option<&AllowedMethods> {
+        self.inner.allowed_methods()
+    }
+
+    fn handle(&self) -> Self::Handle {
+        AuthorizeHandle {
+            extract: Some(self.extractor.extract()),
+            inner: self.inner.handle(),
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct AuthorizeHandle<E, H> {
+    extract: Option<E>,
+    inner: H,
+}
+
+impl<E, H> TryFuture for AuthorizeHandle<E, H>
+where
+    E: TryFuture<Ok = (Option<Principal>,)>,
+    E::Error: Into<Error>,
+    H: TryFuture,
+{
+    type Ok = H::Ok;
+    type Error = Error;
+
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        if let Some(ref mut extract) = self.extract {
+            let (principal,) = futures01::try_ready!(extract.poll_ready(input).map_err(Into::into));
+            if let Some(principal) = principal {
+                principal.insert_into(input.locals);
+            }
+        }
+        self.extract = None;
+        self.inner.poll_ready(input).map_err(Into::into)
+    }
+}
+
+// ==== Authorized ====
+
+/// The handler of a route with a policy, created by `Route::policy`.
+#[allow(missing_debug_implementations)]
+pub struct Authorized<H> {
+    inner: H,
+    policy: Policy,
+}
+
+impl<H> Authorized<H> {
+    pub(crate) fn new(inner: H, policy: Policy) -> Self {
+        Self { inner, policy }
+    }
+}
+
+impl<H> Handler for Authorized<H>
+where
+    H: Handler,
+{
+    type Output = H::Output;
+    type Error = Error;
+    type Handle = AuthorizedHandle<H::Handle>; // private
+
+    fn allowed_methods(&self) -> Option<&AllowedMethods> {
+        self.inner.allowed_methods()
+    }
+
+    fn handle(&self) -> Self::Handle {
+        AuthorizedHandle {
+            state: AuthorizedState::Init(self.policy.clone()),
+            inner: self.inner.handle(),
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct AuthorizedHandle<H> {
+    state: AuthorizedState,
+    inner: H,
+}
+
+enum AuthorizedState {
+    Init(Policy),
+    Evaluating(Decision),
+    Allowed,
+}
+
+impl<H> TryFuture for AuthorizedHandle<H>
+where
+    H: TryFuture,
+{
+    type Ok = H::Ok;
+    type Error = Error;
+
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        loop {
+            self.state = match self.state {
+                AuthorizedState::Init(ref policy) => {
+                    let principal = Principal::get(input.locals)
+                        .cloned()
+                        .ok_or_else(unauthenticated)?;
+                    AuthorizedState::Evaluating(policy.evaluate(&principal, input))
+                }
+                AuthorizedState::Evaluating(ref mut decision) => {
+                    if !futures01::try_ready!(decision.poll()) {
+                        return Err(forbidden());
+                    }
+                    AuthorizedState::Allowed
+                }
+                AuthorizedState::Allowed => {
+                    return self.inner.poll_ready(input).map_err(Into::into)
+                }
+            };
+        }
+    }
+}
+
+/// Creates an `Extractor` that returns the principal identified by `Authorize`.
+///
+/// The request is rejected with `401 Unauthorized` if no principal is identified.
+pub fn principal() -> impl Extractor<
+    Output = (Principal,),
+    Error = Error,
+    Extract = impl TryFuture<Ok = (Principal,), Error = Error> + Send + 'static,
+> {
+    crate::extractor::ready(|input| {
+        Principal::get(input.locals)
+            .cloned()
+            .map(|principal| (principal,))
+            .ok_or_else(unauthenticated)
+    })
+}
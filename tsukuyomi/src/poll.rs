@@ -0,0 +1,439 @@
+//! Long polling, for the clients which cannot use WebSocket or Server-Sent Events.
+//!
+//! A [`Channel`] is a bounded log of the published events, numbered by the
+//! monotonically increasing sequence numbers starting at `1`. The endpoint
+//! created by [`endpoint`] responds with the events from the sequence number
+//! given by the client as the cursor, and waits for a new event up to the
+//! specified timeout if there are none:
+//!
+//! ```text
+//! GET /events?cursor=42
+//!
+//! {"next_cursor":45,"events":[...],"gap":false}
+//! ```
+//!
+//! The client passes `next_cursor` as the cursor of the next request, so that
+//! the events already seen are skipped. The cursor `0` starts from the events
+//! published after the request. If some events after the cursor have already
+//! been discarded from the log, `gap` is set to `true`.
+//!
+//! ```
+//! # use tsukuyomi::{config::prelude::*, extractor, poll, App};
+//! # use std::time::Duration;
+//! let channel: poll::Channel<String> = poll::Channel::builder()
+//!     .capacity(1024)
+//!     .ttl(Duration::from_secs(300))
+//!     .build();
+//!
+//! let app = App::create(chain![
+//!     tsukuyomi::config::state(channel.clone()),
+//!     path!("/events") //
+//!         .to(poll::endpoint(channel, Duration::from_secs(30))),
+//!     path!("/messages") //
+//!         .to(endpoint::post()
+//!             .extract(extractor::body::plain())
+//!             .extract(extractor::state::<poll::Channel<String>>())
+//!             .call(|message: String, channel: poll::Channel<String>| {
+//!                 channel.publish(message);
+//!                 "published"
+//!             })),
+//! ]);
+//! # drop(app);
+//! ```
+//!
+//! [`Channel`]: ./struct.Channel.html
+//! [`endpoint`]: ./fn.endpoint.html
+
+use {
+    crate::{
+        endpoint::{ApplyContext, ApplyError, ApplyResult, Endpoint},
+        error::Error,
+        future::{Async, Poll, TryFuture},
+        handler::AllowedMethods,
+        input::Input,
+        rt::{Clock, Delay, SystemClock},
+    },
+    futures01::{task::AtomicTask, Future},
+    http::Method,
+    serde::{Deserialize, Serialize},
+    std::{
+        collections::VecDeque,
+        fmt,
+        sync::{Arc, Mutex, MutexGuard},
+        time::{Duration, Instant},
+    },
+};
+
+/// A builder of `Channel`.
+pub struct Builder {
+    capacity: usize,
+    ttl: Duration,
+    clock: Arc<dyn Clock>,
+}
+
+impl fmt::Debug for Builder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Builder")
+            .field("capacity", &self.capacity)
+            .field("ttl", &self.ttl)
+            .finish()
+    }
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self {
+            capacity: 1000,
+            ttl: Duration::from_secs(60),
+            clock: Arc::new(SystemClock),
+        }
+    }
+}
+
+impl Builder {
+    /// Sets the maximum number of the events kept in the log.
+    ///
+    /// The oldest event is discarded when the log is full.
+    /// The default value is 1000.
+    pub fn capacity(self, capacity: usize) -> Self {
+        Self { capacity, ..self }
+    }
+
+    /// Sets the duration for which the events are kept in the log.
+    ///
+    /// The default value is 60 seconds.
+    pub fn ttl(self, ttl: Duration) -> Self {
+        Self { ttl, ..self }
+    }
+
+    /// Sets the clock used for checking the expiration of the events.
+    ///
+    /// The timeout of the waiting requests is measured by the clock of the
+    /// application instead.
+    pub fn clock(self, clock: impl Clock) -> Self {
+        Self {
+            clock: Arc::new(clock),
+            ..self
+        }
+    }
+
+    /// Creates a `Channel` with the current configuration.
+    pub fn build<T>(self) -> Channel<T> {
+        Channel {
+            inner: Arc::new(Inner {
+                capacity: self.capacity,
+                ttl: self.ttl,
+                clock: self.clock,
+                state: Mutex::new(State {
+                    events: VecDeque::new(),
+                    last_seq: 0,
+                    waiters: vec![],
+                }),
+            }),
+        }
+    }
+}
+
+/// A bounded log of events that the long polling requests wait on.
+///
+/// The clones of this value refer to the same log.
+pub struct Channel<T> {
+    inner: Arc<Inner<T>>,
+}
+
+struct Inner<T> {
+    capacity: usize,
+    ttl: Duration,
+    clock: Arc<dyn Clock>,
+    state: Mutex<State<T>>,
+}
+
+struct State<T> {
+    events: VecDeque<Entry<T>>,
+    last_seq: u64,
+    waiters: Vec<Arc<AtomicTask>>,
+}
+
+struct Entry<T> {
+    seq: u64,
+    published_at: Instant,
+    event: T,
+}
+
+impl<T> Clone for Channel<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> fmt::Debug for Channel<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.inner.state();
+        f.debug_struct("Channel")
+            .field("capacity", &self.inner.capacity)
+            .field("ttl", &self.inner.ttl)
+            .field("len", &state.events.len())
+            .field("last_seq", &state.last_seq)
+            .finish()
+    }
+}
+
+impl<T> Default for Channel<T> {
+    fn default() -> Self {
+        Builder::default().build()
+    }
+}
+
+impl Channel<()> {
+    /// Creates a `Builder` of `Channel`.
+    pub fn builder() -> Builder {
+        Builder::default()
+    }
+}
+
+impl<T> Channel<T> {
+    /// Appends an event to the log, and wakes up the waiting requests.
+    ///
+    /// Returns the sequence number assigned to the event.
+    pub fn publish(&self, event: T) -> u64 {
+        let now = self.inner.clock.now();
+        let mut state = self.inner.state();
+        state.last_seq += 1;
+        let seq = state.last_seq;
+        state.events.push_back(Entry {
+            seq,
+            published_at: now,
+            event,
+        });
+        while state.events.len() > self.inner.capacity {
+            state.events.pop_front();
+        }
+        self.inner.expire(&mut state, now);
+
+        for waiter in &state.waiters {
+            waiter.notify();
+        }
+        seq
+    }
+
+    /// Returns the sequence number of the last published event, or `0` if none.
+    pub fn last_seq(&self) -> u64 {
+        self.inner.state().last_seq
+    }
+}
+
+impl<T> Inner<T> {
+    fn state(&self) -> MutexGuard<'_, State<T>> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn expire(&self, state: &mut State<T>, now: Instant) {
+        while state
+            .events
+            .front()
+            .map_or(false, |entry| entry.published_at + self.ttl <= now)
+        {
+            state.events.pop_front();
+        }
+    }
+}
+
+impl<T> Inner<T>
+where
+    T: Clone,
+{
+    /// Collects the events whose sequence number is `cursor` or later.
+    fn read(&self, cursor: u64) -> Polled<T> {
+        let now = self.clock.now();
+        let mut state = self.state();
+        self.expire(&mut state, now);
+
+        let head = state.last_seq + 1;
+        // The cursor beyond the log, such as the one issued before restarting
+        // the server, is treated as missing all events in the log.
+        let (cursor, reset) = if cursor > head {
+            (1, true)
+        } else {
+            (cursor, false)
+        };
+        let first_seq = state.events.front().map_or(head, |entry| entry.seq);
+        Polled {
+            next_cursor: head,
+            events: state
+                .events
+                .iter()
+                .filter(|entry| entry.seq >= cursor)
+                .map(|entry| entry.event.clone())
+                .collect(),
+            gap: reset || cursor < first_seq,
+        }
+    }
+}
+
+/// The response body of the long polling endpoint.
+#[derive(Debug, Serialize)]
+struct Polled<T> {
+    next_cursor: u64,
+    events: Vec<T>,
+    gap: bool,
+}
+
+/// Creates an `Endpoint` that waits for the events in the channel up to `wait_timeout`.
+///
+/// The cursor is given by the query parameter `cursor`. If no event has been
+/// published by the timeout, the response contains an empty list of events.
+pub fn endpoint<T>(channel: Channel<T>, wait_timeout: Duration) -> LongPoll<T>
+where
+    T: Serialize + Clone + Send + 'static,
+{
+    LongPoll {
+        channel,
+        wait_timeout,
+    }
+}
+
+/// An `Endpoint` created by `poll::endpoint`.
+#[derive(Debug)]
+pub struct LongPoll<T> {
+    channel: Channel<T>,
+    wait_timeout: Duration,
+}
+
+impl<T> Clone for LongPoll<T> {
+    fn clone(&self) -> Self {
+        Self {
+            channel: self.channel.clone(),
+            wait_timeout: self.wait_timeout,
+        }
+    }
+}
+
+impl<T> Endpoint<()> for LongPoll<T>
+where
+    T: Serialize + Clone + Send + 'static,
+{
+    type Output = serde_json::Value;
+    type Error = Error;
+    type Future = LongPollFuture<T>; // private
+
+    fn apply(&self, _: (), cx: &mut ApplyContext<'_, '_>) -> ApplyResult<(), Self> {
+        if cx.method() != Method::GET {
+            return Err(((), ApplyError::method_not_allowed()));
+        }
+        Ok(LongPollFuture {
+            endpoint: self.clone(),
+            state: PollState::Init,
+        })
+    }
+
+    fn allowed_methods(&self) -> Option<AllowedMethods> {
+        Some(Method::GET.into())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Query {
+    #[serde(default)]
+    cursor: u64,
+}
+
+#[allow(missing_debug_implementations)]
+pub struct LongPollFuture<T> {
+    endpoint: LongPoll<T>,
+    state: PollState,
+}
+
+enum PollState {
+    Init,
+    Waiting {
+        cursor: u64,
+        deadline: Delay,
+        waiter: Arc<AtomicTask>,
+    },
+    Done,
+}
+
+impl<T> LongPollFuture<T> {
+    /// Removes the waiter from the channel, and marks this future as completed.
+    fn finish(&mut self) {
+        if let PollState::Waiting { ref waiter, .. } =
+            std::mem::replace(&mut self.state, PollState::Done)
+        {
+            self.endpoint
+                .channel
+                .inner
+                .state()
+                .waiters
+                .retain(|w| !Arc::ptr_eq(w, waiter));
+        }
+    }
+}
+
+impl<T> TryFuture for LongPollFuture<T>
+where
+    T: Serialize + Clone,
+{
+    type Ok = serde_json::Value;
+    type Error = Error;
+
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        let inner = self.endpoint.channel.inner.clone();
+        loop {
+            let polled = match self.state {
+                PollState::Init => {
+                    let query: Query =
+                        serde_urlencoded::from_str(input.request.uri().query().unwrap_or(""))
+                            .map_err(crate::error::bad_request)?;
+                    let cursor = match query.cursor {
+                        0 => inner.state().last_seq + 1,
+                        cursor => cursor,
+                    };
+                    let waiter = Arc::new(AtomicTask::new());
+                    inner.state().waiters.push(waiter.clone());
+                    let deadline = input.clock().now() + self.endpoint.wait_timeout;
+                    self.state = PollState::Waiting {
+                        cursor,
+                        deadline: input.clock().delay(deadline),
+                        waiter,
+                    };
+                    continue;
+                }
+                PollState::Waiting {
+                    cursor,
+                    ref mut deadline,
+                    ref waiter,
+                } => {
+                    // register the task before reading the log, so that
+                    // the events published in between are not missed.
+                    waiter.register();
+                    let polled = inner.read(cursor);
+                    if polled.events.is_empty() && !polled.gap {
+                        match deadline.poll() {
+                            Ok(Async::NotReady) => return Ok(Async::NotReady),
+                            Ok(Async::Ready(())) => {}
+                            Err(err) => {
+                                self.finish();
+                                return Err(crate::error::internal_server_error(err));
+                            }
+                        }
+                    }
+                    polled
+                }
+                PollState::Done => panic!("the future has already polled."),
+            };
+
+            self.finish();
+            return serde_json::to_value(polled)
+                .map(Async::Ready)
+                .map_err(crate::error::internal_server_error);
+        }
+    }
+}
+
+impl<T> Drop for LongPollFuture<T> {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
@@ -0,0 +1,509 @@
+//! Backpressure-aware access to the pools of the resources, such as the database connections.
+//!
+//! A pool stored as a plain state hands out the resources without any limit on the
+//! waiting time, so an exhausted pool turns into the requests piling up inside the
+//! handlers. [`PooledResource`] wraps a [`Pool`] and acquires the resources with a
+//! timeout, rejecting the requests with `503 Service Unavailable` and `Retry-After`
+//! instead of letting them hang. It also records the utilization of the pool,
+//! reported by [`PooledResource::metrics`].
+//!
+//! The wrapped pool is registered as a state, and the resources are acquired by
+//! `extractor::pooled`. The acquisition timeout can be overridden for the routes
+//! in a scope by registering [`AcquireTimeout`]:
+//!
+//! ```
+//! # use tsukuyomi::{
+//! #     config::prelude::*,
+//! #     extractor,
+//! #     pool::{AcquireTimeout, Pool, Pooled, PooledResource},
+//! #     App,
+//! # };
+//! # use futures01::future::{self, FutureResult};
+//! # use std::time::Duration;
+//! # struct Conn;
+//! # struct ConnPool;
+//! # impl Pool for ConnPool {
+//! #     type Resource = Conn;
+//! #     type Error = String;
+//! #     type Acquire = FutureResult<Conn, String>;
+//! #     fn acquire(&self) -> Self::Acquire { future::ok(Conn) }
+//! # }
+//! let pool = PooledResource::new(ConnPool).acquire_timeout(Duration::from_secs(1));
+//!
+//! let app = App::create(chain![
+//!     tsukuyomi::config::state(pool.clone()),
+//!     path!("/posts") //
+//!         .to(endpoint::get()
+//!             .extract(extractor::pooled::<ConnPool>())
+//!             .call(|_conn: Pooled<ConnPool>| "posts")),
+//!     mount("/reports").with(chain![
+//!         AcquireTimeout::new(Duration::from_secs(10)),
+//!         path!("/") //
+//!             .to(endpoint::get()
+//!                 .extract(extractor::pooled::<ConnPool>())
+//!                 .call(|_conn: Pooled<ConnPool>| "reports")),
+//!     ]),
+//! ]);
+//! # drop(app);
+//! ```
+//!
+//! The blocking pools such as `r2d2` can be adapted by acquiring the connections
+//! on the blocking section of the thread pool of the runtime:
+//!
+//! ```ignore
+//! struct PgPool(r2d2::Pool<PostgresConnectionManager>);
+//!
+//! impl Pool for PgPool {
+//!     type Resource = r2d2::PooledConnection<PostgresConnectionManager>;
+//!     type Error = failure::Error;
+//!     type Acquire = Box<dyn Future<Item = Self::Resource, Error = Self::Error> + Send>;
+//!
+//!     fn acquire(&self) -> Self::Acquire {
+//!         let pool = self.0.clone();
+//!         Box::new(future::poll_fn(move || {
+//!             match tokio_threadpool::blocking(|| pool.get()) {
+//!                 Ok(Async::Ready(conn)) => Ok(Async::Ready(conn?)),
+//!                 Ok(Async::NotReady) => Ok(Async::NotReady),
+//!                 Err(err) => Err(err.into()),
+//!             }
+//!         }))
+//!     }
+//!
+//!     fn idle(&self) -> Option<usize> {
+//!         Some(self.0.state().idle_connections as usize)
+//!     }
+//! }
+//! ```
+//!
+//! [`Pool`]: ./trait.Pool.html
+//! [`PooledResource`]: ./struct.PooledResource.html
+//! [`PooledResource::metrics`]: ./struct.PooledResource.html#method.metrics
+//! [`AcquireTimeout`]: ./struct.AcquireTimeout.html
+
+use {
+    crate::{
+        app::config::{Concurrency, Config, Scope},
+        error::Error,
+        future::TryFuture,
+        input::Input,
+        rt::{Clock, Delay, SystemClock},
+        util::Never,
+    },
+    futures01::{Async, Future, Poll},
+    http::{header::RETRY_AFTER, Response, StatusCode},
+    std::{
+        fmt,
+        ops::{Deref, DerefMut},
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        time::{Duration, Instant},
+    },
+};
+
+/// A trait representing a pool of the resources.
+///
+/// The resources are expected to be returned to the pool when they are dropped.
+pub trait Pool: Send + Sync + 'static {
+    /// The type of the resources handed out by the pool.
+    type Resource: Send + 'static;
+
+    /// The error type that may be returned from `Acquire`.
+    type Error: fmt::Display;
+
+    /// The type of the future that resolves to an acquired resource.
+    type Acquire: Future<Item = Self::Resource, Error = Self::Error> + Send + 'static;
+
+    /// Starts acquiring a resource from the pool.
+    ///
+    /// The returned future is dropped if the acquisition has timed out.
+    fn acquire(&self) -> Self::Acquire;
+
+    /// Returns the number of the idle resources in the pool, if known.
+    fn idle(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// A wrapper of a `Pool` that acquires the resources with a timeout, and records
+/// the utilization of the pool.
+///
+/// The clones of this value share the pool and the statistics.
+pub struct PooledResource<P> {
+    pool: Arc<P>,
+    acquire_timeout: Duration,
+    retry_after: Duration,
+    shared: Arc<Shared>,
+}
+
+impl<P> fmt::Debug for PooledResource<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PooledResource")
+            .field("acquire_timeout", &self.acquire_timeout)
+            .field("retry_after", &self.retry_after)
+            .field("shared", &self.shared)
+            .finish()
+    }
+}
+
+impl<P> Clone for PooledResource<P> {
+    fn clone(&self) -> Self {
+        Self {
+            pool: self.pool.clone(),
+            acquire_timeout: self.acquire_timeout,
+            retry_after: self.retry_after,
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    in_use: AtomicUsize,
+    waiting: AtomicUsize,
+    acquired: AtomicUsize,
+    timeouts: AtomicUsize,
+    failures: AtomicUsize,
+    // The total and the maximum of the waiting times.
+    wait_time: Mutex<(Duration, Duration)>,
+}
+
+impl Shared {
+    fn record_wait(&self, wait: Duration) {
+        let mut wait_time = self.wait_time.lock().unwrap();
+        wait_time.0 += wait;
+        wait_time.1 = wait_time.1.max(wait);
+    }
+}
+
+impl<P> PooledResource<P>
+where
+    P: Pool,
+{
+    /// Creates a `PooledResource` wrapping the specified pool.
+    ///
+    /// By default, the resources are acquired with the timeout of 5 seconds.
+    pub fn new(pool: P) -> Self {
+        Self {
+            pool: Arc::new(pool),
+            acquire_timeout: Duration::from_secs(5),
+            retry_after: Duration::from_secs(1),
+            shared: Arc::new(Shared::default()),
+        }
+    }
+
+    /// Sets the default timeout of the acquisition of the resources.
+    ///
+    /// The timeout is overridden in the scopes where `AcquireTimeout` is registered.
+    pub fn acquire_timeout(self, acquire_timeout: Duration) -> Self {
+        Self {
+            acquire_timeout,
+            ..self
+        }
+    }
+
+    /// Sets the value of `Retry-After` in the responses to the requests that have
+    /// failed to acquire a resource.
+    ///
+    /// The default value is one second.
+    pub fn retry_after(self, retry_after: Duration) -> Self {
+        Self {
+            retry_after,
+            ..self
+        }
+    }
+
+    /// Returns a reference to the wrapped pool.
+    pub fn pool(&self) -> &P {
+        &*self.pool
+    }
+
+    /// Returns a snapshot of the utilization of the pool.
+    pub fn metrics(&self) -> PoolMetrics {
+        let shared = &*self.shared;
+        let (wait_time, max_wait_time) = *shared.wait_time.lock().unwrap();
+        PoolMetrics {
+            in_use: shared.in_use.load(Ordering::SeqCst) as u64,
+            idle: self.pool.idle().map(|idle| idle as u64),
+            waiting: shared.waiting.load(Ordering::SeqCst) as u64,
+            acquired: shared.acquired.load(Ordering::Relaxed) as u64,
+            timeouts: shared.timeouts.load(Ordering::Relaxed) as u64,
+            failures: shared.failures.load(Ordering::Relaxed) as u64,
+            wait_time,
+            max_wait_time,
+        }
+    }
+
+    /// Creates a future that acquires a resource with the default timeout, and
+    /// releases it immediately.
+    ///
+    /// This is intended for the readiness check of the application, which reports
+    /// the application as unavailable while the pool is exhausted or broken.
+    pub fn check(&self) -> impl Future<Item = (), Error = Error> + Send + 'static {
+        self.acquire_with(self.acquire_timeout, Arc::new(SystemClock))
+            .map(drop)
+    }
+
+    /// Creates a future that acquires a resource with the specified timeout.
+    pub fn acquire_with(&self, timeout: Duration, clock: Arc<dyn Clock>) -> Acquire<P> {
+        let started = clock.now();
+        self.shared.waiting.fetch_add(1, Ordering::SeqCst);
+        Acquire {
+            future: self.pool.acquire(),
+            deadline: clock.delay(started + timeout),
+            resource: self.clone(),
+            clock,
+            started,
+            waiting: true,
+        }
+    }
+
+    fn unavailable(&self, message: String) -> Error {
+        let retry_after = std::cmp::max(
+            1,
+            self.retry_after.as_secs() + u64::from(self.retry_after.subsec_nanos() > 0),
+        );
+        crate::error::error_response(
+            Response::builder()
+                .status(StatusCode::SERVICE_UNAVAILABLE)
+                .header(RETRY_AFTER, retry_after)
+                .body(message)
+                .expect("should be a valid response"),
+        )
+    }
+}
+
+/// A snapshot of the utilization of a `PooledResource`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PoolMetrics {
+    /// The number of the resources handed out and not yet released.
+    pub in_use: u64,
+
+    /// The number of the idle resources in the pool, if reported by the pool.
+    pub idle: Option<u64>,
+
+    /// The number of the requests waiting for a resource.
+    pub waiting: u64,
+
+    /// The number of the resources acquired so far.
+    pub acquired: u64,
+
+    /// The number of the acquisitions that have timed out.
+    pub timeouts: u64,
+
+    /// The number of the acquisitions that have failed with an error of the pool.
+    pub failures: u64,
+
+    /// The total time spent for the successful acquisitions.
+    pub wait_time: Duration,
+
+    /// The longest time spent for a successful acquisition.
+    pub max_wait_time: Duration,
+}
+
+impl PoolMetrics {
+    /// Returns the pairs of the metric names prefixed with `pool_` and their values,
+    /// for exporting them to a metrics registry.
+    ///
+    /// The wait times are reported in microseconds, and the number of the idle
+    /// resources is omitted if not reported by the pool.
+    pub fn entries(&self) -> Vec<(&'static str, u64)> {
+        let mut entries = vec![("pool_in_use", self.in_use)];
+        if let Some(idle) = self.idle {
+            entries.push(("pool_idle", idle));
+        }
+        entries.extend_from_slice(&[
+            ("pool_waiting", self.waiting),
+            ("pool_acquired_total", self.acquired),
+            ("pool_timeouts_total", self.timeouts),
+            ("pool_failures_total", self.failures),
+            ("pool_wait_microseconds_total", micros(self.wait_time)),
+            ("pool_max_wait_microseconds", micros(self.max_wait_time)),
+        ]);
+        entries
+    }
+}
+
+fn micros(duration: Duration) -> u64 {
+    duration.as_secs() * 1_000_000 + u64::from(duration.subsec_micros())
+}
+
+/// A `Config` that overrides the acquisition timeout of the `PooledResource`s
+/// for the routes in the current scope and its descendants.
+#[derive(Debug, Clone, Copy)]
+pub struct AcquireTimeout(Duration);
+
+impl AcquireTimeout {
+    /// Creates an `AcquireTimeout` with the specified duration.
+    pub fn new(timeout: Duration) -> Self {
+        AcquireTimeout(timeout)
+    }
+
+    /// Returns the duration of the timeout.
+    pub fn timeout(&self) -> Duration {
+        self.0
+    }
+}
+
+impl<M, C> Config<M, C> for AcquireTimeout
+where
+    C: Concurrency,
+{
+    type Error = Never;
+
+    fn configure(self, cx: &mut Scope<'_, M, C>) -> Result<(), Self::Error> {
+        cx.set_state(self);
+        Ok(())
+    }
+}
+
+/// A resource acquired from a `PooledResource`.
+///
+/// The resource is released when this value is dropped, typically at the end
+/// of the handler, including when the handler has panicked.
+pub struct Pooled<P: Pool> {
+    resource: P::Resource,
+    shared: Arc<Shared>,
+}
+
+impl<P> fmt::Debug for Pooled<P>
+where
+    P: Pool,
+    P::Resource: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pooled")
+            .field("resource", &self.resource)
+            .finish()
+    }
+}
+
+impl<P: Pool> Deref for Pooled<P> {
+    type Target = P::Resource;
+
+    fn deref(&self) -> &Self::Target {
+        &self.resource
+    }
+}
+
+impl<P: Pool> DerefMut for Pooled<P> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.resource
+    }
+}
+
+impl<P: Pool> Drop for Pooled<P> {
+    fn drop(&mut self) {
+        self.shared.in_use.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// The future returned from `PooledResource::acquire_with`.
+#[must_use = "futures do nothing unless polled"]
+pub struct Acquire<P: Pool> {
+    resource: PooledResource<P>,
+    future: P::Acquire,
+    deadline: Delay,
+    clock: Arc<dyn Clock>,
+    started: Instant,
+    waiting: bool,
+}
+
+impl<P: Pool> fmt::Debug for Acquire<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Acquire")
+            .field("resource", &self.resource)
+            .field("started", &self.started)
+            .finish()
+    }
+}
+
+impl<P: Pool> Acquire<P> {
+    fn finish_waiting(&mut self) {
+        if self.waiting {
+            self.waiting = false;
+            self.resource.shared.waiting.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+}
+
+impl<P: Pool> Future for Acquire<P> {
+    type Item = Pooled<P>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let shared = &self.resource.shared;
+        let polled = self.future.poll();
+        match polled {
+            Ok(Async::Ready(resource)) => {
+                shared.in_use.fetch_add(1, Ordering::SeqCst);
+                shared.acquired.fetch_add(1, Ordering::Relaxed);
+                shared.record_wait(self.clock.now().duration_since(self.started));
+                let shared = shared.clone();
+                self.finish_waiting();
+                Ok(Async::Ready(Pooled { resource, shared }))
+            }
+            Err(err) => {
+                shared.failures.fetch_add(1, Ordering::Relaxed);
+                self.finish_waiting();
+                log::warn!("failed to acquire the resource from the pool: {}", err);
+                Err(self
+                    .resource
+                    .unavailable(format!("failed to acquire the resource: {}", err)))
+            }
+            Ok(Async::NotReady) => match self.deadline.poll() {
+                Ok(Async::NotReady) => Ok(Async::NotReady),
+                Ok(Async::Ready(())) => {
+                    shared.timeouts.fetch_add(1, Ordering::Relaxed);
+                    self.finish_waiting();
+                    Err(self
+                        .resource
+                        .unavailable("timed out while acquiring the resource".into()))
+                }
+                Err(err) => {
+                    self.finish_waiting();
+                    Err(crate::error::internal_server_error(err))
+                }
+            },
+        }
+    }
+}
+
+impl<P: Pool> Drop for Acquire<P> {
+    fn drop(&mut self) {
+        self.finish_waiting();
+    }
+}
+
+/// The `TryFuture` of `extractor::pooled`.
+#[allow(missing_debug_implementations)]
+pub struct ExtractPooled<P: Pool> {
+    acquire: Option<Acquire<P>>,
+}
+
+impl<P: Pool> ExtractPooled<P> {
+    pub(crate) fn new() -> Self {
+        Self { acquire: None }
+    }
+}
+
+impl<P: Pool> TryFuture for ExtractPooled<P> {
+    type Ok = (Pooled<P>,);
+    type Error = Error;
+
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> crate::future::Poll<Self::Ok, Self::Error> {
+        if self.acquire.is_none() {
+            let resource = input.state::<PooledResource<P>>().ok_or_else(|| {
+                crate::error::internal_server_error("the requested pool is not registered")
+            })?;
+            let timeout = input
+                .state::<AcquireTimeout>()
+                .map_or(resource.acquire_timeout, AcquireTimeout::timeout);
+            self.acquire = Some(resource.acquire_with(timeout, input.clock().clone()));
+        }
+        let acquire = self.acquire.as_mut().expect("should be started");
+        let pooled = futures01::try_ready!(acquire.poll());
+        Ok(Async::Ready((pooled,)))
+    }
+}
@@ -0,0 +1,729 @@
+//! A scaffold of the CRUD endpoints backed by a storage.
+//!
+//! [`resource`] registers the following routes for a collection named `name`,
+//! whose items are stored in a [`ResourceStore`]:
+//!
+//! | route | operation | response |
+//! |-------|-----------|----------|
+//! | `GET /name` | `list` | `200 OK` with the paginated JSON array |
+//! | `POST /name` | `create` | `201 Created` with `Location` and the JSON item |
+//! | `GET /name/:id` | `get` | `200 OK` with the JSON item |
+//! | `PUT /name/:id` | `update` | `200 OK` with the JSON item |
+//! | `DELETE /name/:id` | `delete` | `204 No Content` |
+//!
+//! The list endpoint takes the parameters of [`extractor::pagination`].
+//! The errors returned from the store are mapped to the responses by [`StoreError`],
+//! such as `404 Not Found` for the missing ids and `409 Conflict` for the conflicts.
+//!
+//! ```
+//! # use tsukuyomi::{config::prelude::*, resource::{self, MemoryStore}, App};
+//! # use serde::{Deserialize, Serialize};
+//! #[derive(Debug, Clone, Serialize, Deserialize)]
+//! struct Post {
+//!     title: String,
+//! }
+//!
+//! let app = App::create(
+//!     mount("/api").with(
+//!         resource::resource("posts", MemoryStore::<Post>::new())
+//!             .override_list(endpoint::get().reply("[]")),
+//!     ),
+//! );
+//! # drop(app);
+//! ```
+//!
+//! [`resource`]: ./fn.resource.html
+//! [`ResourceStore`]: ./trait.ResourceStore.html
+//! [`StoreError`]: ./enum.StoreError.html
+//! [`extractor::pagination`]: ../extractor/pagination/index.html
+
+use {
+    crate::{
+        app::config::{Concurrency, Config, Error as ConfigError, Scope},
+        endpoint::{
+            ApplyContext, ApplyError, BoxedEndpoint, BoxedEndpointFuture, Endpoint, EndpointExt,
+        },
+        error::{Error, HttpError},
+        extractor::pagination::{pagination, Defaults, Pagination},
+        future::{Poll, TryFuture},
+        guard::{Guard, Guards, Pending},
+        handler::{AllowedMethods, Handler, ModifyHandler},
+        input::{param::FromPercentEncoded, Input},
+        output::{IntoResponse, Paginated, ResponseBody},
+        responder::Responder,
+    },
+    futures01::Future,
+    http::{
+        header::{HeaderValue, LOCATION},
+        Request, Response, StatusCode,
+    },
+    serde::{de::DeserializeOwned, Serialize},
+    std::{collections::BTreeMap, fmt, marker::PhantomData, sync::Arc, sync::Mutex},
+    url::percent_encoding::{utf8_percent_encode, PATH_SEGMENT_ENCODE_SET},
+};
+
+/// The type of futures returned from the operations of `ResourceStore`.
+pub type StoreFuture<T> = Box<dyn Future<Item = T, Error = StoreError> + Send + 'static>;
+
+/// The error type returned from the operations of `ResourceStore`.
+#[derive(Debug, failure::Fail)]
+pub enum StoreError {
+    /// No item is associated with the id, mapped to `404 Not Found`.
+    #[fail(display = "not found")]
+    NotFound,
+
+    /// The operation conflicts with the current state of the store, such as the
+    /// duplicate of a unique key, mapped to `409 Conflict`.
+    #[fail(display = "{}", _0)]
+    Conflict(String),
+
+    /// The request is invalid for the store, such as a malformed cursor,
+    /// mapped to `400 Bad Request`.
+    #[fail(display = "{}", _0)]
+    Invalid(String),
+
+    /// Any other error, mapped to `500 Internal Server Error`.
+    #[fail(display = "{}", _0)]
+    Other(failure::Error),
+}
+
+impl StoreError {
+    /// Creates a `StoreError::Conflict` with the specified message.
+    pub fn conflict(msg: impl fmt::Display) -> Self {
+        StoreError::Conflict(msg.to_string())
+    }
+
+    /// Creates a `StoreError::Invalid` with the specified message.
+    pub fn invalid(msg: impl fmt::Display) -> Self {
+        StoreError::Invalid(msg.to_string())
+    }
+
+    /// Returns the status code of the response corresponding to this error.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            StoreError::NotFound => StatusCode::NOT_FOUND,
+            StoreError::Conflict(..) => StatusCode::CONFLICT,
+            StoreError::Invalid(..) => StatusCode::BAD_REQUEST,
+            StoreError::Other(..) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl From<failure::Error> for StoreError {
+    fn from(err: failure::Error) -> Self {
+        StoreError::Other(err)
+    }
+}
+
+impl HttpError for StoreError {
+    type Body = String;
+
+    fn into_response(self, _: &Request<()>) -> Response<Self::Body> {
+        let mut response = Response::new(self.to_string());
+        *response.status_mut() = self.status();
+        response
+    }
+
+    fn message_key(&self) -> Option<&str> {
+        match self {
+            StoreError::NotFound => Some("error.not_found"),
+            _ => None,
+        }
+    }
+}
+
+/// A page of the items returned from `ResourceStore::list`.
+#[derive(Debug)]
+pub struct Page<T> {
+    items: Vec<T>,
+    total: Option<u64>,
+    next_cursor: Option<String>,
+}
+
+impl<T> Page<T> {
+    /// Creates a `Page` with the items in the requested page.
+    pub fn new(items: Vec<T>) -> Self {
+        Self {
+            items,
+            total: None,
+            next_cursor: None,
+        }
+    }
+
+    /// Sets the total number of items in the collection.
+    pub fn total(self, total: u64) -> Self {
+        Self {
+            total: Some(total),
+            ..self
+        }
+    }
+
+    /// Sets the cursor that points to the next page.
+    pub fn next_cursor(self, cursor: impl Into<String>) -> Self {
+        Self {
+            next_cursor: Some(cursor.into()),
+            ..self
+        }
+    }
+
+    /// Returns the items in this page.
+    pub fn items(&self) -> &[T] {
+        &self.items
+    }
+
+    fn into_paginated(self, pagination: Pagination) -> Paginated<Vec<T>>
+    where
+        T: Serialize,
+    {
+        let mut paginated = Paginated::new(self.items, pagination);
+        if let Some(total) = self.total {
+            paginated = paginated.total(total);
+        }
+        if let Some(cursor) = self.next_cursor {
+            paginated = paginated.next_cursor(cursor);
+        }
+        paginated
+    }
+}
+
+/// A trait representing the storage of the items of type `T` used by `resource`.
+pub trait ResourceStore<T>: Send + Sync + 'static {
+    /// The type of ids of the items, extracted from the path parameter `:id`.
+    type Id: FromPercentEncoded + fmt::Display + Send + 'static;
+
+    /// Returns the items in the page specified by `pagination`.
+    fn list(&self, pagination: Pagination) -> StoreFuture<Page<T>>;
+
+    /// Returns the item associated with the id.
+    fn get(&self, id: Self::Id) -> StoreFuture<T>;
+
+    /// Stores a new item, and returns the assigned id with the stored item.
+    fn create(&self, item: T) -> StoreFuture<(Self::Id, T)>;
+
+    /// Replaces the item associated with the id, and returns the stored item.
+    fn update(&self, id: Self::Id, item: T) -> StoreFuture<T>;
+
+    /// Removes the item associated with the id.
+    fn delete(&self, id: Self::Id) -> StoreFuture<()>;
+}
+
+/// An in-memory `ResourceStore`, intended for the tests and the prototypes.
+///
+/// The items are identified by the sequential numbers starting at 1.
+/// With cursor-based pagination, the cursor is the id of the last item in the
+/// previous page.
+pub struct MemoryStore<T> {
+    state: Mutex<MemoryState<T>>,
+    unique_key: Option<UniqueKey<T>>,
+}
+
+type UniqueKey<T> = Box<dyn Fn(&T) -> String + Send + Sync + 'static>;
+
+struct MemoryState<T> {
+    next_id: u64,
+    items: BTreeMap<u64, T>,
+}
+
+impl<T> fmt::Debug for MemoryStore<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemoryStore")
+            .field("len", &self.state.lock().unwrap().items.len())
+            .field("unique_key", &self.unique_key.is_some())
+            .finish()
+    }
+}
+
+impl<T> Default for MemoryStore<T> {
+    fn default() -> Self {
+        Self {
+            state: Mutex::new(MemoryState {
+                next_id: 1,
+                items: BTreeMap::new(),
+            }),
+            unique_key: None,
+        }
+    }
+}
+
+impl<T> MemoryStore<T> {
+    /// Creates an empty `MemoryStore`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the function that computes the unique key of an item.
+    ///
+    /// Storing an item whose key is equal to that of another item fails with
+    /// `StoreError::Conflict`.
+    pub fn unique_by<F>(self, f: F) -> Self
+    where
+        F: Fn(&T) -> String + Send + Sync + 'static,
+    {
+        Self {
+            unique_key: Some(Box::new(f)),
+            ..self
+        }
+    }
+
+    fn check_unique(&self, state: &MemoryState<T>, item: &T, id: u64) -> Result<(), StoreError> {
+        if let Some(ref unique_key) = self.unique_key {
+            let key = unique_key(item);
+            if state
+                .items
+                .iter()
+                .any(|(&other_id, other)| other_id != id && unique_key(other) == key)
+            {
+                return Err(StoreError::conflict(format!("{} already exists", key)));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T> ResourceStore<T> for MemoryStore<T>
+where
+    T: Clone + Send + Sync + 'static,
+{
+    type Id = u64;
+
+    fn list(&self, pagination: Pagination) -> StoreFuture<Page<T>> {
+        let state = self.state.lock().unwrap();
+        let total = state.items.len() as u64;
+        let result = match pagination {
+            Pagination::Page {
+                per_page, offset, ..
+            } => {
+                let items = state
+                    .items
+                    .values()
+                    .skip(offset as usize)
+                    .take(per_page as usize)
+                    .cloned()
+                    .collect();
+                Ok(Page::new(items).total(total))
+            }
+            Pagination::Cursor {
+                ref cursor,
+                per_page,
+            } => cursor
+                .parse::<u64>()
+                .map_err(|_| StoreError::invalid(format!("invalid cursor: {}", cursor)))
+                .map(|last| {
+                    let mut rest = state.items.range(last + 1..);
+                    let items: Vec<(u64, T)> = rest
+                        .by_ref()
+                        .take(per_page as usize)
+                        .map(|(&id, item)| (id, item.clone()))
+                        .collect();
+                    let next = match items.last() {
+                        Some(&(id, _)) if rest.next().is_some() => Some(id),
+                        _ => None,
+                    };
+                    let page = Page::new(items.into_iter().map(|(_, item)| item).collect());
+                    match next {
+                        Some(id) => page.next_cursor(id.to_string()),
+                        None => page,
+                    }
+                }),
+        };
+        Box::new(futures01::future::result(result))
+    }
+
+    fn get(&self, id: u64) -> StoreFuture<T> {
+        let state = self.state.lock().unwrap();
+        let result = state.items.get(&id).cloned().ok_or(StoreError::NotFound);
+        Box::new(futures01::future::result(result))
+    }
+
+    fn create(&self, item: T) -> StoreFuture<(u64, T)> {
+        let mut state = self.state.lock().unwrap();
+        let result = self.check_unique(&state, &item, 0).map(|()| {
+            let id = state.next_id;
+            state.next_id += 1;
+            state.items.insert(id, item.clone());
+            (id, item)
+        });
+        Box::new(futures01::future::result(result))
+    }
+
+    fn update(&self, id: u64, item: T) -> StoreFuture<T> {
+        let mut state = self.state.lock().unwrap();
+        let result = if state.items.contains_key(&id) {
+            self.check_unique(&state, &item, id).map(|()| {
+                state.items.insert(id, item.clone());
+                item
+            })
+        } else {
+            Err(StoreError::NotFound)
+        };
+        Box::new(futures01::future::result(result))
+    }
+
+    fn delete(&self, id: u64) -> StoreFuture<()> {
+        let mut state = self.state.lock().unwrap();
+        let result = state
+            .items
+            .remove(&id)
+            .map(|_| ())
+            .ok_or(StoreError::NotFound);
+        Box::new(futures01::future::result(result))
+    }
+}
+
+/// Creates a `Resource` that registers the CRUD endpoints of the collection `name`
+/// backed by `store`.
+pub fn resource<T, S>(name: impl Into<String>, store: S) -> Resource<T, S>
+where
+    S: ResourceStore<T>,
+{
+    Resource {
+        name: name.into(),
+        store: Arc::new(store),
+        defaults: Defaults::default(),
+        guards: Guards::default(),
+        overrides: Overrides::default(),
+        _marker: PhantomData,
+    }
+}
+
+#[derive(Debug, Default)]
+struct Overrides {
+    list: Option<BoxedEndpoint>,
+    get: Option<BoxedEndpoint>,
+    create: Option<BoxedEndpoint>,
+    update: Option<BoxedEndpoint>,
+    delete: Option<BoxedEndpoint>,
+}
+
+/// A `Config` that registers the CRUD endpoints, created by `resource`.
+pub struct Resource<T, S> {
+    name: String,
+    store: Arc<S>,
+    defaults: Defaults,
+    guards: Guards,
+    overrides: Overrides,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T, S> fmt::Debug for Resource<T, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Resource")
+            .field("name", &self.name)
+            .field("defaults", &self.defaults)
+            .field("guards", &self.guards)
+            .field("overrides", &self.overrides)
+            .finish()
+    }
+}
+
+impl<T, S> Resource<T, S>
+where
+    S: ResourceStore<T>,
+{
+    /// Sets the default values and limits of the pagination used by the list endpoint.
+    pub fn pagination(self, defaults: Defaults) -> Self {
+        Self { defaults, ..self }
+    }
+
+    /// Appends a `Guard` checked before applying every endpoint of this resource,
+    /// including the overriding ones.
+    pub fn guard(mut self, guard: impl Guard + Send + Sync + 'static) -> Self {
+        self.guards.push(guard);
+        self
+    }
+
+    /// Overrides the endpoint of `GET /name`.
+    pub fn override_list<E>(mut self, endpoint: E) -> Self
+    where
+        E: Endpoint<()> + Send + Sync + 'static,
+        E::Output: Responder,
+        <E::Output as Responder>::Respond: Send + 'static,
+        E::Future: Send + 'static,
+    {
+        self.overrides.list = Some(endpoint.boxed());
+        self
+    }
+
+    /// Overrides the endpoint of `POST /name`.
+    pub fn override_create<E>(mut self, endpoint: E) -> Self
+    where
+        E: Endpoint<()> + Send + Sync + 'static,
+        E::Output: Responder,
+        <E::Output as Responder>::Respond: Send + 'static,
+        E::Future: Send + 'static,
+    {
+        self.overrides.create = Some(endpoint.boxed());
+        self
+    }
+
+    /// Overrides the endpoint of `GET /name/:id`.
+    ///
+    /// The id can be extracted by `extractor::param("id")`.
+    pub fn override_get<E>(mut self, endpoint: E) -> Self
+    where
+        E: Endpoint<()> + Send + Sync + 'static,
+        E::Output: Responder,
+        <E::Output as Responder>::Respond: Send + 'static,
+        E::Future: Send + 'static,
+    {
+        self.overrides.get = Some(endpoint.boxed());
+        self
+    }
+
+    /// Overrides the endpoint of `PUT /name/:id`.
+    ///
+    /// The id can be extracted by `extractor::param("id")`.
+    pub fn override_update<E>(mut self, endpoint: E) -> Self
+    where
+        E: Endpoint<()> + Send + Sync + 'static,
+        E::Output: Responder,
+        <E::Output as Responder>::Respond: Send + 'static,
+        E::Future: Send + 'static,
+    {
+        self.overrides.update = Some(endpoint.boxed());
+        self
+    }
+
+    /// Overrides the endpoint of `DELETE /name/:id`.
+    ///
+    /// The id can be extracted by `extractor::param("id")`.
+    pub fn override_delete<E>(mut self, endpoint: E) -> Self
+    where
+        E: Endpoint<()> + Send + Sync + 'static,
+        E::Output: Responder,
+        <E::Output as Responder>::Respond: Send + 'static,
+        E::Future: Send + 'static,
+    {
+        self.overrides.delete = Some(endpoint.boxed());
+        self
+    }
+}
+
+impl<T, S> Resource<T, S>
+where
+    T: Serialize + DeserializeOwned + Send + 'static,
+    S: ResourceStore<T>,
+{
+    fn list_endpoint(&self) -> BoxedEndpoint {
+        let store = self.store.clone();
+        crate::config::endpoint::get()
+            .extract(pagination(self.defaults.clone()))
+            .call_async(move |pagination: Pagination| {
+                store
+                    .list(pagination.clone())
+                    .map(move |page| page.into_paginated(pagination))
+            })
+            .boxed()
+    }
+
+    fn create_endpoint(&self) -> BoxedEndpoint {
+        let store = self.store.clone();
+        crate::config::endpoint::post()
+            .extract(crate::extractor::body::json())
+            .call_async(move |item: T| store.create(item).map(|(id, item)| created(&id, item)))
+            .boxed()
+    }
+
+    fn get_endpoint(&self) -> BoxedEndpoint {
+        let store = self.store.clone();
+        crate::config::endpoint::get()
+            .extract(crate::extractor::param("id"))
+            .call_async(move |id: S::Id| store.get(id).map(crate::output::json))
+            .boxed()
+    }
+
+    fn update_endpoint(&self) -> BoxedEndpoint {
+        let store = self.store.clone();
+        crate::config::endpoint::put()
+            .extract(crate::extractor::param("id"))
+            .extract(crate::extractor::body::json())
+            .call_async(move |id: S::Id, item: T| store.update(id, item).map(crate::output::json))
+            .boxed()
+    }
+
+    fn delete_endpoint(&self) -> BoxedEndpoint {
+        let store = self.store.clone();
+        crate::config::endpoint::delete()
+            .extract(crate::extractor::param("id"))
+            .call_async(move |id: S::Id| store.delete(id).map(|()| StatusCode::NO_CONTENT))
+            .boxed()
+    }
+}
+
+/// Creates the response of `201 Created` whose `Location` points to the created item.
+fn created<T>(
+    id: &impl fmt::Display,
+    item: T,
+) -> impl IntoResponse<Body = ResponseBody, Error = Error>
+where
+    T: Serialize,
+{
+    let id = utf8_percent_encode(&id.to_string(), PATH_SEGMENT_ENCODE_SET).to_string();
+    crate::output::into_response(move |request| {
+        let location = format!("{}/{}", request.uri().path().trim_end_matches('/'), id);
+        let location = HeaderValue::from_shared(location.into())
+            .map_err(crate::error::internal_server_error)?;
+        let mut response =
+            (StatusCode::CREATED, crate::output::json(item)).into_response(request)?;
+        response.headers_mut().insert(LOCATION, location);
+        Ok::<_, Error>(response)
+    })
+}
+
+impl<T, S, M, C> Config<M, C> for Resource<T, S>
+where
+    T: Serialize + DeserializeOwned + Send + 'static,
+    S: ResourceStore<T>,
+    M: ModifyHandler<ResourceHandler>,
+    M::Handler: Into<C::Handler>,
+    C: Concurrency,
+{
+    type Error = ConfigError;
+
+    fn configure(self, scope: &mut Scope<'_, M, C>) -> Result<(), Self::Error> {
+        let name = self.name.trim_matches('/');
+        if name.is_empty() {
+            return Err(ConfigError::custom(failure::format_err!(
+                "the name of resource must not be empty"
+            )));
+        }
+
+        let list = self.list_endpoint();
+        let create = self.create_endpoint();
+        let get = self.get_endpoint();
+        let update = self.update_endpoint();
+        let delete = self.delete_endpoint();
+        let Overrides {
+            list: override_list,
+            create: override_create,
+            get: override_get,
+            update: override_update,
+            delete: override_delete,
+        } = self.overrides;
+        let guards = self.guards;
+
+        scope.route(
+            format!("/{}", name),
+            ResourceHandler::new(
+                vec![
+                    override_list.unwrap_or(list),
+                    override_create.unwrap_or(create),
+                ],
+                guards.clone(),
+            ),
+        )?;
+        scope.route(
+            format!("/{}/:id", name),
+            ResourceHandler::new(
+                vec![
+                    override_get.unwrap_or(get),
+                    override_update.unwrap_or(update),
+                    override_delete.unwrap_or(delete),
+                ],
+                guards,
+            ),
+        )?;
+        Ok(())
+    }
+}
+
+/// The `Handler` of the routes registered by `Resource`.
+pub struct ResourceHandler {
+    inner: Arc<ResourceHandlerInner>,
+    allowed_methods: Option<AllowedMethods>,
+}
+
+struct ResourceHandlerInner {
+    endpoints: Vec<BoxedEndpoint>,
+    guards: Guards,
+}
+
+impl fmt::Debug for ResourceHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResourceHandler")
+            .field("endpoints", &self.inner.endpoints)
+            .field("guards", &self.inner.guards)
+            .field("allowed_methods", &self.allowed_methods)
+            .finish()
+    }
+}
+
+impl ResourceHandler {
+    fn new(endpoints: Vec<BoxedEndpoint>, guards: Guards) -> Self {
+        let allowed_methods = endpoints
+            .iter()
+            .map(|endpoint| endpoint.allowed_methods())
+            .collect::<Option<Vec<_>>>()
+            .map(|methods| methods.iter().flat_map(|m| m.iter().cloned()).collect());
+        Self {
+            inner: Arc::new(ResourceHandlerInner { endpoints, guards }),
+            allowed_methods,
+        }
+    }
+}
+
+impl ResourceHandlerInner {
+    /// Applies the first endpoint that accepts the method and passes the guards.
+    fn apply(&self, input: &mut Input<'_>) -> Result<(Pending, BoxedEndpointFuture), Error> {
+        let mut cx = ApplyContext::new(input);
+        let mut last_err = ApplyError::method_not_allowed();
+        for endpoint in &self.endpoints {
+            let accepted = endpoint
+                .allowed_methods()
+                .map_or(true, |methods| methods.contains(cx.method()));
+            if !accepted {
+                continue;
+            }
+            let result = self
+                .guards
+                .check(&mut cx)
+                .and_then(|()| endpoint.apply((), &mut cx).map_err(|((), err)| err));
+            match result {
+                Ok(future) => return Ok((cx.take_pending(), future)),
+                Err(err) => {
+                    drop(cx.take_pending());
+                    last_err = err;
+                }
+            }
+        }
+        Err(last_err.into())
+    }
+}
+
+impl Handler for ResourceHandler {
+    type Output = Response<ResponseBody>;
+    type Error = Error;
+    type Handle = HandleResource; // private
+
+    fn allowed_methods(&self) -> Option<&AllowedMethods> {
+        self.allowed_methods.as_ref()
+    }
+
+    fn handle(&self) -> Self::Handle {
+        HandleResource {
+            inner: self.inner.clone(),
+            in_flight: None,
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct HandleResource {
+    inner: Arc<ResourceHandlerInner>,
+    in_flight: Option<(Pending, BoxedEndpointFuture)>,
+}
+
+impl TryFuture for HandleResource {
+    type Ok = Response<ResponseBody>;
+    type Error = Error;
+
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        loop {
+            if let Some((ref mut pending, ref mut in_flight)) = self.in_flight {
+                futures01::try_ready!(pending.poll_ready());
+                return in_flight.poll_ready(input);
+            }
+            self.in_flight = Some(self.inner.apply(input)?);
+        }
+    }
+}
@@ -0,0 +1,236 @@
+//! Abstractions of the time and the randomness used within the framework.
+//!
+//! The components that depend on the current time or random numbers read them
+//! through the `Clock` and `Random` registered to the application, so that
+//! the tests can replace them with `MockClock` and `SeededRandom`.
+//!
+//! The clock covers the components of the application only. The transport
+//! layer in `tsukuyomi-server` (the admission of the connections, the idle
+//! timeouts, the warm-up requests, the connector and the signal handling) does
+//! not depend on this crate, and reads the system clock and the timer of the
+//! runtime directly.
+//!
+//! ```
+//! # use tsukuyomi::{config::prelude::*, rt::{MockClock, SeededRandom}, App};
+//! let clock = MockClock::new();
+//! let app = App::create(path!("/").to(endpoint::reply("hello")))
+//!     .unwrap()
+//!     .with_clock(clock.clone())
+//!     .with_random(SeededRandom::new(42));
+//! # drop(app);
+//! ```
+
+use {
+    futures01::{task::Task, Async, Future, Poll},
+    std::{
+        collections::hash_map::RandomState,
+        fmt,
+        hash::{BuildHasher, Hasher},
+        sync::{Arc, Mutex},
+        time::{Duration, Instant, SystemTime},
+    },
+};
+
+/// A future that completes at the deadline, created by `Clock::delay`.
+pub type Delay = Box<dyn Future<Item = (), Error = tokio_timer::Error> + Send + 'static>;
+
+/// A source of the current time and the timers.
+pub trait Clock: Send + Sync + 'static {
+    /// Returns the current monotonic time.
+    fn now(&self) -> Instant;
+
+    /// Returns the current wall-clock time.
+    fn system_now(&self) -> SystemTime;
+
+    /// Creates a future that completes at the specified deadline.
+    fn delay(&self, deadline: Instant) -> Delay;
+}
+
+impl fmt::Debug for dyn Clock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Clock").finish()
+    }
+}
+
+/// The `Clock` that reads the system clock and uses the timer of the runtime.
+///
+/// This is the default clock of the application.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn system_now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn delay(&self, deadline: Instant) -> Delay {
+        Box::new(tokio_timer::Delay::new(deadline))
+    }
+}
+
+/// A `Clock` whose time advances only when `advance` is called.
+///
+/// The clones of this value share the same time.
+#[derive(Debug, Clone)]
+pub struct MockClock {
+    state: Arc<Mutex<MockState>>,
+}
+
+#[derive(Debug)]
+struct MockState {
+    now: Instant,
+    system_now: SystemTime,
+    timers: Vec<(Instant, Task)>,
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockClock {
+    /// Creates a `MockClock` starting at the current time of the system clock.
+    pub fn new() -> Self {
+        Self::starting_at(SystemTime::now())
+    }
+
+    /// Creates a `MockClock` whose wall-clock time starts at the specified time.
+    pub fn starting_at(system_now: SystemTime) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(MockState {
+                now: Instant::now(),
+                system_now,
+                timers: vec![],
+            })),
+        }
+    }
+
+    /// Advances the time by the specified duration, and completes the timers
+    /// whose deadline has been reached.
+    pub fn advance(&self, duration: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.now += duration;
+        state.system_now += duration;
+
+        let now = state.now;
+        let (expired, pending) = state
+            .timers
+            .drain(..)
+            .partition(|&(deadline, _)| deadline <= now);
+        state.timers = pending;
+        drop(state);
+
+        for (_, task) in expired {
+            task.notify();
+        }
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.state.lock().unwrap().now
+    }
+
+    fn system_now(&self) -> SystemTime {
+        self.state.lock().unwrap().system_now
+    }
+
+    fn delay(&self, deadline: Instant) -> Delay {
+        Box::new(MockDelay {
+            state: self.state.clone(),
+            deadline,
+        })
+    }
+}
+
+struct MockDelay {
+    state: Arc<Mutex<MockState>>,
+    deadline: Instant,
+}
+
+impl Future for MockDelay {
+    type Item = ();
+    type Error = tokio_timer::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let mut state = self.state.lock().unwrap();
+        if state.now >= self.deadline {
+            return Ok(Async::Ready(()));
+        }
+        state
+            .timers
+            .push((self.deadline, futures01::task::current()));
+        Ok(Async::NotReady)
+    }
+}
+
+/// A source of random numbers.
+///
+/// The values are not guaranteed to be cryptographically secure.
+pub trait Random: Send + Sync + 'static {
+    /// Returns the next random value.
+    fn next_u64(&self) -> u64;
+}
+
+impl fmt::Debug for dyn Random {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Random").finish()
+    }
+}
+
+/// The `Random` seeded by the randomly initialized keys of the standard hasher.
+///
+/// This is the default source of random numbers of the application.
+#[derive(Debug, Default)]
+pub struct SystemRandom {
+    keys: RandomState,
+    // A 64-bit counter, which must not wrap around even on 32-bit platforms.
+    counter: Mutex<u64>,
+}
+
+impl Random for SystemRandom {
+    fn next_u64(&self) -> u64 {
+        let count = {
+            let mut counter = self.counter.lock().unwrap();
+            *counter = counter.wrapping_add(1);
+            *counter
+        };
+        let mut hasher = self.keys.build_hasher();
+        hasher.write_u64(count);
+        hasher.finish()
+    }
+}
+
+/// A `Random` that generates the same sequence for the same seed.
+#[derive(Debug)]
+pub struct SeededRandom {
+    state: Mutex<u64>,
+}
+
+impl SeededRandom {
+    /// Creates a `SeededRandom` with the specified seed.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            state: Mutex::new(seed),
+        }
+    }
+}
+
+impl Random for SeededRandom {
+    fn next_u64(&self) -> u64 {
+        // SplitMix64
+        let mut z = {
+            let mut state = self.state.lock().unwrap();
+            *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            *state
+        };
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
@@ -0,0 +1,866 @@
+//! Validation of the request and response bodies against JSON Schemas.
+//!
+//! The schemas are attached to the routes as `ModifyHandler`s. [`request`]
+//! validates the bodies parsed by `extractor::body::json` before they are
+//! deserialized, and rejects the violating requests with `422 Unprocessable Entity`
+//! listing the violations. [`response`] validates the JSON bodies of the responses
+//! for auditing, without blocking them unless the strict mode is enabled.
+//!
+//! ```
+//! # use tsukuyomi::{config::prelude::*, extractor, schema, App};
+//! # use serde_json::{json, Value};
+//! # fn main() -> Result<(), failure::Error> {
+//! let app = App::create(
+//!     path!("/users") //
+//!         .to(endpoint::post()
+//!             .extract(extractor::body::json())
+//!             .call(|user: Value| user.to_string()))
+//!         .modify(schema::request(&json!({
+//!             "type": "object",
+//!             "required": ["name"],
+//!             "properties": { "name": { "type": "string", "minLength": 1 } },
+//!         }))?),
+//! )?;
+//! # drop(app);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The supported keywords are a subset of the draft 7: `type`, `enum`, `const`,
+//! `properties`, `required`, `additionalProperties`, `minProperties`, `maxProperties`,
+//! `items`, `minItems`, `maxItems`, `uniqueItems`, `minLength`, `maxLength`,
+//! `minimum`, `maximum`, `exclusiveMinimum`, `exclusiveMaximum`, `multipleOf`,
+//! `allOf`, `anyOf`, `oneOf` and `not`. The annotations such as `title` and `format`
+//! are ignored, and the other keywords such as `$ref` and `pattern` are rejected
+//! when the schema is compiled.
+//!
+//! [`request`]: ./fn.request.html
+//! [`response`]: ./fn.response.html
+
+use {
+    crate::{
+        error::Error,
+        future::{Async, Poll, TryFuture},
+        handler::{AllowedMethods, Handler, ModifyHandler},
+        input::{
+            localmap::{local_key, LocalData},
+            Input,
+        },
+        output::{problem::Problem, IntoResponse, ResponseBody},
+        responder::Responder,
+    },
+    http::{Request, Response, StatusCode},
+    serde_json::Value,
+    std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+/// An error occurred during compiling a schema.
+#[derive(Debug, failure::Fail)]
+#[fail(display = "invalid schema at `{}': {}", pointer, message)]
+pub struct SchemaError {
+    pointer: String,
+    message: String,
+}
+
+impl SchemaError {
+    /// Returns the JSON pointer to the location of the problem in the schema.
+    pub fn pointer(&self) -> &str {
+        &self.pointer
+    }
+}
+
+/// A violation of a schema found in a JSON value.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct Violation {
+    /// The JSON pointer to the violating value in the instance.
+    pub path: String,
+
+    /// The description of the violation.
+    pub message: String,
+}
+
+/// A compiled JSON Schema.
+#[derive(Debug, Clone)]
+pub struct Schema {
+    root: Node,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Bool(bool),
+    Object(Vec<Keyword>),
+}
+
+#[derive(Debug, Clone)]
+enum Keyword {
+    Type(Vec<&'static str>),
+    Enum(Vec<Value>),
+    Const(Value),
+    Properties(Vec<(String, Node)>),
+    Required(Vec<String>),
+    AdditionalProperties(Vec<String>, Box<Node>),
+    MinProperties(usize),
+    MaxProperties(usize),
+    Items(Box<Node>),
+    MinItems(usize),
+    MaxItems(usize),
+    UniqueItems,
+    MinLength(usize),
+    MaxLength(usize),
+    Minimum(f64),
+    Maximum(f64),
+    ExclusiveMinimum(f64),
+    ExclusiveMaximum(f64),
+    MultipleOf(f64),
+    AllOf(Vec<Node>),
+    AnyOf(Vec<Node>),
+    OneOf(Vec<Node>),
+    Not(Box<Node>),
+}
+
+const TYPES: &[&str] = &[
+    "null", "boolean", "object", "array", "number", "integer", "string",
+];
+
+const ANNOTATIONS: &[&str] = &[
+    "$schema",
+    "$id",
+    "$comment",
+    "title",
+    "description",
+    "default",
+    "examples",
+    "format",
+    "readOnly",
+    "writeOnly",
+    "definitions",
+];
+
+impl Schema {
+    /// Compiles a schema from a JSON value.
+    pub fn compile(schema: &Value) -> Result<Self, SchemaError> {
+        Ok(Self {
+            root: compile_node(schema, "")?,
+        })
+    }
+
+    /// Validates the value, and returns up to `max_violations` violations.
+    pub fn validate(&self, value: &Value, max_violations: usize) -> Vec<Violation> {
+        let mut violations = vec![];
+        validate_node(&self.root, value, "", &mut violations);
+        violations.truncate(max_violations);
+        violations
+    }
+
+    /// Returns whether the value conforms to this schema.
+    pub fn is_valid(&self, value: &Value) -> bool {
+        self.validate(value, 1).is_empty()
+    }
+}
+
+fn escape_pointer(token: &str) -> String {
+    token.replace('~', "~0").replace('/', "~1")
+}
+
+fn compile_node(schema: &Value, pointer: &str) -> Result<Node, SchemaError> {
+    let error = |key: &str, message: &str| SchemaError {
+        pointer: format!("{}/{}", pointer, escape_pointer(key)),
+        message: message.to_owned(),
+    };
+    let schema = match *schema {
+        Value::Bool(b) => return Ok(Node::Bool(b)),
+        Value::Object(ref schema) => schema,
+        _ => {
+            return Err(SchemaError {
+                pointer: pointer.to_owned(),
+                message: "a schema must be an object or a boolean".into(),
+            });
+        }
+    };
+
+    let size = |key: &str, value: &Value| {
+        value
+            .as_u64()
+            .map(|n| n as usize)
+            .ok_or_else(|| error(key, "must be a non-negative integer"))
+    };
+    let number =
+        |key: &str, value: &Value| value.as_f64().ok_or_else(|| error(key, "must be a number"));
+    let subschemas = |key: &str, value: &Value| -> Result<Vec<Node>, SchemaError> {
+        let nodes = value
+            .as_array()
+            .filter(|nodes| !nodes.is_empty())
+            .ok_or_else(|| error(key, "must be a non-empty array of schemas"))?;
+        nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| compile_node(node, &format!("{}/{}/{}", pointer, key, i)))
+            .collect()
+    };
+
+    let mut keywords = vec![];
+    for (key, value) in schema {
+        let child = || format!("{}/{}", pointer, escape_pointer(key));
+        let keyword = match &**key {
+            "type" => {
+                let types: Vec<&Value> = match value {
+                    Value::Array(types) => types.iter().collect(),
+                    value => vec![value],
+                };
+                let types = types
+                    .into_iter()
+                    .map(|ty| {
+                        ty.as_str()
+                            .and_then(|ty| TYPES.iter().find(|&&t| t == ty).cloned())
+                            .ok_or_else(|| error(key, "unknown type"))
+                    })
+                    .collect::<Result<_, _>>()?;
+                Keyword::Type(types)
+            }
+            "enum" => Keyword::Enum(
+                value
+                    .as_array()
+                    .cloned()
+                    .ok_or_else(|| error(key, "must be an array"))?,
+            ),
+            "const" => Keyword::Const(value.clone()),
+            "properties" => {
+                let properties = value
+                    .as_object()
+                    .ok_or_else(|| error(key, "must be an object"))?;
+                Keyword::Properties(
+                    properties
+                        .iter()
+                        .map(|(name, node)| {
+                            let pointer = format!("{}/{}", child(), escape_pointer(name));
+                            compile_node(node, &pointer).map(|node| (name.clone(), node))
+                        })
+                        .collect::<Result<_, _>>()?,
+                )
+            }
+            "required" => Keyword::Required(
+                value
+                    .as_array()
+                    .and_then(|names| {
+                        names
+                            .iter()
+                            .map(|name| name.as_str().map(ToOwned::to_owned))
+                            .collect()
+                    })
+                    .ok_or_else(|| error(key, "must be an array of strings"))?,
+            ),
+            "additionalProperties" => {
+                let known = schema
+                    .get("properties")
+                    .and_then(Value::as_object)
+                    .map_or_else(Vec::new, |properties| properties.keys().cloned().collect());
+                Keyword::AdditionalProperties(known, Box::new(compile_node(value, &child())?))
+            }
+            "minProperties" => Keyword::MinProperties(size(key, value)?),
+            "maxProperties" => Keyword::MaxProperties(size(key, value)?),
+            "items" => Keyword::Items(Box::new(compile_node(value, &child())?)),
+            "minItems" => Keyword::MinItems(size(key, value)?),
+            "maxItems" => Keyword::MaxItems(size(key, value)?),
+            "uniqueItems" => match value.as_bool() {
+                Some(true) => Keyword::UniqueItems,
+                Some(false) => continue,
+                None => return Err(error(key, "must be a boolean")),
+            },
+            "minLength" => Keyword::MinLength(size(key, value)?),
+            "maxLength" => Keyword::MaxLength(size(key, value)?),
+            "minimum" => Keyword::Minimum(number(key, value)?),
+            "maximum" => Keyword::Maximum(number(key, value)?),
+            "exclusiveMinimum" => Keyword::ExclusiveMinimum(number(key, value)?),
+            "exclusiveMaximum" => Keyword::ExclusiveMaximum(number(key, value)?),
+            "multipleOf" => match value.as_f64() {
+                Some(n) if n > 0.0 => Keyword::MultipleOf(n),
+                _ => return Err(error(key, "must be a positive number")),
+            },
+            "allOf" => Keyword::AllOf(subschemas(key, value)?),
+            "anyOf" => Keyword::AnyOf(subschemas(key, value)?),
+            "oneOf" => Keyword::OneOf(subschemas(key, value)?),
+            "not" => Keyword::Not(Box::new(compile_node(value, &child())?)),
+            key if ANNOTATIONS.contains(&key) => continue,
+            key => return Err(error(key, "unsupported keyword")),
+        };
+        keywords.push(keyword);
+    }
+
+    Ok(Node::Object(keywords))
+}
+
+fn type_of(value: &Value) -> &'static str {
+    match *value {
+        Value::Null => "null",
+        Value::Bool(..) => "boolean",
+        Value::Object(..) => "object",
+        Value::Array(..) => "array",
+        Value::Number(ref n) if n.is_u64() || n.is_i64() => "integer",
+        Value::Number(..) => "number",
+        Value::String(..) => "string",
+    }
+}
+
+fn validate_node(node: &Node, value: &Value, path: &str, violations: &mut Vec<Violation>) {
+    let keywords = match *node {
+        Node::Bool(true) => return,
+        Node::Bool(false) => {
+            violations.push(Violation {
+                path: path.to_owned(),
+                message: "no value is allowed".into(),
+            });
+            return;
+        }
+        Node::Object(ref keywords) => keywords,
+    };
+
+    let mut violate = |message: String| {
+        violations.push(Violation {
+            path: path.to_owned(),
+            message,
+        })
+    };
+    let mut nested = vec![];
+
+    for keyword in keywords {
+        match (keyword, value) {
+            (Keyword::Type(types), value) => {
+                let actual = type_of(value);
+                let matched = types
+                    .iter()
+                    .any(|&ty| ty == actual || (ty == "number" && actual == "integer"));
+                if !matched {
+                    violate(format!("expected {}, found {}", types.join(" or "), actual));
+                }
+            }
+            (Keyword::Enum(values), value) => {
+                if !values.contains(value) {
+                    violate("the value is not one of the enumerated values".into());
+                }
+            }
+            (Keyword::Const(expected), value) => {
+                if expected != value {
+                    violate(format!("expected {}", expected));
+                }
+            }
+            (Keyword::Properties(properties), Value::Object(object)) => {
+                for (name, node) in properties {
+                    if let Some(value) = object.get(name) {
+                        nested.push((node, value, format!("{}/{}", path, escape_pointer(name))));
+                    }
+                }
+            }
+            (Keyword::Required(names), Value::Object(object)) => {
+                for name in names {
+                    if !object.contains_key(name) {
+                        violate(format!("missing the required property `{}'", name));
+                    }
+                }
+            }
+            (Keyword::AdditionalProperties(known, node), Value::Object(object)) => {
+                for (name, value) in object {
+                    if !known.contains(name) {
+                        nested.push((&**node, value, format!("{}/{}", path, escape_pointer(name))));
+                    }
+                }
+            }
+            (Keyword::MinProperties(min), Value::Object(object)) if object.len() < *min => {
+                violate(format!("expected at least {} properties", min));
+            }
+            (Keyword::MaxProperties(max), Value::Object(object)) if object.len() > *max => {
+                violate(format!("expected at most {} properties", max));
+            }
+            (Keyword::Items(node), Value::Array(items)) => {
+                for (i, item) in items.iter().enumerate() {
+                    nested.push((&**node, item, format!("{}/{}", path, i)));
+                }
+            }
+            (Keyword::MinItems(min), Value::Array(items)) if items.len() < *min => {
+                violate(format!("expected at least {} items", min));
+            }
+            (Keyword::MaxItems(max), Value::Array(items)) if items.len() > *max => {
+                violate(format!("expected at most {} items", max));
+            }
+            (Keyword::UniqueItems, Value::Array(items)) => {
+                let duplicated = items
+                    .iter()
+                    .enumerate()
+                    .any(|(i, item)| items[..i].contains(item));
+                if duplicated {
+                    violate("the items are not unique".into());
+                }
+            }
+            (Keyword::MinLength(min), Value::String(s)) if s.chars().count() < *min => {
+                violate(format!("expected at least {} characters", min));
+            }
+            (Keyword::MaxLength(max), Value::String(s)) if s.chars().count() > *max => {
+                violate(format!("expected at most {} characters", max));
+            }
+            (Keyword::Minimum(min), Value::Number(n)) if n.as_f64().map_or(false, |n| n < *min) => {
+                violate(format!("expected a number >= {}", min));
+            }
+            (Keyword::Maximum(max), Value::Number(n)) if n.as_f64().map_or(false, |n| n > *max) => {
+                violate(format!("expected a number <= {}", max));
+            }
+            (Keyword::ExclusiveMinimum(min), Value::Number(n))
+                if n.as_f64().map_or(false, |n| n <= *min) =>
+            {
+                violate(format!("expected a number > {}", min));
+            }
+            (Keyword::ExclusiveMaximum(max), Value::Number(n))
+                if n.as_f64().map_or(false, |n| n >= *max) =>
+            {
+                violate(format!("expected a number < {}", max));
+            }
+            (Keyword::MultipleOf(divisor), Value::Number(n)) => {
+                let n = n.as_f64().unwrap_or(0.0);
+                let quotient = n / divisor;
+                if (quotient - quotient.round()).abs() > 1e-9 {
+                    violate(format!("expected a multiple of {}", divisor));
+                }
+            }
+            (Keyword::AllOf(nodes), value) => {
+                for node in nodes {
+                    nested.push((node, value, path.to_owned()));
+                }
+            }
+            (Keyword::AnyOf(nodes), value) => {
+                if !nodes.iter().any(|node| conforms(node, value)) {
+                    violate("the value does not match any of the schemas in `anyOf'".into());
+                }
+            }
+            (Keyword::OneOf(nodes), value) => {
+                let matched = nodes.iter().filter(|node| conforms(node, value)).count();
+                if matched != 1 {
+                    violate(format!(
+                        "the value matches {} of the schemas in `oneOf', instead of exactly one",
+                        matched
+                    ));
+                }
+            }
+            (Keyword::Not(node), value) => {
+                if conforms(node, value) {
+                    violate("the value matches the schema in `not'".into());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    for (node, value, path) in nested {
+        validate_node(node, value, &path, violations);
+    }
+}
+
+fn conforms(node: &Node, value: &Value) -> bool {
+    let mut violations = vec![];
+    validate_node(node, value, "", &mut violations);
+    violations.is_empty()
+}
+
+// ==== request ====
+
+/// The default maximum number of the violations reported to the clients.
+const DEFAULT_MAX_VIOLATIONS: usize = 10;
+
+/// Creates a `ModifyHandler` that validates the request bodies against the schema.
+///
+/// The schema is compiled when this function is called, and the errors in the
+/// schema are reported with their locations.
+pub fn request(schema: &Value) -> Result<RequestSchema, SchemaError> {
+    Ok(RequestSchema {
+        schema: Arc::new(Schema::compile(schema)?),
+        max_violations: DEFAULT_MAX_VIOLATIONS,
+    })
+}
+
+/// A `ModifyHandler` that validates the request bodies, created by `schema::request`.
+///
+/// The schema is applied to the bodies parsed by `extractor::body::json` in the
+/// handler, before they are deserialized into the target type.
+#[derive(Debug, Clone)]
+pub struct RequestSchema {
+    schema: Arc<Schema>,
+    max_violations: usize,
+}
+
+impl RequestSchema {
+    /// Sets the maximum number of the violations listed in the error response.
+    ///
+    /// The default value is 10.
+    pub fn max_violations(self, max_violations: usize) -> Self {
+        Self {
+            max_violations,
+            ..self
+        }
+    }
+
+    /// Validates the parsed request body, and creates the error response of
+    /// `422 Unprocessable Entity` if it violates the schema.
+    pub(crate) fn validate(&self, value: &Value) -> Result<(), Error> {
+        let violations = self.schema.validate(value, self.max_violations);
+        if violations.is_empty() {
+            return Ok(());
+        }
+        Err(Problem::new(StatusCode::UNPROCESSABLE_ENTITY)
+            .detail("the request body violates the schema")
+            .extension("violations", violations)
+            .into())
+    }
+}
+
+impl LocalData for RequestSchema {
+    local_key! {
+        /// The local key for the schema of the request body in the current route.
+        const KEY: Self;
+    }
+}
+
+impl<H> ModifyHandler<H> for RequestSchema
+where
+    H: Handler,
+{
+    type Output = H::Output;
+    type Handler = RequestSchemaHandler<H>; // private
+
+    fn modify(&self, inner: H) -> Self::Handler {
+        RequestSchemaHandler {
+            inner,
+            schema: self.clone(),
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct RequestSchemaHandler<H> {
+    inner: H,
+    schema: RequestSchema,
+}
+
+impl<H> Handler for RequestSchemaHandler<H>
+where
+    H: Handler,
+{
+    type Output = H::Output;
+    type Error = H::Error;
+    type Handle = HandleRequestSchema<H::Handle>; // private
+
+    fn allowed_methods(&self) -> Option<&AllowedMethods> {
+        self.inner.allowed_methods()
+    }
+
+    fn handle(&self) -> Self::Handle {
+        HandleRequestSchema {
+            inner: self.inner.handle(),
+            schema: Some(self.schema.clone()),
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct HandleRequestSchema<H> {
+    inner: H,
+    schema: Option<RequestSchema>,
+}
+
+impl<H> TryFuture for HandleRequestSchema<H>
+where
+    H: TryFuture,
+{
+    type Ok = H::Ok;
+    type Error = H::Error;
+
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        if let Some(schema) = self.schema.take() {
+            schema.insert_into(input.locals);
+        }
+        self.inner.poll_ready(input)
+    }
+}
+
+// ==== response ====
+
+/// How the response bodies are validated by `ResponseSchema`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditMode {
+    /// The responses are not validated.
+    Off,
+
+    /// The violations are logged and counted, and the responses are sent as they are.
+    Log,
+
+    /// The violating responses are replaced with `500 Internal Server Error`,
+    /// which is intended for the tests.
+    Strict,
+}
+
+/// Creates a `ModifyHandler` that validates the response bodies against the schema.
+///
+/// The schema is compiled when this function is called, and the errors in the
+/// schema are reported with their locations.
+pub fn response(schema: &Value) -> Result<ResponseSchema, SchemaError> {
+    Ok(ResponseSchema {
+        schema: Arc::new(Schema::compile(schema)?),
+        mode: if cfg!(debug_assertions) {
+            AuditMode::Log
+        } else {
+            AuditMode::Off
+        },
+        violations: Arc::new(AtomicUsize::new(0)),
+    })
+}
+
+/// A `ModifyHandler` that validates the response bodies, created by `schema::response`.
+///
+/// Only the successful (`2xx`) responses whose bodies are buffered in memory
+/// are validated, and the bodies which are not JSON are regarded as violations.
+#[derive(Debug, Clone)]
+pub struct ResponseSchema {
+    schema: Arc<Schema>,
+    mode: AuditMode,
+    violations: Arc<AtomicUsize>,
+}
+
+impl ResponseSchema {
+    /// Sets how the responses are validated.
+    ///
+    /// The default value is `AuditMode::Log` in the debug builds, and `AuditMode::Off`
+    /// in the release builds.
+    pub fn mode(self, mode: AuditMode) -> Self {
+        Self { mode, ..self }
+    }
+
+    /// Returns the number of the violating responses.
+    ///
+    /// The counter is shared among the clones of this value.
+    pub fn violations(&self) -> u64 {
+        self.violations.load(Ordering::Relaxed) as u64
+    }
+
+    fn audit(&self, request: &Request<()>, body: &[u8]) -> Result<(), Error> {
+        let violations = match serde_json::from_slice(body) {
+            Ok(value) => self.schema.validate(&value, DEFAULT_MAX_VIOLATIONS),
+            Err(err) => vec![Violation {
+                path: String::new(),
+                message: format!("the body is not a valid JSON: {}", err),
+            }],
+        };
+        if violations.is_empty() {
+            return Ok(());
+        }
+
+        self.violations.fetch_add(1, Ordering::Relaxed);
+        let listed: Vec<String> = violations
+            .iter()
+            .map(|violation| format!("{}: {}", violation.path, violation.message))
+            .collect();
+        log::warn!(
+            "the response to {} {} violates the schema: {}",
+            request.method(),
+            request.uri().path(),
+            listed.join(", ")
+        );
+        match self.mode {
+            AuditMode::Strict => Err(crate::error::internal_server_error(
+                "the response body violates the schema",
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<H> ModifyHandler<H> for ResponseSchema
+where
+    H: Handler,
+    H::Output: Responder,
+{
+    type Output = Audited<H::Output>;
+    type Handler = ResponseSchemaHandler<H>; // private
+
+    fn modify(&self, inner: H) -> Self::Handler {
+        ResponseSchemaHandler {
+            inner,
+            schema: self.clone(),
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct ResponseSchemaHandler<H> {
+    inner: H,
+    schema: ResponseSchema,
+}
+
+impl<H> Handler for ResponseSchemaHandler<H>
+where
+    H: Handler,
+    H::Output: Responder,
+{
+    type Output = Audited<H::Output>;
+    type Error = H::Error;
+    type Handle = HandleResponseSchema<H::Handle>; // private
+
+    fn allowed_methods(&self) -> Option<&AllowedMethods> {
+        self.inner.allowed_methods()
+    }
+
+    fn handle(&self) -> Self::Handle {
+        HandleResponseSchema {
+            inner: self.inner.handle(),
+            schema: self.schema.clone(),
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct HandleResponseSchema<H> {
+    inner: H,
+    schema: ResponseSchema,
+}
+
+impl<H> TryFuture for HandleResponseSchema<H>
+where
+    H: TryFuture,
+{
+    type Ok = Audited<H::Ok>;
+    type Error = H::Error;
+
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        let inner = futures01::try_ready!(self.inner.poll_ready(input));
+        Ok(Async::Ready(Audited {
+            inner,
+            schema: self.schema.clone(),
+        }))
+    }
+}
+
+/// A `Responder` whose response body is validated against the schema.
+#[derive(Debug)]
+pub struct Audited<T> {
+    inner: T,
+    schema: ResponseSchema,
+}
+
+impl<T> Responder for Audited<T>
+where
+    T: Responder,
+{
+    type Response = AuditedResponse<T::Response>;
+    type Error = T::Error;
+    type Respond = AuditedRespond<T::Respond>; // private
+
+    fn respond(self) -> Self::Respond {
+        AuditedRespond {
+            inner: self.inner.respond(),
+            schema: Some(self.schema),
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct AuditedRespond<R> {
+    inner: R,
+    schema: Option<ResponseSchema>,
+}
+
+impl<R> TryFuture for AuditedRespond<R>
+where
+    R: TryFuture,
+{
+    type Ok = AuditedResponse<R::Ok>;
+    type Error = R::Error;
+
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        let inner = futures01::try_ready!(self.inner.poll_ready(input));
+        Ok(Async::Ready(AuditedResponse {
+            inner,
+            schema: self
+                .schema
+                .take()
+                .expect("the future has already been polled."),
+        }))
+    }
+}
+
+/// An `IntoResponse` whose body is validated against the schema.
+#[derive(Debug)]
+pub struct AuditedResponse<T> {
+    inner: T,
+    schema: ResponseSchema,
+}
+
+impl<T> IntoResponse for AuditedResponse<T>
+where
+    T: IntoResponse,
+{
+    type Body = ResponseBody;
+    type Error = Error;
+
+    fn into_response(self, request: &Request<()>) -> Result<Response<Self::Body>, Self::Error> {
+        let response = self
+            .inner
+            .into_response(request)
+            .map_err(Into::into)?
+            .map(Into::<ResponseBody>::into);
+        if self.schema.mode == AuditMode::Off || !response.status().is_success() {
+            return Ok(response);
+        }
+
+        let (parts, body) = response.into_parts();
+        match body.try_into_bytes() {
+            Ok(body) => {
+                self.schema.audit(request, &body)?;
+                Ok(Response::from_parts(parts, body.into()))
+            }
+            Err(body) => Ok(Response::from_parts(parts, body)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, serde_json::json};
+
+    #[test]
+    fn compile_error_pointer() {
+        let err = Schema::compile(&json!({
+            "type": "object",
+            "properties": { "tags": { "items": { "type": "text" } } },
+        }))
+        .unwrap_err();
+        assert_eq!(err.pointer(), "/properties/tags/items/type");
+
+        let err = Schema::compile(&json!({ "$ref": "#/definitions/user" })).unwrap_err();
+        assert_eq!(err.pointer(), "/$ref");
+    }
+
+    #[test]
+    fn validate_keywords() {
+        let schema = Schema::compile(&json!({
+            "type": "object",
+            "required": ["id", "tags"],
+            "properties": {
+                "id": { "type": "integer", "minimum": 1 },
+                "tags": { "type": "array", "items": { "type": "string" }, "uniqueItems": true },
+                "kind": { "enum": ["a", "b"] },
+            },
+            "additionalProperties": false,
+        }))
+        .unwrap();
+
+        assert!(schema.is_valid(&json!({ "id": 1, "tags": ["x", "y"], "kind": "a" })));
+
+        let violations = schema.validate(
+            &json!({ "id": 0, "tags": ["x", 1, "x"], "kind": "c", "extra": true }),
+            10,
+        );
+        let mut paths: Vec<&str> = violations.iter().map(|v| &*v.path).collect();
+        paths.sort();
+        assert_eq!(paths, vec!["/extra", "/id", "/kind", "/tags", "/tags/1"]);
+
+        assert_eq!(schema.validate(&json!({}), 1).len(), 1);
+    }
+}
@@ -0,0 +1,67 @@
+//! Utilities for testing the applications.
+
+mod fallback;
+
+pub use self::fallback::{
+    fallback_context, read_body, resource, FallbackContext, FallbackKind, Resource,
+};
+
+use std::{env, fs, io, path::Path};
+
+/// Asserts that the manifest of the routing table matches the snapshot file.
+///
+/// The path of the snapshot is relative to the directory of the package which
+/// invokes the macro. If the file does not exist, or the environment variable
+/// `TSUKUYOMI_UPDATE_SNAPSHOTS` is set, the snapshot is written instead.
+/// See also `App::manifest`.
+///
+/// ```no_run
+/// # use tsukuyomi::{config::prelude::*, App};
+/// let app = App::create(path!("/").to(endpoint::get().reply("hello"))).unwrap();
+/// tsukuyomi::test::assert_manifest_snapshot!(app, "tests/snapshots/routes.snap");
+/// ```
+#[macro_export]
+macro_rules! assert_manifest_snapshot {
+    ($app:expr, $path:expr) => {
+        $crate::test::assert_manifest_snapshot_impl(
+            &$app.manifest(),
+            &::std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join($path),
+        )
+    };
+}
+
+#[doc(inline)]
+pub use crate::assert_manifest_snapshot;
+
+#[doc(hidden)]
+pub fn assert_manifest_snapshot_impl(manifest: &str, path: &Path) {
+    if env::var_os("TSUKUYOMI_UPDATE_SNAPSHOTS").is_none() {
+        match fs::read_to_string(path) {
+            Ok(snapshot) => {
+                assert!(
+                    snapshot == manifest,
+                    "the manifest does not match the snapshot {} \
+                     (set TSUKUYOMI_UPDATE_SNAPSHOTS to update it)\n\
+                     --- snapshot\n{}--- manifest\n{}",
+                    path.display(),
+                    snapshot,
+                    manifest,
+                );
+                return;
+            }
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => panic!("failed to read the snapshot {}: {}", path.display(), err),
+        }
+    }
+    write_snapshot(manifest, path);
+}
+
+fn write_snapshot(manifest: &str, path: &Path) {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).unwrap_or_else(|err| {
+            panic!("failed to create the directory {}: {}", dir.display(), err)
+        });
+    }
+    fs::write(path, manifest)
+        .unwrap_or_else(|err| panic!("failed to write the snapshot {}: {}", path.display(), err));
+}
@@ -0,0 +1,307 @@
+use {
+    crate::{
+        app::{Dispatch, DispatchFuture, Routes, States},
+        endpoint::{ApplyContext, Endpoint},
+        error::Error,
+        future::TryFuture,
+        guard::Pending,
+        handler::AllowedMethods,
+        input::{
+            body::RequestBody,
+            localmap::{LocalData, LocalMap},
+            response::ResponseHeaders,
+            CookieState, Cookies, Input,
+        },
+        output::{IntoResponse, ResponseBody},
+        responder::Responder,
+        rt::{Clock, SystemClock, SystemRandom},
+    },
+    bytes::{Bytes, BytesMut},
+    futures01::Async,
+    http::{Method, Request, Response, StatusCode},
+    hyper::body::Payload,
+    std::{
+        any::{Any, TypeId},
+        fmt,
+        marker::PhantomData,
+        sync::Arc,
+    },
+};
+
+/// Creates a `FallbackContext` for invoking a fallback without dispatching the request
+/// through an `App`.
+///
+/// The context initially represents `GET /` which matched no route.
+///
+/// ```
+/// # use tsukuyomi::{fallback, test};
+/// let cx = test::fallback_context()
+///     .request(http::Request::get("/user/42").body(()).unwrap())
+///     .not_found(vec![test::resource("/users/:id", vec![http::Method::GET])]);
+///
+/// let response = cx.call(fallback::suggestions(3)).unwrap();
+/// assert_eq!(response.status(), 404);
+/// assert_eq!(response.headers()["link"], "</users/42>; rel=\"alternate\"");
+/// ```
+pub fn fallback_context() -> FallbackContext {
+    FallbackContext {
+        request: Request::new(()),
+        kind: FallbackKind::NotFound(vec![]),
+    }
+}
+
+/// Creates a synthetic `Resource` with the specified URI pattern and methods.
+pub fn resource(uri: impl Into<String>, methods: impl IntoIterator<Item = Method>) -> Resource {
+    Resource {
+        uri: uri.into(),
+        methods: methods.into_iter().collect(),
+    }
+}
+
+/// Reads the entire of the response body, such as the one returned from `FallbackContext::call`.
+pub fn read_body(mut body: ResponseBody) -> Result<Bytes, hyper::Error> {
+    let mut buf = BytesMut::new();
+    futures01::executor::spawn(futures01::future::poll_fn(|| {
+        while let Some(chunk) = futures01::try_ready!(body.poll_data()) {
+            buf.extend_from_slice(&chunk);
+        }
+        Ok::<_, hyper::Error>(Async::Ready(()))
+    }))
+    .wait_future()?;
+    Ok(buf.freeze())
+}
+
+/// A synthetic route registered in the application, created by `test::resource`.
+#[derive(Debug)]
+pub struct Resource {
+    uri: String,
+    methods: AllowedMethods,
+}
+
+impl Resource {
+    /// Returns the URI pattern of this resource.
+    pub fn uri(&self) -> &str {
+        &self.uri
+    }
+
+    /// Returns the methods accepted by this resource.
+    pub fn methods(&self) -> &AllowedMethods {
+        &self.methods
+    }
+}
+
+/// The kind of the result of routing which leads to a fallback.
+#[derive(Debug)]
+pub enum FallbackKind {
+    /// No route matched the path, and the routes in the list are the candidates
+    /// compared with it, such as by `fallback::suggestions`.
+    NotFound(Vec<Resource>),
+
+    /// A route matched the path, but it does not accept the method of the request.
+    FoundResource(Resource),
+}
+
+/// The context of a fallback, created by `test::fallback_context`.
+#[derive(Debug)]
+pub struct FallbackContext {
+    request: Request<()>,
+    kind: FallbackKind,
+}
+
+impl FallbackContext {
+    /// Sets the request passed to the fallback.
+    pub fn request(self, request: Request<()>) -> Self {
+        Self { request, ..self }
+    }
+
+    /// Makes this context represent the request which matched no route.
+    pub fn not_found(self, candidates: impl IntoIterator<Item = Resource>) -> Self {
+        Self {
+            kind: FallbackKind::NotFound(candidates.into_iter().collect()),
+            ..self
+        }
+    }
+
+    /// Makes this context represent the request whose method is not accepted by the resource.
+    pub fn found_resource(self, resource: Resource) -> Self {
+        Self {
+            kind: FallbackKind::FoundResource(resource),
+            ..self
+        }
+    }
+
+    /// Returns the kind of this context.
+    pub fn kind(&self) -> &FallbackKind {
+        &self.kind
+    }
+
+    /// Invokes the `Endpoint` registered as the default handler, as `path!("*")` does,
+    /// and polls it to the response.
+    ///
+    /// The response headers set through `Input::response` are merged into the response.
+    /// The endpoint is polled on the current thread until it completes.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the context is not `NotFound`, since the default handler
+    /// is not invoked for the requests matched to a route.
+    pub fn call<T>(&self, fallback: T) -> Result<Response<ResponseBody>, Error>
+    where
+        T: Endpoint<()>,
+        T::Output: Responder,
+    {
+        let candidates = match self.kind {
+            FallbackKind::NotFound(ref candidates) => candidates,
+            FallbackKind::FoundResource(..) => {
+                panic!("the default handler is only invoked for the unmatched requests")
+            }
+        };
+        let routes = SyntheticRoutes(candidates);
+
+        let mut cookies = CookieState::default();
+        let mut locals = LocalMap::default();
+        RequestBody::from(hyper::Body::empty()).insert_into(&mut locals);
+        let mut response_headers = ResponseHeaders::default();
+        let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+        let random = SystemRandom::default();
+        let mut input = Input {
+            request: &self.request,
+            params: &None,
+            cookies: &mut Cookies::new(&mut cookies, &self.request),
+            locals: &mut locals,
+            response_headers: &mut response_headers,
+            states: &NoStates,
+            routes: &routes,
+            dispatch: &NoDispatch,
+            clock: &clock,
+            random: &random,
+            _marker: PhantomData,
+        };
+
+        let mut state = State::Apply(fallback);
+        let mut response = futures01::executor::spawn(futures01::future::poll_fn(
+            || -> futures01::Poll<_, Error> {
+                loop {
+                    state = match state {
+                        State::Apply(ref fallback) => {
+                            let mut cx = ApplyContext::new(&mut input);
+                            let handle = fallback
+                                .apply((), &mut cx)
+                                .map_err(|(_args, err)| Error::from(err))?;
+                            State::Handle(cx.take_pending(), handle)
+                        }
+                        State::Handle(ref mut pending, ref mut handle) => {
+                            futures01::try_ready!(pending.poll_ready());
+                            let output = futures01::try_ready!(handle
+                                .poll_ready(&mut input)
+                                .map_err(Into::into));
+                            State::Respond(output.respond())
+                        }
+                        State::Respond(ref mut respond) => {
+                            let output = futures01::try_ready!(respond
+                                .poll_ready(&mut input)
+                                .map_err(Into::into));
+                            let response = output
+                                .into_response(input.request)
+                                .map_err(Into::into)?
+                                .map(Into::into);
+                            return Ok(Async::Ready(response));
+                        }
+                    };
+                }
+            },
+        ))
+        .wait_future()?;
+
+        response_headers.merge_into(response.headers_mut());
+        Ok(response)
+    }
+
+    /// Renders the response with the renderer of `405 Method Not Allowed`, as
+    /// `App::with_method_not_allowed` does.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the context is not `FoundResource`.
+    pub fn render_method_not_allowed<F>(&self, render: F) -> Response<ResponseBody>
+    where
+        F: Fn(&Request<()>, &AllowedMethods) -> Response<ResponseBody>,
+    {
+        match self.kind {
+            FallbackKind::FoundResource(ref resource) => render(&self.request, &resource.methods),
+            FallbackKind::NotFound(..) => {
+                panic!("the renderer of 405 is only invoked for the matched requests")
+            }
+        }
+    }
+
+    /// Returns the response created by the application if no fallback is registered.
+    ///
+    /// It is the empty `404 Not Found` for `NotFound`, and the response of
+    /// `fallback::method_not_allowed` for `FoundResource`.
+    pub fn default_response(&self) -> Response<ResponseBody> {
+        match self.kind {
+            FallbackKind::NotFound(..) => {
+                Error::from(StatusCode::NOT_FOUND).into_response(&self.request)
+            }
+            FallbackKind::FoundResource(..) => {
+                self.render_method_not_allowed(crate::fallback::method_not_allowed)
+            }
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+enum State<T, Fut, R> {
+    Apply(T),
+    Handle(Pending, Fut),
+    Respond(R),
+}
+
+struct SyntheticRoutes<'a>(&'a [Resource]);
+
+impl<'a> fmt::Debug for SyntheticRoutes<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.0.iter().map(|resource| &resource.uri))
+            .finish()
+    }
+}
+
+impl<'a> Routes for SyntheticRoutes<'a> {
+    fn candidates(&self, _: &str) -> Vec<&str> {
+        self.0.iter().map(|resource| &*resource.uri).collect()
+    }
+
+    fn methods(&self) -> AllowedMethods {
+        self.0
+            .iter()
+            .flat_map(|resource| resource.methods.iter().cloned())
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+struct NoStates;
+
+impl States for NoStates {
+    fn get(&self, _: TypeId) -> Option<&(dyn Any + Send + Sync)> {
+        None
+    }
+
+    fn get_all<'a>(
+        &'a self,
+        _: TypeId,
+    ) -> Box<dyn Iterator<Item = &'a (dyn Any + Send + Sync)> + 'a> {
+        Box::new(std::iter::empty())
+    }
+}
+
+#[derive(Debug)]
+struct NoDispatch;
+
+impl Dispatch for NoDispatch {
+    fn dispatch(&self, _: Request<RequestBody>, _: LocalMap) -> Option<DispatchFuture> {
+        None
+    }
+}
@@ -0,0 +1,437 @@
+//! Propagation of the trace context of the incoming requests, as specified by
+//! [W3C Trace Context].
+//!
+//! The modifier [`Trace`] parses `traceparent` and `tracestate` of the incoming
+//! request, and starts a new span of the trace for the request. The resulting
+//! [`TraceContext`] is stored into the local map, and can be extracted by
+//! [`extractor::trace_context`]:
+//!
+//! ```
+//! # use tsukuyomi::{config::prelude::*, extractor, trace::{Trace, TraceContext}, App};
+//! let app = App::create(
+//!     path!("/") //
+//!         .to(endpoint::get()
+//!             .extract(extractor::trace_context())
+//!             .call(|cx: TraceContext| format!("trace-id: {:032x}", cx.trace_id())))
+//!         .modify(Trace::new()),
+//! );
+//! # drop(app);
+//! ```
+//!
+//! The malformed headers are ignored as required by the specification, and a new
+//! trace is started for the request instead. The `traceparent` of the new span is
+//! added to the response, and can be added to the outgoing requests to the other
+//! services with [`TraceContext::inject`].
+//!
+//! [W3C Trace Context]: https://www.w3.org/TR/trace-context/
+//! [`Trace`]: ./struct.Trace.html
+//! [`TraceContext`]: ./struct.TraceContext.html
+//! [`TraceContext::inject`]: ./struct.TraceContext.html#method.inject
+//! [`extractor::trace_context`]: ../extractor/fn.trace_context.html
+
+use {
+    crate::{
+        error::Error,
+        extractor::Extractor,
+        future::{Poll, TryFuture},
+        handler::{AllowedMethods, Handler, ModifyHandler},
+        input::{
+            localmap::{local_key, LocalData},
+            Input,
+        },
+    },
+    http::header::{HeaderMap, HeaderName, HeaderValue},
+    std::collections::HashSet,
+};
+
+/// The name of the header field carrying the position of the request in the trace.
+pub const TRACEPARENT: &str = "traceparent";
+
+/// The name of the header field carrying the vendor-specific trace information.
+pub const TRACESTATE: &str = "tracestate";
+
+/// The flag indicating that the caller may have recorded the trace.
+const SAMPLED: u8 = 0x01;
+
+/// The maximum number of the list members in `tracestate`.
+const MAX_TRACESTATE_MEMBERS: usize = 32;
+
+/// The trace context of the current request.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceContext {
+    trace_id: u128,
+    span_id: u64,
+    parent_id: Option<u64>,
+    flags: u8,
+    tracestate: Option<String>,
+}
+
+impl TraceContext {
+    /// Returns the ID of the whole trace, which is inherited from the caller.
+    pub fn trace_id(&self) -> u128 {
+        self.trace_id
+    }
+
+    /// Returns the ID of the span generated for the current request.
+    pub fn span_id(&self) -> u64 {
+        self.span_id
+    }
+
+    /// Returns the ID of the span of the caller, or `None` if the request started a new trace.
+    pub fn parent_id(&self) -> Option<u64> {
+        self.parent_id
+    }
+
+    /// Returns the trace flags, which are propagated from the caller as they are.
+    pub fn flags(&self) -> u8 {
+        self.flags
+    }
+
+    /// Returns `true` if the caller may have recorded the trace.
+    pub fn is_sampled(&self) -> bool {
+        self.flags & SAMPLED != 0
+    }
+
+    /// Returns the value of `tracestate` received from the caller, if it is valid.
+    pub fn tracestate(&self) -> Option<&str> {
+        self.tracestate.as_ref().map(|s| &**s)
+    }
+
+    /// Returns the value of `traceparent` that identifies the span of the current request.
+    ///
+    /// The value is always serialized with the version `00`.
+    pub fn traceparent(&self) -> String {
+        format!(
+            "00-{:032x}-{:016x}-{:02x}",
+            self.trace_id, self.span_id, self.flags
+        )
+    }
+
+    /// Adds `traceparent` and `tracestate` to the header map of an outgoing request,
+    /// so that the called service continues the trace as a child of the current span.
+    pub fn inject(&self, headers: &mut HeaderMap) {
+        headers.insert(
+            HeaderName::from_static(TRACEPARENT),
+            HeaderValue::from_str(&self.traceparent()).expect("should be a valid header value"),
+        );
+        headers.remove(TRACESTATE);
+        if let Some(ref tracestate) = self.tracestate {
+            headers.insert(
+                HeaderName::from_static(TRACESTATE),
+                HeaderValue::from_str(tracestate).expect("validated when parsing"),
+            );
+        }
+    }
+}
+
+impl LocalData for TraceContext {
+    local_key! {
+        /// The local key to manage the trace context of the current request.
+        const KEY: Self;
+    }
+}
+
+/// Parses the value of `traceparent` into the trace ID, the parent ID and the flags.
+fn parse_traceparent(value: &str) -> Option<(u128, u64, u8)> {
+    fn hex(s: &str) -> Option<&str> {
+        if s.bytes()
+            .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+        {
+            Some(s)
+        } else {
+            None
+        }
+    }
+
+    if value.len() < 55 || !value.is_ascii() {
+        return None;
+    }
+    let version = u8::from_str_radix(hex(value.get(0..2)?)?, 16).ok()?;
+    match version {
+        0xff => return None,
+        0x00 if value.len() != 55 => return None,
+        // the future versions may append the fields after a dash.
+        _ if value.len() > 55 && value.as_bytes()[55] != b'-' => return None,
+        _ => {}
+    }
+    let bytes = value.as_bytes();
+    if bytes[2] != b'-' || bytes[35] != b'-' || bytes[52] != b'-' {
+        return None;
+    }
+    let trace_id = u128::from_str_radix(hex(&value[3..35])?, 16).ok()?;
+    let parent_id = u64::from_str_radix(hex(&value[36..52])?, 16).ok()?;
+    let flags = u8::from_str_radix(hex(&value[53..55])?, 16).ok()?;
+    if trace_id == 0 || parent_id == 0 {
+        return None;
+    }
+    Some((trace_id, parent_id, flags))
+}
+
+/// Combines the values of `tracestate` into a list, or returns `None` if any of
+/// the list members is malformed.
+fn parse_tracestate(headers: &HeaderMap) -> Option<String> {
+    let mut keys = HashSet::new();
+    let mut members = vec![];
+    for value in headers.get_all(TRACESTATE) {
+        for member in value.to_str().ok()?.split(',') {
+            let member = member.trim_matches(|c| c == ' ' || c == '\t');
+            if member.is_empty() {
+                continue;
+            }
+            let pos = member.find('=')?;
+            let (key, value) = (&member[..pos], &member[pos + 1..]);
+            if !is_valid_key(key) || !is_valid_value(value) || !keys.insert(key) {
+                return None;
+            }
+            members.push(member);
+        }
+    }
+    if members.is_empty() || members.len() > MAX_TRACESTATE_MEMBERS {
+        return None;
+    }
+    Some(members.join(","))
+}
+
+fn is_valid_key(key: &str) -> bool {
+    fn is_key_char(b: u8) -> bool {
+        b.is_ascii_lowercase() || b.is_ascii_digit() || b"_-*/".contains(&b)
+    }
+    match key.find('@') {
+        Some(pos) => {
+            let (tenant, system) = (&key[..pos], &key[pos + 1..]);
+            tenant.len() <= 241
+                && system.len() <= 14
+                && tenant
+                    .bytes()
+                    .next()
+                    .map_or(false, |b| b.is_ascii_lowercase() || b.is_ascii_digit())
+                && system
+                    .bytes()
+                    .next()
+                    .map_or(false, |b| b.is_ascii_lowercase())
+                && tenant.bytes().chain(system.bytes()).all(is_key_char)
+        }
+        None => {
+            key.len() <= 256
+                && key.bytes().next().map_or(false, |b| b.is_ascii_lowercase())
+                && key.bytes().all(is_key_char)
+        }
+    }
+}
+
+fn is_valid_value(value: &str) -> bool {
+    value.len() <= 256
+        && !value.ends_with(' ')
+        && !value.is_empty()
+        && value
+            .bytes()
+            .all(|b| b >= 0x20 && b <= 0x7e && b != b',' && b != b'=')
+}
+
+/// A `ModifyHandler` that starts a new span of the trace for each request.
+///
+/// The trace context is read from `traceparent` and `tracestate` of the request,
+/// and a new span ID is generated from `Input::random`. If `traceparent` is missing
+/// or malformed, a new trace is started without the parent, and `tracestate` is
+/// discarded. The resulting `TraceContext` is stored into the local map, and its
+/// `traceparent` is added to the response, including the error responses.
+///
+/// If the trace context has already been set by another `Trace` applied to the
+/// outer scope, it is used as it is.
+#[derive(Debug, Clone, Default)]
+pub struct Trace {
+    sample_roots: bool,
+}
+
+impl Trace {
+    /// Creates a `Trace` with the default configuration.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets whether to set the sampled flag on the traces started by this modifier.
+    ///
+    /// The flag of the traces inherited from the callers are not affected.
+    /// The default value is `false`.
+    pub fn sample_roots(self, enabled: bool) -> Self {
+        Self {
+            sample_roots: enabled,
+        }
+    }
+
+    fn start(&self, input: &Input<'_>) -> TraceContext {
+        let random = input.random();
+        let span_id = loop {
+            let id = random.next_u64();
+            if id != 0 {
+                break id;
+            }
+        };
+
+        let headers = input.request.headers();
+        let mut traceparent = headers.get_all(TRACEPARENT).iter();
+        let parent = match (traceparent.next(), traceparent.next()) {
+            (Some(value), None) => value.to_str().ok().and_then(parse_traceparent),
+            _ => None,
+        };
+        match parent {
+            Some((trace_id, parent_id, flags)) => TraceContext {
+                trace_id,
+                span_id,
+                parent_id: Some(parent_id),
+                flags,
+                tracestate: parse_tracestate(headers),
+            },
+            None => TraceContext {
+                trace_id: loop {
+                    let id = u128::from(random.next_u64()) << 64 | u128::from(random.next_u64());
+                    if id != 0 {
+                        break id;
+                    }
+                },
+                span_id,
+                parent_id: None,
+                flags: if self.sample_roots { SAMPLED } else { 0 },
+                tracestate: None,
+            },
+        }
+    }
+}
+
+impl<H> ModifyHandler<H> for Trace
+where
+    H: Handler,
+{
+    type Output = H::Output;
+    type Handler = TraceHandler<H>; // private
+
+    fn modify(&self, inner: H) -> Self::Handler {
+        TraceHandler {
+            inner,
+            modifier: self.clone(),
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct TraceHandler<H> {
+    inner: H,
+    modifier: Trace,
+}
+
+impl<H> Handler for TraceHandler<H>
+where
+    H: Handler,
+{
+    type Output = H::Output;
+    type Error = Error;
+    type Handle = HandleTrace<H::Handle>; // private
+
+    fn allowed_methods(&self) -> Option<&AllowedMethods> {
+        self.inner.allowed_methods()
+    }
+
+    fn handle(&self) -> Self::Handle {
+        HandleTrace {
+            inner: self.inner.handle(),
+            modifier: Some(self.modifier.clone()),
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct HandleTrace<H> {
+    inner: H,
+    modifier: Option<Trace>,
+}
+
+impl<H> TryFuture for HandleTrace<H>
+where
+    H: TryFuture,
+{
+    type Ok = H::Ok;
+    type Error = Error;
+
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        if let Some(modifier) = self.modifier.take() {
+            if !TraceContext::contains(input.locals) {
+                let cx = modifier.start(input);
+                input
+                    .response()
+                    .set(HeaderName::from_static(TRACEPARENT), cx.traceparent())?;
+                cx.insert_into(input.locals);
+            }
+        }
+        self.inner.poll_ready(input).map_err(Into::into)
+    }
+}
+
+/// Creates an `Extractor` that returns the trace context of the current request.
+///
+/// The extractor fails with `500 Internal Server Error` if `Trace` is not applied.
+pub fn trace_context() -> impl Extractor<
+    Output = (TraceContext,), //
+    Error = Error,
+    Extract = impl TryFuture<Ok = (TraceContext,), Error = Error> + Send + 'static,
+> {
+    crate::extractor::ready(|input| {
+        TraceContext::get(input.locals)
+            .cloned()
+            .map(|cx| (cx,))
+            .ok_or_else(|| crate::error::internal_server_error("the trace context is not set"))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn traceparent() {
+        assert_eq!(
+            parse_traceparent("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"),
+            Some((0x4bf92f3577b34da6a3ce929d0e0e4736, 0x00f067aa0ba902b7, 0x01))
+        );
+        // the future versions.
+        assert_eq!(
+            parse_traceparent("cc-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00-what"),
+            Some((0x4bf92f3577b34da6a3ce929d0e0e4736, 0x00f067aa0ba902b7, 0x00))
+        );
+
+        for malformed in &[
+            "",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+            "cc-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01extra",
+            "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736_00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e473g-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-0x",
+        ] {
+            assert_eq!(parse_traceparent(malformed), None, "{:?}", malformed);
+        }
+    }
+
+    #[test]
+    fn tracestate() {
+        let parse = |values: &[&'static str]| {
+            let mut headers = HeaderMap::new();
+            for value in values {
+                headers.append(TRACESTATE, HeaderValue::from_static(value));
+            }
+            parse_tracestate(&headers)
+        };
+
+        assert_eq!(
+            parse(&["congo=t61rcWkgMzE, rojo=00f067aa0ba902b7", "t@sys=x"]),
+            Some("congo=t61rcWkgMzE,rojo=00f067aa0ba902b7,t@sys=x".into())
+        );
+        assert_eq!(parse(&[]), None);
+        assert_eq!(parse(&["Congo=t61rcWkgMzE"]), None);
+        assert_eq!(parse(&["congo"]), None);
+        assert_eq!(parse(&["congo=a,congo=b"]), None);
+        assert_eq!(parse(&["congo=a=b"]), None);
+    }
+}
@@ -0,0 +1,215 @@
+//! Components for switching the connection to another protocol via HTTP/1.1 upgrade.
+//!
+//! ```
+//! # use tsukuyomi::{config::prelude::*, upgrade::{self, UpgradeRequest}, App};
+//! # use http::Response;
+//! let app = App::create(
+//!     path!("/echo") //
+//!         .to(endpoint::get()
+//!             .extract(upgrade::upgrade())
+//!             .call(|req: UpgradeRequest| {
+//!                 req.require_protocol("lines")?;
+//!                 req.finish(
+//!                     &mut Response::builder(),
+//!                     upgrade::lines(|line: String| Ok(Some(line))),
+//!                 )
+//!             })),
+//! );
+//! # drop(app);
+//! ```
+
+use {
+    crate::{
+        error::Error,
+        extractor::Extractor,
+        future::TryFuture,
+        input::{
+            body::{RequestBody, UpgradedIo},
+            localmap::LocalData,
+        },
+    },
+    futures01::{future::Either, Future, IntoFuture, Sink, Stream},
+    http::{
+        header::{HeaderMap, HeaderValue, CONNECTION, UPGRADE},
+        response::Builder,
+        Response, StatusCode,
+    },
+    tokio_codec::{Framed, LinesCodec},
+    tokio_executor::Executor,
+};
+
+/// Creates an `Extractor` that returns an `UpgradeRequest`.
+///
+/// The extractor fails with `400 Bad Request` if the request does not ask
+/// the protocol upgrade, that is, if the `Upgrade` header is missing or the
+/// `Connection` header does not contain the token `upgrade`.
+pub fn upgrade() -> impl Extractor<
+    Output = (UpgradeRequest,), //
+    Error = Error,
+    Extract = impl TryFuture<Ok = (UpgradeRequest,), Error = Error> + Send + 'static,
+> {
+    crate::extractor::ready(|input| {
+        let headers = input.request.headers();
+
+        let connection_upgrade = headers
+            .get_all(CONNECTION)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|token| token.trim().eq_ignore_ascii_case("upgrade"));
+        if !connection_upgrade {
+            return Err(crate::error::bad_request(
+                "the Connection header does not contain `upgrade'",
+            ));
+        }
+
+        let protocols: Vec<String> = headers
+            .get_all(UPGRADE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .filter(|protocol| !protocol.is_empty())
+            .map(ToOwned::to_owned)
+            .collect();
+        if protocols.is_empty() {
+            return Err(crate::error::bad_request("missing the Upgrade header"));
+        }
+
+        let headers = headers.clone();
+        let body = RequestBody::take_from(input.locals).ok_or_else(|| {
+            crate::error::internal_server_error(
+                "the request body has already been stolen by someone",
+            )
+        })?;
+
+        Ok((UpgradeRequest {
+            protocols,
+            headers,
+            body,
+        },))
+    })
+}
+
+/// A request for switching the protocol of the connection.
+#[derive(Debug)]
+pub struct UpgradeRequest {
+    protocols: Vec<String>,
+    headers: HeaderMap,
+    body: RequestBody,
+}
+
+impl UpgradeRequest {
+    /// Returns the list of protocols requested by the client, in order of preference.
+    pub fn protocols(&self) -> &[String] {
+        &self.protocols[..]
+    }
+
+    /// Returns the header map of the request.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// Checks if the specified protocol is requested by the client, ignoring the case.
+    pub fn require_protocol(&self, protocol: &str) -> Result<(), Error> {
+        if self
+            .protocols
+            .iter()
+            .any(|p| p.eq_ignore_ascii_case(protocol))
+        {
+            Ok(())
+        } else {
+            Err(crate::error::bad_request(format!(
+                "the protocol `{}' is not requested",
+                protocol
+            )))
+        }
+    }
+
+    /// Checks if the header field with the specified name is equal to the value, ignoring the case.
+    pub fn require_header(&self, name: &str, value: &str) -> Result<(), Error> {
+        match self.headers.get(name) {
+            Some(h) if h.as_bytes().eq_ignore_ascii_case(value.as_bytes()) => Ok(()),
+            Some(..) => Err(crate::error::bad_request(format!(
+                "the header value is invalid: `{}'",
+                name
+            ))),
+            None => Err(crate::error::bad_request(format!(
+                "the header is missing: `{}'",
+                name
+            ))),
+        }
+    }
+
+    /// Creates a `101 Switching Protocols` response, and registers the function to be
+    /// called with the upgraded I/O.
+    ///
+    /// The status code of the builder is overwritten with `101`, and the headers
+    /// `Connection: upgrade` and `Upgrade` are added unless they are already set.
+    /// The value of `Upgrade` defaults to the first protocol requested by the client.
+    ///
+    /// The function is called only after the response with the status `101` has
+    /// actually been sent. If the final response has another status, for example
+    /// because a modifier replaced it, the function is dropped without being called.
+    pub fn finish<F, R>(self, response: &mut Builder, on_upgrade: F) -> Result<Response<()>, Error>
+    where
+        F: FnOnce(UpgradedIo) -> R + Send + 'static,
+        R: IntoFuture<Item = (), Error = ()>,
+        R::Future: Send + 'static,
+    {
+        let mut response = response
+            .body(())
+            .map_err(crate::error::internal_server_error)?;
+        *response.status_mut() = StatusCode::SWITCHING_PROTOCOLS;
+        if !response.headers().contains_key(CONNECTION) {
+            response
+                .headers_mut()
+                .insert(CONNECTION, HeaderValue::from_static("upgrade"));
+        }
+        if !response.headers().contains_key(UPGRADE) {
+            let protocol = HeaderValue::from_str(&self.protocols[0])
+                .map_err(crate::error::internal_server_error)?;
+            response.headers_mut().insert(UPGRADE, protocol);
+        }
+
+        let task = self.body.on_upgrade().then(move |result| match result {
+            Ok(io) => Either::A(on_upgrade(io).into_future()),
+            Err(err) => {
+                log::debug!("the connection was not upgraded: {}", err);
+                Either::B(futures01::future::ok(()))
+            }
+        });
+        tokio_executor::DefaultExecutor::current()
+            .spawn(Box::new(task))
+            .map_err(crate::error::internal_server_error)?;
+
+        Ok(response)
+    }
+}
+
+/// Creates a function that handles the upgraded connection as a line-based protocol.
+///
+/// The provided function is called with each line received from the peer, without
+/// the line terminator. If it returns `Some(reply)`, the reply is sent to the peer
+/// followed by `"\n"`. If it returns `None`, the connection is closed.
+pub fn lines<F, R>(
+    f: F,
+) -> impl FnOnce(UpgradedIo) -> Box<dyn Future<Item = (), Error = ()> + Send + 'static> + Send + 'static
+where
+    F: FnMut(String) -> R + Send + 'static,
+    R: IntoFuture<Item = Option<String>, Error = ()> + 'static,
+    R::Future: Send + 'static,
+{
+    move |io| {
+        let (sink, stream) = Framed::new(io, LinesCodec::new()).split();
+        Box::new(
+            stream
+                .map_err(|err| log::debug!("failed to receive a line: {}", err))
+                .and_then(f)
+                .take_while(|reply| Ok(reply.is_some()))
+                .filter_map(|reply| reply)
+                .forward(sink.sink_map_err(|err| log::debug!("failed to send a line: {}", err)))
+                .map(|_| ()),
+        )
+    }
+}
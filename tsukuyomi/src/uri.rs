@@ -13,6 +13,7 @@ use {
 enum UriKind {
     Root,
     Segments(String, Option<CaptureNames>),
+    Asterisk,
 }
 
 /// A type representing the URI of a route.
@@ -42,6 +43,7 @@ impl PartialEq for Uri {
         match (&self.0, &other.0) {
             (&UriKind::Root, &UriKind::Root) => true,
             (&UriKind::Segments(ref s, ..), &UriKind::Segments(ref o, ..)) if s == o => true,
+            (&UriKind::Asterisk, &UriKind::Asterisk) => true,
             _ => false,
         }
     }
@@ -113,6 +115,11 @@ impl Uri {
         Uri(UriKind::Root)
     }
 
+    /// Returns the URI of the asterisk-form request target, `*`.
+    pub(crate) fn asterisk() -> Self {
+        Uri(UriKind::Asterisk)
+    }
+
     pub fn parse(mut s: &str) -> Result<Self, Error> {
         if !s.is_ascii() {
             failure::bail!("The URI is not ASCII");
@@ -179,6 +186,7 @@ impl Uri {
         match self.0 {
             UriKind::Root => "/",
             UriKind::Segments(ref s, ..) => s.as_str(),
+            UriKind::Asterisk => "*",
         }
     }
 
@@ -190,6 +198,9 @@ impl Uri {
     }
 
     pub fn join(&self, other: impl AsRef<Self>) -> Result<Self, Error> {
+        if self.0 == UriKind::Asterisk || other.as_ref().0 == UriKind::Asterisk {
+            failure::bail!("the asterisk URI cannot be joined");
+        }
         match self.0.clone() {
             UriKind::Root => Ok(other.as_ref().clone()),
             UriKind::Segments(mut segment, mut names) => match other.as_ref().0 {
@@ -211,7 +222,9 @@ impl Uri {
                     }
                     Ok(Self::segments(segment, names))
                 }
+                UriKind::Asterisk => unreachable!(),
             },
+            UriKind::Asterisk => unreachable!(),
         }
     }
 }
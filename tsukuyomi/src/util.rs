@@ -75,8 +75,8 @@ impl<L, R> Chain<L, R> {
 macro_rules! chain {
     ($e:expr) => ( $e );
     ($e:expr,) => ( $e );
-    ($h:expr, $($t:expr),+) => ( $crate::util::Chain::new($h, chain!($($t),+)) );
-    ($h:expr, $($t:expr,)+) => ( chain!($h, $($t),+) );
+    ($h:expr, $($t:expr),+) => ( $crate::util::Chain::new($h, $crate::chain!($($t),+)) );
+    ($h:expr, $($t:expr,)+) => ( $crate::chain!($h, $($t),+) );
 }
 
 #[derive(Debug, Copy, Clone, PartialEq)]
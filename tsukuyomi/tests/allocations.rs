@@ -0,0 +1,44 @@
+//! The audit of the number of allocations per request.
+//!
+//! This test is placed in its own binary since it replaces the global allocator,
+//! and all cases run in a single test function so that the counts are not
+//! disturbed by the other threads of the test harness.
+
+#[path = "../benches/support/mod.rs"]
+mod support;
+
+#[global_allocator]
+static ALLOCATOR: support::CountingAlloc = support::CountingAlloc;
+
+#[test]
+fn allocations_per_request() {
+    let app = support::app();
+    let mut service = support::service(&app);
+
+    for &(name, request, limit) in &[
+        ("hello_world", support::hello as fn() -> _, Some(5)),
+        ("two_params", support::params, None),
+        ("json_echo", support::echo, None),
+        ("not_found", support::not_found, None),
+        ("login", support::login, Some(7)),
+    ] {
+        // warm up the lazily initialized values, such as the notifier of the current thread.
+        drop(support::call(&mut service, request()));
+
+        let request = request();
+        let (response, allocations) =
+            support::count_allocations(|| support::call(&mut service, request));
+        drop(response);
+        println!("{}: {} allocations", name, allocations);
+
+        if let Some(limit) = limit {
+            assert!(
+                allocations <= limit,
+                "{}: too many allocations per request ({} > {})",
+                name,
+                allocations,
+                limit
+            );
+        }
+    }
+}
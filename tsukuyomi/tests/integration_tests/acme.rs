@@ -0,0 +1,46 @@
+use {
+    http::{header::CONTENT_TYPE, Request},
+    tsukuyomi::{
+        acme::{ChallengeStore, Http01Challenges},
+        config::prelude::*,
+        App,
+    },
+    tsukuyomi_server::test::ResponseExt,
+};
+
+const TOKEN: &str = "evaGxfADs6pSRb2LAv9IZf17Dt3juxGJ-PCt92wr-oA";
+const KEY_AUTHORIZATION: &str =
+    "evaGxfADs6pSRb2LAv9IZf17Dt3juxGJ-PCt92wr-oA.nP1qzpXGymHBrUEepNY9HCsQk7K8KhOypzEt62jcerQ";
+
+#[test]
+fn serves_registered_challenges() -> tsukuyomi_server::Result<()> {
+    let store = ChallengeStore::default();
+    let app = App::create(chain![
+        Http01Challenges::new(store.clone()),
+        path!("/").to(endpoint::get().reply("index")),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let path = format!("/.well-known/acme-challenge/{}", TOKEN);
+    let response = server.perform(path.as_str())?;
+    assert_eq!(response.status(), 404);
+
+    store.insert(TOKEN, KEY_AUTHORIZATION);
+
+    let response = server.perform(path.as_str())?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.header(CONTENT_TYPE)?, "text/plain");
+    assert_eq!(response.body().to_utf8()?, KEY_AUTHORIZATION);
+
+    let response = server.perform("/.well-known/acme-challenge/unknown")?;
+    assert_eq!(response.status(), 404);
+
+    let response = server.perform(Request::post(path.as_str()))?;
+    assert_eq!(response.status(), 405);
+
+    assert_eq!(store.remove(TOKEN), Some(KEY_AUTHORIZATION.to_owned()));
+    let response = server.perform(path.as_str())?;
+    assert_eq!(response.status(), 404);
+
+    Ok(())
+}
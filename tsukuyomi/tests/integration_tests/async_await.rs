@@ -0,0 +1,65 @@
+use {
+    http::Request,
+    std::time::{Duration, Instant},
+    tsukuyomi::{config::prelude::*, extractor, future::compat01_as_03, App},
+};
+
+async fn sleep(duration: Duration) -> tsukuyomi::Result<()> {
+    compat01_as_03(tokio_timer::Delay::new(Instant::now() + duration))
+        .await
+        .map_err(tsukuyomi::error::internal_server_error)
+}
+
+async fn greet(name: String) -> tsukuyomi::Result<String> {
+    sleep(Duration::from_millis(10)).await?;
+    Ok(format!("Hello, {}.", name))
+}
+
+#[test]
+fn async_fn_handler() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/") //
+            .to(endpoint::get()
+                .extract(extractor::async03_fn(|input| {
+                    let name = input
+                        .request
+                        .headers()
+                        .get("x-name")
+                        .and_then(|value| value.to_str().ok())
+                        .map(ToOwned::to_owned);
+                    async move {
+                        sleep(Duration::from_millis(10)).await?;
+                        name.map(|name| (name,))
+                            .ok_or_else(|| tsukuyomi::error::bad_request("missing name"))
+                    }
+                }))
+                .call_async03(greet)),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(Request::get("/").header("x-name", "Alice"))?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "Hello, Alice.");
+
+    let response = server.perform("/")?;
+    assert_eq!(response.status(), 400);
+
+    Ok(())
+}
+
+#[test]
+fn async_fn_without_extractors() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/:id") //
+            .to(endpoint::call_async03(|id: u32| async move {
+                sleep(Duration::from_millis(1)).await?;
+                Ok::<_, tsukuyomi::Error>(format!("{}", id * 2))
+            })),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/21")?;
+    assert_eq!(response.body().to_utf8()?, "42");
+
+    Ok(())
+}
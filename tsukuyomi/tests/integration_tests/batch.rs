@@ -0,0 +1,110 @@
+use {
+    http::{header::CONTENT_TYPE, Request},
+    serde_json::{json, Value},
+    tsukuyomi::{
+        batch::{self, Limits},
+        config::prelude::*,
+        extractor, App,
+    },
+};
+
+fn app(limits: Limits) -> tsukuyomi::app::Result<App> {
+    App::create(chain![
+        path!("/users/:id") //
+            .to(endpoint::get().call(|id: u32| format!("user {}", id))),
+        path!("/echo") //
+            .to(endpoint::post()
+                .extract(extractor::body::plain())
+                .call(|body: String| body)),
+        path!("/large") //
+            .to(endpoint::get().reply("x".repeat(100))),
+        path!("/batch") //
+            .to(batch::endpoint(limits)),
+    ])
+}
+
+fn perform(
+    server: &mut tsukuyomi_server::test::Server<App>,
+    sub_requests: Value,
+) -> tsukuyomi_server::Result<Value> {
+    let response = server.perform(
+        Request::post("/batch")
+            .header(CONTENT_TYPE, "application/json")
+            .body(sub_requests.to_string()),
+    )?;
+    assert_eq!(response.status(), 200);
+    Ok(serde_json::from_str(&response.body().to_utf8()?)?)
+}
+
+#[test]
+fn batch_of_sub_requests() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app(Limits::default().concurrency(2))?)?;
+
+    let responses = perform(
+        &mut server,
+        json!([
+            { "path": "/users/42" },
+            { "method": "POST", "path": "/echo", "body": "hello" },
+            { "path": "/no-such-path" },
+        ]),
+    )?;
+    let responses = responses.as_array().unwrap();
+    assert_eq!(responses.len(), 3);
+
+    assert_eq!(responses[0]["status"], 200);
+    assert_eq!(responses[0]["body"], "user 42");
+    assert_eq!(
+        responses[0]["headers"]["content-type"],
+        "text/plain; charset=utf-8"
+    );
+    assert_eq!(responses[1]["status"], 200);
+    assert_eq!(responses[1]["body"], "hello");
+    assert_eq!(responses[2]["status"], 404);
+
+    Ok(())
+}
+
+#[test]
+fn nested_batch_is_rejected() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app(Limits::default())?)?;
+
+    let responses = perform(
+        &mut server,
+        json!([
+            { "method": "POST", "path": "/batch", "body": [{ "path": "/users/1" }] },
+            { "path": "/users/1" },
+        ]),
+    )?;
+    assert_eq!(responses[0]["status"], 400);
+    assert_eq!(responses[1]["status"], 200);
+
+    Ok(())
+}
+
+#[test]
+fn sub_response_body_is_truncated() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app(Limits::default().max_body_size(10))?)?;
+
+    let responses = perform(
+        &mut server,
+        json!([{ "path": "/large" }, { "path": "/users/1" }]),
+    )?;
+    assert_eq!(responses[0]["body"], "x".repeat(10));
+    assert_eq!(responses[0]["truncated"], true);
+    assert_eq!(responses[1]["body"], "user 1");
+    assert_eq!(responses[1]["truncated"], false);
+
+    Ok(())
+}
+
+#[test]
+fn too_many_sub_requests() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app(Limits::default().max_requests(1))?)?;
+
+    let response = server.perform(
+        Request::post("/batch").body(json!([{ "path": "/" }, { "path": "/" }]).to_string()),
+    )?;
+    assert_eq!(response.status(), 413);
+
+    Ok(())
+}
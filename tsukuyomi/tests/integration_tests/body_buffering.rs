@@ -0,0 +1,105 @@
+use {
+    http::Request,
+    std::{fs, path::PathBuf},
+    tsukuyomi::{
+        app::BodyBuffering, config::prelude::*, extractor, input::body::BufferedBody, App,
+    },
+    tsukuyomi_server::test::ResponseExt,
+};
+
+fn spill_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("tsukuyomi-test-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn json_items(n: usize) -> String {
+    let items: Vec<_> = (0..n).map(|i| format!("\"item-{}\"", i)).collect();
+    format!("{{\"items\":[{}]}}", items.join(","))
+}
+
+#[test]
+fn spill_large_bodies() -> tsukuyomi_server::Result<()> {
+    let dir = spill_dir("spill");
+    let app = App::create(chain![
+        BodyBuffering::SpillToDisk {
+            threshold: 64,
+            dir: dir.clone(),
+        },
+        path!("/json") //
+            .to(endpoint::post().extract(extractor::body::json()).call(
+                |value: serde_json::Value| value["items"]
+                    .as_array()
+                    .map_or(0, Vec::len)
+                    .to_string()
+            )),
+        path!("/form") //
+            .to(endpoint::post()
+                .extract(extractor::body::urlencoded())
+                .call(|form: Vec<(String, String)>| form.len().to_string())),
+        path!("/raw") //
+            .to(endpoint::post().extract(extractor::body::buffered()).call(
+                |mut body: BufferedBody| {
+                    let spilled = body.is_spilled();
+                    let mut data = String::new();
+                    std::io::Read::read_to_string(&mut body.reader().unwrap(), &mut data).unwrap();
+                    format!("{} {}", spilled, data.len())
+                }
+            )),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    // the small body is buffered in memory.
+    let spills = BodyBuffering::spill_count();
+    let response = server.perform(
+        Request::post("/json")
+            .header("content-type", "application/json")
+            .body(json_items(2)),
+    )?;
+    assert_eq!(response.body().to_utf8()?, "2");
+    assert_eq!(BodyBuffering::spill_count(), spills);
+
+    let response = server.perform(
+        Request::post("/json")
+            .header("content-type", "application/json")
+            .body(json_items(500)),
+    )?;
+    assert_eq!(response.body().to_utf8()?, "500");
+    assert_eq!(BodyBuffering::spill_count(), spills + 1);
+
+    let form: Vec<_> = (0..100).map(|i| format!("key{}=value{}", i, i)).collect();
+    let response = server.perform(
+        Request::post("/form")
+            .header("content-type", "application/x-www-form-urlencoded")
+            .body(form.join("&")),
+    )?;
+    assert_eq!(response.body().to_utf8()?, "100");
+    assert_eq!(BodyBuffering::spill_count(), spills + 2);
+
+    let response = server.perform(Request::post("/raw").body("x".repeat(1000)))?;
+    assert_eq!(response.body().to_utf8()?, "true 1000");
+    let response = server.perform(Request::post("/raw").body("x".repeat(10)))?;
+    assert_eq!(response.body().to_utf8()?, "false 10");
+
+    // no temporary file is left after the requests.
+    assert_eq!(fs::read_dir(&dir)?.count(), 0);
+    fs::remove_dir(&dir)?;
+
+    Ok(())
+}
+
+#[test]
+fn memory_by_default() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/raw") //
+            .to(endpoint::post()
+                .extract(extractor::body::buffered())
+                .call(|body: BufferedBody| format!("{} {}", body.is_spilled(), body.len()))),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(Request::post("/raw").body("x".repeat(100_000)))?;
+    assert_eq!(response.body().to_utf8()?, "false 100000");
+
+    Ok(())
+}
@@ -0,0 +1,115 @@
+use {
+    http::{Request, StatusCode},
+    std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    tsukuyomi::{
+        app::RequestSizeLimit, config::endpoint::Builder, config::prelude::*, extractor, App,
+    },
+    tsukuyomi_server::test::ResponseExt,
+};
+
+fn post(uri: &str, body: &'static str) -> http::request::Builder {
+    let mut request = Request::post(uri);
+    request.header("content-length", body.len().to_string());
+    request
+}
+
+#[test]
+fn body_required() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/comments") //
+            .to(endpoint::post()
+                .body_required()
+                .extract(extractor::body::plain())
+                .call(|body: String| body)),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(Request::post("/comments"))?;
+    assert_eq!(response.status(), StatusCode::LENGTH_REQUIRED);
+    assert!(response.body().to_utf8()?.contains("Content-Length"));
+
+    let response = server.perform(post("/comments", "").body(""))?;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = server.perform(
+        post("/comments", "hello")
+            .header("content-type", "text/plain; charset=utf-8")
+            .body("hello"),
+    )?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.body().to_utf8()?, "hello");
+
+    Ok(())
+}
+
+#[test]
+fn body_forbidden() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/search") //
+            .to(endpoint::get().body_forbidden().reply("results")),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(Request::get("/search").header("content-length", "7"))?;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert!(response.body().to_utf8()?.contains("not allowed on GET"));
+
+    let response =
+        server.perform(Request::get("/search").header("transfer-encoding", "chunked"))?;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = server.perform(Request::get("/search").header("content-length", "0"))?;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = server.perform("/search")?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.body().to_utf8()?, "results");
+
+    Ok(())
+}
+
+#[test]
+fn max_declared_length_overrides_scope() -> tsukuyomi_server::Result<()> {
+    let read = Arc::new(AtomicUsize::new(0));
+    let upload = |endpoint: Builder| {
+        let read = read.clone();
+        endpoint
+            .extract(extractor::body::read_all())
+            .call(move |body: bytes::Bytes| {
+                read.fetch_add(1, Ordering::SeqCst);
+                format!("{} bytes", body.len())
+            })
+    };
+    let app = App::create(chain![
+        RequestSizeLimit::new(10),
+        path!("/default").to(upload(endpoint::post())),
+        path!("/large").to(upload(endpoint::post().max_declared_length(100))),
+        path!("/small").to(upload(endpoint::post().max_declared_length(4))),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    const LONG: &str = "0123456789abcdef";
+    const SHORT: &str = "012345";
+
+    let response = server.perform(post("/default", LONG).body(LONG))?;
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    let response = server.perform(post("/default", SHORT).body(SHORT))?;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // a larger limit than the scope.
+    let response = server.perform(post("/large", LONG).body(LONG))?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.body().to_utf8()?, "16 bytes");
+
+    // a smaller limit than the scope.
+    let response = server.perform(post("/small", SHORT).body(SHORT))?;
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    // the rejected bodies have never been read.
+    assert_eq!(read.load(Ordering::SeqCst), 2);
+
+    Ok(())
+}
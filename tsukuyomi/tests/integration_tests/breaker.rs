@@ -0,0 +1,179 @@
+use {
+    http::Request,
+    std::{
+        collections::VecDeque,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        time::Duration,
+    },
+    tsukuyomi::{
+        breaker::{CircuitBreaker, State},
+        config::prelude::*,
+        extractor::{self, Extractor, ExtractorExt},
+        future::TryFuture,
+        rt::MockClock,
+        App,
+    },
+};
+
+/// A mock of the downstream service, which fails or succeeds as scripted.
+#[derive(Clone, Default)]
+struct Downstream {
+    script: Arc<Mutex<VecDeque<bool>>>,
+    calls: Arc<AtomicUsize>,
+}
+
+impl Downstream {
+    fn script(&self, results: &[bool]) {
+        self.script.lock().unwrap().extend(results);
+    }
+
+    fn calls(&self) -> usize {
+        self.calls.load(Ordering::SeqCst)
+    }
+
+    fn extractor(
+        &self,
+    ) -> impl Extractor<
+        Output = (String,),
+        Error = tsukuyomi::Error,
+        Extract = impl TryFuture<Ok = (String,), Error = tsukuyomi::Error> + Send + 'static,
+    > {
+        let downstream = self.clone();
+        extractor::ready(move |_| {
+            downstream.calls.fetch_add(1, Ordering::SeqCst);
+            match downstream.script.lock().unwrap().pop_front() {
+                Some(true) => Ok(("alice".to_owned(),)),
+                _ => Err(tsukuyomi::error::custom(
+                    http::StatusCode::BAD_GATEWAY,
+                    "the downstream is down",
+                )),
+            }
+        })
+    }
+}
+
+fn breaker(clock: &MockClock) -> CircuitBreaker {
+    CircuitBreaker::builder()
+        .failure_threshold(3)
+        .window(Duration::from_secs(10))
+        .cooldown(Duration::from_secs(30))
+        .clock(clock.clone())
+        .build()
+}
+
+#[test]
+fn opens_after_failures_and_closes_after_probe() -> tsukuyomi_server::Result<()> {
+    let clock = MockClock::new();
+    let breaker = breaker(&clock);
+    let downstream = Downstream::default();
+    downstream.script(&[false, false, false]);
+
+    let app = App::create(
+        path!("/") //
+            .to(endpoint::get()
+                .extract(downstream.extractor().with_breaker(breaker.clone()))
+                .call(|user: String| user)),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    for _ in 0..3 {
+        let response = server.perform(Request::get("/"))?;
+        assert_eq!(response.status(), 502);
+    }
+    assert_eq!(downstream.calls(), 3);
+    assert_eq!(breaker.state(), State::Open);
+
+    // fail fast without calling the downstream.
+    let response = server.perform(Request::get("/"))?;
+    assert_eq!(response.status(), 503);
+    assert_eq!(downstream.calls(), 3);
+
+    clock.advance(Duration::from_secs(30));
+    assert_eq!(breaker.state(), State::HalfOpen);
+
+    downstream.script(&[true]);
+    let response = server.perform(Request::get("/"))?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "alice");
+    assert_eq!(downstream.calls(), 4);
+    assert_eq!(breaker.state(), State::Closed);
+
+    let metrics = breaker.metrics();
+    assert_eq!(metrics.failures, 3);
+    assert_eq!(metrics.successes, 1);
+    assert_eq!(metrics.rejected, 1);
+    assert_eq!(metrics.opened, 1);
+
+    Ok(())
+}
+
+#[test]
+fn failures_outside_window_are_forgotten() -> tsukuyomi_server::Result<()> {
+    let clock = MockClock::new();
+    let breaker = breaker(&clock);
+
+    for _ in 0..2 {
+        breaker.try_acquire().unwrap().fail();
+    }
+    clock.advance(Duration::from_secs(10));
+    breaker.try_acquire().unwrap().fail();
+    assert_eq!(breaker.state(), State::Closed);
+
+    Ok(())
+}
+
+#[test]
+fn failed_probe_reopens() -> tsukuyomi_server::Result<()> {
+    let clock = MockClock::new();
+    let breaker = breaker(&clock);
+
+    for _ in 0..3 {
+        breaker.try_acquire().unwrap().fail();
+    }
+    assert!(breaker.try_acquire().is_none());
+
+    clock.advance(Duration::from_secs(30));
+    let probe = breaker.try_acquire().unwrap();
+    assert!(probe.is_probe());
+    assert!(breaker.try_acquire().is_none());
+    probe.fail();
+    assert_eq!(breaker.state(), State::Open);
+
+    Ok(())
+}
+
+#[test]
+fn fallback_value() -> tsukuyomi_server::Result<()> {
+    let clock = MockClock::new();
+    let breaker = breaker(&clock);
+    let downstream = Downstream::default();
+    downstream.script(&[false, false, false]);
+
+    let app = App::create(
+        path!("/") //
+            .to(endpoint::get()
+                .extract(
+                    downstream
+                        .extractor()
+                        .with_breaker(breaker.clone())
+                        .fallback_value("anonymous".into()),
+                )
+                .call(|user: String| user)),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    for _ in 0..3 {
+        let response = server.perform(Request::get("/"))?;
+        assert_eq!(response.status(), 502);
+    }
+
+    let response = server.perform(Request::get("/"))?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "anonymous");
+    assert_eq!(downstream.calls(), 3);
+
+    Ok(())
+}
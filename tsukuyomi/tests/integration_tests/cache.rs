@@ -0,0 +1,212 @@
+use {
+    std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Barrier,
+        },
+        thread,
+        time::Duration,
+    },
+    tsukuyomi::{
+        cache::{CacheStats, MemoryCache},
+        config::prelude::*,
+        extractor,
+        rt::MockClock,
+        vendor::futures::{sync::oneshot, Future},
+        App,
+    },
+};
+
+#[test]
+fn ttl_expiration() {
+    let clock = MockClock::new();
+    let cache: MemoryCache<&str, u32> = MemoryCache::builder()
+        .ttl(Duration::from_secs(10))
+        .clock(clock.clone())
+        .build();
+
+    cache.insert("a", 1);
+    cache.insert_with_ttl("b", 2, Duration::from_secs(30));
+    assert_eq!(cache.get(&"a"), Some(1));
+    assert_eq!(cache.len(), 2);
+
+    clock.advance(Duration::from_secs(10));
+    assert_eq!(cache.get(&"a"), None);
+    assert_eq!(cache.get(&"b"), Some(2));
+    assert_eq!(cache.len(), 1);
+
+    clock.advance(Duration::from_secs(20));
+    assert_eq!(cache.get(&"b"), None);
+    assert!(cache.is_empty());
+
+    assert_eq!(
+        cache.stats(),
+        CacheStats {
+            hits: 2,
+            misses: 2,
+            loads: 0,
+            evictions: 0,
+        }
+    );
+}
+
+#[test]
+fn retain_entries() {
+    let cache: MemoryCache<u32, &str> = MemoryCache::builder().build();
+    cache.insert(1, "one");
+    cache.insert(2, "two");
+    cache.insert(3, "three");
+
+    cache.retain(|&key, _| key != 2);
+    assert_eq!(cache.len(), 2);
+    assert_eq!(cache.get(&1), Some("one"));
+    assert_eq!(cache.get(&2), None);
+}
+
+#[test]
+fn lru_eviction() {
+    let cache: MemoryCache<u32, &str> = MemoryCache::builder().capacity(2).shards(1).build();
+
+    cache.insert(1, "one");
+    cache.insert(2, "two");
+    assert_eq!(cache.get(&1), Some("one"));
+
+    // The entry `2` is the least recently used one.
+    cache.insert(3, "three");
+    assert_eq!(cache.get(&2), None);
+    assert_eq!(cache.get(&1), Some("one"));
+    assert_eq!(cache.get(&3), Some("three"));
+
+    // Replacing an existing entry does not evict others.
+    cache.insert(3, "drei");
+    assert_eq!(cache.len(), 2);
+    assert_eq!(cache.stats().evictions, 1);
+
+    assert_eq!(cache.remove(&1), Some("one"));
+    cache.clear();
+    assert!(cache.is_empty());
+}
+
+#[test]
+fn single_flight() {
+    let cache: MemoryCache<u32, String> = MemoryCache::builder().build();
+    let calls = Arc::new(AtomicUsize::new(0));
+
+    let (tx, rx) = oneshot::channel();
+    let first = cache.get_or_insert_with(1, {
+        let calls = calls.clone();
+        move || {
+            calls.fetch_add(1, Ordering::SeqCst);
+            rx
+        }
+    });
+
+    let barrier = Arc::new(Barrier::new(5));
+    let waiters: Vec<_> = (0..4)
+        .map(|_| {
+            let cache = cache.clone();
+            let calls = calls.clone();
+            let barrier = barrier.clone();
+            thread::spawn(move || {
+                let future = cache.get_or_insert_with(1, move || {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    Ok::<_, oneshot::Canceled>(String::from("duplicated"))
+                });
+                barrier.wait();
+                future.wait()
+            })
+        })
+        .collect();
+
+    barrier.wait();
+    tx.send(String::from("loaded")).unwrap();
+    assert_eq!(first.wait(), Ok(String::from("loaded")));
+    for waiter in waiters {
+        assert_eq!(waiter.join().unwrap(), Ok(String::from("loaded")));
+    }
+
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+    assert_eq!(cache.stats().loads, 1);
+    assert_eq!(cache.get(&1), Some(String::from("loaded")));
+}
+
+#[test]
+fn load_error_is_not_cached() {
+    let cache: MemoryCache<u32, u32> = MemoryCache::builder().build();
+
+    let result = cache
+        .get_or_insert_with(1, || Err::<u32, _>("failed"))
+        .wait();
+    assert_eq!(result, Err("failed"));
+    assert_eq!(cache.get(&1), None);
+
+    let result = cache.get_or_insert_with(1, || Ok::<_, &str>(42)).wait();
+    assert_eq!(result, Ok(42));
+    assert_eq!(cache.stats().loads, 2);
+}
+
+#[test]
+fn nested_load_in_same_shard() {
+    let cache: MemoryCache<u32, u32> = MemoryCache::builder().shards(1).build();
+
+    // the loader accesses the same shard synchronously.
+    let result = cache
+        .get_or_insert_with(1, || {
+            cache.insert(3, 30);
+            cache
+                .get_or_insert_with(2, || Ok::<_, ()>(20))
+                .map(|n| n + 1)
+        })
+        .wait();
+    assert_eq!(result, Ok(21));
+    assert_eq!(cache.get(&1), Some(21));
+    assert_eq!(cache.get(&2), Some(20));
+    assert_eq!(cache.get(&3), Some(30));
+}
+
+#[test]
+fn panicking_loader_is_not_shared() {
+    let cache: MemoryCache<u32, u32> = MemoryCache::builder().build();
+
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        cache.get_or_insert_with(1, || -> Result<u32, ()> { panic!("failed to load") })
+    }));
+    assert!(result.is_err());
+
+    let result = cache.get_or_insert_with(1, || Ok::<_, ()>(42)).wait();
+    assert_eq!(result, Ok(42));
+}
+
+#[test]
+#[should_panic(expected = "the capacity must be greater than zero")]
+fn zero_capacity() {
+    let _ = MemoryCache::builder().capacity(0);
+}
+
+#[test]
+fn shared_as_state() -> tsukuyomi_server::Result<()> {
+    let cache: MemoryCache<u32, String> = MemoryCache::builder().build();
+    cache.insert(1, String::from("cached"));
+
+    let app = App::create(chain![
+        tsukuyomi::config::state(cache.clone()),
+        path!("/:id") //
+            .to(endpoint::get()
+                .extract(extractor::state::<MemoryCache<u32, String>>())
+                .call(|id: u32, cache: MemoryCache<u32, String>| {
+                    cache.get(&id).unwrap_or_else(|| String::from("missing"))
+                })),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/1")?;
+    assert_eq!(response.body().to_utf8()?, "cached");
+
+    let response = server.perform("/2")?;
+    assert_eq!(response.body().to_utf8()?, "missing");
+
+    assert_eq!(cache.stats().hits, 1);
+    assert_eq!(cache.stats().misses, 1);
+
+    Ok(())
+}
@@ -0,0 +1,141 @@
+use {
+    http::{
+        header::{LOCATION, STRICT_TRANSPORT_SECURITY},
+        Request, StatusCode,
+    },
+    std::{net::SocketAddr, time::Duration},
+    tsukuyomi::{
+        app::CanonicalHost, //
+        config::prelude::*,
+        extractor::forwarded::TrustedProxies,
+        input::ConnectionInfo,
+        App,
+    },
+};
+
+fn app() -> tsukuyomi::app::Result<App> {
+    let app = App::create(chain![
+        TrustedProxies::new().trust("10.0.0.1".parse().unwrap()),
+        path!("/") //
+            .to(endpoint::get().reply("index")),
+        path!("/healthz") //
+            .to(endpoint::get().reply("ok")),
+    ])?;
+    Ok(app.canonical_host(
+        CanonicalHost::new("https://www.example.com")
+            .exempt_paths(vec!["/healthz"])
+            .hsts(Duration::from_secs(31_536_000))
+            .hsts_preload(true),
+    ))
+}
+
+fn request(uri: &str, host: &str) -> http::request::Builder {
+    let mut request = Request::get(uri);
+    request.header("host", host);
+    request
+}
+
+fn via_proxy(uri: &str, host: &str, proto: &str) -> http::request::Builder {
+    let mut request = request(uri, host);
+    request
+        .header("x-forwarded-proto", proto)
+        .extension(ConnectionInfo::new(
+            "10.0.0.1:4000".parse::<SocketAddr>().unwrap(),
+        ));
+    request
+}
+
+#[test]
+fn redirect_host_mismatch() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+
+    let response = server.perform(request("/", "example.com"))?;
+    assert_eq!(response.status(), StatusCode::MOVED_PERMANENTLY);
+    assert_eq!(response.headers()[LOCATION], "https://www.example.com/");
+
+    // unknown paths are also redirected, with the query preserved.
+    let response = server.perform(request("/missing/path?q=1&r=2", "example.com"))?;
+    assert_eq!(response.status(), StatusCode::MOVED_PERMANENTLY);
+    assert_eq!(
+        response.headers()[LOCATION],
+        "https://www.example.com/missing/path?q=1&r=2"
+    );
+    assert!(!response.headers().contains_key(STRICT_TRANSPORT_SECURITY));
+
+    // the canonical host is compared case-insensitively.
+    let response = server.perform(request("/", "WWW.Example.COM"))?;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    Ok(())
+}
+
+#[test]
+fn redirect_to_https_behind_proxy() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+
+    let response = server.perform(via_proxy("/?page=2", "www.example.com", "http"))?;
+    assert_eq!(response.status(), StatusCode::MOVED_PERMANENTLY);
+    assert_eq!(
+        response.headers()[LOCATION],
+        "https://www.example.com/?page=2"
+    );
+
+    let response = server.perform(via_proxy("/?page=2", "www.example.com", "https"))?;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // the forwarding headers from an untrusted peer are ignored.
+    let mut untrusted = request("/", "www.example.com");
+    untrusted
+        .header("x-forwarded-proto", "http")
+        .extension(ConnectionInfo::new(
+            "192.0.2.1:4000".parse::<SocketAddr>().unwrap(),
+        ));
+    let response = server.perform(untrusted)?;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    Ok(())
+}
+
+#[test]
+fn exempt_paths() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+
+    let response = server.perform(request("/healthz", "10.0.0.2:8080"))?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(!response.headers().contains_key(STRICT_TRANSPORT_SECURITY));
+
+    let response = server.perform(request("/healthz/", "10.0.0.2:8080"))?;
+    assert_eq!(response.status(), StatusCode::MOVED_PERMANENTLY);
+
+    Ok(())
+}
+
+#[test]
+fn hsts_only_on_canonical_https() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+
+    let response = server.perform(via_proxy("/", "www.example.com", "https"))?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[STRICT_TRANSPORT_SECURITY],
+        "max-age=31536000; includeSubDomains; preload"
+    );
+
+    let response = server.perform(via_proxy("/", "www.example.com", "http"))?;
+    assert!(!response.headers().contains_key(STRICT_TRANSPORT_SECURITY));
+
+    let response = server.perform(request("/", "example.com"))?;
+    assert!(!response.headers().contains_key(STRICT_TRANSPORT_SECURITY));
+
+    let app = App::create(
+        path!("/") //
+            .to(endpoint::get().reply("index")),
+    )?
+    .canonical_host(CanonicalHost::new("http://localhost:8080").hsts(Duration::from_secs(60)));
+    let mut server = tsukuyomi_server::test::server(app)?;
+    let response = server.perform(request("/", "localhost:8080"))?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(!response.headers().contains_key(STRICT_TRANSPORT_SECURITY));
+
+    Ok(())
+}
@@ -0,0 +1,243 @@
+use {
+    http::{header, Request},
+    std::io::Read,
+    tsukuyomi::{config::prelude::*, input::encoding::Encoding, modifiers, App},
+};
+
+const BODY: &str = "Lorem ipsum dolor sit amet, consectetur adipiscing elit. \
+                    Lorem ipsum dolor sit amet, consectetur adipiscing elit.";
+
+#[test]
+fn negotiate_by_qvalue() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/")
+            .to(endpoint::reply(BODY))
+            .modify(modifiers::compression()),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server
+        .perform(Request::get("/").header(header::ACCEPT_ENCODING, "deflate;q=0.5, gzip;q=1.0"))?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.headers().get(header::CONTENT_ENCODING).unwrap(),
+        "gzip"
+    );
+    assert_eq!(
+        response.headers().get(header::VARY).unwrap(),
+        "accept-encoding"
+    );
+    let mut decoded = String::new();
+    flate2::read::GzDecoder::new(&*response.body().to_bytes()).read_to_string(&mut decoded)?;
+    assert_eq!(decoded, BODY);
+
+    let response = server.perform(Request::get("/"))?;
+    assert!(!response.headers().contains_key(header::CONTENT_ENCODING));
+    assert_eq!(response.body().to_utf8()?, BODY);
+
+    let response = server
+        .perform(Request::get("/").header(header::ACCEPT_ENCODING, "gzip;q=0.5, identity"))?;
+    assert!(!response.headers().contains_key(header::CONTENT_ENCODING));
+    assert_eq!(response.body().to_utf8()?, BODY);
+
+    Ok(())
+}
+
+#[cfg(feature = "brotli")]
+#[test]
+fn negotiate_brotli() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/")
+            .to(endpoint::reply(BODY))
+            .modify(modifiers::compression().level(Encoding::Brotli, 9)),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server
+        .perform(Request::get("/").header(header::ACCEPT_ENCODING, "gzip;q=0.5, br;q=1.0"))?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.headers().get(header::CONTENT_ENCODING).unwrap(),
+        "br"
+    );
+    let mut decoded = String::new();
+    brotli::Decompressor::new(&*response.body().to_bytes(), 4096).read_to_string(&mut decoded)?;
+    assert_eq!(decoded, BODY);
+
+    Ok(())
+}
+
+#[test]
+fn not_acceptable() -> tsukuyomi_server::Result<()> {
+    let app = App::create(chain![
+        path!("/strict")
+            .to(endpoint::reply(BODY))
+            .modify(modifiers::compression()),
+        path!("/fallback")
+            .to(endpoint::reply(BODY))
+            .modify(modifiers::compression().fallback_identity(true)),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server
+        .perform(Request::get("/strict").header(header::ACCEPT_ENCODING, "identity;q=0, *;q=0"))?;
+    assert_eq!(response.status(), 406);
+
+    let response = server.perform(
+        Request::get("/fallback").header(header::ACCEPT_ENCODING, "identity;q=0, *;q=0"),
+    )?;
+    assert_eq!(response.status(), 200);
+    assert!(!response.headers().contains_key(header::CONTENT_ENCODING));
+    assert_eq!(response.body().to_utf8()?, BODY);
+
+    Ok(())
+}
+
+#[test]
+fn disabled_encoding() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/")
+            .to(endpoint::reply(BODY))
+            .modify(modifiers::compression().disable(Encoding::Gzip)),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response =
+        server.perform(Request::get("/").header(header::ACCEPT_ENCODING, "gzip, deflate"))?;
+    assert_eq!(
+        response.headers().get(header::CONTENT_ENCODING).unwrap(),
+        "deflate"
+    );
+    let mut decoded = String::new();
+    flate2::read::ZlibDecoder::new(&*response.body().to_bytes()).read_to_string(&mut decoded)?;
+    assert_eq!(decoded, BODY);
+
+    Ok(())
+}
+
+#[test]
+fn metrics_per_encoding() -> tsukuyomi_server::Result<()> {
+    let compression = modifiers::compression();
+    let app = App::create(
+        path!("/")
+            .to(endpoint::reply(BODY))
+            .modify(compression.clone()),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    for _ in 0..2 {
+        let response = server.perform(Request::get("/").header(header::ACCEPT_ENCODING, "gzip"))?;
+        assert_eq!(
+            response.headers().get(header::CONTENT_ENCODING).unwrap(),
+            "gzip"
+        );
+    }
+    let response = server.perform(Request::get("/"))?;
+    assert!(!response.headers().contains_key(header::CONTENT_ENCODING));
+
+    let metrics = compression.metrics();
+    let gzip = metrics.get("gzip").unwrap();
+    assert_eq!(gzip.responses, 2);
+    assert_eq!(gzip.uncompressed_bytes, 2 * BODY.len() as u64);
+    assert!(gzip.ratio().unwrap() < 1.0);
+    assert!(metrics.get("deflate").is_none());
+
+    Ok(())
+}
+
+#[cfg(feature = "compression-dictionary")]
+mod dictionary {
+    use {
+        super::*,
+        flate2::{Decompress, FlushDecompress},
+        tsukuyomi::modifiers::Dictionary,
+    };
+
+    const DICTIONARY: &[u8] = br#"{"id":,"name":"","email":"@example.com","roles":["admin","user"],"created_at":"2018-"}"#;
+    const JSON: &str = r#"{"id":42,"name":"alice","email":"alice@example.com","roles":["admin","user"],"created_at":"2018-12-01"}"#;
+
+    fn decode_with_dictionary(input: &[u8], dictionary: &[u8]) -> String {
+        let mut decompress = Decompress::new(true);
+        let mut output = Vec::with_capacity(JSON.len() * 2);
+        let err = decompress
+            .decompress_vec(input, &mut output, FlushDecompress::Finish)
+            .expect_err("the preset dictionary should be required");
+        assert!(err.needs_dictionary().is_some());
+        decompress.set_dictionary(dictionary).unwrap();
+        let consumed = decompress.total_in() as usize;
+        decompress
+            .decompress_vec(&input[consumed..], &mut output, FlushDecompress::Finish)
+            .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn negotiate_dictionary() -> tsukuyomi_server::Result<()> {
+        let compression =
+            modifiers::compression().dictionary(Dictionary::new("x-json-dict", DICTIONARY)?);
+        let app = App::create(
+            chain![
+                path!("/user").to(endpoint::call(|| {
+                    http::Response::builder()
+                        .header(header::CONTENT_TYPE, "application/json; charset=utf-8")
+                        .body(JSON)
+                        .unwrap()
+                })),
+                path!("/text").to(endpoint::reply(BODY)),
+            ]
+            .modify(compression.clone()),
+        )?;
+        let mut server = tsukuyomi_server::test::server(app)?;
+
+        let response = server
+            .perform(Request::get("/user").header(header::ACCEPT_ENCODING, "gzip, x-json-dict"))?;
+        assert_eq!(
+            response.headers().get(header::CONTENT_ENCODING).unwrap(),
+            "x-json-dict"
+        );
+        let body = response.body().to_bytes();
+        assert_eq!(decode_with_dictionary(&body, DICTIONARY), JSON);
+
+        let response =
+            server.perform(Request::get("/user").header(header::ACCEPT_ENCODING, "gzip"))?;
+        assert_eq!(
+            response.headers().get(header::CONTENT_ENCODING).unwrap(),
+            "gzip"
+        );
+        let mut decoded = String::new();
+        flate2::read::GzDecoder::new(&*response.body().to_bytes()).read_to_string(&mut decoded)?;
+        assert_eq!(decoded, JSON);
+
+        let response = server
+            .perform(Request::get("/text").header(header::ACCEPT_ENCODING, "gzip, x-json-dict"))?;
+        assert_eq!(
+            response.headers().get(header::CONTENT_ENCODING).unwrap(),
+            "gzip"
+        );
+
+        let metrics = compression.metrics();
+        let dictionary = metrics.get("x-json-dict").unwrap();
+        assert_eq!(dictionary.responses, 1);
+        assert_eq!(dictionary.uncompressed_bytes, JSON.len() as u64);
+        assert_eq!(dictionary.compressed_bytes, body.len() as u64);
+        assert_eq!(metrics.get("gzip").unwrap().responses, 2);
+        assert!(dictionary.ratio().unwrap() < metrics.get("gzip").unwrap().ratio().unwrap());
+
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_dictionary_fails_app_creation() {
+        let app = |token: &str, bytes: &'static [u8]| {
+            App::create(Dictionary::new(token, bytes).map(|dictionary| {
+                path!("/")
+                    .to(endpoint::reply(JSON))
+                    .modify(modifiers::compression().dictionary(dictionary))
+            }))
+        };
+        assert!(app("x-json-dict", DICTIONARY).is_ok());
+        assert!(app("x-json-dict", b"").is_err());
+        assert!(app("gzip", DICTIONARY).is_err());
+        assert!(app("x json", DICTIONARY).is_err());
+    }
+}
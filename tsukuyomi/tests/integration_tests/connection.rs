@@ -0,0 +1,74 @@
+use {
+    http::header::CONNECTION,
+    tsukuyomi::{config::prelude::*, output, App},
+    tsukuyomi_server::test::ResponseExt,
+};
+
+fn app() -> tsukuyomi::app::Result<App> {
+    App::create(chain![
+        path!("/") //
+            .to(endpoint::reply("keep")),
+        path!("/close") //
+            .to(endpoint::call(|| output::with_connection_close("bye"))),
+        path!("/fail") //
+            .to(endpoint::call(|| {
+                output::with_connection_close(Err::<&str, _>(tsukuyomi::error::bad_request(
+                    "invalid payload",
+                )))
+            })),
+    ])
+}
+
+#[test]
+fn route_marked_close() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+    let mut session = server.new_session()?;
+
+    let response = session.perform("/")?;
+    assert!(response.headers().get(CONNECTION).is_none());
+    assert!(session.is_reusable());
+
+    let response = session.perform("/close")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.header(CONNECTION)?, "close");
+    assert!(!session.is_reusable());
+
+    assert!(session.perform("/").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn close_survives_error_response() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+    let mut session = server.new_session()?;
+
+    let response = session.perform("/fail")?;
+    assert_eq!(response.status(), 400);
+    assert_eq!(response.header(CONNECTION)?, "close");
+    assert!(!session.is_reusable());
+
+    Ok(())
+}
+
+#[test]
+fn max_requests_per_connection() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app()?)?.max_requests_per_connection(2);
+
+    let mut session = server.new_session()?;
+    let response = session.perform("/")?;
+    assert!(response.headers().get(CONNECTION).is_none());
+    assert!(session.is_reusable());
+
+    let response = session.perform("/")?;
+    assert_eq!(response.header(CONNECTION)?, "close");
+    assert!(!session.is_reusable());
+    assert!(session.perform("/").is_err());
+
+    // the counter is per connection.
+    let mut session = server.new_session()?;
+    let _ = session.perform("/")?;
+    assert!(session.is_reusable());
+
+    Ok(())
+}
@@ -0,0 +1,172 @@
+use {
+    bytes::Bytes,
+    http::{Request, StatusCode},
+    mime::Mime,
+    tsukuyomi::{
+        config::prelude::*,
+        extractor,
+        modifiers::{self, ContentSniff, Detector, Mismatch},
+        App,
+    },
+};
+
+const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR";
+const JPEG: &[u8] = b"\xff\xd8\xff\xe0\x00\x10JFIF\x00";
+
+fn upload_app(sniff: ContentSniff) -> App {
+    App::create(
+        path!("/upload")
+            .to(endpoint::post()
+                .extract(extractor::body::read_all())
+                .call(|body: Bytes| format!("{} bytes", body.len())))
+            .modify(sniff),
+    )
+    .unwrap()
+}
+
+fn form_data(parts: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut body = vec![];
+    for (i, (content_type, content)) in parts.iter().enumerate() {
+        body.extend_from_slice(b"--boundary\r\n");
+        body.extend_from_slice(
+            format!(
+                "content-disposition: form-data; name=\"file{}\"\r\ncontent-type: {}\r\n\r\n",
+                i, content_type
+            )
+            .as_bytes(),
+        );
+        body.extend_from_slice(content);
+        body.extend_from_slice(b"\r\n");
+    }
+    body.extend_from_slice(b"--boundary--\r\n");
+    body
+}
+
+#[test]
+fn json_mismatch() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(upload_app(modifiers::content_sniff()))?;
+
+    let response = server.perform(
+        Request::post("/upload")
+            .header("content-type", "application/json")
+            .body(&b"\x00\x01\x02\xff"[..]),
+    )?;
+    assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    assert_eq!(
+        response.body().to_utf8()?,
+        "the content declared as application/json looks like binary data"
+    );
+
+    let response = server.perform(
+        Request::post("/upload")
+            .header("content-type", "application/vnd.api+json")
+            .body("not json"),
+    )?;
+    assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+    let response = server.perform(
+        Request::post("/upload")
+            .header("content-type", "application/json")
+            .body("\n  {\"id\": 1}"),
+    )?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.body().to_utf8()?, "12 bytes");
+
+    Ok(())
+}
+
+#[test]
+fn image_parts() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(upload_app(modifiers::content_sniff()))?;
+
+    let body = form_data(&[("image/png", PNG), ("text/plain", b"caption")]);
+    let response = server.perform(
+        Request::post("/upload")
+            .header("content-type", "multipart/form-data; boundary=boundary")
+            .body(body.clone()),
+    )?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.body().to_utf8()?, format!("{} bytes", body.len()));
+
+    let response = server.perform(
+        Request::post("/upload")
+            .header("content-type", "multipart/form-data; boundary=boundary")
+            .body(form_data(&[("image/png", JPEG)])),
+    )?;
+    assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    assert_eq!(
+        response.body().to_utf8()?,
+        "the content declared as image/png looks like JPEG image"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn unchecked_types_are_skipped() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(upload_app(modifiers::content_sniff()))?;
+
+    // no Content-Type.
+    let response = server.perform(Request::post("/upload").body(&b"\x00\x01"[..]))?;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // no detector is registered for the type.
+    let response = server.perform(
+        Request::post("/upload")
+            .header("content-type", "application/octet-stream")
+            .body(PNG),
+    )?;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // a text in the other charset than UTF-8.
+    let response = server.perform(
+        Request::post("/upload")
+            .header("content-type", "text/plain; charset=iso-8859-1")
+            .body(&b"caf\xe9"[..]),
+    )?;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    Ok(())
+}
+
+struct Pdf;
+
+impl Detector for Pdf {
+    fn applies_to(&self, mime: &Mime) -> bool {
+        mime.type_() == mime::APPLICATION && mime.subtype() == "pdf"
+    }
+
+    fn check(&self, _: &Mime, body: &[u8]) -> Result<(), Mismatch> {
+        if body.starts_with(b"%PDF-") {
+            Ok(())
+        } else {
+            Err(Mismatch::describe(body))
+        }
+    }
+}
+
+#[test]
+fn custom_detector() -> tsukuyomi_server::Result<()> {
+    let mut server =
+        tsukuyomi_server::test::server(upload_app(modifiers::content_sniff().detector(Pdf)))?;
+
+    let response = server.perform(
+        Request::post("/upload")
+            .header("content-type", "application/pdf")
+            .body("%PDF-1.4"),
+    )?;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = server.perform(
+        Request::post("/upload")
+            .header("content-type", "application/pdf")
+            .body(PNG),
+    )?;
+    assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    assert_eq!(
+        response.body().to_utf8()?,
+        "the content declared as application/pdf looks like PNG image"
+    );
+
+    Ok(())
+}
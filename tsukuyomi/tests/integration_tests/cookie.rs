@@ -48,3 +48,105 @@ fn disable_manage_cookies() -> tsukuyomi_server::Result<()> {
 
     Ok(())
 }
+
+fn set_cookies(response: &http::Response<tsukuyomi_server::test::Output>) -> Vec<String> {
+    let mut cookies: Vec<_> = response
+        .headers()
+        .get_all("set-cookie")
+        .iter()
+        .map(|value| value.to_str().unwrap().to_owned())
+        .collect();
+    cookies.sort();
+    cookies
+}
+
+#[test]
+fn layers_precedence() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/") //
+            .to(endpoint::reply(tsukuyomi::responder::oneshot(|input| {
+                let tracking = input.cookies_layer("tracking")?;
+                tracking.add(Cookie::new("a", "tracking"));
+                tracking.add(Cookie::new("b", "tracking"));
+                let session = input.cookies_layer("session")?;
+                session.add(Cookie::new("a", "session"));
+                session.add(Cookie::new("c", "session"));
+                input.cookies.jar()?.add(Cookie::new("c", "handler"));
+
+                // the existing layer is returned for the same name.
+                assert!(input.cookies_layer("tracking")?.get("a").is_some());
+
+                let mut pending: Vec<_> = input
+                    .pending_set_cookies()
+                    .iter()
+                    .map(|cookie| cookie.to_string())
+                    .collect();
+                pending.sort();
+                assert_eq!(pending, vec!["a=session", "b=tracking", "c=handler"]);
+                Ok::<_, tsukuyomi::Error>("")
+            }))),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        set_cookies(&response),
+        vec!["a=session", "b=tracking", "c=handler"]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn layer_removes_request_cookie() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/") //
+            .to(endpoint::reply(tsukuyomi::responder::oneshot(|input| {
+                input
+                    .cookies_layer("logout")?
+                    .remove(Cookie::named("session"));
+                // the main jar is not affected by the changes in the layers.
+                assert!(input.cookies.jar()?.get("session").is_some());
+                Ok::<_, tsukuyomi::Error>("")
+            }))),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(http::Request::get("/").header("cookie", "session=xxxx"))?;
+    assert_eq!(response.status(), 200);
+    let cookies = set_cookies(&response);
+    assert_eq!(cookies.len(), 1);
+    assert!(cookies[0].starts_with("session=;"), "{:?}", cookies);
+
+    Ok(())
+}
+
+#[test]
+fn frozen_cookies() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/") //
+            .to(endpoint::reply(tsukuyomi::responder::oneshot(|input| {
+                input
+                    .cookies_layer("csrf")?
+                    .add(Cookie::new("csrf-token", "secret"));
+                input.cookies.freeze();
+                assert!(input.cookies.is_frozen());
+
+                assert!(input.cookies.get("session")?.is_some());
+                assert!(input.cookies_layer("csrf").is_err());
+                input
+                    .cookies
+                    .jar()?
+                    .add(Cookie::new("csrf-token", "overwritten"));
+                Ok::<_, tsukuyomi::Error>("")
+            }))),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(http::Request::get("/").header("cookie", "session=xxxx"))?;
+    assert_eq!(response.status(), 500);
+    assert_eq!(set_cookies(&response), vec!["csrf-token=secret"]);
+
+    Ok(())
+}
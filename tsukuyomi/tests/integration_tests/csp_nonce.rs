@@ -0,0 +1,108 @@
+use {
+    http::{header, HeaderMap, Request, Response},
+    tsukuyomi::{
+        config::prelude::*,
+        extractor,
+        input::localmap::LocalData,
+        modifiers::{self, Nonce},
+        App,
+    },
+};
+
+fn html(body: String) -> Response<String> {
+    Response::builder()
+        .header(header::CONTENT_TYPE, "text/html; charset=utf-8")
+        .body(body)
+        .unwrap()
+}
+
+fn policy_nonce(headers: &HeaderMap) -> String {
+    let policy = headers
+        .get(header::CONTENT_SECURITY_POLICY)
+        .expect("missing Content-Security-Policy")
+        .to_str()
+        .unwrap();
+    assert!(policy.starts_with("script-src 'nonce-"));
+    policy["script-src 'nonce-".len()..policy.len() - 1].to_owned()
+}
+
+#[test]
+fn nonce_in_header_matches_body() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/")
+            .to(endpoint::get()
+                .extract(extractor::local::clone(&Nonce::KEY))
+                .call(|nonce: Nonce| html(format!("<script nonce=\"{}\"></script>", nonce))))
+            .modify(modifiers::csp_nonce("script-src 'nonce-{nonce}'")?),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(Request::get("/"))?;
+    assert_eq!(response.status(), 200);
+    let nonce = policy_nonce(response.headers());
+    assert_eq!(
+        response.body().to_utf8()?,
+        format!("<script nonce=\"{}\"></script>", nonce)
+    );
+
+    let response = server.perform(Request::get("/"))?;
+    assert_ne!(policy_nonce(response.headers()), nonce);
+
+    Ok(())
+}
+
+#[test]
+fn rewrite_placeholder_in_buffered_body() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/")
+            .to(endpoint::call(|| {
+                html(
+                    "<script nonce=\"__NONCE__\"></script><style nonce=\"__NONCE__\"></style>"
+                        .into(),
+                )
+            }))
+            .modify(modifiers::csp_nonce("script-src 'nonce-{nonce}'")?.rewrite("__NONCE__")),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(Request::get("/"))?;
+    let nonce = policy_nonce(response.headers());
+    assert_eq!(
+        response.body().to_utf8()?,
+        format!(
+            "<script nonce=\"{0}\"></script><style nonce=\"{0}\"></style>",
+            nonce
+        )
+    );
+
+    Ok(())
+}
+
+#[test]
+fn non_html_responses_are_untouched() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/")
+            .to(endpoint::call(|| {
+                Response::builder()
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body("{\"nonce\":\"__NONCE__\"}")
+                    .unwrap()
+            }))
+            .modify(modifiers::csp_nonce("script-src 'nonce-{nonce}'")?.rewrite("__NONCE__")),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(Request::get("/"))?;
+    assert!(!response
+        .headers()
+        .contains_key(header::CONTENT_SECURITY_POLICY));
+    assert_eq!(response.body().to_utf8()?, "{\"nonce\":\"__NONCE__\"}");
+
+    Ok(())
+}
+
+#[test]
+fn template_without_nonce_is_rejected() {
+    assert!(modifiers::csp_nonce("script-src 'self'").is_err());
+    assert!(modifiers::csp_nonce("script-src 'nonce-{nonce}'\n").is_err());
+}
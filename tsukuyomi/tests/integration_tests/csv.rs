@@ -0,0 +1,224 @@
+use {
+    http::{
+        header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+        Request,
+    },
+    serde::{Deserialize, Serialize},
+    tsukuyomi::{
+        config::prelude::*,
+        extractor,
+        input::csv::{CsvOptions, CsvRows, RowErrorMode},
+        output,
+        vendor::futures::{stream, Future, Stream},
+        App,
+    },
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Record {
+    id: u32,
+    name: String,
+    note: String,
+}
+
+fn records() -> Vec<Record> {
+    vec![
+        Record {
+            id: 1,
+            name: "plain".into(),
+            note: "".into(),
+        },
+        Record {
+            id: 2,
+            name: "with, comma".into(),
+            note: "say \"hi\"".into(),
+        },
+        Record {
+            id: 3,
+            name: "multi\nline".into(),
+            note: "crlf\r\nend".into(),
+        },
+    ]
+}
+
+/// Collects the rows, responding with the first row error as `400 Bad Request`.
+fn import(rows: CsvRows<Record>) -> impl Future<Item = String, Error = tsukuyomi::Error> {
+    rows.and_then(|row| row.map_err(tsukuyomi::error::bad_request))
+        .collect()
+        .map(|rows| serde_json::to_string(&rows).unwrap())
+}
+
+fn app() -> tsukuyomi::app::Result<App> {
+    App::create(chain![
+        path!("/export") //
+            .to(endpoint::get().call(|| {
+                output::csv(stream::iter_ok::<_, std::io::Error>(records())).filename("report.csv")
+            })),
+        path!("/export/excel") //
+            .to(endpoint::get().call(|| {
+                output::csv(stream::iter_ok::<_, std::io::Error>(records()))
+                    .delimiter(b';')
+                    .bom(true)
+            })),
+        path!("/import") //
+            .to(endpoint::post()
+                .extract(extractor::body::csv())
+                .call_async(import)),
+        path!("/import/excel") //
+            .to(endpoint::post()
+                .extract(extractor::body::csv_with(
+                    CsvOptions::default().delimiter(b';'),
+                ))
+                .call_async(import)),
+        path!("/import/positional") //
+            .to(endpoint::post()
+                .extract(extractor::body::csv_with(
+                    CsvOptions::default().has_headers(false),
+                ))
+                .call_async(import)),
+    ])
+}
+
+#[test]
+fn round_trip() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+
+    let response = server.perform(Request::get("/export"))?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()[CONTENT_TYPE], "text/csv; charset=utf-8");
+    assert_eq!(
+        response.headers()[CONTENT_DISPOSITION],
+        "attachment; filename=\"report.csv\""
+    );
+    let body = response.body().to_bytes().into_owned();
+    assert_eq!(
+        body,
+        &b"id,name,note\n\
+           1,plain,\n\
+           2,\"with, comma\",\"say \"\"hi\"\"\"\n\
+           3,\"multi\nline\",\"crlf\r\nend\"\n"[..]
+    );
+
+    let response = server.perform(
+        Request::post("/import")
+            .header(CONTENT_TYPE, "text/csv")
+            .body(body),
+    )?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.body().to_utf8()?,
+        serde_json::to_string(&records()).unwrap()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn positional_mapping() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+
+    let response = server.perform(
+        Request::post("/import/positional")
+            .header(CONTENT_TYPE, "text/csv; charset=utf-8")
+            .body("7,seven,\n"),
+    )?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.body().to_utf8()?,
+        r#"[{"id":7,"name":"seven","note":""}]"#
+    );
+
+    let response = server.perform(
+        Request::post("/import")
+            .header(CONTENT_TYPE, "application/json")
+            .body("id,name,note\n"),
+    )?;
+    assert_eq!(response.status(), 400);
+
+    Ok(())
+}
+
+#[test]
+fn excel_bom() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+
+    let response = server.perform(Request::get("/export/excel"))?;
+    assert_eq!(response.status(), 200);
+    assert!(response.headers().get(CONTENT_DISPOSITION).is_none());
+    let body = response.body().to_bytes().into_owned();
+    assert!(body.starts_with(b"\xEF\xBB\xBFid;name;note\n1;plain;\n"));
+
+    // the byte order mark is not a part of the first column name.
+    let response = server.perform(Request::post("/import/excel").body(body))?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.body().to_utf8()?,
+        serde_json::to_string(&records()).unwrap()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn abort_on_error_reports_line() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+
+    let response = server.perform(
+        Request::post("/import")
+            .header(CONTENT_TYPE, "text/csv")
+            .body("id,name,note\n1,a,\n2,\"b\nb\",\nthree,c,\n4,d,\n"),
+    )?;
+    assert_eq!(response.status(), 400);
+    let body = response.body().to_utf8()?;
+    assert!(body.starts_with("line 5: "), "unexpected body: {}", body);
+
+    Ok(())
+}
+
+#[test]
+fn large_body_is_streamed() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/import") //
+            .to(endpoint::post()
+                .extract(extractor::body::csv_with(
+                    CsvOptions::default()
+                        .max_row_size(1024)
+                        .on_row_error(RowErrorMode::Skip),
+                ))
+                .call_async(|rows: CsvRows<Record>| {
+                    rows.fold((0u64, vec![]), |(mut sum, mut errors), row| {
+                        match row {
+                            Ok(record) => sum += u64::from(record.id),
+                            Err(err) => errors.push(err.line()),
+                        }
+                        Ok::<_, tsukuyomi::Error>((sum, errors))
+                    })
+                    .map(|(sum, errors)| format!("{} {:?}", sum, errors))
+                })),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    // 100,000 rows generated lazily, with an oversized row of 1 MiB in the middle.
+    let chunks = (0..1000u32).map(|i| {
+        let mut chunk = String::new();
+        if i == 0 {
+            chunk.push_str("id,name,note\n");
+        }
+        for id in i * 100 + 1..=(i + 1) * 100 {
+            if id == 50_000 {
+                chunk.push_str(&format!("{},{},\n", id, "x".repeat(1024 * 1024)));
+            } else {
+                chunk.push_str(&format!("{},row,\n", id));
+            }
+        }
+        chunk
+    });
+    let body = hyper::Body::wrap_stream(stream::iter_ok::<_, std::io::Error>(chunks));
+
+    let response = server.perform(Request::post("/import").body(body))?;
+    assert_eq!(response.status(), 200);
+    let expected = (1..=100_000u64).sum::<u64>() - 50_000;
+    assert_eq!(response.body().to_utf8()?, format!("{} [50001]", expected));
+
+    Ok(())
+}
@@ -0,0 +1,188 @@
+use tsukuyomi::{
+    config::prelude::*, //
+    extractor,
+    input::datetime,
+    output,
+    vendor::chrono::{DateTime, Duration, NaiveDate, Utc},
+    App,
+};
+
+#[test]
+fn path_params() -> tsukuyomi_server::Result<()> {
+    let app = App::create(chain![
+        path!("/since/:since") //
+            .to(endpoint::call(|since: DateTime<Utc>| since.to_rfc3339())),
+        path!("/date/:date") //
+            .to(endpoint::call(|date: NaiveDate| date.to_string())),
+        path!("/wait/:secs") //
+            .to(endpoint::call(|secs: Duration| secs
+                .num_minutes()
+                .to_string())),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/since/2018-11-01T00:00:00Z")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "2018-11-01T00:00:00+00:00");
+
+    let response = server.perform("/since/2018-11-01T09:00:00%2B09:00")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "2018-11-01T00:00:00+00:00");
+
+    let response = server.perform("/since/2018-11-01")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "2018-11-01T00:00:00+00:00");
+
+    let response = server.perform("/since/yesterday")?;
+    assert_eq!(response.status(), 404);
+
+    let response = server.perform("/date/2018-11-01")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "2018-11-01");
+
+    let response = server.perform("/date/2018-13-01")?;
+    assert_eq!(response.status(), 404);
+
+    let response = server.perform("/wait/120")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "2");
+
+    let response = server.perform("/wait/-1")?;
+    assert_eq!(response.status(), 404);
+
+    Ok(())
+}
+
+#[test]
+fn query_params() -> tsukuyomi_server::Result<()> {
+    #[derive(Debug, serde::Deserialize)]
+    struct Query {
+        #[serde(with = "datetime::utc")]
+        until: DateTime<Utc>,
+        #[serde(with = "datetime::date")]
+        day: NaiveDate,
+        #[serde(with = "datetime::seconds")]
+        step: Duration,
+    }
+
+    let app = App::create(
+        path!("/metrics") //
+            .to(endpoint::get()
+                .extract(extractor::query())
+                .call(|q: Query| {
+                    format!(
+                        "{},{},{}",
+                        q.until.to_rfc3339(),
+                        q.day,
+                        q.step.num_seconds()
+                    )
+                })),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/metrics?until=2018-11-01T12:00:00Z&day=2018-11-02&step=60")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.body().to_utf8()?,
+        "2018-11-01T12:00:00+00:00,2018-11-02,60"
+    );
+
+    let response =
+        server.perform("/metrics?until=2018-11-01T12:00:00-03:00&day=2018-11-02&step=60")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.body().to_utf8()?,
+        "2018-11-01T15:00:00+00:00,2018-11-02,60"
+    );
+
+    let response = server.perform("/metrics?until=2018-11-01&day=2018-11-02&step=60")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.body().to_utf8()?,
+        "2018-11-01T00:00:00+00:00,2018-11-02,60"
+    );
+
+    let response = server.perform("/metrics?until=2018-11-01T25:00:00Z&day=2018-11-02&step=60")?;
+    assert_eq!(response.status(), 400);
+    assert_eq!(
+        response.body().to_utf8()?,
+        "query parameter `until': invalid value `2018-11-01T25:00:00Z': \
+         expected an RFC 3339 datetime or a date of the form YYYY-MM-DD"
+    );
+
+    let response = server.perform("/metrics?until=2018-11-01&day=11/02/2018&step=60")?;
+    assert_eq!(response.status(), 400);
+    assert_eq!(
+        response.body().to_utf8()?,
+        "query parameter `day': invalid value `11/02/2018': expected a date of the form YYYY-MM-DD"
+    );
+
+    let response = server.perform("/metrics?until=2018-11-01&day=2018-11-02&step=1m")?;
+    assert_eq!(response.status(), 400);
+    assert_eq!(
+        response.body().to_utf8()?,
+        "query parameter `step': invalid value `1m': expected a non-negative number of seconds"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn serde_round_trip() {
+    #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct Query {
+        #[serde(with = "datetime::utc")]
+        until: DateTime<Utc>,
+        #[serde(with = "datetime::date")]
+        day: NaiveDate,
+        #[serde(with = "datetime::seconds")]
+        step: Duration,
+    }
+
+    let query = Query {
+        until: "2018-11-01T12:00:00Z".parse().unwrap(),
+        day: "2018-11-02".parse().unwrap(),
+        step: Duration::seconds(60),
+    };
+
+    let encoded = serde_urlencoded::to_string(&query).unwrap();
+    assert_eq!(
+        encoded,
+        "until=2018-11-01T12%3A00%3A00%2B00%3A00&day=2018-11-02&step=60"
+    );
+    assert_eq!(
+        serde_urlencoded::from_str::<Query>(&encoded).unwrap(),
+        query
+    );
+
+    let encoded = serde_json::to_string(&query).unwrap();
+    assert_eq!(
+        encoded,
+        r#"{"until":"2018-11-01T12:00:00+00:00","day":"2018-11-02","step":"60"}"#
+    );
+    assert_eq!(serde_json::from_str::<Query>(&encoded).unwrap(), query);
+}
+
+#[test]
+fn last_modified() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/") //
+            .to(endpoint::call(|| {
+                output::last_modified(
+                    "2018-11-01T09:30:00Z".parse::<DateTime<Utc>>().unwrap(),
+                    "hello",
+                )
+            })),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.headers().get("last-modified").unwrap(),
+        "Thu, 01 Nov 2018 09:30:00 GMT"
+    );
+    assert_eq!(response.body().to_utf8()?, "hello");
+
+    Ok(())
+}
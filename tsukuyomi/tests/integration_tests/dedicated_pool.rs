@@ -0,0 +1,184 @@
+use {
+    http::{header::RETRY_AFTER, Request, StatusCode},
+    hyper::Body,
+    std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        thread,
+        time::{Duration, Instant},
+    },
+    tsukuyomi::{
+        config::{prelude::*, state_with_lifecycle},
+        modifiers::{self, DedicatedPool},
+        output::ResponseBody,
+        rt::MockClock,
+        vendor::futures::{
+            executor::{self, Notify, Spawn},
+            Async, Future,
+        },
+        App,
+    },
+    tsukuyomi_service::{MakeService, Service},
+};
+
+type ResponseFuture = Box<dyn Future<Item = http::Response<ResponseBody>, Error = ()> + Send>;
+
+struct Noop;
+
+impl Notify for Noop {
+    fn notify(&self, _: usize) {}
+}
+
+/// An application whose route `/render/{name}` spins on the dedicated pool until released.
+struct Harness {
+    app: App,
+    pool: DedicatedPool,
+    released: Arc<AtomicBool>,
+}
+
+impl Harness {
+    fn new(pool: DedicatedPool) -> tsukuyomi::app::Result<Self> {
+        let released = Arc::new(AtomicBool::new(false));
+        let app = App::create(chain![
+            state_with_lifecycle(pool.clone()),
+            path!("/render/:name")
+                .to(endpoint::get().call({
+                    let released = released.clone();
+                    move |name: String| {
+                        let started = Instant::now();
+                        while !released.load(Ordering::SeqCst)
+                            && started.elapsed() < Duration::from_secs(10)
+                        {
+                            thread::yield_now();
+                        }
+                        let thread = thread::current();
+                        format!("{} on {}", name, thread.name().unwrap_or("?"))
+                    }
+                }))
+                .modify(pool.clone()),
+            path!("/fast").to(endpoint::get().reply("fast")),
+        ])?
+        .with_clock(MockClock::new());
+        Ok(Self {
+            app,
+            pool,
+            released,
+        })
+    }
+
+    fn request(&self, uri: &str) -> Spawn<ResponseFuture> {
+        let mut service = MakeService::<(), Request<Body>>::make_service(&self.app, ())
+            .wait()
+            .unwrap_or_else(|never| match never {});
+        let request = Request::get(uri).body(Body::empty()).unwrap();
+        let future: ResponseFuture =
+            Box::new(service.call(request).map_err(|never| match never {}));
+        executor::spawn(future)
+    }
+
+    fn release(&self) {
+        self.released.store(true, Ordering::SeqCst);
+    }
+
+    /// Waits until the specified number of requests are being processed on the pool.
+    fn wait_busy(&self, busy: u64) {
+        let started = Instant::now();
+        while self.pool.metrics().busy != busy {
+            assert!(started.elapsed() < Duration::from_secs(5), "timed out");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+}
+
+fn poll(request: &mut Spawn<ResponseFuture>) -> Option<http::Response<ResponseBody>> {
+    match request.poll_future_notify(&Arc::new(Noop), 0) {
+        Ok(Async::Ready(response)) => Some(response),
+        Ok(Async::NotReady) => None,
+        Err(()) => unreachable!(),
+    }
+}
+
+fn read_body(response: http::Response<ResponseBody>) -> String {
+    let body = tsukuyomi::test::read_body(response.into_body()).expect("failed to read the body");
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+#[test]
+fn blocking_handler_does_not_delay_other_routes() -> tsukuyomi_server::Result<()> {
+    let harness = Harness::new(modifiers::dedicated_pool("render"))?;
+
+    let mut slow = harness.request("/render/report");
+    assert!(poll(&mut slow).is_none());
+    harness.wait_busy(1);
+
+    // The handler keeps spinning until released, so the fast route has to be
+    // processed while it is running.
+    let started = Instant::now();
+    let response = harness.request("/fast").wait_future().unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(started.elapsed() < Duration::from_secs(2));
+    assert_eq!(harness.pool.metrics().busy, 1);
+
+    harness.release();
+    let response = slow.wait_future().unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(read_body(response).starts_with("report on render-"));
+
+    let metrics = harness.pool.metrics();
+    assert_eq!((metrics.busy, metrics.queued, metrics.completed), (0, 0, 1));
+
+    Ok(())
+}
+
+#[test]
+fn overflow_is_rejected() -> tsukuyomi_server::Result<()> {
+    let pool = DedicatedPool::new("render")
+        .threads(1)
+        .queue_bound(1)
+        .retry_after(Duration::from_secs(3));
+    let harness = Harness::new(pool)?;
+
+    let mut first = harness.request("/render/first");
+    let mut second = harness.request("/render/second");
+    assert!(poll(&mut first).is_none());
+    assert!(poll(&mut second).is_none());
+    harness.wait_busy(1);
+    assert_eq!(harness.pool.metrics().queued, 1);
+
+    let response = poll(&mut harness.request("/render/third")).expect("should be rejected");
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(response.headers()[RETRY_AFTER], "3");
+    assert_eq!(harness.pool.metrics().rejected, 1);
+
+    harness.release();
+    assert_eq!(first.wait_future().unwrap().status(), StatusCode::OK);
+    assert_eq!(second.wait_future().unwrap().status(), StatusCode::OK);
+    assert_eq!(harness.pool.metrics().completed, 2);
+
+    Ok(())
+}
+
+#[test]
+fn shutdown_joins_worker_threads() -> tsukuyomi_server::Result<()> {
+    let harness = Harness::new(DedicatedPool::new("render").threads(2))?;
+    harness.release();
+
+    let response = harness.request("/render/report").wait_future().unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(harness.pool.metrics().threads > 0);
+
+    let timed_out = harness
+        .app
+        .shutdown(Duration::from_secs(5))
+        .wait()
+        .unwrap_or_else(|never| match never {});
+    assert!(timed_out.is_empty());
+    assert_eq!(harness.pool.metrics().threads, 0);
+
+    let response = poll(&mut harness.request("/render/report")).expect("should be rejected");
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+    Ok(())
+}
@@ -0,0 +1,121 @@
+use {
+    http::Request,
+    std::{
+        fs,
+        path::PathBuf,
+        sync::atomic::{AtomicUsize, Ordering},
+    },
+    tsukuyomi::{
+        config::{prelude::*, state},
+        dynamic::{ConfigSet, DynamicConfig},
+        extractor, modifiers, App,
+    },
+};
+
+struct TempFile(PathBuf);
+
+impl TempFile {
+    fn new(content: &str) -> Self {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "tsukuyomi-dynamic-{}-{}.json",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::SeqCst)
+        ));
+        let file = TempFile(path);
+        file.write(content);
+        file
+    }
+
+    fn write(&self, content: &str) {
+        fs::write(&self.0, content).unwrap();
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+fn app(
+    maintenance: &DynamicConfig<bool>,
+    limit: &DynamicConfig<u32>,
+) -> tsukuyomi::app::Result<App> {
+    App::create(chain![
+        mount("/api").with(chain![
+            state(limit.clone()),
+            path!("/limit") //
+                .to(endpoint::get()
+                    .extract(extractor::state::state())
+                    .call(|limit: DynamicConfig<u32>| format!("limit={}", limit.get()))),
+        ]),
+        mount("/shop")
+            .with(
+                path!("/") //
+                    .to(endpoint::get().reply("shop"))
+            )
+            .modify(
+                modifiers::maintenance_mode(maintenance.clone())
+                    .template(mime::TEXT_PLAIN_UTF_8, "{path} is under maintenance"),
+            ),
+    ])
+}
+
+#[test]
+fn flip_maintenance_mode() -> tsukuyomi_server::Result<()> {
+    let maintenance = DynamicConfig::new(false);
+    let limit = DynamicConfig::new(100u32);
+    let file = TempFile::new(r#"{ "maintenance": false }"#);
+    let configs = ConfigSet::new(&file.0)
+        .register("maintenance", &maintenance)
+        .register("rate_limit", &limit);
+    let mut server = tsukuyomi_server::test::server(app(&maintenance, &limit)?)?;
+
+    let response = server.perform("/shop")?;
+    assert_eq!(response.status(), 200);
+
+    file.write(r#"{ "maintenance": true, "rate_limit": 10 }"#);
+    assert_eq!(configs.reload()?, 2);
+
+    let response = server.perform("/shop")?;
+    assert_eq!(response.status(), 503);
+    assert_eq!(response.body().to_utf8()?, "/shop is under maintenance");
+
+    let response = server.perform(Request::get("/api/limit"))?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "limit=10");
+
+    file.write(r#"{ "maintenance": false, "rate_limit": 10 }"#);
+    assert_eq!(configs.reload()?, 1);
+
+    let response = server.perform("/shop")?;
+    assert_eq!(response.status(), 200);
+
+    Ok(())
+}
+
+#[test]
+fn invalid_file_leaves_values_intact() -> tsukuyomi_server::Result<()> {
+    let maintenance = DynamicConfig::new(false);
+    let limit = DynamicConfig::new(100u32);
+    let file = TempFile::new(r#"{ "maintenance": true, "rate_limit": "many" }"#);
+    let configs = ConfigSet::new(&file.0)
+        .register("maintenance", &maintenance)
+        .register("rate_limit", &limit);
+
+    assert!(configs.reload().is_err());
+    assert!(!*maintenance.get());
+    assert_eq!(*limit.get(), 100);
+
+    file.write(r#"{ "maintenance": true, "#);
+    assert!(configs.reload().is_err());
+    assert!(!*maintenance.get());
+
+    file.write(r#"{ "maintenance": true }"#);
+    assert_eq!(configs.reload()?, 1);
+    assert!(*maintenance.get());
+    assert_eq!(*limit.get(), 100);
+
+    Ok(())
+}
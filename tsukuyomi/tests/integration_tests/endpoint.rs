@@ -0,0 +1,306 @@
+use {
+    bytes::Bytes,
+    http::{
+        header::{HeaderValue, ALLOW},
+        Method, Request, Response, StatusCode,
+    },
+    hyper::body::Payload,
+    std::sync::{Arc, Mutex},
+    tsukuyomi::{
+        config::{path::Path, prelude::*},
+        endpoint::BoxedEndpoint,
+        error::Error,
+        extractor,
+        future::{Async, Poll, TryFuture},
+        handler::{AllowedMethods, Handler, ModifyHandler},
+        modifiers,
+        output::ResponseBody,
+        vendor::futures::{stream, Stream},
+        App, Input,
+    },
+    tsukuyomi_server::test::ResponseExt,
+};
+
+#[test]
+fn or_else_recovers_extractor_error() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/") //
+            .to(endpoint::get()
+                .extract(extractor::ready(|_: &mut Input<'_>| {
+                    Err::<(), _>(tsukuyomi::error::not_found("missing"))
+                }))
+                .call(|| "found")
+                .or_else(|err: Error, input: &Input<'_>| {
+                    assert_eq!(input.request.uri().path(), "/");
+                    assert_eq!(err.into_response(input.request).status(), 404);
+                    Ok("not found page")
+                })),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "not found page");
+
+    Ok(())
+}
+
+#[test]
+fn or_else_passes_through_error() -> tsukuyomi_server::Result<()> {
+    let app = App::create(chain![
+        path!("/error") //
+            .to(endpoint::call_async(|| {
+                Err::<&'static str, _>(tsukuyomi::error::bad_request("error"))
+            })
+            .or_else(|err: Error, _: &Input<'_>| Err(err))),
+        path!("/ok") //
+            .to(endpoint::reply("ok").or_else(|_: Error, _: &Input<'_>| Ok("recovered"))),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/error")?;
+    assert_eq!(response.status(), 400);
+
+    let response = server.perform("/ok")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "ok");
+
+    Ok(())
+}
+
+#[test]
+fn or_else_with_modifier() -> tsukuyomi_server::Result<()> {
+    let marker = Arc::new(Mutex::new(vec![]));
+
+    let app = App::create(
+        path!("/") //
+            .to(endpoint::call_async(|| {
+                Err::<&'static str, _>(tsukuyomi::error::internal_server_error("error"))
+            })
+            .or_else(|_: Error, _: &Input<'_>| Ok("recovered")))
+            .modify(Observe {
+                marker: marker.clone(),
+            }),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "recovered");
+    assert_eq!(*marker.lock().unwrap(), vec![Ok("recovered")]);
+
+    Ok(())
+}
+
+#[test]
+fn map_output_adds_header() -> tsukuyomi_server::Result<()> {
+    let app = App::create(chain![
+        path!("/posts/:id") //
+            .to(endpoint::get()
+                .call(|id: u32| format!("post {}", id))
+                .map_output(|mut response: Response<ResponseBody>, input: &Input<'_>| {
+                    assert_eq!(input.request.uri().path(), "/posts/2");
+                    response
+                        .headers_mut()
+                        .insert("link", HeaderValue::from_static("</posts/3>; rel=\"next\""));
+                    response
+                })),
+        path!("/about") //
+            .to(endpoint::get().reply("about")),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/posts/2")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.header("link")?, "</posts/3>; rel=\"next\"");
+    assert_eq!(response.body().to_utf8()?, "post 2");
+
+    let response = server.perform("/about")?;
+    assert!(!response.headers().contains_key("link"));
+
+    Ok(())
+}
+
+#[test]
+fn map_output_wraps_body() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/") //
+            .to(endpoint::get()
+                .call(|| tsukuyomi::output::json(vec![1, 2, 3]))
+                .map_output(|response: Response<ResponseBody>, _: &Input<'_>| {
+                    let (mut parts, mut body) = response.into_parts();
+                    parts.headers.remove(http::header::CONTENT_LENGTH);
+                    let data = stream::poll_fn(move || body.poll_data())
+                        .map(|chunk| Bytes::from(chunk.as_ref()));
+                    let envelope = stream::once(Ok(Bytes::from_static(b"{\"data\":")))
+                        .chain(data)
+                        .chain(stream::once(Ok(Bytes::from_static(b"}"))));
+                    Response::from_parts(parts, ResponseBody::wrap_stream(envelope))
+                })),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.header("content-type")?, "application/json");
+    assert_eq!(response.body().to_utf8()?, r#"{"data":[1,2,3]}"#);
+
+    Ok(())
+}
+
+#[test]
+fn try_map_output_error() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/") //
+            .to(endpoint::get().reply("ok").try_map_output(
+                |_: Response<ResponseBody>, _: &Input<'_>| {
+                    Err(tsukuyomi::error::forbidden("denied"))
+                },
+            )),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/")?;
+    assert_eq!(response.status(), 403);
+
+    Ok(())
+}
+
+#[derive(Clone)]
+struct Observe {
+    marker: Arc<Mutex<Vec<Result<&'static str, String>>>>,
+}
+
+impl<H> ModifyHandler<H> for Observe
+where
+    H: Handler<Output = &'static str>,
+{
+    type Output = &'static str;
+    type Handler = ObserveHandler<H>;
+
+    fn modify(&self, inner: H) -> Self::Handler {
+        ObserveHandler {
+            inner,
+            marker: self.marker.clone(),
+        }
+    }
+}
+
+struct ObserveHandler<H> {
+    inner: H,
+    marker: Arc<Mutex<Vec<Result<&'static str, String>>>>,
+}
+
+impl<H> Handler for ObserveHandler<H>
+where
+    H: Handler<Output = &'static str>,
+{
+    type Output = &'static str;
+    type Error = Error;
+    type Handle = ObserveHandle<H::Handle>;
+
+    fn allowed_methods(&self) -> Option<&AllowedMethods> {
+        self.inner.allowed_methods()
+    }
+
+    fn handle(&self) -> Self::Handle {
+        ObserveHandle {
+            inner: self.inner.handle(),
+            marker: self.marker.clone(),
+        }
+    }
+}
+
+struct ObserveHandle<H> {
+    inner: H,
+    marker: Arc<Mutex<Vec<Result<&'static str, String>>>>,
+}
+
+impl<H> TryFuture for ObserveHandle<H>
+where
+    H: TryFuture<Ok = &'static str>,
+{
+    type Ok = &'static str;
+    type Error = Error;
+
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        let result = self.inner.poll_ready(input).map_err(Into::into);
+        match result {
+            Ok(Async::Ready(output)) => self.marker.lock().unwrap().push(Ok(output)),
+            Ok(Async::NotReady) => {}
+            Err(ref err) => self.marker.lock().unwrap().push(Err(err.to_string())),
+        }
+        result
+    }
+}
+
+#[test]
+fn allow_only_extension_methods() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/") //
+            .to(endpoint::allow_only("PROPFIND, MKCOL")?.call(|| "dav")),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(http::Request::builder().method("PROPFIND").uri("/"))?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "dav");
+
+    let response = server.perform(http::Request::builder().method("MKCOL").uri("/"))?;
+    assert_eq!(response.status(), 200);
+
+    let response = server.perform("/")?;
+    assert_eq!(response.status(), 405);
+
+    Ok(())
+}
+
+#[test]
+fn boxed_endpoints_from_table() -> tsukuyomi_server::Result<()> {
+    let table: Vec<(&str, &[Method], &str)> = vec![
+        ("/a", &[Method::GET], "a"),
+        ("/b", &[Method::POST], "b"),
+        ("/c", &[Method::GET, Method::PUT], "c"),
+        ("/d", &[Method::DELETE], "d"),
+        ("/e/f", &[Method::PATCH], "e"),
+    ];
+
+    let mut endpoints: Vec<(String, BoxedEndpoint)> = vec![];
+    for (i, &(uri, methods, body)) in table.iter().enumerate() {
+        let endpoint = if i % 2 == 0 {
+            endpoint::allow_only(methods.to_vec())?.reply(body).boxed()
+        } else {
+            // the endpoints with different output types.
+            endpoint::allow_only(methods.to_vec())?
+                .call(move || String::from(body))
+                .boxed()
+        };
+        endpoints.push((uri.to_owned(), endpoint));
+    }
+
+    let app = App::create(
+        endpoints
+            .into_iter()
+            .map(|(uri, endpoint)| Path::<()>::new(uri).to(endpoint))
+            .collect::<Vec<_>>()
+            .modify(modifiers::default_options()),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    for &(uri, methods, body) in &table {
+        let response = server.perform(Request::builder().method(methods[0].clone()).uri(uri))?;
+        assert_eq!(response.status(), StatusCode::OK, "uri = {}", uri);
+        assert_eq!(response.body().to_utf8()?, body);
+
+        let response = server.perform(Request::options(uri))?;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let mut expected: Vec<_> = methods.iter().map(Method::as_str).collect();
+        expected.push("OPTIONS");
+        assert_eq!(response.header(ALLOW)?, expected.join(", ").as_str());
+    }
+
+    let response = server.perform(Request::get("/b"))?;
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+    Ok(())
+}
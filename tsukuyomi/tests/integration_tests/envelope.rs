@@ -0,0 +1,165 @@
+use {
+    http::{Request, StatusCode},
+    serde_json::{json, Value},
+    tsukuyomi::{
+        config::prelude::*,
+        extractor::{
+            self,
+            pagination::{Defaults, Pagination},
+        },
+        modifiers,
+        output::{self, Paginated},
+        App,
+    },
+};
+
+fn json_body(response: &http::Response<tsukuyomi_server::test::Output>) -> Value {
+    serde_json::from_str(&response.body().to_utf8().unwrap()).unwrap()
+}
+
+#[test]
+fn wrap_handler_output() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/user")
+            .to(endpoint::get().call(|| output::json(json!({ "name": "alice" }))))
+            .modify(modifiers::envelope()),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(Request::get("/user").header("x-request-id", "req-42"))?;
+    assert_eq!(response.status(), 200);
+    let body = json_body(&response);
+    assert_eq!(body["data"], json!({ "name": "alice" }));
+    assert_eq!(body["meta"]["request_id"], "req-42");
+    assert!(body["meta"]["elapsed_ms"].is_u64());
+    assert!(body["meta"].get("pagination").is_none());
+
+    Ok(())
+}
+
+#[test]
+fn custom_keys() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/") //
+            .to(endpoint::get().call(|| output::json(vec![1, 2])))
+            .modify(modifiers::envelope().data_key("result").meta_key("_meta")),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let body = json_body(&server.perform("/")?);
+    assert_eq!(body["result"], json!([1, 2]));
+    assert!(body["_meta"].is_object());
+    assert!(body.get("data").is_none());
+
+    Ok(())
+}
+
+#[test]
+fn pagination_meta() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/items")
+            .to(endpoint::get()
+                .extract(extractor::pagination(Defaults::new()))
+                .call(|pagination: Pagination| Paginated::new(vec![10, 11], pagination).total(12)))
+            .modify(modifiers::envelope()),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/items?page=6&per_page=2")?;
+    assert_eq!(response.status(), 200);
+    assert!(response.headers().contains_key("link"));
+    let body = json_body(&response);
+    assert_eq!(body["data"], json!([10, 11]));
+    assert_eq!(
+        body["meta"]["pagination"],
+        json!({ "page": 6, "per_page": 2, "total": 12 })
+    );
+
+    Ok(())
+}
+
+#[test]
+fn innermost_envelope_wins() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        mount("/api")
+            .with(chain![
+                path!("/inner")
+                    .to(endpoint::get().call(|| output::json(json!(1))))
+                    .modify(modifiers::envelope().data_key("inner")),
+                path!("/outer").to(endpoint::get().call(|| output::json(json!(2)))),
+            ])
+            .modify(modifiers::envelope().data_key("outer")),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let body = json_body(&server.perform("/api/inner")?);
+    assert_eq!(body["inner"], json!(1));
+    assert!(body.get("outer").is_none());
+    assert!(body["meta"].get("meta").is_none());
+
+    let body = json_body(&server.perform("/api/outer")?);
+    assert_eq!(body["outer"], json!(2));
+
+    Ok(())
+}
+
+#[test]
+fn non_json_untouched() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        chain![
+            path!("/text").to(endpoint::get().reply("plain")),
+            path!("/created").to(endpoint::get().call(|| {
+                http::Response::builder()
+                    .status(StatusCode::ACCEPTED)
+                    .header("content-type", "application/problem+json")
+                    .body("{}")
+                    .unwrap()
+            })),
+        ]
+        .modify(modifiers::envelope()),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/text")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "plain");
+
+    let response = server.perform("/created")?;
+    assert_eq!(response.status(), 202);
+    assert_eq!(response.body().to_utf8()?, "{}");
+
+    Ok(())
+}
+
+#[test]
+fn errors_wrapped_on_request() -> tsukuyomi_server::Result<()> {
+    let failing = || {
+        endpoint::get().call(|| -> tsukuyomi::Result<&'static str> {
+            Err(tsukuyomi::error::bad_request("invalid"))
+        })
+    };
+    let app = App::create(chain![
+        path!("/plain").to(failing()).modify(modifiers::envelope()),
+        path!("/wrapped")
+            .to(failing())
+            .modify(modifiers::envelope().wrap_errors(true)),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/plain")?;
+    assert_eq!(response.status(), 400);
+    assert_eq!(response.body().to_utf8()?, "invalid");
+
+    let response = server.perform(Request::get("/wrapped").header("x-request-id", "req-1"))?;
+    assert_eq!(response.status(), 400);
+    assert_eq!(
+        response.headers().get("content-type").unwrap(),
+        "application/json"
+    );
+    let body = json_body(&response);
+    assert_eq!(body["error"]["status"], 400);
+    assert_eq!(body["error"]["detail"], "invalid");
+    assert_eq!(body["meta"]["request_id"], "req-1");
+
+    Ok(())
+}
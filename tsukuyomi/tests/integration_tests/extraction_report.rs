@@ -0,0 +1,117 @@
+use {
+    http::StatusCode,
+    std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    },
+    tsukuyomi::{
+        app::{ErrorReport, SlowRequestLog},
+        config::prelude::*,
+        extractor::{self, report::ExtractionReport, ExtractorExt},
+        input::localmap::LocalData,
+        rt::MockClock,
+        App,
+    },
+};
+
+#[test]
+fn failure_attribution() -> tsukuyomi_server::Result<()> {
+    let reports = Arc::new(Mutex::new(vec![]));
+
+    let app = App::create(
+        path!("/orders") //
+            .to(endpoint::get()
+                .extract(extractor::value(1u32))
+                .extract(
+                    extractor::ready(|_| {
+                        Err::<(u32,), _>(tsukuyomi::error::internal_server_error(
+                            "session store is down",
+                        ))
+                    })
+                    .label("session"),
+                )
+                .extract(extractor::value(3u32))
+                .call(|_: u32, _: u32, _: u32| "orders")),
+    )?
+    .on_error({
+        let reports = reports.clone();
+        move |report: ErrorReport| reports.lock().unwrap().push(report)
+    });
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/orders")?;
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+    let reports = reports.lock().unwrap();
+    assert_eq!(reports.len(), 1);
+    let report = &reports[0];
+    assert_eq!(report.failed_extractor(), Some("session"));
+
+    let entries = report.extraction().unwrap().entries();
+    assert_eq!(
+        entries
+            .iter()
+            .map(|entry| (entry.label(), entry.error()))
+            .collect::<Vec<_>>(),
+        vec![("#1", None), ("session", Some("session store is down"))]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn timing_in_slow_request_log() -> tsukuyomi_server::Result<()> {
+    let _ = super::logging::take_records("slow request: method=GET pattern=/extractors");
+
+    let clock = MockClock::new();
+    let app = App::create(chain![
+        SlowRequestLog::new(Duration::from_millis(5)),
+        path!("/extractors") //
+            .to(endpoint::get()
+                .extract(extractor::value(1u32))
+                .extract(
+                    extractor::ready({
+                        let clock = clock.clone();
+                        move |_| {
+                            clock.advance(Duration::from_millis(20));
+                            Ok::<_, tsukuyomi::Error>((2u32,))
+                        }
+                    })
+                    .label("slow-lookup"),
+                )
+                .call(|_: u32, _: u32| "done")),
+    ])?
+    .with_clock(clock.clone());
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/extractors")?;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let records = super::logging::take_records("slow request: method=GET pattern=/extractors");
+    assert_eq!(records.len(), 1, "{:?}", records);
+    assert!(
+        records[0].ends_with(" extractors=[#1 ok 0ns, slow-lookup ok 20ms]"),
+        "{}",
+        records[0]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn not_recorded_without_consumers() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/recorded") //
+            .to(endpoint::get()
+                .extract(extractor::ready(|input| {
+                    Ok::<_, tsukuyomi::Error>((ExtractionReport::contains(input.locals),))
+                }))
+                .call(|recorded: bool| format!("recorded={}", recorded))),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/recorded")?;
+    assert_eq!(response.body().to_utf8()?, "recorded=false");
+
+    Ok(())
+}
@@ -0,0 +1,155 @@
+use {
+    http::{
+        header::{ACCEPT, ALLOW, CONTENT_TYPE, LINK},
+        Request, StatusCode,
+    },
+    std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    tsukuyomi::{config::prelude::*, fallback, App},
+};
+
+fn app(suggestions: fallback::Suggestions) -> tsukuyomi::app::Result<App> {
+    App::create(chain![
+        path!("/users") //
+            .to(endpoint::get().reply("users")),
+        path!("/users/:id") //
+            .to(endpoint::get().call(|id: u32| format!("user {}", id))),
+        path!("/posts") //
+            .to(endpoint::get().reply("posts")),
+        mount("/admin").with(chain![
+            path!("/users").to(endpoint::get().reply("admin users")),
+            path!("/posts").to(endpoint::get().reply("admin posts")),
+            path!("/settings").to(endpoint::get().reply("admin settings")),
+            path!("/reports").to(endpoint::get().reply("admin reports")),
+        ]),
+        path!("*").to(suggestions),
+    ])
+}
+
+#[test]
+fn near_miss() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app(fallback::suggestions(3))?)?;
+
+    let response = server.perform("/user/42")?;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(response.headers()[CONTENT_TYPE], "application/json");
+    assert_eq!(response.headers()[LINK], "</users/42>; rel=\"alternate\"");
+    assert_eq!(
+        response.body().to_utf8()?,
+        r#"{"suggestions":["/users/42"]}"#
+    );
+
+    let response = server.perform(Request::get("/admin/post").header(ACCEPT, "text/plain"))?;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(
+        response.headers()[CONTENT_TYPE],
+        "text/plain; charset=utf-8"
+    );
+    assert_eq!(
+        response.body().to_utf8()?,
+        "Not Found\n\nDid you mean:\n  /admin/posts\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn garbage_path() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app(fallback::suggestions(3))?)?;
+
+    let response = server.perform("/qwertyuiop/zzz")?;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert!(!response.headers().contains_key(LINK));
+    assert_eq!(response.body().to_utf8()?, r#"{"suggestions":[]}"#);
+
+    Ok(())
+}
+
+#[test]
+fn comparisons_are_limited_to_inferred_scope() -> tsukuyomi_server::Result<()> {
+    let compared = Arc::new(AtomicUsize::new(0));
+    let suggestions = fallback::suggestions(3).comparator({
+        let compared = compared.clone();
+        move |path, pattern, max_distance| {
+            compared.fetch_add(1, Ordering::SeqCst);
+            fallback::distance(path, pattern, max_distance)
+        }
+    });
+    let mut server = tsukuyomi_server::test::server(app(suggestions)?)?;
+
+    // compared only with the patterns in the root scope.
+    let _ = server.perform("/user/42")?;
+    assert_eq!(compared.swap(0, Ordering::SeqCst), 3);
+
+    // compared only with the patterns under `/admin`.
+    let _ = server.perform("/admin/setting")?;
+    assert_eq!(compared.swap(0, Ordering::SeqCst), 4);
+
+    Ok(())
+}
+
+fn method_not_allowed_app() -> tsukuyomi::app::Result<App> {
+    App::create(
+        path!("/items") //
+            .to(endpoint::allow_only("GET, PUT")?.reply("items")),
+    )
+}
+
+#[test]
+fn method_not_allowed_json() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(method_not_allowed_app()?)?;
+
+    let response = server.perform(Request::post("/items").header(ACCEPT, "application/json"))?;
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(response.headers()[ALLOW], "GET, PUT");
+    assert_eq!(response.headers()[CONTENT_TYPE], "application/json");
+    let body: serde_json::Value = serde_json::from_str(&*response.body().to_utf8()?)?;
+    assert_eq!(
+        body,
+        serde_json::json!({
+            "error": "method_not_allowed",
+            "allowed": ["GET", "PUT"],
+        })
+    );
+
+    Ok(())
+}
+
+#[test]
+fn method_not_allowed_plain_text() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(method_not_allowed_app()?)?;
+
+    let response = server.perform(Request::post("/items"))?;
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(response.headers()[ALLOW], "GET, PUT");
+    assert_eq!(
+        response.headers()[CONTENT_TYPE],
+        "text/plain; charset=utf-8"
+    );
+    assert_eq!(
+        response.body().to_utf8()?,
+        "Method Not Allowed (allowed: GET, PUT)\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn custom_method_not_allowed() -> tsukuyomi_server::Result<()> {
+    let app = method_not_allowed_app()?.with_method_not_allowed(|request, allowed_methods| {
+        let mut response = fallback::method_not_allowed(request, allowed_methods);
+        response
+            .headers_mut()
+            .insert("x-custom", "1".parse().unwrap());
+        response
+    });
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(Request::delete("/items"))?;
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(response.headers()["x-custom"], "1");
+
+    Ok(())
+}
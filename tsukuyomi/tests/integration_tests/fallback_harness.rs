@@ -0,0 +1,116 @@
+use {
+    http::{
+        header::{ACCEPT, ALLOW, CONTENT_TYPE, LINK, LOCATION},
+        Method, Request, Response, StatusCode,
+    },
+    tsukuyomi::{
+        config::prelude::*,
+        extractor, fallback,
+        output::ResponseBody,
+        test::{self, FallbackKind},
+        util::Never,
+    },
+};
+
+fn to_utf8(response: Response<ResponseBody>) -> String {
+    let body = test::read_body(response.into_body()).expect("failed to read the body");
+    String::from_utf8(body.to_vec()).expect("the body is not UTF-8")
+}
+
+#[test]
+fn default_not_found() {
+    let cx = test::fallback_context()
+        .request(Request::get("/missing").body(()).unwrap())
+        .not_found(vec![test::resource("/users", vec![Method::GET])]);
+    match cx.kind() {
+        FallbackKind::NotFound(candidates) => assert_eq!(candidates[0].uri(), "/users"),
+        kind => panic!("unexpected kind: {:?}", kind),
+    }
+
+    let response = cx.default_response();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(to_utf8(response), "");
+}
+
+#[test]
+fn default_method_not_allowed() {
+    let cx = test::fallback_context()
+        .request(
+            Request::post("/items")
+                .header(ACCEPT, "application/json")
+                .body(())
+                .unwrap(),
+        )
+        .found_resource(test::resource("/items", vec![Method::GET, Method::PUT]));
+
+    let response = cx.default_response();
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(response.headers()[ALLOW], "GET, PUT");
+    assert_eq!(response.headers()[CONTENT_TYPE], "application/json");
+    let body: serde_json::Value = serde_json::from_str(&to_utf8(response)).unwrap();
+    assert_eq!(
+        body,
+        serde_json::json!({
+            "error": "method_not_allowed",
+            "allowed": ["GET", "PUT"],
+        })
+    );
+
+    let response = cx.render_method_not_allowed(|request, allowed_methods| {
+        let mut response = fallback::method_not_allowed(request, allowed_methods);
+        response
+            .headers_mut()
+            .insert("x-custom", "1".parse().unwrap());
+        response
+    });
+    assert_eq!(response.headers()["x-custom"], "1");
+}
+
+#[test]
+fn suggestions_without_app() {
+    let cx = test::fallback_context()
+        .request(Request::get("/user/42").body(()).unwrap())
+        .not_found(vec![
+            test::resource("/users", vec![Method::GET]),
+            test::resource("/users/:id", vec![Method::GET]),
+            test::resource("/posts", vec![Method::GET]),
+        ]);
+
+    let response = cx.call(fallback::suggestions(3)).unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(response.headers()[LINK], "</users/42>; rel=\"alternate\"");
+    assert_eq!(response.headers()["vary"], "accept");
+}
+
+#[test]
+fn custom_fallback_without_app() {
+    // a fallback that removes the trailing slash from the path.
+    let fallback = endpoint::any()
+        .extract(extractor::ready(|input| {
+            Ok::<_, Never>((input.request.uri().path().to_owned(),))
+        }))
+        .call(|path: String| {
+            let mut response = Response::new(String::new());
+            if path.len() > 1 && path.ends_with('/') {
+                *response.status_mut() = StatusCode::MOVED_PERMANENTLY;
+                response
+                    .headers_mut()
+                    .insert(LOCATION, path.trim_end_matches('/').parse().unwrap());
+            } else {
+                *response.status_mut() = StatusCode::NOT_FOUND;
+                *response.body_mut() = format!("no route for {}", path);
+            }
+            response
+        });
+    let fallback = std::sync::Arc::new(fallback);
+
+    let cx = test::fallback_context().request(Request::get("/users/").body(()).unwrap());
+    let response = cx.call(fallback.clone()).unwrap();
+    assert_eq!(response.status(), StatusCode::MOVED_PERMANENTLY);
+    assert_eq!(response.headers()[LOCATION], "/users");
+
+    let cx = test::fallback_context().request(Request::get("/missing").body(()).unwrap());
+    let response = cx.call(fallback).unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(to_utf8(response), "no route for /missing");
+}
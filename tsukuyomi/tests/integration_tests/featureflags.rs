@@ -0,0 +1,160 @@
+use {
+    http::Request,
+    std::collections::HashMap,
+    tsukuyomi::{
+        config::prelude::*,
+        dynamic::DynamicConfig,
+        extractor,
+        featureflags::{self as flags, Decisions, Flags, KeySource, Strategy, Variant},
+        input::localmap::LocalData,
+        App,
+    },
+};
+
+fn app(flags: Flags) -> tsukuyomi::app::Result<App> {
+    App::create(chain![
+        tsukuyomi::config::state(flags),
+        path!("/checkout") //
+            .to(endpoint::get()
+                .extract(flags::enabled("new-checkout"))
+                .call(|enabled: bool| if enabled { "new" } else { "old" })),
+        path!("/experiment") //
+            .to(endpoint::get()
+                .extract(flags::enabled("new-checkout"))
+                .extract(flags::variant("exp-42"))
+                .extract(extractor::ready(|input| {
+                    let decisions = Decisions::get(input.locals)
+                        .map(ToString::to_string)
+                        .unwrap_or_default();
+                    Ok::<_, tsukuyomi::Error>((decisions,))
+                }))
+                .call(|_: bool, _: String, decisions: String| decisions)),
+    ])
+}
+
+fn perform(
+    server: &mut tsukuyomi_server::test::Server<App>,
+    path: &str,
+    user: &str,
+) -> tsukuyomi_server::Result<String> {
+    let response = server.perform(Request::get(path).header("x-user-id", user))?;
+    assert_eq!(response.status(), 200);
+    Ok(response.body().to_utf8()?.into_owned())
+}
+
+#[test]
+fn percentage_is_deterministic() -> tsukuyomi_server::Result<()> {
+    let flags = || {
+        Flags::new()
+            .key(KeySource::header("x-user-id"))
+            .flag("new-checkout", Strategy::Percentage { percent: 50.0 })
+    };
+
+    // The decisions are fixed for the keys, across the instances of the application.
+    let expected = vec![
+        ("user-1", "old"),
+        ("user-2", "new"),
+        ("user-3", "new"),
+        ("user-4", "new"),
+        ("user-5", "old"),
+        ("user-6", "new"),
+    ];
+    for _ in 0..2 {
+        let mut server = tsukuyomi_server::test::server(app(flags())?)?;
+        for &(user, decision) in &expected {
+            assert_eq!(perform(&mut server, "/checkout", user)?, decision);
+            assert_eq!(perform(&mut server, "/checkout", user)?, decision);
+        }
+    }
+
+    // The requests without the key are not included.
+    let mut server = tsukuyomi_server::test::server(app(flags())?)?;
+    let response = server.perform("/checkout")?;
+    assert_eq!(response.body().to_utf8()?, "old");
+
+    Ok(())
+}
+
+#[test]
+fn allow_list_and_hot_swap() -> tsukuyomi_server::Result<()> {
+    let strategy = DynamicConfig::new(Strategy::AllowList {
+        keys: vec!["alice".into(), "bob".into()],
+    });
+    let flags = Flags::new()
+        .key(KeySource::header("x-user-id"))
+        .dynamic_flag("new-checkout", strategy.clone());
+    let mut server = tsukuyomi_server::test::server(app(flags)?)?;
+
+    assert_eq!(perform(&mut server, "/checkout", "alice")?, "new");
+    assert_eq!(perform(&mut server, "/checkout", "bob")?, "new");
+    assert_eq!(perform(&mut server, "/checkout", "carol")?, "old");
+
+    strategy.set(serde_json::from_str(r#"{"strategy": "on"}"#).unwrap());
+    assert_eq!(perform(&mut server, "/checkout", "carol")?, "new");
+
+    strategy.set(Strategy::Off);
+    assert_eq!(perform(&mut server, "/checkout", "alice")?, "old");
+
+    Ok(())
+}
+
+#[test]
+fn variant_distribution() {
+    let strategy = Strategy::Variants {
+        variants: vec![
+            Variant::new("A", 50),
+            Variant::new("B", 30),
+            Variant::new("C", 20),
+        ],
+    };
+
+    let mut counts = HashMap::new();
+    for i in 0..10_000 {
+        let key = format!("user-{}", i);
+        *counts
+            .entry(strategy.decide("exp-42", Some(&key)).to_owned())
+            .or_insert(0) += 1;
+    }
+    for &(name, expected) in &[("A", 5000), ("B", 3000), ("C", 2000)] {
+        let count = counts[name];
+        assert!(
+            (count as i32 - expected).abs() < 300,
+            "variant {}: {} (expected {})",
+            name,
+            count,
+            expected
+        );
+    }
+
+    // The first variant is assigned to the requests without the key.
+    assert_eq!(strategy.decide("exp-42", None), "A");
+}
+
+#[test]
+fn decisions_are_recorded() -> tsukuyomi_server::Result<()> {
+    let flags = Flags::new()
+        .key(KeySource::header("x-user-id"))
+        .flag("new-checkout", Strategy::On)
+        .flag(
+            "exp-42",
+            Strategy::Variants {
+                variants: vec![Variant::new("B", 1)],
+            },
+        );
+    let mut server = tsukuyomi_server::test::server(app(flags)?)?;
+
+    assert_eq!(
+        perform(&mut server, "/experiment", "alice")?,
+        "new-checkout=on,exp-42=B"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn unknown_flag() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app(Flags::new())?)?;
+    let response = server.perform("/checkout")?;
+    assert_eq!(response.status(), 500);
+    Ok(())
+}
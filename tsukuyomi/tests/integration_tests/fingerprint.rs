@@ -0,0 +1,73 @@
+use tsukuyomi::{
+    app::config::ThreadSafe,
+    config::{prelude::*, Config},
+    modifiers, App,
+};
+
+fn posts() -> impl Config<(), ThreadSafe> {
+    mount("/posts").with(chain![
+        path!("/") //
+            .to(endpoint::get().reply("list")),
+        path!("/:id")
+            .to(endpoint::allow_only("GET, PUT")
+                .unwrap()
+                .call(|id: u32| format!("post {}", id)))
+            .tag("posts")
+            .modify(modifiers::content_sniff()),
+    ])
+}
+
+fn users() -> impl Config<(), ThreadSafe> {
+    mount("/users").with(
+        path!("/:id") //
+            .to(endpoint::get().call(|id: u32| format!("user {}", id))),
+    )
+}
+
+#[test]
+fn manifest() -> tsukuyomi::app::Result<()> {
+    let app = App::create(chain![posts(), users()])?;
+    assert_eq!(
+        app.manifest(),
+        "/posts GET scopes=/,/posts\n\
+         /posts/:id GET,PUT scopes=/,/posts tags=posts\n\
+         /users/:id GET scopes=/,/users\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn identical_configs() -> tsukuyomi::app::Result<()> {
+    let app1 = App::create(chain![posts(), users()])?;
+    let app2 = App::create(chain![posts(), users()])?;
+    assert_eq!(app1.fingerprint(), app2.fingerprint());
+    Ok(())
+}
+
+#[test]
+fn adding_route_changes_fingerprint() -> tsukuyomi::app::Result<()> {
+    let app1 = App::create(chain![posts(), users()])?;
+    let app2 = App::create(chain![
+        posts(),
+        users(),
+        path!("/health").to(endpoint::get().reply("ok")),
+    ])?;
+    assert_ne!(app1.fingerprint(), app2.fingerprint());
+    Ok(())
+}
+
+#[test]
+fn reordering_sibling_scopes() -> tsukuyomi::app::Result<()> {
+    let app1 = App::create(chain![posts(), users()])?;
+    let app2 = App::create(chain![users(), posts()])?;
+    assert_eq!(app1.manifest(), app2.manifest());
+    assert_eq!(app1.fingerprint(), app2.fingerprint());
+    Ok(())
+}
+
+#[test]
+fn snapshot() -> tsukuyomi::app::Result<()> {
+    let app = App::create(chain![posts(), users()])?;
+    tsukuyomi::test::assert_manifest_snapshot!(app, "tests/snapshots/routes.snap");
+    Ok(())
+}
@@ -0,0 +1,166 @@
+use {
+    http::Request,
+    std::net::SocketAddr,
+    tsukuyomi::{
+        config::prelude::*,
+        extractor::{
+            self,
+            forwarded::{ForwardedInfo, TrustedProxies},
+        },
+        input::ConnectionInfo,
+        App,
+    },
+};
+
+fn app() -> tsukuyomi::app::Result<App> {
+    App::create(chain![
+        TrustedProxies::new()
+            .trust("10.0.0.1".parse().unwrap())
+            .trust_network("192.168.0.0".parse().unwrap(), 16),
+        path!("/") //
+            .to(endpoint::get()
+                .extract(extractor::forwarded())
+                .call(|info: ForwardedInfo| {
+                    format!(
+                        "{} {} {:?} {:?}",
+                        info.scheme,
+                        info.host.unwrap_or_default(),
+                        info.client_ip,
+                        info.proxies,
+                    )
+                })),
+        mount("/untrusted").with(chain![
+            TrustedProxies::new(),
+            path!("/") //
+                .to(endpoint::get()
+                    .extract(extractor::forwarded())
+                    .call(|info: ForwardedInfo| format!("{:?}", info.client_ip))),
+        ]),
+    ])
+}
+
+fn request(uri: &str, peer: &str) -> http::request::Builder {
+    let mut request = Request::get(uri);
+    request
+        .header("host", "internal:8080")
+        .extension(ConnectionInfo::new(peer.parse::<SocketAddr>().unwrap()));
+    request
+}
+
+#[test]
+fn trusted_proxy() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+
+    let response = server.perform(
+        request("/", "10.0.0.1:4000") //
+            .header(
+                "forwarded",
+                r#"for="[2001:db8::1]:4711";proto=HTTPS;host=example.com"#,
+            ),
+    )?;
+    assert_eq!(
+        response.body().to_utf8()?,
+        "https example.com Some(2001:db8::1) [10.0.0.1]"
+    );
+
+    let response = server.perform(
+        request("/", "10.0.0.1:4000")
+            .header("x-forwarded-for", "203.0.113.7")
+            .header("x-forwarded-proto", "https")
+            .header("x-forwarded-host", "example.com"),
+    )?;
+    assert_eq!(
+        response.body().to_utf8()?,
+        "https example.com Some(203.0.113.7) [10.0.0.1]"
+    );
+
+    let response = server.perform(request("/", "10.0.0.1:4000"))?;
+    assert_eq!(
+        response.body().to_utf8()?,
+        "http internal:8080 Some(10.0.0.1) []"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn untrusted_peer() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+
+    let response = server.perform(
+        request("/", "198.51.100.2:4000")
+            .header("forwarded", "for=203.0.113.7;proto=https;host=example.com")
+            .header("x-forwarded-for", "203.0.113.7"),
+    )?;
+    assert_eq!(
+        response.body().to_utf8()?,
+        "http internal:8080 Some(198.51.100.2) []"
+    );
+
+    let response = server.perform(
+        request("/untrusted", "10.0.0.1:4000") //
+            .header("x-forwarded-for", "203.0.113.7"),
+    )?;
+    assert_eq!(response.body().to_utf8()?, "Some(10.0.0.1)");
+
+    let response = server.perform(
+        Request::get("/") //
+            .header("x-forwarded-for", "203.0.113.7"),
+    )?;
+    assert_eq!(response.body().to_utf8()?, "http  None []");
+
+    Ok(())
+}
+
+#[test]
+fn multiple_hops() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+
+    // The leftmost address is spoofed by the client.
+    let response = server.perform(
+        request("/", "10.0.0.1:4000") //
+            .header(
+                "x-forwarded-for",
+                "1.2.3.4, 203.0.113.7, 192.168.1.10, 192.168.2.20",
+            ),
+    )?;
+    assert_eq!(
+        response.body().to_utf8()?,
+        "http internal:8080 Some(203.0.113.7) [192.168.1.10, 192.168.2.20, 10.0.0.1]"
+    );
+
+    let response = server.perform(
+        request("/", "10.0.0.1:4000")
+            .header(
+                "forwarded",
+                "for=1.2.3.4;proto=http, for=203.0.113.7;proto=https",
+            )
+            .header("forwarded", "for=192.168.1.10:8080;host=example.com"),
+    )?;
+    assert_eq!(
+        response.body().to_utf8()?,
+        "https example.com Some(203.0.113.7) [192.168.1.10, 10.0.0.1]"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn malformed_headers() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+
+    for &(name, value) in &[
+        ("forwarded", "for=203.0.113.7;proto"),
+        ("forwarded", "for=not-an-address"),
+        ("x-forwarded-for", "203.0.113.7, garbage"),
+    ] {
+        let response = server.perform(request("/", "10.0.0.1:4000").header(name, value))?;
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            response.body().to_utf8()?,
+            "http internal:8080 Some(10.0.0.1) []"
+        );
+    }
+
+    Ok(())
+}
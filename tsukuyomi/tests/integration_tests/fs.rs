@@ -1,7 +1,15 @@
-use tsukuyomi::{
-    config::prelude::*, //
-    fs::{NamedFile, Staticfiles},
-    App,
+use {
+    http::{header, Request},
+    std::{
+        fs,
+        path::PathBuf,
+        sync::atomic::{AtomicUsize, Ordering},
+    },
+    tsukuyomi::{
+        config::prelude::*, //
+        fs::{NamedFile, OpenConfig, Staticfiles},
+        App,
+    },
 };
 
 #[test]
@@ -20,3 +28,344 @@ fn compiletest() -> tsukuyomi::app::Result<()> {
 fn compiletest_staticfiles() -> tsukuyomi::app::Result<()> {
     App::create(Staticfiles::new("./public")).map(drop)
 }
+
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new() -> Self {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "tsukuyomi-staticfiles-{}-{}",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::SeqCst)
+        ));
+        fs::create_dir_all(path.join("sub")).unwrap();
+        fs::write(path.join("index.html"), "index").unwrap();
+        TempDir(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+#[test]
+fn legacy_mode_does_not_serve_new_files() -> tsukuyomi_server::Result<()> {
+    let dir = TempDir::new();
+    let app = App::create(Staticfiles::new(&dir.0))?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/index.html")?;
+    assert_eq!(response.status(), 200);
+
+    fs::write(dir.0.join("new.txt"), "new").unwrap();
+    let response = server.perform("/new.txt")?;
+    assert_eq!(response.status(), 404);
+
+    Ok(())
+}
+
+#[test]
+fn dynamic_mode_serves_new_files() -> tsukuyomi_server::Result<()> {
+    let dir = TempDir::new();
+    let app = App::create(Staticfiles::new(&dir.0).dynamic(true))?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    fs::write(dir.0.join("new.txt"), "new").unwrap();
+    fs::write(dir.0.join("sub/nested.txt"), "nested").unwrap();
+
+    let response = server.perform("/new.txt")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "new");
+
+    let response = server.perform("/sub/nested.txt")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "nested");
+
+    let response = server.perform("/missing.txt")?;
+    assert_eq!(response.status(), 404);
+
+    Ok(())
+}
+
+#[test]
+fn dynamic_mode_rejects_traversal() -> tsukuyomi_server::Result<()> {
+    let dir = TempDir::new();
+    let app = App::create(Staticfiles::new(dir.0.join("sub")).dynamic(true))?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    for path in &[
+        "/../index.html",
+        "/%2E%2E/index.html",
+        "/a/%2e%2e/%2e%2e/index.html",
+    ] {
+        let response = server.perform(*path)?;
+        assert_eq!(response.status(), 404, "path = {}", path);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn mount_at_prefix() -> tsukuyomi_server::Result<()> {
+    let assets = TempDir::new();
+    let media = TempDir::new();
+    fs::write(assets.0.join("app.js"), "app").unwrap();
+    fs::write(media.0.join("sub/photo.txt"), "photo").unwrap();
+
+    let app = App::create(chain![
+        Staticfiles::new(&assets.0).mount_at("/assets"),
+        Staticfiles::new(&media.0).mount_at("/media").dynamic(true),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/assets/app.js")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "app");
+
+    let response = server.perform("/media/sub/photo.txt")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "photo");
+
+    assert_eq!(server.perform("/assets/index.html")?.status(), 200);
+    assert_eq!(server.perform("/app.js")?.status(), 404);
+
+    Ok(())
+}
+
+#[test]
+fn alias() -> tsukuyomi_server::Result<()> {
+    let dir = TempDir::new();
+    fs::write(dir.0.join("index.prod.html"), "production").unwrap();
+
+    let app = App::create(
+        Staticfiles::new(&dir.0)
+            .mount_at("/app")
+            .alias("/index.html", "index.prod.html")
+            .alias("/", "index.prod.html"),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/app/index.html")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "production");
+
+    let response = server.perform("/app")?;
+    assert_eq!(response.body().to_utf8()?, "production");
+
+    let response = server.perform("/app/index.prod.html")?;
+    assert_eq!(response.status(), 200);
+
+    Ok(())
+}
+
+#[test]
+fn conflicting_routes() {
+    let dir = TempDir::new();
+    fs::write(dir.0.join("a.html"), "a").unwrap();
+    fs::write(dir.0.join("b.html"), "b").unwrap();
+
+    let result = App::create(
+        Staticfiles::new(&dir.0)
+            .alias("/page.html", "a.html")
+            .alias("/page.html", "b.html"),
+    );
+    let err = result.err().expect("should be an error");
+    assert!(
+        err.to_string().contains("conflicting aliases"),
+        "unexpected error: {}",
+        err
+    );
+
+    // the same entries without distinct prefixes.
+    assert!(App::create(chain![Staticfiles::new(&dir.0), Staticfiles::new(&dir.0)]).is_err());
+    assert!(App::create(chain![
+        Staticfiles::new(&dir.0).mount_at("/v1"),
+        Staticfiles::new(&dir.0).mount_at("/v2"),
+    ])
+    .is_ok());
+}
+
+#[cfg(feature = "compression")]
+#[test]
+fn precompressed_sibling_file() -> tsukuyomi_server::Result<()> {
+    let dir = TempDir::new();
+    fs::write(dir.0.join("app.css"), "original").unwrap();
+    fs::write(dir.0.join("app.css.br"), "brotli").unwrap();
+    fs::write(dir.0.join("app.css.gz"), "gzip").unwrap();
+
+    let app = App::create(
+        Staticfiles::new(&dir.0)
+            .open_config(OpenConfig {
+                precompressed: true,
+                ..Default::default()
+            })
+            .modify(tsukuyomi::modifiers::compression()),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(
+        Request::get("/app.css").header(header::ACCEPT_ENCODING, "gzip;q=0.5, br;q=1.0"),
+    )?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.headers().get(header::CONTENT_ENCODING).unwrap(),
+        "br"
+    );
+    assert_eq!(
+        response.headers().get(header::VARY).unwrap(),
+        "accept-encoding"
+    );
+    assert_eq!(
+        response.headers().get(header::CONTENT_TYPE).unwrap(),
+        "text/css"
+    );
+    assert_eq!(response.body().to_utf8()?, "brotli");
+
+    let response = server
+        .perform(Request::get("/app.css").header(header::ACCEPT_ENCODING, "gzip, br;q=0.5"))?;
+    assert_eq!(
+        response.headers().get(header::CONTENT_ENCODING).unwrap(),
+        "gzip"
+    );
+    assert_eq!(response.body().to_utf8()?, "gzip");
+
+    let response = server.perform(Request::get("/app.css"))?;
+    assert!(!response.headers().contains_key(header::CONTENT_ENCODING));
+    assert_eq!(
+        response.headers().get(header::VARY).unwrap(),
+        "accept-encoding"
+    );
+    assert_eq!(response.body().to_utf8()?, "original");
+
+    Ok(())
+}
+
+#[cfg(feature = "notify")]
+#[test]
+fn watch_mode_serves_new_files() -> tsukuyomi_server::Result<()> {
+    let dir = TempDir::new();
+    let app = App::create(Staticfiles::new(&dir.0).watch(true))?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/index.html")?;
+    assert_eq!(response.status(), 200);
+
+    let response = server.perform("/new.txt")?;
+    assert_eq!(response.status(), 404);
+
+    fs::write(dir.0.join("new.txt"), "new").unwrap();
+
+    // wait for the filesystem event to be delivered.
+    let mut status = None;
+    for _ in 0..50 {
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        let response = server.perform("/new.txt")?;
+        status = Some(response.status());
+        if response.status() == 200 {
+            break;
+        }
+    }
+    assert_eq!(status.map(|s| s.as_u16()), Some(200));
+
+    Ok(())
+}
+
+#[cfg(feature = "notify")]
+#[test]
+fn watch_mode_refreshes_metadata() -> tsukuyomi_server::Result<()> {
+    let dir = TempDir::new();
+    let app = App::create(Staticfiles::new(&dir.0).watch(true))?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/index.html")?;
+    assert_eq!(response.status(), 200);
+    let etag = response.headers().get(header::ETAG).unwrap().clone();
+
+    fs::write(dir.0.join("index.html"), "updated index").unwrap();
+
+    // wait for the filesystem event to be delivered.
+    let mut updated = None;
+    for _ in 0..50 {
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        let response = server.perform("/index.html")?;
+        if response.headers().get(header::ETAG) != Some(&etag) {
+            updated = Some(response);
+            break;
+        }
+    }
+    let response = updated.expect("the metadata should be refreshed");
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "updated index");
+
+    Ok(())
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn mmap_large_file() -> tsukuyomi_server::Result<()> {
+    let dir = TempDir::new();
+    let content: Vec<u8> = (0..3 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+    fs::write(dir.0.join("tiles.bin"), &content).unwrap();
+
+    let app = App::create(
+        Staticfiles::new(&dir.0) //
+            .open_config(OpenConfig::default().mmap(true)),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/tiles.bin")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.headers().get(header::ACCEPT_RANGES).unwrap(),
+        "bytes"
+    );
+    assert_eq!(
+        response.headers().get(header::CONTENT_LENGTH).unwrap(),
+        "3145728"
+    );
+    assert!(response.body().to_bytes() == content);
+
+    let response = server
+        .perform(Request::get("/tiles.bin").header(header::RANGE, "bytes=1048576-1049599"))?;
+    assert_eq!(response.status(), 206);
+    assert_eq!(
+        response.headers().get(header::CONTENT_RANGE).unwrap(),
+        "bytes 1048576-1049599/3145728"
+    );
+    assert!(*response.body().to_bytes() == content[1_048_576..1_049_600]);
+
+    let response =
+        server.perform(Request::get("/tiles.bin").header(header::RANGE, "bytes=4000000-"))?;
+    assert_eq!(response.status(), 416);
+    assert_eq!(
+        response.headers().get(header::CONTENT_RANGE).unwrap(),
+        "bytes */3145728"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn mmap_falls_back_below_threshold() -> tsukuyomi_server::Result<()> {
+    let dir = TempDir::new();
+    let content: Vec<u8> = (0..4096).map(|i| (i % 251) as u8).collect();
+    fs::write(dir.0.join("small.bin"), &content).unwrap();
+
+    let app = App::create(
+        Staticfiles::new(&dir.0) //
+            .open_config(OpenConfig::default().mmap(true).mmap_threshold(8192)),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    // the file is read in the normal way, which does not support `Range`.
+    let response =
+        server.perform(Request::get("/small.bin").header(header::RANGE, "bytes=0-99"))?;
+    assert_eq!(response.status(), 200);
+    assert!(!response.headers().contains_key(header::ACCEPT_RANGES));
+    assert!(response.body().to_bytes() == content);
+
+    Ok(())
+}
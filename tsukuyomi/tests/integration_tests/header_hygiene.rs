@@ -0,0 +1,136 @@
+use {
+    http::{header, Request, Response},
+    std::time::{Duration, UNIX_EPOCH},
+    tsukuyomi::{
+        app::{HeaderHygiene, HygieneMetrics, Strictness},
+        config::prelude::*,
+        rt::MockClock,
+        App,
+    },
+};
+
+fn app(hygiene: HeaderHygiene) -> tsukuyomi::app::Result<App> {
+    let app = App::create(chain![
+        path!("/dates") //
+            .to(endpoint::call(|| {
+                Response::builder()
+                    .header(header::DATE, "Sat, 01 Jan 2000 00:00:00 GMT")
+                    .header(header::DATE, "Sun, 02 Jan 2000 00:00:00 GMT")
+                    .body("dates")
+                    .unwrap()
+            })),
+        path!("/hop-by-hop") //
+            .to(endpoint::call(|| {
+                Response::builder()
+                    .header(header::CONNECTION, "close")
+                    .header("keep-alive", "timeout=5")
+                    .body("hop-by-hop")
+                    .unwrap()
+            })),
+        path!("/content-length") //
+            .to(endpoint::call(|| {
+                Response::builder()
+                    .header(header::CONTENT_LENGTH, "100")
+                    .body("short")
+                    .unwrap()
+            })),
+        path!("/content-type") //
+            .to(endpoint::call(|| {
+                Response::builder()
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .header(header::CONTENT_TYPE, "text/plain")
+                    .body("{}")
+                    .unwrap()
+            })),
+    ])?;
+    Ok(app
+        .with_clock(MockClock::starting_at(
+            UNIX_EPOCH + Duration::from_secs(1_514_764_800),
+        ))
+        .header_hygiene(hygiene))
+}
+
+#[test]
+fn duplicated_dates_are_replaced() -> tsukuyomi_server::Result<()> {
+    let hygiene = HeaderHygiene::new();
+    let mut server = tsukuyomi_server::test::server(app(hygiene.clone())?)?;
+
+    let response = server.perform(Request::get("/dates"))?;
+    let dates: Vec<_> = response.headers().get_all(header::DATE).iter().collect();
+    assert_eq!(dates, vec!["Mon, 01 Jan 2018 00:00:00 GMT"]);
+
+    let response = server.perform(Request::get("/content-type"))?;
+    assert_eq!(
+        response.headers().get(header::DATE).unwrap(),
+        "Mon, 01 Jan 2018 00:00:00 GMT"
+    );
+
+    assert_eq!(
+        hygiene.metrics(),
+        HygieneMetrics {
+            date: 1,
+            content_type: 1,
+            ..Default::default()
+        }
+    );
+
+    Ok(())
+}
+
+#[test]
+fn hop_by_hop_headers_are_removed() -> tsukuyomi_server::Result<()> {
+    let hygiene = HeaderHygiene::new();
+    let mut server = tsukuyomi_server::test::server(app(hygiene.clone())?)?;
+
+    let response = server.perform(Request::get("/hop-by-hop"))?;
+    assert!(!response.headers().contains_key(header::CONNECTION));
+    assert!(!response.headers().contains_key("keep-alive"));
+    assert_eq!(response.body().to_utf8()?, "hop-by-hop");
+    assert_eq!(hygiene.metrics().hop_by_hop, 2);
+
+    Ok(())
+}
+
+#[test]
+fn duplicated_content_type_keeps_first() -> tsukuyomi_server::Result<()> {
+    let hygiene = HeaderHygiene::new();
+    let mut server = tsukuyomi_server::test::server(app(hygiene.clone())?)?;
+
+    let response = server.perform(Request::get("/content-type"))?;
+    let content_types: Vec<_> = response
+        .headers()
+        .get_all(header::CONTENT_TYPE)
+        .iter()
+        .collect();
+    assert_eq!(content_types, vec!["application/json"]);
+    assert_eq!(hygiene.metrics().content_type, 1);
+
+    Ok(())
+}
+
+#[test]
+fn content_length_mismatch_is_replaced() -> tsukuyomi_server::Result<()> {
+    let hygiene = HeaderHygiene::new().strictness(Strictness::Strict);
+    let mut server = tsukuyomi_server::test::server(app(hygiene.clone())?)?;
+
+    let response = server.perform(Request::get("/content-length"))?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers().get(header::CONTENT_LENGTH).unwrap(), "5");
+    assert_eq!(response.body().to_utf8()?, "short");
+    assert_eq!(hygiene.metrics().content_length, 1);
+
+    Ok(())
+}
+
+#[test]
+fn content_length_mismatch_in_paranoid_mode() -> tsukuyomi_server::Result<()> {
+    let hygiene = HeaderHygiene::new().strictness(Strictness::Paranoid);
+    let mut server = tsukuyomi_server::test::server(app(hygiene.clone())?)?;
+
+    let response = server.perform(Request::get("/content-length"))?;
+    assert_eq!(response.status(), 500);
+    assert!(response.headers().contains_key(header::DATE));
+    assert_eq!(hygiene.metrics().content_length, 1);
+
+    Ok(())
+}
@@ -0,0 +1,109 @@
+use {
+    http::{header::HeaderName, Response},
+    serde::Deserialize,
+    tsukuyomi::{
+        config::prelude::*,
+        extractor,
+        output::{self, redirect},
+        App,
+    },
+    tsukuyomi_server::test::ResponseExt,
+    url::percent_encoding::percent_decode,
+};
+
+#[derive(Debug, Deserialize)]
+struct Query {
+    value: String,
+}
+
+const INJECTED: &str = "%0D%0ASet-Cookie:%20session=evil%0D%0A%0D%0A%3Cscript%3E";
+
+fn assert_no_crlf<T>(response: &Response<T>) {
+    assert!(!response.headers().contains_key("set-cookie"));
+    for (name, value) in response.headers() {
+        assert!(
+            !value.as_bytes().iter().any(|&b| b == b'\r' || b == b'\n'),
+            "the header field `{}' contains CR or LF: {:?}",
+            name,
+            value
+        );
+    }
+}
+
+#[test]
+fn redirect_query() -> tsukuyomi_server::Result<()> {
+    let app = App::create(chain![
+        path!("/login") //
+            .to(endpoint::get()
+                .extract(extractor::query())
+                .call(|query: Query| redirect::found("/auth#top").query("next", &query.value))),
+        path!("/raw") //
+            .to(endpoint::get()
+                .extract(extractor::query())
+                .call(|query: Query| redirect::found(format!("/auth?next={}", query.value)))),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(format!("/login?value={}", INJECTED))?;
+    assert_eq!(response.status(), 302);
+    assert_no_crlf(&response);
+    assert_eq!(
+        response.header("location")?,
+        "/auth?next=%0D%0ASet-Cookie%3A+session%3Devil%0D%0A%0D%0A%3Cscript%3E#top"
+    );
+
+    let response = server.perform(format!("/raw?value={}", INJECTED))?;
+    assert_eq!(response.status(), 302);
+    assert_no_crlf(&response);
+    assert_eq!(
+        response.header("location")?,
+        "/auth?next=%0D%0ASet-Cookie:%20session=evil%0D%0A%0D%0A<script>"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn attachment_filename() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/download") //
+            .to(endpoint::get()
+                .extract(extractor::query())
+                .call(|query: Query| output::attachment(&query.value, "data"))),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(format!("/download?value=report.csv{}", INJECTED))?;
+    assert_eq!(response.status(), 200);
+    assert_no_crlf(&response);
+    assert_eq!(
+        response.header("content-disposition")?,
+        "attachment; filename=\"report.csvSet-Cookie: session=evil<script>\""
+    );
+
+    Ok(())
+}
+
+#[test]
+fn staged_header() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/note") //
+            .to(endpoint::get()
+                .extract(extractor::ready(|input| {
+                    let query = input.request.uri().query().unwrap_or("");
+                    let value = percent_decode(query.as_bytes()).decode_utf8_lossy();
+                    input
+                        .response()
+                        .append(HeaderName::from_static("x-note"), &*value)
+                }))
+                .reply("unreachable")),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(format!("/note?{}", INJECTED))?;
+    assert_eq!(response.status(), 500);
+    assert_no_crlf(&response);
+    assert!(!response.headers().contains_key("x-note"));
+
+    Ok(())
+}
@@ -0,0 +1,101 @@
+use {
+    http::{
+        header::{ACCEPT, CONTENT_TYPE, COOKIE},
+        Request, Response, StatusCode,
+    },
+    tsukuyomi::{
+        app::{HeaderLimitExceeded, HeaderLimits},
+        config::prelude::*,
+        App,
+    },
+};
+
+fn app(limits: HeaderLimits) -> tsukuyomi::app::Result<App> {
+    let app = App::create(chain![
+        path!("/") //
+            .to(endpoint::get().reply("index")),
+        path!("/internal/sso") //
+            .to(endpoint::get().reply("sso")),
+    ])?;
+    Ok(app.header_limits(limits))
+}
+
+fn with_headers(uri: &str, count: usize) -> http::request::Builder {
+    let mut request = Request::get(uri);
+    for i in 0..count {
+        request.header(format!("x-custom-{}", i).as_str(), "value");
+    }
+    request
+}
+
+#[test]
+fn too_many_headers_with_custom_renderer() -> tsukuyomi_server::Result<()> {
+    let limits = HeaderLimits::new()
+        .max_count(64)
+        .render(|_, exceeded: &HeaderLimitExceeded| {
+            let body = serde_json::json!({
+                "code": "HEADERS_TOO_MANY",
+                "limit": exceeded.limit(),
+            });
+            Response::builder()
+                .status(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE)
+                .header(CONTENT_TYPE, "application/json")
+                .body(body.to_string().into())
+                .unwrap()
+        });
+    let mut server = tsukuyomi_server::test::server(app(limits.clone())?)?;
+
+    let response = server.perform(with_headers("/", 10))?;
+    assert_eq!(response.status(), 200);
+
+    let response = server.perform(with_headers("/", 70))?;
+    assert_eq!(response.status(), 431);
+    assert_eq!(
+        response.body().to_utf8()?,
+        r#"{"code":"HEADERS_TOO_MANY","limit":64}"#
+    );
+
+    let response = server.perform(with_headers("/no-such-path", 70))?;
+    assert_eq!(response.status(), 431);
+
+    assert_eq!(limits.rejected(), 2);
+
+    Ok(())
+}
+
+#[test]
+fn large_cookie_negotiates_json() -> tsukuyomi_server::Result<()> {
+    let limits = HeaderLimits::new()
+        .max_size(COOKIE, 8192)
+        .exempt_prefixes(vec!["/internal/"]);
+    let mut server = tsukuyomi_server::test::server(app(limits)?)?;
+    let cookie = format!("session={}", "x".repeat(9000));
+
+    let response = server.perform(
+        Request::get("/")
+            .header(COOKIE, cookie.as_str())
+            .header(ACCEPT, "application/json"),
+    )?;
+    assert_eq!(response.status(), 431);
+    assert_eq!(
+        response.headers().get(CONTENT_TYPE).unwrap(),
+        "application/json"
+    );
+    let body: serde_json::Value = serde_json::from_str(&response.body().to_utf8()?)?;
+    assert_eq!(body["error"], "request_header_fields_too_large");
+    assert_eq!(body["header"], "cookie");
+    assert_eq!(body["limit"], 8192);
+
+    let response = server.perform(Request::get("/").header(COOKIE, cookie.as_str()))?;
+    assert_eq!(response.status(), 431);
+    assert!(response
+        .body()
+        .to_utf8()?
+        .starts_with("the header field `cookie'"));
+
+    let response = server.perform(Request::get("/internal/sso").header(COOKIE, cookie.as_str()))?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "sso");
+
+    Ok(())
+}
@@ -0,0 +1,127 @@
+use {
+    http::{
+        header::{CONTENT_LANGUAGE, CONTENT_TYPE},
+        Request, StatusCode,
+    },
+    tsukuyomi::{
+        config::prelude::*,
+        extractor::{self, body::RelatedLimits},
+        i18n::{self, Args, Locale},
+        input::multipart::RelatedParts,
+        App,
+    },
+    tsukuyomi_server::test::ResponseExt,
+};
+
+fn french(key: &str, locale: &Locale, args: &Args) -> Option<String> {
+    if locale.language() != "fr" {
+        return None;
+    }
+    match key {
+        "error.not_found" => Some("ressource introuvable".into()),
+        "error.body.too_large" => Some(i18n::interpolate(
+            "le corps dépasse la limite de {limit} octets",
+            args,
+        )),
+        _ => None,
+    }
+}
+
+#[test]
+fn translate_not_found() -> tsukuyomi_server::Result<()> {
+    let app = App::create(chain![
+        i18n::translator(french),
+        path!("/") //
+            .to(endpoint::get().reply("bonjour")),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(
+        Request::get("/missing") //
+            .header("accept-language", "fr-CA, en;q=0.5"),
+    )?;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(response.header(CONTENT_LANGUAGE)?, "fr-ca");
+    assert_eq!(response.body().to_utf8()?, "ressource introuvable");
+
+    // the body is kept as it is without the acceptable translation.
+    let response = server.perform(
+        Request::get("/missing") //
+            .header("accept-language", "de"),
+    )?;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert!(!response.headers().contains_key(CONTENT_LANGUAGE));
+    assert_eq!(response.body().to_utf8()?, "");
+
+    Ok(())
+}
+
+#[test]
+fn fallback_to_default_message() -> tsukuyomi_server::Result<()> {
+    let app = App::create(chain![
+        i18n::translator(french),
+        path!("/") //
+            .to(endpoint::post()
+                .extract(extractor::body::json())
+                .call(|value: serde_json::Value| value.to_string())),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(
+        Request::post("/") //
+            .header("accept-language", "fr")
+            .header("content-type", "application/json")
+            .body("{"),
+    )?;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert!(!response.headers().contains_key(CONTENT_LANGUAGE));
+    assert!(response
+        .body()
+        .to_utf8()?
+        .starts_with("the content of message body is invalid"));
+
+    Ok(())
+}
+
+#[test]
+fn interpolate_arguments() -> tsukuyomi_server::Result<()> {
+    let app = App::create(chain![
+        i18n::translator(french),
+        path!("/") //
+            .to(endpoint::post()
+                .extract(extractor::header::accept_language(vec![
+                    Locale::new("en"),
+                    Locale::new("fr"),
+                ]))
+                .extract(extractor::body::related_with(
+                    RelatedLimits::default().max_total_size(8)
+                ))
+                .call(|_: Locale, _: RelatedParts| "ok")),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let request = |lang: &str| {
+        Request::post("/")
+            .header("accept-language", lang)
+            .header(CONTENT_TYPE, "multipart/related; boundary=xyz")
+            .body("--xyz\r\n\r\ntoo large\r\n--xyz--\r\n")
+    };
+
+    // the locale is negotiated by the extractor.
+    let response = server.perform(request("fr-BE, en;q=0.5"))?;
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(response.header(CONTENT_LANGUAGE)?, "fr");
+    assert_eq!(
+        response.body().to_utf8()?,
+        "le corps dépasse la limite de 8 octets"
+    );
+
+    let response = server.perform(request("en"))?;
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(
+        response.body().to_utf8()?,
+        "the body exceeds the size limit (limit: 8)"
+    );
+
+    Ok(())
+}
@@ -0,0 +1,224 @@
+use {
+    http::{header::WARNING, Request, StatusCode},
+    hyper::Body,
+    std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        time::Duration,
+    },
+    tsukuyomi::{
+        cache::MemoryCache,
+        config::prelude::*,
+        extractor,
+        modifiers::{self, Idempotency},
+        output::ResponseBody,
+        rt::MockClock,
+        vendor::futures::{
+            executor::{self, Notify, Spawn},
+            sync::oneshot,
+            Async, Future,
+        },
+        App,
+    },
+    tsukuyomi_service::{MakeService, Service},
+};
+
+fn charges_app(idempotency: Idempotency, counter: &Arc<AtomicUsize>) -> App {
+    let counter = counter.clone();
+    App::create(
+        path!("/charges")
+            .to(endpoint::post()
+                .extract(extractor::body::plain())
+                .call(move |body: String| {
+                    let n = counter.fetch_add(1, Ordering::SeqCst) + 1;
+                    http::Response::builder()
+                        .status(StatusCode::CREATED)
+                        .header("x-charge-id", n)
+                        .body(format!("charge {}: {}", n, body))
+                        .unwrap()
+                }))
+            .modify(idempotency),
+    )
+    .unwrap()
+}
+
+fn charge(key: &str) -> http::request::Builder {
+    let mut request = Request::post("/charges");
+    request.header("idempotency-key", key);
+    request
+}
+
+#[test]
+fn replay_stored_response() -> tsukuyomi_server::Result<()> {
+    let counter = Arc::new(AtomicUsize::new(0));
+    let idempotency = modifiers::idempotency(MemoryCache::builder().build());
+    let mut server = tsukuyomi_server::test::server(charges_app(idempotency, &counter))?;
+
+    let response = server.perform(charge("k1").body("100 JPY"))?;
+    assert_eq!(response.status(), 201);
+    assert_eq!(response.body().to_utf8()?, "charge 1: 100 JPY");
+    assert!(!response.headers().contains_key("idempotent-replayed"));
+
+    let replayed = server.perform(charge("k1").body("100 JPY"))?;
+    assert_eq!(replayed.status(), 201);
+    assert_eq!(replayed.headers()["x-charge-id"], "1");
+    assert_eq!(replayed.headers()["idempotent-replayed"], "true");
+    assert_eq!(replayed.body().to_bytes(), response.body().to_bytes());
+    assert_eq!(counter.load(Ordering::SeqCst), 1);
+
+    // the requests without the key or with another key are processed as usual.
+    let response = server.perform(Request::post("/charges").body("100 JPY"))?;
+    assert_eq!(response.body().to_utf8()?, "charge 2: 100 JPY");
+    let response = server.perform(charge("k2").body("100 JPY"))?;
+    assert_eq!(response.body().to_utf8()?, "charge 3: 100 JPY");
+
+    Ok(())
+}
+
+#[test]
+fn fingerprint_mismatch() -> tsukuyomi_server::Result<()> {
+    let counter = Arc::new(AtomicUsize::new(0));
+    let idempotency = modifiers::idempotency(MemoryCache::builder().build());
+    let mut server = tsukuyomi_server::test::server(charges_app(idempotency, &counter))?;
+
+    let response = server.perform(charge("k1").body("100 JPY"))?;
+    assert_eq!(response.status(), 201);
+
+    let response = server.perform(charge("k1").body("200 JPY"))?;
+    assert_eq!(response.status(), 422);
+    assert_eq!(counter.load(Ordering::SeqCst), 1);
+
+    Ok(())
+}
+
+#[test]
+fn ttl_expiry() -> tsukuyomi_server::Result<()> {
+    let clock = MockClock::new();
+    let counter = Arc::new(AtomicUsize::new(0));
+    let idempotency = modifiers::idempotency(MemoryCache::builder().clock(clock.clone()).build())
+        .ttl(Duration::from_secs(60));
+    let mut server = tsukuyomi_server::test::server(charges_app(idempotency, &counter))?;
+
+    let _ = server.perform(charge("k1").body("100 JPY"))?;
+    clock.advance(Duration::from_secs(30));
+    let response = server.perform(charge("k1").body("100 JPY"))?;
+    assert_eq!(response.body().to_utf8()?, "charge 1: 100 JPY");
+
+    clock.advance(Duration::from_secs(31));
+    let response = server.perform(charge("k1").body("100 JPY"))?;
+    assert_eq!(response.body().to_utf8()?, "charge 2: 100 JPY");
+    assert!(!response.headers().contains_key("idempotent-replayed"));
+
+    Ok(())
+}
+
+#[test]
+fn large_response_not_stored() -> tsukuyomi_server::Result<()> {
+    let counter = Arc::new(AtomicUsize::new(0));
+    let idempotency = modifiers::idempotency(MemoryCache::builder().build()).max_response_size(10);
+    let mut server = tsukuyomi_server::test::server(charges_app(idempotency, &counter))?;
+
+    let response = server.perform(charge("k1").body("100 JPY"))?;
+    assert_eq!(response.status(), 201);
+    assert!(response.headers().contains_key(WARNING));
+
+    let response = server.perform(charge("k1").body("100 JPY"))?;
+    assert_eq!(response.body().to_utf8()?, "charge 2: 100 JPY");
+    assert!(response.headers().contains_key(WARNING));
+    assert_eq!(counter.load(Ordering::SeqCst), 2);
+
+    Ok(())
+}
+
+#[test]
+fn large_request_rejected() -> tsukuyomi_server::Result<()> {
+    let counter = Arc::new(AtomicUsize::new(0));
+    let idempotency = modifiers::idempotency(MemoryCache::builder().build()).max_request_size(7);
+    let mut server = tsukuyomi_server::test::server(charges_app(idempotency, &counter))?;
+
+    let response = server.perform(charge("k1").body("100 JPY"))?;
+    assert_eq!(response.status(), 201);
+
+    let response = server.perform(charge("k2").body("1000 JPY"))?;
+    assert_eq!(response.status(), 413);
+    assert_eq!(counter.load(Ordering::SeqCst), 1);
+
+    // the requests without the key are not buffered.
+    let response = server.perform(Request::post("/charges").body("1000 JPY"))?;
+    assert_eq!(response.status(), 201);
+
+    Ok(())
+}
+
+type ResponseFuture = Box<dyn Future<Item = http::Response<ResponseBody>, Error = ()> + Send>;
+
+struct Noop;
+
+impl Notify for Noop {
+    fn notify(&self, _: usize) {}
+}
+
+fn poll(request: &mut Spawn<ResponseFuture>) -> Option<http::Response<ResponseBody>> {
+    match request.poll_future_notify(&Arc::new(Noop), 0) {
+        Ok(Async::Ready(response)) => Some(response),
+        Ok(Async::NotReady) => None,
+        Err(()) => unreachable!(),
+    }
+}
+
+fn body(response: http::Response<ResponseBody>) -> String {
+    let body = tsukuyomi::test::read_body(response.into_body()).unwrap();
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+#[test]
+fn concurrent_requests_are_single_flighted() {
+    let started = Arc::new(Mutex::new(vec![]));
+    let app = App::create(
+        path!("/charges")
+            .to(endpoint::post().call_async({
+                let started = started.clone();
+                move || {
+                    let (tx, rx) = oneshot::channel();
+                    let mut started = started.lock().unwrap();
+                    started.push(tx);
+                    let n = started.len();
+                    rx.map(move |()| format!("charge {}", n))
+                        .map_err(tsukuyomi::error::internal_server_error)
+                }
+            }))
+            .modify(modifiers::idempotency(MemoryCache::builder().build())),
+    )
+    .unwrap();
+    let request = || {
+        let mut service = MakeService::<(), Request<Body>>::make_service(&app, ())
+            .wait()
+            .unwrap_or_else(|never| match never {});
+        let request = Request::post("/charges")
+            .header("idempotency-key", "k1")
+            .body(Body::from("100 JPY"))
+            .unwrap();
+        let future: ResponseFuture =
+            Box::new(service.call(request).map_err(|never| match never {}));
+        executor::spawn(future)
+    };
+
+    let mut first = request();
+    let mut second = request();
+    assert!(poll(&mut first).is_none());
+    assert!(poll(&mut second).is_none());
+    assert_eq!(started.lock().unwrap().len(), 1);
+
+    let tx = started.lock().unwrap().remove(0);
+    tx.send(()).unwrap();
+    let first = poll(&mut first).expect("should be completed");
+    assert!(!first.headers().contains_key("idempotent-replayed"));
+    assert_eq!(body(first), "charge 1");
+
+    let second = poll(&mut second).expect("should be completed");
+    assert_eq!(second.headers()["idempotent-replayed"], "true");
+    assert_eq!(body(second), "charge 1");
+    assert!(started.lock().unwrap().is_empty());
+}
@@ -0,0 +1,143 @@
+use {
+    http::{Request, StatusCode},
+    serde::Deserialize,
+    serde_json::json,
+    tsukuyomi::{config::prelude::*, extractor, output, App},
+};
+
+#[derive(Debug, Deserialize)]
+struct Params {
+    fields: Option<String>,
+    #[serde(default)]
+    strict: bool,
+}
+
+fn app() -> tsukuyomi::app::Result<App> {
+    App::create(chain![
+        path!("/post") //
+            .to(endpoint::get()
+                .extract(extractor::query())
+                .call(|params: Params| {
+                    let post = json!({
+                        "id": 42,
+                        "title": "Hello",
+                        "body": "...",
+                        "author": { "id": 1, "name": "alice", "email": "alice@example.com" },
+                    });
+                    output::json_partial(post, params.fields.as_ref().map(String::as_str))
+                        .strict(params.strict)
+                })),
+        path!("/posts") //
+            .to(endpoint::get()
+                .extract(extractor::query())
+                .call(|params: Params| {
+                    let posts = json!([
+                        { "id": 1, "title": "foo", "tags": [{ "id": 1, "name": "rust" }] },
+                        { "id": 2, "title": "bar", "tags": [] },
+                    ]);
+                    output::json_partial(posts, params.fields.as_ref().map(String::as_str))
+                        .strict(params.strict)
+                })),
+    ])
+}
+
+#[test]
+fn nested_selection() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+
+    let response = server.perform("/post?fields=id,author.name")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.headers().get("content-type").unwrap(),
+        "application/json"
+    );
+    assert_eq!(
+        response.body().to_utf8()?,
+        r#"{"author":{"name":"alice"},"id":42}"#
+    );
+
+    // the whole field takes precedence over its nested fields.
+    let response = server.perform("/post?fields=author.id,author")?;
+    assert_eq!(
+        response.body().to_utf8()?,
+        r#"{"author":{"email":"alice@example.com","id":1,"name":"alice"}}"#
+    );
+
+    let response = server.perform("/post?fields=")?;
+    assert_eq!(
+        response.body().to_utf8()?,
+        r#"{"author":{"email":"alice@example.com","id":1,"name":"alice"},"body":"...","id":42,"title":"Hello"}"#
+    );
+
+    Ok(())
+}
+
+#[test]
+fn array_filtering() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+
+    let response = server.perform("/posts?fields=id,tags.name")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.body().to_utf8()?,
+        r#"[{"id":1,"tags":[{"name":"rust"}]},{"id":2,"tags":[]}]"#
+    );
+
+    Ok(())
+}
+
+#[test]
+fn distinct_etags_and_not_modified() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+
+    let response = server.perform("/post?fields=id")?;
+    let etag_id = response.headers().get("etag").unwrap().clone();
+    let response = server.perform("/post?fields=id,title")?;
+    let etag_title = response.headers().get("etag").unwrap().clone();
+    assert_ne!(etag_id, etag_title);
+
+    // the same selection in the different order results in the same output.
+    let response = server.perform("/post?fields=title,id")?;
+    assert_eq!(response.headers().get("etag").unwrap(), etag_title);
+
+    let response =
+        server.perform(Request::get("/post?fields=id").header("if-none-match", etag_id.clone()))?;
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(response.headers().get("etag").unwrap(), etag_id);
+    assert_eq!(response.body().to_utf8()?, "");
+
+    let response = server.perform(
+        Request::get("/post?fields=id")
+            .header("if-none-match", format!("\"foo\", W/{}", etag_id.to_str()?)),
+    )?;
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+    let response = server
+        .perform(Request::get("/post?fields=id,title").header("if-none-match", etag_id.clone()))?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.body().to_utf8()?, r#"{"id":42,"title":"Hello"}"#);
+
+    Ok(())
+}
+
+#[test]
+fn unknown_fields() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+
+    let response = server.perform("/post?fields=id,unknown,author.age")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, r#"{"author":{},"id":42}"#);
+
+    let response = server.perform("/post?fields=id,unknown,author.age&strict=true")?;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        response.body().to_utf8()?,
+        "unknown fields: author.age,unknown"
+    );
+
+    // the fields found in some of the elements are known.
+    let response = server.perform("/posts?fields=id,tags.name&strict=true")?;
+    assert_eq!(response.status(), 200);
+
+    Ok(())
+}
@@ -0,0 +1,127 @@
+use {
+    http::Request,
+    hyper::Body,
+    std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        time::Duration,
+    },
+    tsukuyomi::{
+        app::Lifecycle,
+        config::{prelude::*, state_with_lifecycle},
+        rt::MockClock,
+        vendor::futures::{
+            executor::{self, Notify, Spawn},
+            future,
+            sync::oneshot,
+            Async, Future,
+        },
+        App,
+    },
+    tsukuyomi_service::{MakeService, Service},
+};
+
+struct Noop;
+
+impl Notify for Noop {
+    fn notify(&self, _: usize) {}
+}
+
+fn poll<F: Future>(future: &mut Spawn<F>) -> Option<F::Item>
+where
+    F::Error: std::fmt::Debug,
+{
+    match future.poll_future_notify(&Arc::new(Noop), 0) {
+        Ok(Async::Ready(item)) => Some(item),
+        Ok(Async::NotReady) => None,
+        Err(err) => panic!("unexpected error: {:?}", err),
+    }
+}
+
+#[derive(Clone, Default)]
+struct Flush {
+    calls: Arc<AtomicUsize>,
+}
+
+impl Lifecycle for Flush {
+    fn on_shutdown(&self) -> Box<dyn Future<Item = (), Error = ()> + Send + 'static> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        Box::new(future::ok(()))
+    }
+}
+
+struct Stuck;
+
+impl Lifecycle for Stuck {
+    const NAME: &'static str = "Stuck";
+
+    fn on_shutdown(&self) -> Box<dyn Future<Item = (), Error = ()> + Send + 'static> {
+        Box::new(future::empty())
+    }
+}
+
+#[test]
+fn hooks_run_once_after_in_flight_requests() -> tsukuyomi::app::Result<()> {
+    let flush = Flush::default();
+    let senders = Arc::new(Mutex::new(vec![]));
+    let app = App::create(chain![
+        state_with_lifecycle(flush.clone()),
+        path!("/") //
+            .to(endpoint::call_async({
+                let senders = senders.clone();
+                move || {
+                    let (tx, rx) = oneshot::channel::<()>();
+                    senders.lock().unwrap().push(tx);
+                    rx.map(|()| "done")
+                        .map_err(tsukuyomi::error::internal_server_error)
+                }
+            })),
+    ])?;
+
+    let mut service = MakeService::<(), Request<Body>>::make_service(&app, ())
+        .wait()
+        .unwrap_or_else(|never| match never {});
+    let mut request = executor::spawn(service.call(Request::get("/").body(Body::empty()).unwrap()));
+    assert!(poll(&mut request).is_none());
+
+    let mut shutdown = executor::spawn(app.shutdown(Duration::from_secs(5)));
+    assert!(poll(&mut shutdown).is_none());
+    assert_eq!(flush.calls.load(Ordering::SeqCst), 0);
+
+    senders.lock().unwrap().pop().unwrap().send(()).unwrap();
+    assert!(poll(&mut request).is_some());
+    assert!(poll(&mut shutdown).is_none());
+    drop(request);
+
+    assert_eq!(poll(&mut shutdown), Some(vec![]));
+    assert_eq!(flush.calls.load(Ordering::SeqCst), 1);
+
+    let mut shutdown = executor::spawn(app.shutdown(Duration::from_secs(5)));
+    assert_eq!(poll(&mut shutdown), Some(vec![]));
+    assert_eq!(flush.calls.load(Ordering::SeqCst), 1);
+
+    Ok(())
+}
+
+#[test]
+fn hook_exceeding_deadline_is_abandoned() -> tsukuyomi::app::Result<()> {
+    let clock = MockClock::new();
+    let flush = Flush::default();
+    let app = App::create(chain![
+        state_with_lifecycle(flush.clone()),
+        mount("/api").with(state_with_lifecycle(Stuck)),
+    ])?
+    .with_clock(clock.clone());
+
+    let mut shutdown = executor::spawn(app.shutdown(Duration::from_secs(5)));
+    assert!(poll(&mut shutdown).is_none());
+    assert_eq!(flush.calls.load(Ordering::SeqCst), 1);
+
+    clock.advance(Duration::from_secs(5));
+    let timed_out = poll(&mut shutdown).expect("the shutdown should complete");
+    assert_eq!(timed_out, vec!["Stuck"]);
+
+    Ok(())
+}
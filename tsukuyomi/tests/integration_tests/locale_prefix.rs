@@ -0,0 +1,159 @@
+use {
+    http::{
+        header::{ACCEPT_LANGUAGE, LOCATION, VARY},
+        Request,
+    },
+    tsukuyomi::{
+        app::{LocalePrefix, Localized},
+        config::prelude::*,
+        extractor,
+        i18n::Locale,
+        input::localmap::LocalData,
+        App,
+    },
+};
+
+fn pages() -> impl Config<(), tsukuyomi::app::config::ThreadSafe, Error = tsukuyomi::app::Error> {
+    chain![
+        path!("/about") //
+            .to(endpoint::get()
+                .extract(extractor::header::accept_language(vec![
+                    Locale::new("en"),
+                    Locale::new("ja"),
+                ]))
+                .call(|locale: Locale| format!("about ({})", locale))),
+        path!("/posts/:id") //
+            .to(endpoint::get()
+                .extract(extractor::local::clone(&Localized::KEY))
+                .call(|id: u32, localized: Localized| {
+                    format!(
+                        "{} {}",
+                        localized.url_for(&format!("/posts/{}/comments", id)),
+                        localized.url_for_locale(&Locale::new("de"), "/"),
+                    )
+                })),
+    ]
+}
+
+#[test]
+fn prefixes_share_handlers() -> tsukuyomi_server::Result<()> {
+    let app = App::create(chain![
+        LocalePrefix::new(&["en", "ja", "de"], "en"), //
+        pages(),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(Request::get("/en/about"))?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "about (en)");
+
+    // the prefix takes precedence over `Accept-Language`.
+    let response = server.perform(Request::get("/ja/about").header(ACCEPT_LANGUAGE, "en"))?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "about (ja)");
+
+    let response = server.perform(Request::get("/fr/about"))?;
+    assert_eq!(response.status(), 404);
+
+    Ok(())
+}
+
+#[test]
+fn unprefixed_redirect() -> tsukuyomi_server::Result<()> {
+    let app = App::create(chain![
+        LocalePrefix::new(&["en", "ja", "de"], "en"), //
+        pages(),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response =
+        server.perform(Request::get("/about?ref=top").header(ACCEPT_LANGUAGE, "ja, en;q=0.5"))?;
+    assert_eq!(response.status(), 302);
+    assert_eq!(response.headers()[LOCATION], "/ja/about?ref=top");
+    assert_eq!(response.headers()[VARY], "accept-language");
+
+    let response = server.perform(Request::get("/posts/42").header(ACCEPT_LANGUAGE, "fr"))?;
+    assert_eq!(response.status(), 302);
+    assert_eq!(response.headers()[LOCATION], "/en/posts/42");
+
+    let response = server.perform(Request::get("/missing"))?;
+    assert_eq!(response.status(), 404);
+
+    Ok(())
+}
+
+#[test]
+fn unprefixed_rewrite() -> tsukuyomi_server::Result<()> {
+    let app = App::create(chain![
+        LocalePrefix::new(&["en", "ja", "de"], "en").rewrite(true), //
+        pages(),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(Request::get("/about").header(ACCEPT_LANGUAGE, "ja"))?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "about (ja)");
+    assert_eq!(response.headers()[VARY], "accept-language");
+
+    Ok(())
+}
+
+#[test]
+fn url_for_current_locale() -> tsukuyomi_server::Result<()> {
+    let app = App::create(chain![
+        mount("/docs").with(chain![
+            LocalePrefix::new(&["en", "ja", "de"], "en"), //
+            pages(),
+        ]),
+        path!("/api/status") //
+            .to(endpoint::get().reply("ok")),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(Request::get("/docs/ja/posts/42"))?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.body().to_utf8()?,
+        "/docs/ja/posts/42/comments /docs/de"
+    );
+
+    let response = server.perform(Request::get("/docs/posts/42"))?;
+    assert_eq!(response.status(), 302);
+    assert_eq!(response.headers()[LOCATION], "/docs/en/posts/42");
+
+    // the routes outside of the localized scope are not affected.
+    let response = server.perform(Request::get("/api/status"))?;
+    assert_eq!(response.status(), 200);
+    let response = server.perform(Request::get("/ja/api/status"))?;
+    assert_eq!(response.status(), 404);
+
+    Ok(())
+}
+
+#[test]
+fn conflict_with_route() {
+    let err = App::create(chain![
+        mount("/").with(chain![
+            LocalePrefix::new(&["en", "ja", "de"], "en"), //
+            pages(),
+        ]),
+        path!("/de/impressum") //
+            .to(endpoint::get().reply("impressum")),
+    ])
+    .err()
+    .expect("should be a conflict");
+    assert!(
+        err.to_string().contains("`/de/impressum'"),
+        "unexpected error: {}",
+        err
+    );
+
+    let err = App::create(LocalePrefix::new(&["en", "ja"], "de"))
+        .err()
+        .expect("should be an invalid default");
+    assert!(
+        err.to_string().contains("`de'"),
+        "unexpected error: {}",
+        err
+    );
+}
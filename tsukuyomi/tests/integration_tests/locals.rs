@@ -0,0 +1,124 @@
+use {
+    http::Response,
+    std::{
+        sync::{mpsc, Arc, Mutex},
+        time::Duration,
+    },
+    tsukuyomi::{
+        config::prelude::*,
+        extractor,
+        input::localmap::local_key,
+        output::ResponseBody,
+        vendor::futures::{stream, Async, Stream},
+        App,
+    },
+};
+
+type Log = Arc<Mutex<Vec<&'static str>>>;
+
+struct Guard(&'static str, Log);
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        self.1.lock().unwrap().push(self.0);
+    }
+}
+
+local_key! {
+    static FIRST: Guard;
+    static SECOND: Guard;
+}
+
+#[test]
+fn teardown_after_streaming_body() -> tsukuyomi_server::Result<()> {
+    let log = Log::default();
+    let (tx, rx) = mpsc::channel();
+    let tx = Arc::new(Mutex::new(tx));
+
+    let app = App::create(
+        path!("/").to(endpoint::get()
+            .extract(extractor::ready({
+                let log = log.clone();
+                move |input| {
+                    input.locals.insert(&FIRST, Guard("first", log.clone()));
+                    input.locals.insert(&SECOND, Guard("second", log.clone()));
+                    let log = log.clone();
+                    let tx = tx.lock().unwrap().clone();
+                    input.locals.defer(move || {
+                        log.lock().unwrap().push("deferred");
+                        let _ = tx.send(());
+                        Ok(())
+                    });
+                    Ok::<_, tsukuyomi::Error>(())
+                }
+            }))
+            .call({
+                let log = log.clone();
+                move || {
+                    let log = log.clone();
+                    let end = stream::poll_fn(move || {
+                        log.lock().unwrap().push("body-end");
+                        Ok::<_, std::io::Error>(Async::Ready(None::<&'static str>))
+                    });
+                    Response::new(ResponseBody::wrap_stream(
+                        stream::iter_ok(vec!["hello, ", "world"]).chain(end),
+                    ))
+                }
+            })),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "hello, world");
+
+    rx.recv_timeout(Duration::from_secs(5))?;
+    assert_eq!(
+        *log.lock().unwrap(),
+        vec!["body-end", "second", "first", "deferred"]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn panicking_destructor_does_not_skip_others() -> tsukuyomi_server::Result<()> {
+    struct Panicking;
+
+    impl Drop for Panicking {
+        fn drop(&mut self) {
+            panic!("explicit panic");
+        }
+    }
+
+    local_key! {
+        static PANICKING: Panicking;
+    }
+
+    let log = Log::default();
+    let app = App::create(
+        path!("/").to(endpoint::get()
+            .extract(extractor::ready({
+                let log = log.clone();
+                move |input| {
+                    input.locals.insert(&FIRST, Guard("first", log.clone()));
+                    input.locals.insert(&PANICKING, Panicking);
+                    input.locals.insert(&SECOND, Guard("second", log.clone()));
+                    Ok::<_, tsukuyomi::Error>(())
+                }
+            }))
+            .reply("ok")),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "ok");
+    assert_eq!(*log.lock().unwrap(), vec!["second", "first"]);
+
+    // the server keeps working after the panic.
+    let response = server.perform("/")?;
+    assert_eq!(response.status(), 200);
+
+    Ok(())
+}
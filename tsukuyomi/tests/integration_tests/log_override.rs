@@ -0,0 +1,144 @@
+use {
+    http::Request,
+    log::Level,
+    std::time::Duration,
+    tsukuyomi::{
+        app::{LogLevelOverride, LogOverride},
+        config::prelude::*,
+        dynamic::DynamicConfig,
+        extractor,
+        rt::MockClock,
+        App,
+    },
+};
+
+fn take_records(prefix: &str) -> Vec<(Level, String)> {
+    super::logging::take_records_with_level(prefix)
+}
+
+#[test]
+fn override_targeted_routes() -> tsukuyomi_server::Result<()> {
+    let _ = take_records("access: method=GET path=/targeted/");
+
+    let setting = DynamicConfig::new(None);
+    let app = App::create(mount("/targeted").with(chain![
+        LogLevelOverride::new(setting.clone()),
+        path!("/posts/:id").to(endpoint::call(|_id: u32| "post")),
+        path!("/users").to(endpoint::call(|| "users")),
+    ]))?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let _ = server.perform("/targeted/posts/42")?;
+    assert!(take_records("access: method=GET path=/targeted/").is_empty());
+
+    setting.set(Some(
+        LogOverride::new(Level::Debug).route("/targeted/posts/:id"),
+    ));
+
+    let _ = server.perform("/targeted/posts/42")?;
+    let _ = server.perform("/targeted/users")?;
+    let records = take_records("access: method=GET path=/targeted/");
+    assert_eq!(records.len(), 1, "{:?}", records);
+    assert_eq!(records[0].0, Level::Debug);
+    assert!(
+        records[0].1.starts_with(
+            "access: method=GET path=/targeted/posts/42 pattern=/targeted/posts/:id status=200 "
+        ),
+        "{}",
+        records[0].1
+    );
+
+    let _ = server.perform("/targeted/posts/foo")?;
+    let records = take_records("error: method=GET path=/targeted/posts/foo");
+    assert_eq!(records.len(), 1, "{:?}", records);
+    assert!(
+        records[0].1.contains(" status=400 message="),
+        "{}",
+        records[0].1
+    );
+    let _ = take_records("access: method=GET path=/targeted/");
+
+    setting.set(None);
+    let _ = server.perform("/targeted/posts/42")?;
+    assert!(take_records("access: method=GET path=/targeted/").is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn override_expires() -> tsukuyomi_server::Result<()> {
+    let _ = take_records("access: method=GET path=/expiring");
+
+    let clock = MockClock::new();
+    let setting = DynamicConfig::new(Some(LogOverride::new(Level::Trace)));
+    let app = App::create(mount("/expiring").with(chain![
+        LogLevelOverride::new(setting.clone()).expires_after(Duration::from_secs(60)),
+        path!("/").to(endpoint::call(|| "index")),
+    ]))?
+    .with_clock(clock.clone());
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let _ = server.perform("/expiring")?;
+    let records = take_records("access: method=GET path=/expiring");
+    assert_eq!(records.len(), 1, "{:?}", records);
+    assert_eq!(records[0].0, Level::Trace);
+
+    clock.advance(Duration::from_secs(60));
+    let _ = server.perform("/expiring")?;
+    assert!(take_records("access: method=GET path=/expiring").is_empty());
+
+    // replacing the value restarts the expiration.
+    setting.set(Some(LogOverride::new(Level::Trace)));
+    let _ = server.perform("/expiring")?;
+    assert_eq!(take_records("access: method=GET path=/expiring").len(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn capture_body() -> tsukuyomi_server::Result<()> {
+    let _ = take_records("request ");
+
+    let setting = DynamicConfig::new(Some(LogOverride::new(Level::Debug).capture_body(true)));
+    let app = App::create(mount("/capture").with(chain![
+        LogLevelOverride::new(setting.clone())
+            .max_body_size(8)
+            .redact_header(http::header::HeaderName::from_static("x-api-key")),
+        path!("/")
+            .to(endpoint::post()
+                .extract(extractor::body::read_all())
+                .call(|body: bytes::Bytes| format!("{} bytes", body.len()))),
+    ]))?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(
+        Request::post("/capture")
+            .header("authorization", "Bearer secret")
+            .header("cookie", "session=secret")
+            .header("x-api-key", "secret")
+            .header("x-request-id", "abc")
+            .body("0123456789abcdef"),
+    )?;
+    assert_eq!(response.body().to_utf8()?, "16 bytes");
+    let _ = take_records("access: method=POST path=/capture");
+
+    let records = take_records("request headers:");
+    assert_eq!(records.len(), 1, "{:?}", records);
+    let headers = &records[0].1;
+    assert!(!headers.contains("secret"), "{}", headers);
+    assert!(headers.contains("authorization: <redacted>"), "{}", headers);
+    assert!(headers.contains("cookie: <redacted>"), "{}", headers);
+    assert!(headers.contains("x-api-key: <redacted>"), "{}", headers);
+    assert!(headers.contains("x-request-id: abc"), "{}", headers);
+
+    let records = take_records("request body:");
+    assert_eq!(
+        records,
+        vec![(
+            Level::Debug,
+            "request body: 16 bytes (truncated): \"01234567\"".to_owned()
+        )]
+    );
+
+    Ok(())
+}
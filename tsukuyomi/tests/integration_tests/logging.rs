@@ -0,0 +1,71 @@
+//! A logger which captures the warnings and errors emitted during the tests.
+//!
+//! The records of the overridden routes (`app::LOG_OVERRIDE_TARGET`) are captured
+//! at all levels.
+
+use {
+    lazy_static::lazy_static,
+    log::{Level, Log, Metadata, Record},
+    std::sync::Mutex,
+    tsukuyomi::app::LOG_OVERRIDE_TARGET,
+};
+
+struct CapturingLogger {
+    records: Mutex<Vec<(Level, String)>>,
+}
+
+impl CapturingLogger {
+    fn captures(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.level() <= Level::Warn || metadata.target() == LOG_OVERRIDE_TARGET
+    }
+}
+
+impl Log for CapturingLogger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        self.captures(metadata)
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if self.captures(record.metadata()) {
+            self.records
+                .lock()
+                .unwrap()
+                .push((record.level(), record.args().to_string()));
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+lazy_static! {
+    static ref LOGGER: CapturingLogger = CapturingLogger {
+        records: Mutex::new(Vec::new()),
+    };
+
+    // The logger is installed at the first access.
+    static ref INIT: () = {
+        log::set_logger(&*LOGGER).unwrap();
+        log::set_max_level(log::LevelFilter::Trace);
+    };
+}
+
+/// Removes the captured records that start with `prefix`, and returns them.
+///
+/// The records with other prefixes are left for the other tests running concurrently.
+pub fn take_records(prefix: &str) -> Vec<String> {
+    take_records_with_level(prefix)
+        .into_iter()
+        .map(|(_, message)| message)
+        .collect()
+}
+
+/// Same as `take_records`, but returns the records along with their levels.
+pub fn take_records_with_level(prefix: &str) -> Vec<(Level, String)> {
+    lazy_static::initialize(&INIT);
+    let mut records = LOGGER.records.lock().unwrap();
+    let (taken, rest) = records
+        .drain(..)
+        .partition(|(_, message)| message.starts_with(prefix));
+    *records = rest;
+    taken
+}
@@ -215,3 +215,153 @@ mod responder {
         Ok(())
     }
 }
+
+mod route {
+    use {
+        http::{header::CONTENT_TYPE, Request},
+        serde::Deserialize,
+        tsukuyomi::{config::prelude::*, extractor, App},
+        tsukuyomi_server::test::ResponseExt,
+    };
+
+    #[derive(Debug, Deserialize)]
+    struct Pagination {
+        page: u32,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Post {
+        title: String,
+    }
+
+    fn list_posts(p: Pagination) -> String {
+        format!("list page={}", p.page)
+    }
+
+    fn create_post(post: Post) -> String {
+        format!("create title={}", post.title)
+    }
+
+    fn show_post(id: u32) -> String {
+        format!("show id={}", id)
+    }
+
+    fn update_post(id: u32, post: Post) -> String {
+        format!("update id={} title={}", id, post.title)
+    }
+
+    fn delete_post(id: u32) -> String {
+        format!("delete id={}", id)
+    }
+
+    fn assert_crud(app: App) -> tsukuyomi_server::Result<()> {
+        let mut server = tsukuyomi_server::test::server(app)?;
+
+        let response = server.perform("/posts?page=2")?;
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.body().to_utf8()?, "list page=2");
+
+        let response = server.perform(
+            Request::post("/posts")
+                .header(CONTENT_TYPE, "application/json")
+                .body(r#"{"title":"hello"}"#),
+        )?;
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.body().to_utf8()?, "create title=hello");
+
+        let response = server.perform("/posts/42")?;
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.body().to_utf8()?, "show id=42");
+
+        let response = server.perform(
+            Request::put("/posts/42")
+                .header(CONTENT_TYPE, "application/json")
+                .body(r#"{"title":"updated"}"#),
+        )?;
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.body().to_utf8()?, "update id=42 title=updated");
+
+        let response = server.perform(Request::delete("/posts/42"))?;
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.body().to_utf8()?, "delete id=42");
+
+        let response = server.perform(Request::delete("/posts"))?;
+        assert_eq!(response.status(), 405);
+
+        let response = server.perform("/posts/foo")?;
+        assert_eq!(response.status(), 400);
+
+        Ok(())
+    }
+
+    #[test]
+    fn crud_long_form() -> tsukuyomi_server::Result<()> {
+        assert_crud(App::create(chain![
+            path!("/posts") //
+                .to(chain![
+                    endpoint::get()
+                        .extract(extractor::query::<Pagination>())
+                        .call(list_posts),
+                    endpoint::post()
+                        .extract(extractor::body::json::<Post>())
+                        .call(create_post),
+                ]),
+            path!("/posts/:id") //
+                .to(chain![
+                    endpoint::get().call(show_post),
+                    endpoint::put()
+                        .extract(extractor::body::json::<Post>())
+                        .call(update_post),
+                    endpoint::delete().call(delete_post),
+                ]),
+        ])?)
+    }
+
+    #[test]
+    fn crud_sugar() -> tsukuyomi_server::Result<()> {
+        assert_crud(App::create(chain![
+            route!("/posts", {
+                GET, query(Pagination) => list_posts,
+                POST, json(Post) => create_post,
+            }),
+            route!("/posts/:id", {
+                GET => show_post,
+                PUT, json(Post) => update_post,
+                DELETE => delete_post,
+            }),
+        ])?)
+    }
+
+    #[test]
+    fn param_and_arbitrary_extractor() -> tsukuyomi_server::Result<()> {
+        let app = App::create(route!(
+            "/files/:name",
+            GET,
+            param(name: String),
+            extract(extractor::method())
+            => |_raw: String, name: String, method: http::Method| format!("{} {}", method, name)
+        ))?;
+        let mut server = tsukuyomi_server::test::server(app)?;
+
+        let response = server.perform("/files/foo%20bar")?;
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.body().to_utf8()?, "GET foo bar");
+
+        Ok(())
+    }
+
+    #[test]
+    fn trailing_modify() -> tsukuyomi_server::Result<()> {
+        let app = App::create(
+            route!("/posts/:id", GET => |id: u32| id * 2)
+                .modify(tsukuyomi::modifiers::map_output(|n: u32| n.to_string())),
+        )?;
+        let mut server = tsukuyomi_server::test::server(app)?;
+
+        let response = server.perform("/posts/21")?;
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.body().to_utf8()?, "42");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,127 @@
+use {
+    std::sync::{Arc, Mutex},
+    tsukuyomi::{config::prelude::*, extractor, input::param::MatrixParams, App},
+    tsukuyomi_server::test::ResponseExt,
+};
+
+fn tiles() -> tsukuyomi::config::Result<App> {
+    App::create(chain![
+        path!("/map/:zoom/tiles") //
+            .to(endpoint::get()
+                .extract(extractor::path::matrix::<f64>("lat"))
+                .extract(extractor::path::matrix::<f64>("long"))
+                .call(|zoom: u32, lat: f64, long: f64| {
+                    format!("zoom={},lat={},long={}", zoom, lat, long)
+                })),
+        path!("/raw/:segment") //
+            .to(endpoint::get().call(|segment: String| segment)),
+    ])
+}
+
+#[test]
+fn disabled_by_default() -> tsukuyomi_server::Result<()> {
+    let app = tiles()?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/map/3;lat=10;long=20/tiles")?;
+    assert_eq!(response.status(), 400);
+
+    let response = server.perform("/map;lat=10/3/tiles")?;
+    assert_eq!(response.status(), 404);
+
+    let response = server.perform("/raw/a;b=c")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "a;b=c");
+
+    Ok(())
+}
+
+#[test]
+fn matching() -> tsukuyomi_server::Result<()> {
+    let app = tiles()?.matrix_params(true);
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/map/3;lat=35.5;long=139.7/tiles")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "zoom=3,lat=35.5,long=139.7");
+
+    let response = server.perform("/map;lat=1/4;long=-2/tiles;v=2")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "zoom=4,lat=1,long=-2");
+
+    let response = server.perform("/map/3;lat=10/tiles")?;
+    assert_eq!(response.status(), 400);
+
+    let response = server.perform("/map/3;lat=north;long=20/tiles")?;
+    assert_eq!(response.status(), 400);
+
+    let response = server.perform("/map/3/tiles")?;
+    assert_eq!(response.status(), 400);
+
+    Ok(())
+}
+
+#[test]
+fn repeated_and_encoded_keys() -> tsukuyomi_server::Result<()> {
+    let captured = Arc::new(Mutex::new(vec![]));
+    let app = App::create({
+        let captured = captured.clone();
+        path!("/search/:kind") //
+            .to(endpoint::get()
+                .extract(extractor::path::matrix::<String>("q"))
+                .extract(extractor::ready(move |input| {
+                    let mut captured = captured.lock().unwrap();
+                    for index in 0..3 {
+                        captured.push(input.matrix_params(index).cloned());
+                    }
+                    Ok::<_, tsukuyomi::Error>(())
+                }))
+                .call(|kind: String, q: String| format!("{}:{}", kind, q)))
+    })?
+    .matrix_params(true);
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/search;v/books;q=a%20b;tag=x;t%61g=y%3Bz;;q=c")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "books:a b");
+
+    let captured = captured.lock().unwrap();
+    let search = captured[0].as_ref().unwrap();
+    assert_eq!(search.get("v").as_ref().map(|v| &**v), Some(""));
+    let books = captured[1].as_ref().unwrap();
+    assert_eq!(books.len(), 4);
+    assert_eq!(books.get_all("q").collect::<Vec<_>>(), vec!["a b", "c"]);
+    assert_eq!(books.get_all("tag").collect::<Vec<_>>(), vec!["x", "y;z"]);
+    assert!(captured[2].is_none());
+
+    Ok(())
+}
+
+#[test]
+fn round_trip() -> tsukuyomi_server::Result<()> {
+    let app = tiles()?.matrix_params(true);
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let params = MatrixParams::new()
+        .append("lat", "12.5")
+        .append("long", "-3")
+        .append("label", "café; bar=1/2");
+    let uri = format!("/map/7{}/tiles", params);
+    assert_eq!(
+        uri,
+        "/map/7;lat=12.5;long=-3;label=caf%C3%A9%3B%20bar%3D1%2F2/tiles"
+    );
+
+    let response = server.perform(&*uri)?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "zoom=7,lat=12.5,long=-3");
+
+    let parsed = MatrixParams::parse(&params.to_string()[1..]);
+    assert_eq!(parsed, params);
+    assert_eq!(
+        parsed.get("label").as_ref().map(|v| &**v),
+        Some("café; bar=1/2")
+    );
+
+    Ok(())
+}
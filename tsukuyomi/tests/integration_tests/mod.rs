@@ -1,6 +1,91 @@
+#[cfg(feature = "acme")]
+mod acme;
 mod app;
+#[cfg(feature = "async-await")]
+mod async_await;
+mod batch;
+mod body_buffering;
+mod body_rules;
+mod breaker;
+mod cache;
+mod canonical_host;
+#[cfg(feature = "compression")]
+mod compression;
+mod connection;
+mod content_sniff;
 mod cookie;
+mod csp_nonce;
+#[cfg(feature = "csv")]
+mod csv;
+#[cfg(feature = "chrono")]
+mod datetime;
+mod dedicated_pool;
+mod dynamic;
+mod endpoint;
+mod envelope;
 mod extract;
+mod extraction_report;
+mod fallback;
+mod fallback_harness;
+mod featureflags;
+mod fingerprint;
+mod forwarded;
 mod fs;
+mod header_hygiene;
+mod header_injection;
+mod header_limits;
+mod i18n;
+mod idempotency;
+mod json_partial;
+mod lifecycle;
+mod locale_prefix;
+mod locals;
+mod log_override;
+mod logging;
 mod macros;
+mod matrix;
 mod modifier;
+mod modify_response;
+mod negotiation;
+mod output;
+mod overrides;
+mod pagination;
+mod pipe;
+mod policy;
+mod poll;
+mod pool;
+mod problem;
+mod progress;
+mod query;
+mod queue_limit;
+mod ranged;
+mod redirect;
+mod redirects;
+mod related;
+mod replay;
+mod report;
+mod resource;
+mod response_cache;
+mod response_headers;
+mod response_size;
+mod reverse_dns;
+mod rt;
+mod schema;
+mod serialize;
+mod server_options;
+mod shadow;
+mod slow_request;
+mod sni;
+mod split;
+mod state;
+mod static_routes;
+mod stream_blocking;
+mod tags;
+mod trace;
+mod upgrade;
+#[cfg(feature = "compression")]
+mod vary;
+mod version;
+mod vnd;
+#[cfg(feature = "secure")]
+mod webhook;
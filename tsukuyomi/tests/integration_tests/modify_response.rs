@@ -0,0 +1,121 @@
+use {
+    http::{
+        header::{HeaderName, HeaderValue},
+        Request, Response,
+    },
+    serde::Deserialize,
+    tsukuyomi::{
+        config::{self, prelude::*},
+        extractor,
+        output::ResponseBody,
+        App,
+    },
+    tsukuyomi_server::test::ResponseExt,
+};
+
+/// Creates a `ModifyResponse` which appends `name` to the header field `x-hooks`.
+fn append(
+    name: &'static str,
+) -> impl Fn(&Request<()>, &mut Response<ResponseBody>) + Send + Sync + 'static {
+    move |_, response| {
+        response.headers_mut().append(
+            HeaderName::from_static("x-hooks"),
+            HeaderValue::from_static(name),
+        );
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Search {
+    limit: u32,
+}
+
+fn app() -> tsukuyomi::app::Result<App> {
+    App::create(chain![
+        mount("/api").with(chain![
+            path!("/users/:id") //
+                .to(endpoint::get().call(|id: u32| format!("user {}", id))),
+            path!("/search") //
+                .to(endpoint::get()
+                    .extract(extractor::query())
+                    .call(|search: Search| format!("limit = {}", search.limit))),
+            path!("/fail") //
+                .to(endpoint::get().call(|| -> tsukuyomi::Result<&'static str> {
+                    Err(tsukuyomi::error::bad_request("failed"))
+                })),
+            config::modify_response(append("api")),
+        ]),
+        path!("/") //
+            .to(endpoint::get().reply("index")),
+        config::modify_response(append("root")),
+    ])
+}
+
+#[test]
+fn applied_to_every_response_in_scope() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+
+    let cases: Vec<(Request<&str>, u16)> = vec![
+        // success
+        (Request::get("/api/users/42").body("")?, 200),
+        // extractor error
+        (Request::get("/api/search?limit=many").body("")?, 400),
+        // handler error
+        (Request::get("/api/fail").body("")?, 400),
+        // no route
+        (Request::get("/api/posts").body("")?, 404),
+        // method not allowed
+        (Request::post("/api/users/42").body("")?, 405),
+    ];
+    for (request, status) in cases {
+        let uri = request.uri().clone();
+        let response = server.perform(request)?;
+        assert_eq!(response.status(), status, "{}", uri);
+        let hooks: Vec<_> = response
+            .headers()
+            .get_all("x-hooks")
+            .iter()
+            .map(|value| value.to_str().unwrap())
+            .collect();
+        assert_eq!(hooks, vec!["api", "root"], "{}", uri);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn not_applied_outside_scope() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+
+    let response = server.perform("/")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.header("x-hooks")?, "root");
+
+    let response = server.perform("/posts")?;
+    assert_eq!(response.status(), 404);
+    assert_eq!(response.header("x-hooks")?, "root");
+
+    Ok(())
+}
+
+#[test]
+fn skip_errors() -> tsukuyomi_server::Result<()> {
+    let app = App::create(chain![
+        path!("/") //
+            .to(endpoint::get().reply("index")),
+        config::modify_response(append("always")),
+        config::modify_response(append("success")).skip_errors(),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/")?;
+    let hooks: Vec<_> = response.headers().get_all("x-hooks").iter().collect();
+    assert_eq!(hooks, vec!["always", "success"]);
+
+    let response = server.perform("/posts")?;
+    assert_eq!(response.status(), 404);
+    let hooks: Vec<_> = response.headers().get_all("x-hooks").iter().collect();
+    assert_eq!(hooks, vec!["always"]);
+
+    Ok(())
+}
@@ -0,0 +1,83 @@
+use {
+    http::{
+        header::{ACCEPT, CONTENT_TYPE, VARY},
+        Request, StatusCode,
+    },
+    tsukuyomi::{config::prelude::*, guard, App},
+    tsukuyomi_server::test::ResponseExt,
+};
+
+fn app() -> tsukuyomi::app::Result<App> {
+    App::create(
+        path!("/") //
+            .to(chain![
+                endpoint::get()
+                    .guard(guard::accepts(mime::APPLICATION_JSON))
+                    .reply(r#"{"message":"hello"}"#)
+                    .map_output(|mut response, _| {
+                        response
+                            .headers_mut()
+                            .insert(CONTENT_TYPE, "application/json".parse().unwrap());
+                        response
+                    }),
+                endpoint::get()
+                    .guard(guard::accepts(mime::TEXT_HTML))
+                    .reply("<p>hello</p>"),
+            ]),
+    )
+}
+
+#[test]
+fn browser_prefers_html() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+
+    let response = server.perform(Request::get("/").header(
+        ACCEPT,
+        "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8",
+    ))?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.body().to_utf8()?, "<p>hello</p>");
+    assert_eq!(response.header(VARY)?, "accept");
+
+    Ok(())
+}
+
+#[test]
+fn json_client() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+
+    let response = server.perform(Request::get("/").header(ACCEPT, "application/json"))?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.header(CONTENT_TYPE)?, "application/json");
+    assert_eq!(response.body().to_utf8()?, r#"{"message":"hello"}"#);
+    assert_eq!(response.header(VARY)?, "accept");
+
+    // The preference of the client wins over the order of the endpoints.
+    let response = server
+        .perform(Request::get("/").header(ACCEPT, "text/html;q=0.5, application/json;q=0.9"))?;
+    assert_eq!(response.body().to_utf8()?, r#"{"message":"hello"}"#);
+
+    // Without `Accept`, the first endpoint is applied.
+    let response = server.perform("/")?;
+    assert_eq!(response.body().to_utf8()?, r#"{"message":"hello"}"#);
+
+    Ok(())
+}
+
+#[test]
+fn not_acceptable() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+
+    let response = server.perform(Request::get("/").header(ACCEPT, "image/png, text/html;q=0"))?;
+    assert_eq!(response.status(), StatusCode::NOT_ACCEPTABLE);
+    assert_eq!(response.header(VARY)?, "accept");
+    assert_eq!(
+        response.body().to_utf8()?,
+        "no acceptable representation (available: application/json, text/html)"
+    );
+
+    let response = server.perform(Request::post("/").header(ACCEPT, "text/html"))?;
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+    Ok(())
+}
@@ -0,0 +1,114 @@
+use {
+    http::{
+        header::{self, HeaderMap, HeaderValue},
+        Response, StatusCode,
+    },
+    tsukuyomi::{config::prelude::*, output, App},
+    tsukuyomi_server::test::ResponseExt,
+};
+
+#[test]
+fn status_code() -> tsukuyomi_server::Result<()> {
+    let app = App::create(chain![
+        path!("/accepted").to(endpoint::call(|| StatusCode::ACCEPTED)),
+        path!("/continue").to(endpoint::call(|| StatusCode::CONTINUE)),
+        path!("/status").to(endpoint::call(|| output::status(StatusCode::RESET_CONTENT))),
+        path!("/no_content").to(endpoint::call(output::no_content)),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/accepted")?;
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    assert!(response.body().to_bytes().is_empty());
+
+    let response = server.perform("/continue")?;
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+    let response = server.perform("/status")?;
+    assert_eq!(response.status(), StatusCode::RESET_CONTENT);
+
+    let response = server.perform("/no_content")?;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert!(response.body().to_bytes().is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn created() -> tsukuyomi_server::Result<()> {
+    let app = App::create(chain![
+        path!("/posts").to(endpoint::post().call(|| output::created("/posts/42"))),
+        path!("/invalid").to(endpoint::post().call(|| output::created("/posts/4 2"))),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(http::Request::post("/posts"))?;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert_eq!(response.header(header::LOCATION)?, "/posts/42");
+
+    let response = server.perform(http::Request::post("/invalid"))?;
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert!(response.headers().get(header::LOCATION).is_none());
+
+    Ok(())
+}
+
+#[test]
+fn tuple_overrides_status() -> tsukuyomi_server::Result<()> {
+    let app = App::create(chain![
+        path!("/accepted").to(endpoint::call(|| (StatusCode::ACCEPTED, "queued"))),
+        path!("/not_modified").to(endpoint::call(|| (StatusCode::NOT_MODIFIED, "stale"))),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/accepted")?;
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    assert_eq!(
+        response.header(header::CONTENT_TYPE)?,
+        "text/plain; charset=utf-8"
+    );
+    assert_eq!(response.body().to_utf8()?, "queued");
+
+    let response = server.perform("/not_modified")?;
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert!(response.body().to_bytes().is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn tuple_merges_headers() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/") //
+            .to(endpoint::call(|| {
+                let mut headers = HeaderMap::new();
+                headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("text/csv"));
+                headers.append(header::VARY, HeaderValue::from_static("accept"));
+                headers.append(header::VARY, HeaderValue::from_static("accept-language"));
+                let inner = Response::builder()
+                    .header(header::CONTENT_TYPE, "text/plain")
+                    .header(header::VARY, "cookie")
+                    .header(header::CACHE_CONTROL, "no-cache")
+                    .body("a,b")
+                    .unwrap();
+                (StatusCode::CREATED, headers, inner)
+            })),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/")?;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert_eq!(response.header(header::CONTENT_TYPE)?, "text/csv");
+    assert_eq!(
+        response
+            .headers()
+            .get_all(header::VARY)
+            .iter()
+            .collect::<Vec<_>>(),
+        vec!["accept", "accept-language"]
+    );
+    assert_eq!(response.header(header::CACHE_CONTROL)?, "no-cache");
+    assert_eq!(response.body().to_utf8()?, "a,b");
+
+    Ok(())
+}
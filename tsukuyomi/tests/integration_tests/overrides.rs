@@ -0,0 +1,102 @@
+use {
+    http::{Request, StatusCode},
+    tsukuyomi::{
+        config::{self, prelude::*},
+        App,
+    },
+    tsukuyomi_server::test::ResponseExt,
+};
+
+/// The routes provided by a library, mounted onto the application.
+fn library() -> impl Config<(), tsukuyomi::app::config::ThreadSafe> {
+    mount("/").with(
+        path!("/health") //
+            .to(chain![
+                endpoint::get().reply("library"),
+                endpoint::post().reply("reset"),
+            ]),
+    )
+}
+
+#[test]
+fn override_library_route() -> tsukuyomi_server::Result<()> {
+    let app = App::create(chain![
+        library(),
+        path!("/health")
+            .to(endpoint::get().reply("app"))
+            .override_existing(),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/health")?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.body().to_utf8()?, "app");
+
+    // The other endpoints of the overridden route are kept.
+    let response = server.perform(Request::post("/health"))?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.body().to_utf8()?, "reset");
+
+    let response = server.perform(Request::delete("/health"))?;
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+    Ok(())
+}
+
+#[test]
+fn scope_level_overrides() -> tsukuyomi_server::Result<()> {
+    let app = App::create(chain![
+        library(),
+        config::allow_overrides(true),
+        path!("/health").to(endpoint::get().reply("app")),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/health")?;
+    assert_eq!(response.body().to_utf8()?, "app");
+
+    Ok(())
+}
+
+#[test]
+fn duplicate_without_flag() {
+    let app = App::create(chain![
+        library(),
+        path!("/health").to(endpoint::get().reply("app")),
+    ]);
+    let err = app.err().expect("the duplicate route should be rejected");
+    assert_eq!(
+        err.to_string(),
+        "the route `/health' has already been registered"
+    );
+
+    // The flag is restricted to the scope where it is set.
+    let app = App::create(chain![
+        library(),
+        mount("/").with(config::allow_overrides(true)),
+        path!("/health").to(endpoint::get().reply("app")),
+    ]);
+    assert!(app.is_err());
+}
+
+#[test]
+fn debug_routes_shows_shadowed_route() -> tsukuyomi_server::Result<()> {
+    let app = App::create(chain![
+        library(),
+        path!("/health")
+            .to(endpoint::get().reply("app"))
+            .override_existing(),
+        path!("/version").to(endpoint::any().reply("1.0")),
+    ])?;
+
+    assert_eq!(
+        app.debug_routes(),
+        "/health\n\
+         \x20   GET [scope /]\n\
+         \x20   GET, POST [scope /] shadowed: GET\n\
+         /version\n\
+         \x20   * [scope /]\n"
+    );
+
+    Ok(())
+}
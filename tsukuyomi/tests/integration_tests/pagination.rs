@@ -0,0 +1,128 @@
+use tsukuyomi::{
+    config::prelude::*,
+    extractor::{
+        self,
+        pagination::{Defaults, Pagination},
+    },
+    output::Paginated,
+    App,
+};
+
+fn items(pagination: &Pagination, total: u64) -> Vec<u64> {
+    match *pagination {
+        Pagination::Page {
+            offset, per_page, ..
+        } => (offset..std::cmp::min(offset + u64::from(per_page), total)).collect(),
+        Pagination::Cursor { .. } => vec![],
+    }
+}
+
+fn app(defaults: Defaults) -> tsukuyomi::app::Result<App> {
+    App::create(
+        path!("/items") //
+            .to(endpoint::get()
+                .extract(extractor::pagination(defaults))
+                .call(|pagination: Pagination| {
+                    let items = items(&pagination, 95);
+                    Paginated::new(items, pagination).total(95)
+                })),
+    )
+}
+
+#[test]
+fn clamp_per_page() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app(Defaults::new().max_per_page(10))?)?;
+
+    let response = server.perform("/items?per_page=50")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "[0,1,2,3,4,5,6,7,8,9]");
+    assert_eq!(response.headers().get("x-total-count").unwrap(), "95");
+
+    let mut server =
+        tsukuyomi_server::test::server(app(Defaults::new().max_per_page(10).reject_excess(true))?)?;
+    let response = server.perform("/items?per_page=50")?;
+    assert_eq!(response.status(), 400);
+
+    let response = server.perform("/items?per_page=0")?;
+    assert_eq!(response.status(), 400);
+
+    let response = server.perform("/items?page=0")?;
+    assert_eq!(response.status(), 400);
+
+    Ok(())
+}
+
+#[test]
+fn links_for_middle_page() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app(Defaults::new())?)?;
+
+    let response = server.perform("/items?sort=name%20asc&page=3&per_page=10")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.body().to_utf8()?,
+        "[20,21,22,23,24,25,26,27,28,29]"
+    );
+    assert_eq!(
+        response.headers().get("link").unwrap(),
+        "</items?sort=name+asc&page=1&per_page=10>; rel=\"first\", \
+         </items?sort=name+asc&page=2&per_page=10>; rel=\"prev\", \
+         </items?sort=name+asc&page=4&per_page=10>; rel=\"next\", \
+         </items?sort=name+asc&page=10&per_page=10>; rel=\"last\""
+    );
+
+    Ok(())
+}
+
+#[test]
+fn links_for_first_and_last_page() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app(Defaults::new().per_page(50))?)?;
+
+    let response = server.perform("/items")?;
+    assert_eq!(
+        response.headers().get("link").unwrap(),
+        "</items?page=1&per_page=50>; rel=\"first\", \
+         </items?page=2&per_page=50>; rel=\"next\", \
+         </items?page=2&per_page=50>; rel=\"last\""
+    );
+
+    let response = server.perform("/items?page=2")?;
+    assert_eq!(response.body().to_utf8()?.matches(',').count(), 44);
+    assert_eq!(
+        response.headers().get("link").unwrap(),
+        "</items?page=1&per_page=50>; rel=\"first\", \
+         </items?page=1&per_page=50>; rel=\"prev\", \
+         </items?page=2&per_page=50>; rel=\"last\""
+    );
+
+    Ok(())
+}
+
+#[test]
+fn cursor_passthrough() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/items") //
+            .to(endpoint::get()
+                .extract(extractor::pagination(Defaults::new()))
+                .call(|pagination: Pagination| {
+                    let cursor = match pagination {
+                        Pagination::Cursor { ref cursor, .. } => cursor.clone(),
+                        Pagination::Page { .. } => panic!("unexpected page-based pagination"),
+                    };
+                    assert_eq!(cursor, "abc/def");
+                    Paginated::new(vec!["a", "b"], pagination).next_cursor("ghi/jkl")
+                })),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/items?cursor=abc%2Fdef&per_page=2&q=x")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, r#"["a","b"]"#);
+    assert!(!response.headers().contains_key("x-total-count"));
+    assert_eq!(
+        response.headers().get("link").unwrap(),
+        "</items?per_page=2&q=x>; rel=\"first\", \
+         </items?cursor=ghi%2Fjkl&per_page=2&q=x>; rel=\"next\""
+    );
+
+    Ok(())
+}
@@ -0,0 +1,125 @@
+use {
+    http::{header::CONTENT_TYPE, Request},
+    hyper::{body::Payload, Body},
+    std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        thread,
+        time::Duration,
+    },
+    tsukuyomi::{
+        config::prelude::*,
+        output::ResponseBody,
+        vendor::futures::{future, stream, Future, Stream},
+        App,
+    },
+    tsukuyomi_service::{MakeService, Service},
+};
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Performs the request on a thread pool, and returns the response with the
+/// unbuffered body.
+fn perform_streaming(
+    app: &App,
+    pool: &tokio_threadpool::ThreadPool,
+    body: Body,
+) -> http::Response<ResponseBody> {
+    let mut service = MakeService::<(), Request<Body>>::make_service(app, ())
+        .wait()
+        .unwrap_or_else(|never| match never {});
+    let request = Request::post("/").body(body).unwrap();
+    pool.spawn_handle(future::lazy(move || service.call(request)))
+        .wait()
+        .unwrap_or_else(|never| match never {})
+}
+
+fn chunks(body: ResponseBody) -> impl Iterator<Item = Result<hyper::Chunk, hyper::Error>> {
+    let mut body = body;
+    stream::poll_fn(move || body.poll_data()).wait()
+}
+
+#[test]
+fn uppercase_large_body_with_backpressure() -> tsukuyomi_server::Result<()> {
+    const NUM_CHUNKS: usize = 160; // 10MB
+
+    let app = App::create(
+        path!("/") //
+            .to(
+                endpoint::pipe(|body| body.map(|chunk| chunk.to_ascii_uppercase().into()))
+                    .content_type(mime::TEXT_PLAIN),
+            ),
+    )?;
+    let pool = tokio_threadpool::ThreadPool::new();
+
+    let (mut sender, body) = Body::channel();
+    let sent = Arc::new(AtomicUsize::new(0));
+    let client = thread::spawn({
+        let sent = sent.clone();
+        move || {
+            for _ in 0..NUM_CHUNKS {
+                future::poll_fn(|| sender.poll_ready()).wait().unwrap();
+                sender
+                    .send_data(vec![b'a'; CHUNK_SIZE].into())
+                    .unwrap_or_else(|_| panic!("the request body has been dropped"));
+                sent.fetch_add(CHUNK_SIZE, Ordering::SeqCst);
+            }
+        }
+    });
+
+    let response = perform_streaming(&app, &pool, body);
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()[CONTENT_TYPE], "text/plain");
+
+    let mut received = 0;
+    for (i, chunk) in chunks(response.into_body()).enumerate() {
+        let chunk = chunk?;
+        assert!(chunk.iter().all(|&b| b == b'A'));
+        received += chunk.len();
+        if i % 16 == 0 {
+            // give the client enough time to fill the buffers.
+            thread::sleep(Duration::from_millis(20));
+        }
+        // the chunk in the slot of the channel and the one waiting for it.
+        let in_flight = sent.load(Ordering::SeqCst) - received;
+        assert!(in_flight <= 2 * CHUNK_SIZE, "in flight: {}", in_flight);
+    }
+
+    client.join().unwrap();
+    assert_eq!(received, NUM_CHUNKS * CHUNK_SIZE);
+
+    Ok(())
+}
+
+#[test]
+fn error_in_transform_aborts_response() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/") //
+            .to(endpoint::pipe(|body| {
+                let mut count = 0;
+                body.and_then(move |chunk| {
+                    count += 1;
+                    if count > 3 {
+                        return Err(tsukuyomi::error::bad_request("invalid chunk"));
+                    }
+                    Ok(chunk)
+                })
+            })),
+    )?;
+    let pool = tokio_threadpool::ThreadPool::new();
+
+    let body = Body::wrap_stream(stream::iter_ok::<_, std::io::Error>(
+        (0..10).map(|i| format!("{}\n", i)),
+    ));
+    let response = perform_streaming(&app, &pool, body);
+    assert_eq!(response.status(), 200);
+
+    let chunks: Vec<_> = chunks(response.into_body()).collect();
+    assert_eq!(chunks.len(), 4);
+    assert!(chunks[..3].iter().all(Result::is_ok));
+    assert!(chunks[3].is_err());
+
+    Ok(())
+}
@@ -0,0 +1,176 @@
+use {
+    http::{Request, StatusCode},
+    tsukuyomi::{
+        config::{prelude::*, state},
+        extractor::{self, Extractor},
+        future::TryFuture,
+        input::Input,
+        policy::{self, Authorize, Principal},
+        util::Never,
+        App,
+    },
+};
+
+/// Identifies the caller by `X-User: <id>:<role>,<role>,...`.
+fn authorize() -> Authorize<
+    impl Extractor<
+        Output = (Option<Principal>,),
+        Error = Never,
+        Extract = impl TryFuture<Ok = (Option<Principal>,), Error = Never> + Send + 'static,
+    >,
+> {
+    Authorize::new(extractor::ready(|input| {
+        let principal = input
+            .request
+            .headers()
+            .get("x-user")
+            .and_then(|h| h.to_str().ok())
+            .map(|value| {
+                let mut parts = value.splitn(2, ':');
+                let id = parts.next().unwrap_or("");
+                parts
+                    .next()
+                    .into_iter()
+                    .flat_map(|roles| roles.split(','))
+                    .fold(Principal::new(id), Principal::role)
+            });
+        Ok::<_, Never>((principal,))
+    }))
+}
+
+#[test]
+fn require_role() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        chain![
+            path!("/admin")
+                .to(endpoint::post()
+                    .extract(extractor::body::json())
+                    .call(|_: serde_json::Value| "admin"))
+                .policy(policy::require_role("admin")),
+            path!("/me").to(endpoint::get()
+                .extract(policy::principal())
+                .call(|principal: Principal| principal.id().to_owned())),
+            path!("/public").to(endpoint::get().reply("public")),
+        ]
+        .modify(authorize()),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(
+        Request::post("/admin")
+            .header("x-user", "alice:admin")
+            .header("content-type", "application/json")
+            .body("{}"),
+    )?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.body().to_utf8()?, "admin");
+
+    // denied before the body is read, so the invalid body is not reported.
+    let response = server.perform(
+        Request::post("/admin")
+            .header("x-user", "bob:editor")
+            .header("content-type", "application/json")
+            .body("{"),
+    )?;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let response = server.perform(Request::post("/admin").body("{}"))?;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = server.perform(Request::get("/me").header("x-user", "carol:"))?;
+    assert_eq!(response.body().to_utf8()?, "carol");
+    let response = server.perform(Request::get("/me"))?;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = server.perform(Request::get("/public"))?;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    Ok(())
+}
+
+#[test]
+fn policy_without_authorize_is_unauthenticated() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/admin")
+            .to(endpoint::get().reply("admin"))
+            .policy(policy::require_role("admin")),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(Request::get("/admin").header("x-user", "alice:admin"))?;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    Ok(())
+}
+
+#[test]
+fn custom_policy_with_state() -> tsukuyomi_server::Result<()> {
+    struct Owners(Vec<&'static str>);
+
+    let is_owner = policy::custom("owner", |principal: &Principal, input: &mut Input<'_>| {
+        let owner = input
+            .state::<Owners>()
+            .into_iter()
+            .any(|owners| owners.0.contains(&principal.id()));
+        futures01::future::lazy(move || Ok::<_, tsukuyomi::Error>(owner))
+    });
+
+    let app = App::create(
+        chain![
+            state(Owners(vec!["alice"])),
+            path!("/projects/settings")
+                .to(endpoint::get().reply("settings"))
+                .policy(policy::any_of(vec![
+                    policy::require_role("admin"),
+                    is_owner,
+                ])),
+        ]
+        .modify(authorize()),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(Request::get("/projects/settings").header("x-user", "alice:"))?;
+    assert_eq!(response.status(), StatusCode::OK);
+    let response =
+        server.perform(Request::get("/projects/settings").header("x-user", "bob:admin"))?;
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = server.perform(Request::get("/projects/settings").header("x-user", "carol:"))?;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    Ok(())
+}
+
+#[test]
+fn debug_routes_shows_policies() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        chain![
+            path!("/admin")
+                .to(endpoint::get().reply("admin"))
+                .policy(policy::require_role("admin")),
+            path!("/reports")
+                .to(endpoint::get().reply("reports"))
+                .policy(policy::any_of(vec![
+                    policy::require_role("admin"),
+                    policy::require_role("auditor"),
+                ]))
+                .policy(policy::custom(
+                    "business_hours",
+                    |_: &Principal, _: &mut Input<'_>| { Ok::<_, tsukuyomi::Error>(true) }
+                )),
+            path!("/public").to(endpoint::get().reply("public")),
+        ]
+        .modify(authorize()),
+    )?;
+
+    assert_eq!(
+        app.debug_routes(),
+        "/admin\n\
+         \x20   GET [scope /] policy: role(admin)\n\
+         /reports\n\
+         \x20   GET [scope /] policy: all_of(any_of(role(admin), role(auditor)), business_hours)\n\
+         /public\n\
+         \x20   GET [scope /]\n"
+    );
+
+    Ok(())
+}
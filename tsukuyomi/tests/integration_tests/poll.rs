@@ -0,0 +1,183 @@
+use {
+    http::{Request, StatusCode},
+    hyper::{body::Payload, Body},
+    std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        time::Duration,
+    },
+    tsukuyomi::{
+        config::prelude::*,
+        output::ResponseBody,
+        poll::{self, Channel},
+        rt::MockClock,
+        vendor::futures::{
+            executor::{self, Notify, Spawn},
+            stream, Async, Future, Stream,
+        },
+        App,
+    },
+    tsukuyomi_service::{MakeService, Service},
+};
+
+type ResponseFuture = Box<dyn Future<Item = http::Response<ResponseBody>, Error = ()> + Send>;
+
+/// Records whether the task has been notified.
+#[derive(Default)]
+struct Flag(AtomicBool);
+
+impl Notify for Flag {
+    fn notify(&self, _: usize) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+}
+
+struct Harness {
+    app: App,
+    channel: Channel<String>,
+    clock: MockClock,
+}
+
+impl Harness {
+    fn new(ttl: Duration) -> tsukuyomi::app::Result<Self> {
+        let clock = MockClock::new();
+        let channel = Channel::builder()
+            .capacity(16)
+            .ttl(ttl)
+            .clock(clock.clone())
+            .build();
+        let app = App::create(
+            path!("/events") //
+                .to(poll::endpoint(channel.clone(), Duration::from_secs(30))),
+        )?
+        .with_clock(clock.clone());
+        Ok(Self {
+            app,
+            channel,
+            clock,
+        })
+    }
+
+    fn request(&self, cursor: u64) -> Spawn<ResponseFuture> {
+        let mut service = MakeService::<(), Request<Body>>::make_service(&self.app, ())
+            .wait()
+            .unwrap_or_else(|never| match never {});
+        let request = Request::get(format!("/events?cursor={}", cursor))
+            .body(Body::empty())
+            .unwrap();
+        let future: ResponseFuture =
+            Box::new(service.call(request).map_err(|never| match never {}));
+        executor::spawn(future)
+    }
+}
+
+fn poll(request: &mut Spawn<ResponseFuture>, flag: &Arc<Flag>) -> Option<serde_json::Value> {
+    match request.poll_future_notify(flag, 0) {
+        Ok(Async::Ready(response)) => {
+            assert_eq!(response.status(), StatusCode::OK);
+            let mut body = response.into_body();
+            let body = stream::poll_fn(move || body.poll_data())
+                .concat2()
+                .wait()
+                .unwrap();
+            Some(serde_json::from_slice(&body).unwrap())
+        }
+        Ok(Async::NotReady) => None,
+        Err(()) => unreachable!(),
+    }
+}
+
+#[test]
+fn publish_then_poll() -> tsukuyomi_server::Result<()> {
+    let harness = Harness::new(Duration::from_secs(60))?;
+    let flag = Arc::new(Flag::default());
+
+    let mut request = harness.request(0);
+    assert!(poll(&mut request, &flag).is_none());
+    harness.clock.advance(Duration::from_secs(30));
+    assert_eq!(
+        poll(&mut request, &flag).expect("should be timed out"),
+        serde_json::json!({ "next_cursor": 1, "events": [], "gap": false })
+    );
+
+    assert_eq!(harness.channel.publish("first".into()), 1);
+
+    let mut request = harness.request(1);
+    assert_eq!(
+        poll(&mut request, &flag).expect("should respond immediately"),
+        serde_json::json!({ "next_cursor": 2, "events": ["first"], "gap": false })
+    );
+
+    Ok(())
+}
+
+#[test]
+fn publish_wakes_waiting_request() -> tsukuyomi_server::Result<()> {
+    let harness = Harness::new(Duration::from_secs(60))?;
+    let flag = Arc::new(Flag::default());
+
+    let mut request = harness.request(0);
+    assert!(poll(&mut request, &flag).is_none());
+    assert!(!flag.0.load(Ordering::SeqCst));
+
+    harness.channel.publish("hello".into());
+    assert!(flag.0.load(Ordering::SeqCst));
+    assert_eq!(
+        poll(&mut request, &flag).expect("should be woken up"),
+        serde_json::json!({ "next_cursor": 2, "events": ["hello"], "gap": false })
+    );
+
+    Ok(())
+}
+
+#[test]
+fn cursor_skips_seen_events() -> tsukuyomi_server::Result<()> {
+    let harness = Harness::new(Duration::from_secs(60))?;
+    let flag = Arc::new(Flag::default());
+
+    for event in &["a", "b", "c"] {
+        harness.channel.publish(event.to_string());
+    }
+
+    let mut request = harness.request(2);
+    assert_eq!(
+        poll(&mut request, &flag).unwrap(),
+        serde_json::json!({ "next_cursor": 4, "events": ["b", "c"], "gap": false })
+    );
+
+    let mut request = harness.request(4);
+    assert!(poll(&mut request, &flag).is_none());
+    harness.channel.publish("d".into());
+    assert_eq!(
+        poll(&mut request, &flag).unwrap(),
+        serde_json::json!({ "next_cursor": 5, "events": ["d"], "gap": false })
+    );
+
+    Ok(())
+}
+
+#[test]
+fn expired_events_are_reported_as_gap() -> tsukuyomi_server::Result<()> {
+    let harness = Harness::new(Duration::from_secs(10))?;
+    let flag = Arc::new(Flag::default());
+
+    harness.channel.publish("old".into());
+    harness.clock.advance(Duration::from_secs(20));
+    harness.channel.publish("new".into());
+
+    let mut request = harness.request(1);
+    assert_eq!(
+        poll(&mut request, &flag).unwrap(),
+        serde_json::json!({ "next_cursor": 3, "events": ["new"], "gap": true })
+    );
+
+    let mut request = harness.request(2);
+    assert_eq!(
+        poll(&mut request, &flag).unwrap(),
+        serde_json::json!({ "next_cursor": 3, "events": ["new"], "gap": false })
+    );
+
+    Ok(())
+}
@@ -0,0 +1,248 @@
+use {
+    http::{Request, Response, StatusCode},
+    hyper::Body,
+    std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    },
+    tsukuyomi::{
+        config::prelude::*,
+        extractor,
+        output::ResponseBody,
+        pool::{AcquireTimeout, Pool, Pooled, PooledResource},
+        rt::MockClock,
+        vendor::futures::{
+            executor::{self, Notify, Spawn},
+            task::{self, Task},
+            Async, Future, Poll,
+        },
+        App,
+    },
+    tsukuyomi_service::{MakeService, Service},
+};
+
+/// A pool that hands out up to the specified number of connections.
+#[derive(Clone, Default)]
+struct MockPool(Arc<Mutex<MockState>>);
+
+#[derive(Default)]
+struct MockState {
+    available: usize,
+    waiters: Vec<Task>,
+}
+
+impl MockPool {
+    fn new(available: usize) -> Self {
+        let pool = Self::default();
+        pool.release(available);
+        pool
+    }
+
+    fn release(&self, n: usize) {
+        let mut state = self.0.lock().unwrap();
+        state.available += n;
+        for waiter in state.waiters.drain(..) {
+            waiter.notify();
+        }
+    }
+}
+
+struct Conn(MockPool);
+
+impl Drop for Conn {
+    fn drop(&mut self) {
+        self.0.release(1);
+    }
+}
+
+struct AcquireConn(MockPool);
+
+impl Future for AcquireConn {
+    type Item = Conn;
+    type Error = String;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let mut state = (self.0).0.lock().unwrap();
+        if state.available == 0 {
+            state.waiters.push(task::current());
+            return Ok(Async::NotReady);
+        }
+        state.available -= 1;
+        Ok(Async::Ready(Conn(self.0.clone())))
+    }
+}
+
+impl Pool for MockPool {
+    type Resource = Conn;
+    type Error = String;
+    type Acquire = AcquireConn;
+
+    fn acquire(&self) -> Self::Acquire {
+        AcquireConn(self.clone())
+    }
+
+    fn idle(&self) -> Option<usize> {
+        Some(self.0.lock().unwrap().available)
+    }
+}
+
+struct NoopNotify;
+
+impl Notify for NoopNotify {
+    fn notify(&self, _: usize) {}
+}
+
+type ResponseFuture = Box<dyn Future<Item = Response<ResponseBody>, Error = ()> + Send>;
+
+struct Harness {
+    app: App,
+    pool: MockPool,
+    resource: PooledResource<MockPool>,
+    clock: MockClock,
+}
+
+impl Harness {
+    fn new(available: usize) -> tsukuyomi::app::Result<Self> {
+        let clock = MockClock::new();
+        let pool = MockPool::new(available);
+        let resource = PooledResource::new(pool.clone())
+            .acquire_timeout(Duration::from_secs(1))
+            .retry_after(Duration::from_secs(3));
+        let app = App::create(chain![
+            tsukuyomi::config::state(resource.clone()),
+            path!("/conn") //
+                .to(endpoint::get()
+                    .extract(extractor::pooled::<MockPool>())
+                    .call({
+                        let resource = resource.clone();
+                        move |_conn: Pooled<MockPool>| {
+                            format!("in_use={}", resource.metrics().in_use)
+                        }
+                    })),
+            path!("/panic") //
+                .to(endpoint::get()
+                    .extract(extractor::pooled::<MockPool>())
+                    .call(|_conn: Pooled<MockPool>| -> &'static str { panic!("oops") })),
+            mount("/reports").with(chain![
+                AcquireTimeout::new(Duration::from_secs(10)),
+                path!("/") //
+                    .to(endpoint::get()
+                        .extract(extractor::pooled::<MockPool>())
+                        .call(|_conn: Pooled<MockPool>| "report")),
+            ]),
+        ])?
+        .with_clock(clock.clone())
+        .recover_panics(true);
+        Ok(Self {
+            app,
+            pool,
+            resource,
+            clock,
+        })
+    }
+
+    fn request(&self, path: &str) -> Spawn<ResponseFuture> {
+        let mut service = MakeService::<(), Request<Body>>::make_service(&self.app, ())
+            .wait()
+            .unwrap_or_else(|never| match never {});
+        let request = Request::get(path).body(Body::empty()).unwrap();
+        let future: ResponseFuture =
+            Box::new(service.call(request).map_err(|never| match never {}));
+        executor::spawn(future)
+    }
+}
+
+fn poll(request: &mut Spawn<ResponseFuture>) -> Option<Response<ResponseBody>> {
+    match request.poll_future_notify(&Arc::new(NoopNotify), 0) {
+        Ok(Async::Ready(response)) => Some(response),
+        Ok(Async::NotReady) => None,
+        Err(()) => unreachable!(),
+    }
+}
+
+#[test]
+fn acquire_and_release() -> tsukuyomi_server::Result<()> {
+    let harness = Harness::new(2)?;
+    let mut server = tsukuyomi_server::test::server(harness.app)?;
+
+    let response = server.perform("/conn")?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.body().to_utf8()?, "in_use=1");
+
+    let metrics = harness.resource.metrics();
+    assert_eq!(metrics.in_use, 0);
+    assert_eq!(metrics.idle, Some(2));
+    assert_eq!(metrics.acquired, 1);
+    assert_eq!(metrics.waiting, 0);
+
+    Ok(())
+}
+
+#[test]
+fn exhaustion_timeout() -> tsukuyomi_server::Result<()> {
+    let harness = Harness::new(0)?;
+
+    let mut request = harness.request("/conn");
+    let mut report = harness.request("/reports");
+    assert!(poll(&mut request).is_none());
+    assert!(poll(&mut report).is_none());
+    assert_eq!(harness.resource.metrics().waiting, 2);
+
+    harness.clock.advance(Duration::from_secs(1));
+    let response = poll(&mut request).expect("should be timed out");
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(response.headers().get("retry-after").unwrap(), "3");
+
+    // the timeout is extended in the scope.
+    assert!(poll(&mut report).is_none());
+    harness.clock.advance(Duration::from_secs(9));
+    let response = poll(&mut report).expect("should be timed out");
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+    let metrics = harness.resource.metrics();
+    assert_eq!(metrics.timeouts, 2);
+    assert_eq!(metrics.waiting, 0);
+    assert_eq!(metrics.acquired, 0);
+
+    Ok(())
+}
+
+#[test]
+fn wait_time_gauges() -> tsukuyomi_server::Result<()> {
+    let harness = Harness::new(0)?;
+
+    let mut request = harness.request("/conn");
+    assert!(poll(&mut request).is_none());
+    harness.clock.advance(Duration::from_millis(300));
+    harness.pool.release(1);
+    let response = poll(&mut request).expect("should be acquired");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let metrics = harness.resource.metrics();
+    assert_eq!(metrics.acquired, 1);
+    assert_eq!(metrics.wait_time, Duration::from_millis(300));
+    assert_eq!(metrics.max_wait_time, Duration::from_millis(300));
+    assert!(metrics
+        .entries()
+        .contains(&("pool_wait_microseconds_total", 300_000)));
+
+    Ok(())
+}
+
+#[test]
+fn release_on_panic() -> tsukuyomi_server::Result<()> {
+    let harness = Harness::new(1)?;
+    let mut server = tsukuyomi_server::test::server(harness.app)?;
+
+    let response = server.perform("/panic")?;
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+    let metrics = harness.resource.metrics();
+    assert_eq!(metrics.in_use, 0);
+    assert_eq!(metrics.idle, Some(1));
+
+    let response = server.perform("/conn")?;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    Ok(())
+}
@@ -0,0 +1,179 @@
+use {
+    http::{
+        header::{ALLOW, CONTENT_TYPE},
+        Request, StatusCode,
+    },
+    serde::Deserialize,
+    std::{borrow::Cow, fmt},
+    tsukuyomi::{
+        config::prelude::*,
+        extractor,
+        output::problem::{IntoProblem, Problem},
+        App,
+    },
+    tsukuyomi_server::test::ResponseExt,
+};
+
+#[derive(Debug)]
+enum TransferError {
+    InsufficientFunds { balance: u32, cost: u32 },
+    Frozen,
+}
+
+impl fmt::Display for TransferError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransferError::InsufficientFunds { balance, cost } => write!(
+                f,
+                "your current balance is {}, but that costs {}",
+                balance, cost
+            ),
+            TransferError::Frozen => f.write_str("the account is frozen"),
+        }
+    }
+}
+
+impl IntoProblem for TransferError {
+    fn status(&self) -> StatusCode {
+        StatusCode::FORBIDDEN
+    }
+
+    fn type_uri(&self) -> Option<Cow<'static, str>> {
+        match self {
+            TransferError::InsufficientFunds { .. } => {
+                Some("https://example.com/probs/out-of-credit".into())
+            }
+            TransferError::Frozen => None,
+        }
+    }
+
+    fn extensions(&self, problem: Problem) -> Problem {
+        match self {
+            TransferError::InsufficientFunds { balance, .. } => {
+                problem.extension("balance", balance)
+            }
+            TransferError::Frozen => problem,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Transfer {
+    amount: u32,
+}
+
+fn app() -> tsukuyomi::app::Result<App> {
+    Ok(App::create(chain![
+        path!("/accounts/:id") //
+            .to(endpoint::get().call(|id: u32| format!("account {}", id))),
+        path!("/accounts/:id/transfer") //
+            .to(endpoint::post().extract(extractor::body::json()).call(
+                |id: u32, transfer: Transfer| -> Result<String, tsukuyomi::Error> {
+                    match id {
+                        1 => Err(TransferError::InsufficientFunds {
+                            balance: 30,
+                            cost: transfer.amount,
+                        }
+                        .into_problem()
+                        .into()),
+                        2 => Err(TransferError::Frozen.into_problem().into()),
+                        _ => Ok("transferred".into()),
+                    }
+                }
+            )),
+        path!("/crash") //
+            .to(
+                endpoint::get().call(|| -> Result<&'static str, failure::Error> {
+                    Err(failure::format_err!("the database is down"))
+                })
+            ),
+    ])?
+    .problem_details(true))
+}
+
+#[test]
+fn not_found() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+
+    let response = server.perform("/users")?;
+    assert_eq!(response.status(), 404);
+    assert_eq!(response.header(CONTENT_TYPE)?, "application/problem+json");
+    assert_eq!(
+        response.body().to_utf8()?,
+        r#"{"type":"about:blank","title":"Not Found","status":404}"#
+    );
+
+    Ok(())
+}
+
+#[test]
+fn method_not_allowed() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+
+    let response = server.perform(Request::delete("/accounts/1"))?;
+    assert_eq!(response.status(), 405);
+    assert_eq!(response.header(CONTENT_TYPE)?, "application/problem+json");
+    assert_eq!(response.header(ALLOW)?, "GET");
+    assert_eq!(
+        response.body().to_utf8()?,
+        r#"{"type":"about:blank","title":"Method Not Allowed","status":405,"allowed":["GET"]}"#
+    );
+
+    Ok(())
+}
+
+#[test]
+fn custom_error() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+
+    let response = server.perform(
+        Request::post("/accounts/1/transfer")
+            .header(CONTENT_TYPE, "application/json")
+            .body(r#"{"amount":50}"#),
+    )?;
+    assert_eq!(response.status(), 403);
+    assert_eq!(response.header(CONTENT_TYPE)?, "application/problem+json");
+    assert_eq!(
+        response.body().to_utf8()?,
+        r#"{"type":"https://example.com/probs/out-of-credit","title":"Forbidden","status":403,"detail":"your current balance is 30, but that costs 50","balance":30}"#
+    );
+
+    let response = server.perform(
+        Request::post("/accounts/2/transfer")
+            .header(CONTENT_TYPE, "application/json")
+            .body(r#"{"amount":50}"#),
+    )?;
+    assert_eq!(response.status(), 403);
+    assert_eq!(
+        response.body().to_utf8()?,
+        r#"{"type":"about:blank","title":"Forbidden","status":403,"detail":"the account is frozen"}"#
+    );
+
+    Ok(())
+}
+
+#[test]
+fn framework_errors() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+
+    let response = server.perform(
+        Request::post("/accounts/1/transfer")
+            .header(CONTENT_TYPE, "application/json")
+            .body("{"),
+    )?;
+    assert_eq!(response.status(), 400);
+    assert_eq!(response.header(CONTENT_TYPE)?, "application/problem+json");
+    let problem: serde_json::Value = serde_json::from_str(&response.body().to_utf8()?)?;
+    assert_eq!(problem["title"], "Bad Request");
+    assert!(problem["detail"].is_string());
+
+    let response = server.perform("/crash")?;
+    assert_eq!(response.status(), 500);
+    assert_eq!(response.header(CONTENT_TYPE)?, "application/problem+json");
+    assert_eq!(
+        response.body().to_utf8()?,
+        r#"{"type":"about:blank","title":"Internal Server Error","status":500}"#
+    );
+
+    Ok(())
+}
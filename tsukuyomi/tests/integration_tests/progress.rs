@@ -0,0 +1,81 @@
+use {
+    std::{
+        sync::{Arc, Mutex},
+        time::{Duration, Instant},
+    },
+    tsukuyomi::{
+        config::prelude::*, //
+        extractor,
+        input::{body::RequestBody, progress::UploadProgress},
+        vendor::futures::{stream, Future, Stream},
+        App,
+    },
+};
+
+fn delayed_body(chunks: &'static [&'static str], delay: Duration) -> hyper::Body {
+    hyper::Body::wrap_stream(stream::iter_ok(chunks).and_then(move |chunk| {
+        tokio_timer::Delay::new(Instant::now() + delay).map(move |()| *chunk)
+    }))
+}
+
+#[test]
+fn progress_increases_while_receiving() -> tsukuyomi_server::Result<()> {
+    let observed = Arc::new(Mutex::new(None));
+    let app = App::create({
+        let observed = observed.clone();
+        path!("/") //
+            .to(endpoint::post()
+                .extract(extractor::body::progress(Some(Duration::from_secs(5))))
+                .extract(extractor::body::stream())
+                .call_async(move |progress: UploadProgress, body: RequestBody| {
+                    *observed.lock().unwrap() = Some(progress.clone());
+                    body.fold(vec![], move |mut snapshots, _chunk| {
+                        snapshots.push(progress.received());
+                        Ok::<_, hyper::Error>(snapshots)
+                    })
+                    .map(|snapshots| format!("{:?}", snapshots))
+                }))
+    })?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(http::Request::post("/").body(delayed_body(
+        &["abcd", "efgh", "ijkl"],
+        Duration::from_millis(20),
+    )))?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "[4, 8, 12]");
+
+    let progress = observed.lock().unwrap().take().expect("should be observed");
+    assert_eq!(progress.received(), 12);
+    assert!(progress.is_finished());
+    assert!(progress.last_chunk_at().unwrap() >= progress.started_at());
+
+    Ok(())
+}
+
+#[test]
+fn stalled_upload_is_aborted() -> tsukuyomi_server::Result<()> {
+    let app = App::create({
+        path!("/") //
+            .to(endpoint::post()
+                .extract(extractor::body::progress(Some(Duration::from_millis(50))))
+                .extract(extractor::body::read_all())
+                .call(|progress: UploadProgress, body: bytes::Bytes| {
+                    format!("{}:{}", progress.received(), body.len())
+                }))
+    })?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(
+        http::Request::post("/").body(delayed_body(&["abcd", "efgh"], Duration::from_millis(10))),
+    )?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "8:8");
+
+    let response = server.perform(
+        http::Request::post("/").body(delayed_body(&["abcd", "efgh"], Duration::from_millis(300))),
+    )?;
+    assert_eq!(response.status(), 408);
+
+    Ok(())
+}
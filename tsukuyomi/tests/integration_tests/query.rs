@@ -0,0 +1,157 @@
+use {
+    http::{Request, StatusCode},
+    serde::Deserialize,
+    tsukuyomi::{
+        config::prelude::*,
+        extractor::{self, Extractor},
+        future::TryFuture,
+        util::Never,
+        App,
+    },
+};
+
+/// Formats the pairs yielded by `Input::query` as `key=value`, or `key` for the bare keys.
+fn pairs() -> impl Extractor<
+    Output = (String,),
+    Error = Never,
+    Extract = impl TryFuture<Ok = (String,), Error = Never> + Send + 'static,
+> {
+    extractor::ready(|input| {
+        let pairs: Vec<_> = input
+            .query()
+            .map(|(key, value)| match value {
+                Some(value) => format!("{}={}", key, value),
+                None => key.into_owned(),
+            })
+            .collect();
+        Ok((pairs.join(","),))
+    })
+}
+
+#[derive(Debug, Deserialize)]
+struct Search {
+    q: String,
+    page: Option<u32>,
+}
+
+fn app() -> tsukuyomi::app::Result<App> {
+    App::create(chain![
+        path!("/pairs").to(endpoint::get().extract(pairs()).call(|pairs| pairs)),
+        path!("/tags").to(endpoint::get()
+            .extract(extractor::ready(|input| {
+                let query = input.query();
+                let tags: Vec<_> = query.all("tag").collect();
+                Ok::<_, Never>((format!(
+                    "{};first={:?};debug={}",
+                    tags.join(","),
+                    query.first("tag"),
+                    query.has_flag("debug"),
+                ),))
+            }))
+            .call(|tags| tags)),
+        path!("/search").to(endpoint::get()
+            .extract(extractor::query())
+            .call(|search: Search| format!("{}:{:?}", search.q, search.page))),
+    ])
+}
+
+#[test]
+fn duplicate_keys_in_order() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+
+    let response = server.perform(Request::get("/pairs?b=1&a=2&b=3&&a=4"))?;
+    assert_eq!(response.body().to_utf8()?, "b=1,a=2,b=3,a=4");
+
+    let response = server.perform(Request::get("/tags?tag=rust&sort=new&tag=http&tag=web"))?;
+    assert_eq!(
+        response.body().to_utf8()?,
+        "rust,http,web;first=Some(\"rust\");debug=false"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn bare_flags() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+
+    let response = server.perform(Request::get("/pairs?debug&verbose=&level=2"))?;
+    assert_eq!(response.body().to_utf8()?, "debug,verbose=,level=2");
+
+    let response = server.perform(Request::get("/tags?debug&tag"))?;
+    assert_eq!(response.body().to_utf8()?, ";first=Some(\"\");debug=true");
+
+    // a key with `=` is not a flag.
+    let response = server.perform(Request::get("/tags?debug="))?;
+    assert_eq!(response.body().to_utf8()?, ";first=None;debug=false");
+
+    Ok(())
+}
+
+#[test]
+fn plus_as_space() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+
+    let response = server.perform(Request::get("/pairs?hello+world=a+b%2Bc%20d"))?;
+    assert_eq!(response.body().to_utf8()?, "hello world=a b+c d");
+
+    let response = server.perform(Request::get("/search?q=a+b%2Bc%20d&page=2"))?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.body().to_utf8()?, "a b+c d:Some(2)");
+
+    Ok(())
+}
+
+#[test]
+fn invalid_percent_encoding() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+
+    let response = server.perform(Request::get("/pairs?q=100%&name=%E3%81"))?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.body().to_utf8()?, "q=100\u{FFFD},name=\u{FFFD}");
+
+    let response = server.perform(Request::get("/search?q=100%"))?;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = server.perform(Request::get("/search?q=%E3%81"))?;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    // the other errors are reported as before.
+    let response = server.perform(Request::get("/search?q=rust&page=first"))?;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = server.perform(Request::get("/search?q=rust&unknown=1"))?;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    Ok(())
+}
+
+#[test]
+fn sequence_of_pairs() -> tsukuyomi_server::Result<()> {
+    let app = App::create(chain![
+        path!("/pairs").to(endpoint::get()
+            .extract(extractor::query())
+            .call(|pairs: Vec<(String, String)>| format!("{:?}", pairs))),
+        path!("/points").to(endpoint::get()
+            .extract(extractor::query())
+            .call(|points: Vec<(String, u32)>| format!("{:?}", points))),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(Request::get("/pairs?b=1&a=2&b=3&debug"))?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.body().to_utf8()?,
+        r#"[("b", "1"), ("a", "2"), ("b", "3"), ("debug", "")]"#
+    );
+
+    let response = server.perform(Request::get("/points?x=1&y=2"))?;
+    assert_eq!(response.body().to_utf8()?, r#"[("x", 1), ("y", 2)]"#);
+
+    let response = server.perform(Request::get("/points?x=1&y=two"))?;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert!(response
+        .body()
+        .to_utf8()?
+        .starts_with("query parameter `y': "));
+
+    Ok(())
+}
@@ -0,0 +1,175 @@
+use {
+    http::{header::RETRY_AFTER, Request, StatusCode},
+    hyper::Body,
+    std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    },
+    tsukuyomi::{
+        config::prelude::*,
+        modifiers::{self, QueueLimit},
+        output::ResponseBody,
+        rt::MockClock,
+        vendor::futures::{
+            executor::{self, Notify, Spawn},
+            sync::oneshot,
+            Async, Future,
+        },
+        App,
+    },
+    tsukuyomi_service::{MakeService, Service},
+};
+
+type ResponseFuture = Box<dyn Future<Item = http::Response<ResponseBody>, Error = ()> + Send>;
+
+struct Noop;
+
+impl Notify for Noop {
+    fn notify(&self, _: usize) {}
+}
+
+/// A set of the requests sent to the application, each of which is polled manually.
+struct Harness {
+    app: App,
+    /// The senders to complete the handlers that have started, in order of starting.
+    started: Arc<Mutex<Vec<oneshot::Sender<()>>>>,
+    limit: QueueLimit,
+}
+
+impl Harness {
+    fn new(limit: QueueLimit, clock: MockClock) -> tsukuyomi::app::Result<Self> {
+        let started = Arc::new(Mutex::new(vec![]));
+        let app = App::create(
+            path!("/export") //
+                .to(endpoint::get().call_async({
+                    let started = started.clone();
+                    move || {
+                        let (tx, rx) = oneshot::channel();
+                        started.lock().unwrap().push(tx);
+                        rx.map(|()| "exported")
+                            .map_err(tsukuyomi::error::internal_server_error)
+                    }
+                }))
+                .modify(limit.clone()),
+        )?
+        .with_clock(clock);
+        Ok(Self {
+            app,
+            started,
+            limit,
+        })
+    }
+
+    fn request(&self) -> Spawn<ResponseFuture> {
+        let mut service = MakeService::<(), Request<Body>>::make_service(&self.app, ())
+            .wait()
+            .unwrap_or_else(|never| match never {});
+        let request = Request::get("/export").body(Body::empty()).unwrap();
+        let future: ResponseFuture =
+            Box::new(service.call(request).map_err(|never| match never {}));
+        executor::spawn(future)
+    }
+
+    fn started(&self) -> usize {
+        self.started.lock().unwrap().len()
+    }
+
+    fn complete(&self, i: usize) {
+        let tx = std::mem::replace(&mut self.started.lock().unwrap()[i], oneshot::channel().0);
+        tx.send(()).unwrap();
+    }
+}
+
+fn poll(request: &mut Spawn<ResponseFuture>) -> Option<http::Response<ResponseBody>> {
+    match request.poll_future_notify(&Arc::new(Noop), 0) {
+        Ok(Async::Ready(response)) => Some(response),
+        Ok(Async::NotReady) => None,
+        Err(()) => unreachable!(),
+    }
+}
+
+#[test]
+fn queued_requests_run_in_order() -> tsukuyomi_server::Result<()> {
+    let limit = modifiers::queue_limit(1, 1, Duration::from_secs(10));
+    let harness = Harness::new(limit, MockClock::new())?;
+
+    let mut first = harness.request();
+    let mut second = harness.request();
+    let mut third = harness.request();
+
+    assert!(poll(&mut first).is_none());
+    assert!(poll(&mut second).is_none());
+    assert_eq!(harness.started(), 1);
+    assert_eq!((harness.limit.running(), harness.limit.queued()), (1, 1));
+
+    let response = poll(&mut third).expect("should be rejected immediately");
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(response.headers()[RETRY_AFTER], "10");
+
+    harness.complete(0);
+    let response = poll(&mut first).expect("should be completed");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!((harness.limit.running(), harness.limit.queued()), (1, 0));
+
+    assert!(poll(&mut second).is_none());
+    assert_eq!(harness.started(), 2);
+    harness.complete(1);
+    let response = poll(&mut second).expect("should be completed");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!((harness.limit.running(), harness.limit.queued()), (0, 0));
+
+    Ok(())
+}
+
+#[test]
+fn cancelled_waiter_frees_its_slot() -> tsukuyomi_server::Result<()> {
+    let limit = modifiers::queue_limit(1, 1, Duration::from_secs(10));
+    let harness = Harness::new(limit, MockClock::new())?;
+
+    let mut first = harness.request();
+    let mut second = harness.request();
+    assert!(poll(&mut first).is_none());
+    assert!(poll(&mut second).is_none());
+    assert_eq!(harness.limit.queued(), 1);
+
+    // the client of the second request has disconnected.
+    drop(second);
+    assert_eq!(harness.limit.queued(), 0);
+
+    let mut third = harness.request();
+    assert!(poll(&mut third).is_none());
+    assert_eq!(harness.limit.queued(), 1);
+
+    harness.complete(0);
+    assert!(poll(&mut first).is_some());
+    assert!(poll(&mut third).is_none());
+    assert_eq!(harness.started(), 2);
+    harness.complete(1);
+    assert_eq!(poll(&mut third).unwrap().status(), StatusCode::OK);
+
+    Ok(())
+}
+
+#[test]
+fn waiter_times_out() -> tsukuyomi_server::Result<()> {
+    let clock = MockClock::new();
+    let limit = modifiers::queue_limit(1, 1, Duration::from_millis(1500));
+    let harness = Harness::new(limit, clock.clone())?;
+
+    let mut first = harness.request();
+    let mut second = harness.request();
+    assert!(poll(&mut first).is_none());
+    assert!(poll(&mut second).is_none());
+
+    clock.advance(Duration::from_secs(2));
+    let response = poll(&mut second).expect("should be timed out");
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(response.headers()[RETRY_AFTER], "2");
+    assert_eq!((harness.limit.running(), harness.limit.queued()), (1, 0));
+
+    harness.complete(0);
+    assert!(poll(&mut first).is_some());
+    assert_eq!((harness.limit.running(), harness.limit.queued()), (0, 0));
+
+    Ok(())
+}
@@ -0,0 +1,95 @@
+use {
+    http::{
+        header::{ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, RANGE},
+        Request, StatusCode,
+    },
+    tsukuyomi::{config::prelude::*, output, App},
+};
+
+fn app() -> tsukuyomi::app::Result<App> {
+    App::create(
+        path!("/audio") //
+            .to(endpoint::allow_only("GET, POST")?.call(|| {
+                let body: Vec<u8> = (0..1000u32).map(|i| (i % 256) as u8).collect();
+                output::ranged(body, "audio/wav".parse().unwrap())
+            })),
+    )
+}
+
+fn expected(range: std::ops::Range<u32>) -> Vec<u8> {
+    range.map(|i| (i % 256) as u8).collect()
+}
+
+#[test]
+fn without_range() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+
+    let response = server.perform("/audio")?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[ACCEPT_RANGES], "bytes");
+    assert_eq!(response.headers()[CONTENT_TYPE], "audio/wav");
+    assert_eq!(response.headers()[CONTENT_LENGTH], "1000");
+    assert!(!response.headers().contains_key(CONTENT_RANGE));
+
+    Ok(())
+}
+
+#[test]
+fn suffix_range() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+
+    let response = server.perform(Request::get("/audio").header(RANGE, "bytes=-100"))?;
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(response.headers()[CONTENT_RANGE], "bytes 900-999/1000");
+    assert_eq!(response.headers()[CONTENT_LENGTH], "100");
+    assert_eq!(response.headers()[CONTENT_TYPE], "audio/wav");
+    assert_eq!(response.body().to_bytes(), &expected(900..1000)[..]);
+
+    Ok(())
+}
+
+#[test]
+fn open_ended_range() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+
+    let response = server.perform(Request::get("/audio").header(RANGE, "bytes=100-"))?;
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(response.headers()[CONTENT_RANGE], "bytes 100-999/1000");
+    assert_eq!(response.body().to_bytes(), &expected(100..1000)[..]);
+
+    let response = server.perform(Request::get("/audio").header(RANGE, "bytes=10-19"))?;
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(response.headers()[CONTENT_RANGE], "bytes 10-19/1000");
+    assert_eq!(response.body().to_bytes(), &expected(10..20)[..]);
+
+    Ok(())
+}
+
+#[test]
+fn ignored_ranges() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+
+    for value in &["bytes=abc", "bytes=20-10", "items=0-10", "bytes=0-1,5-6"] {
+        let response = server.perform(Request::get("/audio").header(RANGE, *value))?;
+        assert_eq!(response.status(), StatusCode::OK, "{}", value);
+        assert_eq!(response.body().to_bytes().len(), 1000);
+    }
+
+    // Range is applied only to GET.
+    let response = server.perform(Request::post("/audio").header(RANGE, "bytes=0-9"))?;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    Ok(())
+}
+
+#[test]
+fn unsatisfiable_range() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+
+    let response = server.perform(Request::get("/audio").header(RANGE, "bytes=1000-"))?;
+    assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+    assert_eq!(response.headers()[CONTENT_RANGE], "bytes */1000");
+    assert!(response.body().to_bytes().is_empty());
+
+    Ok(())
+}
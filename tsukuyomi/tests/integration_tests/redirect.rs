@@ -0,0 +1,129 @@
+use {
+    http::{Method, Request, Response, StatusCode},
+    tsukuyomi::{config::prelude::*, extractor, App},
+};
+
+fn redirect(status: u16, location: &'static str) -> Response<()> {
+    Response::builder()
+        .status(status)
+        .header("location", location)
+        .body(())
+        .unwrap()
+}
+
+#[test]
+fn see_other_after_post() -> tsukuyomi_server::Result<()> {
+    let app = App::create(chain![
+        path!("/login") //
+            .to(endpoint::post()
+                .extract(extractor::body::plain())
+                .call(|name: String| {
+                    Response::builder()
+                        .status(303)
+                        .header("location", "/home")
+                        .header("set-cookie", format!("session={}", name))
+                        .body(())
+                        .unwrap()
+                })),
+        path!("/home") //
+            .to(endpoint::get().extract(extractor::header::headers()).call(
+                |headers: http::HeaderMap| {
+                    headers
+                        .get("cookie")
+                        .map_or("anonymous", |_| "logged in")
+                        .to_owned()
+                }
+            )),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let followed = server.perform_follow(Request::post("/login").body("alice"))?;
+    assert_eq!(followed.response.status(), 200);
+    assert_eq!(followed.response.body().to_utf8()?, "logged in");
+    assert_eq!(followed.redirects.len(), 1);
+    assert_eq!(followed.redirects[0].0, StatusCode::SEE_OTHER);
+    assert_eq!(followed.redirects[0].1, "/home");
+
+    Ok(())
+}
+
+#[test]
+fn relative_location() -> tsukuyomi_server::Result<()> {
+    let app = App::create(chain![
+        path!("/docs/old") //
+            .to(endpoint::call(|| redirect(301, "new?lang=en"))),
+        path!("/docs/new") //
+            .to(endpoint::call(|| redirect(302, "../index"))),
+        path!("/index") //
+            .to(endpoint::get()
+                .extract(extractor::uri())
+                .call(|uri: http::Uri| uri.to_string())),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let followed = server.perform_follow("/docs/old")?;
+    assert_eq!(followed.response.status(), 200);
+    assert_eq!(followed.response.body().to_utf8()?, "/index");
+    let redirects: Vec<_> = followed
+        .redirects
+        .iter()
+        .map(|(status, location)| (status.as_u16(), location.to_string()))
+        .collect();
+    assert_eq!(
+        redirects,
+        vec![
+            (301, "/docs/new?lang=en".to_owned()),
+            (302, "/index".to_owned()),
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn temporary_redirect_preserves_method() -> tsukuyomi_server::Result<()> {
+    let app = App::create(chain![
+        path!("/upload") //
+            .to(endpoint::call(|| redirect(307, "/upload/v2"))),
+        path!("/upload/v2") //
+            .to(endpoint::any()
+                .extract(extractor::method())
+                .extract(extractor::body::plain())
+                .call(|method: Method, body: String| format!("{} {}", method, body))),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let followed = server.perform_follow(Request::put("/upload").body("data"))?;
+    assert_eq!(followed.response.body().to_utf8()?, "PUT data");
+
+    Ok(())
+}
+
+#[test]
+fn redirect_loop() -> tsukuyomi_server::Result<()> {
+    let app = App::create(chain![
+        path!("/ping") //
+            .to(endpoint::call(|| redirect(302, "/pong"))),
+        path!("/pong") //
+            .to(endpoint::call(|| redirect(302, "/ping"))),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let err = server.perform_follow("/ping").unwrap_err().to_string();
+    assert!(err.contains("too many redirects (limit = 5)"), "{}", err);
+    assert!(
+        err.contains("/ping -(302)-> /pong -(302)-> /ping"),
+        "{}",
+        err
+    );
+
+    let mut session = server.new_session()?.max_redirects(1);
+    let err = session.perform_follow("/ping").unwrap_err().to_string();
+    assert!(
+        err.contains("(limit = 1): /ping -(302)-> /pong -(302)-> /ping"),
+        "{}",
+        err
+    );
+
+    Ok(())
+}
@@ -0,0 +1,187 @@
+use {
+    http::{header::LOCATION, Request},
+    std::{
+        fs,
+        path::PathBuf,
+        sync::atomic::{AtomicUsize, Ordering},
+    },
+    tsukuyomi::{
+        app::redirects::{self, Redirect, RedirectTable},
+        config::prelude::*,
+        dynamic::{ConfigSet, DynamicConfig},
+        App,
+    },
+};
+
+fn app(table: redirects::Table) -> tsukuyomi::app::Result<App> {
+    App::create(chain![
+        path!("/posts/:slug") //
+            .to(endpoint::get().call(|slug: String| slug)),
+        table,
+    ])
+}
+
+#[test]
+fn capture_substitution() -> tsukuyomi_server::Result<()> {
+    let table = RedirectTable::new().add(Redirect::new("/p/:slug", "/posts/:slug", 301)?)?;
+    let app = app(redirects::table(table))?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(Request::get("/p/hello-world"))?;
+    assert_eq!(response.status(), 301);
+    assert_eq!(
+        response.headers().get(LOCATION).unwrap(),
+        "/posts/hello-world"
+    );
+
+    let response = server.perform(Request::get("/posts/hello-world"))?;
+    assert_eq!(response.status(), 200);
+
+    let response = server.perform(Request::get("/missing"))?;
+    assert_eq!(response.status(), 404);
+
+    Ok(())
+}
+
+#[cfg(feature = "csv")]
+#[test]
+fn external_absolute_target() -> tsukuyomi_server::Result<()> {
+    let table = RedirectTable::from_csv(
+        "# vanity URLs\n\
+         /promo, https://example.com/campaign/2019, 302\n\
+         /docs/*path, https://docs.example.com/*, 308\n",
+    )?;
+    let app = app(redirects::table(table))?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(Request::get("/promo"))?;
+    assert_eq!(response.status(), 302);
+    assert_eq!(
+        response.headers().get(LOCATION).unwrap(),
+        "https://example.com/campaign/2019"
+    );
+
+    let response = server.perform(Request::get("/docs/guide/intro"))?;
+    assert_eq!(response.status(), 308);
+    assert_eq!(
+        response.headers().get(LOCATION).unwrap(),
+        "https://docs.example.com/guide/intro"
+    );
+
+    Ok(())
+}
+
+#[cfg(feature = "csv")]
+#[test]
+fn invalid_status_is_rejected() {
+    let err = RedirectTable::from_csv(
+        "/promo, https://example.com/, 302\n\
+         \n\
+         /old, /new, 200\n",
+    )
+    .unwrap_err();
+    assert_eq!(err.line(), Some(3));
+    assert!(err.to_string().starts_with("line 3: invalid status 200"));
+}
+
+#[cfg(feature = "csv")]
+#[test]
+fn quoted_fields() -> tsukuyomi_server::Result<()> {
+    let table = RedirectTable::from_csv(
+        "/promo,\"https://example.com/campaign?tags=a,b\", 302\n\
+         \"/sale\",\"/promo\", 301\n",
+    )?;
+    let app = app(redirects::table(table))?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(Request::get("/promo"))?;
+    assert_eq!(response.status(), 302);
+    assert_eq!(
+        response.headers().get(LOCATION).unwrap(),
+        "https://example.com/campaign?tags=a,b"
+    );
+
+    let response = server.perform(Request::get("/sale"))?;
+    assert_eq!(response.status(), 301);
+    assert_eq!(response.headers().get(LOCATION).unwrap(), "/promo");
+
+    let err = RedirectTable::from_csv(
+        "# vanity URLs\n\
+         /promo, https://example.com/campaign?tags=a,b, 302\n",
+    )
+    .unwrap_err();
+    assert_eq!(err.line(), Some(2));
+    assert!(err.to_string().contains("expected 3 fields"), "{}", err);
+
+    Ok(())
+}
+
+#[cfg(feature = "csv")]
+#[test]
+fn conflicts_with_routes() -> tsukuyomi_server::Result<()> {
+    let table = || RedirectTable::from_csv("/posts/latest, /posts/2019, 302").unwrap();
+
+    assert!(app(redirects::table(table()).on_conflict(redirects::OnConflict::Error)).is_err());
+
+    let app = app(redirects::table(table()))?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+    let response = server.perform(Request::get("/posts/latest"))?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "latest");
+
+    Ok(())
+}
+
+struct TempFile(PathBuf);
+
+impl TempFile {
+    fn new(content: &str) -> Self {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "tsukuyomi-redirects-{}-{}.json",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::SeqCst)
+        ));
+        let file = TempFile(path);
+        file.write(content);
+        file
+    }
+
+    fn write(&self, content: &str) {
+        fs::write(&self.0, content).unwrap();
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+#[test]
+fn hot_swap() -> tsukuyomi_server::Result<()> {
+    let file = TempFile::new(
+        r#"{ "redirects": [{ "source": "/promo", "target": "/posts/spring", "status": 302 }] }"#,
+    );
+    let table = DynamicConfig::new(RedirectTable::new());
+    let config_set = ConfigSet::new(&file.0).register("redirects", &table);
+    config_set.reload()?;
+
+    let app = app(redirects::dynamic(table.clone()))?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(Request::get("/promo"))?;
+    assert_eq!(response.status(), 302);
+    assert_eq!(response.headers().get(LOCATION).unwrap(), "/posts/spring");
+
+    file.write(
+        r#"{ "redirects": [{ "source": "/promo", "target": "/posts/summer", "status": 307 }] }"#,
+    );
+    config_set.reload()?;
+
+    let response = server.perform(Request::get("/promo"))?;
+    assert_eq!(response.status(), 307);
+    assert_eq!(response.headers().get(LOCATION).unwrap(), "/posts/summer");
+
+    Ok(())
+}
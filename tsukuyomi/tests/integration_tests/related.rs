@@ -0,0 +1,90 @@
+use {
+    http::{header::CONTENT_TYPE, Request},
+    tsukuyomi::{
+        config::prelude::*,
+        extractor::{self, body::RelatedLimits},
+        input::multipart::RelatedParts,
+        App,
+    },
+};
+
+const BODY: &str = "--xyz\r\n\
+                    Content-Type: application/json\r\n\
+                    Content-ID: <root@example.com>\r\n\
+                    \r\n\
+                    {\"attachment\":\"cid:image@example.com\"}\r\n\
+                    --xyz\r\n\
+                    Content-Type: text/plain\r\n\
+                    Content-ID: <image@example.com>\r\n\
+                    Content-Transfer-Encoding: base64\r\n\
+                    \r\n\
+                    aGVsbG8s\r\n\
+                    IHdvcmxk\r\n\
+                    --xyz--\r\n";
+
+fn app(limits: RelatedLimits) -> tsukuyomi::app::Result<App> {
+    App::create(
+        path!("/") //
+            .to(endpoint::post()
+                .extract(extractor::body::related_with(limits))
+                .call(|parts: RelatedParts| {
+                    let root: serde_json::Value =
+                        serde_json::from_slice(parts.root().body()).unwrap();
+                    let cid = root["attachment"].as_str().unwrap();
+                    let attachment = parts.get(cid).unwrap();
+                    String::from_utf8(attachment.body().to_vec()).unwrap()
+                })),
+    )
+}
+
+#[test]
+fn resolves_attachment_by_cid() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app(RelatedLimits::default())?)?;
+
+    let response = server.perform(
+        Request::post("/")
+            .header(
+                CONTENT_TYPE,
+                "multipart/related; boundary=xyz; type=\"application/json\"; \
+                 start=\"<root@example.com>\"",
+            )
+            .body(BODY),
+    )?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "hello, world");
+
+    Ok(())
+}
+
+#[test]
+fn missing_start_part() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app(RelatedLimits::default())?)?;
+
+    let response = server.perform(
+        Request::post("/")
+            .header(
+                CONTENT_TYPE,
+                "multipart/related; boundary=xyz; start=\"<missing@example.com>\"",
+            )
+            .body(BODY),
+    )?;
+    assert_eq!(response.status(), 400);
+    assert!(response.body().to_utf8()?.contains("missing@example.com"));
+
+    Ok(())
+}
+
+#[test]
+fn part_exceeding_size_limit() -> tsukuyomi_server::Result<()> {
+    let mut server =
+        tsukuyomi_server::test::server(app(RelatedLimits::default().max_part_size(16))?)?;
+
+    let response = server.perform(
+        Request::post("/")
+            .header(CONTENT_TYPE, "multipart/related; boundary=xyz")
+            .body(BODY),
+    )?;
+    assert_eq!(response.status(), 413);
+
+    Ok(())
+}
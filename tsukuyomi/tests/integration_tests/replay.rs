@@ -0,0 +1,288 @@
+use {
+    http::{Request, StatusCode},
+    hyper::Body,
+    std::{
+        sync::{Arc, Barrier},
+        thread,
+        time::{Duration, UNIX_EPOCH},
+    },
+    tsukuyomi::{
+        cache::MemoryCache,
+        config::prelude::*,
+        extractor::{
+            self,
+            replay::{ReplayGuard, VerifiedSignature},
+            Extractor, ExtractorExt,
+        },
+        future::TryFuture,
+        input::localmap::LocalData,
+        rt::MockClock,
+        vendor::futures::Future,
+        App,
+    },
+    tsukuyomi_service::{MakeService, Service},
+};
+
+const NOW: u64 = 1_700_000_000;
+
+/// A stand-in for the signature verification, accepting the header field listing the
+/// hex-encoded signatures as `webhook::hmac_sha256` does, any of which starts with `valid`.
+fn verify() -> impl Extractor<
+    Output = (),
+    Error = tsukuyomi::Error,
+    Extract = impl TryFuture<Ok = (), Error = tsukuyomi::Error> + Send + 'static,
+> {
+    extractor::ready(|input| {
+        let matched = input
+            .request
+            .headers()
+            .get("x-signature")
+            .and_then(|h| h.to_str().ok())
+            .into_iter()
+            .flat_map(|h| h.split(','))
+            .filter_map(|s| decode_hex(s.trim().trim_start_matches("sha256=")))
+            .find(|sig| sig.starts_with(b"valid"))
+            .ok_or_else(|| tsukuyomi::error::unauthorized("invalid signature"))?;
+        VerifiedSignature::new(matched).insert_into(input.locals);
+        Ok(())
+    })
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if s.len() % 2 != 0 {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Returns the signature in the canonical form, i.e. the lower-case hex digits.
+fn sig(s: &str) -> String {
+    s.bytes().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn hooks_app(guard: ReplayGuard, clock: &MockClock) -> App {
+    App::create(
+        path!("/hooks") //
+            .to(endpoint::post()
+                .extract(verify().with_replay_guard(guard))
+                .call(|| "accepted")),
+    )
+    .unwrap()
+    .with_clock(clock.clone())
+}
+
+fn setup(window: Duration) -> (App, MockClock) {
+    let clock = MockClock::starting_at(UNIX_EPOCH + Duration::from_secs(NOW));
+    let store = MemoryCache::builder().clock(clock.clone()).build();
+    let guard = ReplayGuard::new(store)
+        .window(window)
+        .max_skew(Duration::from_secs(300));
+    (hooks_app(guard, &clock), clock)
+}
+
+fn hook(signature: &str, timestamp: u64) -> http::request::Builder {
+    hook_raw(&sig(signature), timestamp)
+}
+
+/// Creates a request with the value of the signature header field as it is.
+fn hook_raw(signature: &str, timestamp: u64) -> http::request::Builder {
+    let mut request = Request::post("/hooks");
+    request
+        .header("x-signature", signature)
+        .header("x-timestamp", timestamp.to_string());
+    request
+}
+
+#[test]
+fn duplicate_is_rejected() -> tsukuyomi_server::Result<()> {
+    let (app, _clock) = setup(Duration::from_secs(600));
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(hook("valid-1", NOW))?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "accepted");
+
+    let response = server.perform(hook("valid-1", NOW))?;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    // another signature is another request, but the timestamp is not a part of the key.
+    let response = server.perform(hook("valid-2", NOW))?;
+    assert_eq!(response.status(), 200);
+    let response = server.perform(hook("valid-1", NOW + 1))?;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    Ok(())
+}
+
+#[test]
+fn mutated_signature_is_rejected() -> tsukuyomi_server::Result<()> {
+    let (app, _clock) = setup(Duration::from_secs(600));
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let signature = sig("valid-1");
+    let response = server.perform(hook_raw(&signature, NOW))?;
+    assert_eq!(response.status(), 200);
+
+    // the forms accepted by the verifier are the same signature.
+    for mutated in &[
+        signature.to_uppercase(),
+        format!("{},sha256=00", signature),
+        format!("sha256=00, sha256={}", signature),
+    ] {
+        let response = server.perform(hook_raw(mutated, NOW))?;
+        assert_eq!(response.status(), StatusCode::CONFLICT, "{}", mutated);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn missing_verified_signature() -> tsukuyomi_server::Result<()> {
+    let clock = MockClock::starting_at(UNIX_EPOCH + Duration::from_secs(NOW));
+    let app = App::create(
+        path!("/hooks") //
+            .to(endpoint::post()
+                .extract(
+                    extractor::ready(|_| Ok::<_, tsukuyomi::Error>(()))
+                        .with_replay_guard(ReplayGuard::new(MemoryCache::builder().build())),
+                )
+                .call(|| "accepted")),
+    )?
+    .with_clock(clock);
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(hook("valid-1", NOW))?;
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+    Ok(())
+}
+
+#[test]
+fn unverified_requests_are_not_recorded() -> tsukuyomi_server::Result<()> {
+    let (app, _clock) = setup(Duration::from_secs(600));
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    for _ in 0..2 {
+        let response = server.perform(hook("forged", NOW))?;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    let response = server.perform(Request::post("/hooks").header("x-signature", sig("valid-1")))?;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    Ok(())
+}
+
+#[test]
+fn duplicate_status() -> tsukuyomi_server::Result<()> {
+    let clock = MockClock::starting_at(UNIX_EPOCH + Duration::from_secs(NOW));
+    let guard = ReplayGuard::new(MemoryCache::builder().build())
+        .timestamp_header("x-signed-at")
+        .duplicate_status(StatusCode::UNAUTHORIZED);
+    let mut server = tsukuyomi_server::test::server(hooks_app(guard, &clock))?;
+
+    let request = || {
+        let mut request = Request::post("/hooks");
+        request
+            .header("x-signature", sig("valid-1"))
+            .header("x-signed-at", NOW.to_string());
+        request
+    };
+    let response = server.perform(request())?;
+    assert_eq!(response.status(), 200);
+    let response = server.perform(request())?;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    Ok(())
+}
+
+#[test]
+fn expiry_allows_reuse() -> tsukuyomi_server::Result<()> {
+    // a window shorter than the skew, in order to observe the expiration.
+    let (app, clock) = setup(Duration::from_secs(60));
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(hook("valid-1", NOW))?;
+    assert_eq!(response.status(), 200);
+
+    clock.advance(Duration::from_secs(59));
+    let response = server.perform(hook("valid-1", NOW))?;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    clock.advance(Duration::from_secs(1));
+    let response = server.perform(hook("valid-1", NOW))?;
+    assert_eq!(response.status(), 200);
+
+    Ok(())
+}
+
+#[test]
+fn skewed_timestamp_is_rejected() -> tsukuyomi_server::Result<()> {
+    let (app, _clock) = setup(Duration::from_secs(600));
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(hook("valid-1", NOW - 301))?;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = server.perform(hook("valid-2", NOW + 301))?;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = server.perform(hook("valid-3", NOW - 300))?;
+    assert_eq!(response.status(), 200);
+
+    let response = server.perform(
+        Request::post("/hooks")
+            .header("x-signature", sig("valid-4"))
+            .header("x-timestamp", "yesterday"),
+    )?;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    // the rejected requests are not recorded.
+    let response = server.perform(hook("valid-1", NOW - 300))?;
+    assert_eq!(response.status(), 200);
+
+    Ok(())
+}
+
+#[test]
+fn concurrent_duplicates() {
+    let clock = MockClock::starting_at(UNIX_EPOCH + Duration::from_secs(NOW));
+    let guard = ReplayGuard::new(MemoryCache::builder().build());
+    let app = Arc::new(hooks_app(guard, &clock));
+
+    let n = 8;
+    let barrier = Arc::new(Barrier::new(n));
+    let handles: Vec<_> = (0..n)
+        .map(|_| {
+            let app = app.clone();
+            let barrier = barrier.clone();
+            thread::spawn(move || {
+                let mut service = MakeService::<(), Request<Body>>::make_service(&*app, ())
+                    .wait()
+                    .unwrap_or_else(|never| match never {});
+                let request = Request::post("/hooks")
+                    .header("x-signature", sig("valid-1"))
+                    .header("x-timestamp", NOW.to_string())
+                    .body(Body::empty())
+                    .unwrap();
+                barrier.wait();
+                service
+                    .call(request)
+                    .wait()
+                    .unwrap_or_else(|never| match never {})
+                    .status()
+            })
+        })
+        .collect();
+
+    let statuses: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+    assert_eq!(statuses.iter().filter(|&&s| s == StatusCode::OK).count(), 1);
+    assert_eq!(
+        statuses
+            .iter()
+            .filter(|&&s| s == StatusCode::CONFLICT)
+            .count(),
+        n - 1
+    );
+}
@@ -0,0 +1,94 @@
+use {
+    http::{
+        header::{AUTHORIZATION, USER_AGENT},
+        Request, StatusCode,
+    },
+    std::sync::{Arc, Mutex},
+    tsukuyomi::{
+        app::{ErrorReport, PanicReport},
+        config::prelude::*,
+        App,
+    },
+};
+
+#[test]
+fn error_report() -> tsukuyomi_server::Result<()> {
+    let reports = Arc::new(Mutex::new(vec![]));
+
+    let app = App::create(chain![
+        path!("/users/:id").to(endpoint::get().call(|_id: u32| {
+            Err::<&str, _>(tsukuyomi::error::internal_server_error("database is down"))
+        })),
+        path!("/bad").to(endpoint::get()
+            .call(|| { Err::<&str, _>(tsukuyomi::error::bad_request("invalid query")) })),
+    ])?
+    .on_error({
+        let reports = reports.clone();
+        move |report: ErrorReport| reports.lock().unwrap().push(report)
+    });
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(
+        Request::get("/users/42")
+            .header("x-request-id", "req-1234")
+            .header(AUTHORIZATION, "Bearer secret")
+            .header(USER_AGENT, "tester"),
+    )?;
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+    {
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.len(), 1);
+        let report = &reports[0];
+        assert_eq!(report.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(report.message(), "database is down");
+        assert_eq!(report.request().method(), "GET");
+        assert_eq!(report.request().path(), "/users/42");
+        assert_eq!(report.request().pattern(), Some("/users/:id"));
+        assert_eq!(report.request().request_id(), Some("req-1234"));
+        assert_eq!(report.request().headers()[AUTHORIZATION], "[redacted]");
+        assert_eq!(report.request().headers()[USER_AGENT], "tester");
+    }
+
+    // The client errors are not reported.
+    assert_eq!(server.perform("/bad")?.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(server.perform("/missing")?.status(), StatusCode::NOT_FOUND);
+    assert_eq!(reports.lock().unwrap().len(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn panic_report() -> tsukuyomi_server::Result<()> {
+    let errors = Arc::new(Mutex::new(vec![]));
+    let panics = Arc::new(Mutex::new(vec![]));
+
+    let app = App::create(chain![
+        path!("/panic").to(endpoint::get().call(|| -> &'static str {
+            panic!("something went wrong");
+        })),
+        path!("/ok").to(endpoint::get().reply("ok")),
+    ])?
+    .recover_panics(true)
+    .on_error({
+        let errors = errors.clone();
+        move |report: ErrorReport| errors.lock().unwrap().push(report)
+    })
+    .on_panic({
+        let panics = panics.clone();
+        move |report: PanicReport| panics.lock().unwrap().push(report)
+    });
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/panic")?;
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(server.perform("/ok")?.body().to_utf8()?, "ok");
+
+    let panics = panics.lock().unwrap();
+    assert_eq!(panics.len(), 1);
+    assert_eq!(panics[0].payload(), "something went wrong");
+    assert_eq!(panics[0].request().pattern(), Some("/panic"));
+    assert!(errors.lock().unwrap().is_empty());
+
+    Ok(())
+}
@@ -0,0 +1,195 @@
+use {
+    http::Request,
+    serde::{Deserialize, Serialize},
+    tsukuyomi::{
+        config::prelude::*,
+        extractor,
+        resource::{self, MemoryStore},
+        App,
+    },
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Post {
+    title: String,
+}
+
+fn store() -> MemoryStore<Post> {
+    MemoryStore::new().unique_by(|post: &Post| post.title.clone())
+}
+
+fn post(title: &str) -> String {
+    serde_json::json!({ "title": title }).to_string()
+}
+
+#[test]
+fn crud_lifecycle() -> tsukuyomi_server::Result<()> {
+    let app = App::create(mount("/api").with(resource::resource("posts", store())))?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(
+        Request::post("/api/posts")
+            .header("content-type", "application/json")
+            .body(post("hello")),
+    )?;
+    assert_eq!(response.status(), 201);
+    assert_eq!(response.headers().get("location").unwrap(), "/api/posts/1");
+    assert_eq!(response.body().to_utf8()?, r#"{"title":"hello"}"#);
+
+    let response = server.perform(
+        Request::post("/api/posts")
+            .header("content-type", "application/json")
+            .body(post("world")),
+    )?;
+    assert_eq!(response.status(), 201);
+    assert_eq!(response.headers().get("location").unwrap(), "/api/posts/2");
+
+    let response = server.perform("/api/posts?per_page=1")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, r#"[{"title":"hello"}]"#);
+    assert_eq!(response.headers().get("x-total-count").unwrap(), "2");
+
+    let response = server.perform("/api/posts?cursor=1")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, r#"[{"title":"world"}]"#);
+
+    let response = server.perform("/api/posts/2")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, r#"{"title":"world"}"#);
+
+    let response = server.perform(
+        Request::put("/api/posts/2")
+            .header("content-type", "application/json")
+            .body(post("updated")),
+    )?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, r#"{"title":"updated"}"#);
+
+    let response = server.perform(Request::delete("/api/posts/1"))?;
+    assert_eq!(response.status(), 204);
+
+    let response = server.perform("/api/posts")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, r#"[{"title":"updated"}]"#);
+    assert_eq!(response.headers().get("x-total-count").unwrap(), "1");
+
+    Ok(())
+}
+
+#[test]
+fn missing_id() -> tsukuyomi_server::Result<()> {
+    let app = App::create(resource::resource("posts", store()))?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/posts/42")?;
+    assert_eq!(response.status(), 404);
+
+    let response = server.perform(
+        Request::put("/posts/42")
+            .header("content-type", "application/json")
+            .body(post("hello")),
+    )?;
+    assert_eq!(response.status(), 404);
+
+    let response = server.perform(Request::delete("/posts/42"))?;
+    assert_eq!(response.status(), 404);
+
+    let response = server.perform("/posts?cursor=abc")?;
+    assert_eq!(response.status(), 400);
+
+    Ok(())
+}
+
+#[test]
+fn conflict() -> tsukuyomi_server::Result<()> {
+    let app = App::create(resource::resource("posts", store()))?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    for title in &["hello", "world"] {
+        let response = server.perform(
+            Request::post("/posts")
+                .header("content-type", "application/json")
+                .body(post(title)),
+        )?;
+        assert_eq!(response.status(), 201);
+    }
+
+    let response = server.perform(
+        Request::post("/posts")
+            .header("content-type", "application/json")
+            .body(post("hello")),
+    )?;
+    assert_eq!(response.status(), 409);
+    assert_eq!(response.body().to_utf8()?, "hello already exists");
+
+    let response = server.perform(
+        Request::put("/posts/2")
+            .header("content-type", "application/json")
+            .body(post("hello")),
+    )?;
+    assert_eq!(response.status(), 409);
+
+    // updating an item with its own key is not a conflict.
+    let response = server.perform(
+        Request::put("/posts/1")
+            .header("content-type", "application/json")
+            .body(post("hello")),
+    )?;
+    assert_eq!(response.status(), 200);
+
+    Ok(())
+}
+
+#[test]
+fn override_list() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        resource::resource("posts", store())
+            .override_list(endpoint::get().reply("overridden"))
+            .override_get(
+                endpoint::get()
+                    .extract(extractor::param::<u64>("id"))
+                    .call(|id: u64| format!("post {}", id)),
+            ),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/posts")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "overridden");
+
+    let response = server.perform("/posts/7")?;
+    assert_eq!(response.body().to_utf8()?, "post 7");
+
+    let response = server.perform(
+        Request::post("/posts")
+            .header("content-type", "application/json")
+            .body(post("hello")),
+    )?;
+    assert_eq!(response.status(), 201);
+
+    Ok(())
+}
+
+#[test]
+fn guards() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        resource::resource("posts", store())
+            .override_list(endpoint::get().reply("overridden"))
+            .guard(tsukuyomi::guard::accepts(mime::APPLICATION_JSON)),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(Request::get("/posts").header("accept", "text/html"))?;
+    assert_eq!(response.status(), 406);
+
+    let response = server.perform(Request::get("/posts/1").header("accept", "text/html"))?;
+    assert_eq!(response.status(), 406);
+
+    let response = server.perform(Request::get("/posts").header("accept", "application/json"))?;
+    assert_eq!(response.status(), 200);
+
+    let response = server.perform(Request::patch("/posts"))?;
+    assert_eq!(response.status(), 405);
+
+    Ok(())
+}
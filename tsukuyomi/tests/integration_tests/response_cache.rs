@@ -0,0 +1,392 @@
+use {
+    http::{header::RETRY_AFTER, Request, StatusCode},
+    hyper::Body,
+    std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        time::Duration,
+    },
+    tsukuyomi::{
+        cache::MemoryCache,
+        config::prelude::*,
+        extractor,
+        modifiers::{self, CacheEntry, CoalesceTimeout, QueryMatch, ResponseCache},
+        output::ResponseBody,
+        rt::MockClock,
+        vendor::futures::{
+            executor::{self, Notify, Spawn},
+            sync::oneshot,
+            Async, Future,
+        },
+        App,
+    },
+    tsukuyomi_service::{MakeService, Service},
+};
+
+struct Counter(Arc<AtomicUsize>);
+
+impl Counter {
+    fn new() -> Self {
+        Counter(Arc::new(AtomicUsize::new(0)))
+    }
+
+    fn count(&self) -> usize {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+fn posts_app(cache: &ResponseCache, counter: &Counter) -> App {
+    let list_counter = counter.0.clone();
+    let post_counter = counter.0.clone();
+    App::create(chain![
+        path!("/posts")
+            .to(endpoint::get().call(move || {
+                list_counter.fetch_add(1, Ordering::SeqCst);
+                "posts"
+            }))
+            .modify(cache.clone()),
+        path!("/posts/:id")
+            .to(chain![
+                endpoint::get().call(move |id: u32| {
+                    post_counter.fetch_add(1, Ordering::SeqCst);
+                    format!("post {}", id)
+                }),
+                endpoint::put()
+                    .extract(extractor::body::plain())
+                    .call(|id: u32, body: String| {
+                        if body.is_empty() {
+                            Err(tsukuyomi::error::bad_request("empty body"))
+                        } else {
+                            Ok(format!("updated {}", id))
+                        }
+                    }),
+            ])
+            .modify(cache.invalidates(&["/posts/:id", "/posts"])),
+        path!("/users/:id")
+            .to(endpoint::put().call(|_id: u32| "updated"))
+            .modify(cache.invalidates(&["/users/:id"])),
+    ])
+    .unwrap()
+}
+
+#[test]
+fn get_responses_are_cached() -> tsukuyomi_server::Result<()> {
+    let cache = modifiers::response_cache(MemoryCache::builder().build());
+    let counter = Counter::new();
+    let mut server = tsukuyomi_server::test::server(posts_app(&cache, &counter))?;
+
+    let response = server.perform(Request::get("/posts/1"))?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "post 1");
+    assert_eq!(counter.count(), 1);
+
+    let response = server.perform(Request::get("/posts/1"))?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "post 1");
+    assert_eq!(counter.count(), 1);
+
+    let _ = server.perform(Request::get("/posts/2"))?;
+    assert_eq!(counter.count(), 2);
+    assert_eq!(cache.cache().len(), 2);
+
+    Ok(())
+}
+
+#[test]
+fn successful_mutation_purges_matching_entries() -> tsukuyomi_server::Result<()> {
+    let cache = modifiers::response_cache(MemoryCache::builder().build());
+    let counter = Counter::new();
+    let mut server = tsukuyomi_server::test::server(posts_app(&cache, &counter))?;
+
+    let _ = server.perform(Request::get("/posts"))?;
+    let _ = server.perform(Request::get("/posts/1"))?;
+    let _ = server.perform(Request::get("/posts/2"))?;
+    assert_eq!(counter.count(), 3);
+
+    let response = server.perform(Request::put("/posts/1").body("title=foo"))?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(cache.cache().len(), 1);
+
+    let _ = server.perform(Request::get("/posts/1"))?;
+    let _ = server.perform(Request::get("/posts"))?;
+    assert_eq!(counter.count(), 5);
+
+    // the entry of the other post is still cached.
+    let _ = server.perform(Request::get("/posts/2"))?;
+    assert_eq!(counter.count(), 5);
+
+    Ok(())
+}
+
+#[test]
+fn unrelated_mutation_does_not_purge() -> tsukuyomi_server::Result<()> {
+    let cache = modifiers::response_cache(MemoryCache::builder().build());
+    let counter = Counter::new();
+    let mut server = tsukuyomi_server::test::server(posts_app(&cache, &counter))?;
+
+    let _ = server.perform(Request::get("/posts/1"))?;
+    assert_eq!(counter.count(), 1);
+
+    let response = server.perform(Request::put("/users/1"))?;
+    assert_eq!(response.status(), 200);
+
+    let _ = server.perform(Request::get("/posts/1"))?;
+    assert_eq!(counter.count(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn failed_mutation_does_not_purge() -> tsukuyomi_server::Result<()> {
+    let cache = modifiers::response_cache(MemoryCache::builder().build());
+    let counter = Counter::new();
+    let mut server = tsukuyomi_server::test::server(posts_app(&cache, &counter))?;
+
+    let _ = server.perform(Request::get("/posts/1"))?;
+    assert_eq!(counter.count(), 1);
+
+    let response = server.perform(Request::put("/posts/1"))?;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let _ = server.perform(Request::get("/posts/1"))?;
+    assert_eq!(counter.count(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn query_match_rules() -> tsukuyomi_server::Result<()> {
+    for &(query_match, purged) in &[(QueryMatch::Ignore, true), (QueryMatch::Exact, false)] {
+        let cache =
+            modifiers::response_cache(MemoryCache::builder().build()).query_match(query_match);
+        let counter = Counter::new();
+        let mut server = tsukuyomi_server::test::server(posts_app(&cache, &counter))?;
+
+        let _ = server.perform(Request::get("/posts?page=2"))?;
+        let _ = server.perform(Request::put("/posts/1").body("title=foo"))?;
+        let _ = server.perform(Request::get("/posts?page=2"))?;
+        assert_eq!(counter.count(), if purged { 2 } else { 1 });
+    }
+
+    Ok(())
+}
+
+type ResponseFuture = Box<dyn Future<Item = http::Response<ResponseBody>, Error = ()> + Send>;
+
+struct Noop;
+
+impl Notify for Noop {
+    fn notify(&self, _: usize) {}
+}
+
+/// A set of the requests to an expensive page, each of which is polled manually.
+struct Harness {
+    app: App,
+    clock: MockClock,
+    /// The senders to complete the renders that have started, in order of starting.
+    started: Arc<Mutex<Vec<oneshot::Sender<()>>>>,
+}
+
+impl Harness {
+    fn new(
+        cache: impl FnOnce(MemoryCache<String, CacheEntry>) -> ResponseCache,
+    ) -> (Self, ResponseCache) {
+        let clock = MockClock::new();
+        let cache = cache(
+            MemoryCache::builder()
+                .ttl(Duration::from_secs(60))
+                .clock(clock.clone())
+                .build(),
+        );
+        let started = Arc::new(Mutex::new(vec![]));
+        let app = App::create(
+            path!("/report") //
+                .to(endpoint::get().call_async({
+                    let started = started.clone();
+                    move || {
+                        let (tx, rx) = oneshot::channel();
+                        let mut started = started.lock().unwrap();
+                        started.push(tx);
+                        let version = started.len();
+                        rx.map(move |()| format!("report v{}", version))
+                            .map_err(tsukuyomi::error::internal_server_error)
+                    }
+                }))
+                .modify(cache.clone()),
+        )
+        .unwrap()
+        .with_clock(clock.clone());
+        (
+            Self {
+                app,
+                clock,
+                started,
+            },
+            cache,
+        )
+    }
+
+    fn request(&self) -> Spawn<ResponseFuture> {
+        let mut service = MakeService::<(), Request<Body>>::make_service(&self.app, ())
+            .wait()
+            .unwrap_or_else(|never| match never {});
+        let request = Request::get("/report").body(Body::empty()).unwrap();
+        let future: ResponseFuture =
+            Box::new(service.call(request).map_err(|never| match never {}));
+        executor::spawn(future)
+    }
+
+    fn started(&self) -> usize {
+        self.started.lock().unwrap().len()
+    }
+
+    fn complete(&self, i: usize) {
+        let tx = std::mem::replace(&mut self.started.lock().unwrap()[i], oneshot::channel().0);
+        tx.send(()).unwrap();
+    }
+
+    /// Caches the first version of the report, and lets it expire.
+    fn expire(&self) {
+        let mut request = self.request();
+        assert!(poll(&mut request).is_none());
+        self.complete(0);
+        assert_eq!(body(poll(&mut request).unwrap()), "report v1");
+        self.clock.advance(Duration::from_secs(61));
+    }
+}
+
+fn poll(request: &mut Spawn<ResponseFuture>) -> Option<http::Response<ResponseBody>> {
+    match request.poll_future_notify(&Arc::new(Noop), 0) {
+        Ok(Async::Ready(response)) => Some(response),
+        Ok(Async::NotReady) => None,
+        Err(()) => unreachable!(),
+    }
+}
+
+fn body(response: http::Response<ResponseBody>) -> String {
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = tsukuyomi::test::read_body(response.into_body()).unwrap();
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+#[test]
+fn concurrent_misses_are_coalesced() {
+    let (harness, cache) = Harness::new(|cache| {
+        modifiers::response_cache(cache)
+            .coalesce(Duration::from_secs(10), CoalesceTimeout::Unavailable)
+    });
+    harness.expire();
+
+    let mut requests: Vec<_> = (0..5).map(|_| harness.request()).collect();
+    for request in &mut requests {
+        assert!(poll(request).is_none());
+    }
+    assert_eq!(harness.started(), 2);
+    assert_eq!(cache.metrics().coalesced, 4);
+
+    // the client of a waiting request has disconnected.
+    drop(requests.pop());
+
+    harness.complete(1);
+    for request in &mut requests {
+        assert_eq!(
+            body(poll(request).expect("should be completed")),
+            "report v2"
+        );
+    }
+    assert_eq!(harness.started(), 2);
+    assert_eq!(cache.metrics().timeouts, 0);
+}
+
+#[test]
+fn coalesced_requests_give_up_waiting() {
+    for &(on_timeout, status) in &[
+        (
+            CoalesceTimeout::Unavailable,
+            StatusCode::SERVICE_UNAVAILABLE,
+        ),
+        (CoalesceTimeout::Compute, StatusCode::OK),
+    ] {
+        let (harness, cache) = Harness::new(|cache| {
+            modifiers::response_cache(cache).coalesce(Duration::from_millis(1500), on_timeout)
+        });
+
+        let mut first = harness.request();
+        let mut second = harness.request();
+        assert!(poll(&mut first).is_none());
+        assert!(poll(&mut second).is_none());
+        assert_eq!(harness.started(), 1);
+
+        harness.clock.advance(Duration::from_secs(2));
+        let response = match poll(&mut second) {
+            Some(response) => response,
+            None => {
+                // the request is computing the response by itself.
+                assert_eq!(harness.started(), 2);
+                harness.complete(1);
+                poll(&mut second).expect("should be completed")
+            }
+        };
+        assert_eq!(response.status(), status);
+        if status == StatusCode::SERVICE_UNAVAILABLE {
+            assert_eq!(response.headers()[RETRY_AFTER], "2");
+        }
+        assert_eq!(cache.metrics().timeouts, 1);
+
+        harness.complete(0);
+        assert_eq!(body(poll(&mut first).unwrap()), "report v1");
+    }
+}
+
+#[test]
+fn stale_while_revalidate() -> tsukuyomi_server::Result<()> {
+    let clock = MockClock::new();
+    let cache = modifiers::response_cache(
+        MemoryCache::builder()
+            .ttl(Duration::from_secs(60))
+            .clock(clock.clone())
+            .build(),
+    )
+    .stale_while_revalidate(Duration::from_secs(30));
+    let counter = Counter::new();
+    let app = App::create(
+        path!("/report")
+            .to(endpoint::get().call({
+                let counter = counter.0.clone();
+                move || format!("report v{}", counter.fetch_add(1, Ordering::SeqCst) + 1)
+            }))
+            .modify(cache.clone()),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(Request::get("/report"))?;
+    assert_eq!(response.body().to_utf8()?, "report v1");
+
+    clock.advance(Duration::from_secs(61));
+    let response = server.perform(Request::get("/report"))?;
+    assert_eq!(response.body().to_utf8()?, "report v1");
+    assert_eq!(cache.metrics().stale_served, 1);
+
+    // waits for the refresh running in the background.
+    for _ in 0..500 {
+        if cache.metrics().revalidated == 1 {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(cache.metrics().revalidated, 1);
+
+    let response = server.perform(Request::get("/report"))?;
+    assert_eq!(response.body().to_utf8()?, "report v2");
+    assert_eq!(counter.count(), 2);
+
+    // beyond the window, the expired entry is not returned.
+    clock.advance(Duration::from_secs(120));
+    let response = server.perform(Request::get("/report"))?;
+    assert_eq!(response.body().to_utf8()?, "report v3");
+    assert_eq!(cache.metrics().stale_served, 1);
+
+    Ok(())
+}
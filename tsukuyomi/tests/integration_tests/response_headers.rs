@@ -0,0 +1,109 @@
+use {
+    http::{
+        header::{CACHE_CONTROL, CONTENT_LENGTH, LINK, TRANSFER_ENCODING, X_FRAME_OPTIONS},
+        HeaderMap, Request, StatusCode,
+    },
+    tsukuyomi::{config::prelude::*, extractor, App},
+    tsukuyomi_server::test::ResponseExt,
+};
+
+/// A handler that sets `Link` and `Cache-Control` by itself.
+fn handler_headers() -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(LINK, "</next>; rel=next".parse().unwrap());
+    headers.insert(CACHE_CONTROL, "no-store".parse().unwrap());
+    headers
+}
+
+#[test]
+fn append_and_set_against_handler() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/").to(endpoint::get()
+            .extract(extractor::ready(|input| {
+                let response = input.response();
+                response.append(LINK, "</style.css>; rel=preload")?;
+                response.set(CACHE_CONTROL, "max-age=60")?;
+                response.set(X_FRAME_OPTIONS, "DENY")?;
+                Ok::<_, tsukuyomi::Error>(())
+            }))
+            .call(|| (StatusCode::OK, handler_headers(), "hello"))),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/")?;
+    assert_eq!(response.status(), 200);
+    let links: Vec<_> = response.headers().get_all(LINK).iter().collect();
+    assert_eq!(
+        links,
+        vec!["</next>; rel=next", "</style.css>; rel=preload"]
+    );
+    assert_eq!(response.header(CACHE_CONTROL)?, "no-store");
+    assert_eq!(response.headers().get_all(CACHE_CONTROL).iter().count(), 1);
+    assert_eq!(response.header(X_FRAME_OPTIONS)?, "DENY");
+
+    Ok(())
+}
+
+#[test]
+fn set_if_absent_and_remove() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/").to(endpoint::get()
+            .extract(extractor::ready(|input| {
+                let response = input.response();
+                assert!(response.set_if_absent(X_FRAME_OPTIONS, "DENY")?);
+                assert!(!response.set_if_absent(X_FRAME_OPTIONS, "SAMEORIGIN")?);
+                response.append(LINK, "</a>; rel=preload")?;
+                assert!(response.remove(&LINK));
+                assert!(!response.remove(&LINK));
+                Ok::<_, tsukuyomi::Error>(())
+            }))
+            .reply("hello")),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/")?;
+    assert_eq!(response.header(X_FRAME_OPTIONS)?, "DENY");
+    assert!(response.headers().get(LINK).is_none());
+
+    Ok(())
+}
+
+#[test]
+fn reserved_headers_are_rejected() -> tsukuyomi_server::Result<()> {
+    let app = App::create(path!("/").to(endpoint::get().call(|| {
+        tsukuyomi::responder::oneshot(|input| {
+            let response = input.response();
+            assert!(response.set(CONTENT_LENGTH, "0").is_err());
+            assert!(response.append(TRANSFER_ENCODING, "chunked").is_err());
+            assert!(response.is_empty());
+            Ok::<_, tsukuyomi::Error>("hello")
+        })
+    })))?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.header(CONTENT_LENGTH)?, "5");
+    assert!(response.headers().get(TRANSFER_ENCODING).is_none());
+
+    Ok(())
+}
+
+#[test]
+fn invalid_value_is_an_error() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/").to(endpoint::get()
+            .extract(extractor::ready(|input| {
+                input.response().append(LINK, "</a>\r\nX-Injected: 1")
+            }))
+            .reply("hello")),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(Request::get("/"))?;
+    assert_eq!(response.status(), 500);
+    assert!(response.headers().get(LINK).is_none());
+    assert!(response.headers().get("x-injected").is_none());
+
+    Ok(())
+}
@@ -0,0 +1,98 @@
+use {
+    http::Response,
+    tsukuyomi::{
+        app::ResponseSizeLimit,
+        config::prelude::*,
+        output::ResponseBody,
+        vendor::futures::stream,
+        App, //
+    },
+};
+
+fn take_records() -> Vec<String> {
+    super::logging::take_records("response size limit exceeded:")
+}
+
+/// Creates a streaming response of `n` chunks, each of which is 4 bytes.
+fn chunks(n: usize) -> Response<ResponseBody> {
+    let chunks = stream::iter_ok::<_, std::io::Error>((0..n).map(|_| vec![b'x'; 4]));
+    Response::new(ResponseBody::wrap_stream(chunks))
+}
+
+fn app() -> tsukuyomi::app::Result<App> {
+    App::create(chain![
+        ResponseSizeLimit::new(10).exempt_tag("download"),
+        path!("/small").to(endpoint::call(|| "hello")),
+        path!("/large").to(endpoint::call(|| "x".repeat(11))),
+        path!("/stream/small").to(endpoint::call(|| chunks(2))),
+        path!("/stream/large").to(endpoint::call(|| chunks(8))),
+        path!("/download")
+            .to(endpoint::call(|| chunks(8)))
+            .tag("download"),
+        mount("/relaxed").with(chain![
+            ResponseSizeLimit::new(100),
+            path!("/large").to(endpoint::call(|| "x".repeat(11))),
+        ]),
+    ])
+}
+
+#[test]
+fn buffered_body_over_limit() -> tsukuyomi_server::Result<()> {
+    let _ = take_records();
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+
+    let response = server.perform("/small")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "hello");
+
+    let response = server.perform("/large")?;
+    assert_eq!(response.status(), 500);
+    assert_ne!(response.body().to_utf8()?, "x".repeat(11));
+
+    let response = server.perform("/relaxed/large")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "x".repeat(11));
+
+    assert_eq!(
+        take_records(),
+        vec!["response size limit exceeded: method=GET pattern=/large limit=10 size=11"]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn streaming_body_over_limit() -> tsukuyomi_server::Result<()> {
+    let _ = take_records();
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+
+    let response = server.perform("/stream/small")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "xxxxxxxx");
+
+    // The body is aborted in the middle of transmission.
+    assert!(server.perform("/stream/large").is_err());
+
+    assert_eq!(
+        take_records(),
+        vec!["response size limit exceeded: method=GET pattern=/stream/large limit=10 size=12"]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn exempted_route() -> tsukuyomi_server::Result<()> {
+    let _ = take_records();
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+
+    let response = server.perform("/download")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "x".repeat(32));
+
+    assert!(take_records()
+        .iter()
+        .all(|record| !record.contains("pattern=/download ")));
+
+    Ok(())
+}
@@ -0,0 +1,221 @@
+use {
+    http::{Request, StatusCode},
+    hyper::Body,
+    std::{
+        collections::HashMap,
+        net::{IpAddr, SocketAddr},
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    },
+    tsukuyomi::{
+        config::prelude::*,
+        guard::reverse_dns::{Lookup, Resolver, ReverseDns},
+        input::ConnectionInfo,
+        output::ResponseBody,
+        rt::MockClock,
+        vendor::futures::{
+            executor::{self, Notify},
+            future, Async, Future,
+        },
+        App,
+    },
+    tsukuyomi_service::{MakeService, Service},
+};
+
+const CALLBACK: &str = "203.0.113.10";
+const SPOOFED: &str = "198.51.100.7";
+const UNRELATED: &str = "192.0.2.1";
+const STALLED: &str = "192.0.2.99";
+
+#[derive(Clone, Default)]
+struct MockResolver {
+    reverse_calls: Arc<AtomicUsize>,
+    forward_calls: Arc<AtomicUsize>,
+}
+
+impl MockResolver {
+    fn reverse_calls(&self) -> usize {
+        self.reverse_calls.load(Ordering::SeqCst)
+    }
+
+    fn forward_calls(&self) -> usize {
+        self.forward_calls.load(Ordering::SeqCst)
+    }
+}
+
+impl Resolver for MockResolver {
+    fn reverse(&self, addr: IpAddr) -> Lookup<Vec<String>> {
+        self.reverse_calls.fetch_add(1, Ordering::SeqCst);
+        let ptr: HashMap<IpAddr, &[&str]> = vec![
+            (
+                CALLBACK.parse().unwrap(),
+                &["hook-1.payments.example.com."][..],
+            ),
+            // the owner of the address can claim any host name.
+            (
+                SPOOFED.parse().unwrap(),
+                &["hook-1.payments.example.com."][..],
+            ),
+            (UNRELATED.parse().unwrap(), &["mail.badexample.com."][..]),
+        ]
+        .into_iter()
+        .collect();
+        if addr == STALLED.parse::<IpAddr>().unwrap() {
+            return Box::new(future::empty());
+        }
+        match ptr.get(&addr) {
+            Some(hosts) => Box::new(future::ok(hosts.iter().map(|&h| h.to_owned()).collect())),
+            None => Box::new(future::err(failure::format_err!("NXDOMAIN"))),
+        }
+    }
+
+    fn forward(&self, host: &str) -> Lookup<Vec<IpAddr>> {
+        self.forward_calls.fetch_add(1, Ordering::SeqCst);
+        match host {
+            "hook-1.payments.example.com" => Box::new(future::ok(vec![
+                "2001:db8::10".parse().unwrap(),
+                CALLBACK.parse().unwrap(),
+            ])),
+            _ => Box::new(future::err(failure::format_err!("NXDOMAIN"))),
+        }
+    }
+}
+
+fn app(guard: ReverseDns, clock: &MockClock) -> tsukuyomi::app::Result<App> {
+    Ok(App::create(
+        path!("/webhook") //
+            .to(endpoint::post().guard(guard).reply("accepted")),
+    )?
+    .with_clock(clock.clone()))
+}
+
+fn request(peer: &str) -> Request<Body> {
+    Request::post("/webhook")
+        .extension(ConnectionInfo::new(SocketAddr::new(
+            peer.parse().unwrap(),
+            443,
+        )))
+        .body(Body::empty())
+        .unwrap()
+}
+
+#[test]
+fn forward_confirmed() -> tsukuyomi_server::Result<()> {
+    let resolver = MockResolver::default();
+    let guard = ReverseDns::with_resolver(resolver.clone(), vec![".payments.example.com"]);
+    let mut server = tsukuyomi_server::test::server(app(guard, &MockClock::new())?)?;
+
+    let response = server.perform(request(CALLBACK))?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!((resolver.reverse_calls(), resolver.forward_calls()), (1, 1));
+
+    Ok(())
+}
+
+#[test]
+fn spoofed_ptr() -> tsukuyomi_server::Result<()> {
+    let resolver = MockResolver::default();
+    let guard = ReverseDns::with_resolver(resolver.clone(), vec!["example.com"]);
+    let mut server = tsukuyomi_server::test::server(app(guard, &MockClock::new())?)?;
+
+    // the PTR record is within the domain, but the host name does not resolve to the peer.
+    let response = server.perform(request(SPOOFED))?;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_eq!((resolver.reverse_calls(), resolver.forward_calls()), (1, 1));
+
+    // the suffix matches at the label boundary, so the forward lookup is not performed.
+    let response = server.perform(request(UNRELATED))?;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_eq!((resolver.reverse_calls(), resolver.forward_calls()), (2, 1));
+
+    // the failure of the reverse lookup.
+    let response = server.perform(request("192.0.2.2"))?;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    // the request without the connection info.
+    let response = server.perform(Request::post("/webhook"))?;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    Ok(())
+}
+
+#[test]
+fn cached_verdicts() -> tsukuyomi_server::Result<()> {
+    let resolver = MockResolver::default();
+    let clock = MockClock::new();
+    let guard = ReverseDns::with_resolver(resolver.clone(), vec!["payments.example.com"])
+        .ttl(Duration::from_secs(60));
+    let mut server = tsukuyomi_server::test::server(app(guard, &clock)?)?;
+
+    for _ in 0..3 {
+        assert_eq!(server.perform(request(CALLBACK))?.status(), StatusCode::OK);
+        assert_eq!(
+            server.perform(request(SPOOFED))?.status(),
+            StatusCode::FORBIDDEN
+        );
+    }
+    assert_eq!((resolver.reverse_calls(), resolver.forward_calls()), (2, 2));
+
+    clock.advance(Duration::from_secs(61));
+    assert_eq!(server.perform(request(CALLBACK))?.status(), StatusCode::OK);
+    assert_eq!((resolver.reverse_calls(), resolver.forward_calls()), (3, 3));
+
+    // the failures of the lookups are not cached.
+    for _ in 0..2 {
+        assert_eq!(
+            server.perform(request("192.0.2.2"))?.status(),
+            StatusCode::FORBIDDEN
+        );
+    }
+    assert_eq!(resolver.reverse_calls(), 5);
+
+    Ok(())
+}
+
+struct Noop;
+
+impl Notify for Noop {
+    fn notify(&self, _: usize) {}
+}
+
+#[test]
+fn timeout() -> tsukuyomi_server::Result<()> {
+    let resolver = MockResolver::default();
+    let clock = MockClock::new();
+    let guard = ReverseDns::with_resolver(resolver.clone(), vec!["payments.example.com"])
+        .timeout(Duration::from_secs(2))
+        .rejection(StatusCode::UNAUTHORIZED);
+    let app = app(guard, &clock)?;
+
+    let mut service = MakeService::<(), Request<Body>>::make_service(&app, ())
+        .wait()
+        .unwrap_or_else(|never| match never {});
+    let future: Box<dyn Future<Item = http::Response<ResponseBody>, Error = ()> + Send> = Box::new(
+        service
+            .call(request(STALLED))
+            .map_err(|never| match never {}),
+    );
+    let mut future = executor::spawn(future);
+    let notify = Arc::new(Noop);
+
+    assert!(future
+        .poll_future_notify(&notify, 0)
+        .unwrap()
+        .is_not_ready());
+    clock.advance(Duration::from_secs(1));
+    assert!(future
+        .poll_future_notify(&notify, 0)
+        .unwrap()
+        .is_not_ready());
+    clock.advance(Duration::from_secs(1));
+    match future.poll_future_notify(&notify, 0) {
+        Ok(Async::Ready(response)) => assert_eq!(response.status(), StatusCode::UNAUTHORIZED),
+        _ => panic!("should be timed out"),
+    }
+    assert_eq!(resolver.reverse_calls(), 1);
+
+    Ok(())
+}
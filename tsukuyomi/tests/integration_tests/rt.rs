@@ -0,0 +1,33 @@
+use tsukuyomi::{
+    config::prelude::*,
+    extractor,
+    rt::{Random, SeededRandom},
+    App,
+};
+
+fn request_ids(seed: u64) -> tsukuyomi_server::Result<Vec<String>> {
+    let app = App::create(
+        path!("/") //
+            .to(endpoint::get()
+                .extract(extractor::ready(|input| {
+                    Ok::<_, tsukuyomi::Error>((format!("{:016x}", input.random().next_u64()),))
+                }))
+                .call(|request_id: String| request_id)),
+    )?
+    .with_random(SeededRandom::new(seed));
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    (0..3)
+        .map(|_| Ok(server.perform("/")?.body().to_utf8()?.into_owned()))
+        .collect()
+}
+
+#[test]
+fn seeded_request_ids_are_reproducible() -> tsukuyomi_server::Result<()> {
+    let ids = request_ids(42)?;
+    assert_eq!(ids, request_ids(42)?);
+    assert_ne!(ids, request_ids(43)?);
+    assert_ne!(ids[0], ids[1]);
+    assert_ne!(ids[1], ids[2]);
+    Ok(())
+}
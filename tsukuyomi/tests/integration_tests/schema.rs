@@ -0,0 +1,100 @@
+use {
+    http::Request,
+    serde_json::{json, Value},
+    tsukuyomi::{
+        config::prelude::*,
+        extractor,
+        schema::{self, AuditMode},
+        App,
+    },
+};
+
+fn user_schema() -> Value {
+    json!({
+        "type": "object",
+        "required": ["name", "age"],
+        "properties": {
+            "name": { "type": "string", "minLength": 1 },
+            "age": { "type": "integer", "minimum": 0 },
+        },
+    })
+}
+
+#[test]
+fn missing_required_property() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/users") //
+            .to(endpoint::post()
+                .extract(extractor::body::json())
+                .call(|user: Value| user["name"].to_string()))
+            .modify(schema::request(&user_schema())?),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(
+        Request::post("/users")
+            .header("content-type", "application/json")
+            .body(r#"{ "age": -1 }"#),
+    )?;
+    assert_eq!(response.status(), 422);
+    let problem: Value = serde_json::from_str(&response.body().to_utf8()?)?;
+    assert_eq!(
+        problem["violations"],
+        json!([
+            { "path": "", "message": "missing the required property `name'" },
+            { "path": "/age", "message": "expected a number >= 0" },
+        ])
+    );
+
+    let response = server.perform(
+        Request::post("/users")
+            .header("content-type", "application/json")
+            .body(r#"{ "name": "alice", "age": 20 }"#),
+    )?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, r#""alice""#);
+
+    Ok(())
+}
+
+#[test]
+fn compile_error_names_pointer() {
+    let err = schema::request(&json!({
+        "properties": { "age": { "minimum": "zero" } },
+    }))
+    .unwrap_err();
+    assert_eq!(err.pointer(), "/properties/age/minimum");
+}
+
+#[test]
+fn response_audit() -> tsukuyomi_server::Result<()> {
+    let audit = schema::response(&user_schema())?.mode(AuditMode::Log);
+    let strict = schema::response(&user_schema())?.mode(AuditMode::Strict);
+
+    let app = App::create(chain![
+        path!("/conforming") //
+            .to(endpoint::reply(r#"{ "name": "alice", "age": 20 }"#))
+            .modify(audit.clone()),
+        path!("/log") //
+            .to(endpoint::reply(r#"{ "name": "alice" }"#))
+            .modify(audit.clone()),
+        path!("/strict") //
+            .to(endpoint::reply(r#"{ "name": "alice" }"#))
+            .modify(strict.clone()),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/conforming")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(audit.violations(), 0);
+
+    let response = server.perform("/log")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(audit.violations(), 1);
+
+    let response = server.perform("/strict")?;
+    assert_eq!(response.status(), 500);
+    assert_eq!(strict.violations(), 1);
+
+    Ok(())
+}
@@ -0,0 +1,118 @@
+use {
+    http::{
+        header::{ACCEPT, CONTENT_TYPE, VARY},
+        Request, StatusCode,
+    },
+    tsukuyomi::{
+        config::prelude::*,
+        output::{Encoders, Serialize},
+        App,
+    },
+};
+
+#[derive(Debug, serde::Serialize)]
+struct User {
+    id: u32,
+    name: String,
+}
+
+fn user() -> Result<Serialize<User>, tsukuyomi::Error> {
+    Ok(Serialize(User {
+        id: 1,
+        name: "alice".into(),
+    }))
+}
+
+#[test]
+fn json_by_default() -> tsukuyomi_server::Result<()> {
+    let app = App::create(path!("/user").to(endpoint::get().call_async(user)))?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/user")?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[CONTENT_TYPE], "application/json");
+    assert_eq!(response.headers()[VARY], "accept");
+    assert_eq!(response.body().to_utf8()?, r#"{"id":1,"name":"alice"}"#);
+
+    let response = server.perform(Request::get("/user").header(ACCEPT, "application/*"))?;
+    assert_eq!(response.headers()[CONTENT_TYPE], "application/json");
+
+    Ok(())
+}
+
+#[cfg(feature = "msgpack")]
+#[test]
+fn msgpack_by_accept() -> tsukuyomi_server::Result<()> {
+    let app = App::create(path!("/user").to(endpoint::get().call_async(user)))?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(
+        Request::get("/user").header(ACCEPT, "application/json;q=0.5, application/msgpack"),
+    )?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[CONTENT_TYPE], "application/msgpack");
+    // {"id": 1, "name": "alice"}
+    assert_eq!(
+        &*response.body().to_bytes(),
+        &b"\x82\xa2id\x01\xa4name\xa5alice"[..]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn custom_encoder() -> tsukuyomi_server::Result<()> {
+    let encoders = Encoders::new().register(
+        "text/csv".parse().unwrap(),
+        |value: &dyn tsukuyomi::vendor::erased_serde::Serialize| {
+            // A toy encoder which writes the fields of the JSON object in a row.
+            let value =
+                serde_json::to_value(value).map_err(tsukuyomi::error::internal_server_error)?;
+            let fields: Vec<String> = value
+                .as_object()
+                .into_iter()
+                .flat_map(|object| object.values())
+                .map(|value| match value {
+                    serde_json::Value::String(s) => s.clone(),
+                    value => value.to_string(),
+                })
+                .collect();
+            Ok(fields.join(",").into_bytes())
+        },
+    );
+    let app = App::create(chain![
+        path!("/user").to(endpoint::get().call_async(user)),
+        mount("/csv").with(chain![
+            tsukuyomi::config::state(encoders),
+            path!("/user").to(endpoint::get().call_async(user)),
+        ]),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(Request::get("/csv/user").header(ACCEPT, "text/csv"))?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[CONTENT_TYPE], "text/csv");
+    assert_eq!(response.body().to_utf8()?, "1,alice");
+
+    // The encoder is not registered in the other scope.
+    let response = server.perform(Request::get("/user").header(ACCEPT, "text/csv"))?;
+    assert_eq!(response.status(), StatusCode::NOT_ACCEPTABLE);
+
+    Ok(())
+}
+
+#[test]
+fn not_acceptable() -> tsukuyomi_server::Result<()> {
+    let app = App::create(path!("/user").to(endpoint::get().call_async(user)))?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(Request::get("/user").header(ACCEPT, "image/png"))?;
+    assert_eq!(response.status(), StatusCode::NOT_ACCEPTABLE);
+    assert_eq!(response.headers()[VARY], "accept");
+    assert!(response
+        .body()
+        .to_utf8()?
+        .starts_with("no acceptable representation (available: application/json"));
+
+    Ok(())
+}
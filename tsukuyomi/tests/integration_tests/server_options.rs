@@ -0,0 +1,131 @@
+use {
+    http::{
+        header::{ALLOW, LINK, SERVER},
+        Method, Request, StatusCode,
+    },
+    tsukuyomi::{
+        config::{prelude::*, Scope},
+        handler::AllowedMethods,
+        util::Never,
+        App,
+    },
+    tsukuyomi_server::test::ResponseExt,
+};
+
+macro_rules! manual_route {
+    ($method:expr) => {
+        |cx: &mut Scope<'_, (), _>| {
+            cx.asterisk_route(tsukuyomi::handler::handler(
+                || tsukuyomi::future::poll_fn(|_| Ok::<_, Never>("manual".into())),
+                Some(AllowedMethods::from($method)),
+            ))
+        }
+    };
+}
+
+#[test]
+fn allow_union_across_scopes() -> tsukuyomi_server::Result<()> {
+    let app = App::create(chain![
+        tsukuyomi::config::server_options(),
+        path!("/posts").to(chain![
+            endpoint::get().reply("list"),
+            endpoint::post().reply("create"),
+        ]),
+        mount("/api").with(chain![
+            path!("/items/:id") //
+                .to(chain![
+                    endpoint::put().call(|_: u32| "update"),
+                    endpoint::delete().call(|_: u32| "delete"),
+                ]),
+            mount("/v2").with(
+                path!("/items") //
+                    .to(endpoint::patch().reply("patch"))
+            ),
+        ]),
+        // the routes accepting any method are not listed.
+        path!("/any").to(endpoint::any().reply("any")),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(Request::options("*"))?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.header(ALLOW)?,
+        "GET, POST, PUT, DELETE, PATCH, OPTIONS"
+    );
+    assert!(!response.headers().contains_key(SERVER));
+
+    // the asterisk-form is only for OPTIONS.
+    let response = server.perform(Request::get("*"))?;
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(response.header(ALLOW)?, "OPTIONS");
+
+    Ok(())
+}
+
+#[test]
+fn customize_headers() -> tsukuyomi_server::Result<()> {
+    let app = App::create(chain![
+        tsukuyomi::config::server_options()
+            .server("example/1.0")
+            .api_description("/openapi.json")
+            .header("x-api-version".parse().unwrap(), "2019-03")
+            .header(LINK, "</docs>; rel=\"service-doc\""),
+        path!("/").to(endpoint::get().reply("index")),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(Request::options("*"))?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.header(ALLOW)?, "GET, OPTIONS");
+    assert_eq!(response.header(SERVER)?, "example/1.0");
+    assert_eq!(response.header("x-api-version")?, "2019-03");
+    let links: Vec<_> = response.headers().get_all(LINK).iter().collect();
+    assert_eq!(
+        links,
+        vec![
+            "</openapi.json>; rel=\"service-desc\"",
+            "</docs>; rel=\"service-doc\"",
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn conflict_with_manual_route() {
+    let result = App::create(chain![
+        tsukuyomi::config::server_options(),
+        manual_route!(Method::OPTIONS),
+    ]);
+    let err = result.err().expect("should be an error");
+    assert!(
+        err.to_string().contains("has already been registered"),
+        "{}",
+        err
+    );
+
+    let result = App::create(chain![
+        manual_route!(Method::OPTIONS),
+        tsukuyomi::config::server_options(),
+    ]);
+    assert!(result.is_err());
+}
+
+#[test]
+fn asterisk_route_constraints() -> tsukuyomi_server::Result<()> {
+    // only OPTIONS
+    let result = App::create(manual_route!(Method::GET));
+    assert!(result.is_err());
+
+    // no prefix
+    let result = App::create(mount("/api").with(tsukuyomi::config::server_options()));
+    assert!(result.is_err());
+
+    let app = App::create(manual_route!(Method::OPTIONS))?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+    let response = server.perform(Request::options("*"))?;
+    assert_eq!(response.body().to_utf8()?, "manual");
+
+    Ok(())
+}
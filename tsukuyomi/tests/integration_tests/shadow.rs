@@ -0,0 +1,145 @@
+use {
+    http::Request,
+    hyper::Body,
+    std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    },
+    tsukuyomi::{
+        config::prelude::*,
+        extractor,
+        modifiers::{self, Shadow},
+        rt::SeededRandom,
+        App,
+    },
+};
+
+/// Waits for the mirrored requests running in the background.
+fn wait_until(f: impl Fn() -> bool) {
+    for _ in 0..500 {
+        if f() {
+            return;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    panic!("timed out");
+}
+
+fn app(shadow: &Shadow, observed: &Arc<Mutex<Vec<String>>>) -> tsukuyomi::app::Result<App> {
+    App::create(chain![
+        path!("/users/:id")
+            .to(endpoint::post()
+                .extract(extractor::body::plain())
+                .call(|id: u32, body: String| format!("user {}: {}", id, body)))
+            .modify(shadow.clone()),
+        path!("/ping")
+            .to(endpoint::get().reply("pong"))
+            .modify(shadow.clone()),
+        mount("/shadow").with(chain![
+            path!("/users/:id") //
+                .to(endpoint::post().extract(extractor::body::plain()).call({
+                    let observed = observed.clone();
+                    move |id: u32, body: String| {
+                        observed.lock().unwrap().push(format!("{}: {}", id, body));
+                        "shadow"
+                    }
+                })),
+            path!("/ping") //
+                .to(endpoint::get().call(|| {
+                    Err::<&str, _>(tsukuyomi::error::internal_server_error("not implemented"))
+                })),
+        ]),
+    ])
+}
+
+#[test]
+fn mirrored_request_reaches_shadow_route() -> tsukuyomi_server::Result<()> {
+    let shadow = modifiers::shadow("/shadow")?;
+    let observed = Arc::new(Mutex::new(vec![]));
+    let mut server = tsukuyomi_server::test::server(app(&shadow, &observed)?)?;
+
+    let response = server.perform(Request::post("/users/42").body("hello"))?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "user 42: hello");
+
+    wait_until(|| shadow.metrics().matched == 1);
+    assert_eq!(*observed.lock().unwrap(), vec!["42: hello".to_owned()]);
+    let metrics = shadow.metrics();
+    assert_eq!(metrics.mirrored, 1);
+    assert_eq!(metrics.diverged, 0);
+    assert_eq!(metrics.entries()[0], ("shadow_requests_total", 1));
+
+    Ok(())
+}
+
+#[test]
+fn streaming_and_large_bodies_are_not_mirrored() -> tsukuyomi_server::Result<()> {
+    let shadow = modifiers::shadow("/shadow")?.max_body_size(4);
+    let observed = Arc::new(Mutex::new(vec![]));
+    let mut server = tsukuyomi_server::test::server(app(&shadow, &observed)?)?;
+
+    let response = server.perform(Request::post("/users/1").body("too large"))?;
+    assert_eq!(response.body().to_utf8()?, "user 1: too large");
+
+    let (mut sender, body) = Body::channel();
+    std::thread::spawn(move || {
+        let _ = sender.send_data("abc".into());
+    });
+    let response = server.perform(Request::post("/users/2").body(body))?;
+    assert_eq!(response.body().to_utf8()?, "user 2: abc");
+
+    let metrics = shadow.metrics();
+    assert_eq!(metrics.skipped, 2);
+    assert_eq!(metrics.mirrored, 0);
+    assert!(observed.lock().unwrap().is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn sampling_with_seeded_random() -> tsukuyomi_server::Result<()> {
+    let mirrored = |sample_rate: f64| -> tsukuyomi_server::Result<u64> {
+        let shadow = modifiers::shadow("/shadow")?.sample_rate(sample_rate);
+        let app = app(&shadow, &Arc::default())?.with_random(SeededRandom::new(42));
+        let mut server = tsukuyomi_server::test::server(app)?;
+        for _ in 0..32 {
+            let response = server.perform(Request::get("/ping"))?;
+            assert_eq!(response.body().to_utf8()?, "pong");
+        }
+        let mirrored = shadow.metrics().mirrored;
+        wait_until(|| shadow.metrics().diverged == mirrored);
+        Ok(mirrored)
+    };
+
+    let sampled = mirrored(0.5)?;
+    assert!(sampled > 0 && sampled < 32, "sampled = {}", sampled);
+    assert_eq!(mirrored(0.5)?, sampled);
+    assert_eq!(mirrored(0.0)?, 0);
+    assert_eq!(mirrored(1.0)?, 32);
+
+    Ok(())
+}
+
+#[test]
+fn divergence_is_logged() -> tsukuyomi_server::Result<()> {
+    let _ = super::logging::take_records("shadow response diverged:");
+
+    let shadow = modifiers::shadow("/shadow")?.log_divergence(true);
+    let mut server = tsukuyomi_server::test::server(app(&shadow, &Arc::default())?)?;
+
+    let response = server.perform(Request::get("/ping?verbose=1"))?;
+    assert_eq!(response.status(), 200);
+
+    wait_until(|| shadow.metrics().diverged == 1);
+    assert_eq!(
+        super::logging::take_records("shadow response diverged:"),
+        vec!["shadow response diverged: GET /shadow/ping?verbose=1 (primary: 200, shadow: 500)"]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn target_must_be_absolute_path() {
+    assert!(modifiers::shadow("shadow").is_err());
+}
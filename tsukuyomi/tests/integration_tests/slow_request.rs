@@ -0,0 +1,110 @@
+use {
+    std::time::Duration,
+    tsukuyomi::{app::SlowRequestLog, config::prelude::*, rt::MockClock, App},
+};
+
+fn take_records() -> Vec<String> {
+    super::logging::take_records("slow request:")
+}
+
+/// Creates a function that advances the mock clock as if the handler took a long time.
+fn sleep(clock: &MockClock) -> impl Fn() + Clone + Send + Sync + 'static {
+    let clock = clock.clone();
+    move || clock.advance(Duration::from_millis(20))
+}
+
+#[test]
+fn slow_request_log() -> tsukuyomi_server::Result<()> {
+    let _ = take_records();
+
+    let clock = MockClock::new();
+    let app = App::create(chain![
+        SlowRequestLog::new(Duration::from_millis(5)),
+        path!("/fast").to(endpoint::call(|| "fast")),
+        path!("/slow/:id").to(endpoint::call({
+            let sleep = sleep(&clock);
+            move |_id: u32| {
+                sleep();
+                "slow"
+            }
+        })),
+        path!("/error").to(endpoint::call({
+            let sleep = sleep(&clock);
+            move || {
+                sleep();
+                Err::<&str, _>(tsukuyomi::error::internal_server_error("oops"))
+            }
+        })),
+        mount("/relaxed").with(chain![
+            SlowRequestLog::new(Duration::from_secs(60)),
+            path!("/slow").to(endpoint::call({
+                let sleep = sleep(&clock);
+                move || {
+                    sleep();
+                    "slow"
+                }
+            })),
+        ]),
+        mount("/sampled").with(chain![
+            SlowRequestLog::new(Duration::from_millis(5)).sample_rate(0.5),
+            path!("/slow").to(endpoint::call({
+                let sleep = sleep(&clock);
+                move || {
+                    sleep();
+                    "slow"
+                }
+            })),
+        ]),
+    ])?
+    .with_clock(clock.clone());
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let _ = server.perform("/fast")?;
+    assert!(take_records().is_empty());
+
+    let _ = server.perform("/slow/42")?;
+    let records = take_records();
+    assert_eq!(records.len(), 1, "{:?}", records);
+    assert!(
+        records[0].starts_with(
+            "slow request: method=GET pattern=/slow/:id status=200 elapsed=20ms recognize=0ns "
+        ),
+        "{}",
+        records[0]
+    );
+
+    let _ = server.perform("/error")?;
+    let records = take_records();
+    assert_eq!(records.len(), 1, "{:?}", records);
+    assert!(
+        records[0].starts_with("slow request: method=GET pattern=/error status=500 "),
+        "{}",
+        records[0]
+    );
+
+    let _ = server.perform("/relaxed/slow")?;
+    assert!(take_records().is_empty());
+
+    for _ in 0..4 {
+        let _ = server.perform("/sampled/slow")?;
+    }
+    assert_eq!(take_records().len(), 2);
+
+    let app = App::create(chain![
+        SlowRequestLog::new(Duration::from_millis(0)),
+        path!("/").to(endpoint::call(|| "index")),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/missing")?;
+    assert_eq!(response.status(), 404);
+    let records = take_records();
+    assert_eq!(records.len(), 1, "{:?}", records);
+    assert!(
+        records[0].starts_with("slow request: method=GET pattern=- status=404 "),
+        "{}",
+        records[0]
+    );
+
+    Ok(())
+}
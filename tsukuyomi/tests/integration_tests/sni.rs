@@ -0,0 +1,94 @@
+use {
+    http::{Request, StatusCode},
+    tsukuyomi::{config::prelude::*, extractor, guard, input::ConnectionInfo, App},
+};
+
+fn request(host: &str, server_name: Option<&str>) -> http::request::Builder {
+    let mut info = ConnectionInfo::new(([192, 0, 2, 1], 50000).into());
+    info.set_server_name(server_name.map(ToOwned::to_owned));
+    let mut request = Request::get("/");
+    request.header("host", host).extension(info);
+    request
+}
+
+#[test]
+fn extract_server_name() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/") //
+            .to(endpoint::get()
+                .extract(extractor::sni())
+                .call(|name: Option<String>| name.unwrap_or_else(|| "none".into()))),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(request("tenant1.example.com", Some("tenant1.example.com")))?;
+    assert_eq!(response.body().to_utf8()?, "tenant1.example.com");
+
+    // plain HTTP connections do not have the server name.
+    let response = server.perform(request("tenant1.example.com", None))?;
+    assert_eq!(response.body().to_utf8()?, "none");
+
+    Ok(())
+}
+
+#[test]
+fn route_by_server_name() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/") //
+            .to(chain![
+                endpoint::get()
+                    .guard(guard::sni("tenant1.example.com"))
+                    .reply("tenant1"),
+                endpoint::get()
+                    .guard(guard::sni("tenant2.example.com"))
+                    .reply("tenant2"),
+            ]),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(request("tenant1.example.com", Some("tenant1.example.com")))?;
+    assert_eq!(response.body().to_utf8()?, "tenant1");
+
+    let response = server.perform(request("tenant2.example.com", Some("TENANT2.example.com")))?;
+    assert_eq!(response.body().to_utf8()?, "tenant2");
+
+    let response = server.perform(request("tenant3.example.com", Some("tenant3.example.com")))?;
+    assert_eq!(response.status(), StatusCode::MISDIRECTED_REQUEST);
+
+    let response = server.perform(request("tenant1.example.com", None))?;
+    assert_eq!(response.status(), StatusCode::MISDIRECTED_REQUEST);
+
+    Ok(())
+}
+
+#[test]
+fn strict_rejects_host_mismatch() -> tsukuyomi_server::Result<()> {
+    let app = App::create(chain![
+        path!("/") //
+            .to(endpoint::get()
+                .guard(guard::sni("tenant1.example.com").strict(true))
+                .reply("tenant1")),
+        path!("/lenient") //
+            .to(endpoint::get()
+                .guard(guard::sni("tenant1.example.com"))
+                .reply("tenant1")),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(request(
+        "tenant1.example.com:443",
+        Some("tenant1.example.com"),
+    ))?;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // the connection for tenant1 is reused for tenant2.
+    let response = server.perform(request("tenant2.example.com", Some("tenant1.example.com")))?;
+    assert_eq!(response.status(), StatusCode::MISDIRECTED_REQUEST);
+
+    let mut lenient = request("tenant2.example.com", Some("tenant1.example.com"));
+    lenient.uri("/lenient");
+    let response = server.perform(lenient)?;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    Ok(())
+}
@@ -0,0 +1,170 @@
+use {
+    http::{Method, Request},
+    tsukuyomi::{
+        config::prelude::*,
+        dynamic::DynamicConfig,
+        endpoint::{Side, Split},
+        extractor,
+        featureflags::KeySource,
+        input::localmap::LocalData,
+        App,
+    },
+    tsukuyomi_server::test::ResponseExt,
+};
+
+fn app(split: Split) -> tsukuyomi::app::Result<App> {
+    App::create(
+        path!("/checkout") //
+            .to(endpoint::split(
+                endpoint::get().reply("old"),
+                endpoint::get()
+                    .extract(extractor::ready(|input| {
+                        Ok::<_, tsukuyomi::Error>((Side::get(input.locals).cloned(),))
+                    }))
+                    .call(|side: Option<Side>| {
+                        assert_eq!(side, Some(Side::New));
+                        "new"
+                    }),
+                split,
+            )),
+    )
+}
+
+fn perform(
+    server: &mut tsukuyomi_server::test::Server<App>,
+    user: &str,
+) -> tsukuyomi_server::Result<String> {
+    let response = server.perform(Request::get("/checkout").header("x-user-id", user))?;
+    assert_eq!(response.status(), 200);
+    Ok(response.body().to_utf8()?.into_owned())
+}
+
+#[test]
+fn assignment_is_deterministic_per_key() -> tsukuyomi_server::Result<()> {
+    let users: Vec<String> = (0..20).map(|i| format!("user-{}", i)).collect();
+
+    let mut assigned = vec![];
+    let mut server = tsukuyomi_server::test::server(app(Split::percentage(
+        KeySource::header("x-user-id"),
+        50.0,
+    ))?)?;
+    for user in &users {
+        assigned.push(perform(&mut server, user)?);
+    }
+    assert!(assigned.iter().any(|side| side == "old"));
+    assert!(assigned.iter().any(|side| side == "new"));
+
+    // The assignments do not change across the requests and the instances.
+    let mut server = tsukuyomi_server::test::server(app(Split::percentage(
+        KeySource::header("x-user-id"),
+        50.0,
+    ))?)?;
+    for (user, side) in users.iter().zip(&assigned) {
+        assert_eq!(perform(&mut server, user)?, *side);
+        assert_eq!(perform(&mut server, user)?, *side);
+    }
+
+    // The requests without the key are handled by the old side.
+    let response = server.perform("/checkout")?;
+    assert_eq!(response.body().to_utf8()?, "old");
+
+    Ok(())
+}
+
+#[test]
+fn override_header_forces_new_side() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app(Split::percentage(
+        KeySource::header("x-user-id"),
+        0.0,
+    )
+    .force_header("x-canary", "always"))?)?;
+
+    assert_eq!(perform(&mut server, "user-1")?, "old");
+
+    let response = server.perform(
+        Request::get("/checkout")
+            .header("x-user-id", "user-1")
+            .header("x-canary", "always"),
+    )?;
+    assert_eq!(response.body().to_utf8()?, "new");
+
+    let response = server.perform(Request::get("/checkout").header("x-canary", "never"))?;
+    assert_eq!(response.body().to_utf8()?, "old");
+
+    // The header match can be used as the only strategy.
+    let mut server = tsukuyomi_server::test::server(app(Split::header("x-canary", "always"))?)?;
+    let response = server.perform(Request::get("/checkout").header("x-canary", "always"))?;
+    assert_eq!(response.body().to_utf8()?, "new");
+    let response = server.perform("/checkout")?;
+    assert_eq!(response.body().to_utf8()?, "old");
+
+    Ok(())
+}
+
+#[test]
+fn live_percentage_change() -> tsukuyomi_server::Result<()> {
+    let percent = DynamicConfig::new(0.0);
+    let mut server = tsukuyomi_server::test::server(app(Split::dynamic(
+        KeySource::header("x-user-id"),
+        percent.clone(),
+    ))?)?;
+
+    let users: Vec<String> = (0..10).map(|i| format!("user-{}", i)).collect();
+    for user in &users {
+        assert_eq!(perform(&mut server, user)?, "old");
+    }
+
+    percent.set(100.0);
+    for user in &users {
+        assert_eq!(perform(&mut server, user)?, "new");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn annotation_header() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app(Split::header("x-canary", "always"))?)?;
+    let response = server.perform(Request::get("/checkout").header("x-canary", "always"))?;
+    assert_eq!(response.header("x-split-side")?, "new");
+    let response = server.perform("/checkout")?;
+    assert_eq!(response.header("x-split-side")?, "old");
+
+    let mut server = tsukuyomi_server::test::server(app(
+        Split::header("x-canary", "always").annotate("x-release")
+    )?)?;
+    let response = server.perform("/checkout")?;
+    assert_eq!(response.header("x-release")?, "old");
+    assert!(!response.headers().contains_key("x-split-side"));
+
+    let mut server =
+        tsukuyomi_server::test::server(app(Split::header("x-canary", "always").no_annotation())?)?;
+    let response = server.perform("/checkout")?;
+    assert!(!response.headers().contains_key("x-split-side"));
+
+    Ok(())
+}
+
+#[test]
+fn allowed_methods_are_shared() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app(Split::header("x-canary", "always"))?)?;
+    let response = server.perform(Request::post("/checkout").header("x-canary", "always"))?;
+    assert_eq!(response.status(), 405);
+    assert_eq!(response.header("allow")?, "GET");
+
+    Ok(())
+}
+
+#[test]
+#[should_panic(expected = "the endpoints of `split` must accept the same methods")]
+fn mismatched_methods() {
+    let _ = App::create(
+        path!("/").to(endpoint::split(
+            endpoint::get().reply("old"),
+            endpoint::allow_only(vec![Method::GET, Method::POST])
+                .unwrap()
+                .reply("new"),
+            Split::header("x-canary", "always"),
+        )),
+    );
+}
@@ -0,0 +1,74 @@
+use tsukuyomi::{
+    config::{prelude::*, state},
+    extractor, App,
+};
+
+#[derive(Debug, Clone)]
+struct Name(&'static str);
+
+fn app() -> tsukuyomi::app::Result<App> {
+    let chain = || {
+        endpoint::get()
+            .extract(extractor::state::<Name>())
+            .extract(extractor::state::collect::<Name>())
+            .call(|nearest: Name, chain: Vec<Name>| {
+                let chain: Vec<_> = chain.iter().map(|name| name.0).collect();
+                format!("{} {}", nearest.0, chain.join(","))
+            })
+    };
+    App::create(chain![
+        state(Name("G")),
+        path!("/").to(chain()),
+        mount("/a").with(chain![
+            state(Name("A")), //
+            path!("/").to(chain()),
+        ]),
+        mount("/b").with(chain![
+            state(Name("B")),
+            path!("/").to(chain()),
+            mount("/c").with(chain![
+                state(Name("C")), //
+                path!("/").to(chain()),
+            ]),
+            mount("/unnamed").with(path!("/").to(chain())),
+        ]),
+    ])
+}
+
+#[test]
+fn collect_along_ancestors() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+
+    let response = server.perform("/")?;
+    assert_eq!(response.body().to_utf8()?, "G G");
+
+    let response = server.perform("/a")?;
+    assert_eq!(response.body().to_utf8()?, "A G,A");
+
+    let response = server.perform("/b")?;
+    assert_eq!(response.body().to_utf8()?, "B G,B");
+
+    let response = server.perform("/b/c")?;
+    assert_eq!(response.body().to_utf8()?, "C G,B,C");
+
+    let response = server.perform("/b/unnamed")?;
+    assert_eq!(response.body().to_utf8()?, "B G,B");
+
+    Ok(())
+}
+
+#[test]
+fn collect_without_states() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/") //
+            .to(endpoint::get()
+                .extract(extractor::state::collect::<Name>())
+                .call(|chain: Vec<Name>| format!("{}", chain.len()))),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/")?;
+    assert_eq!(response.body().to_utf8()?, "0");
+
+    Ok(())
+}
@@ -0,0 +1,120 @@
+use {
+    http::{header, Request},
+    tsukuyomi::{static_routes, App},
+};
+
+fn app() -> tsukuyomi::app::Result<App> {
+    App::create(static_routes!(
+        "./tests/fixtures/static",
+        exclude = ["*.map", "drafts/**"],
+    ))
+}
+
+#[test]
+fn serves_embedded_files() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+
+    let response = server.perform("/style.css")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.headers().get(header::CONTENT_TYPE).unwrap(),
+        "text/css"
+    );
+    assert!(response.headers().contains_key(header::ETAG));
+    assert_eq!(response.body().to_utf8()?, "body { color: black; }\n");
+
+    let response = server.perform("/sub/app.js")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "console.log(\"app\");\n");
+
+    Ok(())
+}
+
+#[test]
+fn serves_index_files() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+
+    let response = server.perform("/")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.headers().get(header::CONTENT_TYPE).unwrap(),
+        "text/html"
+    );
+    assert_eq!(response.body().to_utf8()?, "<h1>index</h1>\n");
+
+    let response = server.perform("/sub/")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "<h1>sub</h1>\n");
+
+    let response = server.perform("/sub/index.html")?;
+    assert_eq!(response.status(), 200);
+
+    Ok(())
+}
+
+#[test]
+fn excluded_files_are_not_found() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+
+    let response = server.perform("/sub/app.js.map")?;
+    assert_eq!(response.status(), 404);
+
+    let response = server.perform("/drafts/draft.html")?;
+    assert_eq!(response.status(), 404);
+
+    Ok(())
+}
+
+#[test]
+fn not_modified_with_matching_etag() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+
+    let response = server.perform("/style.css")?;
+    let etag = response.headers().get(header::ETAG).unwrap().clone();
+
+    let response =
+        server.perform(Request::get("/style.css").header(header::IF_NONE_MATCH, etag.clone()))?;
+    assert_eq!(response.status(), 304);
+    assert_eq!(response.headers().get(header::ETAG), Some(&etag));
+    assert!(response.body().to_bytes().is_empty());
+
+    let response = server
+        .perform(Request::get("/style.css").header(header::IF_NONE_MATCH, "\"mismatched\""))?;
+    assert_eq!(response.status(), 200);
+
+    Ok(())
+}
+
+#[test]
+fn partial_content() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+
+    let response = server.perform("/style.css")?;
+    assert_eq!(
+        response.headers().get(header::ACCEPT_RANGES).unwrap(),
+        "bytes"
+    );
+
+    let response = server.perform(Request::get("/style.css").header(header::RANGE, "bytes=0-3"))?;
+    assert_eq!(response.status(), 206);
+    assert_eq!(
+        response.headers().get(header::CONTENT_RANGE).unwrap(),
+        "bytes 0-3/23"
+    );
+    assert_eq!(
+        response.headers().get(header::CONTENT_TYPE).unwrap(),
+        "text/css"
+    );
+    assert!(response.headers().contains_key(header::ETAG));
+    assert_eq!(response.body().to_utf8()?, "body");
+
+    let response =
+        server.perform(Request::get("/style.css").header(header::RANGE, "bytes=100-"))?;
+    assert_eq!(response.status(), 416);
+    assert_eq!(
+        response.headers().get(header::CONTENT_RANGE).unwrap(),
+        "bytes */23"
+    );
+
+    Ok(())
+}
@@ -0,0 +1,128 @@
+use {
+    http::Request,
+    hyper::{body::Payload, Body},
+    std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        thread,
+        time::Duration,
+    },
+    tsukuyomi::{
+        config::prelude::*,
+        output,
+        vendor::futures::{future, stream, Future, Stream},
+        App,
+    },
+    tsukuyomi_service::{MakeService, Service},
+};
+
+/// Performs the request on a thread pool, and returns the chunks of the response body
+/// without buffering them.
+fn perform_streaming(
+    app: &App,
+    pool: &tokio_threadpool::ThreadPool,
+    path: &str,
+) -> impl Iterator<Item = Result<hyper::Chunk, hyper::Error>> {
+    let mut service = MakeService::<(), Request<Body>>::make_service(app, ())
+        .wait()
+        .unwrap_or_else(|never| match never {});
+    let request = Request::get(path).body(Body::empty()).unwrap();
+    let response = pool
+        .spawn_handle(future::lazy(move || service.call(request)))
+        .wait()
+        .unwrap_or_else(|never| match never {});
+    assert_eq!(response.status(), 200);
+
+    let mut body = response.into_body();
+    stream::poll_fn(move || body.poll_data()).wait()
+}
+
+#[test]
+fn slow_client_does_not_exceed_capacity() -> tsukuyomi_server::Result<()> {
+    const CAPACITY: usize = 4;
+    let produced = Arc::new(AtomicUsize::new(0));
+    let app = App::create({
+        let produced = produced.clone();
+        path!("/") //
+            .to(endpoint::get().call(move || {
+                let produced = produced.clone();
+                output::stream_blocking(move || {
+                    (0..1000).map(move |i| {
+                        produced.fetch_add(1, Ordering::SeqCst);
+                        format!("{}\n", i)
+                    })
+                })
+                .capacity(CAPACITY)
+            }))
+    })?;
+    let pool = tokio_threadpool::ThreadPool::new();
+
+    let mut received = vec![];
+    for (consumed, chunk) in perform_streaming(&app, &pool, "/").enumerate() {
+        received.extend_from_slice(&chunk?);
+        if consumed % 100 == 0 {
+            // give the iterator enough time to fill the channel.
+            thread::sleep(Duration::from_millis(20));
+        }
+        let in_flight = produced.load(Ordering::SeqCst) - (consumed + 1);
+        assert!(in_flight <= CAPACITY, "in flight: {}", in_flight);
+    }
+
+    let expected: String = (0..1000).map(|i| format!("{}\n", i)).collect();
+    assert_eq!(String::from_utf8(received)?, expected);
+    assert_eq!(produced.load(Ordering::SeqCst), 1000);
+
+    Ok(())
+}
+
+#[test]
+fn panic_in_iterator_aborts_response() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/") //
+            .to(endpoint::get().call(|| {
+                output::stream_blocking(|| {
+                    (0..10).map(|i| {
+                        if i == 5 {
+                            panic!("explicit panic");
+                        }
+                        format!("{}\n", i)
+                    })
+                })
+            })),
+    )?;
+    let pool = tokio_threadpool::ThreadPool::new();
+
+    let chunks: Vec<_> = perform_streaming(&app, &pool, "/").collect();
+    assert_eq!(chunks.len(), 6);
+    assert!(chunks[..5].iter().all(Result::is_ok));
+    assert!(chunks[5].is_err());
+
+    Ok(())
+}
+
+#[test]
+fn serialize_items() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/") //
+            .to(endpoint::get().call(|| {
+                output::stream_blocking_with(
+                    || vec![(1, "alice"), (2, "bob")],
+                    |row| {
+                        serde_json::to_vec(&row).map(|mut line| {
+                            line.push(b'\n');
+                            line
+                        })
+                    },
+                )
+            })),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "[1,\"alice\"]\n[2,\"bob\"]\n");
+
+    Ok(())
+}
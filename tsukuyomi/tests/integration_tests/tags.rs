@@ -0,0 +1,119 @@
+use {
+    std::sync::{Arc, Mutex},
+    tsukuyomi::{
+        config::prelude::*,
+        handler::{AllowedMethods, Handler, ModifyHandler},
+        App,
+    },
+};
+
+#[derive(Clone)]
+struct MockModifier {
+    marker: Arc<Mutex<Vec<&'static str>>>,
+    name: &'static str,
+}
+
+impl<H: Handler> ModifyHandler<H> for MockModifier {
+    type Output = H::Output;
+    type Handler = MockHandler<H>;
+
+    fn modify(&self, inner: H) -> Self::Handler {
+        MockHandler {
+            inner,
+            marker: self.marker.clone(),
+            name: self.name,
+        }
+    }
+}
+
+struct MockHandler<H> {
+    inner: H,
+    marker: Arc<Mutex<Vec<&'static str>>>,
+    name: &'static str,
+}
+
+impl<H> Handler for MockHandler<H>
+where
+    H: Handler,
+{
+    type Output = H::Output;
+    type Error = H::Error;
+    type Handle = H::Handle;
+
+    fn allowed_methods(&self) -> Option<&AllowedMethods> {
+        self.inner.allowed_methods()
+    }
+
+    fn handle(&self) -> Self::Handle {
+        self.marker.lock().unwrap().push(self.name);
+        self.inner.handle()
+    }
+}
+
+fn app(marker: &Arc<Mutex<Vec<&'static str>>>) -> tsukuyomi::app::Result<App> {
+    App::create(
+        mount("/api")
+            .with(chain![
+                path!("/public") //
+                    .to(endpoint::get().reply("public"))
+                    .tag("public-api"),
+                path!("/internal") //
+                    .to(endpoint::get().reply("internal")),
+            ])
+            .modify(MockModifier {
+                marker: marker.clone(),
+                name: "scope",
+            }),
+    )
+}
+
+#[test]
+fn apply_modifier_to_tagged_routes() -> tsukuyomi_server::Result<()> {
+    let marker = Arc::new(Mutex::new(vec![]));
+    let app = app(&marker)?.apply_to_tag(
+        "public-api",
+        MockModifier {
+            marker: marker.clone(),
+            name: "tag",
+        },
+    )?;
+    assert_eq!(
+        app.debug_routes(),
+        "/api/public\n\
+         \x20   GET [scope /api] tags: public-api\n\
+         /api/internal\n\
+         \x20   GET [scope /api]\n"
+    );
+
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/api/public")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(*marker.lock().unwrap(), vec!["tag", "scope"]);
+
+    marker.lock().unwrap().clear();
+    let response = server.perform("/api/internal")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(*marker.lock().unwrap(), vec!["scope"]);
+
+    Ok(())
+}
+
+#[test]
+fn unknown_tag_in_strict_mode() -> tsukuyomi_server::Result<()> {
+    let marker = Arc::new(Mutex::new(vec![]));
+    let modifier = MockModifier {
+        marker: marker.clone(),
+        name: "tag",
+    };
+
+    assert!(app(&marker)?
+        .apply_to_tag("admin", modifier.clone())
+        .is_ok());
+    assert!(app(&marker)?
+        .strict_tags(true)
+        .apply_to_tag("admin", modifier)
+        .is_err());
+
+    Ok(())
+}
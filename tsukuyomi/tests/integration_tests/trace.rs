@@ -0,0 +1,186 @@
+use {
+    http::{HeaderMap, Request},
+    std::sync::{Arc, Mutex},
+    tsukuyomi::{
+        config::prelude::*,
+        extractor,
+        rt::SeededRandom,
+        trace::{Trace, TraceContext},
+        App,
+    },
+    tsukuyomi_server::test::ResponseExt,
+};
+
+const TRACEPARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+/// A mock of the client which records the header fields of the requests to the upstream.
+#[derive(Clone, Default)]
+struct Upstream {
+    requests: Arc<Mutex<Vec<HeaderMap>>>,
+}
+
+impl Upstream {
+    fn send(&self, request: Request<()>) {
+        self.requests
+            .lock()
+            .unwrap()
+            .push(request.headers().clone());
+    }
+}
+
+fn app(upstream: Upstream) -> tsukuyomi::app::Result<App> {
+    Ok(App::create(
+        path!("/")
+            .to(endpoint::get().extract(extractor::trace_context()).call(
+                move |cx: TraceContext| {
+                    let mut request = Request::get("http://upstream.local/").body(()).unwrap();
+                    cx.inject(request.headers_mut());
+                    upstream.send(request);
+                    format!(
+                        "{:032x} {:016x} {:?} {}",
+                        cx.trace_id(),
+                        cx.span_id(),
+                        cx.parent_id().map(|id| format!("{:016x}", id)),
+                        cx.is_sampled()
+                    )
+                },
+            ))
+            .modify(Trace::new()),
+    )?
+    .with_random(SeededRandom::new(42)))
+}
+
+fn span_id(traceparent: &str) -> &str {
+    traceparent.split('-').nth(2).unwrap()
+}
+
+#[test]
+fn valid_traceparent_round_trip() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app(Upstream::default())?)?;
+
+    let response = server.perform(
+        Request::get("/")
+            .header("traceparent", TRACEPARENT)
+            .header("tracestate", "congo=t61rcWkgMzE"),
+    )?;
+    assert_eq!(response.status(), 200);
+
+    let traceparent = response.header("traceparent")?.to_str()?.to_owned();
+    assert!(traceparent.starts_with("00-4bf92f3577b34da6a3ce929d0e0e4736-"));
+    assert!(traceparent.ends_with("-01"));
+    let span_id = span_id(&traceparent);
+    assert_ne!(span_id, "00f067aa0ba902b7");
+    assert_ne!(span_id, "0000000000000000");
+
+    assert_eq!(
+        response.body().to_utf8()?,
+        format!(
+            "4bf92f3577b34da6a3ce929d0e0e4736 {} Some(\"00f067aa0ba902b7\") true",
+            span_id
+        )
+    );
+
+    // the sampling flag is preserved.
+    let response = server.perform(Request::get("/").header(
+        "traceparent",
+        "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00",
+    ))?;
+    assert!(response.header("traceparent")?.to_str()?.ends_with("-00"));
+
+    Ok(())
+}
+
+#[test]
+fn malformed_traceparent_starts_new_trace() -> tsukuyomi_server::Result<()> {
+    let upstream = Upstream::default();
+    let mut server = tsukuyomi_server::test::server(app(upstream.clone())?)?;
+
+    for malformed in &[
+        "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+        "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+        "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+    ] {
+        let response = server.perform(
+            Request::get("/")
+                .header("traceparent", *malformed)
+                .header("tracestate", "congo=t61rcWkgMzE"),
+        )?;
+        assert_eq!(response.status(), 200);
+
+        let traceparent = response.header("traceparent")?.to_str()?;
+        assert!(!traceparent.contains("4bf92f3577b34da6a3ce929d0e0e4736"));
+        assert!(!traceparent.contains("00000000000000000000000000000000"));
+        assert!(traceparent.ends_with("-00"));
+        assert!(response.body().to_utf8()?.ends_with(" None false"));
+    }
+
+    // the tracestate is discarded along with the malformed traceparent.
+    let requests = upstream.requests.lock().unwrap();
+    assert!(requests
+        .iter()
+        .all(|headers| !headers.contains_key("tracestate")));
+
+    Ok(())
+}
+
+#[test]
+fn propagation_to_upstream() -> tsukuyomi_server::Result<()> {
+    let upstream = Upstream::default();
+    let mut server = tsukuyomi_server::test::server(app(upstream.clone())?)?;
+
+    let response = server.perform(
+        Request::get("/")
+            .header("traceparent", TRACEPARENT)
+            .header("tracestate", "congo=t61rcWkgMzE")
+            .header("tracestate", "rojo=00f067aa0ba902b7"),
+    )?;
+    let traceparent = response.header("traceparent")?.to_str()?;
+
+    let requests = upstream.requests.lock().unwrap();
+    assert_eq!(requests.len(), 1);
+    // the upstream continues the trace as a child of the span of this request.
+    assert_eq!(requests[0]["traceparent"], traceparent);
+    assert_eq!(
+        requests[0]["tracestate"],
+        "congo=t61rcWkgMzE,rojo=00f067aa0ba902b7"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn response_header() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(App::create(chain![
+        path!("/error")
+            .to(endpoint::get().call(|| -> tsukuyomi::Result<&'static str> {
+                Err(tsukuyomi::error::bad_request("error"))
+            }))
+            .modify(Trace::new().sample_roots(true)),
+        path!("/untraced").to(endpoint::reply("untraced")),
+    ])?)?;
+
+    let response = server.perform("/error")?;
+    assert_eq!(response.status(), 400);
+    let traceparent = response.header("traceparent")?.to_str()?;
+    assert_eq!(traceparent.len(), 55);
+    assert!(traceparent.starts_with("00-"));
+    assert!(traceparent.ends_with("-01"));
+
+    let response = server.perform("/untraced")?;
+    assert!(!response.headers().contains_key("traceparent"));
+
+    Ok(())
+}
+
+#[test]
+fn trace_context_without_modifier() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(App::create(
+        path!("/").to(endpoint::get()
+            .extract(extractor::trace_context())
+            .call(|_: TraceContext| "traced")),
+    )?)?;
+    let response = server.perform("/")?;
+    assert_eq!(response.status(), 500);
+    Ok(())
+}
@@ -0,0 +1,138 @@
+use {
+    http::{
+        header::{CONNECTION, UPGRADE},
+        Request, Response,
+    },
+    std::{
+        io::{BufRead, BufReader, Write},
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        thread,
+        time::{Duration, Instant},
+    },
+    tsukuyomi::{
+        config::prelude::*,
+        upgrade::{self, UpgradeRequest},
+        App,
+    },
+    tsukuyomi_server::test::ResponseExt,
+};
+
+fn echo_app() -> tsukuyomi::app::Result<App> {
+    App::create(
+        path!("/echo") //
+            .to(endpoint::get()
+                .extract(upgrade::upgrade())
+                .call(|req: UpgradeRequest| {
+                    req.require_protocol("lines")?;
+                    req.finish(
+                        &mut Response::builder(),
+                        upgrade::lines(|line: String| {
+                            if line == "quit" {
+                                Ok(None)
+                            } else {
+                                Ok(Some(line.to_uppercase()))
+                            }
+                        }),
+                    )
+                })),
+    )
+}
+
+#[test]
+fn echo_lines() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(echo_app()?)?;
+
+    let upgraded = server.perform_upgrade(
+        Request::get("/echo")
+            .header(CONNECTION, "upgrade")
+            .header(UPGRADE, "lines"),
+    )?;
+    assert_eq!(upgraded.response().status(), 101);
+    assert_eq!(upgraded.response().header(UPGRADE)?, "lines");
+    assert_eq!(upgraded.response().header(CONNECTION)?, "upgrade");
+
+    let mut stream = BufReader::new(upgraded);
+    let mut line = String::new();
+    for message in &["hello", "world"] {
+        writeln!(stream.get_mut(), "{}", message)?;
+        line.clear();
+        stream.read_line(&mut line)?;
+        assert_eq!(line, format!("{}\n", message.to_uppercase()));
+    }
+
+    writeln!(stream.get_mut(), "quit")?;
+    line.clear();
+    assert_eq!(stream.read_line(&mut line)?, 0);
+
+    Ok(())
+}
+
+#[test]
+fn rejects_invalid_requests() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(echo_app()?)?;
+
+    let response = server.perform("/echo")?;
+    assert_eq!(response.status(), 400);
+
+    let response = server.perform(
+        Request::get("/echo")
+            .header(CONNECTION, "keep-alive, Upgrade")
+            .header(UPGRADE, "websocket"),
+    )?;
+    assert_eq!(response.status(), 400);
+
+    Ok(())
+}
+
+struct DropGuard(Arc<AtomicBool>);
+
+impl Drop for DropGuard {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn not_upgraded() -> tsukuyomi_server::Result<()> {
+    let called = Arc::new(AtomicBool::new(false));
+    let dropped = Arc::new(AtomicBool::new(false));
+
+    let app = App::create({
+        let called = called.clone();
+        let dropped = dropped.clone();
+        path!("/") //
+            .to(endpoint::get()
+                .extract(upgrade::upgrade())
+                .call(move |req: UpgradeRequest| {
+                    let called = called.clone();
+                    let guard = DropGuard(dropped.clone());
+                    req.finish(&mut Response::builder(), move |_io| {
+                        drop(guard);
+                        called.store(true, Ordering::SeqCst);
+                        Ok(())
+                    })
+                }))
+    })?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    // The test client of `perform` does not support the upgrade, so the
+    // registered function must be dropped without being called.
+    let response = server.perform(
+        Request::get("/")
+            .header(CONNECTION, "upgrade")
+            .header(UPGRADE, "lines"),
+    )?;
+    assert_eq!(response.status(), 101);
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !dropped.load(Ordering::SeqCst) && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert!(dropped.load(Ordering::SeqCst));
+    assert!(!called.load(Ordering::SeqCst));
+
+    Ok(())
+}
@@ -0,0 +1,54 @@
+use {
+    http::{
+        header::{ACCEPT, VARY},
+        Request, Response,
+    },
+    tsukuyomi::{config::prelude::*, extractor, guard, modifiers, App},
+    tsukuyomi_server::test::ResponseExt,
+};
+
+#[test]
+fn merge_members_from_components() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/")
+            .to(chain![
+                endpoint::get()
+                    .guard(guard::accepts(mime::TEXT_PLAIN))
+                    .extract(extractor::ready(|input| {
+                        input.response().append(VARY, "Origin, accept")
+                    }))
+                    .reply("hello"),
+                endpoint::get()
+                    .guard(guard::accepts(mime::TEXT_HTML))
+                    .reply("<p>hello</p>"),
+            ])
+            .modify(modifiers::compression()),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(Request::get("/").header(ACCEPT, "text/plain"))?;
+    assert_eq!(response.body().to_utf8()?, "hello");
+    assert_eq!(response.headers().get_all(VARY).iter().count(), 1);
+    assert_eq!(response.header(VARY)?, "accept-encoding, accept, Origin");
+
+    Ok(())
+}
+
+#[test]
+fn asterisk_dominates() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/")
+            .to(endpoint::get()
+                .guard(guard::accepts(mime::TEXT_PLAIN))
+                .call(|| Response::builder().header(VARY, "*").body("hello").unwrap()))
+            .modify(modifiers::compression()),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(Request::get("/").header(ACCEPT, "text/plain"))?;
+    assert_eq!(response.body().to_utf8()?, "hello");
+    assert_eq!(response.headers().get_all(VARY).iter().count(), 1);
+    assert_eq!(response.header(VARY)?, "*");
+
+    Ok(())
+}
@@ -0,0 +1,119 @@
+use {
+    std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    },
+    tsukuyomi::{
+        config::{
+            prelude::*,
+            version::{versioned, ApiVersion},
+        },
+        extractor,
+        input::localmap::LocalData,
+        App,
+    },
+};
+
+// 2018-11-01T00:00:00Z
+const SINCE: u64 = 1_541_030_400;
+// 2019-01-01T00:00:00Z
+const SUNSET: u64 = 1_546_300_800;
+
+fn at(secs: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+#[test]
+fn deprecation_headers() -> tsukuyomi_server::Result<()> {
+    let app = App::create(chain![
+        versioned("/v1")
+            .deprecated(at(SINCE), at(SUNSET))
+            .successor("/v2")
+            .mount(chain![
+                path!("/version") //
+                    .to(endpoint::get()
+                        .extract(extractor::local::clone(&ApiVersion::KEY))
+                        .call(|version: ApiVersion| version.to_string())),
+                path!("/error") //
+                    .to(endpoint::call(|| Err::<&'static str, _>(
+                        tsukuyomi::error::bad_request("error")
+                    ))),
+            ]),
+        versioned("/v2").mount(
+            path!("/version") //
+                .to(endpoint::get()
+                    .extract(extractor::local::clone(&ApiVersion::KEY))
+                    .call(|version: ApiVersion| version.to_string()))
+        ),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/v1/version")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.headers().get("deprecation").map(|h| h.as_bytes()),
+        Some(&b"Thu, 01 Nov 2018 00:00:00 GMT"[..])
+    );
+    assert_eq!(
+        response.headers().get("sunset").map(|h| h.as_bytes()),
+        Some(&b"Tue, 01 Jan 2019 00:00:00 GMT"[..])
+    );
+    assert_eq!(
+        response.headers().get("link").map(|h| h.as_bytes()),
+        Some(&br#"</v2>; rel="successor-version""#[..])
+    );
+    assert_eq!(response.body().to_utf8()?, "v1");
+
+    let response = server.perform("/v1/error")?;
+    assert_eq!(response.status(), 400);
+    assert!(response.headers().contains_key("deprecation"));
+    assert!(response.headers().contains_key("sunset"));
+
+    let response = server.perform("/v2/version")?;
+    assert_eq!(response.status(), 200);
+    assert!(!response.headers().contains_key("deprecation"));
+    assert!(!response.headers().contains_key("sunset"));
+    assert!(!response.headers().contains_key("link"));
+    assert_eq!(response.body().to_utf8()?, "v2");
+
+    Ok(())
+}
+
+#[test]
+fn gone_after_sunset() -> tsukuyomi_server::Result<()> {
+    let now = Arc::new(AtomicUsize::new(SINCE as usize));
+    let app = App::create(
+        versioned("/v1")
+            .deprecated(at(SINCE), at(SUNSET))
+            .gone_after_sunset(true)
+            .clock({
+                let now = now.clone();
+                move || at(now.load(Ordering::SeqCst) as u64)
+            })
+            .mount(path!("/").to(endpoint::reply("v1"))),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/v1")?;
+    assert_eq!(response.status(), 200);
+
+    now.store(SUNSET as usize, Ordering::SeqCst);
+    let response = server.perform("/v1")?;
+    assert_eq!(response.status(), 410);
+    assert!(response.headers().contains_key("sunset"));
+
+    Ok(())
+}
+
+#[test]
+fn invalid_successor() {
+    assert!(App::create(
+        versioned("/v1")
+            .successor("/v2\n")
+            .mount(path!("/").to(endpoint::reply("v1")))
+    )
+    .is_err());
+}
@@ -0,0 +1,112 @@
+use {
+    http::{
+        header::{ACCEPT, CONTENT_TYPE, VARY},
+        Request, StatusCode,
+    },
+    tsukuyomi::{config::prelude::*, extractor, extractor::vnd::ApiVersion, output, App},
+    tsukuyomi_server::test::ResponseExt,
+};
+
+fn app() -> tsukuyomi::app::Result<App> {
+    App::create(chain![
+        path!("/users").to(endpoint::get()
+            .extract(extractor::vnd("myapp", vec![1, 2]))
+            .call(|ApiVersion(version)| output::json(vec![format!("v{}", version)]))),
+        path!("/plain").to(endpoint::get()
+            .extract(extractor::vnd("myapp", vec![1, 2]))
+            .call(|ApiVersion(version)| format!("v{}", version))),
+    ])
+}
+
+#[test]
+fn explicit_version() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+
+    let response =
+        server.perform(Request::get("/users").header(ACCEPT, "application/vnd.myapp.v1+json"))?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.body().to_utf8()?, r#"["v1"]"#);
+    assert_eq!(
+        response.header(CONTENT_TYPE)?,
+        "application/vnd.myapp.v1+json"
+    );
+    assert_eq!(response.header(VARY)?, "accept");
+
+    let response =
+        server.perform(Request::get("/users").header(ACCEPT, "application/vnd.myapp.v2+json"))?;
+    assert_eq!(response.body().to_utf8()?, r#"["v2"]"#);
+    assert_eq!(
+        response.header(CONTENT_TYPE)?,
+        "application/vnd.myapp.v2+json"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn preferred_by_quality() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+
+    let response = server.perform(Request::get("/users").header(
+        ACCEPT,
+        "application/vnd.myapp.v2+json;q=0.5, application/vnd.myapp.v1+json",
+    ))?;
+    assert_eq!(response.body().to_utf8()?, r#"["v1"]"#);
+
+    let response = server.perform(
+        Request::get("/users").header(ACCEPT, "application/vnd.myapp.v1+json;q=0.9, */*;q=0.1"),
+    )?;
+    assert_eq!(response.body().to_utf8()?, r#"["v1"]"#);
+
+    Ok(())
+}
+
+#[test]
+fn defaults_to_highest_version() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+
+    for accept in &["*/*", "application/json", "application/*"] {
+        let response = server.perform(Request::get("/users").header(ACCEPT, *accept))?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.body().to_utf8()?, r#"["v2"]"#);
+        assert_eq!(
+            response.header(CONTENT_TYPE)?,
+            "application/vnd.myapp.v2+json"
+        );
+    }
+
+    let response = server.perform(Request::get("/users"))?;
+    assert_eq!(response.body().to_utf8()?, r#"["v2"]"#);
+
+    Ok(())
+}
+
+#[test]
+fn not_acceptable() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+
+    for accept in &["text/html", "application/vnd.myapp.v3+json"] {
+        let response = server.perform(Request::get("/users").header(ACCEPT, *accept))?;
+        assert_eq!(response.status(), StatusCode::NOT_ACCEPTABLE);
+        assert_eq!(response.header(VARY)?, "accept");
+        assert_eq!(
+            response.body().to_utf8()?,
+            "no acceptable representation (available: \
+             application/vnd.myapp.v2+json, application/vnd.myapp.v1+json)"
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn non_json_response_is_untouched() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+
+    let response =
+        server.perform(Request::get("/plain").header(ACCEPT, "application/vnd.myapp.v1+json"))?;
+    assert_eq!(response.body().to_utf8()?, "v1");
+    assert_eq!(response.header(CONTENT_TYPE)?, "text/plain; charset=utf-8");
+
+    Ok(())
+}
@@ -0,0 +1,151 @@
+use {
+    http::{header::CONTENT_TYPE, Request, StatusCode},
+    ring::{digest, hmac},
+    serde::Deserialize,
+    std::time::{Duration, UNIX_EPOCH},
+    tsukuyomi::{
+        cache::MemoryCache,
+        config::prelude::*,
+        extractor::{self, replay::ReplayGuard, ExtractorExt},
+        rt::MockClock,
+        App,
+    },
+};
+
+const SECRET: &str = "webhook-secret";
+const PAYLOAD: &str = r#"{"action":"opened","number":42}"#;
+
+#[derive(Debug, Deserialize)]
+struct Event {
+    action: String,
+    number: u32,
+}
+
+fn sign(payload: &str) -> String {
+    let key = hmac::SigningKey::new(&digest::SHA256, SECRET.as_bytes());
+    hmac::sign(&key, payload.as_bytes())
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn app(clock: MockClock) -> tsukuyomi::app::Result<App> {
+    let app = App::create(chain![
+        path!("/github") //
+            .to(endpoint::post()
+                .extract(extractor::webhook::github(SECRET))
+                .extract(extractor::body::json())
+                .call(|event: Event| format!("{} #{}", event.action, event.number))),
+        path!("/stripe") //
+            .to(endpoint::post()
+                .extract(extractor::webhook::stripe(
+                    |_: &mut tsukuyomi::input::Input<'_>| Some(SECRET.as_bytes().to_vec()),
+                    Duration::from_secs(300),
+                ))
+                .extract(extractor::body::json())
+                .call(|event: Event| format!("{} #{}", event.action, event.number))),
+    ])?;
+    Ok(app.with_clock(clock))
+}
+
+#[test]
+fn github_signature() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(app(MockClock::new())?)?;
+
+    let response = server.perform(
+        Request::post("/github")
+            .header(CONTENT_TYPE, "application/json")
+            .header("x-hub-signature-256", format!("sha256={}", sign(PAYLOAD)))
+            .body(PAYLOAD),
+    )?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "opened #42");
+
+    let tampered = PAYLOAD.replace("42", "43");
+    let response = server.perform(
+        Request::post("/github")
+            .header(CONTENT_TYPE, "application/json")
+            .header("x-hub-signature-256", format!("sha256={}", sign(PAYLOAD)))
+            .body(tampered),
+    )?;
+    assert_eq!(response.status(), 401);
+
+    let response = server.perform(
+        Request::post("/github")
+            .header(CONTENT_TYPE, "application/json")
+            .body(PAYLOAD),
+    )?;
+    assert_eq!(response.status(), 401);
+
+    Ok(())
+}
+
+#[test]
+fn replay_with_mutated_signature() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/github") //
+            .to(endpoint::post()
+                .extract(extractor::webhook::github(SECRET).with_replay_guard(
+                    ReplayGuard::new(MemoryCache::builder().build()).without_timestamp(),
+                ))
+                .call(|| "accepted")),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let signature = sign(PAYLOAD);
+    for &(ref header, status) in &[
+        (format!("sha256={}", signature), StatusCode::OK),
+        (
+            format!("sha256={}", signature.to_uppercase()),
+            StatusCode::CONFLICT,
+        ),
+        (
+            format!("sha256={},sha256=00", signature),
+            StatusCode::CONFLICT,
+        ),
+    ] {
+        let response = server.perform(
+            Request::post("/github")
+                .header("x-hub-signature-256", header.as_str())
+                .body(PAYLOAD),
+        )?;
+        assert_eq!(response.status(), status, "{}", header);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn stripe_signature() -> tsukuyomi_server::Result<()> {
+    let timestamp = 1_500_000_000;
+    let clock = MockClock::starting_at(UNIX_EPOCH + Duration::from_secs(timestamp + 60));
+    let mut server = tsukuyomi_server::test::server(app(clock.clone())?)?;
+
+    let signature = sign(&format!("{}.{}", timestamp, PAYLOAD));
+    let header = format!(
+        "t={},v1={},v1={}",
+        timestamp,
+        sign("another payload"),
+        signature
+    );
+    let response = server.perform(
+        Request::post("/stripe")
+            .header(CONTENT_TYPE, "application/json")
+            .header("stripe-signature", header.as_str())
+            .body(PAYLOAD),
+    )?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "opened #42");
+
+    clock.advance(Duration::from_secs(300));
+    let response = server.perform(
+        Request::post("/stripe")
+            .header(CONTENT_TYPE, "application/json")
+            .header("stripe-signature", header.as_str())
+            .body(PAYLOAD),
+    )?;
+    assert_eq!(response.status(), 401);
+
+    Ok(())
+}